@@ -0,0 +1,140 @@
+use crate::config::bridge::BridgeRule;
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService};
+use crate::payload::{PayloadFormat, PayloadFormatError};
+use crate::MqtlibError;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{debug, error, info, warn};
+
+/// Subscribes to the topics of the rules on the source broker and
+/// republishes the received messages on the target broker until the
+/// shutdown future completes or one of the connections is closed.
+pub async fn run_bridge<F>(
+    source: Arc<Mutex<dyn MqttService>>,
+    target: Arc<Mutex<dyn MqttService>>,
+    rules: Vec<BridgeRule>,
+    capacity: usize,
+    shutdown: F,
+) -> Result<(), MqtlibError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (sender_source, mut receiver_source) = broadcast::channel::<MqttReceiveEvent>(capacity);
+    let (sender_target, _) = broadcast::channel::<MqttReceiveEvent>(capacity);
+    let (sender_disconnect, _) = broadcast::channel::<()>(1);
+
+    let mut target_loop_handle = target
+        .lock()
+        .await
+        .connect(sender_target, sender_disconnect.subscribe())
+        .await?;
+    let mut source_loop_handle = source
+        .lock()
+        .await
+        .connect(sender_source, sender_disconnect.subscribe())
+        .await?;
+
+    tokio::pin!(shutdown);
+
+    let connected = loop {
+        select! {
+            _ = &mut shutdown => {
+                info!("Exit signal received, shutting down");
+                break true;
+            }
+            _ = &mut source_loop_handle => {
+                warn!("Connection to the source broker was closed");
+                break false;
+            }
+            _ = &mut target_loop_handle => {
+                warn!("Connection to the target broker was closed");
+                break false;
+            }
+            event = receiver_source.recv() => match event {
+                Ok(event) => handle_event(&source, &target, &rules, event).await,
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Bridge lagged behind, {count} messages were dropped");
+                }
+                Err(RecvError::Closed) => break false,
+            }
+        }
+    };
+
+    let _ = sender_disconnect.send(());
+    if connected {
+        let _ = source_loop_handle.await;
+        let _ = target_loop_handle.await;
+    }
+
+    Ok(())
+}
+
+async fn handle_event(
+    source: &Arc<Mutex<dyn MqttService>>,
+    target: &Arc<Mutex<dyn MqttService>>,
+    rules: &[BridgeRule],
+    event: MqttReceiveEvent,
+) {
+    if event.is_connect() {
+        for rule in rules {
+            info!(
+                "Subscribing to topic {} with QoS {:?} on the source broker",
+                rule.topic(),
+                rule.qos()
+            );
+            if let Err(e) = source
+                .lock()
+                .await
+                .subscribe(rule.topic().clone(), *rule.qos())
+                .await
+            {
+                error!("Could not subscribe to topic {}: {}", rule.topic(), e);
+            }
+        }
+        return;
+    }
+
+    let Some((topic, qos, retain, payload)) = event.incoming_publish() else {
+        return;
+    };
+
+    // the first matching rule wins, so overlapping rules don't republish a
+    // message twice
+    let Some(rule) = rules.iter().find(|rule| rule.matches(&topic)) else {
+        return;
+    };
+
+    let payload = match convert_payload(rule, payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Could not convert the payload of a message on topic {topic}: {e:?}");
+            return;
+        }
+    };
+
+    let target_topic = rule.target_topic(&topic);
+    debug!("Bridging message from topic {topic} to {target_topic}");
+
+    target
+        .lock()
+        .await
+        .publish(MessagePublishData::new(
+            target_topic,
+            qos,
+            rule.retain().unwrap_or(retain),
+            payload,
+        ))
+        .await;
+}
+
+fn convert_payload(rule: &BridgeRule, payload: Vec<u8>) -> Result<Vec<u8>, PayloadFormatError> {
+    let Some(format) = rule.format() else {
+        return Ok(payload);
+    };
+
+    let source = PayloadFormat::try_from((rule.payload_type().clone(), payload))?;
+    Vec::<u8>::try_from(PayloadFormat::try_from((source, format))?)
+}
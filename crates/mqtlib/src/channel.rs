@@ -0,0 +1,104 @@
+use crate::config::channels::{Channels, OverflowPolicy};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Longest time to wait for a full channel with the block_then_drop policy.
+/// Tasks which publish messages also receive from the channels, so waiting
+/// until there is room could block the connection forever.
+const MAX_BLOCK_DURATION: Duration = Duration::from_secs(1);
+
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+static DROPPED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of messages skipped by receivers of the internal
+/// channels which couldn't keep up, since the start of the process.
+pub fn dropped_messages() -> u64 {
+    DROPPED_MESSAGES.load(Ordering::Relaxed)
+}
+
+/// Counts messages skipped by a receiver which lagged behind.
+pub fn count_dropped(skipped: u64) {
+    DROPPED_MESSAGES.fetch_add(skipped, Ordering::Relaxed);
+}
+
+/// Receives the next value of the channel. Values skipped because the
+/// receiver lagged behind are counted and logged. Returns none once the
+/// channel is closed.
+pub async fn recv<T: Clone>(receiver: &mut Receiver<T>, name: &str) -> Option<T> {
+    loop {
+        match receiver.recv().await {
+            Ok(value) => return Some(value),
+            Err(RecvError::Lagged(skipped)) => {
+                count_dropped(skipped);
+                warn!("{name} skipped {skipped} messages");
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Waits up to [`MAX_BLOCK_DURATION`] until all receivers have room for
+/// another value, if the channels are configured to block. Broadcast
+/// channels can't be awaited for room, so their length is polled; if the
+/// receivers don't catch up in time, the send drops the oldest value.
+pub async fn wait_for_capacity<T>(sender: &Sender<T>, channels: &Channels) {
+    if channels.overflow != OverflowPolicy::BlockThenDrop {
+        return;
+    }
+
+    let start = Instant::now();
+    while sender.len() >= channels.capacity {
+        if start.elapsed() >= MAX_BLOCK_DURATION {
+            warn!("Receivers did not catch up, the oldest messages will be dropped");
+            return;
+        }
+
+        tokio::time::sleep(BLOCK_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn count_dropped_messages() {
+        let (sender, mut receiver) = broadcast::channel::<u32>(2);
+        let dropped = dropped_messages();
+
+        for value in 0..5 {
+            sender.send(value).unwrap();
+        }
+        drop(sender);
+
+        assert_eq!(Some(3), recv(&mut receiver, "Test").await);
+        assert_eq!(Some(4), recv(&mut receiver, "Test").await);
+        assert_eq!(None, recv(&mut receiver, "Test").await);
+        assert!(dropped_messages() >= dropped + 3);
+    }
+
+    #[tokio::test]
+    async fn wait_until_received() {
+        let (sender, mut receiver) = broadcast::channel::<u32>(4);
+        let channels = Channels {
+            capacity: 1,
+            overflow: OverflowPolicy::BlockThenDrop,
+        };
+
+        sender.send(1).unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            receiver.recv().await.unwrap();
+        });
+
+        wait_for_capacity(&sender, &channels).await;
+        assert_eq!(0, sender.len());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::config::channels::Channels;
+use crate::config::mqtli_config::MqttBrokerConnect;
+use crate::config::topic::Topic;
+use crate::config::PayloadType;
+use crate::hooks::Hooks;
+use crate::mqtt::{
+    ConversionErrorData, MessagePublishData, MessageReceivedData, MqttReceiveEvent, MqttService,
+    QoS,
+};
+use crate::payload::PayloadFormat;
+use crate::{new_mqtt_service, MqtlibError};
+use futures::{stream, Stream};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::{broadcast, watch, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// MQTT client for embedding the payload format handling of mqtli in other
+/// programs. Payloads are converted from and to the given payload types just
+/// like in the CLI.
+///
+/// ```no_run
+/// # use mqtlib::client::MqtlibClient;
+/// # use mqtlib::config::mqtli_config::MqttBrokerConnect;
+/// # use mqtlib::config::PayloadType;
+/// # use mqtlib::mqtt::QoS;
+/// # use mqtlib::payload::PayloadFormat;
+/// # async fn example(config: MqttBrokerConnect) -> Result<(), mqtlib::MqtlibError> {
+/// let client = MqtlibClient::connect(config).await?;
+///
+/// let mut subscription = client
+///     .subscribe("sensors/#", QoS::AtLeastOnce, PayloadType::Json)
+///     .await?;
+///
+/// let payload = PayloadFormat::try_from((PayloadType::Json, b"{\"value\": 21.5}".to_vec()))?;
+/// client
+///     .publish("sensors/1", QoS::AtLeastOnce, false, payload)
+///     .await?;
+///
+/// let message = subscription.recv().await?;
+/// println!("{}: {}", message.topic, String::try_from(message.payload)?);
+///
+/// client.disconnect().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MqtlibClient {
+    service: Mutex<Box<dyn MqttService>>,
+    hooks: Arc<Hooks>,
+    sender_receive: Sender<MqttReceiveEvent>,
+    sender_exit: Sender<()>,
+    /// Set once the connection task ended, e.g. because the connection was
+    /// lost and not established again
+    receiver_closed: watch::Receiver<bool>,
+    task_handle: JoinHandle<()>,
+}
+
+/// Messages received on a subscribed topic filter, decoded to the payload
+/// type of the subscription.
+pub struct MqtlibSubscription {
+    filter: Topic,
+    hooks: Arc<Hooks>,
+    receiver: Receiver<MqttReceiveEvent>,
+    receiver_closed: watch::Receiver<bool>,
+}
+
+impl MqtlibClient {
+    /// Connects to the broker using the MQTT version of the configuration.
+    pub async fn connect(config: MqttBrokerConnect) -> Result<Self, MqtlibError> {
+        Self::connect_with_hooks(config, Hooks::default()).await
+    }
+
+    /// Connects to the broker and invokes the registered hooks on the events
+    /// of this client.
+    pub async fn connect_with_hooks(
+        config: MqttBrokerConnect,
+        hooks: Hooks,
+    ) -> Result<Self, MqtlibError> {
+        Self::connect_with_channels(config, hooks, Channels::default()).await
+    }
+
+    /// Connects to the broker with the given capacity of the internal
+    /// channels. Each subscription buffers up to the capacity of messages;
+    /// if it isn't received from fast enough, the oldest messages are
+    /// skipped and reported by [`MqtlibSubscription::recv`].
+    pub async fn connect_with_channels(
+        config: MqttBrokerConnect,
+        hooks: Hooks,
+        channels: Channels,
+    ) -> Result<Self, MqtlibError> {
+        let hooks = Arc::new(hooks);
+        let capacity = channels.capacity;
+
+        let mut service: Box<dyn MqttService> = new_mqtt_service(&config, &channels);
+
+        let (sender_receive, _) = broadcast::channel::<MqttReceiveEvent>(capacity);
+        let (sender_exit, _) = broadcast::channel::<()>(1);
+        let (sender_closed, receiver_closed) = watch::channel(false);
+
+        if !hooks.is_empty() {
+            start_connection_hooks(
+                hooks.clone(),
+                sender_receive.subscribe(),
+                receiver_closed.clone(),
+            );
+        }
+
+        let connection_task = service
+            .connect(sender_receive.clone(), sender_exit.subscribe())
+            .await?;
+
+        // the client keeps a sender of the events to subscribe, so the
+        // receivers are notified separately once the connection task ended
+        let task_handle = tokio::spawn(async move {
+            let _ = connection_task.await;
+            let _ = sender_closed.send(true);
+        });
+
+        Ok(Self {
+            service: Mutex::new(service),
+            hooks,
+            sender_receive,
+            sender_exit,
+            receiver_closed,
+            task_handle,
+        })
+    }
+
+    /// Publishes the payload on the topic. The payload is sent in its binary
+    /// representation, e.g. JSON is sent as UTF-8 encoded text.
+    pub async fn publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: PayloadFormat,
+    ) -> Result<(), MqtlibError> {
+        let message = MessagePublishData::new(
+            topic.to_string(),
+            qos,
+            retain,
+            Vec::<u8>::try_from(payload)?,
+        );
+
+        self.service.lock().await.publish(message.clone()).await;
+        self.hooks.message_published(&message);
+
+        Ok(())
+    }
+
+    /// Serializes the value with serde and publishes it converted to the
+    /// payload type, e.g. a struct as JSON, YAML or protobuf message.
+    pub async fn publish_serialize<T: Serialize + ?Sized>(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        value: &T,
+        payload_type: PayloadType,
+    ) -> Result<(), MqtlibError> {
+        let payload = PayloadFormat::from_serialize(value, &payload_type)?;
+
+        self.publish(topic, qos, retain, payload).await
+    }
+
+    /// Subscribes to the topic filter, which may contain wildcards. Received
+    /// payloads are decoded to the given payload type.
+    pub async fn subscribe(
+        &self,
+        topic: &str,
+        qos: QoS,
+        payload_type: PayloadType,
+    ) -> Result<MqtlibSubscription, MqtlibError> {
+        // subscribe to the events before the broker can send any message
+        let receiver = self.sender_receive.subscribe();
+
+        self.service
+            .lock()
+            .await
+            .subscribe(topic.to_string(), qos)
+            .await?;
+
+        Ok(MqtlibSubscription {
+            filter: Topic {
+                topic: topic.to_string(),
+                payload_type,
+                ..Default::default()
+            },
+            hooks: self.hooks.clone(),
+            receiver,
+            receiver_closed: self.receiver_closed.clone(),
+        })
+    }
+
+    /// Disconnects from the broker and waits until the connection is closed.
+    pub async fn disconnect(self) -> Result<(), MqtlibError> {
+        self.service.lock().await.disconnect().await?;
+
+        let _ = self.sender_exit.send(());
+        let _ = self.task_handle.await;
+
+        Ok(())
+    }
+}
+
+impl MqtlibSubscription {
+    /// Waits for the next message on the subscribed topic filter. Messages
+    /// whose payload can't be decoded are returned as error, as well as the
+    /// number of messages skipped because they weren't received fast enough.
+    /// Once the connection is closed and all buffered messages are received,
+    /// [`MqtlibError::Disconnected`] is returned.
+    pub async fn recv(&mut self) -> Result<MessageReceivedData, MqtlibError> {
+        loop {
+            let event = select! {
+                biased;
+                event = self.receiver.recv() => event,
+                _ = self.receiver_closed.wait_for(|closed| *closed) => {
+                    return Err(MqtlibError::Disconnected);
+                }
+            };
+
+            match event {
+                Ok(event) => {
+                    let Some((topic, qos, retain, payload)) = event.incoming_publish() else {
+                        continue;
+                    };
+
+                    if !self.filter.contains(&topic) {
+                        continue;
+                    }
+
+                    let converted = PayloadFormat::try_from((
+                        self.filter.payload_type.clone(),
+                        payload.clone(),
+                    ));
+
+                    return match converted {
+                        Ok(payload) => {
+                            let message = MessageReceivedData::new(topic, qos, retain, payload);
+                            self.hooks.message_received(&message);
+                            Ok(message)
+                        }
+                        Err(e) => {
+                            self.hooks.conversion_error(&ConversionErrorData {
+                                topic,
+                                payload,
+                                error: e.to_string(),
+                            });
+                            Err(e.into())
+                        }
+                    };
+                }
+                Err(RecvError::Lagged(skipped_messages)) => {
+                    return Err(MqtlibError::SubscriptionLagged(skipped_messages));
+                }
+                Err(RecvError::Closed) => return Err(MqtlibError::Disconnected),
+            }
+        }
+    }
+
+    /// Turns the subscription into a stream of the received messages, so
+    /// that the combinators of `StreamExt` can be used. Messages whose
+    /// payload can't be decoded and skipped messages are logged; the stream
+    /// ends when the connection to the broker is closed, either by the
+    /// client or because it was lost and not established again.
+    ///
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # use mqtlib::client::MqtlibSubscription;
+    /// # async fn example(subscription: MqtlibSubscription) {
+    /// let mut temperatures = subscription
+    ///     .into_stream()
+    ///     .filter(|message| std::future::ready(message.topic.ends_with("/temperature")))
+    ///     .take(10);
+    ///
+    /// while let Some(message) = temperatures.next().await {
+    ///     println!("{}: {:?}", message.topic, message.payload);
+    /// }
+    /// # }
+    /// ```
+    pub fn into_stream(self) -> impl Stream<Item = MessageReceivedData> {
+        stream::unfold(self, |mut subscription| async move {
+            loop {
+                match subscription.recv().await {
+                    Ok(message) => return Some((message, subscription)),
+                    Err(MqtlibError::Disconnected) => return None,
+                    Err(MqtlibError::SubscriptionLagged(skipped_messages)) => {
+                        warn!("Subscription skipped {skipped_messages} messages")
+                    }
+                    Err(e) => error!("Could not decode message: {e:?}"),
+                }
+            }
+        })
+    }
+}
+
+/// Invokes the connect and disconnect hooks until the connection task ended.
+fn start_connection_hooks(
+    hooks: Arc<Hooks>,
+    mut receiver: Receiver<MqttReceiveEvent>,
+    mut receiver_closed: watch::Receiver<bool>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let event = select! {
+                biased;
+                event = receiver.recv() => event,
+                _ = receiver_closed.wait_for(|closed| *closed) => break,
+            };
+
+            match event {
+                Ok(event) => hooks.mqtt_event(&event),
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
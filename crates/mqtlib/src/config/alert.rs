@@ -0,0 +1,207 @@
+use crate::config::connection_hooks::{ConnectionHookCommand, ConnectionHookPublish};
+use crate::config::effective::redact_headers;
+use crate::config::publish::{deserialize_duration_milliseconds, serialize_duration_milliseconds};
+use crate::config::{deserialize_qos, serialize_qos};
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use jsonpath_rust::JsonPath;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use validator::Validate;
+
+/// Named rule running actions when the messages of a topic meet a
+/// condition, e.g. to watch devices.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct AlertRule {
+    #[validate(length(min = 1, message = "Name of the alert must be given"))]
+    pub name: String,
+    /// Topic of the watched messages, may contain wildcards
+    #[validate(length(min = 1, message = "Topic of the alert must be given"))]
+    pub topic: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    pub condition: AlertCondition,
+    #[validate(length(min = 1, message = "Alert must have at least one action"))]
+    pub actions: Vec<AlertAction>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum AlertCondition {
+    /// A value of the JSON payload compares to the given value
+    #[serde(rename = "json")]
+    Json(AlertConditionJson),
+    /// No message was received within the timeout
+    #[serde(rename = "absence")]
+    Absence(AlertConditionAbsence),
+}
+
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct AlertConditionJson {
+    pub jsonpath: String,
+    pub operator: AlertOperator,
+    pub value: Value,
+}
+
+impl AlertConditionJson {
+    /// Checks if any value selected by the JSONPath compares to the value of
+    /// the condition. Payloads which are not JSON never match.
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        let Ok(json) = serde_json::from_slice::<Value>(payload) else {
+            return false;
+        };
+
+        json.query(self.jsonpath.as_str())
+            .map(|values| {
+                values
+                    .iter()
+                    .any(|value| self.operator.compare(value, &self.value))
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct AlertConditionAbsence {
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub timeout: Duration,
+}
+
+/// Comparison of a value of the payload with the value of the condition.
+/// Numbers are compared by their value and strings lexicographically.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum AlertOperator {
+    #[serde(rename = "eq")]
+    #[strum(serialize = "eq")]
+    Equal,
+    #[serde(rename = "ne")]
+    #[strum(serialize = "ne")]
+    NotEqual,
+    #[serde(rename = "gt")]
+    #[strum(serialize = "gt")]
+    Greater,
+    #[serde(rename = "ge")]
+    #[strum(serialize = "ge")]
+    GreaterOrEqual,
+    #[serde(rename = "lt")]
+    #[strum(serialize = "lt")]
+    Less,
+    #[serde(rename = "le")]
+    #[strum(serialize = "le")]
+    LessOrEqual,
+}
+
+impl AlertOperator {
+    pub fn compare(&self, lhs: &Value, rhs: &Value) -> bool {
+        let ordering = match (lhs, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => lhs
+                .as_f64()
+                .zip(rhs.as_f64())
+                .and_then(|(lhs, rhs)| lhs.partial_cmp(&rhs)),
+            (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+            (lhs, rhs) if lhs == rhs => Some(Ordering::Equal),
+            _ => None,
+        };
+
+        match self {
+            AlertOperator::Equal => ordering == Some(Ordering::Equal),
+            AlertOperator::NotEqual => ordering != Some(Ordering::Equal),
+            AlertOperator::Greater => ordering == Some(Ordering::Greater),
+            AlertOperator::GreaterOrEqual => {
+                matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+            }
+            AlertOperator::Less => ordering == Some(Ordering::Less),
+            AlertOperator::LessOrEqual => {
+                matches!(ordering, Some(Ordering::Less | Ordering::Equal))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum AlertAction {
+    #[serde(rename = "publish")]
+    Publish(ConnectionHookPublish),
+    #[serde(rename = "command")]
+    Command(ConnectionHookCommand),
+    #[serde(rename = "webhook")]
+    Webhook(AlertWebhook),
+}
+
+/// Posts the alert as JSON document to the URL.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct AlertWebhook {
+    pub url: String,
+    #[serde(default)]
+    #[serde(serialize_with = "redact_headers")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_webhook_timeout")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub timeout: Duration,
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn compare() {
+        assert!(AlertOperator::Greater.compare(&json!(30.5), &json!(30)));
+        assert!(!AlertOperator::Greater.compare(&json!(30), &json!(30)));
+        assert!(AlertOperator::GreaterOrEqual.compare(&json!(30), &json!(30.0)));
+        assert!(AlertOperator::Equal.compare(&json!(1), &json!(1.0)));
+        assert!(AlertOperator::Equal.compare(&json!("on"), &json!("on")));
+        assert!(AlertOperator::Equal.compare(&json!(true), &json!(true)));
+        assert!(AlertOperator::NotEqual.compare(&json!("on"), &json!(1)));
+        assert!(!AlertOperator::Less.compare(&json!("1"), &json!(2)));
+    }
+
+    #[test]
+    fn json_condition() {
+        let rule: AlertRule = serde_yaml::from_str(
+            "name: overheating
+topic: sensors/+/temperature
+condition:
+  type: json
+  jsonpath: $.value
+  operator: gt
+  value: 80
+actions:
+  - type: publish
+    topic: alerts/overheating
+    payload: '{{topic}}'",
+        )
+        .unwrap();
+
+        let AlertCondition::Json(condition) = &rule.condition else {
+            panic!("unexpected condition {:?}", rule.condition);
+        };
+        assert!(condition.matches(b"{\"value\": 81}"));
+        assert!(!condition.matches(b"{\"value\": 80}"));
+        assert!(!condition.matches(b"{\"other\": 81}"));
+        assert!(!condition.matches(b"81"));
+        assert!(!condition.matches(b"not json"));
+    }
+}
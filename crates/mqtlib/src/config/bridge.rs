@@ -0,0 +1,132 @@
+use crate::config::mqtli_config::MqttBrokerConnect;
+use crate::config::subscription::TopicRegex;
+use crate::config::topic::topic_matches;
+use crate::config::PayloadType;
+use crate::config::{deserialize_qos, serialize_qos};
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Republishes messages received from the broker of the connection settings
+/// (the source) on a target broker.
+#[derive(Clone, Debug, Getters, Serialize, Validate)]
+pub struct Bridge {
+    #[validate(nested)]
+    pub target: MqttBrokerConnect,
+    #[validate(nested)]
+    pub rules: Vec<BridgeRule>,
+}
+
+impl Default for Bridge {
+    fn default() -> Self {
+        Self {
+            // the default client id of the source would make both
+            // connections to the same broker kick each other out
+            target: MqttBrokerConnect {
+                client_id: "mqtli-bridge".to_string(),
+                ..MqttBrokerConnect::default()
+            },
+            rules: vec![],
+        }
+    }
+}
+
+/// Topic pattern subscribed on the source broker and how the messages
+/// received on it are republished on the target broker.
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct BridgeRule {
+    #[validate(length(min = 1, message = "Topic of the bridge rule must be given"))]
+    pub topic: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    /// Removed from the start of the topic before it is republished
+    #[serde(default)]
+    pub strip_prefix: Option<String>,
+    /// Rewrites the topic after the prefix was removed
+    #[serde(default)]
+    pub rewrite: Option<TopicRewrite>,
+    /// Added to the start of the topic before it is republished
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Payload type of the received messages, used to convert the payload
+    #[serde(default)]
+    pub payload_type: PayloadType,
+    /// Converts the payload to this type before republishing it, otherwise
+    /// the payload is republished unchanged
+    #[serde(default)]
+    pub format: Option<PayloadType>,
+    /// Overrides the retain flag of the received messages
+    #[serde(default)]
+    pub retain: Option<bool>,
+}
+
+impl BridgeRule {
+    pub fn matches(&self, topic: &str) -> bool {
+        topic_matches(&self.topic, topic)
+    }
+
+    /// Returns the topic on the target broker for a topic of the source
+    /// broker.
+    pub fn target_topic(&self, topic: &str) -> String {
+        let topic = match &self.strip_prefix {
+            Some(prefix) => topic.strip_prefix(prefix.as_str()).unwrap_or(topic),
+            None => topic,
+        };
+
+        let topic = match &self.rewrite {
+            Some(rewrite) => rewrite.pattern.replace_all(topic, &rewrite.replacement),
+            None => topic.to_string(),
+        };
+
+        match &self.prefix {
+            Some(prefix) => format!("{prefix}{topic}"),
+            None => topic,
+        }
+    }
+}
+
+/// Regular expression replaced in the topic, e.g. pattern `^sensors/(.+)$`
+/// with replacement `site-a/$1`.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct TopicRewrite {
+    pub pattern: TopicRegex,
+    pub replacement: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn target_topic() {
+        let rule = BridgeRule {
+            topic: "site/#".to_string(),
+            strip_prefix: Some("site/".to_string()),
+            rewrite: Some(TopicRewrite {
+                pattern: TopicRegex::from_str("^sensor-(\\d+)/").unwrap(),
+                replacement: "sensors/$1/".to_string(),
+            }),
+            prefix: Some("bridge/".to_string()),
+            ..BridgeRule::default()
+        };
+
+        assert!(rule.matches("site/sensor-1/temp"));
+        assert!(!rule.matches("other/sensor-1/temp"));
+        assert_eq!(
+            "bridge/sensors/1/temp",
+            rule.target_topic("site/sensor-1/temp")
+        );
+        assert_eq!(
+            "bridge/site/x",
+            BridgeRule {
+                prefix: Some("bridge/".to_string()),
+                ..BridgeRule::default()
+            }
+            .target_topic("site/x")
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumString;
+use validator::Validate;
+
+/// Capacity of the internal channels between receiving, converting and
+/// writing messages, and what to do if they are full.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct Channels {
+    #[serde(default = "default_capacity")]
+    #[validate(range(min = 1, message = "Channel capacity must be at least 1"))]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Self {
+            capacity: default_capacity(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+fn default_capacity() -> usize {
+    1024
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum OverflowPolicy {
+    /// Drop the oldest messages for receivers which can't keep up
+    #[default]
+    #[serde(rename = "drop_oldest")]
+    #[strum(serialize = "drop_oldest")]
+    DropOldest,
+    /// Stop reading from the broker for up to one second per message to let
+    /// the receivers catch up, then drop the oldest messages like
+    /// [`OverflowPolicy::DropOldest`]
+    #[serde(rename = "block_then_drop")]
+    #[strum(serialize = "block_then_drop")]
+    BlockThenDrop,
+}
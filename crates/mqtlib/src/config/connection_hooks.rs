@@ -0,0 +1,55 @@
+use crate::config::publish::{deserialize_duration_milliseconds, serialize_duration_milliseconds};
+use crate::config::{deserialize_qos, serialize_qos};
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Actions run when the connection to the broker is established or closed,
+/// e.g. to notify external systems.
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+pub struct ConnectionHooks {
+    /// Run after each successful connect, including reconnects
+    #[serde(default)]
+    pub on_connect: Vec<ConnectionHookAction>,
+    /// Run when the connection is closed; messages are only published on a
+    /// graceful shutdown
+    #[serde(default)]
+    pub on_disconnect: Vec<ConnectionHookAction>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum ConnectionHookAction {
+    #[serde(rename = "command")]
+    Command(ConnectionHookCommand),
+    #[serde(rename = "publish")]
+    Publish(ConnectionHookPublish),
+}
+
+/// Runs the command in the shell and waits until it exits.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct ConnectionHookCommand {
+    pub command: String,
+    #[serde(default = "default_hook_timeout")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct ConnectionHookPublish {
+    pub topic: String,
+    #[serde(default)]
+    pub payload: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+fn default_hook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
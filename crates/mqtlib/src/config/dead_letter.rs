@@ -0,0 +1,57 @@
+use crate::config::{deserialize_qos, serialize_qos};
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+/// Topic to which received messages are republished if their payload
+/// couldn't be decoded, filtered or converted to the format of an output.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct DeadLetter {
+    #[validate(custom(
+        function = "validate_dead_letter_topic",
+        message = "Dead letter topic must not be empty or contain wildcards"
+    ))]
+    pub topic: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+impl DeadLetter {
+    pub fn new(topic: String) -> Self {
+        Self {
+            topic,
+            qos: QoS::default(),
+            retain: false,
+        }
+    }
+}
+
+fn validate_dead_letter_topic(value: &str) -> Result<(), ValidationError> {
+    if !value.is_empty() && !value.contains(['+', '#']) {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_dead_letter_topic"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_letter() {
+        let dead_letter: DeadLetter =
+            serde_yaml::from_str("topic: mqtli/dead_letter\nqos: 1").unwrap();
+
+        assert_eq!("mqtli/dead_letter", dead_letter.topic);
+        assert_eq!(QoS::AtLeastOnce, dead_letter.qos);
+        assert!(!dead_letter.retain);
+        assert!(dead_letter.validate().is_ok());
+        assert!(DeadLetter::new("errors/#".to_string()).validate().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::publish::{deserialize_duration_milliseconds, serialize_duration_milliseconds};
 use crate::config::PayloadType;
 use crate::payload::json::PayloadFormatJson;
 use crate::payload::text::PayloadFormatText;
@@ -5,9 +6,22 @@ use crate::payload::{PayloadFormat, PayloadFormatError};
 use derive_getters::Getters;
 use jsonpath_rust::parser::errors::JsonPathError;
 use jsonpath_rust::JsonPath;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
+use validator::{Validate, ValidationErrors};
+
+mod signature;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use signature::{FilterTypeHmacSign, FilterTypeHmacVerify};
+#[cfg(feature = "wasm")]
+pub use wasm::FilterTypeWasm;
 
 #[derive(Error, Debug)]
 pub enum FilterError {
@@ -17,11 +31,28 @@ pub enum FilterError {
     WrongJsonPath(#[from] JsonPathError),
     #[error("Error in payload format")]
     PayloadFormatError(#[from] Box<PayloadFormatError>),
+    #[cfg(feature = "wasm")]
+    #[error("Error in WebAssembly filter: {0}")]
+    WasmError(String),
+    #[error("Downsample filter with mean aggregation requires a field")]
+    MissingDownsampleField,
+    #[error("HMAC signature of the payload is missing or invalid")]
+    InvalidSignature,
 }
 
 pub trait FilterImpl {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError>;
 
+    /// Applies the filter to a message of the given topic. Filters keeping
+    /// state per topic override this, all others ignore the topic.
+    fn apply_on_topic(
+        &self,
+        _topic: &str,
+        data: PayloadFormat,
+    ) -> Result<Vec<PayloadFormat>, FilterError> {
+        self.apply(data)
+    }
+
     fn convert_payload_format(
         &self,
         data: PayloadFormat,
@@ -32,15 +63,23 @@ pub trait FilterImpl {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct FilterTypes(pub(crate) Vec<FilterType>);
 
 impl FilterTypes {
     pub fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        self.apply_on_topic("", data)
+    }
+
+    pub fn apply_on_topic(
+        &self,
+        topic: &str,
+        data: PayloadFormat,
+    ) -> Result<Vec<PayloadFormat>, FilterError> {
         self.0.iter().try_fold(vec![data], |payloads, filter| {
             let result: Result<Vec<PayloadFormat>, FilterError> = payloads
                 .iter()
-                .map(|payload| FilterImpl::apply(filter, payload.clone()))
+                .map(|payload| FilterImpl::apply_on_topic(filter, topic, payload.clone()))
                 .try_fold(vec![], |mut unrolled, result| {
                     unrolled.extend(result?);
                     Ok(unrolled)
@@ -51,6 +90,12 @@ impl FilterTypes {
     }
 }
 
+impl Validate for FilterTypes {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        self.0.iter().try_for_each(|filter| filter.validate())
+    }
+}
+
 impl Display for FilterTypes {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         self.0
@@ -65,7 +110,7 @@ impl From<Vec<FilterType>> for FilterTypes {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypeExtractJson {
     jsonpath: String,
 }
@@ -92,13 +137,13 @@ impl FilterImpl for FilterTypeExtractJson {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypeToUpperCase {}
 
 impl FilterImpl for FilterTypeToUpperCase {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
         let result: Result<Vec<PayloadFormat>, FilterError> =
-            match self.convert_payload_format(data, PayloadType::Text)? {
+            match self.convert_payload_format(data, PayloadType::Text(Default::default()))? {
                 PayloadFormat::Text(data) => {
                     let res = PayloadFormatText::from(data.content().to_ascii_uppercase());
                     Ok(vec![PayloadFormat::Text(res)])
@@ -110,13 +155,13 @@ impl FilterImpl for FilterTypeToUpperCase {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypeToLowerCase {}
 
 impl FilterImpl for FilterTypeToLowerCase {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
         let result: Result<Vec<PayloadFormat>, FilterError> =
-            match self.convert_payload_format(data, PayloadType::Text)? {
+            match self.convert_payload_format(data, PayloadType::Text(Default::default()))? {
                 PayloadFormat::Text(data) => {
                     let res = PayloadFormatText::from(data.content().to_ascii_lowercase());
                     Ok(vec![PayloadFormat::Text(res)])
@@ -128,7 +173,7 @@ impl FilterImpl for FilterTypeToLowerCase {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypePrepend {
     content: String,
 }
@@ -136,7 +181,7 @@ pub struct FilterTypePrepend {
 impl FilterImpl for FilterTypePrepend {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
         let result: Result<Vec<PayloadFormat>, FilterError> =
-            match self.convert_payload_format(data, PayloadType::Text)? {
+            match self.convert_payload_format(data, PayloadType::Text(Default::default()))? {
                 PayloadFormat::Text(data) => {
                     let mut result = Vec::from(self.content.as_bytes());
                     result.extend(data.content());
@@ -150,7 +195,7 @@ impl FilterImpl for FilterTypePrepend {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypeAppend {
     content: String,
 }
@@ -158,7 +203,7 @@ pub struct FilterTypeAppend {
 impl FilterImpl for FilterTypeAppend {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
         let result: Result<Vec<PayloadFormat>, FilterError> =
-            match self.convert_payload_format(data, PayloadType::Text)? {
+            match self.convert_payload_format(data, PayloadType::Text(Default::default()))? {
                 PayloadFormat::Text(data) => {
                     let mut result = data.content().clone();
                     result.extend(self.content.as_bytes());
@@ -172,17 +217,17 @@ impl FilterImpl for FilterTypeAppend {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypeToText {}
 
 impl FilterImpl for FilterTypeToText {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
-        self.convert_payload_format(data, PayloadType::Text)
+        self.convert_payload_format(data, PayloadType::Text(Default::default()))
             .map(|e| vec![e])
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct FilterTypeToJson {}
 
 impl FilterImpl for FilterTypeToJson {
@@ -192,11 +237,274 @@ impl FilterImpl for FilterTypeToJson {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, strum_macros::Display)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+pub struct FilterTypeToYaml {
+    /// Outputs one payload per document of multi-document YAML
+    #[serde(default)]
+    split_documents: bool,
+}
+
+impl FilterImpl for FilterTypeToYaml {
+    fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        match self.convert_payload_format(data, PayloadType::Yaml)? {
+            PayloadFormat::Yaml(data) if self.split_documents => Ok(data
+                .split_documents()
+                .into_iter()
+                .map(PayloadFormat::Yaml)
+                .collect()),
+            PayloadFormat::Yaml(data) => Ok(vec![PayloadFormat::Yaml(data)]),
+            _ => Err(FilterError::WrongPayloadFormat("yaml".into())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct FilterTypeFlattenJson {
+    /// Separator between the keys of the nested objects
+    #[serde(default = "default_flatten_separator")]
+    separator: String,
+    /// Flattens arrays with the index of the elements as key instead of
+    /// keeping them as values
+    #[serde(default)]
+    explode_arrays: bool,
+}
+
+impl Default for FilterTypeFlattenJson {
+    fn default() -> Self {
+        Self {
+            separator: default_flatten_separator(),
+            explode_arrays: false,
+        }
+    }
+}
+
+fn default_flatten_separator() -> String {
+    String::from(".")
+}
+
+impl FilterTypeFlattenJson {
+    fn flatten(&self, prefix: Option<String>, value: &Value, flat: &mut Map<String, Value>) {
+        let key = |child: &str| match &prefix {
+            Some(prefix) => format!("{prefix}{}{child}", self.separator),
+            None => child.to_string(),
+        };
+
+        match value {
+            Value::Object(object) if !object.is_empty() => {
+                for (child, value) in object {
+                    self.flatten(Some(key(child)), value, flat);
+                }
+            }
+            Value::Array(array) if self.explode_arrays && !array.is_empty() => {
+                for (index, value) in array.iter().enumerate() {
+                    self.flatten(Some(key(&index.to_string())), value, flat);
+                }
+            }
+            _ => {
+                flat.insert(prefix.unwrap_or_default(), value.clone());
+            }
+        }
+    }
+}
+
+impl FilterImpl for FilterTypeFlattenJson {
+    fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        match self.convert_payload_format(data, PayloadType::Json)? {
+            PayloadFormat::Json(data) => {
+                let content = data.content();
+                if !content.is_object() && !(self.explode_arrays && content.is_array()) {
+                    return Ok(vec![PayloadFormat::Json(data)]);
+                }
+
+                let mut flat = Map::new();
+                self.flatten(None, content, &mut flat);
+                Ok(vec![PayloadFormat::Json(PayloadFormatJson::from(
+                    Value::Object(flat),
+                ))])
+            }
+            _ => Err(FilterError::WrongPayloadFormat("json".into())),
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum DownsampleAggregation {
+    /// Forwards the first message of a bucket immediately
+    #[default]
+    #[serde(rename = "first")]
+    #[strum(serialize = "first")]
+    First,
+    /// Forwards the last message of a bucket once the bucket is over
+    #[serde(rename = "last")]
+    #[strum(serialize = "last")]
+    Last,
+    /// Forwards the last message of a bucket with the field set to the mean
+    /// of the field of all messages in the bucket once the bucket is over
+    #[serde(rename = "mean")]
+    #[strum(serialize = "mean")]
+    Mean,
+}
+
+#[derive(Debug)]
+struct DownsampleBucket {
+    index: u128,
+    pending: Option<PayloadFormat>,
+    sum: f64,
+    count: u64,
+}
+
+impl DownsampleBucket {
+    fn new(index: u128) -> Self {
+        Self {
+            index,
+            pending: None,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+/// Buckets of the downsample filter per topic, shared between clones of the
+/// filter.
+#[derive(Clone, Debug, Default)]
+struct DownsampleState(Arc<Mutex<HashMap<String, DownsampleBucket>>>);
+
+impl PartialEq for DownsampleState {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// Forwards at most one message per topic and time bucket. Buckets are
+/// aligned to the unix epoch, e.g. full minutes for an interval of 60 s.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct FilterTypeDownsample {
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    interval: Duration,
+    #[serde(default)]
+    aggregation: DownsampleAggregation,
+    /// JSON pointer to the numeric field averaged by the mean aggregation,
+    /// e.g. `/temperature`
+    #[serde(default)]
+    field: Option<String>,
+    #[serde(skip)]
+    state: DownsampleState,
+}
+
+impl FilterTypeDownsample {
+    fn apply_at(
+        &self,
+        topic: &str,
+        data: PayloadFormat,
+        now: Duration,
+    ) -> Result<Vec<PayloadFormat>, FilterError> {
+        let index = now.as_millis() / self.interval.as_millis().max(1);
+        let mut buckets = self.state.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if self.aggregation == DownsampleAggregation::First {
+            if buckets
+                .get(topic)
+                .is_some_and(|bucket| bucket.index == index)
+            {
+                return Ok(vec![]);
+            }
+
+            buckets.insert(topic.to_string(), DownsampleBucket::new(index));
+            return Ok(vec![data]);
+        }
+
+        let (data, value) = if self.aggregation == DownsampleAggregation::Mean {
+            let field = self
+                .field
+                .as_ref()
+                .ok_or(FilterError::MissingDownsampleField)?;
+            let data = self.convert_payload_format(data, PayloadType::Json)?;
+            let value = match &data {
+                PayloadFormat::Json(json) => json.content().pointer(field).and_then(Value::as_f64),
+                _ => None,
+            };
+            (data, value)
+        } else {
+            (data, None)
+        };
+
+        let bucket = buckets
+            .entry(topic.to_string())
+            .or_insert_with(|| DownsampleBucket::new(index));
+
+        let mut result = vec![];
+        if bucket.index != index {
+            if let Some(pending) = bucket.pending.take() {
+                result.push(self.aggregate(pending, bucket));
+            }
+            *bucket = DownsampleBucket::new(index);
+        }
+
+        bucket.pending = Some(data);
+        if let Some(value) = value {
+            bucket.sum += value;
+            bucket.count += 1;
+        }
+
+        Ok(result)
+    }
+
+    fn aggregate(&self, pending: PayloadFormat, bucket: &DownsampleBucket) -> PayloadFormat {
+        let (PayloadFormat::Json(json), Some(field)) = (&pending, &self.field) else {
+            return pending;
+        };
+        if self.aggregation != DownsampleAggregation::Mean || bucket.count == 0 {
+            return pending;
+        }
+
+        let mut content = json.content().clone();
+        let mean = serde_json::Number::from_f64(bucket.sum / bucket.count as f64);
+        match (content.pointer_mut(field), mean) {
+            (Some(value), Some(mean)) => *value = Value::Number(mean),
+            _ => return pending,
+        }
+
+        PayloadFormat::Json(PayloadFormatJson::from(content))
+    }
+}
+
+impl FilterImpl for FilterTypeDownsample {
+    fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        self.apply_on_topic("", data)
+    }
+
+    fn apply_on_topic(
+        &self,
+        topic: &str,
+        data: PayloadFormat,
+    ) -> Result<Vec<PayloadFormat>, FilterError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.apply_at(topic, data, now)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, strum_macros::Display)]
 #[serde(tag = "type")]
 pub enum FilterType {
     #[serde(rename = "extract_json")]
     ExtractJson(FilterTypeExtractJson),
+    #[serde(rename = "flatten_json")]
+    FlattenJson(FilterTypeFlattenJson),
+    #[serde(rename = "downsample")]
+    Downsample(FilterTypeDownsample),
     #[serde(rename = "to_upper")]
     ToUpperCase(FilterTypeToUpperCase),
     #[serde(rename = "to_lower")]
@@ -209,6 +517,29 @@ pub enum FilterType {
     ToText(FilterTypeToText),
     #[serde(rename = "to_json")]
     ToJson(FilterTypeToJson),
+    #[serde(rename = "to_yaml")]
+    ToYaml(FilterTypeToYaml),
+    #[cfg(feature = "wasm")]
+    #[serde(rename = "wasm")]
+    Wasm(FilterTypeWasm),
+    #[serde(rename = "hmac_sign")]
+    HmacSign(FilterTypeHmacSign),
+    #[serde(rename = "hmac_verify")]
+    HmacVerify(FilterTypeHmacVerify),
+}
+
+impl Validate for FilterType {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            FilterType::HmacSign(value) => {
+                ValidationErrors::merge(Ok(()), "HmacSign", value.validate())
+            }
+            FilterType::HmacVerify(value) => {
+                ValidationErrors::merge(Ok(()), "HmacVerify", value.validate())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl Default for FilterType {
@@ -221,12 +552,30 @@ impl FilterImpl for FilterType {
     fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
         match self {
             FilterType::ExtractJson(filter) => filter.apply(data),
+            FilterType::FlattenJson(filter) => filter.apply(data),
+            FilterType::Downsample(filter) => filter.apply(data),
             FilterType::ToUpperCase(filter) => filter.apply(data),
             FilterType::ToLowerCase(filter) => filter.apply(data),
             FilterType::Prepend(filter) => filter.apply(data),
             FilterType::Append(filter) => filter.apply(data),
             FilterType::ToText(filter) => filter.apply(data),
             FilterType::ToJson(filter) => filter.apply(data),
+            FilterType::ToYaml(filter) => filter.apply(data),
+            #[cfg(feature = "wasm")]
+            FilterType::Wasm(filter) => filter.apply(data),
+            FilterType::HmacSign(filter) => filter.apply(data),
+            FilterType::HmacVerify(filter) => filter.apply(data),
+        }
+    }
+
+    fn apply_on_topic(
+        &self,
+        topic: &str,
+        data: PayloadFormat,
+    ) -> Result<Vec<PayloadFormat>, FilterError> {
+        match self {
+            FilterType::Downsample(filter) => filter.apply_on_topic(topic, data),
+            _ => self.apply(data),
         }
     }
 }
@@ -269,6 +618,24 @@ mod tests {
         assert_eq!("MQTli", result.content().get("name").unwrap());
     }
 
+    #[test]
+    fn to_yaml_split_documents() {
+        let filter = FilterTypeToYaml {
+            split_documents: true,
+        };
+        let payload = PayloadFormat::Text(PayloadFormatText::from("a: 1\n---\nb: 2\n"));
+
+        let result = filter.apply(payload);
+
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert_eq!(2, result.len());
+        let PayloadFormat::Yaml(result) = &result[1] else {
+            panic!()
+        };
+        assert_eq!("b: 2\n", result.to_string());
+    }
+
     #[test]
     fn to_upper() {
         let filter = FilterTypeToUpperCase::default();
@@ -305,6 +672,114 @@ mod tests {
         assert_eq!("MQTli", result.content());
     }
 
+    #[test]
+    fn flatten_json() {
+        let payload = PayloadFormat::Json(
+            PayloadFormatJson::try_from(
+                "{\"a\":{\"b\":{\"c\":1},\"d\":{}},\"items\":[{\"id\":1},2],\"e\":null}"
+                    .to_string(),
+            )
+            .unwrap(),
+        );
+
+        let filter = FilterTypeFlattenJson::default();
+        let PayloadFormat::Json(result) = filter.apply(payload.clone()).unwrap().remove(0) else {
+            panic!()
+        };
+        assert_eq!(
+            &serde_json::json!({"a.b.c": 1, "a.d": {}, "items": [{"id": 1}, 2], "e": null}),
+            result.content()
+        );
+
+        let filter = FilterTypeFlattenJson {
+            separator: String::from("_"),
+            explode_arrays: true,
+        };
+        let PayloadFormat::Json(result) = filter.apply(payload).unwrap().remove(0) else {
+            panic!()
+        };
+        assert_eq!(
+            &serde_json::json!({"a_b_c": 1, "a_d": {}, "items_0_id": 1, "items_1": 2, "e": null}),
+            result.content()
+        );
+    }
+
+    fn downsample(aggregation: DownsampleAggregation) -> FilterTypeDownsample {
+        FilterTypeDownsample {
+            interval: Duration::from_secs(10),
+            aggregation,
+            field: Some(String::from("/value")),
+            state: DownsampleState::default(),
+        }
+    }
+
+    fn value(value: f64) -> PayloadFormat {
+        PayloadFormat::Json(PayloadFormatJson::from(serde_json::json!({"value": value})))
+    }
+
+    fn values(result: Vec<PayloadFormat>) -> Vec<f64> {
+        result
+            .into_iter()
+            .map(|payload| {
+                let PayloadFormat::Json(json) = payload else {
+                    panic!()
+                };
+                json.content().pointer("/value").unwrap().as_f64().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn downsample_first() {
+        let filter = downsample(DownsampleAggregation::First);
+        let at = Duration::from_secs;
+
+        assert_eq!(
+            vec![1.0],
+            values(filter.apply_at("a", value(1.0), at(10)).unwrap())
+        );
+        assert!(filter.apply_at("a", value(2.0), at(19)).unwrap().is_empty());
+        assert_eq!(
+            vec![3.0],
+            values(filter.apply_at("b", value(3.0), at(19)).unwrap())
+        );
+        assert_eq!(
+            vec![4.0],
+            values(filter.apply_at("a", value(4.0), at(20)).unwrap())
+        );
+    }
+
+    #[test]
+    fn downsample_last_and_mean() {
+        let last = downsample(DownsampleAggregation::Last);
+        let mean = downsample(DownsampleAggregation::Mean);
+        let at = Duration::from_secs;
+
+        for (payload, time) in [(1.0, 10), (2.0, 12), (6.0, 19)] {
+            assert!(last
+                .apply_at("a", value(payload), at(time))
+                .unwrap()
+                .is_empty());
+            assert!(mean
+                .apply_at("a", value(payload), at(time))
+                .unwrap()
+                .is_empty());
+        }
+
+        assert_eq!(
+            vec![6.0],
+            values(last.apply_at("a", value(7.0), at(35)).unwrap())
+        );
+        assert_eq!(
+            vec![3.0],
+            values(mean.apply_at("a", value(7.0), at(35)).unwrap())
+        );
+        assert_eq!(
+            vec![7.0],
+            values(mean.apply_at("a", value(8.0), at(40)).unwrap())
+        );
+    }
+
     #[test]
     fn preprend_json_string() {
         let payload =
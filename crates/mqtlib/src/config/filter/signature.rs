@@ -0,0 +1,308 @@
+use crate::config::effective::redact_secret;
+use crate::config::filter::{FilterError, FilterImpl};
+use crate::config::PayloadType;
+use crate::payload::raw::PayloadFormatRaw;
+use crate::payload::text::PayloadFormatText;
+use crate::payload::PayloadFormat;
+use derive_getters::Getters;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Sha512};
+use tracing::warn;
+use validator::Validate;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum HmacAlgorithm {
+    #[default]
+    #[serde(rename = "sha256")]
+    #[strum(serialize = "sha256")]
+    Sha256,
+    #[serde(rename = "sha512")]
+    #[strum(serialize = "sha512")]
+    Sha512,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum HmacFailureAction {
+    /// Fails the filter, so the message is logged as error and sent to the
+    /// dead letter topic
+    #[default]
+    #[serde(rename = "error")]
+    #[strum(serialize = "error")]
+    Error,
+    /// Drops the message and logs a warning
+    #[serde(rename = "drop")]
+    #[strum(serialize = "drop")]
+    Drop,
+}
+
+/// Shared key and format of the signature, which is appended to the
+/// payload as hex string after the separator.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct HmacSignature {
+    #[serde(serialize_with = "redact_secret")]
+    key: String,
+    #[serde(default)]
+    algorithm: HmacAlgorithm,
+    #[serde(default = "HmacSignature::default_separator")]
+    #[validate(length(min = 1, message = "Separator of the HMAC signature must not be empty"))]
+    separator: String,
+}
+
+impl HmacSignature {
+    fn default_separator() -> String {
+        String::from(".")
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        match self.algorithm {
+            HmacAlgorithm::Sha256 => sign::<Hmac<Sha256>>(self.key.as_bytes(), payload),
+            HmacAlgorithm::Sha512 => sign::<Hmac<Sha512>>(self.key.as_bytes(), payload),
+        }
+    }
+
+    /// Splits the signature off the signed payload and returns the payload if
+    /// the signature is valid. Comparing the signature takes constant time.
+    fn verify<'a>(&self, signed: &'a [u8]) -> Option<&'a [u8]> {
+        let separator = self.separator.as_bytes();
+        let position = signed
+            .windows(separator.len())
+            .rposition(|window| window == separator)?;
+
+        let payload = &signed[..position];
+        let signature = hex::decode(&signed[position + separator.len()..]).ok()?;
+
+        let valid = match self.algorithm {
+            HmacAlgorithm::Sha256 => {
+                verify::<Hmac<Sha256>>(self.key.as_bytes(), payload, &signature)
+            }
+            HmacAlgorithm::Sha512 => {
+                verify::<Hmac<Sha512>>(self.key.as_bytes(), payload, &signature)
+            }
+        };
+
+        valid.then_some(payload)
+    }
+}
+
+fn new_mac<M: Mac + KeyInit>(key: &[u8], payload: &[u8]) -> M {
+    let mut mac = <M as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload);
+    mac
+}
+
+fn sign<M: Mac + KeyInit>(key: &[u8], payload: &[u8]) -> Vec<u8> {
+    new_mac::<M>(key, payload).finalize().into_bytes().to_vec()
+}
+
+fn verify<M: Mac + KeyInit>(key: &[u8], payload: &[u8], signature: &[u8]) -> bool {
+    new_mac::<M>(key, payload).verify_slice(signature).is_ok()
+}
+
+/// Returns the payload in the format of the filter input. Payloads which are
+/// no longer valid in that format, e.g. JSON with the signature appended,
+/// are returned as text, or as raw bytes if they aren't valid UTF-8.
+fn with_input_format(payload_type: PayloadType, payload: Vec<u8>) -> PayloadFormat {
+    if let Ok(formatted) = PayloadFormat::try_from((payload_type, payload.clone())) {
+        return formatted;
+    }
+
+    match std::str::from_utf8(&payload) {
+        Ok(_) => PayloadFormat::Text(PayloadFormatText::from(payload)),
+        Err(_) => PayloadFormat::Raw(PayloadFormatRaw::from(payload)),
+    }
+}
+
+/// Appends the HMAC signature of the payload, e.g. before publishing.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct FilterTypeHmacSign {
+    #[serde(flatten)]
+    #[validate(nested)]
+    signature: HmacSignature,
+}
+
+impl FilterImpl for FilterTypeHmacSign {
+    fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        let payload_type = PayloadType::from(data.clone());
+        let mut payload =
+            Vec::<u8>::try_from(data).map_err(|e| FilterError::PayloadFormatError(Box::new(e)))?;
+
+        let signature = hex::encode(self.signature.sign(&payload));
+        payload.extend(self.signature.separator.as_bytes());
+        payload.extend(signature.as_bytes());
+
+        Ok(vec![with_input_format(payload_type, payload)])
+    }
+}
+
+/// Verifies the HMAC signature appended to the payload and removes it.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct FilterTypeHmacVerify {
+    #[serde(flatten)]
+    #[validate(nested)]
+    signature: HmacSignature,
+    #[serde(default)]
+    on_failure: HmacFailureAction,
+}
+
+impl FilterImpl for FilterTypeHmacVerify {
+    fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        let payload_type = PayloadType::from(data.clone());
+        let signed =
+            Vec::<u8>::try_from(data).map_err(|e| FilterError::PayloadFormatError(Box::new(e)))?;
+
+        match self.signature.verify(&signed) {
+            Some(payload) => Ok(vec![with_input_format(payload_type, payload.to_vec())]),
+            None if self.on_failure == HmacFailureAction::Drop => {
+                warn!("Dropping message with missing or invalid HMAC signature");
+                Ok(vec![])
+            }
+            None => Err(FilterError::InvalidSignature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(algorithm: HmacAlgorithm) -> HmacSignature {
+        HmacSignature {
+            key: String::from("secret"),
+            algorithm,
+            separator: HmacSignature::default_separator(),
+        }
+    }
+
+    fn content(payloads: Vec<PayloadFormat>) -> Vec<String> {
+        payloads
+            .into_iter()
+            .map(|payload| String::try_from(payload).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        for algorithm in [HmacAlgorithm::Sha256, HmacAlgorithm::Sha512] {
+            let sign = FilterTypeHmacSign {
+                signature: signature(algorithm),
+            };
+            let verify = FilterTypeHmacVerify {
+                signature: signature(algorithm),
+                on_failure: HmacFailureAction::Error,
+            };
+
+            let signed = sign
+                .apply(PayloadFormat::Text(PayloadFormatText::from("a.b")))
+                .unwrap();
+            let verified = verify.apply(signed[0].clone()).unwrap();
+
+            assert_eq!(vec!["a.b".to_string()], content(verified));
+        }
+    }
+
+    #[test]
+    fn sign_sha256() {
+        // RFC 4231, test case 2
+        let sign = FilterTypeHmacSign {
+            signature: HmacSignature {
+                key: String::from("Jefe"),
+                algorithm: HmacAlgorithm::Sha256,
+                separator: HmacSignature::default_separator(),
+            },
+        };
+
+        let signed = sign
+            .apply(PayloadFormat::Text(PayloadFormatText::from(
+                "what do ya want for nothing?",
+            )))
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "what do ya want for nothing?.5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+                    .to_string()
+            ],
+            content(signed)
+        );
+    }
+
+    #[test]
+    fn keeps_input_format() {
+        let sign = FilterTypeHmacSign {
+            signature: signature(HmacAlgorithm::Sha256),
+        };
+        let verify = FilterTypeHmacVerify {
+            signature: signature(HmacAlgorithm::Sha256),
+            on_failure: HmacFailureAction::Error,
+        };
+
+        let binary = vec![0xff, 0x00, 0xfe];
+        let signed = sign
+            .apply(PayloadFormat::Raw(PayloadFormatRaw::from(binary.clone())))
+            .unwrap();
+        assert!(matches!(signed[0], PayloadFormat::Raw(_)));
+        let verified = verify.apply(signed[0].clone()).unwrap();
+        let PayloadFormat::Raw(verified) = verified[0].clone() else {
+            panic!()
+        };
+        assert_eq!(binary, Vec::<u8>::from(verified));
+
+        // signed JSON is no valid JSON anymore
+        let json = PayloadFormat::try_from((PayloadType::Json, Vec::from("{\"a\":1}"))).unwrap();
+        let signed = sign.apply(json).unwrap();
+        assert!(matches!(signed[0], PayloadFormat::Text(_)));
+    }
+
+    #[test]
+    fn empty_separator() {
+        let signature = HmacSignature {
+            separator: String::new(),
+            ..signature(HmacAlgorithm::Sha256)
+        };
+
+        assert!(signature.validate().is_err());
+    }
+
+    #[test]
+    fn verify_failure() {
+        let mut verify = FilterTypeHmacVerify {
+            signature: signature(HmacAlgorithm::Sha256),
+            on_failure: HmacFailureAction::Error,
+        };
+        let forged = format!("forged.{}", hex::encode(verify.signature.sign(b"payload")));
+
+        for payload in [forged.as_str(), "unsigned", "payload.zz"] {
+            let payload = PayloadFormat::Text(PayloadFormatText::from(payload));
+            assert!(matches!(
+                verify.apply(payload),
+                Err(FilterError::InvalidSignature)
+            ));
+        }
+
+        verify.on_failure = HmacFailureAction::Drop;
+        let payload = PayloadFormat::Text(PayloadFormatText::from(forged.as_str()));
+        assert!(verify.apply(payload).unwrap().is_empty());
+    }
+}
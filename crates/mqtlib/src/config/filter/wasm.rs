@@ -0,0 +1,260 @@
+use crate::config::filter::{FilterError, FilterImpl};
+use crate::config::PayloadType;
+use crate::payload::PayloadFormat;
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Amount of fuel a module may consume per message, stops endless loops.
+const MAX_FUEL: u64 = 100_000_000;
+/// Maximum size of the linear memory of a module.
+const MAX_MEMORY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Transforms payloads with a function exported by a WebAssembly module.
+///
+/// The module is instantiated for each message without any imports, so it
+/// can't access the file system, the network or the host environment. It must
+/// export:
+/// - `memory`: the linear memory
+/// - `alloc(len: i32) -> i32`: allocates `len` bytes and returns the pointer
+/// - `transform(ptr: i32, len: i32, meta_ptr: i32, meta_len: i32) -> i64`:
+///   receives the payload and the metadata as JSON, and returns the pointer
+///   (upper 32 bits) and length (lower 32 bits) of the result, or a negative
+///   value on error. The result is a sequence of payloads, each prefixed with
+///   its length as little endian u32.
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+pub struct FilterTypeWasm {
+    path: PathBuf,
+    #[serde(default = "FilterTypeWasm::default_function")]
+    function: String,
+    #[serde(default)]
+    format: PayloadType,
+    #[serde(skip)]
+    #[getter(skip)]
+    module: WasmModule,
+}
+
+impl FilterTypeWasm {
+    fn default_function() -> String {
+        String::from("transform")
+    }
+
+    fn compiled_module(&self) -> Result<&(Engine, Module), FilterError> {
+        self.module
+            .0
+            .get_or_init(|| load_module(&self.path).map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(|e| FilterError::WasmError(e.clone()))
+    }
+
+    fn transform(&self, payload: &[u8], metadata: &[u8]) -> Result<Vec<u8>, wasmtime::Error> {
+        let (engine, module) = self.compiled_module()?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_SIZE)
+            .build();
+        let mut store = Store::new(engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(MAX_FUEL)?;
+
+        let instance = Instance::new(&mut store, module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("Module does not export `memory`"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let transform = instance
+            .get_typed_func::<(i32, i32, i32, i32), i64>(&mut store, self.function.as_str())?;
+
+        let write = |store: &mut Store<StoreLimits>, data: &[u8]| {
+            let len = i32::try_from(data.len())?;
+            let ptr = alloc.call(&mut *store, len)?;
+            memory.write(&mut *store, ptr as u32 as usize, data)?;
+            Ok::<(i32, i32), wasmtime::Error>((ptr, len))
+        };
+
+        let (ptr, len) = write(&mut store, payload)?;
+        let (meta_ptr, meta_len) = write(&mut store, metadata)?;
+
+        let result = transform.call(&mut store, (ptr, len, meta_ptr, meta_len))?;
+        if result < 0 {
+            return Err(wasmtime::Error::msg(format!(
+                "Function `{}` returned error code {result}",
+                self.function
+            )));
+        }
+
+        let result_ptr = (result as u64 >> 32) as usize;
+        let result_len = (result as u64 & 0xFFFF_FFFF) as usize;
+        let mut output = vec![0; result_len];
+        memory.read(&store, result_ptr, &mut output)?;
+
+        Ok(output)
+    }
+}
+
+impl FilterImpl for FilterTypeWasm {
+    fn apply(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
+        let metadata = serde_json::json!({ "format": format_name(&data) }).to_string();
+        let payload =
+            Vec::<u8>::try_from(data).map_err(|e| FilterError::PayloadFormatError(Box::new(e)))?;
+
+        let output = self
+            .transform(&payload, metadata.as_bytes())
+            .map_err(|e| FilterError::WasmError(e.to_string()))?;
+
+        split_payloads(&output)?
+            .into_iter()
+            .map(|payload| {
+                PayloadFormat::try_from((self.format.clone(), payload))
+                    .map_err(|e| FilterError::PayloadFormatError(Box::new(e)))
+            })
+            .collect()
+    }
+}
+
+/// Compiled module, shared between all clones of the filter and compiled on
+/// first use.
+#[derive(Clone, Default)]
+struct WasmModule(Arc<OnceLock<Result<(Engine, Module), String>>>);
+
+impl Debug for WasmModule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WasmModule")
+    }
+}
+
+impl PartialEq for WasmModule {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+fn load_module(path: &Path) -> Result<(Engine, Module), wasmtime::Error> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+
+    let engine = Engine::new(&config)?;
+    let module = Module::from_file(&engine, path)?;
+
+    Ok((engine, module))
+}
+
+fn format_name(data: &PayloadFormat) -> &'static str {
+    match data {
+        PayloadFormat::Text(_) => "text",
+        PayloadFormat::Raw(_) => "raw",
+        PayloadFormat::Protobuf(_) => "protobuf",
+        PayloadFormat::Hex(_) => "hex",
+        PayloadFormat::Hexdump(_) => "hexdump",
+        PayloadFormat::Base64(_) => "base64",
+        PayloadFormat::Json(_) => "json",
+        PayloadFormat::Yaml(_) => "yaml",
+        PayloadFormat::Sparkplug(_) => "sparkplug",
+        PayloadFormat::SparkplugJson(_) => "sparkplug_json",
+    }
+}
+
+/// Splits the output of the module into payloads, each prefixed with its
+/// length as little endian u32.
+fn split_payloads(mut output: &[u8]) -> Result<Vec<Vec<u8>>, FilterError> {
+    let mut payloads = vec![];
+
+    while !output.is_empty() {
+        let (len, rest) = output
+            .split_first_chunk::<4>()
+            .ok_or_else(|| FilterError::WasmError("Incomplete payload length".into()))?;
+        let len = u32::from_le_bytes(*len) as usize;
+
+        if rest.len() < len {
+            return Err(FilterError::WasmError(format!(
+                "Payload length {len} exceeds the remaining {} bytes",
+                rest.len()
+            )));
+        }
+
+        let (payload, rest) = rest.split_at(len);
+        payloads.push(payload.to_vec());
+        output = rest;
+    }
+
+    Ok(payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::text::PayloadFormatText;
+
+    const ECHO_MODULE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "transform")
+            (param $ptr i32) (param $len i32) (param $meta_ptr i32) (param $meta_len i32)
+            (result i64)
+            (i32.store (i32.const 0) (local.get $len))
+            (memory.copy (i32.const 4) (local.get $ptr) (local.get $len))
+            (i64.extend_i32_u (i32.add (local.get $len) (i32.const 4))))
+          (func (export "fail")
+            (param i32) (param i32) (param i32) (param i32)
+            (result i64)
+            (i64.const -1)))
+    "#;
+
+    fn filter(function: &str) -> FilterTypeWasm {
+        let path = std::env::temp_dir().join(format!("mqtli-filter-{}.wat", uuid::Uuid::new_v4()));
+        std::fs::write(&path, ECHO_MODULE).unwrap();
+
+        FilterTypeWasm {
+            path,
+            function: function.into(),
+            format: PayloadType::Text(Default::default()),
+            module: WasmModule::default(),
+        }
+    }
+
+    #[test]
+    fn transform() {
+        let filter = filter("transform");
+        let payload = PayloadFormat::Text(PayloadFormatText::from("MQTli"));
+
+        let mut result = filter.apply(payload).unwrap();
+
+        assert_eq!(1, result.len());
+        let PayloadFormat::Text(result) = result.remove(0) else {
+            panic!()
+        };
+        assert_eq!("MQTli", result.to_string());
+    }
+
+    #[test]
+    fn transform_error() {
+        let filter = filter("fail");
+        let payload = PayloadFormat::Text(PayloadFormatText::from("MQTli"));
+
+        assert!(matches!(
+            filter.apply(payload),
+            Err(FilterError::WasmError(_))
+        ));
+    }
+
+    #[test]
+    fn split() {
+        let output = [2, 0, 0, 0, b'a', b'b', 0, 0, 0, 0, 1, 0, 0, 0, b'c'];
+
+        assert_eq!(
+            vec![b"ab".to_vec(), vec![], b"c".to_vec()],
+            split_payloads(&output).unwrap()
+        );
+        assert!(split_payloads(&[5, 0, 0, 0, b'a']).is_err());
+        assert!(split_payloads(&[1, 0]).is_err());
+    }
+}
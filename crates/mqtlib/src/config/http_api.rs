@@ -0,0 +1,9 @@
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use validator::Validate;
+
+#[derive(Clone, Debug, Deserialize, Getters, Serialize, Validate)]
+pub struct HttpApi {
+    pub listen: SocketAddr,
+}
@@ -0,0 +1,65 @@
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use strum_macros::EnumString;
+
+/// Format of the log messages.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    #[serde(rename = "text")]
+    #[strum(serialize = "text")]
+    Text,
+    /// One JSON object per line, e.g. for log shippers
+    #[serde(rename = "json")]
+    #[strum(serialize = "json")]
+    Json,
+}
+
+/// File the log messages are written to in addition to the terminal.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct LogFile {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub rotation: LogRotation,
+}
+
+/// Interval in which a new log file is started. The start of the interval
+/// is appended to the file name of rotated files.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum LogRotation {
+    #[default]
+    #[serde(rename = "never")]
+    #[strum(serialize = "never")]
+    Never,
+    #[serde(rename = "minutely")]
+    #[strum(serialize = "minutely")]
+    Minutely,
+    #[serde(rename = "hourly")]
+    #[strum(serialize = "hourly")]
+    Hourly,
+    #[serde(rename = "daily")]
+    #[strum(serialize = "daily")]
+    Daily,
+}
@@ -1,28 +1,46 @@
-use crate::mqtt::QoS;
+use crate::config::effective::{redact_headers, redact_secret_option};
+use crate::config::publish::{deserialize_duration_milliseconds, serialize_duration_milliseconds};
+use crate::mqtt::{PayloadFormatIndicator, QoS};
 use crate::payload::PayloadFormat;
 use derive_getters::Getters;
 use serde::de::{Error, Unexpected};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
-use strum_macros::EnumString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use strum_macros::{EnumString, IntoStaticStr};
 use validator::{Validate, ValidationError, ValidationErrors};
 
+pub mod alert;
+pub mod bridge;
+pub mod channels;
+pub mod connection_hooks;
+pub mod dead_letter;
+pub mod effective;
 pub mod filter;
+pub mod http_api;
+pub mod logging;
 pub mod mqtli_config;
+pub mod ping;
+pub mod proto_check;
 pub mod publish;
+pub mod secret;
+pub mod self_test;
+pub mod sparkplug;
 pub mod sql_storage;
 pub mod subscription;
 pub mod topic;
+pub mod topic_stats;
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, EnumString)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, EnumString, IntoStaticStr, Serialize)]
 #[serde(tag = "type")]
 pub enum PayloadType {
     #[serde(rename = "text")]
     #[strum(serialize = "text")]
     #[default]
-    Text,
+    Text(PayloadText),
     #[serde(rename = "protobuf")]
     #[strum(serialize = "protobuf")]
     Protobuf(PayloadProtobuf),
@@ -35,9 +53,12 @@ pub enum PayloadType {
     #[serde(rename = "hex")]
     #[strum(serialize = "hex")]
     Hex,
+    #[serde(rename = "hexdump")]
+    #[strum(serialize = "hexdump")]
+    Hexdump,
     #[serde(rename = "base64")]
     #[strum(serialize = "base64")]
-    Base64,
+    Base64(PayloadBase64),
     #[serde(rename = "raw")]
     #[strum(serialize = "raw")]
     Raw,
@@ -47,6 +68,29 @@ pub enum PayloadType {
     #[serde(rename = "sparkplug_json")]
     #[strum(serialize = "sparkplug_json")]
     SparkplugJson,
+    /// Detects the format of each payload: JSON, text, protobuf without a
+    /// definition or hex
+    #[serde(rename = "auto")]
+    #[strum(serialize = "auto")]
+    Auto,
+}
+
+impl PayloadType {
+    /// Returns whether payloads of this type are UTF-8 encoded text.
+    pub fn payload_format_indicator(&self) -> PayloadFormatIndicator {
+        match self {
+            PayloadType::Text(text) if text.encoding == TextEncoding::Utf8 => {
+                PayloadFormatIndicator::Utf8
+            }
+            PayloadType::Json
+            | PayloadType::Yaml
+            | PayloadType::Hex
+            | PayloadType::Hexdump
+            | PayloadType::Base64(_)
+            | PayloadType::SparkplugJson => PayloadFormatIndicator::Utf8,
+            _ => PayloadFormatIndicator::Unspecified,
+        }
+    }
 }
 
 impl Display for PayloadType {
@@ -55,8 +99,8 @@ impl Display for PayloadType {
             PayloadType::Protobuf(value) => {
                 write!(f, "Protobuf [Options: {}]", value)
             }
-            PayloadType::Text => {
-                write!(f, "Text")
+            PayloadType::Text(value) => {
+                write!(f, "Text [Options: {}]", value)
             }
             PayloadType::Json => {
                 write!(f, "Json")
@@ -67,14 +111,18 @@ impl Display for PayloadType {
             PayloadType::Hex => {
                 write!(f, "Hex")
             }
-            PayloadType::Base64 => {
-                write!(f, "Base64")
+            PayloadType::Hexdump => {
+                write!(f, "Hexdump")
+            }
+            PayloadType::Base64(value) => {
+                write!(f, "Base64 [Options: {}]", value)
             }
             PayloadType::Raw => {
                 write!(f, "Raw")
             }
             PayloadType::Sparkplug => write!(f, "Sparkplug"),
             PayloadType::SparkplugJson => write!(f, "Sparkplug Json"),
+            PayloadType::Auto => write!(f, "Auto"),
         }
     }
 }
@@ -82,11 +130,14 @@ impl Display for PayloadType {
 impl From<PayloadFormat> for PayloadType {
     fn from(value: PayloadFormat) -> Self {
         match value {
-            PayloadFormat::Text(_) => PayloadType::Text,
+            PayloadFormat::Text(value) => PayloadType::Text(PayloadText {
+                encoding: *value.encoding(),
+            }),
             PayloadFormat::Raw(_) => PayloadType::Raw,
             PayloadFormat::Protobuf(_) => PayloadType::Protobuf(Default::default()),
             PayloadFormat::Hex(_) => PayloadType::Hex,
-            PayloadFormat::Base64(_) => PayloadType::Base64,
+            PayloadFormat::Hexdump(_) => PayloadType::Hexdump,
+            PayloadFormat::Base64(_) => PayloadType::Base64(Default::default()),
             PayloadFormat::Json(_) => PayloadType::Json,
             PayloadFormat::Yaml(_) => PayloadType::Yaml,
             PayloadFormat::Sparkplug(_) => PayloadType::Sparkplug,
@@ -95,20 +146,171 @@ impl From<PayloadFormat> for PayloadType {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+pub struct PayloadText {
+    /// Character encoding of the text
+    #[serde(default)]
+    encoding: TextEncoding,
+}
+
+impl Display for PayloadText {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "encoding: {}", self.encoding)
+    }
+}
+
+/// Character encoding of text payloads.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum TextEncoding {
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    #[strum(to_string = "utf-8", serialize = "utf8")]
+    Utf8,
+    /// ISO 8859-1, one byte per character
+    #[serde(rename = "latin1", alias = "iso-8859-1")]
+    #[strum(to_string = "latin1", serialize = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "utf-16le")]
+    #[strum(serialize = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    #[strum(serialize = "utf-16be")]
+    Utf16Be,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
 pub struct PayloadProtobuf {
     definition: PathBuf,
+    #[serde(default)]
     message: String,
+    /// Further messages tried in order when a received payload can't be
+    /// parsed as `message`, for topics carrying several message types
+    #[serde(default)]
+    messages: Vec<String>,
+    /// Only accepts a candidate message if the payload has no fields unknown
+    /// to it and all its required fields are set
+    #[serde(default)]
+    strict: bool,
+    /// Shows the fields of the payload which are unknown to the message in
+    /// the text and JSON output instead of dropping them
+    #[serde(default)]
+    show_unknown: bool,
+    /// Rendering of the message when it is output as text
+    #[serde(default)]
+    render: ProtobufRender,
+}
+
+impl PayloadProtobuf {
+    /// Names of the messages tried in order when decoding a received payload.
+    pub fn candidates(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.message)
+            .filter(|message| !message.is_empty())
+            .chain(self.messages.iter())
+    }
+
+    /// Message used when converting other formats to protobuf.
+    pub fn primary_message(&self) -> &str {
+        self.candidates()
+            .next()
+            .map_or("", |message| message.as_str())
+    }
 }
 
 impl Display for PayloadProtobuf {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "definition: {:?}", self.definition)?;
-        write!(f, "message: {:?}", self.message)
+        write!(f, "message: {:?}", self.message)?;
+        write!(f, "messages: {:?}", self.messages)?;
+        write!(f, "strict: {}", self.strict)?;
+        write!(f, "show unknown: {}", self.show_unknown)?;
+        write!(f, "render: {}", self.render)
     }
 }
 
-#[derive(Clone, Debug, Deserialize, strum_macros::Display, EnumString)]
+/// Text representation of a Protobuf message.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum ProtobufRender {
+    /// Protobuf text format, one field per line
+    #[default]
+    #[serde(rename = "text")]
+    #[strum(serialize = "text")]
+    Text,
+    /// JSON on a single line
+    #[serde(rename = "json")]
+    #[strum(serialize = "json")]
+    Json,
+    /// Indented JSON
+    #[serde(rename = "json_pretty")]
+    #[strum(serialize = "json_pretty")]
+    JsonPretty,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+pub struct PayloadBase64 {
+    /// Alphabet and padding used when encoding
+    #[serde(default)]
+    variant: Base64Variant,
+}
+
+impl Display for PayloadBase64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "variant: {}", self.variant)
+    }
+}
+
+/// Alphabet and padding of base64 encoded content, see RFC 4648.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum Base64Variant {
+    /// Standard alphabet with padding
+    #[default]
+    #[serde(rename = "standard")]
+    #[strum(serialize = "standard")]
+    Standard,
+    /// Standard alphabet without padding
+    #[serde(rename = "standard_no_pad")]
+    #[strum(serialize = "standard_no_pad")]
+    StandardNoPad,
+    /// URL and filename safe alphabet with padding
+    #[serde(rename = "url_safe")]
+    #[strum(serialize = "url_safe")]
+    UrlSafe,
+    /// URL and filename safe alphabet without padding
+    #[serde(rename = "url_safe_no_pad")]
+    #[strum(serialize = "url_safe_no_pad")]
+    UrlSafeNoPad,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, strum_macros::Display, EnumString)]
 #[serde(tag = "type")]
 pub enum PublishInputType {
     #[serde(rename = "text")]
@@ -129,6 +331,27 @@ pub enum PublishInputType {
     #[serde(rename = "base64")]
     #[strum(serialize = "base64")]
     Base64(PublishInputTypeContentPath),
+    #[serde(rename = "directory")]
+    #[strum(serialize = "directory")]
+    Directory(PublishInputTypeDirectory),
+    #[serde(rename = "csv")]
+    #[strum(serialize = "csv")]
+    Csv(PublishInputTypeCsv),
+    #[serde(rename = "ndjson")]
+    #[strum(serialize = "ndjson")]
+    Ndjson(PublishInputTypeNdjson),
+    #[serde(rename = "http")]
+    #[strum(serialize = "http")]
+    Http(PublishInputTypeHttp),
+    #[serde(rename = "command")]
+    #[strum(serialize = "command")]
+    Command(PublishInputTypeCommand),
+    #[serde(rename = "env")]
+    #[strum(serialize = "env")]
+    Env(PublishInputTypeEnv),
+    #[serde(rename = "messages")]
+    #[strum(serialize = "messages")]
+    Messages(PublishInputTypeMessages),
     #[serde(rename = "null")]
     #[strum(serialize = "null")]
     Null,
@@ -140,6 +363,31 @@ impl Default for PublishInputType {
     }
 }
 
+impl PublishInputType {
+    /// Input reading the file, with the format derived from the file
+    /// extension. Files with unknown extensions are read as raw payload.
+    pub fn from_file(path: &Path) -> Self {
+        let input = PublishInputTypeContentPath {
+            content: None,
+            path: Some(path.to_path_buf()),
+        };
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "txt" | "text" => Self::Text(input),
+            "json" | "json5" | "jsonc" => Self::Json(input),
+            "yaml" | "yml" => Self::Yaml(input),
+            "hex" => Self::Hex(input),
+            "base64" | "b64" => Self::Base64(input),
+            _ => Self::Raw(input.into()),
+        }
+    }
+}
+
 impl Validate for PublishInputType {
     fn validate(&self) -> Result<(), ValidationErrors> {
         match self {
@@ -161,14 +409,36 @@ impl Validate for PublishInputType {
             PublishInputType::Base64(value) => {
                 ValidationErrors::merge(Ok(()), "Base64", value.validate())
             }
+            PublishInputType::Directory(value) => {
+                ValidationErrors::merge(Ok(()), "Directory", value.validate())
+            }
+            PublishInputType::Csv(value) => {
+                ValidationErrors::merge(Ok(()), "Csv", value.validate())
+            }
+            PublishInputType::Ndjson(value) => {
+                ValidationErrors::merge(Ok(()), "Ndjson", value.validate())
+            }
+            PublishInputType::Http(value) => {
+                ValidationErrors::merge(Ok(()), "Http", value.validate())
+            }
+            PublishInputType::Command(value) => {
+                ValidationErrors::merge(Ok(()), "Command", value.validate())
+            }
+            PublishInputType::Env(value) => {
+                ValidationErrors::merge(Ok(()), "Env", value.validate())
+            }
+            PublishInputType::Messages(value) => {
+                ValidationErrors::merge(Ok(()), "Messages", value.validate())
+            }
             PublishInputType::Null => ValidationErrors::merge(Ok(()), "Null", Ok(())),
         }
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, Serialize)]
 pub struct PublishInputTypeContentPath {
     #[serde(deserialize_with = "parse_string_as_vec")]
+    #[serde(serialize_with = "serialize_vec_as_string")]
     pub content: Option<Vec<u8>>,
     pub path: Option<PathBuf>,
 }
@@ -181,6 +451,16 @@ where
     Ok(Some(value.into_bytes()))
 }
 
+fn serialize_vec_as_string<S>(value: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(&String::from_utf8_lossy(value)),
+        None => serializer.serialize_none(),
+    }
+}
+
 impl Validate for PublishInputTypeContentPath {
     fn validate(&self) -> Result<(), ValidationErrors> {
         let mut err = ValidationError::new("invalid_publish_input");
@@ -198,7 +478,7 @@ impl Validate for PublishInputTypeContentPath {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, Validate)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, Serialize, Validate)]
 pub struct PublishInputTypePath {
     path: PathBuf,
 }
@@ -211,6 +491,234 @@ impl From<PublishInputTypeContentPath> for PublishInputTypePath {
     }
 }
 
+/// Publishes the files of a directory one after another, sorted by file
+/// name. The format of each file is derived from its extension.
+#[derive(Clone, Debug, Default, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeDirectory {
+    path: PathBuf,
+    #[serde(default)]
+    on_end: DirectoryEnd,
+}
+
+impl From<PathBuf> for PublishInputTypeDirectory {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            on_end: DirectoryEnd::default(),
+        }
+    }
+}
+
+/// Publishes the rows of a CSV file one after another, each as JSON object
+/// with the column names of the header row as fields.
+#[derive(Clone, Debug, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeCsv {
+    path: PathBuf,
+    #[serde(default = "default_csv_delimiter")]
+    delimiter: char,
+    /// Publish numbers and booleans as such instead of as strings
+    #[serde(default = "default_csv_infer_types")]
+    infer_types: bool,
+    #[serde(default)]
+    on_end: DirectoryEnd,
+}
+
+impl Default for PublishInputTypeCsv {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            delimiter: default_csv_delimiter(),
+            infer_types: default_csv_infer_types(),
+            on_end: DirectoryEnd::default(),
+        }
+    }
+}
+
+/// Publishes each row once.
+impl From<PathBuf> for PublishInputTypeCsv {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            on_end: DirectoryEnd::Stop,
+            ..Self::default()
+        }
+    }
+}
+
+/// Publishes the payloads of several inputs one after another, in the order
+/// they are given.
+#[derive(Clone, Debug, Default, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeMessages {
+    #[validate(
+        length(min = 1, message = "At least one message must be given"),
+        nested
+    )]
+    messages: Vec<PublishInputType>,
+    #[serde(default)]
+    on_end: DirectoryEnd,
+}
+
+impl PublishInputTypeMessages {
+    pub fn new(messages: Vec<PublishInputType>, on_end: DirectoryEnd) -> Self {
+        Self { messages, on_end }
+    }
+}
+
+fn default_csv_delimiter() -> char {
+    ','
+}
+
+fn default_csv_infer_types() -> bool {
+    true
+}
+
+/// Publishes the lines of a newline-delimited JSON file one after another.
+/// The topic and QoS of each message may be read from fields of its line.
+#[derive(Clone, Debug, Default, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeNdjson {
+    path: PathBuf,
+    /// Field with the topic to publish the line to instead of the topic
+    topic_field: Option<String>,
+    /// Field with the QoS to publish the line with instead of the QoS of
+    /// the publish
+    qos_field: Option<String>,
+    /// Field with the payload, the whole line if not given
+    payload_field: Option<String>,
+    #[serde(default)]
+    on_end: DirectoryEnd,
+}
+
+/// Publishes each line once.
+impl From<PathBuf> for PublishInputTypeNdjson {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            on_end: DirectoryEnd::Stop,
+            ..Self::default()
+        }
+    }
+}
+
+/// Fetches the payload from an HTTP(S) URL each time the trigger fires.
+/// The response body is interpreted according to the format, or its content
+/// type if no format is given.
+#[derive(Clone, Debug, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeHttp {
+    #[validate(url)]
+    url: String,
+    /// Headers sent with the request
+    #[serde(default)]
+    #[serde(serialize_with = "redact_headers")]
+    headers: BTreeMap<String, String>,
+    /// User for basic authentication
+    username: Option<String>,
+    /// Password for basic authentication
+    #[serde(serialize_with = "redact_secret_option")]
+    password: Option<String>,
+    /// Token for bearer authentication
+    #[serde(serialize_with = "redact_secret_option")]
+    bearer_token: Option<String>,
+    #[serde(default = "default_input_timeout")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    timeout: Duration,
+    format: Option<PayloadType>,
+}
+
+impl Default for PublishInputTypeHttp {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            headers: BTreeMap::new(),
+            username: None,
+            password: None,
+            bearer_token: None,
+            timeout: default_input_timeout(),
+            format: None,
+        }
+    }
+}
+
+/// Uses the content as URL.
+impl From<PublishInputTypeContentPath> for PublishInputTypeHttp {
+    fn from(value: PublishInputTypeContentPath) -> Self {
+        Self {
+            url: String::from_utf8_lossy(&value.content.unwrap_or_default()).to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Runs the command in the shell each time the trigger fires and publishes
+/// its output.
+#[derive(Clone, Debug, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeCommand {
+    #[validate(length(min = 1))]
+    command: String,
+    #[serde(default = "default_input_timeout")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    timeout: Duration,
+    /// Payload type of the output, text if not given
+    format: Option<PayloadType>,
+}
+
+impl Default for PublishInputTypeCommand {
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            timeout: default_input_timeout(),
+            format: None,
+        }
+    }
+}
+
+/// Uses the content as command.
+impl From<PublishInputTypeContentPath> for PublishInputTypeCommand {
+    fn from(value: PublishInputTypeContentPath) -> Self {
+        Self {
+            command: String::from_utf8_lossy(&value.content.unwrap_or_default()).to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Reads the payload from an environment variable, once at startup or each
+/// time the trigger fires.
+#[derive(Clone, Debug, Default, Deserialize, Getters, Serialize, Validate)]
+pub struct PublishInputTypeEnv {
+    #[validate(length(min = 1))]
+    variable: String,
+    /// Read the variable each time the trigger fires instead of at startup
+    #[serde(default)]
+    per_trigger: bool,
+    /// Payload type of the value, text if not given
+    format: Option<PayloadType>,
+}
+
+/// Uses the content as name of the variable.
+impl From<PublishInputTypeContentPath> for PublishInputTypeEnv {
+    fn from(value: PublishInputTypeContentPath) -> Self {
+        Self {
+            variable: String::from_utf8_lossy(&value.content.unwrap_or_default()).to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+fn default_input_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum DirectoryEnd {
+    #[default]
+    #[serde(rename = "loop")]
+    Loop,
+    #[serde(rename = "stop")]
+    Stop,
+}
+
 pub fn deserialize_qos<'a, D>(deserializer: D) -> Result<QoS, D::Error>
 where
     D: Deserializer<'a>,
@@ -231,3 +739,28 @@ where
         &"unsigned integer between 0 and 2",
     ))
 }
+
+pub fn deserialize_qos_option<'a, D>(deserializer: D) -> Result<Option<QoS>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    Ok(Some(deserialize_qos(deserializer)?))
+}
+
+/// Serializes the QoS as number, like it is given in the config file.
+pub fn serialize_qos<S>(value: &QoS, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u8(*value as u8)
+}
+
+pub fn serialize_qos_option<S>(value: &Option<QoS>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(&(*value as u8)),
+        None => serializer.serialize_none(),
+    }
+}
@@ -1,26 +1,176 @@
+use crate::config::alert::AlertRule;
+use crate::config::bridge::Bridge;
+use crate::config::channels::Channels;
+use crate::config::connection_hooks::ConnectionHooks;
+use crate::config::dead_letter::DeadLetter;
+use crate::config::effective::redact_secret_option;
+use crate::config::http_api::HttpApi;
+use crate::config::logging::{LogFile, LogFormat};
+use crate::config::ping::Ping;
+use crate::config::proto_check::ProtoCheck;
+use crate::config::publish::serialize_duration_milliseconds;
+use crate::config::self_test::SelfTest;
+use crate::config::serialize_qos;
+use crate::config::sparkplug::Sparkplug;
 use crate::config::sql_storage::SqlStorage;
+use crate::config::subscription::PayloadLimit;
 use crate::config::topic::TopicStorage;
+use crate::config::topic_stats::TopicStats;
+use crate::config::PayloadType;
 use crate::mqtt::QoS;
+use crate::payload::{PayloadFormat, PayloadFormatError};
+use crate::publish::trigger_periodic::rate_interval;
+use crate::template;
 use derive_builder::Builder;
 use derive_getters::Getters;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, Serializer};
 use std::borrow::Cow;
 use std::fmt::{Debug, Display, Formatter};
 use std::path::PathBuf;
 use std::time::Duration;
+use strum_macros::EnumString;
 use tracing::Level;
 use validator::{Validate, ValidationError};
 
-#[derive(Debug, Getters, Validate, Builder)]
+#[derive(Debug, Getters, Serialize, Validate, Builder)]
 pub struct MqtliConfig {
     #[validate(nested)]
     pub broker: MqttBrokerConnect,
+    #[serde(serialize_with = "serialize_level")]
     pub log_level: Level,
+    /// Log level directives per module, e.g. `rumqttc=warn,mqtlib::storage=debug`,
+    /// which override the log level for the matching modules
+    pub log_filter: Option<String>,
+    pub log_format: LogFormat,
+    /// Also writes the log messages to this file
+    pub log_file: Option<LogFile>,
+    /// Disables colored output on the console
+    pub no_color: bool,
+    /// Colors used for messages and Sparkplug output on the console
+    pub theme: Theme,
+    /// Print statistics of the received messages in this interval instead
+    /// of the messages themselves
+    #[validate(custom(
+        function = "validate_stats_interval",
+        message = "Statistics interval must be at least 1 second"
+    ))]
+    #[serde(serialize_with = "serialize_duration_seconds_option")]
+    pub stats_interval: Option<Duration>,
+    /// Limits the size of received payloads of all subscriptions without
+    /// their own limit
+    #[validate(nested)]
+    pub payload_limit: Option<PayloadLimit>,
+    /// Number of tasks converting, filtering and writing the received
+    /// messages concurrently; messages of a topic are kept in order
+    #[validate(range(min = 1, message = "Number of workers must be at least 1"))]
+    pub workers: usize,
+    #[validate(nested)]
+    pub channels: Channels,
+    /// Topic on which commands to control mqtli while running are received
+    #[validate(custom(
+        function = "validate_control_topic",
+        message = "Control topic must not be empty or contain wildcards"
+    ))]
+    pub control_topic: Option<String>,
+    /// Republishes messages which failed conversion to this topic
+    #[validate(nested)]
+    pub dead_letter: Option<DeadLetter>,
+    /// Maximum time to wait on exit for received messages to be written and
+    /// publishes to be acknowledged before disconnecting anyway
+    #[serde(serialize_with = "serialize_duration_seconds")]
+    pub shutdown_timeout: Duration,
+    /// Maximum time the whole shutdown may take before the process exits
+    /// immediately
+    #[serde(serialize_with = "serialize_duration_seconds_option")]
+    pub force_quit_timeout: Option<Duration>,
+    /// What to do once all counted periodic publishes were sent
+    pub on_publish_complete: OnPublishComplete,
+    /// Maximum number of messages per second published by periodic
+    /// triggers; messages due earlier are delayed
+    #[validate(custom(function = "validate_publish_rate"))]
+    pub publish_rate: Option<f64>,
+    /// Notifies systemd when ready and stopping and sends watchdog keepalives
+    pub systemd: bool,
+    /// Prints the capabilities the broker announced in the CONNACK
+    pub show_broker_info: bool,
+    /// Template of the client id of dedicated connections, supporting the
+    /// placeholders {{client_id}} and {{connection}}
+    #[validate(length(min = 1, message = "Client id of connections must be given"))]
+    pub connection_client_id: String,
+    /// Number of parallel clients running the configured topics
+    #[validate(range(min = 1, message = "Number of clients must be at least 1"))]
+    pub clients: usize,
+    /// Client id of each of several parallel clients, may contain the
+    /// placeholders {{client_id}} and {{index}}
+    #[validate(length(min = 1, message = "Client id of clients must be given"))]
+    pub clients_client_id: String,
+    /// Commands and messages run on connect and disconnect
+    pub connection_hooks: ConnectionHooks,
+    /// Rules running actions when messages meet a condition
+    #[validate(nested)]
+    pub alerts: Vec<AlertRule>,
+    #[serde(flatten)]
     #[validate(nested)]
     pub topic_storage: TopicStorage,
+    #[serde(skip)]
     pub mode: Mode,
     #[validate(nested)]
     pub sql_storage: Option<SqlStorage>,
+    #[validate(nested)]
+    pub http_api: Option<HttpApi>,
+    #[serde(skip)]
+    #[validate(nested)]
+    pub sparkplug: Sparkplug,
+    #[serde(skip)]
+    #[validate(nested)]
+    pub ping: Ping,
+    #[validate(nested)]
+    pub bridge: Bridge,
+    #[serde(skip)]
+    #[validate(nested)]
+    pub topic_stats: TopicStats,
+    #[serde(skip)]
+    #[validate(nested)]
+    pub self_test: SelfTest,
+    #[serde(skip)]
+    pub proto_check: ProtoCheck,
+}
+
+impl MqtliConfig {
+    /// Returns the broker configuration of the dedicated connection with the
+    /// given name. Last will, birth message and outgoing queue are only used
+    /// by the default connection.
+    pub fn dedicated_connection(&self, name: &str) -> MqttBrokerConnect {
+        MqttBrokerConnect {
+            client_id: template::render(
+                &self.connection_client_id,
+                |placeholder| match placeholder {
+                    "client_id" => Some(self.broker.client_id.clone()),
+                    "connection" => Some(name.to_string()),
+                    placeholder => template::common(placeholder),
+                },
+            )
+            .into_owned(),
+            last_will: None,
+            birth: None,
+            outgoing_queue: None,
+            ..self.broker.clone()
+        }
+    }
+
+    /// Configuration of the parallel client with the given index, starting
+    /// at 1. Dedicated connections derive their client id from the one of
+    /// the client.
+    pub fn client(&self, index: usize) -> MqtliConfig {
+        let mut config = self.clone();
+        config.broker.client_id = template::render(&self.clients_client_id, |name| match name {
+            "client_id" => Some(self.broker.client_id.clone()),
+            "index" => Some(index.to_string()),
+            name => template::common(name),
+        })
+        .into_owned();
+        config
+    }
 }
 
 impl Display for MqtliConfig {
@@ -40,20 +190,120 @@ impl Default for MqtliConfig {
         Self {
             broker: Default::default(),
             log_level: Level::INFO,
+            log_filter: None,
+            log_format: LogFormat::default(),
+            log_file: None,
+            no_color: false,
+            theme: Theme::default(),
+            stats_interval: None,
+            payload_limit: None,
+            workers: 1,
+            channels: Channels::default(),
+            control_topic: None,
+            dead_letter: None,
+            shutdown_timeout: Duration::from_secs(5),
+            force_quit_timeout: None,
+            on_publish_complete: OnPublishComplete::default(),
+            publish_rate: None,
+            systemd: false,
+            show_broker_info: false,
+            connection_client_id: "{{client_id}}-{{connection}}".to_string(),
+            clients: 1,
+            clients_client_id: "{{client_id}}-{{index}}".to_string(),
+            connection_hooks: ConnectionHooks::default(),
+            alerts: vec![],
             topic_storage: TopicStorage::default(),
             mode: Default::default(),
             sql_storage: Default::default(),
+            http_api: None,
+            sparkplug: Sparkplug::default(),
+            ping: Ping::default(),
+            bridge: Bridge::default(),
+            topic_stats: TopicStats::default(),
+            self_test: SelfTest::default(),
+            proto_check: ProtoCheck::default(),
         }
     }
 }
 
+/// Colors of the console output, chosen to be readable on the background of
+/// the terminal.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum Theme {
+    /// Colors for terminals with a dark background
+    #[default]
+    #[serde(rename = "dark")]
+    #[strum(serialize = "dark")]
+    Dark,
+    /// Colors for terminals with a light background
+    #[serde(rename = "light")]
+    #[strum(serialize = "light")]
+    Light,
+    /// No colors
+    #[serde(rename = "none")]
+    #[strum(serialize = "none")]
+    None,
+}
+
+/// Behavior after all counted periodic publishes were sent.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum OnPublishComplete {
+    /// Exit unless there are subscriptions, on message triggers or a control
+    /// topic, otherwise stay connected
+    #[default]
+    #[serde(rename = "auto")]
+    #[strum(serialize = "auto")]
+    Auto,
+    /// Exit even if there are subscriptions
+    #[serde(rename = "exit")]
+    #[strum(serialize = "exit")]
+    Exit,
+    /// Stay connected until the process is stopped
+    #[serde(rename = "stay")]
+    #[strum(serialize = "stay")]
+    Stay,
+    /// Exit and print the number of published messages per topic
+    #[serde(rename = "summary")]
+    #[strum(serialize = "summary")]
+    Summary,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, PartialEq)]
 pub enum Mode {
     #[default]
     MultiTopic,
     Publish,
     Subscribe,
+    Copy,
     Sparkplug,
+    SparkplugEmulator,
+    SparkplugCheck,
+    Ping,
+    Bridge,
+    TopicStats,
+    SelfTest,
+    ProtoCheck,
+    ConfigShow,
 }
 
 impl Display for Mode {
@@ -62,12 +312,21 @@ impl Display for Mode {
             Mode::MultiTopic => write!(f, "Multi-Topic"),
             Mode::Publish => write!(f, "Publish"),
             Mode::Subscribe => write!(f, "Subscribe"),
+            Mode::Copy => write!(f, "Copy"),
             Mode::Sparkplug => write!(f, "Sparkplug"),
+            Mode::SparkplugEmulator => write!(f, "Sparkplug emulator"),
+            Mode::SparkplugCheck => write!(f, "Sparkplug conformance check"),
+            Mode::Ping => write!(f, "Ping"),
+            Mode::Bridge => write!(f, "Bridge"),
+            Mode::TopicStats => write!(f, "Topic statistics"),
+            Mode::SelfTest => write!(f, "Self test"),
+            Mode::ProtoCheck => write!(f, "Protobuf check"),
+            Mode::ConfigShow => write!(f, "Config show"),
         }
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub enum TlsVersion {
     #[default]
     #[serde(rename = "all")]
@@ -78,7 +337,7 @@ pub enum TlsVersion {
     Version1_3,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub enum MqttVersion {
     #[serde(rename = "v311")]
     V311,
@@ -88,7 +347,7 @@ pub enum MqttVersion {
     V5,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub enum MqttProtocol {
     #[default]
     #[serde(rename = "tcp")]
@@ -98,7 +357,7 @@ pub enum MqttProtocol {
     Websocket,
 }
 
-#[derive(Clone, Debug, Getters, Validate, Builder)]
+#[derive(Clone, Debug, Getters, Serialize, Validate, Builder)]
 #[validate(schema(function = "validate_credentials", skip_on_field_errors = false))]
 #[validate(schema(function = "validate_tls_client"))]
 pub struct MqttBrokerConnect {
@@ -114,18 +373,58 @@ pub struct MqttBrokerConnect {
         function = "validate_keep_alive",
         message = "Keep alive must be a number and at least 5 seconds"
     ))]
+    #[serde(serialize_with = "serialize_duration_seconds")]
     pub keep_alive: Duration,
+    /// Exit with an error if the broker didn't acknowledge the first connect
+    /// within this time
+    #[serde(serialize_with = "serialize_duration_seconds_option")]
+    pub connect_timeout: Option<Duration>,
+    /// Reconnect at most this often after the connection failed or was
+    /// lost, then exit with an error; without it, mqtli exits right away
+    pub max_reconnect_attempts: Option<u32>,
     pub username: Option<String>,
+    #[serde(serialize_with = "redact_secret_option")]
     pub password: Option<String>,
 
     pub use_tls: bool,
     pub tls_ca_file: Option<PathBuf>,
     pub tls_client_certificate: Option<PathBuf>,
     pub tls_client_key: Option<PathBuf>,
+    /// Decrypts the client key if it is an encrypted PKCS#8 key
+    #[serde(serialize_with = "redact_secret_option")]
+    pub tls_client_key_passphrase: Option<String>,
     pub tls_version: TlsVersion,
 
     #[validate(nested)]
     pub last_will: Option<LastWillConfig>,
+    /// Message published after each successful connect, e.g. to mark the
+    /// client as online on an availability topic
+    #[validate(nested)]
+    pub birth: Option<BirthConfig>,
+    /// Topic on which the connection lifecycle events are published as JSON
+    #[validate(nested)]
+    pub status: Option<StatusConfig>,
+    /// SQLite database keeping QoS 1 and 2 publishes until they are
+    /// acknowledged, so they are published again after a restart
+    pub outgoing_queue: Option<PathBuf>,
+
+    /// Maximum number of unacknowledged QoS 1 and 2 publishes the broker may
+    /// send to this client (MQTT v5 only)
+    #[validate(range(min = 1, message = "Receive maximum must be at least 1"))]
+    pub receive_maximum: Option<u16>,
+    /// Maximum size in bytes of a packet sent to or received from the broker
+    #[validate(range(min = 1, message = "Maximum packet size must be at least 1 byte"))]
+    pub maximum_packet_size: Option<u32>,
+    /// Maximum number of unacknowledged QoS 1 and 2 publishes sent by this
+    /// client
+    #[validate(range(min = 1, message = "Inflight must be at least 1"))]
+    pub inflight: Option<u16>,
+    /// Close the connection if the broker grants a lower QoS than requested
+    /// for a subscription or rejects it, instead of only warning
+    pub strict_qos: bool,
+    /// Randomly disturbs the connection and the publishes
+    #[validate(nested)]
+    pub chaos: Option<ChaosConfig>,
 }
 
 impl Default for MqttBrokerConnect {
@@ -137,27 +436,196 @@ impl Default for MqttBrokerConnect {
             client_id: "mqtli".to_string(),
             mqtt_version: MqttVersion::V5,
             keep_alive: Duration::from_secs(5),
+            connect_timeout: None,
+            max_reconnect_attempts: None,
             username: None,
             password: None,
             use_tls: false,
             tls_ca_file: None,
             tls_client_certificate: None,
             tls_client_key: None,
+            tls_client_key_passphrase: None,
             tls_version: Default::default(),
             last_will: None,
+            birth: None,
+            status: None,
+            outgoing_queue: None,
+            receive_maximum: None,
+            maximum_packet_size: None,
+            inflight: None,
+            strict_qos: false,
+            chaos: None,
         }
     }
 }
 
-#[derive(Clone, Debug, Default, Getters, Validate, Builder)]
+#[derive(Clone, Debug, Default, Getters, Serialize, Validate, Builder)]
 pub struct LastWillConfig {
     #[validate(length(min = 1, message = "Last will topic must be given"))]
     pub topic: String,
+    #[serde(serialize_with = "serialize_payload")]
+    pub payload: Vec<u8>,
+    /// Format of the payload as it is configured; it is converted to the
+    /// payload type of the topic before connecting
+    pub payload_format: Option<PayloadType>,
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    pub retain: bool,
+    /// Delay after which the broker publishes the will message once the
+    /// connection is lost (MQTT v5 only)
+    #[serde(serialize_with = "serialize_duration_seconds_option")]
+    pub delay_interval: Option<Duration>,
+    /// Content type of the will message (MQTT v5 only)
+    pub content_type: Option<String>,
+    /// User properties of the will message (MQTT v5 only)
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl LastWillConfig {
+    /// Checks if any of the will properties is set, which are only sent with
+    /// MQTT v5.
+    pub fn has_properties(&self) -> bool {
+        self.delay_interval.is_some()
+            || self.content_type.is_some()
+            || !self.user_properties.is_empty()
+    }
+
+    /// Converts the payload from its configured format to the payload type of
+    /// the first topic matching the last will topic, e.g. JSON to protobuf.
+    /// Without a matching topic the payload is converted to its own format.
+    /// The payload is sent as it is if no format is configured.
+    pub fn convert_payload(
+        &mut self,
+        topic_storage: &TopicStorage,
+    ) -> Result<(), PayloadFormatError> {
+        let Some(payload_format) = self.payload_format.take() else {
+            return Ok(());
+        };
+
+        let payload_type = topic_storage
+            .topics
+            .iter()
+            .find(|topic| topic.contains(&self.topic))
+            .map(|topic| topic.payload_type_of(&self.topic).clone())
+            .unwrap_or_else(|| payload_format.clone());
+
+        let payload = PayloadFormat::try_from((payload_format, std::mem::take(&mut self.payload)))?;
+        self.payload = PayloadFormat::try_from((payload, &payload_type))?.try_into()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Getters, Serialize, Validate, Builder)]
+pub struct BirthConfig {
+    #[validate(length(min = 1, message = "Birth topic must be given"))]
+    pub topic: String,
+    #[serde(serialize_with = "serialize_payload")]
     pub payload: Vec<u8>,
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// Topic on which mqtli publishes its own connection lifecycle events, e.g.
+/// to monitor a bridge over MQTT.
+#[derive(Clone, Debug, Default, Getters, Serialize, Validate, Builder)]
+pub struct StatusConfig {
+    #[validate(length(min = 1, message = "Status topic must be given"))]
+    pub topic: String,
+    #[serde(serialize_with = "serialize_qos")]
     pub qos: QoS,
     pub retain: bool,
 }
 
+/// Probabilities of the disturbances of chaos mode, which tests how
+/// applications cope with an unreliable connection.
+#[derive(Clone, Debug, Getters, Serialize, Validate, Builder)]
+pub struct ChaosConfig {
+    /// Probability per second that the connection is dropped without
+    /// disconnecting; the client connects again right away
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Chaos probabilities must be between 0 and 1"
+    ))]
+    pub drop_connection: f64,
+    /// Probability that a publish is dropped instead of being sent
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Chaos probabilities must be between 0 and 1"
+    ))]
+    pub drop_publish: f64,
+    /// Probability that a publish is delayed
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Chaos probabilities must be between 0 and 1"
+    ))]
+    pub delay_publish: f64,
+    /// Maximum delay of a delayed publish, each delay is random up to it
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub max_delay: Duration,
+    /// Probability that a QoS 1 publish is sent twice
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Chaos probabilities must be between 0 and 1"
+    ))]
+    pub duplicate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            drop_connection: 0.01,
+            drop_publish: 0.0,
+            delay_publish: 0.1,
+            max_delay: Duration::from_secs(2),
+            duplicate: 0.05,
+        }
+    }
+}
+
+fn serialize_level<S>(value: &Level, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&value.as_str().to_lowercase())
+}
+
+pub(crate) fn serialize_duration_seconds<S>(
+    value: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u64(value.as_secs())
+}
+
+pub(crate) fn serialize_duration_seconds_option<S>(
+    value: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(&value.as_secs()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes a payload as text, replacing invalid UTF-8 sequences.
+fn serialize_payload<S>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&String::from_utf8_lossy(value))
+}
+
 fn validate_keep_alive(value: &Duration) -> Result<(), ValidationError> {
     if value.as_secs() >= 5 {
         return Ok(());
@@ -169,6 +637,35 @@ fn validate_keep_alive(value: &Duration) -> Result<(), ValidationError> {
     Err(err)
 }
 
+fn validate_publish_rate(value: &f64) -> Result<(), ValidationError> {
+    if rate_interval(*value).is_some() {
+        return Ok(());
+    }
+
+    let mut err = ValidationError::new("wrong_publish_rate");
+    err.message = Some(Cow::from(
+        "Publish rate must be greater than 0 and at most 1000000000 messages per second",
+    ));
+
+    Err(err)
+}
+
+fn validate_control_topic(value: &str) -> Result<(), ValidationError> {
+    if !value.is_empty() && !value.contains(['+', '#']) {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_control_topic"))
+}
+
+fn validate_stats_interval(value: &Duration) -> Result<(), ValidationError> {
+    if value.as_secs() >= 1 {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_stats_interval"))
+}
+
 fn validate_credentials(value: &MqttBrokerConnect) -> Result<(), ValidationError> {
     let mut err = ValidationError::new("wrong_credentials");
 
@@ -0,0 +1,33 @@
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use std::time::Duration;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Measures the latency of the broker: the time until the connection is
+/// acknowledged and the round trip of messages published on a topic the
+/// client subscribed to.
+#[derive(Clone, Debug, Getters, Validate)]
+pub struct Ping {
+    #[validate(range(min = 1, message = "Ping count must be at least 1"))]
+    pub count: usize,
+    /// Time between two messages
+    pub interval: Duration,
+    /// Time to wait for a message to come back before it counts as lost
+    pub timeout: Duration,
+    #[validate(length(min = 1, message = "Ping topic must be given"))]
+    pub topic: String,
+    pub qos: QoS,
+}
+
+impl Default for Ping {
+    fn default() -> Self {
+        Self {
+            count: 5,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+            topic: format!("mqtli/ping/{}", Uuid::new_v4()),
+            qos: QoS::AtMostOnce,
+        }
+    }
+}
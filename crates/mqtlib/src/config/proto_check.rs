@@ -0,0 +1,12 @@
+use derive_getters::Getters;
+use std::path::PathBuf;
+
+/// Checks the protobuf definitions referenced from the configuration before
+/// going live.
+#[derive(Clone, Debug, Default, Getters)]
+pub struct ProtoCheck {
+    /// Payload decoded with each referenced message to verify it matches
+    pub sample: Option<PathBuf>,
+    /// Only the definitions referenced by this topic are checked
+    pub topic: Option<String>,
+}
@@ -1,35 +1,45 @@
-use crate::config::deserialize_qos;
 use crate::config::filter::{FilterError, FilterTypes};
 use crate::config::PublishInputType;
+use crate::config::{deserialize_qos, serialize_qos};
 use crate::mqtt::QoS;
 use crate::payload::{PayloadFormat, PayloadFormatError};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, Timelike, Utc};
 use derive_builder::Builder;
 use derive_getters::Getters;
 use derive_new::new;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use std::time::Duration;
-use validator::Validate;
+use validator::{Validate, ValidationError, ValidationErrors};
 
-#[derive(Builder, Clone, Debug, Deserialize, Getters, Validate)]
+#[derive(Builder, Clone, Debug, Deserialize, Getters, Serialize, Validate)]
 pub struct Publish {
     #[serde(default)]
     enabled: bool,
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
     qos: QoS,
     #[serde(default)]
     retain: bool,
     #[serde(default)]
+    #[validate(nested)]
     trigger: Vec<PublishTriggerType>,
     #[validate(nested)]
     input: PublishInputType,
     #[serde(default)]
+    #[validate(nested)]
     filters: FilterTypes,
 }
 
 impl Publish {
+    pub fn disable(&mut self) {
+        self.enabled = false;
+    }
+
     pub fn apply_filters(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
         self.filters.apply(data)
     }
@@ -82,15 +92,55 @@ impl Default for Publish {
     }
 }
 
-#[derive(Builder, Clone, Debug, Deserialize, Getters, Validate, new)]
+#[derive(Builder, Clone, Debug, Deserialize, Getters, Serialize, Validate, new)]
+#[validate(schema(function = "validate_jitter"))]
+#[validate(schema(function = "validate_align_to"))]
 pub struct PublishTriggerTypePeriodic {
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
     interval: Duration,
     count: Option<u32>,
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
     initial_delay: Duration,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    jitter: Duration,
+    #[serde(default)]
+    start_at: Option<TriggerTime>,
+    #[serde(default)]
+    end_at: Option<TriggerTime>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds_option")]
+    #[serde(serialize_with = "serialize_duration_milliseconds_option")]
+    align_to: Option<Duration>,
+}
+
+impl PublishTriggerTypePeriodic {
+    pub fn window(&self) -> TriggerWindow {
+        TriggerWindow::new(self.start_at.clone(), self.end_at.clone())
+    }
+
+    /// Returns the delay before the first publish. If the trigger is aligned,
+    /// the first publish happens at the first boundary of the local time of
+    /// day after the initial delay, so later publishes fire at predictable
+    /// wall-clock times.
+    pub fn start_delay(&self, now: &DateTime<Local>) -> Duration {
+        let align_to = match self.align_to {
+            Some(align_to) if !align_to.is_zero() => align_to.as_millis(),
+            _ => return self.initial_delay,
+        };
+
+        let since_midnight = u128::from(now.num_seconds_from_midnight()) * 1000
+            + u128::from(now.nanosecond() / 1_000_000);
+        let remainder = (since_midnight + self.initial_delay.as_millis()) % align_to;
+        let until_boundary = (align_to - remainder) % align_to;
+
+        self.initial_delay + Duration::from_millis(until_boundary as u64)
+    }
 }
 
 impl Default for PublishTriggerTypePeriodic {
@@ -99,15 +149,184 @@ impl Default for PublishTriggerTypePeriodic {
             interval: Duration::from_secs(1),
             count: None,
             initial_delay: Duration::from_millis(1000),
+            jitter: Duration::ZERO,
+            start_at: None,
+            end_at: None,
+            align_to: None,
+        }
+    }
+}
+
+/// A point in time limiting when a trigger is active. Either an absolute
+/// point in time or a time of day which applies to every day.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriggerTime {
+    Absolute(DateTime<Utc>),
+    TimeOfDay(NaiveTime),
+}
+
+impl TriggerTime {
+    fn has_passed(&self, now: &DateTime<Local>) -> bool {
+        match self {
+            TriggerTime::Absolute(value) => now.with_timezone(&Utc) >= *value,
+            TriggerTime::TimeOfDay(value) => now.time() >= *value,
+        }
+    }
+}
+
+/// Parses an absolute point in time in RFC 3339 format (e.g.
+/// `2025-01-01T08:00:00+01:00`) or without offset in local time (e.g.
+/// `2025-01-01 08:00:00`), or a time of day (e.g. `08:00` or `08:00:30`).
+impl FromStr for TriggerTime {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+
+        if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+            return Ok(TriggerTime::Absolute(date_time.with_timezone(&Utc)));
+        }
+
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+            if let Ok(date_time) = NaiveDateTime::parse_from_str(value, format) {
+                if let Some(date_time) = date_time.and_local_timezone(Local).earliest() {
+                    return Ok(TriggerTime::Absolute(date_time.with_timezone(&Utc)));
+                }
+            }
+        }
+
+        for format in ["%H:%M:%S", "%H:%M"] {
+            if let Ok(time) = NaiveTime::parse_from_str(value, format) {
+                return Ok(TriggerTime::TimeOfDay(time));
+            }
+        }
+
+        Err(format!(
+            "{value} is neither a valid point in time nor a valid time of day"
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for TriggerTime {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value: String = Deserialize::deserialize(deserializer)?;
+        TriggerTime::from_str(value.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes absolute points in time in RFC 3339 format and times of day
+/// with seconds, both of which are parsed again.
+impl Serialize for TriggerTime {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            TriggerTime::Absolute(value) => serializer.serialize_str(&value.to_rfc3339()),
+            TriggerTime::TimeOfDay(value) => serializer.collect_str(&value.format("%H:%M:%S")),
+        }
+    }
+}
+
+/// Window in which a trigger is allowed to publish. If both bounds are a
+/// time of day and the start is after the end, the window spans midnight.
+#[derive(Clone, Debug, Default, PartialEq, new)]
+pub struct TriggerWindow {
+    start_at: Option<TriggerTime>,
+    end_at: Option<TriggerTime>,
+}
+
+impl TriggerWindow {
+    pub fn is_active(&self, now: &DateTime<Local>) -> bool {
+        match (&self.start_at, &self.end_at) {
+            (Some(TriggerTime::TimeOfDay(start)), Some(TriggerTime::TimeOfDay(end)))
+                if start > end =>
+            {
+                now.time() >= *start || now.time() < *end
+            }
+            (start_at, end_at) => {
+                start_at
+                    .as_ref()
+                    .map_or(true, |start| start.has_passed(now))
+                    && end_at.as_ref().map_or(true, |end| !end.has_passed(now))
+            }
+        }
+    }
+
+    /// Returns true if the window ended at an absolute point in time and
+    /// will thus never become active again.
+    pub fn is_expired(&self, now: &DateTime<Local>) -> bool {
+        matches!(&self.end_at, Some(end @ TriggerTime::Absolute(_)) if end.has_passed(now))
+    }
+}
+
+fn validate_jitter(value: &PublishTriggerTypePeriodic) -> Result<(), ValidationError> {
+    if value.jitter * 2 < value.interval || value.jitter.is_zero() {
+        return Ok(());
+    }
+
+    let mut err = ValidationError::new("wrong_jitter");
+    err.message = Some(Cow::from(
+        "Jitter of periodic trigger must be less than half of the interval",
+    ));
+
+    Err(err)
+}
+
+/// Boundaries are computed from the local time of day, so the alignment must
+/// divide a day evenly to be the same every day.
+fn validate_align_to(value: &PublishTriggerTypePeriodic) -> Result<(), ValidationError> {
+    match value.align_to {
+        Some(align_to) if align_to.is_zero() || DAY.as_millis() % align_to.as_millis() != 0 => {
+            let mut err = ValidationError::new("wrong_align_to");
+            err.message = Some(Cow::from(
+                "Alignment of periodic trigger must be a divisor of a day in milliseconds",
+            ));
+
+            Err(err)
         }
+        _ => Ok(()),
     }
 }
 
-#[derive(Clone, Debug, Deserialize, strum_macros::Display)]
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Builder, Clone, Debug, Default, Deserialize, Getters, Serialize, Validate, new)]
+pub struct PublishTriggerTypeOnMessage {
+    #[validate(length(min = 1, message = "Topic of on_message trigger must be given"))]
+    topic: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
+    qos: QoS,
+    #[serde(default)]
+    #[validate(nested)]
+    filters: FilterTypes,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, strum_macros::Display)]
 #[serde(tag = "type")]
 pub enum PublishTriggerType {
     #[serde(rename = "periodic")]
     Periodic(PublishTriggerTypePeriodic),
+    #[serde(rename = "on_message")]
+    OnMessage(PublishTriggerTypeOnMessage),
+}
+
+impl Validate for PublishTriggerType {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            PublishTriggerType::Periodic(value) => {
+                ValidationErrors::merge(Ok(()), "Periodic", value.validate())
+            }
+            PublishTriggerType::OnMessage(value) => {
+                ValidationErrors::merge(Ok(()), "OnMessage", value.validate())
+            }
+        }
+    }
 }
 
 impl Default for PublishTriggerType {
@@ -123,3 +342,171 @@ where
     let value: u64 = Deserialize::deserialize(deserializer)?;
     Ok(Duration::from_millis(value))
 }
+
+pub fn deserialize_duration_milliseconds_option<'a, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let value: Option<u64> = Deserialize::deserialize(deserializer)?;
+    Ok(value.map(Duration::from_millis))
+}
+
+pub fn serialize_duration_milliseconds<S>(
+    value: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u64(value.as_millis() as u64)
+}
+
+pub fn serialize_duration_milliseconds_option<S>(
+    value: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(&(value.as_millis() as u64)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn validate_jitter() {
+        let trigger = |interval, jitter| {
+            PublishTriggerTypePeriodic::new(
+                Duration::from_millis(interval),
+                None,
+                Duration::ZERO,
+                Duration::from_millis(jitter),
+                None,
+                None,
+                None,
+            )
+        };
+
+        assert!(trigger(1000, 0).validate().is_ok());
+        assert!(trigger(1000, 499).validate().is_ok());
+        assert!(trigger(1000, 500).validate().is_err());
+        assert!(trigger(0, 0).validate().is_ok());
+    }
+
+    #[test]
+    fn start_delay_aligned() {
+        let trigger = |initial_delay, align_to: Option<u64>| {
+            PublishTriggerTypePeriodic::new(
+                Duration::from_secs(60),
+                None,
+                Duration::from_millis(initial_delay),
+                Duration::ZERO,
+                None,
+                None,
+                align_to.map(Duration::from_millis),
+            )
+        };
+        let now = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_milli_opt(8, 15, 30, 250)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap();
+
+        assert_eq!(
+            Duration::from_millis(1000),
+            trigger(1000, None).start_delay(&now)
+        );
+        assert_eq!(
+            Duration::from_millis(29_750),
+            trigger(1000, Some(60_000)).start_delay(&now)
+        );
+        assert_eq!(
+            Duration::from_millis(60_000 + 29_750),
+            trigger(40_000, Some(60_000)).start_delay(&now)
+        );
+        assert_eq!(
+            Duration::from_millis(44 * 60_000 + 29_750),
+            trigger(0, Some(3_600_000)).start_delay(&now)
+        );
+        assert!(trigger(0, Some(60_000)).validate().is_ok());
+        assert!(trigger(0, Some(7_000)).validate().is_err());
+    }
+
+    #[test]
+    fn parse_trigger_time() {
+        assert_eq!(
+            TriggerTime::TimeOfDay(NaiveTime::from_hms_opt(8, 30, 0).unwrap()),
+            TriggerTime::from_str("08:30").unwrap()
+        );
+        assert_eq!(
+            TriggerTime::TimeOfDay(NaiveTime::from_hms_opt(17, 0, 15).unwrap()),
+            TriggerTime::from_str("17:00:15").unwrap()
+        );
+        assert_eq!(
+            TriggerTime::Absolute(
+                DateTime::parse_from_rfc3339("2025-01-01T07:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            ),
+            TriggerTime::from_str("2025-01-01T08:00:00+01:00").unwrap()
+        );
+        assert!(TriggerTime::from_str("2025-01-01 08:00:00").is_ok());
+        assert!(TriggerTime::from_str("tomorrow").is_err());
+    }
+
+    #[test]
+    fn window_time_of_day() {
+        let at = |h, m| {
+            NaiveDate::from_ymd_opt(2025, 1, 1)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .earliest()
+                .unwrap()
+        };
+
+        let window = TriggerWindow::new(
+            Some(TriggerTime::from_str("08:00").unwrap()),
+            Some(TriggerTime::from_str("17:00").unwrap()),
+        );
+        assert!(!window.is_active(&at(7, 59)));
+        assert!(window.is_active(&at(8, 0)));
+        assert!(window.is_active(&at(16, 59)));
+        assert!(!window.is_active(&at(17, 0)));
+        assert!(!window.is_expired(&at(17, 0)));
+
+        let window = TriggerWindow::new(
+            Some(TriggerTime::from_str("22:00").unwrap()),
+            Some(TriggerTime::from_str("06:00").unwrap()),
+        );
+        assert!(window.is_active(&at(23, 0)));
+        assert!(window.is_active(&at(5, 0)));
+        assert!(!window.is_active(&at(12, 0)));
+    }
+
+    #[test]
+    fn window_absolute() {
+        let now = Local::now();
+        let window = TriggerWindow::new(
+            None,
+            Some(TriggerTime::Absolute(
+                (now - chrono::Duration::seconds(1)).with_timezone(&Utc),
+            )),
+        );
+
+        assert!(!window.is_active(&now));
+        assert!(window.is_expired(&now));
+        assert!(TriggerWindow::default().is_active(&now));
+    }
+}
@@ -0,0 +1,173 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::process::{Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("Could not run secret command \"{1}\"")]
+    CommandNotExecutable(#[source] io::Error, String),
+    #[error("Secret command \"{0}\" failed with {1}")]
+    CommandFailed(String, ExitStatus),
+    #[error("Output of secret command \"{0}\" is not valid UTF-8")]
+    CommandOutputNotUtf8(String),
+    #[cfg(feature = "keyring")]
+    #[error("Could not read secret from keyring entry \"{1}\"")]
+    KeyringNotReadable(#[source] keyring::Error, KeyringEntry),
+    #[cfg(not(feature = "keyring"))]
+    #[error(
+        "Could not read secret from keyring entry \"{0}\", mqtlib is built without feature keyring"
+    )]
+    KeyringNotSupported(KeyringEntry),
+    #[error("Keyring entry \"{0}\" must have the format service:user")]
+    InvalidKeyringEntry(String),
+}
+
+/// Entry in the keyring of the operating system, given as `service:user`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyringEntry {
+    pub service: String,
+    pub user: String,
+}
+
+impl KeyringEntry {
+    #[cfg(feature = "keyring")]
+    pub fn read(&self) -> Result<String, SecretError> {
+        keyring::Entry::new(&self.service, &self.user)
+            .and_then(|entry| entry.get_password())
+            .map_err(|e| SecretError::KeyringNotReadable(e, self.clone()))
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    pub fn read(&self) -> Result<String, SecretError> {
+        Err(SecretError::KeyringNotSupported(self.clone()))
+    }
+}
+
+impl FromStr for KeyringEntry {
+    type Err = SecretError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some((service, user)) if !service.is_empty() && !user.is_empty() => Ok(Self {
+                service: service.to_string(),
+                user: user.to_string(),
+            }),
+            _ => Err(SecretError::InvalidKeyringEntry(value.to_string())),
+        }
+    }
+}
+
+impl Display for KeyringEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.service, self.user)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyringEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        KeyringEntry::from_str(&value).map_err(D::Error::custom)
+    }
+}
+
+/// Runs the command in the shell and returns its output without the trailing
+/// line break. Stdin and stderr are passed through, so the command can ask
+/// for a master password.
+pub fn read_secret_from_command(command: &str) -> Result<String, SecretError> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    let output = shell
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| SecretError::CommandNotExecutable(e, command.to_string()))?;
+
+    if !output.status.success() {
+        return Err(SecretError::CommandFailed(
+            command.to_string(),
+            output.status,
+        ));
+    }
+
+    let secret = String::from_utf8(output.stdout)
+        .map_err(|_| SecretError::CommandOutputNotUtf8(command.to_string()))?;
+
+    Ok(secret.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Returns the secret given directly, else the output of the command, else
+/// the secret from the keyring entry.
+pub fn resolve_secret(
+    value: Option<String>,
+    command: Option<&str>,
+    keyring: Option<&KeyringEntry>,
+) -> Result<Option<String>, SecretError> {
+    if value.is_some() {
+        return Ok(value);
+    }
+
+    if let Some(command) = command {
+        return read_secret_from_command(command).map(Some);
+    }
+
+    keyring.map(KeyringEntry::read).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keyring_entry() {
+        assert_eq!(
+            KeyringEntry {
+                service: "mqtli".to_string(),
+                user: "broker:admin".to_string()
+            },
+            KeyringEntry::from_str("mqtli:broker:admin").unwrap()
+        );
+        assert!(KeyringEntry::from_str("mqtli").is_err());
+        assert!(KeyringEntry::from_str(":admin").is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn secret_from_command() {
+        assert_eq!(
+            Some("s3cret".to_string()),
+            resolve_secret(None, Some("echo s3cret"), None).unwrap()
+        );
+        assert!(matches!(
+            read_secret_from_command("exit 1"),
+            Err(SecretError::CommandFailed(_, _))
+        ));
+    }
+
+    #[test]
+    fn secret_given_directly() {
+        assert_eq!(
+            Some("plain".to_string()),
+            resolve_secret(Some("plain".to_string()), Some("exit 1"), None).unwrap()
+        );
+        assert_eq!(None, resolve_secret(None, None, None).unwrap());
+    }
+}
@@ -0,0 +1,37 @@
+use crate::config::PayloadType;
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use serde_json::{json, Value};
+use std::time::Duration;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Checks the connection and credentials: a message is published on a
+/// temporary topic the client subscribed to and must come back unchanged
+/// after converting it to the payload type and back.
+#[derive(Clone, Debug, Getters, Validate)]
+pub struct SelfTest {
+    #[validate(length(min = 1, message = "Self test topic must be given"))]
+    pub topic: String,
+    pub qos: QoS,
+    /// Time to wait for each step before the test fails
+    pub timeout: Duration,
+    /// Payload type the message is published as
+    pub payload_type: PayloadType,
+    /// Content of the message, converted to the payload type
+    pub content: Value,
+}
+
+impl Default for SelfTest {
+    fn default() -> Self {
+        let id = Uuid::new_v4();
+
+        Self {
+            topic: format!("mqtli/selftest/{id}"),
+            qos: QoS::AtLeastOnce,
+            timeout: Duration::from_secs(5),
+            payload_type: PayloadType::Json,
+            content: json!({"mqtli": "selftest", "id": id.to_string()}),
+        }
+    }
+}
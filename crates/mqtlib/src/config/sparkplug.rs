@@ -0,0 +1,303 @@
+use crate::mqtt::QoS;
+use crate::sparkplug::{EdgeNodeId, GroupId};
+use derive_getters::Getters;
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use strum_macros::EnumString;
+use validator::{Validate, ValidationError};
+
+#[derive(Clone, Debug, Default, Getters, Validate)]
+pub struct Sparkplug {
+    /// Publish a Node Control/Rebirth command to edge nodes whose data
+    /// messages can't be resolved because no birth message was seen.
+    pub request_rebirth: bool,
+    /// Show the network in an interactive terminal UI.
+    pub tui: bool,
+    /// Store all messages in the normalized Sparkplug schema of the SQL
+    /// storage.
+    pub store_sql: bool,
+    /// Print a summary of each group in this interval.
+    #[validate(custom(
+        function = "validate_summary_interval",
+        message = "Summary interval must be at least 1 second"
+    ))]
+    pub summary_interval: Option<Duration>,
+    #[validate(nested)]
+    pub snapshot: Option<SparkplugSnapshotExport>,
+    #[validate(nested)]
+    pub emulator: Option<SparkplugEmulator>,
+    pub metric_filter: SparkplugMetricFilter,
+    #[validate(nested)]
+    pub primary_host: Option<SparkplugPrimaryHost>,
+    #[validate(nested)]
+    pub check: SparkplugCheck,
+}
+
+/// Observes the traffic of groups and validates it against the Sparkplug B
+/// specification.
+#[derive(Clone, Debug, Getters, Validate)]
+pub struct SparkplugCheck {
+    /// Groups to observe, all groups if empty
+    pub groups: Vec<GroupId>,
+    /// Time to observe before the report is printed, until exit if not given
+    #[validate(custom(
+        function = "validate_check_duration",
+        message = "Check duration must be at least 1 second"
+    ))]
+    pub duration: Option<Duration>,
+    pub qos: QoS,
+}
+
+impl Default for SparkplugCheck {
+    fn default() -> Self {
+        Self {
+            groups: vec![],
+            duration: None,
+            qos: QoS::AtLeastOnce,
+        }
+    }
+}
+
+fn validate_check_duration(value: &Duration) -> Result<(), ValidationError> {
+    if value.as_secs() >= 1 {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_check_duration"))
+}
+
+/// Primary host application whose STATE messages are tracked.
+#[derive(Clone, Debug, Default, Getters, Validate)]
+pub struct SparkplugPrimaryHost {
+    #[validate(length(min = 1, message = "Primary host id must be given"))]
+    pub host_id: String,
+    /// Highlight on the console when the primary host goes offline
+    pub alert_console: bool,
+    /// URL a JSON notification is posted to when the primary host goes
+    /// offline
+    pub webhook: Option<String>,
+}
+
+/// Selects metrics by their names using glob patterns, where `*` matches
+/// any number of characters and `?` exactly one. A metric is included if it
+/// matches any include pattern (or none are given) and no exclude pattern.
+#[derive(Clone, Debug, Default, Getters, PartialEq)]
+pub struct SparkplugMetricFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl SparkplugMetricFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn is_included(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_matches(p, name)))
+            && !self.exclude.iter().any(|p| glob_matches(p, name))
+    }
+}
+
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    // position of the last * in the pattern and the value position it matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Emulates an edge node which publishes the given metrics.
+#[derive(Clone, Debug, Getters, Validate)]
+pub struct SparkplugEmulator {
+    #[validate(length(min = 1, message = "Group id must be given"))]
+    pub group_id: GroupId,
+    #[validate(length(min = 1, message = "Edge node id must be given"))]
+    pub edge_node_id: EdgeNodeId,
+    #[validate(custom(
+        function = "validate_emulator_interval",
+        message = "Emulator interval must be at least 100 milliseconds"
+    ))]
+    pub interval: Duration,
+    pub metrics: Vec<EmulatedMetric>,
+}
+
+/// Metric of an emulated edge node, given as `name:type=value`. The value
+/// may contain the placeholders `{{counter}}`, `{{toggle}}`, `{{timestamp}}`
+/// and `{{random:min:max}}` which are expanded for every data message.
+#[derive(Clone, Debug, Getters, PartialEq)]
+pub struct EmulatedMetric {
+    pub name: String,
+    pub datatype: EmulatedDataType,
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+pub enum EmulatedDataType {
+    #[strum(serialize = "int")]
+    Int,
+    #[strum(serialize = "double")]
+    Double,
+    #[strum(serialize = "bool")]
+    Boolean,
+    #[strum(serialize = "string")]
+    String,
+}
+
+impl FromStr for EmulatedMetric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some((definition, template)) = value.split_once('=') else {
+            return Err(format!(
+                "{value} is not a valid metric, expected name:type=value"
+            ));
+        };
+
+        let Some((name, datatype)) = definition.rsplit_once(':') else {
+            return Err(format!(
+                "{value} is not a valid metric, expected name:type=value"
+            ));
+        };
+
+        if name.is_empty() {
+            return Err(format!("Metric {value} has no name"));
+        }
+
+        let datatype = EmulatedDataType::from_str(datatype).map_err(|_| {
+            format!("{datatype} is not a valid metric type (possible values: int, double, bool, string)")
+        })?;
+
+        Ok(Self {
+            name: name.to_string(),
+            datatype,
+            value: template.to_string(),
+        })
+    }
+}
+
+/// Periodically exports the state of the Sparkplug network to a file
+/// and/or a topic.
+#[derive(Clone, Debug, Getters, Validate)]
+#[validate(schema(function = "validate_snapshot_target"))]
+pub struct SparkplugSnapshotExport {
+    #[validate(custom(
+        function = "validate_snapshot_interval",
+        message = "Snapshot interval must be at least 1 second"
+    ))]
+    pub interval: Duration,
+    pub format: SnapshotFormat,
+    pub path: Option<PathBuf>,
+    pub topic: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, EnumString)]
+pub enum SnapshotFormat {
+    #[default]
+    #[strum(serialize = "json")]
+    Json,
+    #[strum(serialize = "yaml")]
+    Yaml,
+}
+
+fn validate_snapshot_interval(value: &Duration) -> Result<(), ValidationError> {
+    if value.as_secs() >= 1 {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_snapshot_interval"))
+}
+
+fn validate_summary_interval(value: &Duration) -> Result<(), ValidationError> {
+    if value.as_secs() >= 1 {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_summary_interval"))
+}
+
+fn validate_emulator_interval(value: &Duration) -> Result<(), ValidationError> {
+    if value.as_millis() >= 100 {
+        return Ok(());
+    }
+
+    Err(ValidationError::new("wrong_emulator_interval"))
+}
+
+fn validate_snapshot_target(value: &SparkplugSnapshotExport) -> Result<(), ValidationError> {
+    if value.path.is_none() && value.topic.is_none() {
+        let mut err = ValidationError::new("missing_snapshot_target");
+        err.message = Some(Cow::from(
+            "Either a path or a topic must be given to export the snapshot to",
+        ));
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_emulated_metric() {
+        assert_eq!(
+            Ok(EmulatedMetric {
+                name: "Sensors/Temperature".to_string(),
+                datatype: EmulatedDataType::Double,
+                value: "{{random:15:25}}".to_string(),
+            }),
+            EmulatedMetric::from_str("Sensors/Temperature:double={{random:15:25}}")
+        );
+        assert_eq!(
+            Ok(EmulatedDataType::Boolean),
+            EmulatedMetric::from_str("a:b:bool=true").map(|m| m.datatype)
+        );
+        assert!(EmulatedMetric::from_str("temperature:double").is_err());
+        assert!(EmulatedMetric::from_str("temperature=1").is_err());
+        assert!(EmulatedMetric::from_str("temperature:long=1").is_err());
+        assert!(EmulatedMetric::from_str(":int=1").is_err());
+    }
+
+    #[test]
+    fn metric_filter() {
+        let filter = SparkplugMetricFilter {
+            include: vec![],
+            exclude: vec!["Diagnostics/*".to_string(), "Debug?".to_string()],
+        };
+        assert!(filter.is_included("Temperature"));
+        assert!(filter.is_included("Debug"));
+        assert!(!filter.is_included("Debug1"));
+        assert!(!filter.is_included("Diagnostics/Cpu/Load"));
+
+        let filter = SparkplugMetricFilter {
+            include: vec!["*/Temp*".to_string(), "bdSeq".to_string()],
+            exclude: vec!["*Raw".to_string()],
+        };
+        assert!(filter.is_included("Line1/Temperature"));
+        assert!(filter.is_included("bdSeq"));
+        assert!(!filter.is_included("Line1/TemperatureRaw"));
+        assert!(!filter.is_included("Temperature"));
+    }
+}
@@ -1,10 +1,100 @@
+use crate::config::effective::redact_connection_string;
+use crate::config::mqtli_config::serialize_duration_seconds;
+use crate::payload::PayloadFormat;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use strum_macros::EnumString;
 use url::Url;
 use validator::{Validate, ValidationError};
 
-#[derive(Clone, Debug, Default, Validate)]
+#[derive(Clone, Debug, Serialize, Validate)]
 pub struct SqlStorage {
     #[validate(length(min = 1), custom(function = "validate_connection_string"))]
+    #[serde(serialize_with = "redact_connection_string")]
     pub connection_string: String,
+    /// Number of writes waiting for the database before the overflow
+    /// policy applies
+    #[validate(range(min = 1, message = "SQL queue size must be at least 1"))]
+    pub queue_size: usize,
+    pub queue_overflow: SqlQueueOverflow,
+    /// File buffering the writes while the database is unavailable; writes
+    /// are logged and dropped if not given
+    pub buffer_path: Option<PathBuf>,
+    /// Maximum number of buffered writes, further writes are dropped
+    #[validate(range(min = 1, message = "SQL buffer size must be at least 1"))]
+    pub buffer_size: usize,
+    /// Interval in which the buffered writes are replayed
+    #[serde(serialize_with = "serialize_duration_seconds")]
+    pub retry_interval: Duration,
+    /// Insert statements of SQL outputs without their own statement
+    pub statements: SqlStatements,
+}
+
+impl Default for SqlStorage {
+    fn default() -> Self {
+        Self {
+            connection_string: String::new(),
+            queue_size: 1000,
+            queue_overflow: SqlQueueOverflow::default(),
+            buffer_path: None,
+            buffer_size: 100_000,
+            retry_interval: Duration::from_secs(5),
+            statements: SqlStatements::default(),
+        }
+    }
+}
+
+/// Insert statements by the payload type of the message, used by SQL outputs
+/// which don't define their own statement. Messages of other payload types
+/// use the default statement, or the default schema if there is none.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SqlStatements {
+    #[serde(default)]
+    pub json: Option<String>,
+    #[serde(default)]
+    pub sparkplug: Option<String>,
+    #[serde(default)]
+    pub sparkplug_json: Option<String>,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+impl SqlStatements {
+    /// Returns the statement inserting the payload, if any.
+    pub fn for_payload(&self, payload: &PayloadFormat) -> Option<&String> {
+        match payload {
+            PayloadFormat::Json(_) => self.json.as_ref(),
+            PayloadFormat::Sparkplug(_) => self.sparkplug.as_ref(),
+            PayloadFormat::SparkplugJson(_) => self.sparkplug_json.as_ref(),
+            _ => None,
+        }
+        .or(self.default.as_ref())
+    }
+}
+
+/// Handling of writes if the queue of the database is full.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum SqlQueueOverflow {
+    /// Wait until the database caught up, delaying all outputs
+    #[default]
+    #[serde(rename = "block")]
+    #[strum(serialize = "block")]
+    Block,
+    /// Drop the write and log a warning
+    #[serde(rename = "drop")]
+    #[strum(serialize = "drop")]
+    Drop,
 }
 
 impl SqlStorage {
@@ -20,8 +110,10 @@ fn validate_connection_string(connection_string: &str) -> Result<(), ValidationE
 
     match url.scheme() {
         "sqlite" | "mariadb" | "mysql" | "postgresql" => Ok(()),
+        #[cfg(feature = "duckdb")]
+        "duckdb" => Ok(()),
         _ => Err(ValidationError::new(
-            "Only schemes sqlite, mariadb, mysql and postgresql are currently supported",
+            "Only schemes sqlite, mariadb, mysql, postgresql and duckdb (with feature duckdb) are currently supported",
         )),
     }
 }
@@ -29,11 +121,35 @@ fn validate_connection_string(connection_string: &str) -> Result<(), ValidationE
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::payload::json::PayloadFormatJson;
+    use crate::payload::text::PayloadFormatText;
+
+    #[test]
+    fn statement_for_payload() {
+        let statements = SqlStatements {
+            json: Some("JSON".to_string()),
+            sparkplug: Some("SPARKPLUG".to_string()),
+            ..SqlStatements::default()
+        };
+        let json = PayloadFormat::Json(PayloadFormatJson::try_from("{}".to_string()).unwrap());
+        let text = PayloadFormat::Text(PayloadFormatText::from("text"));
+
+        assert_eq!(Some(&"JSON".to_string()), statements.for_payload(&json));
+        assert_eq!(None, statements.for_payload(&text));
+
+        let statements = SqlStatements {
+            default: Some("DEFAULT".to_string()),
+            ..statements
+        };
+        assert_eq!(Some(&"JSON".to_string()), statements.for_payload(&json));
+        assert_eq!(Some(&"DEFAULT".to_string()), statements.for_payload(&text));
+    }
 
     #[test]
     fn validate_sqlite_in_memory() {
         let conf = SqlStorage {
             connection_string: "sqlite::memory:".to_string(),
+            ..SqlStorage::default()
         };
         let result = conf.validate();
 
@@ -44,6 +160,7 @@ mod tests {
     fn validate_sqlite_temporary_file() {
         let conf = SqlStorage {
             connection_string: "sqlite://".to_string(),
+            ..SqlStorage::default()
         };
         let result = conf.validate();
 
@@ -54,6 +171,7 @@ mod tests {
     fn validate_sqlite_file_no_authority() {
         let conf = SqlStorage {
             connection_string: "sqlite:data.db".to_string(),
+            ..SqlStorage::default()
         };
         let result = conf.validate();
 
@@ -64,6 +182,19 @@ mod tests {
     fn validate_sqlite_file_with_authority() {
         let conf = SqlStorage {
             connection_string: "sqlite://data.db".to_string(),
+            ..SqlStorage::default()
+        };
+        let result = conf.validate();
+
+        assert!(result.is_ok());
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn validate_duckdb_file() {
+        let conf = SqlStorage {
+            connection_string: "duckdb:data.duckdb".to_string(),
+            ..SqlStorage::default()
         };
         let result = conf.validate();
 
@@ -74,6 +205,7 @@ mod tests {
     fn validate_invalid_file() {
         let conf = SqlStorage {
             connection_string: "file.db".to_string(),
+            ..SqlStorage::default()
         };
         let result = conf.validate();
 
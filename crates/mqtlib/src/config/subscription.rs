@@ -1,29 +1,250 @@
-use crate::config::deserialize_qos;
+use crate::config::alert::AlertConditionJson;
+#[cfg(feature = "websocket")]
+use crate::config::effective::redact_headers;
+#[cfg(feature = "email")]
+use crate::config::effective::redact_secret_option;
 use crate::config::filter::{FilterError, FilterTypes};
+use crate::config::publish::{deserialize_duration_milliseconds, serialize_duration_milliseconds};
 use crate::config::PayloadType;
+use crate::config::{deserialize_qos, deserialize_qos_option, serialize_qos, serialize_qos_option};
 use crate::mqtt::QoS;
 use crate::payload::PayloadFormat;
 use derive_builder::Builder;
 use derive_getters::Getters;
-use serde::Deserialize;
+#[cfg(feature = "email")]
+use lettre::message::Mailbox;
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+#[cfg(any(feature = "websocket", feature = "grpc"))]
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
+use std::mem::discriminant;
 use std::path::PathBuf;
-use validator::Validate;
+use std::str::FromStr;
+use std::time::Duration;
+use strum_macros::EnumString;
+use validator::{Validate, ValidationError};
 
-#[derive(Builder, Clone, Debug, Deserialize, Getters, PartialEq, Validate)]
+#[derive(Builder, Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+#[validate(schema(function = "validate_message_filters"))]
 pub struct Subscription {
     pub enabled: bool,
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
     pub qos: QoS,
+    #[validate(nested)]
     pub outputs: Vec<Output>,
     #[serde(default)]
+    #[validate(nested)]
     pub filters: FilterTypes,
+    /// Only messages whose topic matches this regular expression are
+    /// processed, all others are dropped before filters and outputs run
+    #[serde(default)]
+    pub topic_filter_regex: Option<TopicRegex>,
+    /// Drops messages delivered with the retain flag set, e.g. the retained
+    /// message the broker sends right after subscribing
+    #[serde(default)]
+    pub ignore_retained: bool,
+    /// Only processes messages delivered with the retain flag set, e.g. to
+    /// react only to state changes
+    #[serde(default)]
+    pub only_retained: bool,
+    /// Only processes messages delivered without the retain flag, like
+    /// ignore_retained, e.g. to react only to live telemetry
+    #[serde(default)]
+    pub only_live: bool,
+    /// Drops messages delivered with a lower QoS
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos_option")]
+    #[serde(serialize_with = "serialize_qos_option")]
+    pub min_qos: Option<QoS>,
+    /// Drops messages delivered with a higher QoS
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos_option")]
+    #[serde(serialize_with = "serialize_qos_option")]
+    pub max_qos: Option<QoS>,
+    /// Processes all messages matching this topic in the order they were
+    /// received, also across different concrete topics of a wildcard
+    #[serde(default)]
+    pub ordered: bool,
+    /// Limits the size of received payloads, overriding the global limit
+    #[serde(default)]
+    #[validate(nested)]
+    pub payload_limit: Option<PayloadLimit>,
 }
 
 impl Subscription {
-    pub fn apply_filters(&self, data: PayloadFormat) -> Result<Vec<PayloadFormat>, FilterError> {
-        self.filters.apply(data)
+    /// Applies the filters to a message received on the given concrete
+    /// topic.
+    pub fn apply_filters(
+        &self,
+        topic: &str,
+        data: PayloadFormat,
+    ) -> Result<Vec<PayloadFormat>, FilterError> {
+        self.filters.apply_on_topic(topic, data)
+    }
+
+    /// Returns true if the concrete topic of a received message matches the
+    /// topic filter regex, or if none is configured.
+    pub fn matches_topic(&self, topic: &str) -> bool {
+        self.topic_filter_regex
+            .as_ref()
+            .map_or(true, |regex| regex.is_match(topic))
+    }
+
+    /// Returns true if a message with the given retain flag is dropped
+    /// because retained messages are ignored, or only retained messages are
+    /// processed.
+    pub fn ignores(&self, retain: bool) -> bool {
+        if retain {
+            self.ignore_retained || self.only_live
+        } else {
+            self.only_retained
+        }
+    }
+
+    /// Returns true if a message delivered with the given QoS is within the
+    /// QoS bounds, or if none are configured.
+    pub fn accepts_qos(&self, qos: QoS) -> bool {
+        self.min_qos
+            .map_or(true, |min_qos| qos as u8 >= min_qos as u8)
+            && self
+                .max_qos
+                .map_or(true, |max_qos| qos as u8 <= max_qos as u8)
+    }
+}
+
+fn validate_message_filters(value: &Subscription) -> Result<(), ValidationError> {
+    let qos_reversed = match (value.min_qos, value.max_qos) {
+        (Some(min_qos), Some(max_qos)) => min_qos as u8 > max_qos as u8,
+        _ => false,
+    };
+
+    let message = if value.only_retained && (value.only_live || value.ignore_retained) {
+        "Subscription cannot process only retained messages while ignoring them"
+    } else if qos_reversed {
+        "Min QoS of subscription must not be greater than its max QoS"
+    } else {
+        return Ok(());
+    };
+
+    let mut err = ValidationError::new("wrong_message_filters");
+    err.message = Some(Cow::from(message));
+
+    Err(err)
+}
+
+/// Marker appended to truncated payloads.
+pub const TRUNCATION_MARKER: &[u8] = b"...[truncated]";
+
+/// Maximum size of received payloads and what to do with larger payloads.
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct PayloadLimit {
+    #[validate(range(min = 1, message = "Max payload size must be at least 1 byte"))]
+    pub max_size: usize,
+    #[serde(default)]
+    pub action: PayloadLimitAction,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum PayloadLimitAction {
+    /// Cut the payload at the max size and append a marker
+    #[default]
+    #[serde(rename = "truncate")]
+    #[strum(serialize = "truncate")]
+    Truncate,
+    /// Drop the message silently
+    #[serde(rename = "skip")]
+    #[strum(serialize = "skip")]
+    Skip,
+    /// Drop the message and log an error
+    #[serde(rename = "error")]
+    #[strum(serialize = "error")]
+    Error,
+}
+
+impl PayloadLimit {
+    pub fn exceeds(&self, payload: &[u8]) -> bool {
+        payload.len() > self.max_size
+    }
+
+    /// Returns the first bytes of the payload up to the max size followed by
+    /// the truncation marker.
+    pub fn truncate(&self, payload: &[u8]) -> Vec<u8> {
+        let mut result = payload[..self.max_size.min(payload.len())].to_vec();
+        result.extend_from_slice(TRUNCATION_MARKER);
+        result
+    }
+}
+
+/// Regular expression which is matched against the concrete topic of a
+/// message. It matches anywhere in the topic unless anchored with `^` and `$`.
+#[derive(Clone, Debug)]
+pub struct TopicRegex(Regex);
+
+impl TopicRegex {
+    pub fn is_match(&self, topic: &str) -> bool {
+        self.0.is_match(topic)
+    }
+
+    /// Replaces all matches in the topic, the replacement may refer to
+    /// capture groups with `$1` or `${name}`.
+    pub fn replace_all(&self, topic: &str, replacement: &str) -> String {
+        self.0.replace_all(topic, replacement).to_string()
+    }
+}
+
+impl FromStr for TopicRegex {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Regex::new(s).map(Self)
+    }
+}
+
+impl PartialEq for TopicRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl<'de> Deserialize<'de> for TopicRegex {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+
+        TopicRegex::from_str(&value)
+            .map_err(|e| D::Error::custom(format!("Invalid topic filter regex \"{value}\": {e}")))
+    }
+}
+
+impl Serialize for TopicRegex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl Display for TopicRegex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.as_str())
     }
 }
 
@@ -32,6 +253,34 @@ impl Display for Subscription {
         writeln!(f, "Enabled: {}", self.enabled)?;
         writeln!(f, "QoS: {}", self.qos)?;
 
+        if let Some(regex) = &self.topic_filter_regex {
+            writeln!(f, "Topic filter regex: {regex}")?;
+        }
+
+        if self.ignore_retained {
+            writeln!(f, "Ignore retained: true")?;
+        }
+
+        if self.only_retained {
+            writeln!(f, "Only retained: true")?;
+        }
+
+        if self.only_live {
+            writeln!(f, "Only live: true")?;
+        }
+
+        if let Some(min_qos) = self.min_qos {
+            writeln!(f, "Min QoS: {min_qos}")?;
+        }
+
+        if let Some(max_qos) = self.max_qos {
+            writeln!(f, "Max QoS: {max_qos}")?;
+        }
+
+        if self.ordered {
+            writeln!(f, "Ordered: true")?;
+        }
+
         for (i, output) in self.outputs.iter().enumerate() {
             writeln!(f, "Output: {i}\n{}", output)?;
         }
@@ -47,27 +296,194 @@ impl Default for Subscription {
             qos: Default::default(),
             outputs: vec![],
             filters: Default::default(),
+            topic_filter_regex: None,
+            ignore_retained: false,
+            only_retained: false,
+            only_live: false,
+            min_qos: None,
+            max_qos: None,
+            ordered: false,
+            payload_limit: None,
         }
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Validate)]
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize, Validate)]
 pub struct Output {
     pub format: PayloadType,
     #[serde(default)]
     pub target: OutputTarget,
+    /// Indent JSON payloads instead of writing them on a single line
+    #[serde(default)]
+    pub json_pretty: bool,
+    /// Forwards only a sample of the messages, e.g. to follow a high
+    /// frequency topic on the console
+    #[serde(default)]
+    #[validate(custom(function = "validate_sample"))]
+    pub sample: Option<Sample>,
+    /// Writes only the messages meeting the condition, e.g. to route alarms
+    /// and normal telemetry of one subscription to different targets
+    #[serde(default)]
+    pub when: Option<OutputCondition>,
 }
 
 impl Display for Output {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "format: {}", self.format)?;
         writeln!(f, "target: {}", self.target)?;
+        writeln!(f, "json pretty: {}", self.json_pretty)?;
+
+        if let Some(sample) = &self.sample {
+            writeln!(f, "sample: {sample}")?;
+        }
+
+        if let Some(when) = &self.when {
+            writeln!(f, "when: {when}")?;
+        }
 
         Ok(())
     }
 }
 
-#[derive(Clone, Debug, Deserialize, strum_macros::Display, PartialEq)]
+/// Condition of an output, evaluated on the messages after the filters of
+/// the subscription ran. All given conditions must be met.
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+pub struct OutputCondition {
+    /// A value of the payload converted to JSON compares to the given value
+    #[serde(default)]
+    pub json: Option<AlertConditionJson>,
+    /// The topic of the message matches the regular expression
+    #[serde(default)]
+    pub topic_regex: Option<TopicRegex>,
+    /// The payload has this type, e.g. after a filter converted it
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_payload_type_name")]
+    #[serde(serialize_with = "serialize_payload_type_name")]
+    pub payload_type: Option<PayloadType>,
+}
+
+impl OutputCondition {
+    pub fn matches(&self, topic: &str, payload: &PayloadFormat) -> bool {
+        if let Some(payload_type) = &self.payload_type {
+            if discriminant(payload_type) != discriminant(&PayloadType::from(payload.clone())) {
+                return false;
+            }
+        }
+
+        if let Some(regex) = &self.topic_regex {
+            if !regex.is_match(topic) {
+                return false;
+            }
+        }
+
+        if let Some(json) = &self.json {
+            let content = PayloadFormat::try_from((payload.clone(), PayloadType::Json))
+                .and_then(Vec::<u8>::try_from);
+
+            return content.is_ok_and(|content| json.matches(&content));
+        }
+
+        true
+    }
+}
+
+impl Display for OutputCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut conditions: Vec<String> = vec![];
+
+        if let Some(json) = &self.json {
+            conditions.push(format!(
+                "{} {} {}",
+                json.jsonpath, json.operator, json.value
+            ));
+        }
+        if let Some(regex) = &self.topic_regex {
+            conditions.push(format!("topic matches {regex}"));
+        }
+        if let Some(payload_type) = &self.payload_type {
+            conditions.push(format!("payload type is {payload_type}"));
+        }
+
+        write!(f, "{}", conditions.join(" and "))
+    }
+}
+
+/// Deserializes the name of a payload type, e.g. `json`, using the default
+/// options of the type.
+fn deserialize_payload_type_name<'de, D>(deserializer: D) -> Result<Option<PayloadType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+
+    PayloadType::from_str(&value)
+        .map(Some)
+        .map_err(|_| D::Error::custom(format!("Unknown payload type \"{value}\"")))
+}
+
+fn serialize_payload_type_name<S>(
+    value: &Option<PayloadType>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.serialize_some(<&str>::from(value)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Sample rate of an output, given as whole number n to forward every nth
+/// message or as probability between 0 and 1 to forward each message with.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Sample {
+    Every(u64),
+    Probability(f64),
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(n) = s.parse::<u64>() {
+            return Ok(Sample::Every(n));
+        }
+
+        s.parse::<f64>()
+            .map(Sample::Probability)
+            .map_err(|_| format!("Invalid sample \"{s}\", expected a number n or a probability"))
+    }
+}
+
+impl Display for Sample {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sample::Every(n) => write!(f, "every {n}. message"),
+            Sample::Probability(probability) => write!(f, "probability {probability}"),
+        }
+    }
+}
+
+fn validate_sample(value: &Sample) -> Result<(), ValidationError> {
+    let valid = match value {
+        Sample::Every(n) => *n >= 1,
+        Sample::Probability(probability) => *probability > 0.0 && *probability <= 1.0,
+    };
+
+    if valid {
+        return Ok(());
+    }
+
+    let mut err = ValidationError::new("wrong_sample");
+    err.message = Some(Cow::from(
+        "Sample must be a number of at least 1 or a probability greater than 0 and at most 1",
+    ));
+    Err(err)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, strum_macros::Display, PartialEq)]
 #[serde(tag = "type")]
 pub enum OutputTarget {
     #[serde(rename = "console")]
@@ -78,6 +494,19 @@ pub enum OutputTarget {
     Topic(OutputTargetTopic),
     #[serde(rename = "sql")]
     Sql(OutputTargetSql),
+    #[serde(rename = "sparkplug_sql")]
+    SparkplugSql(OutputTargetSparkplugSql),
+    #[serde(rename = "pipe")]
+    Pipe(OutputTargetPipe),
+    #[cfg(feature = "email")]
+    #[serde(rename = "email")]
+    Email(OutputTargetEmail),
+    #[cfg(feature = "websocket")]
+    #[serde(rename = "websocket")]
+    WebSocket(OutputTargetWebSocket),
+    #[cfg(feature = "grpc")]
+    #[serde(rename = "grpc")]
+    Grpc(OutputTargetGrpc),
 }
 
 impl Default for OutputTarget {
@@ -86,31 +515,133 @@ impl Default for OutputTarget {
     }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Validate)]
-pub struct OutputTargetConsole {}
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct OutputTargetConsole {
+    /// Indent and highlight JSON and YAML payloads
+    #[serde(default)]
+    pub pretty: bool,
+    /// Print only the fields of JSON and YAML payloads which changed
+    /// compared to the previous message on the same topic
+    #[serde(default)]
+    pub diff: bool,
+    /// Write the payload as it is without header and terminate each message
+    /// with a NUL byte instead of a newline
+    #[serde(default)]
+    pub null_delimited: bool,
+    /// Write to stderr instead of stdout
+    #[serde(default)]
+    pub stderr: bool,
+    /// Print a header line before each message
+    #[serde(default = "default_show_header")]
+    pub show_header: bool,
+    /// Template of the header line, supporting the placeholders `{{time}}`,
+    /// `{{timestamp}}`, `{{topic}}`, `{{qos}}`, `{{retain}}`, `{{size}}` and
+    /// `{{format}}`; the default header is printed if not set
+    pub header: Option<String>,
+    /// How control characters in payloads are printed
+    #[serde(default)]
+    pub escape: ConsoleEscape,
+    /// Print the MQTT v5 properties of each message below its header
+    #[serde(default)]
+    pub show_properties: bool,
+}
 
-#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Validate)]
+impl Default for OutputTargetConsole {
+    fn default() -> Self {
+        Self {
+            pretty: false,
+            diff: false,
+            null_delimited: false,
+            stderr: false,
+            show_header: default_show_header(),
+            header: None,
+            escape: ConsoleEscape::default(),
+            show_properties: false,
+        }
+    }
+}
+
+fn default_show_header() -> bool {
+    true
+}
+
+/// Printing of control characters, which could garble the terminal. Line
+/// feeds and tabs are always printed as they are.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum ConsoleEscape {
+    /// C-style escape sequences like `\r` or `\x1b`
+    #[default]
+    #[serde(rename = "c")]
+    #[strum(serialize = "c")]
+    C,
+    /// The Unicode replacement character U+FFFD
+    #[serde(rename = "replace")]
+    #[strum(serialize = "replace")]
+    Replace,
+    /// Unmodified, e.g. for piping
+    #[serde(rename = "none")]
+    #[strum(serialize = "none")]
+    None,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize, Validate)]
 pub struct OutputTargetTopic {
     pub topic: String,
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_qos")]
+    #[serde(serialize_with = "serialize_qos")]
     pub qos: QoS,
     #[serde(default)]
     pub retain: bool,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Validate)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, Validate)]
 pub struct OutputTargetSql {
-    pub insert_statement: String,
+    /// Statement inserting the message; if omitted, the message is stored in
+    /// the default table created by mqtli
+    #[serde(default)]
+    pub insert_statement: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Validate)]
+/// Stores Sparkplug messages in the normalized schema created by mqtli.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, Validate)]
+pub struct OutputTargetSparkplugSql {}
+
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
 pub struct OutputTargetFile {
     pub path: PathBuf,
     #[serde(default)]
     pub overwrite: bool,
     pub prepend: Option<String>,
     pub append: Option<String>,
+    /// Size of the write buffer in bytes; the buffer is written to the file
+    /// when it is full. Messages are written immediately if zero.
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// Interval in which buffered messages are written to the file
+    #[serde(default = "default_flush_interval")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub flush_interval: Duration,
+    /// Write each message to a new file, the path is a template of the file
+    /// name, see [`crate::output::file::FileOutput::output_per_message`]
+    #[serde(default)]
+    pub per_message: bool,
+    /// Write each message as a JSON line with its topic, QoS, retain flag,
+    /// payload and MQTT v5 properties, see
+    /// [`crate::output::file::FileOutput::output_json_line`]
+    #[serde(default)]
+    pub ndjson: bool,
 }
 
 impl Default for OutputTargetFile {
@@ -120,6 +651,471 @@ impl Default for OutputTargetFile {
             overwrite: false,
             prepend: None,
             append: Some("\n".to_string()),
+            buffer_size: default_buffer_size(),
+            flush_interval: default_flush_interval(),
+            per_message: false,
+            ndjson: false,
+        }
+    }
+}
+
+/// Streams all messages to the stdin of one long-running command, which is
+/// started with the first message.
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct OutputTargetPipe {
+    pub command: String,
+    #[serde(default)]
+    pub delimiter: PipeDelimiter,
+}
+
+/// Delimiter written after each message to the stdin of a pipe output
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum PipeDelimiter {
+    #[default]
+    #[serde(rename = "newline")]
+    #[strum(serialize = "newline")]
+    Newline,
+    #[serde(rename = "nul")]
+    #[strum(serialize = "nul")]
+    Nul,
+}
+
+impl PipeDelimiter {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            PipeDelimiter::Newline => b"\n",
+            PipeDelimiter::Nul => b"\0",
+        }
+    }
+}
+
+/// Sends an email for each message, e.g. to alert on messages passing a
+/// threshold filter.
+#[cfg(feature = "email")]
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize, Validate)]
+pub struct OutputTargetEmail {
+    pub smtp: SmtpServer,
+    pub from: Mailbox,
+    #[validate(length(min = 1, message = "At least one recipient must be given"))]
+    pub to: Vec<Mailbox>,
+    /// Template of the subject
+    #[serde(default = "default_email_subject")]
+    pub subject: String,
+    /// Template of the body
+    #[serde(default = "default_email_body")]
+    pub body: String,
+}
+
+/// Connection to the SMTP server sending the emails.
+#[cfg(feature = "email")]
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct SmtpServer {
+    pub host: String,
+    /// Port of the server, defaults to the port of the TLS mode
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    #[serde(serialize_with = "redact_secret_option")]
+    pub password: Option<String>,
+}
+
+/// Encryption of the connection to the SMTP server
+#[cfg(feature = "email")]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum SmtpTls {
+    /// Upgrade the connection with STARTTLS (default port 587)
+    #[default]
+    #[serde(rename = "starttls")]
+    #[strum(serialize = "starttls")]
+    StartTls,
+    /// Connect with TLS (default port 465)
+    #[serde(rename = "tls")]
+    #[strum(serialize = "tls")]
+    Tls,
+    /// Unencrypted connection, e.g. to a local relay (default port 25)
+    #[serde(rename = "none")]
+    #[strum(serialize = "none")]
+    None,
+}
+
+/// Sends each message as frame to a WebSocket endpoint, e.g. to feed an
+/// existing ingestion service. The connection is opened with the first
+/// message and opened again after it was lost.
+#[cfg(feature = "websocket")]
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct OutputTargetWebSocket {
+    /// URL of the endpoint, ws:// or wss://
+    pub url: String,
+    #[serde(default)]
+    pub frame: WebSocketFrame,
+    /// Headers of the HTTP upgrade request, e.g. for authentication
+    #[serde(default)]
+    #[serde(serialize_with = "redact_headers")]
+    pub headers: BTreeMap<String, String>,
+    /// Time to wait before connecting again after the connection failed or
+    /// was lost
+    #[serde(default = "default_reconnect_interval")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub reconnect_interval: Duration,
+    /// Maximum number of messages waiting to be sent, e.g. while the
+    /// endpoint is unreachable; further messages are dropped
+    #[serde(default = "default_output_queue_size")]
+    pub queue_size: usize,
+}
+
+/// Type of the frames sent by a WebSocket output
+#[cfg(feature = "websocket")]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum WebSocketFrame {
+    /// Payload must be valid UTF-8
+    #[default]
+    #[serde(rename = "text")]
+    #[strum(serialize = "text")]
+    Text,
+    #[serde(rename = "binary")]
+    #[strum(serialize = "binary")]
+    Binary,
+}
+
+/// Streams each message to a client streaming method of a gRPC service,
+/// e.g. of an ingestion layer. The request is a message of a protobuf
+/// definition whose fields are set from the received message.
+#[cfg(feature = "grpc")]
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+pub struct OutputTargetGrpc {
+    /// URL of the service, http:// or https://
+    pub endpoint: String,
+    /// Full path of the method, e.g. /ingest.Ingest/Publish
+    pub method: String,
+    /// Definition file containing the request message
+    pub definition: PathBuf,
+    /// Name of the request message, relative to the package
+    pub message: String,
+    /// Fields of the request by name and where their values come from
+    pub fields: BTreeMap<String, GrpcFieldSource>,
+    /// Time to wait before connecting again after the stream failed or was
+    /// closed by the service
+    #[serde(default = "default_reconnect_interval")]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[serde(serialize_with = "serialize_duration_milliseconds")]
+    pub reconnect_interval: Duration,
+    /// Maximum number of messages waiting to be sent; further messages are
+    /// dropped
+    #[serde(default = "default_output_queue_size")]
+    pub queue_size: usize,
+}
+
+/// Source of the value of a field of a gRPC request
+#[cfg(feature = "grpc")]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum GrpcFieldSource {
+    Topic,
+    /// Payload converted to the output format
+    Payload,
+    Qos,
+    Retain,
+    /// Time the message was received
+    Time,
+    /// Value selected by a JSONPath from the payload
+    JsonPath(String),
+}
+
+#[cfg(feature = "grpc")]
+impl TryFrom<String> for GrpcFieldSource {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "topic" => Ok(Self::Topic),
+            "payload" => Ok(Self::Payload),
+            "qos" => Ok(Self::Qos),
+            "retain" => Ok(Self::Retain),
+            "time" => Ok(Self::Time),
+            path if path.starts_with('$') => Ok(Self::JsonPath(value)),
+            _ => Err(format!(
+                "Unknown field source \"{value}\", expected topic, payload, qos, retain, time or a JSONPath"
+            )),
         }
     }
 }
+
+#[cfg(feature = "grpc")]
+impl From<GrpcFieldSource> for String {
+    fn from(value: GrpcFieldSource) -> Self {
+        match value {
+            GrpcFieldSource::Topic => "topic".to_string(),
+            GrpcFieldSource::Payload => "payload".to_string(),
+            GrpcFieldSource::Qos => "qos".to_string(),
+            GrpcFieldSource::Retain => "retain".to_string(),
+            GrpcFieldSource::Time => "time".to_string(),
+            GrpcFieldSource::JsonPath(path) => path,
+        }
+    }
+}
+
+#[cfg(any(feature = "websocket", feature = "grpc"))]
+fn default_reconnect_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+#[cfg(any(feature = "websocket", feature = "grpc"))]
+fn default_output_queue_size() -> usize {
+    1000
+}
+
+#[cfg(feature = "email")]
+fn default_email_subject() -> String {
+    "Message on {{topic}}".to_string()
+}
+
+#[cfg(feature = "email")]
+fn default_email_body() -> String {
+    "{{payload}}".to_string()
+}
+
+fn default_buffer_size() -> usize {
+    8 * 1024
+}
+
+fn default_flush_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "email")]
+    #[test]
+    fn email() {
+        let target: OutputTarget = serde_yaml::from_str(
+            "type: email
+smtp:
+  host: smtp.example.com
+  username: alerts
+  password: secret
+from: mqtli <alerts@example.com>
+to: [ops@example.com, oncall@example.com]
+subject: Alert on {{topic}}",
+        )
+        .unwrap();
+
+        let OutputTarget::Email(email) = target else {
+            panic!("unexpected output target {target:?}");
+        };
+        assert_eq!("smtp.example.com", email.smtp.host);
+        assert_eq!(SmtpTls::StartTls, email.smtp.tls);
+        assert_eq!(None, email.smtp.port);
+        assert_eq!("alerts@example.com", email.from.email.to_string());
+        assert_eq!(2, email.to.len());
+        assert_eq!("Alert on {{topic}}", email.subject);
+        assert_eq!("{{payload}}", email.body);
+
+        assert!(serde_yaml::from_str::<OutputTarget>(
+            "type: email\nsmtp: { host: smtp.example.com }\nfrom: invalid\nto: []"
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn websocket() {
+        let target: OutputTarget = serde_yaml::from_str(
+            "type: websocket
+url: wss://ingest.example.com/mqtt
+frame: binary
+headers:
+  Authorization: Bearer secret",
+        )
+        .unwrap();
+
+        let OutputTarget::WebSocket(websocket) = target else {
+            panic!("unexpected output target {target:?}");
+        };
+        assert_eq!("wss://ingest.example.com/mqtt", websocket.url);
+        assert_eq!(WebSocketFrame::Binary, websocket.frame);
+        assert_eq!("Bearer secret", websocket.headers["Authorization"]);
+        assert_eq!(Duration::from_secs(5), websocket.reconnect_interval);
+        assert_eq!(1000, websocket.queue_size);
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn grpc() {
+        let target: OutputTarget = serde_yaml::from_str(
+            "type: grpc
+endpoint: http://localhost:50051
+method: /ingest.Ingest/Publish
+definition: ingest.proto
+message: IngestRequest
+fields:
+  topic: topic
+  payload: payload
+  temperature: $.temperature",
+        )
+        .unwrap();
+
+        let OutputTarget::Grpc(grpc) = target else {
+            panic!("unexpected output target {target:?}");
+        };
+        assert_eq!(GrpcFieldSource::Topic, grpc.fields["topic"]);
+        assert_eq!(GrpcFieldSource::Payload, grpc.fields["payload"]);
+        assert_eq!(
+            GrpcFieldSource::JsonPath("$.temperature".to_string()),
+            grpc.fields["temperature"]
+        );
+
+        assert!(serde_yaml::from_str::<OutputTarget>(
+            "type: grpc\nendpoint: http://localhost\nmethod: /a.B/C\ndefinition: a.proto\nmessage: A\nfields: { a: unknown }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn topic_filter_regex() {
+        let subscription: Subscription = serde_yaml::from_str(
+            "enabled: true\noutputs: []\ntopic_filter_regex: ^sensors/[0-9]+/temperature$",
+        )
+        .unwrap();
+
+        assert!(subscription.matches_topic("sensors/12/temperature"));
+        assert!(!subscription.matches_topic("sensors/abc/temperature"));
+        assert!(Subscription::default().matches_topic("sensors/abc/temperature"));
+    }
+
+    #[test]
+    fn payload_limit() {
+        let subscription: Subscription = serde_yaml::from_str(
+            "enabled: true\noutputs: []\npayload_limit:\n  max_size: 5\n  action: skip",
+        )
+        .unwrap();
+        let limit = subscription.payload_limit.unwrap();
+
+        assert_eq!(PayloadLimitAction::Skip, limit.action);
+        assert!(!limit.exceeds(b"12345"));
+        assert!(limit.exceeds(b"123456"));
+        assert_eq!(b"12345...[truncated]".to_vec(), limit.truncate(b"1234567"));
+    }
+
+    #[test]
+    fn ignore_retained() {
+        let subscription: Subscription =
+            serde_yaml::from_str("enabled: true\noutputs: []\nignore_retained: true").unwrap();
+
+        assert!(subscription.ignores(true));
+        assert!(!subscription.ignores(false));
+        assert!(!Subscription::default().ignores(true));
+    }
+
+    #[test]
+    fn retain_and_qos_filters() {
+        let subscription = |yaml: &str| -> Subscription {
+            serde_yaml::from_str(&format!("enabled: true\noutputs: []\n{yaml}")).unwrap()
+        };
+
+        let only_retained = subscription("only_retained: true");
+        assert!(!only_retained.ignores(true));
+        assert!(only_retained.ignores(false));
+
+        let only_live = subscription("only_live: true");
+        assert!(only_live.ignores(true));
+        assert!(!only_live.ignores(false));
+
+        let qos = subscription("min_qos: 1\nmax_qos: 1");
+        assert!(!qos.accepts_qos(QoS::AtMostOnce));
+        assert!(qos.accepts_qos(QoS::AtLeastOnce));
+        assert!(!qos.accepts_qos(QoS::ExactlyOnce));
+        assert!(Subscription::default().accepts_qos(QoS::ExactlyOnce));
+
+        assert!(qos.validate().is_ok());
+        assert!(subscription("only_retained: true\nonly_live: true")
+            .validate()
+            .is_err());
+        assert!(subscription("min_qos: 2\nmax_qos: 1").validate().is_err());
+    }
+
+    #[test]
+    fn sample() {
+        let output: Output = serde_yaml::from_str("format:\n  type: text\nsample: 10").unwrap();
+        assert_eq!(Some(Sample::Every(10)), output.sample);
+
+        let output: Output = serde_yaml::from_str("format:\n  type: text\nsample: 0.25").unwrap();
+        assert_eq!(Some(Sample::Probability(0.25)), output.sample);
+        assert!(output.validate().is_ok());
+
+        assert_eq!(Ok(Sample::Every(5)), Sample::from_str("5"));
+        assert_eq!(Ok(Sample::Probability(0.5)), Sample::from_str("0.5"));
+        assert!(Sample::from_str("often").is_err());
+        assert!(validate_sample(&Sample::Every(0)).is_err());
+        assert!(validate_sample(&Sample::Probability(1.5)).is_err());
+    }
+
+    #[test]
+    fn output_condition() {
+        let when: OutputCondition = serde_yaml::from_str(
+            "json: { jsonpath: $.level, operator: eq, value: alarm }\ntopic_regex: ^plant/\npayload_type: json",
+        )
+        .unwrap();
+        let json = |content: &str| {
+            PayloadFormat::Json(
+                crate::payload::json::PayloadFormatJson::try_from(Vec::from(content)).unwrap(),
+            )
+        };
+
+        assert!(when.matches("plant/1", &json("{\"level\": \"alarm\"}")));
+        assert!(!when.matches("plant/1", &json("{\"level\": \"info\"}")));
+        assert!(!when.matches("office/1", &json("{\"level\": \"alarm\"}")));
+        assert!(!when.matches(
+            "plant/1",
+            &PayloadFormat::Text(crate::payload::text::PayloadFormatText::from(
+                "{\"level\": \"alarm\"}"
+            ))
+        ));
+        assert!(OutputCondition::default().matches("office/1", &json("{}")));
+        assert!(serde_yaml::from_str::<OutputCondition>("payload_type: csv").is_err());
+    }
+
+    #[test]
+    fn invalid_topic_filter_regex() {
+        assert!(serde_yaml::from_str::<Subscription>(
+            "enabled: true\noutputs: []\ntopic_filter_regex: \"sensors/(\""
+        )
+        .is_err());
+    }
+}
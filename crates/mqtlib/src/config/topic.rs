@@ -1,14 +1,19 @@
-use crate::config::publish::Publish;
-use crate::config::subscription::{Output, Subscription};
+use crate::config::publish::{Publish, PublishTriggerType};
+use crate::config::subscription::{Output, OutputTarget, Subscription};
 use crate::config::PayloadType;
+use crate::payload::hex::PayloadFormatHex;
+use crate::payload::raw::PayloadFormatRaw;
+use crate::payload::PayloadFormat;
 use derive_builder::Builder;
 use derive_getters::Getters;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use strum_macros::EnumString;
 use validator::Validate;
 
-#[derive(Builder, Clone, Debug, Default, Validate)]
+#[derive(Builder, Clone, Debug, Default, Serialize, Validate)]
 pub struct TopicStorage {
+    #[validate(nested)]
     pub topics: Vec<Topic>,
 }
 
@@ -21,9 +26,79 @@ impl TopicStorage {
             .flat_map(|s| s.outputs())
             .collect()
     }
+
+    /// Returns the key which decides the worker processing a message. It is
+    /// the topic of the first enabled ordered subscription matching the
+    /// message, so all its messages are processed in order by the same
+    /// worker, otherwise the concrete topic of the message.
+    pub fn ordering_key<'a>(&'a self, topic: &'a str) -> &'a str {
+        self.topics
+            .iter()
+            .filter(|t| {
+                t.subscription
+                    .as_ref()
+                    .is_some_and(|s| s.enabled && s.ordered)
+            })
+            .find(|t| t.contains(topic))
+            .map_or(topic, |t| t.topic.as_str())
+    }
+
+    /// Checks if any subscription stores messages in the Sparkplug SQL schema.
+    pub fn has_output_sparkplug_sql(&self) -> bool {
+        self.topics
+            .iter()
+            .filter_map(|t| t.subscription.as_ref())
+            .flat_map(|s| s.outputs())
+            .any(|o| matches!(o.target(), OutputTarget::SparkplugSql(_)))
+    }
+
+    /// Checks if any subscription stores messages in the default SQL schema.
+    pub fn has_output_default_sql(&self) -> bool {
+        self.topics
+            .iter()
+            .filter_map(|t| t.subscription.as_ref())
+            .flat_map(|s| s.outputs())
+            .any(|o| matches!(o.target(), OutputTarget::Sql(sql) if sql.insert_statement.is_none()))
+    }
+
+    /// Disables the subscription and publishing of all topics which don't match
+    /// any of the `only` patterns, if given, and of all topics which match any
+    /// of the `skip` patterns. Patterns may contain the wildcards + and #.
+    pub fn disable_topics(&mut self, only: &[String], skip: &[String]) {
+        let matches_any = |patterns: &[String], topic: &str| {
+            patterns.iter().any(|pattern| topic_matches(pattern, topic))
+        };
+
+        for topic in &mut self.topics {
+            if (!only.is_empty() && !matches_any(only, &topic.topic))
+                || matches_any(skip, &topic.topic)
+            {
+                topic.disable();
+            }
+        }
+    }
+
+    /// Checks if any enabled publish has a trigger reacting on incoming messages.
+    pub fn has_trigger_on_message(&self) -> bool {
+        self.topics
+            .iter()
+            .filter_map(|t| t.publish.as_ref())
+            .filter(|p| *p.enabled())
+            .flat_map(|p| p.trigger())
+            .any(|t| matches!(t, PublishTriggerType::OnMessage(_)))
+    }
+
+    pub fn has_trigger_periodic(&self) -> bool {
+        self.topics
+            .iter()
+            .filter_map(|t| t.publish.as_ref())
+            .filter(|p| *p.enabled())
+            .flat_map(|p| p.trigger())
+            .any(|t| matches!(t, PublishTriggerType::Periodic(_)))
+    }
 }
 
-#[derive(Builder, Clone, Debug, Default, Deserialize, Getters, Validate)]
+#[derive(Builder, Clone, Debug, Default, Deserialize, Getters, Serialize, Validate)]
 pub struct Topic {
     #[validate(length(min = 1, message = "Topic must be given"))]
     pub topic: String,
@@ -32,41 +107,146 @@ pub struct Topic {
     #[serde(default)]
     #[serde(rename = "payload")]
     pub payload_type: PayloadType,
+    /// Payload types of more specific topics matched by the topic, e.g. of
+    /// subtrees with a different format under a wildcard
+    #[serde(default)]
+    #[validate(nested)]
+    pub payload_overrides: Vec<PayloadOverride>,
+    #[serde(default)]
+    pub on_decode_error: DecodeErrorPolicy,
     #[validate(nested)]
     pub publish: Option<Publish>,
+    /// Name of a dedicated connection to the broker used for subscribing and
+    /// publishing on this topic instead of the default connection
+    #[serde(default)]
+    #[validate(length(min = 1, message = "Name of the connection must not be empty"))]
+    pub connection: Option<String>,
+}
+
+/// Payload type of the topics matching a pattern, overriding the payload type
+/// of the topic entry.
+#[derive(Clone, Debug, Deserialize, Getters, Serialize, Validate)]
+pub struct PayloadOverride {
+    #[validate(length(min = 1, message = "Topic of the payload override must be given"))]
+    pub topic: String,
+    #[serde(rename = "payload")]
+    pub payload_type: PayloadType,
+}
+
+/// Defines what happens with a received message whose payload can't be
+/// decoded with the payload type of the topic.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    EnumString,
+    PartialEq,
+    Serialize,
+    strum_macros::Display,
+)]
+pub enum DecodeErrorPolicy {
+    /// Drop the message silently
+    #[serde(rename = "skip")]
+    #[strum(serialize = "skip")]
+    Skip,
+    /// Pass the undecoded payload on as raw bytes
+    #[serde(rename = "raw")]
+    #[strum(serialize = "raw")]
+    Raw,
+    /// Pass the undecoded payload on hex encoded
+    #[serde(rename = "hex")]
+    #[strum(serialize = "hex")]
+    Hex,
+    /// Drop the message and log an error
+    #[default]
+    #[serde(rename = "error")]
+    #[strum(serialize = "error")]
+    Error,
+}
+
+impl DecodeErrorPolicy {
+    /// Returns the payload in the fallback representation of this policy,
+    /// or `None` if the message is dropped.
+    pub fn fallback(&self, payload: Vec<u8>) -> Option<PayloadFormat> {
+        match self {
+            DecodeErrorPolicy::Raw => Some(PayloadFormat::Raw(PayloadFormatRaw::from(payload))),
+            DecodeErrorPolicy::Hex => {
+                PayloadFormatHex::try_from(PayloadFormatHex::encode_to_hex(&payload))
+                    .ok()
+                    .map(PayloadFormat::Hex)
+            }
+            DecodeErrorPolicy::Skip | DecodeErrorPolicy::Error => None,
+        }
+    }
 }
 
 impl Topic {
     /// Checks if the given topic is contained in this topic considering all wildcards.
     pub(crate) fn contains(&self, rhs: &str) -> bool {
-        if self.topic == rhs {
-            return true;
-        }
-
-        let parts_self: Vec<&str> = self.topic.split("/").collect();
-        let parts_rhs: Vec<&str> = rhs.split("/").collect();
+        topic_matches(&self.topic, rhs)
+    }
 
-        let result = parts_self
+    /// Returns the payload type of the given topic: the type of the first
+    /// override matching the topic, otherwise the type of this topic.
+    pub fn payload_type_of(&self, topic: &str) -> &PayloadType {
+        self.payload_overrides
             .iter()
-            .enumerate()
-            .zip(parts_rhs.iter().enumerate())
-            .map(|((l_i, &l), (r_i, &r))| {
-                let is_last_on_either_side = (l_i == parts_self.len() - 1
-                    && parts_self.len() < parts_rhs.len())
-                    || (r_i == parts_rhs.len() - 1 && parts_rhs.len() < parts_self.len());
-
-                ((l == r || l == "+") && !is_last_on_either_side) || l == "#"
+            .find(|payload_override| topic_matches(&payload_override.topic, topic))
+            .map_or(&self.payload_type, |payload_override| {
+                &payload_override.payload_type
             })
-            .all(|part| part);
+    }
+
+    /// Disables the subscription and publishing of this topic.
+    pub fn disable(&mut self) {
+        if let Some(subscription) = self.subscription.as_mut() {
+            subscription.enabled = false;
+        }
+        if let Some(publish) = self.publish.as_mut() {
+            publish.disable();
+        }
+    }
+}
 
-        result
+/// Checks if the topic is contained in the pattern considering all wildcards.
+pub fn topic_matches(pattern: &str, rhs: &str) -> bool {
+    if pattern == rhs {
+        return true;
     }
+
+    let parts_self: Vec<&str> = pattern.split("/").collect();
+    let parts_rhs: Vec<&str> = rhs.split("/").collect();
+
+    let result = parts_self
+        .iter()
+        .enumerate()
+        .zip(parts_rhs.iter().enumerate())
+        .map(|((l_i, &l), (r_i, &r))| {
+            let is_last_on_either_side = (l_i == parts_self.len() - 1
+                && parts_self.len() < parts_rhs.len())
+                || (r_i == parts_rhs.len() - 1 && parts_rhs.len() < parts_self.len());
+
+            ((l == r || l == "+") && !is_last_on_either_side) || l == "#"
+        })
+        .all(|part| part);
+
+    result
 }
 
 impl Display for Topic {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "topic: {}", self.topic)?;
         writeln!(f, "payload type: {}", self.payload_type)?;
+        for payload_override in &self.payload_overrides {
+            writeln!(
+                f,
+                "payload type of {}: {}",
+                payload_override.topic, payload_override.payload_type
+            )?;
+        }
+        writeln!(f, "on decode error: {}", self.on_decode_error)?;
         writeln!(
             f,
             "Subscription:\n{}",
@@ -89,6 +269,7 @@ impl Display for Topic {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::subscription::SubscriptionBuilder;
 
     #[test]
     fn topic_contains() {
@@ -96,7 +277,10 @@ mod tests {
             topic: "the/topic".to_string(),
             subscription: Default::default(),
             payload_type: Default::default(),
+            payload_overrides: vec![],
+            on_decode_error: Default::default(),
             publish: None,
+            connection: None,
         };
 
         assert_eq!(true, topic.contains("the/topic"));
@@ -150,12 +334,147 @@ mod tests {
         assert_eq!(false, topic.contains("/the/topic/something"));
     }
 
+    #[test]
+    fn disable_topics() {
+        let subscribed_topic = |topic: &str| Topic {
+            subscription: Some(
+                SubscriptionBuilder::default()
+                    .enabled(true)
+                    .qos(Default::default())
+                    .outputs(vec![])
+                    .filters(Default::default())
+                    .topic_filter_regex(None)
+                    .ignore_retained(false)
+                    .only_retained(false)
+                    .only_live(false)
+                    .min_qos(None)
+                    .max_qos(None)
+                    .ordered(false)
+                    .payload_limit(None)
+                    .build()
+                    .unwrap(),
+            ),
+            ..get_topic(topic)
+        };
+        let is_enabled = |storage: &TopicStorage| -> Vec<bool> {
+            storage
+                .topics
+                .iter()
+                .map(|t| t.subscription.as_ref().unwrap().enabled)
+                .collect()
+        };
+
+        let topics = vec![
+            subscribed_topic("sensors/1/temp"),
+            subscribed_topic("sensors/2/temp"),
+            subscribed_topic("actors/1"),
+        ];
+
+        let mut storage = TopicStorage {
+            topics: topics.clone(),
+        };
+        storage.disable_topics(&["sensors/#".to_string()], &[]);
+        assert_eq!(vec![true, true, false], is_enabled(&storage));
+
+        let mut storage = TopicStorage {
+            topics: topics.clone(),
+        };
+        storage.disable_topics(&[], &["sensors/+/temp".to_string()]);
+        assert_eq!(vec![false, false, true], is_enabled(&storage));
+
+        let mut storage = TopicStorage { topics };
+        storage.disable_topics(&["sensors/#".to_string()], &["sensors/2/temp".to_string()]);
+        assert_eq!(vec![true, false, false], is_enabled(&storage));
+    }
+
+    #[test]
+    fn ordering_key() {
+        let storage = TopicStorage {
+            topics: vec![
+                Topic {
+                    subscription: Some(Subscription {
+                        ordered: true,
+                        ..Subscription::default()
+                    }),
+                    ..get_topic("sensors/+/temp")
+                },
+                Topic {
+                    subscription: Some(Subscription::default()),
+                    ..get_topic("actors/#")
+                },
+            ],
+        };
+
+        assert_eq!("sensors/+/temp", storage.ordering_key("sensors/1/temp"));
+        assert_eq!("sensors/+/temp", storage.ordering_key("sensors/2/temp"));
+        assert_eq!("actors/1", storage.ordering_key("actors/1"));
+        assert_eq!("other", storage.ordering_key("other"));
+    }
+
+    #[test]
+    fn on_decode_error() {
+        let topic: Topic = serde_yaml::from_str("topic: sensors/#\non_decode_error: hex").unwrap();
+        assert_eq!(DecodeErrorPolicy::Hex, topic.on_decode_error);
+        assert_eq!(
+            DecodeErrorPolicy::Error,
+            get_topic("sensors/#").on_decode_error
+        );
+
+        let fallback: Vec<u8> = DecodeErrorPolicy::Hex
+            .fallback(vec![0x08, 0x96])
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(b"0896".to_vec(), fallback);
+
+        let fallback: Vec<u8> = DecodeErrorPolicy::Raw
+            .fallback(vec![0x08, 0x96])
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(vec![0x08, 0x96], fallback);
+
+        assert!(DecodeErrorPolicy::Skip.fallback(vec![0x08]).is_none());
+        assert!(DecodeErrorPolicy::Error.fallback(vec![0x08]).is_none());
+    }
+
+    #[test]
+    fn payload_type_of_overrides() {
+        let topic = Topic {
+            payload_type: PayloadType::Sparkplug,
+            payload_overrides: vec![
+                PayloadOverride {
+                    topic: "spBv1.0/+/STATE/#".to_string(),
+                    payload_type: PayloadType::Json,
+                },
+                PayloadOverride {
+                    topic: "spBv1.0/#".to_string(),
+                    payload_type: PayloadType::Raw,
+                },
+            ],
+            ..get_topic("#")
+        };
+
+        assert_eq!(
+            PayloadType::Json,
+            *topic.payload_type_of("spBv1.0/group/STATE/host")
+        );
+        assert_eq!(
+            PayloadType::Raw,
+            *topic.payload_type_of("spBv1.0/group/NBIRTH/node")
+        );
+        assert_eq!(PayloadType::Sparkplug, *topic.payload_type_of("other"));
+    }
+
     fn get_topic(topic: &str) -> Topic {
         Topic {
             topic: topic.to_string(),
             subscription: Default::default(),
             payload_type: Default::default(),
+            payload_overrides: vec![],
+            on_decode_error: Default::default(),
             publish: None,
+            connection: None,
         }
     }
 }
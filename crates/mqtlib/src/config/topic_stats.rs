@@ -0,0 +1,30 @@
+use crate::mqtt::QoS;
+use derive_getters::Getters;
+use std::time::Duration;
+use validator::Validate;
+
+/// Collects message counts and byte volumes per topic for a while to show
+/// which parts of the topic tree are the noisiest.
+#[derive(Clone, Debug, Getters, Validate)]
+pub struct TopicStats {
+    #[validate(length(min = 1, message = "Topic pattern must be given"))]
+    pub pattern: String,
+    /// Time to collect messages before the tree is printed
+    pub duration: Duration,
+    pub qos: QoS,
+    /// Number of topic levels printed, deeper levels are summed up in their
+    /// parent
+    #[validate(range(min = 1, message = "Depth must be at least 1"))]
+    pub depth: Option<usize>,
+}
+
+impl Default for TopicStats {
+    fn default() -> Self {
+        Self {
+            pattern: "#".to_string(),
+            duration: Duration::from_secs(10),
+            qos: QoS::AtMostOnce,
+            depth: None,
+        }
+    }
+}
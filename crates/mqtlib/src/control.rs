@@ -0,0 +1,256 @@
+use crate::channel;
+use crate::config::deserialize_qos;
+use crate::config::topic::topic_matches;
+use crate::config::PayloadType;
+use crate::mqtt::{MqttService, QoS};
+use crate::output::statistics::{MessageStatistics, TopicStatistics};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+/// Command received on the control topic as JSON, e.g.
+/// `{"command": "subscribe", "topic": "sensors/#", "qos": 1}`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Subscribes to the topic and prints its messages to the console
+    Subscribe {
+        topic: String,
+        #[serde(default)]
+        #[serde(deserialize_with = "deserialize_qos")]
+        qos: QoS,
+        #[serde(default)]
+        format: PayloadType,
+    },
+    /// Removes a subscription added with a subscribe command
+    Unsubscribe {
+        topic: String,
+    },
+    PausePublish,
+    ResumePublish,
+    Statistics,
+}
+
+/// Response published for each command on the response topic.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ControlResponse {
+    pub success: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statistics: Option<ControlStatistics>,
+}
+
+impl ControlResponse {
+    fn success(message: String) -> Self {
+        Self {
+            success: true,
+            message,
+            statistics: None,
+        }
+    }
+
+    fn failure(message: String) -> Self {
+        Self {
+            success: false,
+            message,
+            statistics: None,
+        }
+    }
+}
+
+/// Messages received since the start, as reported by the statistics command.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ControlStatistics {
+    pub started_at: String,
+    pub paused: bool,
+    pub subscriptions: Vec<String>,
+    pub total: TopicStatistics,
+    pub topics: BTreeMap<String, TopicStatistics>,
+    /// Messages skipped by internal receivers which couldn't keep up
+    pub dropped: u64,
+}
+
+/// Subscription added at runtime with a subscribe command.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlSubscription {
+    pub topic: String,
+    pub qos: QoS,
+    pub format: PayloadType,
+}
+
+/// State changed by the commands received on the control topic.
+pub struct Controller {
+    topic: String,
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    publish_paused: Arc<AtomicBool>,
+    subscriptions: Vec<ControlSubscription>,
+    statistics: MessageStatistics,
+}
+
+impl Controller {
+    pub fn new(
+        topic: String,
+        mqtt_service: Arc<Mutex<dyn MqttService>>,
+        publish_paused: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            topic,
+            mqtt_service,
+            publish_paused,
+            subscriptions: vec![],
+            statistics: MessageStatistics::default(),
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn mqtt_service(&self) -> &Arc<Mutex<dyn MqttService>> {
+        &self.mqtt_service
+    }
+
+    /// Topic on which the responses to the commands are published.
+    pub fn response_topic(&self) -> String {
+        format!("{}/response", self.topic)
+    }
+
+    pub fn subscriptions(&self) -> &[ControlSubscription] {
+        &self.subscriptions
+    }
+
+    /// Returns the subscription added at runtime which matches the topic.
+    pub fn find_subscription(&self, topic: &str) -> Option<&ControlSubscription> {
+        self.subscriptions
+            .iter()
+            .find(|subscription| topic_matches(&subscription.topic, topic))
+    }
+
+    pub fn count_message(&mut self, topic: &str, size: usize) {
+        self.statistics.count_message(topic, size);
+    }
+
+    /// Parses and executes a command received on the control topic.
+    pub async fn handle(&mut self, payload: &[u8]) -> ControlResponse {
+        match serde_json::from_slice::<ControlCommand>(payload) {
+            Ok(command) => self.execute(command).await,
+            Err(e) => ControlResponse::failure(format!("Invalid command: {e}")),
+        }
+    }
+
+    pub async fn execute(&mut self, command: ControlCommand) -> ControlResponse {
+        info!("Executing control command {command:?}");
+
+        match command {
+            ControlCommand::Subscribe { topic, qos, format } => {
+                if let Err(e) = self
+                    .mqtt_service
+                    .lock()
+                    .await
+                    .subscribe(topic.clone(), qos)
+                    .await
+                {
+                    return ControlResponse::failure(format!(
+                        "Could not subscribe to topic {topic}: {e}"
+                    ));
+                }
+
+                self.subscriptions
+                    .retain(|subscription| subscription.topic != topic);
+                self.subscriptions.push(ControlSubscription {
+                    topic: topic.clone(),
+                    qos,
+                    format,
+                });
+
+                ControlResponse::success(format!("Subscribed to topic {topic}"))
+            }
+            ControlCommand::Unsubscribe { topic } => {
+                if !self
+                    .subscriptions
+                    .iter()
+                    .any(|subscription| subscription.topic == topic)
+                {
+                    return ControlResponse::failure(format!(
+                        "Topic {topic} was not subscribed with a control command"
+                    ));
+                }
+
+                if let Err(e) = self
+                    .mqtt_service
+                    .lock()
+                    .await
+                    .unsubscribe(topic.clone())
+                    .await
+                {
+                    return ControlResponse::failure(format!(
+                        "Could not unsubscribe from topic {topic}: {e}"
+                    ));
+                }
+
+                self.subscriptions
+                    .retain(|subscription| subscription.topic != topic);
+
+                ControlResponse::success(format!("Unsubscribed from topic {topic}"))
+            }
+            ControlCommand::PausePublish => {
+                self.publish_paused.store(true, Ordering::Relaxed);
+                ControlResponse::success("Publishing paused".to_string())
+            }
+            ControlCommand::ResumePublish => {
+                self.publish_paused.store(false, Ordering::Relaxed);
+                ControlResponse::success("Publishing resumed".to_string())
+            }
+            ControlCommand::Statistics => ControlResponse {
+                statistics: Some(self.current_statistics()),
+                ..ControlResponse::success("Statistics".to_string())
+            },
+        }
+    }
+
+    fn current_statistics(&self) -> ControlStatistics {
+        ControlStatistics {
+            started_at: self.statistics.started_at.to_rfc3339(),
+            paused: self.publish_paused.load(Ordering::Relaxed),
+            subscriptions: self
+                .subscriptions
+                .iter()
+                .map(|subscription| subscription.topic.clone())
+                .collect(),
+            total: self.statistics.total(),
+            topics: self.statistics.topics.clone(),
+            dropped: channel::dropped_messages(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            ControlCommand::Subscribe {
+                topic: "a/#".to_string(),
+                qos: QoS::AtLeastOnce,
+                format: PayloadType::default(),
+            },
+            serde_json::from_str(r#"{"command": "subscribe", "topic": "a/#", "qos": 1}"#).unwrap()
+        );
+        assert_eq!(
+            ControlCommand::Unsubscribe {
+                topic: "a/#".to_string()
+            },
+            serde_json::from_str(r#"{"command": "unsubscribe", "topic": "a/#"}"#).unwrap()
+        );
+        assert_eq!(
+            ControlCommand::PausePublish,
+            serde_json::from_str(r#"{"command": "pause_publish"}"#).unwrap()
+        );
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"command": "restart"}"#).is_err());
+    }
+}
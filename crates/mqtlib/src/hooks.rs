@@ -0,0 +1,256 @@
+use crate::channel;
+use crate::mqtt::{
+    ConversionErrorData, MessageEvent, MessagePublishData, MessageReceivedData, MqttReceiveEvent,
+};
+use crate::sparkplug::host_application::PrimaryHostState;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tracing::{debug, warn};
+
+type Hook<T> = Arc<dyn Fn(T) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Registry of async callbacks which are invoked on events of the client.
+/// Each callback runs in its own task, so slow callbacks don't delay the
+/// processing of messages.
+///
+/// ```
+/// # use mqtlib::hooks::Hooks;
+/// let mut hooks = Hooks::default();
+/// hooks
+///     .on_connect(|| async { println!("Connected") })
+///     .on_message_received(|message| async move {
+///         println!("Received message on topic {}", message.topic);
+///     });
+/// ```
+#[derive(Clone, Default)]
+pub struct Hooks {
+    on_connect: Vec<Hook<()>>,
+    on_disconnect: Vec<Hook<()>>,
+    on_message_received: Vec<Hook<MessageReceivedData>>,
+    on_message_published: Vec<Hook<MessagePublishData>>,
+    on_conversion_error: Vec<Hook<ConversionErrorData>>,
+    on_primary_host_state: Vec<Hook<PrimaryHostState>>,
+}
+
+impl Hooks {
+    /// Registers a callback invoked when the connection to the broker is
+    /// established.
+    pub fn on_connect<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_connect.push(Arc::new(move |_| hook().boxed()));
+        self
+    }
+
+    /// Registers a callback invoked when the connection to the broker is
+    /// closed by either side.
+    pub fn on_disconnect<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_disconnect.push(Arc::new(move |_| hook().boxed()));
+        self
+    }
+
+    /// Registers a callback invoked with each received message after it was
+    /// converted to the payload type of its topic.
+    pub fn on_message_received<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(MessageReceivedData) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_message_received
+            .push(Arc::new(move |message| hook(message).boxed()));
+        self
+    }
+
+    /// Registers a callback invoked with each message handed over to the
+    /// client for publishing.
+    pub fn on_message_published<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(MessagePublishData) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_message_published
+            .push(Arc::new(move |message| hook(message).boxed()));
+        self
+    }
+
+    /// Registers a callback invoked when a received payload can't be
+    /// converted to the payload type of its topic.
+    pub fn on_conversion_error<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(ConversionErrorData) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_conversion_error
+            .push(Arc::new(move |error| hook(error).boxed()));
+        self
+    }
+
+    /// Registers a callback invoked when the tracked Sparkplug primary host
+    /// application goes online or offline.
+    pub fn on_primary_host_state<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(PrimaryHostState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_primary_host_state
+            .push(Arc::new(move |state| hook(state).boxed()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.on_connect.is_empty()
+            && self.on_disconnect.is_empty()
+            && self.on_message_received.is_empty()
+            && self.on_message_published.is_empty()
+            && self.on_conversion_error.is_empty()
+            && self.on_primary_host_state.is_empty()
+    }
+
+    pub fn connected(&self) {
+        invoke(&self.on_connect, ());
+    }
+
+    pub fn disconnected(&self) {
+        invoke(&self.on_disconnect, ());
+    }
+
+    pub fn message_received(&self, message: &MessageReceivedData) {
+        invoke(&self.on_message_received, message.clone());
+    }
+
+    pub fn message_published(&self, message: &MessagePublishData) {
+        invoke(&self.on_message_published, message.clone());
+    }
+
+    pub fn conversion_error(&self, error: &ConversionErrorData) {
+        invoke(&self.on_conversion_error, error.clone());
+    }
+
+    pub fn primary_host_state(&self, state: &PrimaryHostState) {
+        invoke(&self.on_primary_host_state, state.clone());
+    }
+
+    /// Invokes the callbacks matching the event of the MQTT client.
+    pub fn mqtt_event(&self, event: &MqttReceiveEvent) {
+        if event.is_connect() {
+            self.connected();
+        } else if event.is_disconnect() {
+            self.disconnected();
+        }
+    }
+
+    /// Invokes the callbacks matching the message event.
+    pub fn message_event(&self, event: &MessageEvent) {
+        match event {
+            MessageEvent::ReceivedUnfiltered(message) => self.message_received(message),
+            MessageEvent::Publish(message) => self.message_published(message),
+            MessageEvent::ConversionError(error) => self.conversion_error(error),
+            MessageEvent::PrimaryHostState(state) => self.primary_host_state(state),
+            MessageEvent::ReceivedFiltered(_) => {}
+        }
+    }
+}
+
+fn invoke<T: Clone>(hooks: &[Hook<T>], value: T) {
+    for hook in hooks {
+        tokio::spawn(hook(value.clone()));
+    }
+}
+
+/// Invokes the hooks for all events of the MQTT client and messages until
+/// both channels are closed.
+pub fn start_hooks_task(
+    hooks: Arc<Hooks>,
+    mut receiver_mqtt: Receiver<MqttReceiveEvent>,
+    mut receiver_message: Receiver<MessageEvent>,
+) {
+    debug!("Starting hooks");
+
+    tokio::spawn(async move {
+        loop {
+            select! {
+                event = receiver_mqtt.recv() => match event {
+                    Ok(event) => hooks.mqtt_event(&event),
+                    Err(RecvError::Lagged(skipped_events)) => {
+                        channel::count_dropped(skipped_events);
+                        warn!("Hooks skipped {skipped_events} events");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                event = receiver_message.recv() => match event {
+                    Ok(event) => hooks.message_event(&event),
+                    Err(RecvError::Lagged(skipped_messages)) => {
+                        channel::count_dropped(skipped_messages);
+                        warn!("Hooks skipped {skipped_messages} messages");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+
+        debug!("Hooks exited");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PayloadType;
+    use crate::mqtt::QoS;
+    use crate::payload::PayloadFormat;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn invoke_hooks() {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let mut hooks = Hooks::default();
+        assert!(hooks.is_empty());
+
+        let count_connect = count.clone();
+        hooks
+            .on_connect(move || {
+                let count = count_connect.clone();
+                async move {
+                    count.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .on_message_received(move |message| {
+                let sender = sender.clone();
+                async move {
+                    sender.send(message.topic).unwrap();
+                }
+            });
+        assert!(!hooks.is_empty());
+
+        hooks.message_event(&MessageEvent::ReceivedUnfiltered(MessageReceivedData::new(
+            "a/b".to_string(),
+            QoS::AtMostOnce,
+            false,
+            PayloadFormat::try_from((PayloadType::Text(Default::default()), b"a".to_vec()))
+                .unwrap(),
+        )));
+        hooks.message_event(&MessageEvent::Publish(MessagePublishData::new(
+            "c/d".to_string(),
+            QoS::AtMostOnce,
+            false,
+            vec![],
+        )));
+
+        assert_eq!(Some("a/b".to_string()), receiver.recv().await);
+        assert_eq!(0, count.load(Ordering::SeqCst));
+    }
+}
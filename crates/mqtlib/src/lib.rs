@@ -1,40 +1,723 @@
-use crate::config::mqtli_config::MqtliConfig;
-use crate::storage::{get_sql_storage, SqlStorageError, SqlStorageImpl};
+use crate::bridge::run_bridge;
+use crate::config::channels::Channels;
+use crate::config::mqtli_config::{
+    Mode, MqtliConfig, MqttBrokerConnect, MqttVersion, OnPublishComplete,
+};
+use crate::config::subscription::Subscription;
+use crate::config::PayloadType;
+use crate::control::Controller;
+use crate::hooks::{start_hooks_task, Hooks};
+use crate::mqtt::group::MqttServiceGroup;
+use crate::mqtt::mqtt_handler::MqttHandler;
+use crate::mqtt::router::{MqttServiceRouter, RoutedService};
+use crate::mqtt::v311::mqtt_service::MqttServiceV311;
+use crate::mqtt::v5::mqtt_service::MqttServiceV5;
+use crate::mqtt::{MessageEvent, MqttReceiveEvent, MqttService, MqttServiceError};
+use crate::output::console::ConsoleOutput;
+use crate::output::OutputContext;
+use crate::payload::PayloadFormatError;
+use crate::ping::run_ping;
+use crate::proto_check::run_proto_check;
+use crate::publish::trigger_http::TriggerHttp;
+use crate::publish::trigger_on_message::TriggerOnMessage;
+use crate::publish::trigger_periodic::TriggerPeriodic;
+use crate::publish::TriggerError;
+use crate::self_test::run_self_test;
+use crate::sparkplug::conformance::run_conformance_check;
+use crate::sparkplug::network::SparkplugNetwork;
+use crate::storage::buffer::SqlBuffer;
+use crate::storage::queue::SqlWriteQueue;
+use crate::storage::{get_sql_storage, SqlStorageError};
+use crate::tasks::dump::StatisticsDump;
+use crate::tasks::shutdown::{start_force_quit_task, DrainQueues};
+use crate::topic_stats::run_topic_stats;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
 use thiserror::Error;
+use tokio::select;
+use tokio::signal;
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tracing::{error, info, warn};
 
+pub mod bridge;
+pub mod channel;
+pub mod client;
 pub mod config;
+pub mod control;
+pub mod hooks;
 pub mod mqtt;
 pub mod output;
 pub mod payload;
+pub mod ping;
+pub mod proto_check;
 pub mod publish;
+pub mod self_test;
 pub mod sparkplug;
 pub mod storage;
+pub mod systemd;
+mod tasks;
+pub mod template;
+pub mod testing;
+pub mod topic_stats;
 
 #[derive(Error, Debug)]
 pub enum MqtlibError {
     #[error("SQL storage error")]
     SqlStorageError(#[from] SqlStorageError),
+    #[error("MQTT error")]
+    MqttServiceError(#[from] MqttServiceError),
+    #[error("Error while converting payload")]
+    PayloadFormatError(#[from] PayloadFormatError),
+    #[error("Error while starting HTTP API")]
+    HttpApiError(#[source] TriggerError),
+    #[error("Storing Sparkplug messages requires a SQL storage")]
+    SparkplugSqlStorageMissing,
+    #[error("Connection to the broker was closed")]
+    Disconnected,
+    #[error("Subscription skipped {0} messages which were not received in time")]
+    SubscriptionLagged(u64),
+    #[error("Self test failed")]
+    SelfTestFailed,
+    #[error("Could not read the sample payload \"{1}\"")]
+    CouldNotReadProtoSample(#[source] std::io::Error, std::path::PathBuf),
+    #[error("Protobuf check failed")]
+    ProtoCheckFailed,
+    #[error("Subscription was not granted with the requested QoS")]
+    SubscriptionNotGranted,
+    #[error("Could not connect to the broker")]
+    ConnectionFailed,
 }
 
-#[derive(Debug, Default)]
+/// Cause of leaving the main loop of [`Mqtlib::run_until`].
+#[derive(Debug, PartialEq)]
+enum ShutdownReason {
+    /// The shutdown future completed, e.g. ctrl + c was pressed
+    Signal,
+    /// All scheduled messages were published
+    PublishComplete,
+    /// The connection to the broker was closed
+    ConnectionClosed,
+}
+
+/// Runs mqtli as configured: connects to the broker, subscribes to the
+/// topics, publishes messages using their triggers and writes received
+/// messages to the outputs.
 pub struct Mqtlib {
     config: MqtliConfig,
-    sql_storage: Option<Box<dyn SqlStorageImpl>>,
+    hooks: Hooks,
+    sparkplug_network: Arc<Mutex<SparkplugNetwork>>,
+    /// Files, commands and connections of the outputs of this instance
+    output_context: Arc<OutputContext>,
+    /// Used instead of connecting to the configured broker
+    mqtt_service: Option<Arc<Mutex<dyn MqttService>>>,
 }
 
 impl Mqtlib {
     pub fn new(config: MqtliConfig) -> Self {
+        let sparkplug_network = Arc::new(Mutex::new(
+            SparkplugNetwork::new(config.sparkplug.metric_filter.clone()).with_primary_host(
+                config
+                    .sparkplug
+                    .primary_host
+                    .as_ref()
+                    .map(|primary_host| primary_host.host_id.clone()),
+            ),
+        ));
+
         Self {
             config,
-            sql_storage: None,
+            hooks: Hooks::default(),
+            sparkplug_network,
+            output_context: Arc::new(OutputContext::default()),
+            mqtt_service: None,
         }
     }
 
-    pub async fn init(&mut self) -> Result<(), MqtlibError> {
-        if let Some(sql) = self.config.sql_storage.as_ref() {
-            self.sql_storage = Some(get_sql_storage(sql).await?);
+    /// Uses the given service instead of connecting to the configured
+    /// broker, e.g. a [`testing::MockMqttService`] in tests.
+    pub fn with_mqtt_service(mut self, mqtt_service: Arc<Mutex<dyn MqttService>>) -> Self {
+        self.mqtt_service = Some(mqtt_service);
+        self
+    }
+
+    /// Hooks invoked while running, to register callbacks before calling
+    /// [`Mqtlib::run`].
+    pub fn hooks(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+
+    /// Sparkplug network which is monitored while running.
+    pub fn sparkplug_network(&self) -> Arc<Mutex<SparkplugNetwork>> {
+        self.sparkplug_network.clone()
+    }
+
+    /// Runs until ctrl + c is pressed or SIGTERM is received, e.g. when
+    /// systemd stops the service.
+    pub async fn run(self) -> Result<(), MqtlibError> {
+        self.run_until(async {
+            select! {
+                result = signal::ctrl_c() => {
+                    if let Err(e) = result {
+                        error!("Could not add ctrl + c handler: {e:?}");
+                    }
+                }
+                _ = terminate_signal() => {}
+            }
+        })
+        .await
+    }
+
+    /// Runs until the shutdown future completes or the connection is closed.
+    /// On shutdown, waits up to the shutdown timeout until the received
+    /// messages are written and the publishes are acknowledged, then flushes
+    /// the outputs and disconnects from the broker. Pressing ctrl + c again
+    /// or exceeding the force quit timeout exits the process immediately
+    /// with exit code 130.
+    pub async fn run_until<F>(self, shutdown: F) -> Result<(), MqtlibError>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let config = self.config;
+        let output_context = self.output_context;
+
+        let force_quit_timeout = config.force_quit_timeout;
+        let shutdown = async move {
+            shutdown.await;
+            start_force_quit_task(force_quit_timeout);
+        };
+
+        // shutdown happens in steps: the exit signal stops taking in new
+        // work, then the queues are drained, outputs flushed and finally the
+        // client disconnects
+        let (sender_exit, _) = broadcast::channel::<()>(5);
+        let (sender_flush, _) = broadcast::channel::<()>(1);
+        let (sender_disconnect, _) = broadcast::channel::<()>(1);
+
+        if config.mode == Mode::ProtoCheck {
+            let result = run_proto_check(&config, &config.proto_check)?;
+            if let Err(e) = ConsoleOutput::output_string(result.to_string()) {
+                error!("Error while printing the protobuf check result: {e:?}");
+            }
+            if !result.is_success() {
+                return Err(MqtlibError::ProtoCheckFailed);
+            }
+            return Ok(());
+        }
+
+        let mqtt_service = match self.mqtt_service {
+            Some(mqtt_service) => mqtt_service,
+            None => create_routed_mqtt_service(&config),
+        };
+
+        if config.mode == Mode::Ping {
+            let result = run_ping(mqtt_service, &config.ping, config.channels.capacity).await?;
+            if let Err(e) = ConsoleOutput::output_string(result.to_string()) {
+                error!("Error while printing the ping result: {e:?}");
+            }
+            return Ok(());
+        }
+
+        if config.mode == Mode::SelfTest {
+            let result =
+                run_self_test(mqtt_service, &config.self_test, config.channels.capacity).await?;
+            if let Err(e) = ConsoleOutput::output_string(result.to_string()) {
+                error!("Error while printing the self test result: {e:?}");
+            }
+            if !result.is_success() {
+                return Err(MqtlibError::SelfTestFailed);
+            }
+            return Ok(());
+        }
+
+        if config.mode == Mode::TopicStats {
+            let result = run_topic_stats(
+                mqtt_service,
+                &config.topic_stats,
+                config.channels.capacity,
+                shutdown,
+            )
+            .await?;
+            if let Err(e) = ConsoleOutput::output_string(result.to_string()) {
+                error!("Error while printing the topic statistics: {e:?}");
+            }
+            return Ok(());
+        }
+
+        if config.mode == Mode::SparkplugCheck {
+            let checker = run_conformance_check(
+                mqtt_service,
+                &config.sparkplug.check,
+                config.channels.capacity,
+                shutdown,
+            )
+            .await?;
+            if let Err(e) = ConsoleOutput::output_string(checker.to_string()) {
+                error!("Error while printing the conformance report: {e:?}");
+            }
+            return Ok(());
+        }
+
+        if config.mode == Mode::Bridge {
+            let target: Arc<Mutex<dyn MqttService>> = Arc::new(Mutex::new(new_mqtt_service(
+                &config.bridge.target,
+                &config.channels,
+            )));
+            return run_bridge(
+                mqtt_service,
+                target,
+                config.bridge.rules.clone(),
+                config.channels.capacity,
+                shutdown,
+            )
+            .await;
+        }
+
+        let filtered_subscriptions: Vec<(Subscription, String)> = config
+            .topic_storage
+            .topics
+            .iter()
+            .filter_map(|topic| {
+                topic
+                    .subscription()
+                    .clone()
+                    .map(|s| (s, topic.topic().clone()))
+            })
+            .filter(|(s, _)| *s.enabled())
+            .collect();
+
+        let capacity = config.channels.capacity;
+        let (sender_receive, _) = broadcast::channel::<MqttReceiveEvent>(capacity);
+        let (sender_message, _) = broadcast::channel::<MessageEvent>(capacity);
+
+        let topic_storage = Arc::new(config.topic_storage);
+
+        if !self.hooks.is_empty() {
+            start_hooks_task(
+                Arc::new(self.hooks),
+                sender_receive.subscribe(),
+                sender_message.subscribe(),
+            );
+        }
+
+        if config.systemd {
+            let control_subscriptions = usize::from(config.control_topic.is_some());
+            tasks::systemd::start_systemd_task(
+                filtered_subscriptions.len() + control_subscriptions,
+                sender_receive.subscribe(),
+                sender_exit.subscribe(),
+            );
+        }
+
+        let mut mqtt_loop_handle = mqtt_service
+            .lock()
+            .await
+            .connect(sender_receive.clone(), sender_disconnect.subscribe())
+            .await?;
+
+        tasks::publish::start_publish_task(sender_message.subscribe(), mqtt_service.clone());
+
+        if let Some(http_api) = config.http_api.as_ref() {
+            TriggerHttp::new(
+                http_api.clone(),
+                topic_storage.clone(),
+                sender_message.clone(),
+            )
+            .start(sender_exit.subscribe())
+            .await
+            .map_err(MqtlibError::HttpApiError)?;
+        }
+
+        let scheduler = TriggerPeriodic::new(mqtt_service.clone(), capacity)
+            .await
+            .with_rate(config.publish_rate);
+
+        let trigger_on_message = TriggerOnMessage::new(mqtt_service.clone());
+
+        let (sender_complete, receiver_complete) = oneshot::channel::<()>();
+        tasks::scheduler::start_scheduler_monitor_task(
+            scheduler.get_receiver_command(),
+            config.on_publish_complete,
+            topic_storage.has_trigger_periodic(),
+            !filtered_subscriptions.is_empty()
+                || topic_storage.has_trigger_on_message()
+                || config.control_topic.is_some()
+                || !config.alerts.is_empty(),
+            sender_complete,
+        );
+
+        if let Some(control_topic) = config.control_topic.clone() {
+            tasks::control::start_control_task(
+                Controller::new(control_topic, mqtt_service.clone(), scheduler.paused()),
+                sender_receive.subscribe(),
+                sender_exit.subscribe(),
+            );
+        }
+
+        if let Some(dead_letter) = config.dead_letter.clone() {
+            tasks::dead_letter::start_dead_letter_task(
+                dead_letter,
+                sender_message.subscribe(),
+                sender_message.clone(),
+            );
+        }
+
+        let scheduler_status = scheduler.status();
+        tasks::scheduler::start_scheduler_task(
+            scheduler,
+            trigger_on_message,
+            sender_receive.clone(),
+            topic_storage.clone(),
+            sender_exit.subscribe(),
+        );
+
+        let mut incoming_messages_handler = MqttHandler::new(
+            topic_storage.clone(),
+            config.payload_limit.clone(),
+            config.workers,
+            config.channels.clone(),
+        );
+        incoming_messages_handler.start_task(sender_receive.subscribe(), sender_message.clone());
+
+        tasks::subscription::start_subscription_task(
+            mqtt_service.clone(),
+            sender_receive.clone(),
+            filtered_subscriptions,
+        );
+
+        tasks::connection_hooks::start_connection_hooks_task(
+            config.connection_hooks.clone(),
+            sender_receive.clone(),
+            sender_message.clone(),
+        );
+
+        if !config.alerts.is_empty() {
+            tasks::alerts::start_alerts_task(
+                config.alerts.clone(),
+                mqtt_service.clone(),
+                &sender_receive,
+                sender_message.clone(),
+                sender_exit.subscribe(),
+            );
+        }
+
+        tasks::broker_info::start_broker_info_task(sender_receive.clone(), config.show_broker_info);
+
+        if let Some(birth) = config.broker.birth() {
+            tasks::birth::start_birth_task(
+                birth.clone(),
+                sender_receive.clone(),
+                sender_message.clone(),
+            );
+        }
+
+        let exclude_types = match config.mode {
+            Mode::Sparkplug | Mode::SparkplugEmulator => vec![PayloadType::Sparkplug],
+            _ => vec![],
+        };
+
+        let db = if let Some(sql) = &config.sql_storage {
+            Some(get_sql_storage(sql).await?)
+        } else {
+            None
+        };
+
+        if config.sparkplug.store_sql || topic_storage.has_output_sparkplug_sql() {
+            match &db {
+                Some(db) => db.create_sparkplug_schema().await?,
+                None => return Err(MqtlibError::SparkplugSqlStorageMissing),
+            }
+        }
+
+        let has_default_statement = config
+            .sql_storage
+            .as_ref()
+            .is_some_and(|sql| sql.statements.default.is_some());
+        if topic_storage.has_output_default_sql() && !has_default_statement {
+            if let Some(db) = &db {
+                db.create_default_schema().await?;
+            }
+        }
+
+        let (db, db_handle) = match (db, &config.sql_storage) {
+            (Some(db), Some(sql)) => {
+                let buffer = match &sql.buffer_path {
+                    Some(path) => Some(SqlBuffer::open(path, sql.buffer_size).await?),
+                    None => None,
+                };
+                let (queue, handle) =
+                    SqlWriteQueue::start(db, sql, buffer, sender_flush.subscribe());
+                (Some(queue), Some(handle))
+            }
+            _ => (None, None),
+        };
+
+        if let Some(export) = config.sparkplug.snapshot() {
+            tasks::sparkplug::start_snapshot_export_task(
+                self.sparkplug_network.clone(),
+                export.clone(),
+                sender_message.clone(),
+                sender_exit.subscribe(),
+            );
+        }
+
+        // printing the summary would corrupt the terminal UI
+        if let Some(interval) = config
+            .sparkplug
+            .summary_interval
+            .filter(|_| !config.sparkplug.tui)
+        {
+            tasks::sparkplug::start_summary_task(
+                self.sparkplug_network.clone(),
+                interval,
+                sender_exit.subscribe(),
+            );
+        }
+
+        if let Some(emulator) = config.sparkplug.emulator() {
+            tasks::sparkplug::start_sparkplug_emulator(
+                emulator.clone(),
+                sender_message.clone(),
+                sender_message.subscribe(),
+                sender_exit.subscribe(),
+            );
+        }
+
+        if let Some(primary_host) = config.sparkplug.primary_host.clone() {
+            tasks::sparkplug::start_primary_host_alert_task(
+                primary_host,
+                sender_message.subscribe(),
+            );
+        }
+
+        tasks::sparkplug::start_sparkplug_monitor(
+            self.sparkplug_network,
+            config.sparkplug.clone(),
+            topic_storage.clone(),
+            sender_message.clone(),
+            sender_message.subscribe(),
+            db.clone(),
+            output_context.clone(),
+        );
+
+        tasks::dump::start_statistics_dump_task(
+            StatisticsDump {
+                mqtt_service: mqtt_service.clone(),
+                sender_receive: sender_receive.clone(),
+                sender_message: sender_message.clone(),
+                scheduler: scheduler_status,
+                sql_queue: db.clone(),
+            },
+            sender_exit.subscribe(),
+        );
+
+        // printing the statistics would corrupt the terminal UI
+        let statistics_handle =
+            config
+                .stats_interval
+                .filter(|_| !config.sparkplug.tui)
+                .map(|interval| {
+                    tasks::statistics::start_statistics_task(
+                        sender_message.subscribe(),
+                        interval,
+                        db.clone(),
+                        sender_exit.subscribe(),
+                    )
+                });
+
+        tasks::output::start_output_task(
+            sender_message.subscribe(),
+            topic_storage.clone(),
+            sender_message.clone(),
+            exclude_types,
+            db.clone(),
+            config.stats_interval.is_none(),
+            config.workers,
+            output_context.clone(),
+        );
+
+        tasks::output::start_file_flush_task(output_context.clone(), sender_exit.subscribe());
+
+        let reason = select! {
+            _ = shutdown => {
+                info!("Exit signal received, shutting down");
+                ShutdownReason::Signal
+            }
+            Ok(()) = receiver_complete => {
+                info!("All scheduled messages published, shutting down");
+                ShutdownReason::PublishComplete
+            }
+            result = &mut mqtt_loop_handle => {
+                result.expect("Error while waiting for tasks to shut down");
+                ShutdownReason::ConnectionClosed
+            }
+        };
+        let connected = reason != ShutdownReason::ConnectionClosed;
+
+        if reason != ShutdownReason::Signal {
+            start_force_quit_task(config.force_quit_timeout);
+        }
+
+        // stop triggers, HTTP API and control topic
+        if let Err(e) = sender_exit.send(()) {
+            warn!("No active listeners for exit signal present: {e:?}");
+        };
+
+        if connected {
+            tasks::connection_hooks::publish_disconnect_messages(
+                &config.connection_hooks,
+                &sender_message,
+            );
+        }
+
+        let started_at = Instant::now();
+        DrainQueues {
+            mqtt_service: &mqtt_service,
+            sender_receive: &sender_receive,
+            sender_message: &sender_message,
+            sql_queue: db.as_ref(),
+            connected,
+        }
+        .drain(config.shutdown_timeout)
+        .await;
+
+        let _ = sender_flush.send(());
+        if let Some(db_handle) = db_handle {
+            let remaining = config.shutdown_timeout.saturating_sub(started_at.elapsed());
+            if tokio::time::timeout(remaining, db_handle).await.is_err() {
+                warn!("Shutdown timeout elapsed while writing to SQL storage");
+            }
+        }
+
+        output_context
+            .close(started_at + config.shutdown_timeout)
+            .await;
+
+        if connected {
+            let _ = sender_disconnect.send(());
+            mqtt_loop_handle
+                .await
+                .expect("Error while waiting for tasks to shut down");
+        }
+
+        tasks::connection_hooks::run_disconnect_commands(&config.connection_hooks).await;
+
+        if let Some(statistics_handle) = statistics_handle {
+            statistics_handle
+                .await
+                .expect("Error while waiting for the message statistics");
+        }
+
+        if reason == ShutdownReason::PublishComplete
+            && config.on_publish_complete == OnPublishComplete::Summary
+        {
+            tasks::statistics::print_publish_summary(&mqtt_service.lock().await.published());
+        }
+
+        if mqtt_service.lock().await.granted_qos_failed() {
+            return Err(MqtlibError::SubscriptionNotGranted);
+        }
+
+        if mqtt_service.lock().await.connection_failed() {
+            return Err(MqtlibError::ConnectionFailed);
         }
 
         Ok(())
     }
 }
+
+/// Creates the service for the configured broker. If topics have a dedicated
+/// connection, each connection gets its own client and the subscriptions and
+/// publishes are routed by their topic. With several clients, each of them
+/// runs all topics.
+fn create_routed_mqtt_service(config: &MqtliConfig) -> Arc<Mutex<dyn MqttService>> {
+    if config.clients > 1 {
+        let clients = (1..=config.clients)
+            .map(|index| new_routed_mqtt_service(&config.client(index)))
+            .collect();
+        return Arc::new(Mutex::new(MqttServiceGroup::new(clients)));
+    }
+
+    match create_router(config) {
+        Some(router) => Arc::new(Mutex::new(router)),
+        None => Arc::new(Mutex::new(new_mqtt_service(
+            &config.broker,
+            &config.channels,
+        ))),
+    }
+}
+
+fn new_routed_mqtt_service(config: &MqtliConfig) -> RoutedService {
+    match create_router(config) {
+        Some(router) => Box::new(router),
+        None => new_mqtt_service(&config.broker, &config.channels),
+    }
+}
+
+/// Creates the router of the dedicated connections, None if no topic has a
+/// dedicated connection.
+fn create_router(config: &MqtliConfig) -> Option<MqttServiceRouter> {
+    let routes: Vec<(&String, &String)> = config
+        .topic_storage
+        .topics
+        .iter()
+        .filter_map(|topic| {
+            topic
+                .connection
+                .as_ref()
+                .map(|connection| (&topic.topic, connection))
+        })
+        .collect();
+
+    if routes.is_empty() {
+        return None;
+    }
+
+    let mut router = MqttServiceRouter::new(new_mqtt_service(&config.broker, &config.channels));
+    let connections: BTreeSet<&String> = routes.iter().map(|(_, connection)| *connection).collect();
+    for connection in connections {
+        router = router.with_connection(
+            connection,
+            new_mqtt_service(&config.dedicated_connection(connection), &config.channels),
+        );
+    }
+    for (topic, connection) in routes {
+        router = router.with_route(topic, connection);
+    }
+
+    Some(router)
+}
+
+/// Creates the client of the MQTT version configured for the broker.
+fn new_mqtt_service(broker: &MqttBrokerConnect, channels: &Channels) -> RoutedService {
+    match broker.mqtt_version() {
+        MqttVersion::V311 => Box::new(MqttServiceV311::new(
+            Arc::new(broker.clone()),
+            channels.clone(),
+        )),
+        MqttVersion::V5 => Box::new(MqttServiceV5::new(
+            Arc::new(broker.clone()),
+            channels.clone(),
+        )),
+    }
+}
+
+/// Completes when SIGTERM is received.
+#[cfg(unix)]
+async fn terminate_signal() {
+    match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            error!("Could not add SIGTERM handler: {e:?}");
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminate_signal() {
+    std::future::pending::<()>().await;
+}
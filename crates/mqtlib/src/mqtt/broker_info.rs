@@ -0,0 +1,177 @@
+use colored::Colorize;
+use rumqttc::v5::mqttbytes::v5::ConnAckProperties;
+use std::fmt::{Display, Formatter};
+
+/// Capabilities of the broker announced in the CONNACK properties of MQTT v5.
+/// Properties the broker didn't send are set to the defaults of the
+/// specification.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrokerInfo {
+    pub maximum_qos: u8,
+    pub retain_available: bool,
+    pub wildcard_subscription_available: bool,
+    pub subscription_identifiers_available: bool,
+    pub shared_subscription_available: bool,
+    pub topic_alias_maximum: u16,
+    pub receive_maximum: u16,
+    pub maximum_packet_size: Option<u32>,
+    pub server_keep_alive: Option<u16>,
+    pub session_expiry_interval: Option<u32>,
+    pub assigned_client_id: Option<String>,
+    pub response_information: Option<String>,
+    pub server_reference: Option<String>,
+    pub reason: Option<String>,
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl Default for BrokerInfo {
+    fn default() -> Self {
+        Self {
+            maximum_qos: 2,
+            retain_available: true,
+            wildcard_subscription_available: true,
+            subscription_identifiers_available: true,
+            shared_subscription_available: true,
+            topic_alias_maximum: 0,
+            receive_maximum: u16::MAX,
+            maximum_packet_size: None,
+            server_keep_alive: None,
+            session_expiry_interval: None,
+            assigned_client_id: None,
+            response_information: None,
+            server_reference: None,
+            reason: None,
+            user_properties: vec![],
+        }
+    }
+}
+
+impl From<Option<&ConnAckProperties>> for BrokerInfo {
+    fn from(value: Option<&ConnAckProperties>) -> Self {
+        let default = BrokerInfo::default();
+        let Some(properties) = value else {
+            return default;
+        };
+        let flag = |value: Option<u8>, default: bool| value.map_or(default, |value| value == 1);
+
+        Self {
+            maximum_qos: properties.max_qos.unwrap_or(default.maximum_qos),
+            retain_available: flag(properties.retain_available, default.retain_available),
+            wildcard_subscription_available: flag(
+                properties.wildcard_subscription_available,
+                default.wildcard_subscription_available,
+            ),
+            subscription_identifiers_available: flag(
+                properties.subscription_identifiers_available,
+                default.subscription_identifiers_available,
+            ),
+            shared_subscription_available: flag(
+                properties.shared_subscription_available,
+                default.shared_subscription_available,
+            ),
+            topic_alias_maximum: properties
+                .topic_alias_max
+                .unwrap_or(default.topic_alias_maximum),
+            receive_maximum: properties.receive_max.unwrap_or(default.receive_maximum),
+            maximum_packet_size: properties.max_packet_size,
+            server_keep_alive: properties.server_keep_alive,
+            session_expiry_interval: properties.session_expiry_interval,
+            assigned_client_id: properties.assigned_client_identifier.clone(),
+            response_information: properties.response_information.clone(),
+            server_reference: properties.server_reference.clone(),
+            reason: properties.reason_string.clone(),
+            user_properties: properties.user_properties.clone(),
+        }
+    }
+}
+
+impl Display for BrokerInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+        writeln!(f, "{}", " Broker info ".on_blue())?;
+        writeln!(f, "maximum QoS: {}", self.maximum_qos)?;
+        writeln!(f, "retain available: {}", self.retain_available)?;
+        writeln!(
+            f,
+            "wildcard subscriptions available: {}",
+            self.wildcard_subscription_available
+        )?;
+        writeln!(
+            f,
+            "subscription identifiers available: {}",
+            self.subscription_identifiers_available
+        )?;
+        writeln!(
+            f,
+            "shared subscriptions available: {}",
+            self.shared_subscription_available
+        )?;
+        writeln!(f, "topic alias maximum: {}", self.topic_alias_maximum)?;
+        writeln!(f, "receive maximum: {}", self.receive_maximum)?;
+        writeln!(
+            f,
+            "maximum packet size: {}",
+            optional(self.maximum_packet_size.map(|size| format!("{size} bytes")))
+        )?;
+        writeln!(
+            f,
+            "server keep alive: {}",
+            optional(self.server_keep_alive.map(|seconds| format!("{seconds} s")))
+        )?;
+        writeln!(
+            f,
+            "session expiry interval: {}",
+            optional(
+                self.session_expiry_interval
+                    .map(|seconds| format!("{seconds} s"))
+            )
+        )?;
+        writeln!(
+            f,
+            "assigned client id: {}",
+            optional(self.assigned_client_id.clone())
+        )?;
+        writeln!(
+            f,
+            "response information: {}",
+            optional(self.response_information.clone())
+        )?;
+        writeln!(
+            f,
+            "server reference: {}",
+            optional(self.server_reference.clone())
+        )?;
+        write!(f, "reason: {}", optional(self.reason.clone()))?;
+        for (key, value) in &self.user_properties {
+            write!(f, "\nuser property: {key}={value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_for_missing_properties() {
+        let properties = ConnAckProperties {
+            max_qos: Some(1),
+            retain_available: Some(0),
+            topic_alias_max: Some(10),
+            assigned_client_identifier: Some("auto-1".to_string()),
+            ..ConnAckProperties::new()
+        };
+
+        let info = BrokerInfo::from(Some(&properties));
+
+        assert_eq!(1, info.maximum_qos);
+        assert!(!info.retain_available);
+        assert!(info.shared_subscription_available);
+        assert_eq!(10, info.topic_alias_maximum);
+        assert_eq!(Some("auto-1".to_string()), info.assigned_client_id);
+        assert_eq!(BrokerInfo::default(), BrokerInfo::from(None));
+    }
+}
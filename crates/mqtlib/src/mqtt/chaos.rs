@@ -0,0 +1,93 @@
+use crate::config::mqtli_config::ChaosConfig;
+use crate::mqtt::QoS;
+use rand::Rng;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Decides randomly when to disturb the connection and the publishes, so
+/// applications consuming from the broker can be tested against an
+/// unreliable connection.
+#[derive(Debug)]
+pub struct ChaosMonkey {
+    config: ChaosConfig,
+    /// Time of the last decision whether to drop the connection
+    checked_at: Mutex<Instant>,
+}
+
+impl ChaosMonkey {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self {
+            config,
+            checked_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Decides whether to drop the connection now. The probability applies
+    /// per second, so it is scaled to the time since the last decision.
+    pub fn drop_connection(&self) -> bool {
+        let mut checked_at = self.checked_at.lock().unwrap();
+        let elapsed = checked_at.elapsed().as_secs_f64();
+        *checked_at = Instant::now();
+
+        chance(1.0 - (1.0 - self.config.drop_connection).powf(elapsed))
+    }
+
+    /// Decides whether to drop a publish instead of sending it.
+    pub fn drop_publish(&self) -> bool {
+        chance(self.config.drop_publish)
+    }
+
+    /// Returns the random time to delay a publish by, if it is delayed.
+    pub fn publish_delay(&self) -> Option<Duration> {
+        chance(self.config.delay_publish)
+            .then(|| self.config.max_delay.mul_f64(rand::thread_rng().gen()))
+    }
+
+    /// Decides whether to send a publish twice. Only QoS 1 publishes are
+    /// duplicated, as the receivers of those must cope with duplicates.
+    pub fn duplicate(&self, qos: QoS) -> bool {
+        qos == QoS::AtLeastOnce && chance(self.config.duplicate)
+    }
+}
+
+fn chance(probability: f64) -> bool {
+    probability > 0.0 && rand::thread_rng().gen_bool(probability.min(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monkey(probability: f64) -> ChaosMonkey {
+        ChaosMonkey::new(ChaosConfig {
+            drop_connection: probability,
+            drop_publish: probability,
+            delay_publish: probability,
+            max_delay: Duration::from_millis(100),
+            duplicate: probability,
+        })
+    }
+
+    #[test]
+    fn never() {
+        let monkey = monkey(0.0);
+
+        assert!(!monkey.drop_connection());
+        assert!(!monkey.drop_publish());
+        assert_eq!(None, monkey.publish_delay());
+        assert!(!monkey.duplicate(QoS::AtLeastOnce));
+    }
+
+    #[test]
+    fn always() {
+        let monkey = monkey(1.0);
+
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(monkey.drop_connection());
+        assert!(monkey.drop_publish());
+        assert!(monkey.publish_delay().unwrap() <= Duration::from_millis(100));
+        assert!(monkey.duplicate(QoS::AtLeastOnce));
+        assert!(!monkey.duplicate(QoS::AtMostOnce));
+        assert!(!monkey.duplicate(QoS::ExactlyOnce));
+    }
+}
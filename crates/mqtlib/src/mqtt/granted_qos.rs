@@ -0,0 +1,181 @@
+use crate::mqtt::{MqttReceiveEvent, QoS};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, error, warn};
+
+/// Compares the QoS the broker granted for each subscription with the
+/// requested QoS and reports downgraded or rejected subscriptions.
+///
+/// Subscriptions are matched with their acknowledgements by the packet id
+/// the client assigns when sending them. Packets are sent in the order they
+/// were passed to the client, so the requests are kept in that order until
+/// the packet id is known.
+#[derive(Debug, Default)]
+pub struct GrantedQosCheck {
+    /// Fail instead of warn if a subscription was not granted as requested
+    strict: bool,
+    /// Subscriptions passed to the client but not sent yet
+    unsent: Mutex<VecDeque<(String, QoS)>>,
+    /// Subscriptions by packet id, waiting to be acknowledged
+    sent: Mutex<HashMap<u16, (String, QoS)>>,
+    /// Topic and granted QoS of the subscription acknowledged last, None if
+    /// it was rejected
+    acknowledged: Mutex<Option<(String, Option<QoS>)>>,
+    failed: AtomicBool,
+}
+
+impl GrantedQosCheck {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            ..Default::default()
+        }
+    }
+
+    /// Must be called right before each subscription is passed to the client.
+    pub fn track(&self, topic: String, qos: QoS) {
+        self.unsent.lock().unwrap().push_back((topic, qos));
+    }
+
+    /// Must be called if the subscription tracked last could not be passed
+    /// to the client.
+    pub fn untrack(&self) {
+        self.unsent.lock().unwrap().pop_back();
+    }
+
+    /// Returns true if a subscription was not granted as requested in strict
+    /// mode.
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the topic and granted QoS of the subscription acknowledged by
+    /// the event passed to [`Self::handle_event`] last.
+    pub fn take_acknowledged(&self) -> Option<(String, Option<QoS>)> {
+        self.acknowledged.lock().unwrap().take()
+    }
+
+    /// Assigns packet ids to sent subscriptions and checks the granted QoS
+    /// of acknowledged ones. Returns true if a subscription was not granted
+    /// as requested in strict mode and the connection should be closed.
+    pub fn handle_event(&self, event: &MqttReceiveEvent) -> bool {
+        if let Some(pkid) = event.outgoing_subscribe_pkid() {
+            let request = self.unsent.lock().unwrap().pop_front();
+            if let Some(request) = request {
+                self.sent.lock().unwrap().insert(pkid, request);
+            }
+            return false;
+        }
+
+        let Some((pkid, granted)) = event.subscribe_ack() else {
+            return false;
+        };
+        let Some((topic, requested)) = self.sent.lock().unwrap().remove(&pkid) else {
+            return false;
+        };
+
+        let granted = granted.first().copied().flatten();
+        *self.acknowledged.lock().unwrap() = Some((topic.clone(), granted));
+
+        let problem = match granted {
+            None => format!("Subscription to topic {topic} was rejected by the broker"),
+            Some(granted) if (granted as u8) < (requested as u8) => format!(
+                "Subscription to topic {topic} was downgraded by the broker from QoS {} to QoS {}",
+                requested as u8, granted as u8
+            ),
+            Some(granted) => {
+                debug!(
+                    "Subscription to topic {topic} granted with QoS {}",
+                    granted as u8
+                );
+                return false;
+            }
+        };
+
+        if self.strict {
+            error!("{problem}, closing the connection as strict QoS checking is enabled");
+            self.failed.store(true, Ordering::Relaxed);
+        } else {
+            warn!("{problem}");
+        }
+
+        self.strict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::{Event, Incoming, Outgoing, SubAck, SubscribeReasonCode};
+
+    fn sent(pkid: u16) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(Event::Outgoing(Outgoing::Subscribe(pkid)))
+    }
+
+    fn ack(pkid: u16, code: SubscribeReasonCode) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(Event::Incoming(Incoming::SubAck(SubAck::new(
+            pkid,
+            vec![code],
+        ))))
+    }
+
+    #[test]
+    fn granted() {
+        let check = GrantedQosCheck::new(true);
+        check.track("a".to_string(), QoS::AtLeastOnce);
+        check.track("b".to_string(), QoS::AtMostOnce);
+
+        assert!(!check.handle_event(&sent(1)));
+        assert!(!check.handle_event(&sent(2)));
+        assert!(!check.handle_event(&ack(
+            2,
+            SubscribeReasonCode::Success(rumqttc::QoS::AtMostOnce)
+        )));
+        assert!(!check.handle_event(&ack(
+            1,
+            SubscribeReasonCode::Success(rumqttc::QoS::ExactlyOnce)
+        )));
+        assert!(!check.failed());
+        assert_eq!(
+            Some(("a".to_string(), Some(QoS::ExactlyOnce))),
+            check.take_acknowledged()
+        );
+        assert_eq!(None, check.take_acknowledged());
+    }
+
+    #[test]
+    fn downgraded() {
+        let check = GrantedQosCheck::new(false);
+        check.track("a".to_string(), QoS::ExactlyOnce);
+        check.handle_event(&sent(1));
+
+        assert!(!check.handle_event(&ack(
+            1,
+            SubscribeReasonCode::Success(rumqttc::QoS::AtLeastOnce)
+        )));
+        assert!(!check.failed());
+
+        let check = GrantedQosCheck::new(true);
+        check.track("a".to_string(), QoS::ExactlyOnce);
+        check.handle_event(&sent(1));
+
+        assert!(check.handle_event(&ack(
+            1,
+            SubscribeReasonCode::Success(rumqttc::QoS::AtLeastOnce)
+        )));
+        assert!(check.failed());
+    }
+
+    #[test]
+    fn rejected() {
+        let check = GrantedQosCheck::new(true);
+        check.track("a".to_string(), QoS::AtMostOnce);
+        check.track("b".to_string(), QoS::AtMostOnce);
+        check.untrack();
+        check.handle_event(&sent(7));
+
+        assert!(check.handle_event(&ack(7, SubscribeReasonCode::Failure)));
+        assert!(check.failed());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::mqtt::router::{connect_all, RoutedService};
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService, MqttServiceError, QoS};
+use crate::output::statistics::MessageStatistics;
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// Runs the same scenario on several parallel clients, e.g. to load test
+/// the session handling of a broker. Every client subscribes to all topics
+/// and publishes every message, and the received events of all clients are
+/// sent to the same channel, so outputs and statistics cover all clients.
+pub struct MqttServiceGroup {
+    clients: Vec<RoutedService>,
+}
+
+impl MqttServiceGroup {
+    pub fn new(clients: Vec<RoutedService>) -> Self {
+        Self { clients }
+    }
+}
+
+#[async_trait]
+impl MqttService for MqttServiceGroup {
+    /// Connects all clients. The returned task completes once all
+    /// connections are closed; closing one of them closes the others.
+    async fn connect(
+        &mut self,
+        channel: broadcast::Sender<MqttReceiveEvent>,
+        receiver_exit: Receiver<()>,
+    ) -> Result<JoinHandle<()>, MqttServiceError> {
+        info!("Opening {} client connections", self.clients.len());
+        connect_all(self.clients.iter_mut(), channel, receiver_exit).await
+    }
+
+    async fn disconnect(&self) -> Result<(), MqttServiceError> {
+        for client in self.clients.iter() {
+            client.disconnect().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn publish(&self, payload: MessagePublishData) {
+        for client in self.clients.iter() {
+            client.publish(payload.clone()).await;
+        }
+    }
+
+    async fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), MqttServiceError> {
+        for client in self.clients.iter_mut() {
+            client.subscribe(topic.clone(), qos).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, topic: String) -> Result<(), MqttServiceError> {
+        for client in self.clients.iter_mut() {
+            client.unsubscribe(topic.clone()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn pending_publishes(&self) -> usize {
+        self.clients
+            .iter()
+            .map(|client| client.pending_publishes())
+            .sum()
+    }
+
+    fn published(&self) -> MessageStatistics {
+        let mut clients = self.clients.iter();
+        let mut published = clients
+            .next()
+            .map(|client| client.published())
+            .unwrap_or_default();
+        for client in clients {
+            published.merge(client.published());
+        }
+
+        published
+    }
+
+    fn granted_qos_failed(&self) -> bool {
+        self.clients
+            .iter()
+            .any(|client| client.granted_qos_failed())
+    }
+
+    fn connection_failed(&self) -> bool {
+        self.clients.iter().any(|client| client.connection_failed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockMqttService;
+
+    #[tokio::test]
+    async fn every_client_runs_the_scenario() {
+        let (first, first_broker) = MockMqttService::new();
+        let (second, second_broker) = MockMqttService::new();
+        let mut group = MqttServiceGroup::new(vec![Box::new(first), Box::new(second)]);
+
+        group
+            .subscribe("commands/#".to_string(), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        group
+            .publish(MessagePublishData::new(
+                "sensors/1".to_string(),
+                QoS::AtMostOnce,
+                false,
+                vec![1, 2],
+            ))
+            .await;
+
+        for broker in [&first_broker, &second_broker] {
+            assert_eq!(
+                vec![("commands/#".to_string(), QoS::AtLeastOnce)],
+                broker.subscriptions()
+            );
+            assert_eq!(1, broker.published().len());
+        }
+
+        let published = group.published();
+        assert_eq!(2, published.topics["sensors/1"].messages);
+        assert_eq!(4, published.topics["sensors/1"].bytes);
+    }
+}
@@ -3,11 +3,20 @@ use std::fs::File;
 use std::io;
 use std::io::BufReader;
 use std::path::PathBuf;
+use std::str::from_utf8;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::config::mqtli_config::{MqttBrokerConnect, MqttProtocol, TlsVersion};
+use crate::config::PayloadType;
+#[cfg(feature = "pkcs11")]
+use crate::mqtt::pkcs11::Pkcs11ClientCert;
+use crate::output::statistics::MessageStatistics;
 use crate::payload::PayloadFormat;
+use crate::sparkplug::host_application::PrimaryHostState;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use rumqttc::tokio_rustls::rustls::version::{TLS12, TLS13};
 use rumqttc::tokio_rustls::rustls::{Certificate, PrivateKey, SupportedProtocolVersion};
 use rumqttc::{TlsConfiguration, Transport};
@@ -20,9 +29,22 @@ use tracing::{debug, info};
 
 pub mod v5;
 
+pub mod broker_info;
+pub mod chaos;
+pub mod granted_qos;
+pub mod group;
 pub mod mqtt_handler;
+pub mod outgoing_queue;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+pub mod reconnect;
+pub mod router;
+pub mod status;
 pub mod v311;
 
+/// Prefix of client keys which are stored on a PKCS#11 token instead of a file
+pub const PKCS11_URI_PREFIX: &str = "pkcs11:";
+
 #[derive(Error, Debug)]
 pub enum MqttServiceError {
     #[error("CA certificate must be present when using TLS")]
@@ -37,14 +59,38 @@ pub enum MqttServiceError {
     PrivateKeyNoneFound(PathBuf),
     #[error("More than one PKCS8-encoded private key found in file \"{0}\"")]
     PrivateKeyTooManyFound(PathBuf),
+    #[error("Could not decrypt client key from file \"{1}\" with the given passphrase")]
+    PrivateKeyNotDecryptable(#[source] pkcs8::Error, PathBuf),
     #[error("Client key must be present when using TLS authentication")]
     ClientKeyMustBePresent(),
+    #[cfg(feature = "pkcs11")]
+    #[error("Invalid PKCS#11 URI of the client key: {0}")]
+    Pkcs11UriInvalid(String),
+    #[cfg(feature = "pkcs11")]
+    #[error("PKCS#11 operation failed while {1}")]
+    Pkcs11OperationFailed(#[source] cryptoki::error::Error, String),
+    #[cfg(feature = "pkcs11")]
+    #[error("PKCS#11 token \"{0}\" not found")]
+    Pkcs11TokenNotFound(String),
+    #[cfg(feature = "pkcs11")]
+    #[error("Private key \"{0}\" not found on the PKCS#11 token")]
+    Pkcs11KeyNotFound(String),
+    #[cfg(feature = "pkcs11")]
+    #[error("Type of the private key on the PKCS#11 token is not supported; use RSA, or EC with P-256 or P-384")]
+    Pkcs11KeyTypeUnsupported,
+    #[cfg(not(feature = "pkcs11"))]
+    #[error(
+        "Client key \"{0}\" on a PKCS#11 token requires mqtlib to be built with feature pkcs11"
+    )]
+    Pkcs11NotSupported(String),
     #[error("Client error occurred")]
     ClientErrorV5(#[from] rumqttc::v5::ClientError),
     #[error("Client error occurred")]
     ClientErrorV311(#[from] rumqttc::ClientError),
     #[error("Not connected")]
     NotConnected,
+    #[error("Error in the outgoing queue \"{1}\"")]
+    OutgoingQueue(#[source] sqlx::Error, PathBuf),
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -56,6 +102,140 @@ pub enum QoS {
     ExactlyOnce = 2,
 }
 
+/// Payload format indicator of MQTT v5 messages, telling the receiver
+/// whether the payload is UTF-8 encoded text.
+#[derive(Clone, Copy, Debug, PartialEq, strum_macros::Display)]
+pub enum PayloadFormatIndicator {
+    #[strum(serialize = "bytes")]
+    Unspecified = 0,
+    #[strum(serialize = "utf-8")]
+    Utf8 = 1,
+}
+
+impl From<u8> for PayloadFormatIndicator {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => PayloadFormatIndicator::Utf8,
+            _ => PayloadFormatIndicator::Unspecified,
+        }
+    }
+}
+
+/// Properties of a received MQTT v5 message, except the payload format
+/// indicator which is kept separately.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageProperties {
+    pub content_type: Option<String>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Vec<u8>>,
+    pub user_properties: Vec<(String, String)>,
+    /// Remaining lifetime of the message in seconds
+    pub message_expiry_interval: Option<u32>,
+    pub subscription_identifiers: Vec<usize>,
+    pub topic_alias: Option<u16>,
+}
+
+impl MessageProperties {
+    /// Returns the correlation data as text if it is valid UTF-8, otherwise
+    /// hex encoded.
+    pub fn correlation_data_string(&self) -> Option<String> {
+        self.correlation_data
+            .as_ref()
+            .map(|data| String::from_utf8(data.clone()).unwrap_or_else(|_| hex::encode(data)))
+    }
+
+    /// Returns the properties as JSON object, leaving out those which were
+    /// not sent.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut result = serde_json::Map::new();
+
+        if let Some(content_type) = &self.content_type {
+            result.insert("content_type".into(), content_type.clone().into());
+        }
+        if let Some(response_topic) = &self.response_topic {
+            result.insert("response_topic".into(), response_topic.clone().into());
+        }
+        if let Some(correlation_data) = self.correlation_data_string() {
+            result.insert("correlation_data".into(), correlation_data.into());
+        }
+        if !self.user_properties.is_empty() {
+            let user_properties = self
+                .user_properties
+                .iter()
+                .map(|(key, value)| serde_json::json!([key, value]))
+                .collect::<Vec<serde_json::Value>>();
+            result.insert("user_properties".into(), user_properties.into());
+        }
+        if let Some(interval) = self.message_expiry_interval {
+            result.insert("message_expiry_interval".into(), interval.into());
+        }
+        if !self.subscription_identifiers.is_empty() {
+            result.insert(
+                "subscription_identifiers".into(),
+                self.subscription_identifiers.clone().into(),
+            );
+        }
+        if let Some(topic_alias) = self.topic_alias {
+            result.insert("topic_alias".into(), topic_alias.into());
+        }
+
+        result.into()
+    }
+}
+
+impl From<&rumqttc::v5::mqttbytes::v5::PublishProperties> for MessageProperties {
+    fn from(value: &rumqttc::v5::mqttbytes::v5::PublishProperties) -> Self {
+        Self {
+            content_type: value.content_type.clone(),
+            response_topic: value.response_topic.clone(),
+            correlation_data: value.correlation_data.as_ref().map(|data| data.to_vec()),
+            user_properties: value.user_properties.clone(),
+            message_expiry_interval: value.message_expiry_interval,
+            subscription_identifiers: value.subscription_identifiers.clone(),
+            topic_alias: value.topic_alias,
+        }
+    }
+}
+
+/// One line per property which was sent.
+impl Display for MessageProperties {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut lines = Vec::new();
+
+        if let Some(content_type) = &self.content_type {
+            lines.push(format!("Content type: {content_type}"));
+        }
+        if let Some(response_topic) = &self.response_topic {
+            lines.push(format!("Response topic: {response_topic}"));
+        }
+        if let Some(correlation_data) = self.correlation_data_string() {
+            lines.push(format!("Correlation data: {correlation_data}"));
+        }
+        for (key, value) in &self.user_properties {
+            lines.push(format!("User property: {key} = {value}"));
+        }
+        if let Some(interval) = self.message_expiry_interval {
+            lines.push(format!("Message expiry interval: {interval} s"));
+        }
+        if !self.subscription_identifiers.is_empty() {
+            let identifiers = self
+                .subscription_identifiers
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>();
+            lines.push(format!(
+                "Subscription identifiers: {}",
+                identifiers.join(", ")
+            ));
+        }
+        if let Some(topic_alias) = self.topic_alias {
+            lines.push(format!("Topic alias: {topic_alias}"));
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
 impl Display for QoS {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let display = match self {
@@ -144,6 +324,41 @@ pub trait MqttService: Send {
     async fn publish(&self, payload: MessagePublishData);
 
     async fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), MqttServiceError>;
+
+    async fn unsubscribe(&mut self, topic: String) -> Result<(), MqttServiceError>;
+
+    /// Number of QoS 1 and 2 publishes not yet acknowledged by the broker.
+    fn pending_publishes(&self) -> usize;
+
+    /// Messages and bytes published on each topic since connecting.
+    fn published(&self) -> MessageStatistics;
+
+    /// Returns true if the connection was closed because the broker didn't
+    /// grant a subscription as requested and strict QoS checking is enabled.
+    fn granted_qos_failed(&self) -> bool;
+
+    /// Returns true if the broker didn't acknowledge the first connect within
+    /// the connect timeout or the reconnect attempts were used up.
+    fn connection_failed(&self) -> bool;
+}
+
+/// Counts a publish which waits for an acknowledgement of the broker.
+/// Returns false for QoS 0 publishes, which are not acknowledged.
+fn count_pending_publish(pending: &AtomicUsize, qos: QoS) -> bool {
+    if matches!(qos, QoS::AtMostOnce) {
+        return false;
+    }
+
+    pending.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// Stops counting a publish, either because it was acknowledged or because
+/// it could not be sent.
+fn count_completed_publish(pending: &AtomicUsize) {
+    let _ = pending.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+        count.checked_sub(1)
+    });
 }
 
 #[derive(Clone, Debug)]
@@ -152,11 +367,216 @@ pub enum MqttReceiveEvent {
     V311(rumqttc::Event),
 }
 
+impl MqttReceiveEvent {
+    /// Returns the topic, QoS, retain flag and payload if the event is an
+    /// incoming message.
+    pub fn incoming_publish(&self) -> Option<(String, QoS, bool, Vec<u8>)> {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::Publish(
+                value,
+            ))) => Some((
+                from_utf8(value.topic.as_ref()).ok()?.to_string(),
+                QoS::from(value.qos),
+                value.retain,
+                value.payload.to_vec(),
+            )),
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(value))) => {
+                Some((
+                    value.topic.clone(),
+                    QoS::from(value.qos),
+                    value.retain,
+                    value.payload.to_vec(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the topic if the event is an incoming message.
+    pub fn incoming_topic(&self) -> Option<String> {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::Publish(
+                value,
+            ))) => Some(from_utf8(value.topic.as_ref()).ok()?.to_string()),
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(value))) => {
+                Some(value.topic.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the connection to the broker was acknowledged.
+    pub fn is_connect(&self) -> bool {
+        matches!(
+            self,
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(
+                rumqttc::v5::Incoming::ConnAck(_)
+            )) | MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::ConnAck(_)))
+        )
+    }
+
+    /// Returns true if the broker acknowledged a subscription.
+    pub fn is_subscribe_ack(&self) -> bool {
+        matches!(
+            self,
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::SubAck(
+                _
+            ))) | MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::SubAck(_)))
+        )
+    }
+
+    /// Returns true if the broker acknowledged a subscription but rejected
+    /// at least one of its topics.
+    pub fn is_subscribe_rejected(&self) -> bool {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::SubAck(
+                ack,
+            ))) => ack.return_codes.iter().any(|code| {
+                !matches!(
+                    code,
+                    rumqttc::v5::mqttbytes::v5::SubscribeReasonCode::Success(_)
+                )
+            }),
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::SubAck(ack))) => ack
+                .return_codes
+                .iter()
+                .any(|code| matches!(code, rumqttc::SubscribeReasonCode::Failure)),
+            _ => false,
+        }
+    }
+
+    /// Returns the packet id if the event is a subscription sent to the
+    /// broker.
+    pub fn outgoing_subscribe_pkid(&self) -> Option<u16> {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Outgoing(rumqttc::Outgoing::Subscribe(
+                pkid,
+            )))
+            | MqttReceiveEvent::V311(rumqttc::Event::Outgoing(rumqttc::Outgoing::Subscribe(
+                pkid,
+            ))) => Some(*pkid),
+            _ => None,
+        }
+    }
+
+    /// Returns the packet id and the granted QoS of each topic filter if the
+    /// broker acknowledged a subscription; None for rejected topic filters.
+    pub fn subscribe_ack(&self) -> Option<(u16, Vec<Option<QoS>>)> {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::SubAck(
+                ack,
+            ))) => Some((
+                ack.pkid,
+                ack.return_codes
+                    .iter()
+                    .map(|code| match code {
+                        rumqttc::v5::mqttbytes::v5::SubscribeReasonCode::Success(qos) => {
+                            Some(QoS::from(qos))
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            )),
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::SubAck(ack))) => {
+                Some((
+                    ack.pkid,
+                    ack.return_codes
+                        .iter()
+                        .map(|code| match code {
+                            rumqttc::SubscribeReasonCode::Success(qos) => Some(QoS::from(qos)),
+                            rumqttc::SubscribeReasonCode::Failure => None,
+                        })
+                        .collect(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the broker acknowledged a QoS 1 or 2 publish completely.
+    pub fn is_publish_completed(&self) -> bool {
+        matches!(
+            self,
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(
+                rumqttc::v5::Incoming::PubAck(_) | rumqttc::v5::Incoming::PubComp(_)
+            )) | MqttReceiveEvent::V311(rumqttc::Event::Incoming(
+                rumqttc::Incoming::PubAck(_) | rumqttc::Incoming::PubComp(_)
+            ))
+        )
+    }
+
+    /// Returns the packet id if the event is a publish sent to the broker.
+    pub fn outgoing_publish_pkid(&self) -> Option<u16> {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Outgoing(rumqttc::Outgoing::Publish(
+                pkid,
+            )))
+            | MqttReceiveEvent::V311(rumqttc::Event::Outgoing(rumqttc::Outgoing::Publish(pkid))) => {
+                Some(*pkid)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the packet id if the broker acknowledged a QoS 1 or 2 publish
+    /// completely.
+    pub fn completed_publish_pkid(&self) -> Option<u16> {
+        match self {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::PubAck(
+                ack,
+            ))) => Some(ack.pkid),
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::PubComp(
+                comp,
+            ))) => Some(comp.pkid),
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::PubAck(ack))) => {
+                Some(ack.pkid)
+            }
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::PubComp(comp))) => {
+                Some(comp.pkid)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the connection was closed by the broker or the client.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(
+                rumqttc::v5::Incoming::Disconnect(_)
+            )) | MqttReceiveEvent::V5(rumqttc::v5::Event::Outgoing(rumqttc::Outgoing::Disconnect))
+                | MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::Disconnect))
+                | MqttReceiveEvent::V311(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect))
+        )
+    }
+
+    /// Returns true if the client closed the connection itself.
+    pub fn is_client_disconnect(&self) -> bool {
+        matches!(
+            self,
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Outgoing(rumqttc::Outgoing::Disconnect))
+                | MqttReceiveEvent::V311(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect))
+        )
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum MessageEvent {
     ReceivedFiltered(MessageReceivedData),
     ReceivedUnfiltered(MessageReceivedData),
     Publish(MessagePublishData),
+    ConversionError(ConversionErrorData),
+    /// The tracked Sparkplug primary host application went online or offline
+    PrimaryHostState(PrimaryHostState),
+}
+
+/// Received message whose payload couldn't be converted to the payload type
+/// of its topic, filtered or converted to the format of an output.
+#[derive(Clone, Debug)]
+pub struct ConversionErrorData {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub error: String,
 }
 
 #[derive(Clone, Debug)]
@@ -165,6 +585,14 @@ pub struct MessageReceivedData {
     pub qos: QoS,
     pub retain: bool,
     pub payload: PayloadFormat,
+    /// Payload format indicator sent with the message (MQTT v5 only)
+    pub payload_format_indicator: Option<PayloadFormatIndicator>,
+    /// Other properties sent with the message (MQTT v5 only)
+    pub properties: Option<MessageProperties>,
+    /// Moment the message was received from the broker
+    pub received_at: DateTime<Utc>,
+    /// Time spent converting and filtering the message
+    pub processing_time: Duration,
 }
 
 impl MessageReceivedData {
@@ -174,8 +602,17 @@ impl MessageReceivedData {
             qos,
             retain,
             payload,
+            payload_format_indicator: None,
+            properties: None,
+            received_at: Utc::now(),
+            processing_time: Duration::ZERO,
         }
     }
+
+    /// Processing time in milliseconds, with microsecond precision.
+    pub fn processing_ms(&self) -> f64 {
+        self.processing_time.as_micros() as f64 / 1000.0
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -184,6 +621,8 @@ pub struct MessagePublishData {
     pub qos: QoS,
     pub retain: bool,
     pub payload: Vec<u8>,
+    /// Payload format indicator sent with the message (MQTT v5 only)
+    pub payload_format_indicator: Option<PayloadFormatIndicator>,
 }
 
 impl MessagePublishData {
@@ -193,14 +632,53 @@ impl MessagePublishData {
             qos,
             retain,
             payload,
+            payload_format_indicator: None,
         }
     }
+
+    /// Sets the payload format indicator matching the payload type the
+    /// payload was converted to.
+    pub fn with_payload_type(mut self, payload_type: &PayloadType) -> Self {
+        self.payload_format_indicator = Some(payload_type.payload_format_indicator());
+        self
+    }
 }
 
 fn configure_tls_rustls(
     config: Arc<MqttBrokerConnect>,
 ) -> Result<TlsConfiguration, MqttServiceError> {
-    fn load_private_key_from_file(path: &PathBuf) -> Result<PrivateKey, MqttServiceError> {
+    fn load_encrypted_private_key_from_file(
+        path: &PathBuf,
+        passphrase: &str,
+    ) -> Result<PrivateKey, MqttServiceError> {
+        let pem = match std::fs::read(path) {
+            Ok(pem) => pem,
+            Err(e) => {
+                return Err(MqttServiceError::PrivateKeyNotReadable(
+                    e,
+                    PathBuf::from(path),
+                ));
+            }
+        };
+
+        let key = pkcs8::der::pem::decode_vec(&pem)
+            .map_err(pkcs8::Error::from)
+            .and_then(|(_, der)| {
+                pkcs8::EncryptedPrivateKeyInfo::try_from(der.as_slice())?.decrypt(passphrase)
+            })
+            .map_err(|e| MqttServiceError::PrivateKeyNotDecryptable(e, PathBuf::from(path)))?;
+
+        Ok(PrivateKey(key.as_bytes().to_vec()))
+    }
+
+    fn load_private_key_from_file(
+        path: &PathBuf,
+        passphrase: Option<&String>,
+    ) -> Result<PrivateKey, MqttServiceError> {
+        if let Some(passphrase) = passphrase {
+            return load_encrypted_private_key_from_file(path, passphrase);
+        }
+
         let file = match File::open(path) {
             Ok(file) => file,
             Err(e) => {
@@ -308,7 +786,33 @@ fn configure_tls_rustls(
                 return Err(MqttServiceError::ClientKeyMustBePresent());
             };
 
-            let client_key = load_private_key_from_file(client_key_file)?;
+            if let Some(uri) = client_key_file
+                .to_str()
+                .filter(|key| key.starts_with(PKCS11_URI_PREFIX))
+            {
+                info!("Using TLS client key from PKCS#11 token");
+
+                #[cfg(not(feature = "pkcs11"))]
+                return Err(MqttServiceError::Pkcs11NotSupported(uri.to_string()));
+
+                #[cfg(feature = "pkcs11")]
+                {
+                    let resolver = Pkcs11ClientCert::new(
+                        uri,
+                        config.tls_client_key_passphrase().as_ref(),
+                        client_certificate,
+                    )?;
+
+                    return Ok(TlsConfiguration::Rustls(Arc::new(
+                        tls_config.with_client_cert_resolver(Arc::new(resolver)),
+                    )));
+                }
+            }
+
+            let client_key = load_private_key_from_file(
+                client_key_file,
+                config.tls_client_key_passphrase().as_ref(),
+            )?;
 
             tls_config
                 .with_client_auth_cert(client_certificate, client_key)
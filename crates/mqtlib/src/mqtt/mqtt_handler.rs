@@ -1,25 +1,48 @@
 use std::sync::Arc;
+use std::time::Instant;
 
+use chrono::{DateTime, Utc};
 use rumqttc::v5::mqttbytes::v5::PublishProperties;
 use tokio::sync::broadcast::{Receiver, Sender};
 use tokio::task;
 use tokio::task::JoinHandle;
-use tracing::error;
+use tracing::{debug, error};
 
-use crate::config::topic::TopicStorage;
-use crate::mqtt::{MessageEvent, MessageReceivedData, MqttReceiveEvent, QoS};
-use crate::payload::PayloadFormat;
+use crate::channel::{self, wait_for_capacity};
+use crate::config::channels::Channels;
+use crate::config::subscription::{PayloadLimit, PayloadLimitAction};
+use crate::config::topic::{DecodeErrorPolicy, TopicStorage};
+use crate::config::PayloadType;
+use crate::mqtt::{
+    ConversionErrorData, MessageEvent, MessageProperties, MessageReceivedData, MqttReceiveEvent,
+    PayloadFormatIndicator, QoS,
+};
+use crate::payload::{auto, PayloadFormat};
+use crate::tasks::worker_pool::{WorkerPool, WorkerReceiver};
 
 pub struct MqttHandler {
     task_handle: Option<JoinHandle<()>>,
     topic_storage: Arc<TopicStorage>,
+    /// Limit applied to subscriptions without their own limit
+    payload_limit: Option<PayloadLimit>,
+    /// Number of tasks converting and filtering the incoming messages
+    workers: usize,
+    channels: Channels,
 }
 
 impl MqttHandler {
-    pub fn new(topic_storage: Arc<TopicStorage>) -> MqttHandler {
+    pub fn new(
+        topic_storage: Arc<TopicStorage>,
+        payload_limit: Option<PayloadLimit>,
+        workers: usize,
+        channels: Channels,
+    ) -> MqttHandler {
         MqttHandler {
             task_handle: None,
             topic_storage,
+            payload_limit,
+            workers,
+            channels,
         }
     }
 
@@ -29,10 +52,43 @@ impl MqttHandler {
         sender_message: Sender<MessageEvent>,
     ) {
         let topic_storage = self.topic_storage.clone();
+        let payload_limit = self.payload_limit.clone();
+        let channels = self.channels.clone();
 
+        // messages of the same topic, or of the same ordered subscription,
+        // are handled by the same worker to keep their order
+        let pool = WorkerPool::start(
+            self.workers,
+            move |mut events: WorkerReceiver<(DateTime<Utc>, MqttReceiveEvent)>| {
+                let topic_storage = topic_storage.clone();
+                let payload_limit = payload_limit.clone();
+                let sender_message = sender_message.clone();
+                let channels = channels.clone();
+
+                async move {
+                    while let Some((received_at, event)) = events.recv().await {
+                        wait_for_capacity(&sender_message, &channels).await;
+                        MqttHandler::handle_event(
+                            event,
+                            received_at,
+                            &topic_storage,
+                            payload_limit.as_ref(),
+                            &sender_message,
+                        );
+                    }
+                }
+            },
+        );
+
+        let topic_storage = self.topic_storage.clone();
         self.task_handle = Some(task::spawn(async move {
-            while let Ok(event) = receiver.recv().await {
-                MqttHandler::handle_event(event, &topic_storage, &sender_message);
+            while let Some(event) = channel::recv(&mut receiver, "Message handler").await {
+                if let Some(topic) = event.incoming_topic() {
+                    // the moment of receiving is taken before the message
+                    // waits for a worker
+                    pool.dispatch(topic_storage.ordering_key(&topic), (Utc::now(), event))
+                        .await;
+                }
             }
         }));
     }
@@ -48,26 +104,43 @@ impl MqttHandler {
 
     pub fn handle_event(
         event: MqttReceiveEvent,
+        received_at: DateTime<Utc>,
         topic_storage: &Arc<TopicStorage>,
+        payload_limit: Option<&PayloadLimit>,
         sender_message: &Sender<MessageEvent>,
     ) {
         match event {
             MqttReceiveEvent::V5(event) => {
-                v5::handle_event(event, topic_storage, sender_message);
+                v5::handle_event(
+                    event,
+                    received_at,
+                    topic_storage,
+                    payload_limit,
+                    sender_message,
+                );
             }
             MqttReceiveEvent::V311(event) => {
-                v311::handle_event(event, topic_storage, sender_message);
+                v311::handle_event(
+                    event,
+                    received_at,
+                    topic_storage,
+                    payload_limit,
+                    sender_message,
+                );
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn handle_incoming_message(
         topic_storage: &Arc<TopicStorage>,
+        payload_limit: Option<&PayloadLimit>,
         incoming_value: Vec<u8>,
         incoming_topic_str: &str,
         qos: QoS,
         retain: bool,
-        _option: Option<PublishProperties>,
+        properties: Option<PublishProperties>,
+        received_at: DateTime<Utc>,
         sender_message: &Sender<MessageEvent>,
     ) {
         topic_storage
@@ -78,12 +151,88 @@ impl MqttHandler {
                 topic
                     .subscription()
                     .as_ref()
-                    .map(|subscription| (subscription, topic.payload_type()))
+                    .map(|subscription| {
+                        (
+                            subscription,
+                            topic.payload_type_of(incoming_topic_str),
+                            topic.on_decode_error(),
+                        )
+                    })
+            })
+            .filter(|(subscription, _, _)| *subscription.enabled())
+            .filter(|(subscription, _, _)| subscription.matches_topic(incoming_topic_str))
+            .filter(|(subscription, _, _)| {
+                let ignored = subscription.ignores(retain);
+                if ignored {
+                    debug!("Ignoring message with retain flag {retain} on topic {incoming_topic_str}");
+                }
+                !ignored
             })
-            .filter(|(subscription, _)| *subscription.enabled())
-            .for_each(|(subscription, payload_type)| {
-                let result =
-                    PayloadFormat::try_from((payload_type.clone(), incoming_value.clone()));
+            .filter(|(subscription, _, _)| {
+                let accepted = subscription.accepts_qos(qos);
+                if !accepted {
+                    debug!("Ignoring message with QoS {qos} on topic {incoming_topic_str}");
+                }
+                accepted
+            })
+            .for_each(|(subscription, payload_type, on_decode_error)| {
+                let started = Instant::now();
+                let value = match subscription.payload_limit().as_ref().or(payload_limit) {
+                    Some(limit) if limit.exceeds(&incoming_value) => match limit.action() {
+                        PayloadLimitAction::Truncate => limit.truncate(&incoming_value),
+                        PayloadLimitAction::Skip => {
+                            debug!(
+                                "Skipping message on topic {incoming_topic_str} with {} bytes exceeding the limit of {} bytes",
+                                incoming_value.len(),
+                                limit.max_size()
+                            );
+                            return;
+                        }
+                        PayloadLimitAction::Error => {
+                            error!(
+                                "Message on topic {incoming_topic_str} with {} bytes exceeds the limit of {} bytes",
+                                incoming_value.len(),
+                                limit.max_size()
+                            );
+                            return;
+                        }
+                    },
+                    _ => incoming_value.clone(),
+                };
+                let fallback_value = matches!(
+                    on_decode_error,
+                    DecodeErrorPolicy::Raw | DecodeErrorPolicy::Hex
+                )
+                .then(|| value.clone());
+
+                let result = match payload_type {
+                    PayloadType::Auto => auto::detect_with_content_type(
+                        properties
+                            .as_ref()
+                            .and_then(|properties| properties.content_type.as_deref()),
+                        value,
+                    ),
+                    payload_type => PayloadFormat::try_from((payload_type.clone(), value)),
+                };
+                let result = match (result, on_decode_error) {
+                    (Err(e), DecodeErrorPolicy::Skip) => {
+                        debug!("Skipping message on topic {incoming_topic_str} which could not be decoded: {e}");
+                        return;
+                    }
+                    (Err(e), DecodeErrorPolicy::Raw | DecodeErrorPolicy::Hex) => {
+                        debug!("Passing on message on topic {incoming_topic_str} as {on_decode_error} as it could not be decoded: {e}");
+                        on_decode_error
+                            .fallback(fallback_value.unwrap_or_default())
+                            .ok_or(e)
+                    }
+                    (result, _) => result,
+                };
+
+                let payload_format_indicator = properties
+                    .as_ref()
+                    .and_then(|properties| properties.payload_format_indicator)
+                    .map(PayloadFormatIndicator::from);
+                let message_properties = properties.as_ref().map(MessageProperties::from);
 
                 match result {
                     Ok(content) => {
@@ -93,14 +242,19 @@ impl MqttHandler {
                                 qos,
                                 retain,
                                 payload: content.clone(),
+                                payload_format_indicator,
+                                properties: message_properties.clone(),
+                                received_at,
+                                processing_time: started.elapsed(),
                             }))
                             .is_err()
                         {
                             //ignore, no receiver is listening
                         }
 
-                        match subscription.apply_filters(content.clone()) {
+                        match subscription.apply_filters(incoming_topic_str, content.clone()) {
                             Ok(content) => {
+                                let processing_time = started.elapsed();
                                 content.iter().for_each(|content| {
                                     if sender_message
                                         .send(MessageEvent::ReceivedFiltered(MessageReceivedData {
@@ -108,6 +262,10 @@ impl MqttHandler {
                                             qos,
                                             retain,
                                             payload: content.clone(),
+                                            payload_format_indicator,
+                                            properties: message_properties.clone(),
+                                            received_at,
+                                            processing_time,
                                         }))
                                         .is_err()
                                     {
@@ -117,11 +275,33 @@ impl MqttHandler {
                             }
                             Err(e) => {
                                 error!("{:?}", e);
+
+                                if sender_message
+                                    .send(MessageEvent::ConversionError(ConversionErrorData {
+                                        topic: incoming_topic_str.into(),
+                                        payload: incoming_value.clone(),
+                                        error: e.to_string(),
+                                    }))
+                                    .is_err()
+                                {
+                                    //ignore, no receiver is listening
+                                }
                             }
                         }
                     }
                     Err(e) => {
                         error!("{}", e);
+
+                        if sender_message
+                            .send(MessageEvent::ConversionError(ConversionErrorData {
+                                topic: incoming_topic_str.into(),
+                                payload: incoming_value.clone(),
+                                error: e.to_string(),
+                            }))
+                            .is_err()
+                        {
+                            //ignore, no receiver is listening
+                        }
                     }
                 };
             })
@@ -129,9 +309,11 @@ impl MqttHandler {
 }
 
 mod v5 {
+    use crate::config::subscription::PayloadLimit;
     use crate::config::topic::TopicStorage;
     use crate::mqtt::mqtt_handler::MqttHandler;
     use crate::mqtt::{MessageEvent, QoS};
+    use chrono::{DateTime, Utc};
     use std::str::from_utf8;
     use std::sync::Arc;
     use tokio::sync::broadcast::Sender;
@@ -139,7 +321,9 @@ mod v5 {
 
     pub fn handle_event(
         event: rumqttc::v5::Event,
+        received_at: DateTime<Utc>,
         topic_storage: &Arc<TopicStorage>,
+        payload_limit: Option<&PayloadLimit>,
         sender_message: &Sender<MessageEvent>,
     ) {
         match event {
@@ -156,11 +340,13 @@ mod v5 {
 
                     MqttHandler::handle_incoming_message(
                         topic_storage,
+                        payload_limit,
                         value.payload.to_vec(),
                         incoming_topic,
                         qos,
                         value.retain,
                         value.properties,
+                        received_at,
                         sender_message,
                     );
                 }
@@ -171,9 +357,11 @@ mod v5 {
 }
 
 mod v311 {
+    use crate::config::subscription::PayloadLimit;
     use crate::config::topic::TopicStorage;
     use crate::mqtt::mqtt_handler::MqttHandler;
     use crate::mqtt::{MessageEvent, QoS};
+    use chrono::{DateTime, Utc};
     use std::str::from_utf8;
     use std::sync::Arc;
     use tokio::sync::broadcast::Sender;
@@ -181,7 +369,9 @@ mod v311 {
 
     pub fn handle_event(
         event: rumqttc::Event,
+        received_at: DateTime<Utc>,
         topic_storage: &Arc<TopicStorage>,
+        payload_limit: Option<&PayloadLimit>,
         sender_message: &Sender<MessageEvent>,
     ) {
         match event {
@@ -198,11 +388,13 @@ mod v311 {
 
                     MqttHandler::handle_incoming_message(
                         topic_storage,
+                        payload_limit,
                         value.payload.to_vec(),
                         incoming_topic,
                         qos,
                         value.retain,
                         None,
+                        received_at,
                         sender_message,
                     );
                 }
@@ -0,0 +1,226 @@
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, QoS};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{debug, error};
+
+/// Keeps QoS 1 and 2 publishes in a SQLite database until the broker
+/// acknowledged them, so they survive a restart of the process and are
+/// published again after the next connect.
+///
+/// Publishes are matched with their acknowledgements by the packet id the
+/// client assigns when sending them. Packets are sent in the order they were
+/// passed to the client, so the ids of the stored messages are kept in that
+/// order until the packet id is known. Packets sent again, e.g. after a
+/// reconnect, keep their packet id and are not matched again.
+#[derive(Debug)]
+pub struct OutgoingQueue {
+    pool: SqlitePool,
+    /// Ids of the stored messages passed to the client but not sent yet,
+    /// None for publishes which are not stored
+    unsent: Mutex<VecDeque<Option<i64>>>,
+    /// Ids of the stored messages by packet id, waiting to be acknowledged,
+    /// None for publishes which are not stored
+    sent: Mutex<HashMap<u16, Option<i64>>>,
+}
+
+impl OutgoingQueue {
+    pub async fn open(path: &Path) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS outgoing_messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                topic TEXT NOT NULL,
+                qos INTEGER NOT NULL,
+                retain INTEGER NOT NULL,
+                payload BLOB NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self {
+            pool,
+            unsent: Mutex::new(VecDeque::new()),
+            sent: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Stores a QoS 1 or 2 publish and returns its id. QoS 0 publishes are
+    /// not acknowledged and therefore not stored.
+    pub async fn store(&self, message: &MessagePublishData) -> Result<Option<i64>, sqlx::Error> {
+        if matches!(message.qos, QoS::AtMostOnce) {
+            return Ok(None);
+        }
+
+        let id = sqlx::query(
+            "INSERT INTO outgoing_messages (topic, qos, retain, payload) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&message.topic)
+        .bind(message.qos as i64)
+        .bind(message.retain)
+        .bind(&message.payload)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+
+        Ok(Some(id))
+    }
+
+    /// Returns all stored messages which were not acknowledged yet, oldest
+    /// first.
+    pub async fn stored(&self) -> Result<Vec<(i64, MessagePublishData)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, topic, qos, retain, payload FROM outgoing_messages ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let qos = match row.get::<i64, _>("qos") {
+                    2 => QoS::ExactlyOnce,
+                    _ => QoS::AtLeastOnce,
+                };
+                (
+                    row.get("id"),
+                    MessagePublishData::new(
+                        row.get("topic"),
+                        qos,
+                        row.get("retain"),
+                        row.get("payload"),
+                    ),
+                )
+            })
+            .collect())
+    }
+
+    /// Must be called right before each publish is passed to the client.
+    pub fn track(&self, id: Option<i64>) {
+        self.unsent.lock().unwrap().push_back(id);
+    }
+
+    /// Must be called if the publish tracked last could not be passed to the
+    /// client.
+    pub fn untrack(&self) {
+        self.unsent.lock().unwrap().pop_back();
+    }
+
+    /// Assigns packet ids to sent publishes and removes the stored messages
+    /// acknowledged by the broker.
+    pub async fn handle_event(&self, event: &MqttReceiveEvent) {
+        if let Some(pkid) = event.outgoing_publish_pkid() {
+            let mut sent = self.sent.lock().unwrap();
+            if sent.contains_key(&pkid) {
+                debug!("Publish with packet id {pkid} was sent again");
+                return;
+            }
+
+            let id = self.unsent.lock().unwrap().pop_front().flatten();
+            // QoS 0 publishes have no packet id and are not acknowledged
+            if pkid != 0 {
+                sent.insert(pkid, id);
+            }
+        } else if let Some(pkid) = event.completed_publish_pkid() {
+            let id = self.sent.lock().unwrap().remove(&pkid).flatten();
+            if let Some(id) = id {
+                debug!("Removing acknowledged message {id} from the outgoing queue");
+                if let Err(e) = sqlx::query("DELETE FROM outgoing_messages WHERE id = $1")
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await
+                {
+                    error!("Could not remove message {id} from the outgoing queue: {e:?}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn store_and_load() {
+        let path = std::env::temp_dir().join(format!("mqtli-queue-{}.db", uuid::Uuid::new_v4()));
+        let queue = OutgoingQueue::open(&path).await.unwrap();
+
+        let qos0 = MessagePublishData::new("a".to_string(), QoS::AtMostOnce, false, vec![0]);
+        let qos1 = MessagePublishData::new("b".to_string(), QoS::AtLeastOnce, true, vec![1, 2]);
+        let qos2 = MessagePublishData::new("c".to_string(), QoS::ExactlyOnce, false, vec![3]);
+
+        assert_eq!(None, queue.store(&qos0).await.unwrap());
+        let id1 = queue.store(&qos1).await.unwrap().unwrap();
+        let id2 = queue.store(&qos2).await.unwrap().unwrap();
+        drop(queue);
+
+        let queue = OutgoingQueue::open(&path).await.unwrap();
+        let stored = queue.stored().await.unwrap();
+
+        assert_eq!(2, stored.len());
+        assert_eq!(id1, stored[0].0);
+        assert_eq!("b", stored[0].1.topic);
+        assert_eq!(QoS::AtLeastOnce, stored[0].1.qos);
+        assert!(stored[0].1.retain);
+        assert_eq!(vec![1, 2], stored[0].1.payload);
+        assert_eq!(id2, stored[1].0);
+        assert_eq!(QoS::ExactlyOnce, stored[1].1.qos);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    fn outgoing_publish(pkid: u16) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Outgoing(rumqttc::Outgoing::Publish(pkid)))
+    }
+
+    fn puback(pkid: u16) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::PubAck(
+            rumqttc::PubAck::new(pkid),
+        )))
+    }
+
+    #[tokio::test]
+    async fn resent_after_reconnect() {
+        let path = std::env::temp_dir().join(format!("mqtli-queue-{}.db", uuid::Uuid::new_v4()));
+        let queue = OutgoingQueue::open(&path).await.unwrap();
+
+        let mut ids = vec![];
+        for topic in ["a", "b", "c"] {
+            let message =
+                MessagePublishData::new(topic.to_string(), QoS::AtLeastOnce, false, vec![0]);
+            ids.push(queue.store(&message).await.unwrap());
+        }
+
+        queue.track(ids[0]);
+        queue.track(ids[1]);
+        queue.handle_event(&outgoing_publish(1)).await;
+        queue.handle_event(&outgoing_publish(2)).await;
+
+        // the client sends the unacknowledged packets again after the
+        // reconnect, before the next publish
+        queue.track(ids[2]);
+        queue.handle_event(&outgoing_publish(1)).await;
+        queue.handle_event(&outgoing_publish(2)).await;
+        queue.handle_event(&outgoing_publish(3)).await;
+
+        queue.handle_event(&puback(1)).await;
+        queue.handle_event(&puback(2)).await;
+        let stored = queue.stored().await.unwrap();
+        assert_eq!(1, stored.len());
+        assert_eq!("c", stored[0].1.topic);
+
+        queue.handle_event(&puback(3)).await;
+        assert!(queue.stored().await.unwrap().is_empty());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,378 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsPssParams};
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use percent_encoding::percent_decode_str;
+use rumqttc::tokio_rustls::rustls;
+use rumqttc::tokio_rustls::rustls::client::ResolvesClientCert;
+use rumqttc::tokio_rustls::rustls::sign::{CertifiedKey, Signer, SigningKey};
+use rumqttc::tokio_rustls::rustls::{Certificate, SignatureAlgorithm, SignatureScheme};
+use tracing::{debug, error};
+
+use crate::mqtt::{MqttServiceError, PKCS11_URI_PREFIX};
+
+/// DER encoded object identifiers of the supported elliptic curves
+const OID_PRIME256V1: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_SECP384R1: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+
+/// Reference to a private key on a PKCS#11 token as defined in RFC 7512, e.g.
+/// `pkcs11:token=device;object=client?module-path=/usr/lib/softhsm/libsofthsm2.so&pin-value=1234`.
+/// Keys in a TPM are used through its PKCS#11 module, e.g. tpm2-pkcs11.
+#[derive(Debug, Default, PartialEq)]
+pub struct Pkcs11Uri {
+    pub token: Option<String>,
+    pub object: Option<String>,
+    pub id: Option<Vec<u8>>,
+    pub module_path: PathBuf,
+    pub pin: Option<String>,
+}
+
+impl Pkcs11Uri {
+    pub fn parse(uri: &str) -> Result<Self, MqttServiceError> {
+        let invalid = |reason: &str| MqttServiceError::Pkcs11UriInvalid(reason.to_string());
+
+        let rest = uri
+            .strip_prefix(PKCS11_URI_PREFIX)
+            .ok_or_else(|| invalid("must start with pkcs11:"))?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut result = Pkcs11Uri::default();
+        let mut module_path = None;
+
+        for (key, value) in attributes(path, ';').chain(attributes(query, '&')) {
+            let decoded = || percent_decode_str(value).collect::<Vec<u8>>();
+            let decoded_string = || String::from_utf8_lossy(&decoded()).to_string();
+
+            match key {
+                "token" => result.token = Some(decoded_string()),
+                "object" => result.object = Some(decoded_string()),
+                "id" => result.id = Some(decoded()),
+                "module-path" => module_path = Some(PathBuf::from(decoded_string())),
+                "pin-value" => result.pin = Some(decoded_string()),
+                _ => debug!("Ignoring attribute {key} of PKCS#11 URI"),
+            }
+        }
+
+        result.module_path = module_path.ok_or_else(|| invalid("module-path is missing"))?;
+        if result.object.is_none() && result.id.is_none() {
+            return Err(invalid("object or id of the key is missing"));
+        }
+
+        Ok(result)
+    }
+}
+
+fn attributes(value: &str, separator: char) -> impl Iterator<Item = (&str, &str)> {
+    value
+        .split(separator)
+        .filter(|attribute| !attribute.is_empty())
+        .map(|attribute| attribute.split_once('=').unwrap_or((attribute, "")))
+}
+
+/// Resolves the client certificate with the private key on the token.
+pub struct Pkcs11ClientCert {
+    certified_key: Arc<CertifiedKey>,
+}
+
+impl Pkcs11ClientCert {
+    /// Opens a session on the token, logs in with the PIN of the URI or the
+    /// given one and looks up the private key.
+    pub fn new(
+        uri: &str,
+        pin: Option<&String>,
+        certificates: Vec<Certificate>,
+    ) -> Result<Self, MqttServiceError> {
+        let uri = Pkcs11Uri::parse(uri)?;
+        let failed = |operation: &str| {
+            let operation = operation.to_string();
+            move |e| MqttServiceError::Pkcs11OperationFailed(e, operation)
+        };
+
+        let pkcs11 = Pkcs11::new(&uri.module_path).map_err(failed("loading the module"))?;
+        pkcs11
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(failed("initializing the module"))?;
+
+        let mut slot = None;
+        for candidate in pkcs11
+            .get_slots_with_token()
+            .map_err(failed("listing the tokens"))?
+        {
+            let info = pkcs11
+                .get_token_info(candidate)
+                .map_err(failed("reading the token info"))?;
+            if uri
+                .token
+                .as_deref()
+                .map_or(true, |token| info.label() == token)
+            {
+                slot = Some(candidate);
+                break;
+            }
+        }
+        let slot = slot.ok_or(MqttServiceError::Pkcs11TokenNotFound(
+            uri.token.clone().unwrap_or_default(),
+        ))?;
+
+        let session = pkcs11
+            .open_ro_session(slot)
+            .map_err(failed("opening a session"))?;
+        if let Some(pin) = uri.pin.as_ref().or(pin) {
+            session
+                .login(UserType::User, Some(&AuthPin::new(pin.clone())))
+                .map_err(failed("logging in"))?;
+        }
+
+        let mut template = vec![Attribute::Class(ObjectClass::PRIVATE_KEY)];
+        if let Some(object) = &uri.object {
+            template.push(Attribute::Label(object.clone().into_bytes()));
+        }
+        if let Some(id) = &uri.id {
+            template.push(Attribute::Id(id.clone()));
+        }
+        let key = session
+            .find_objects(&template)
+            .map_err(failed("searching the key"))?
+            .into_iter()
+            .next()
+            .ok_or(MqttServiceError::Pkcs11KeyNotFound(
+                uri.object.clone().unwrap_or_default(),
+            ))?;
+
+        let algorithm = key_algorithm(&session, key)?;
+        debug!("Using {algorithm:?} key from PKCS#11 token");
+
+        let signing_key = Pkcs11SigningKey {
+            session: Arc::new(Mutex::new(session)),
+            key,
+            algorithm,
+        };
+
+        Ok(Self {
+            certified_key: Arc::new(CertifiedKey::new(certificates, Arc::new(signing_key))),
+        })
+    }
+}
+
+impl ResolvesClientCert for Pkcs11ClientCert {
+    fn resolve(
+        &self,
+        _acceptable_issuers: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum KeyAlgorithm {
+    Rsa,
+    EcdsaP256,
+    EcdsaP384,
+}
+
+fn key_algorithm(session: &Session, key: ObjectHandle) -> Result<KeyAlgorithm, MqttServiceError> {
+    let attributes = session
+        .get_attributes(key, &[AttributeType::KeyType, AttributeType::EcParams])
+        .map_err(|e| MqttServiceError::Pkcs11OperationFailed(e, "reading the key type".into()))?;
+
+    let key_type = attributes.iter().find_map(|attribute| match attribute {
+        Attribute::KeyType(key_type) => Some(*key_type),
+        _ => None,
+    });
+    let ec_params = attributes.iter().find_map(|attribute| match attribute {
+        Attribute::EcParams(params) => Some(params.as_slice()),
+        _ => None,
+    });
+
+    match (key_type, ec_params) {
+        (Some(KeyType::RSA), _) => Ok(KeyAlgorithm::Rsa),
+        (Some(KeyType::EC), Some(OID_PRIME256V1)) => Ok(KeyAlgorithm::EcdsaP256),
+        (Some(KeyType::EC), Some(OID_SECP384R1)) => Ok(KeyAlgorithm::EcdsaP384),
+        _ => Err(MqttServiceError::Pkcs11KeyTypeUnsupported),
+    }
+}
+
+struct Pkcs11SigningKey {
+    session: Arc<Mutex<Session>>,
+    key: ObjectHandle,
+    algorithm: KeyAlgorithm,
+}
+
+impl SigningKey for Pkcs11SigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        let supported: &[SignatureScheme] = match self.algorithm {
+            KeyAlgorithm::Rsa => &[
+                SignatureScheme::RSA_PSS_SHA256,
+                SignatureScheme::RSA_PSS_SHA384,
+                SignatureScheme::RSA_PKCS1_SHA256,
+                SignatureScheme::RSA_PKCS1_SHA384,
+            ],
+            KeyAlgorithm::EcdsaP256 => &[SignatureScheme::ECDSA_NISTP256_SHA256],
+            KeyAlgorithm::EcdsaP384 => &[SignatureScheme::ECDSA_NISTP384_SHA384],
+        };
+
+        supported
+            .iter()
+            .find(|scheme| offered.contains(scheme))
+            .map(|scheme| {
+                Box::new(Pkcs11Signer {
+                    session: self.session.clone(),
+                    key: self.key,
+                    scheme: *scheme,
+                }) as Box<dyn Signer>
+            })
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        match self.algorithm {
+            KeyAlgorithm::Rsa => SignatureAlgorithm::RSA,
+            KeyAlgorithm::EcdsaP256 | KeyAlgorithm::EcdsaP384 => SignatureAlgorithm::ECDSA,
+        }
+    }
+}
+
+struct Pkcs11Signer {
+    session: Arc<Mutex<Session>>,
+    key: ObjectHandle,
+    scheme: SignatureScheme,
+}
+
+impl Signer for Pkcs11Signer {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        let pss = |hash_alg, mgf, s_len: u64| PkcsPssParams {
+            hash_alg,
+            mgf,
+            s_len: s_len.into(),
+        };
+
+        let mechanism = match self.scheme {
+            SignatureScheme::RSA_PSS_SHA256 => Mechanism::Sha256RsaPkcsPss(pss(
+                MechanismType::SHA256,
+                PkcsMgfType::MGF1_SHA256,
+                32,
+            )),
+            SignatureScheme::RSA_PSS_SHA384 => Mechanism::Sha384RsaPkcsPss(pss(
+                MechanismType::SHA384,
+                PkcsMgfType::MGF1_SHA384,
+                48,
+            )),
+            SignatureScheme::RSA_PKCS1_SHA256 => Mechanism::Sha256RsaPkcs,
+            SignatureScheme::RSA_PKCS1_SHA384 => Mechanism::Sha384RsaPkcs,
+            SignatureScheme::ECDSA_NISTP256_SHA256 => Mechanism::EcdsaSha256,
+            SignatureScheme::ECDSA_NISTP384_SHA384 => Mechanism::EcdsaSha384,
+            scheme => {
+                return Err(rustls::Error::General(format!(
+                    "Signature scheme {scheme:?} is not supported"
+                )))
+            }
+        };
+
+        let signature = self
+            .session
+            .lock()
+            .unwrap()
+            .sign(&mechanism, self.key, message)
+            .map_err(|e| {
+                error!("Could not sign with the key on the PKCS#11 token: {e:?}");
+                rustls::Error::General(format!("PKCS#11 signing failed: {e}"))
+            })?;
+
+        match self.scheme {
+            SignatureScheme::ECDSA_NISTP256_SHA256 | SignatureScheme::ECDSA_NISTP384_SHA384 => {
+                Ok(ecdsa_signature_to_der(&signature))
+            }
+            _ => Ok(signature),
+        }
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+}
+
+/// Converts the ECDSA signature of PKCS#11, the concatenated values r and s,
+/// to the DER encoded sequence expected by TLS.
+fn ecdsa_signature_to_der(signature: &[u8]) -> Vec<u8> {
+    fn integer(value: &[u8]) -> Vec<u8> {
+        let value = match value.iter().position(|byte| *byte != 0) {
+            Some(start) => &value[start..],
+            None => &[0],
+        };
+
+        let mut result = vec![0x02];
+        if value[0] & 0x80 != 0 {
+            result.push(value.len() as u8 + 1);
+            result.push(0);
+        } else {
+            result.push(value.len() as u8);
+        }
+        result.extend_from_slice(value);
+        result
+    }
+
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let content = [integer(r), integer(s)].concat();
+
+    let mut result = vec![0x30];
+    if content.len() >= 0x80 {
+        result.push(0x81);
+    }
+    result.push(content.len() as u8);
+    result.extend(content);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uri() {
+        let uri = Pkcs11Uri::parse(
+            "pkcs11:token=my%20device;object=client;id=%01%02?module-path=/usr/lib/libtpm2_pkcs11.so&pin-value=1234",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Pkcs11Uri {
+                token: Some("my device".to_string()),
+                object: Some("client".to_string()),
+                id: Some(vec![1, 2]),
+                module_path: PathBuf::from("/usr/lib/libtpm2_pkcs11.so"),
+                pin: Some("1234".to_string()),
+            },
+            uri
+        );
+    }
+
+    #[test]
+    fn parse_uri_invalid() {
+        assert!(Pkcs11Uri::parse("pkcs11:object=client").is_err());
+        assert!(Pkcs11Uri::parse("pkcs11:token=a?module-path=/lib.so").is_err());
+        assert!(Pkcs11Uri::parse("file:client.key").is_err());
+    }
+
+    #[test]
+    fn ecdsa_der() {
+        let mut signature = vec![0u8; 64];
+        signature[0] = 0x80;
+        signature[31] = 0x01;
+        signature[63] = 0x02;
+
+        let der = ecdsa_signature_to_der(&signature);
+
+        assert_eq!(&[0x30, 0x26, 0x02, 0x21, 0x00, 0x80], &der[..6]);
+        assert_eq!(&[0x02, 0x01, 0x02], &der[der.len() - 3..]);
+        assert_eq!(der.len(), 0x26 + 2);
+    }
+}
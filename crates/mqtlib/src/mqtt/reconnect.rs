@@ -0,0 +1,213 @@
+use crate::config::mqtli_config::MqttBrokerConnect;
+use crate::mqtt::MqttReceiveEvent;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+/// Time to wait before connecting again after the connection failed
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Decides whether the connection task connects again after an error and
+/// enforces the timeout of the first connect.
+///
+/// Without a maximum number of attempts, the task stops at the first error
+/// as before. Otherwise, the consecutive failed attempts are counted until
+/// the broker acknowledges a connect; once they are used up or the first
+/// connect times out, the connection is marked as failed, so mqtli exits
+/// with an error. A connection dropped by chaos mode is always connected
+/// again, until the broker acknowledges a connect.
+#[derive(Debug)]
+pub struct Reconnect {
+    connect_timeout: Option<Duration>,
+    max_attempts: Option<u32>,
+    started_at: Instant,
+    /// The broker acknowledged a connect at least once
+    connected: bool,
+    /// The connection is closed on purpose and must not be established again
+    closing: bool,
+    /// Chaos mode dropped the connection and it is not connected again yet
+    dropped: bool,
+    attempts: u32,
+    failed: Arc<AtomicBool>,
+}
+
+impl Reconnect {
+    pub fn new(config: &MqttBrokerConnect, failed: Arc<AtomicBool>) -> Self {
+        Self {
+            connect_timeout: config.connect_timeout,
+            max_attempts: config.max_reconnect_attempts,
+            started_at: Instant::now(),
+            connected: false,
+            closing: false,
+            dropped: false,
+            attempts: 0,
+            failed,
+        }
+    }
+
+    /// Time until which the broker must acknowledge the first connect.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.connected {
+            true => None,
+            false => self
+                .connect_timeout
+                .map(|connect_timeout| self.started_at + connect_timeout),
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts.unwrap_or_default()
+    }
+
+    pub fn handle_event(&mut self, event: &MqttReceiveEvent) {
+        if event.is_connect() {
+            self.connected = true;
+            self.dropped = false;
+            self.attempts = 0;
+        } else if event.is_client_disconnect() {
+            // A disconnect sent by the broker is an error to recover from
+            self.closing = true;
+        }
+    }
+
+    /// Notes that chaos mode dropped the connection, so it is connected
+    /// again even without a maximum number of attempts.
+    pub fn dropped(&mut self) {
+        self.dropped = true;
+    }
+
+    /// Marks the connection as failed because the first connect was not
+    /// acknowledged in time.
+    pub fn timed_out(&self) {
+        if let Some(connect_timeout) = self.connect_timeout {
+            error!(
+                "Could not connect to the broker within {} seconds",
+                connect_timeout.as_secs_f64()
+            );
+        }
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the number of the next attempt if the connection task should
+    /// connect again after an error.
+    pub fn next_attempt(&mut self) -> Option<u32> {
+        if self.closing {
+            return None;
+        }
+
+        if self.dropped && self.max_attempts.is_none() {
+            self.attempts += 1;
+            warn!(
+                "Reconnecting in {} seconds after chaos dropped the connection (attempt {})",
+                RECONNECT_DELAY.as_secs(),
+                self.attempts
+            );
+            return Some(self.attempts);
+        }
+
+        let max_attempts = self.max_attempts?;
+        if self.attempts >= max_attempts {
+            error!("Giving up after {max_attempts} reconnect attempts");
+            self.failed.store(true, Ordering::Relaxed);
+            return None;
+        }
+
+        self.attempts += 1;
+        warn!(
+            "Reconnecting in {} seconds (attempt {} of {max_attempts})",
+            RECONNECT_DELAY.as_secs(),
+            self.attempts
+        );
+        Some(self.attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connack() -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::ConnAck(
+            rumqttc::ConnAck::new(rumqttc::ConnectReturnCode::Success, false),
+        )))
+    }
+
+    #[test]
+    fn attempts() {
+        let failed = Arc::new(AtomicBool::new(false));
+        let config = MqttBrokerConnect {
+            max_reconnect_attempts: Some(2),
+            ..MqttBrokerConnect::default()
+        };
+        let mut reconnect = Reconnect::new(&config, failed.clone());
+
+        assert_eq!(Some(1), reconnect.next_attempt());
+        reconnect.handle_event(&connack());
+        assert_eq!(Some(1), reconnect.next_attempt());
+        assert_eq!(Some(2), reconnect.next_attempt());
+        assert!(!failed.load(Ordering::Relaxed));
+        assert_eq!(None, reconnect.next_attempt());
+        assert!(failed.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn broker_disconnect() {
+        let config = MqttBrokerConnect {
+            max_reconnect_attempts: Some(1),
+            ..MqttBrokerConnect::default()
+        };
+        let mut reconnect = Reconnect::new(&config, Arc::new(AtomicBool::new(false)));
+
+        reconnect.handle_event(&connack());
+        reconnect.handle_event(&MqttReceiveEvent::V311(rumqttc::Event::Incoming(
+            rumqttc::Incoming::Disconnect,
+        )));
+        assert_eq!(Some(1), reconnect.next_attempt());
+
+        reconnect.handle_event(&connack());
+        reconnect.handle_event(&MqttReceiveEvent::V311(rumqttc::Event::Outgoing(
+            rumqttc::Outgoing::Disconnect,
+        )));
+        assert_eq!(None, reconnect.next_attempt());
+    }
+
+    #[test]
+    fn without_attempts() {
+        let failed = Arc::new(AtomicBool::new(false));
+        let mut reconnect = Reconnect::new(&MqttBrokerConnect::default(), failed.clone());
+
+        assert_eq!(None, reconnect.next_attempt());
+        assert!(!failed.load(Ordering::Relaxed));
+        assert_eq!(None, reconnect.deadline());
+    }
+
+    #[test]
+    fn chaos_dropped() {
+        let failed = Arc::new(AtomicBool::new(false));
+        let mut reconnect = Reconnect::new(&MqttBrokerConnect::default(), failed.clone());
+
+        reconnect.handle_event(&connack());
+        reconnect.dropped();
+        assert_eq!(Some(1), reconnect.next_attempt());
+        assert_eq!(Some(2), reconnect.next_attempt());
+
+        reconnect.handle_event(&connack());
+        assert_eq!(None, reconnect.next_attempt());
+        assert!(!failed.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn deadline_until_connected() {
+        let config = MqttBrokerConnect {
+            connect_timeout: Some(Duration::from_secs(3)),
+            ..MqttBrokerConnect::default()
+        };
+        let mut reconnect = Reconnect::new(&config, Arc::new(AtomicBool::new(false)));
+
+        assert!(reconnect.deadline().is_some());
+        reconnect.handle_event(&connack());
+        assert_eq!(None, reconnect.deadline());
+    }
+}
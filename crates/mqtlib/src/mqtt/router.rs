@@ -0,0 +1,277 @@
+use crate::config::topic::topic_matches;
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService, MqttServiceError, QoS};
+use crate::output::statistics::MessageStatistics;
+use async_trait::async_trait;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// Publishes are passed on while borrowing the router, so the services must
+/// be shareable between threads.
+pub type RoutedService = Box<dyn MqttService + Sync>;
+
+/// Lets a boxed service be used like the service itself, e.g. shared behind
+/// a mutex.
+#[async_trait]
+impl MqttService for RoutedService {
+    async fn connect(
+        &mut self,
+        channel: broadcast::Sender<MqttReceiveEvent>,
+        receiver_exit: Receiver<()>,
+    ) -> Result<JoinHandle<()>, MqttServiceError> {
+        self.as_mut().connect(channel, receiver_exit).await
+    }
+
+    async fn disconnect(&self) -> Result<(), MqttServiceError> {
+        self.as_ref().disconnect().await
+    }
+
+    async fn publish(&self, payload: MessagePublishData) {
+        self.as_ref().publish(payload).await
+    }
+
+    async fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), MqttServiceError> {
+        self.as_mut().subscribe(topic, qos).await
+    }
+
+    async fn unsubscribe(&mut self, topic: String) -> Result<(), MqttServiceError> {
+        self.as_mut().unsubscribe(topic).await
+    }
+
+    fn pending_publishes(&self) -> usize {
+        self.as_ref().pending_publishes()
+    }
+
+    fn published(&self) -> MessageStatistics {
+        self.as_ref().published()
+    }
+
+    fn granted_qos_failed(&self) -> bool {
+        self.as_ref().granted_qos_failed()
+    }
+
+    fn connection_failed(&self) -> bool {
+        self.as_ref().connection_failed()
+    }
+}
+
+/// Passes subscriptions and publishes of topics with a dedicated connection
+/// to the client of that connection and everything else to the default
+/// client, so a flooding topic can't starve the keep alives and QoS flows of
+/// the other topics. Received events of all connections are sent to the same
+/// channel.
+pub struct MqttServiceRouter {
+    default: RoutedService,
+    /// Dedicated connections by their name
+    connections: BTreeMap<String, RoutedService>,
+    /// Topic patterns with the name of their connection, checked in order
+    routes: Vec<(String, String)>,
+}
+
+impl MqttServiceRouter {
+    pub fn new(default: RoutedService) -> Self {
+        Self {
+            default,
+            connections: BTreeMap::new(),
+            routes: vec![],
+        }
+    }
+
+    /// Adds a dedicated connection.
+    pub fn with_connection(mut self, name: &str, service: RoutedService) -> Self {
+        self.connections.insert(name.to_string(), service);
+        self
+    }
+
+    /// Routes the topics matching the pattern to the dedicated connection.
+    pub fn with_route(mut self, pattern: &str, connection: &str) -> Self {
+        self.routes
+            .push((pattern.to_string(), connection.to_string()));
+        self
+    }
+
+    fn connection_of(&self, topic: &str) -> Option<&String> {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| topic_matches(pattern, topic))
+            .map(|(_, connection)| connection)
+            .filter(|connection| self.connections.contains_key(*connection))
+    }
+
+    fn route(&self, topic: &str) -> &(dyn MqttService + Sync) {
+        match self.connection_of(topic) {
+            Some(connection) => self.connections[connection].as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+
+    fn route_mut(&mut self, topic: &str) -> &mut (dyn MqttService + Sync) {
+        match self.connection_of(topic).cloned() {
+            Some(connection) => self.connections.get_mut(&connection).unwrap().as_mut(),
+            None => self.default.as_mut(),
+        }
+    }
+
+    fn services(&self) -> impl Iterator<Item = &RoutedService> {
+        std::iter::once(&self.default).chain(self.connections.values())
+    }
+}
+
+/// Connects all services, sending their received events to the channel.
+/// The returned task completes once all connections are closed; closing one
+/// of them closes the others.
+pub(crate) async fn connect_all(
+    services: impl Iterator<Item = &mut RoutedService>,
+    channel: broadcast::Sender<MqttReceiveEvent>,
+    receiver_exit: Receiver<()>,
+) -> Result<JoinHandle<()>, MqttServiceError> {
+    let (sender_close, _) = broadcast::channel::<()>(1);
+
+    let handles = FuturesUnordered::new();
+    for service in services {
+        handles.push(
+            service
+                .connect(channel.clone(), sender_close.subscribe())
+                .await?,
+        );
+    }
+
+    Ok(tokio::spawn(async move {
+        let mut handles = handles;
+        let mut receiver_exit = receiver_exit;
+
+        select! {
+            _ = receiver_exit.recv() => {},
+            _ = handles.next() => debug!("Connection closed, closing the other connections"),
+        }
+
+        let _ = sender_close.send(());
+        while handles.next().await.is_some() {}
+    }))
+}
+
+#[async_trait]
+impl MqttService for MqttServiceRouter {
+    /// Connects all clients. The returned task completes once all
+    /// connections are closed; closing one of them closes the others.
+    async fn connect(
+        &mut self,
+        channel: broadcast::Sender<MqttReceiveEvent>,
+        receiver_exit: Receiver<()>,
+    ) -> Result<JoinHandle<()>, MqttServiceError> {
+        for name in self.connections.keys() {
+            info!("Opening dedicated connection {name}");
+        }
+
+        let services = std::iter::once(&mut self.default).chain(self.connections.values_mut());
+        connect_all(services, channel, receiver_exit).await
+    }
+
+    async fn disconnect(&self) -> Result<(), MqttServiceError> {
+        for service in self.services() {
+            service.disconnect().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn publish(&self, payload: MessagePublishData) {
+        self.route(&payload.topic).publish(payload).await;
+    }
+
+    async fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), MqttServiceError> {
+        self.route_mut(&topic).subscribe(topic, qos).await
+    }
+
+    async fn unsubscribe(&mut self, topic: String) -> Result<(), MqttServiceError> {
+        self.route_mut(&topic).unsubscribe(topic).await
+    }
+
+    fn pending_publishes(&self) -> usize {
+        self.services()
+            .map(|service| service.pending_publishes())
+            .sum()
+    }
+
+    fn published(&self) -> MessageStatistics {
+        let mut published = self.default.published();
+        for service in self.connections.values() {
+            published.merge(service.published());
+        }
+
+        published
+    }
+
+    fn granted_qos_failed(&self) -> bool {
+        self.services().any(|service| service.granted_qos_failed())
+    }
+
+    fn connection_failed(&self) -> bool {
+        self.services().any(|service| service.connection_failed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockBroker, MockMqttService};
+
+    #[tokio::test]
+    async fn routes_by_topic() {
+        let (default, default_broker) = MockMqttService::new();
+        let (bulk, bulk_broker) = MockMqttService::new();
+        let mut router = MqttServiceRouter::new(Box::new(default))
+            .with_connection("bulk", Box::new(bulk))
+            .with_route("sensors/#", "bulk")
+            .with_route("unknown/#", "unknown");
+
+        router
+            .subscribe("sensors/#".to_string(), QoS::AtMostOnce)
+            .await
+            .unwrap();
+        router
+            .subscribe("status".to_string(), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        router
+            .publish(MessagePublishData::new(
+                "sensors/1".to_string(),
+                QoS::AtMostOnce,
+                false,
+                vec![],
+            ))
+            .await;
+        router
+            .publish(MessagePublishData::new(
+                "unknown/1".to_string(),
+                QoS::AtMostOnce,
+                false,
+                vec![],
+            ))
+            .await;
+
+        assert_eq!(
+            vec![("status".to_string(), QoS::AtLeastOnce)],
+            default_broker.subscriptions()
+        );
+        assert_eq!(
+            vec![("sensors/#".to_string(), QoS::AtMostOnce)],
+            bulk_broker.subscriptions()
+        );
+        assert_eq!(vec!["unknown/1"], topics(&default_broker));
+        assert_eq!(vec!["sensors/1"], topics(&bulk_broker));
+    }
+
+    fn topics(broker: &MockBroker) -> Vec<String> {
+        broker
+            .published()
+            .into_iter()
+            .map(|message| message.topic)
+            .collect()
+    }
+}
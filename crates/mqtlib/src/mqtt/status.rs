@@ -0,0 +1,131 @@
+use crate::config::mqtli_config::{MqttBrokerConnect, StatusConfig};
+use crate::mqtt::{MessagePublishData, QoS};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+/// Connection lifecycle event published on the status topic.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusEvent {
+    /// The broker acknowledged a connect, also after a reconnect
+    Connected,
+    /// mqtli disconnects on shutdown
+    Disconnected,
+    /// The connection failed or was lost and is established again; the
+    /// event is sent once the connection is back
+    Reconnecting { attempt: u32, max_attempts: u32 },
+    /// The broker acknowledged a subscription; the QoS is None if it was
+    /// rejected
+    Subscribed { topic: String, qos: Option<QoS> },
+}
+
+/// Creates the messages of the connection events for the status topic.
+#[derive(Clone, Debug)]
+pub struct ConnectionStatus {
+    config: StatusConfig,
+    client_id: String,
+}
+
+impl ConnectionStatus {
+    /// Returns None if no status topic is configured.
+    pub fn new(broker: &MqttBrokerConnect) -> Option<Self> {
+        broker.status().as_ref().map(|config| Self {
+            config: config.clone(),
+            client_id: broker.client_id().clone(),
+        })
+    }
+
+    pub fn message(&self, event: StatusEvent) -> MessagePublishData {
+        MessagePublishData::new(
+            self.config.topic().clone(),
+            *self.config.qos(),
+            *self.config.retain(),
+            self.payload(event).to_string().into_bytes(),
+        )
+    }
+
+    fn payload(&self, event: StatusEvent) -> Value {
+        let mut payload = match event {
+            StatusEvent::Connected => json!({"event": "connected"}),
+            StatusEvent::Disconnected => json!({"event": "disconnected"}),
+            StatusEvent::Reconnecting {
+                attempt,
+                max_attempts,
+            } => json!({
+                "event": "reconnecting",
+                "attempt": attempt,
+                "max_attempts": max_attempts,
+            }),
+            StatusEvent::Subscribed { topic, qos } => json!({
+                "event": "subscribed",
+                "topic": topic,
+                "qos": qos.map(|qos| qos as u8),
+            }),
+        };
+
+        payload["client_id"] = json!(self.client_id);
+        payload["time"] = json!(Utc::now().to_rfc3339());
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::mqtli_config::StatusConfigBuilder;
+
+    #[test]
+    fn payload() {
+        let broker = MqttBrokerConnect {
+            status: Some(
+                StatusConfigBuilder::default()
+                    .topic("mqtli/status".to_string())
+                    .qos(QoS::AtLeastOnce)
+                    .retain(true)
+                    .build()
+                    .unwrap(),
+            ),
+            ..MqttBrokerConnect::default()
+        };
+        let status = ConnectionStatus::new(&broker).unwrap();
+
+        let message = status.message(StatusEvent::Subscribed {
+            topic: "sensors/#".to_string(),
+            qos: Some(QoS::AtLeastOnce),
+        });
+        assert_eq!(
+            ("mqtli/status", QoS::AtLeastOnce, true),
+            (message.topic.as_str(), message.qos, message.retain)
+        );
+
+        let mut payload: Value = serde_json::from_slice(&message.payload).unwrap();
+        assert!(payload["time"].is_string());
+        payload.as_object_mut().unwrap().remove("time");
+        assert_eq!(
+            json!({
+                "client_id": "mqtli",
+                "event": "subscribed",
+                "topic": "sensors/#",
+                "qos": 1,
+            }),
+            payload
+        );
+
+        let payload = status.payload(StatusEvent::Reconnecting {
+            attempt: 2,
+            max_attempts: 3,
+        });
+        assert_eq!(
+            (json!("reconnecting"), json!(2), json!(3)),
+            (
+                payload["event"].clone(),
+                payload["attempt"].clone(),
+                payload["max_attempts"].clone()
+            )
+        );
+    }
+
+    #[test]
+    fn without_topic() {
+        assert!(ConnectionStatus::new(&MqttBrokerConnect::default()).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use std::io::ErrorKind;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use rumqttc::{AsyncClient, ConnectionError, EventLoop, MqttOptions, StateError};
@@ -7,38 +8,91 @@ use rumqttc::{ConnectReturnCode, LastWill};
 use tokio::sync::broadcast;
 use tokio::sync::broadcast::Receiver;
 use tokio::task::JoinHandle;
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
+use crate::channel::wait_for_capacity;
+use crate::config::channels::Channels;
 use crate::config::mqtli_config::MqttBrokerConnect;
+use crate::mqtt::chaos::ChaosMonkey;
+use crate::mqtt::granted_qos::GrantedQosCheck;
+use crate::mqtt::outgoing_queue::OutgoingQueue;
+use crate::mqtt::reconnect::{Reconnect, RECONNECT_DELAY};
+use crate::mqtt::status::{ConnectionStatus, StatusEvent};
 use crate::mqtt::{
-    get_transport_parameters, MessagePublishData, MqttReceiveEvent, MqttService, MqttServiceError,
-    QoS,
+    count_completed_publish, count_pending_publish, get_transport_parameters, MessagePublishData,
+    MqttReceiveEvent, MqttService, MqttServiceError, QoS,
 };
+use crate::output::statistics::MessageStatistics;
 
 pub struct MqttServiceV311 {
+    /// Capacity and overflow policy of the channel of received events
+    channels: Channels,
     client: Option<AsyncClient>,
     config: Arc<MqttBrokerConnect>,
+    /// QoS 1 and 2 publishes not yet acknowledged by the broker
+    pending_publishes: Arc<AtomicUsize>,
+    /// Keeps QoS 1 and 2 publishes on disk until they are acknowledged
+    outgoing_queue: Option<Arc<OutgoingQueue>>,
+    /// Checks the QoS the broker granted for the subscriptions
+    granted_qos: Arc<GrantedQosCheck>,
+    /// Set if the connection could not be established within the configured
+    /// timeout or reconnect attempts
+    connection_failed: Arc<AtomicBool>,
+    /// Disturbs the connection and the publishes if chaos is configured
+    chaos: Option<Arc<ChaosMonkey>>,
+    /// Publishes the connection events if a status topic is configured
+    status: Option<Arc<ConnectionStatus>>,
+    published: Arc<Mutex<MessageStatistics>>,
 }
 
 impl MqttServiceV311 {
-    pub fn new(config: Arc<MqttBrokerConnect>) -> MqttServiceV311 {
+    pub fn new(config: Arc<MqttBrokerConnect>, channels: Channels) -> MqttServiceV311 {
         MqttServiceV311 {
+            channels,
             client: None,
             config,
+            pending_publishes: Arc::new(AtomicUsize::new(0)),
+            outgoing_queue: None,
+            granted_qos: Arc::new(GrantedQosCheck::new(*config.strict_qos())),
+            connection_failed: Arc::new(AtomicBool::new(false)),
+            chaos: config
+                .chaos()
+                .clone()
+                .map(|chaos| Arc::new(ChaosMonkey::new(chaos))),
+            status: ConnectionStatus::new(&config).map(Arc::new),
+            published: Arc::new(Mutex::new(MessageStatistics::default())),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn start_connection_task(
         mut event_loop: EventLoop,
         client: AsyncClient,
         channel: broadcast::Sender<MqttReceiveEvent>,
+        channels: Channels,
+        pending_publishes: Arc<AtomicUsize>,
+        outgoing_queue: Option<Arc<OutgoingQueue>>,
+        granted_qos: Arc<GrantedQosCheck>,
+        chaos: Option<Arc<ChaosMonkey>>,
+        status: Option<Arc<ConnectionStatus>>,
+        mut reconnect: Reconnect,
         mut receiver_exit: Receiver<()>,
     ) -> JoinHandle<()> {
         let client_exit = client.clone();
+        // stops reconnecting once mqtli shuts down
+        let mut receiver_closing = receiver_exit.resubscribe();
+        let status_exit = status.clone();
+        let outgoing_queue_exit = outgoing_queue.clone();
 
         tokio::task::spawn(async move {
             loop {
                 if receiver_exit.recv().await.is_ok() {
+                    publish_status(
+                        &client_exit,
+                        &status_exit,
+                        &outgoing_queue_exit,
+                        StatusEvent::Disconnected,
+                    );
                     if let Err(e) = client_exit.disconnect().await {
                         error!("Error while disconnecting client on exit signal: {e:?}");
                     }
@@ -49,35 +103,163 @@ impl MqttServiceV311 {
 
         tokio::task::spawn(async move {
             loop {
-                match event_loop.poll().await {
+                let result = match reconnect.deadline() {
+                    Some(deadline) => {
+                        match tokio::time::timeout_at(deadline, event_loop.poll()).await {
+                            Ok(result) => result,
+                            Err(_) => {
+                                reconnect.timed_out();
+                                return;
+                            }
+                        }
+                    }
+                    None => event_loop.poll().await,
+                };
+
+                match result {
                     Ok(event) => {
                         trace!("Received {:?}", &event);
-                        let _ = channel.send(MqttReceiveEvent::V311(event));
-                    }
-                    Err(e) => match e {
-                        ConnectionError::ConnectionRefused(ConnectReturnCode::NotAuthorized) => {
-                            error!("Not authorized, check if the credentials are valid");
-                            return;
+                        let event = MqttReceiveEvent::V311(event);
+                        reconnect.handle_event(&event);
+                        if event.is_publish_completed() {
+                            count_completed_publish(&pending_publishes);
                         }
-                        ConnectionError::MqttState(StateError::Io(value)) => match value.kind() {
-                            ErrorKind::ConnectionAborted => {
-                                info!("Connection was terminated by the broker");
-                                return;
+                        if let Some(outgoing_queue) = &outgoing_queue {
+                            outgoing_queue.handle_event(&event).await;
+                        }
+                        if event.is_connect() {
+                            publish_status(
+                                &client,
+                                &status,
+                                &outgoing_queue,
+                                StatusEvent::Connected,
+                            );
+                        }
+                        let granted_qos_failed = granted_qos.handle_event(&event);
+                        if let Some((topic, qos)) = granted_qos.take_acknowledged() {
+                            publish_status(
+                                &client,
+                                &status,
+                                &outgoing_queue,
+                                StatusEvent::Subscribed { topic, qos },
+                            );
+                        }
+                        if granted_qos_failed {
+                            // don't wait for the request queue, it is only emptied by polling
+                            if let Err(e) = client.try_disconnect() {
+                                error!("Error while disconnecting client: {e:?}");
                             }
-                            e => {
-                                error!("Connection error: {}", e);
+                        }
+
+                        wait_for_capacity(&channel, &channels).await;
+                        let _ = channel.send(event);
+
+                        if chaos.as_ref().is_some_and(|chaos| chaos.drop_connection()) {
+                            // drops the network connection without disconnecting, so the
+                            // broker publishes the last will; the next poll connects again
+                            warn!("Chaos: dropping the connection");
+                            event_loop.clean();
+                            reconnect.dropped();
+                        }
+                    }
+                    Err(e) => {
+                        match e {
+                            ConnectionError::ConnectionRefused(
+                                ConnectReturnCode::NotAuthorized,
+                            ) => {
+                                error!("Not authorized, check if the credentials are valid");
                                 return;
                             }
-                        },
-                        _ => {
-                            error!("Error while processing mqtt loop: {}", e);
+                            ConnectionError::MqttState(StateError::Io(value)) => {
+                                match value.kind() {
+                                    ErrorKind::ConnectionAborted => {
+                                        info!("Connection was terminated by the broker")
+                                    }
+                                    e => error!("Connection error: {}", e),
+                                }
+                            }
+                            _ => error!("Error while processing mqtt loop: {}", e),
+                        }
+
+                        if receiver_closing.try_recv().is_ok() {
                             return;
                         }
-                    },
+                        let Some(attempt) = reconnect.next_attempt() else {
+                            return;
+                        };
+                        publish_status(
+                            &client,
+                            &status,
+                            &outgoing_queue,
+                            StatusEvent::Reconnecting {
+                                attempt,
+                                max_attempts: reconnect.max_attempts(),
+                            },
+                        );
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
                 }
             }
         })
     }
+
+    /// Parts of the service which send publishes, e.g. from a task.
+    fn publish_sender(&self) -> PublishSender {
+        PublishSender {
+            client: self.client.clone(),
+            pending_publishes: self.pending_publishes.clone(),
+            outgoing_queue: self.outgoing_queue.clone(),
+            published: self.published.clone(),
+        }
+    }
+}
+
+/// Sends publishes on behalf of the service; publishes delayed by chaos mode
+/// are sent from a separate task, so they don't hold the service lock.
+#[derive(Clone)]
+struct PublishSender {
+    client: Option<AsyncClient>,
+    pending_publishes: Arc<AtomicUsize>,
+    outgoing_queue: Option<Arc<OutgoingQueue>>,
+    published: Arc<Mutex<MessageStatistics>>,
+}
+
+impl PublishSender {
+    /// Passes the publish to the client; id is the id of the message in the
+    /// outgoing queue if it is stored there.
+    async fn send(&self, id: Option<i64>, payload: MessagePublishData) {
+        if let Some(client) = self.client.as_ref() {
+            let pending = count_pending_publish(&self.pending_publishes, payload.qos);
+            let size = payload.payload.len();
+            if let Some(outgoing_queue) = &self.outgoing_queue {
+                outgoing_queue.track(id);
+            }
+
+            if let Err(e) = client
+                .publish(
+                    &payload.topic,
+                    payload.qos.into(),
+                    payload.retain,
+                    payload.payload,
+                )
+                .await
+            {
+                if pending {
+                    count_completed_publish(&self.pending_publishes);
+                }
+                if let Some(outgoing_queue) = &self.outgoing_queue {
+                    outgoing_queue.untrack();
+                }
+                error!("Error during publish: {}", e);
+            } else {
+                info!("Message published on topic {}", payload.topic);
+                self.published
+                    .lock()
+                    .unwrap()
+                    .count_message(&payload.topic, size);
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -105,6 +287,21 @@ impl MqttService for MqttServiceV311 {
         );
         options.set_keep_alive(*self.config.keep_alive());
 
+        if self.config.receive_maximum().is_some() {
+            warn!("The receive maximum is only sent with MQTT v5");
+        }
+
+        if let Some(maximum_packet_size) = self.config.maximum_packet_size() {
+            debug!("Setting maximum packet size to {maximum_packet_size} bytes");
+            let maximum_packet_size = *maximum_packet_size as usize;
+            options.set_max_packet_size(maximum_packet_size, maximum_packet_size);
+        }
+
+        if let Some(inflight) = self.config.inflight() {
+            debug!("Setting inflight limit to {inflight}");
+            options.set_inflight(*inflight);
+        }
+
         if self.config.username().is_some() && self.config.password().is_some() {
             info!("Using username/password for authentication");
             options.set_credentials(
@@ -123,6 +320,9 @@ impl MqttService for MqttServiceV311 {
                 last_will.qos(),
                 last_will.retain(),
             );
+            if last_will.has_properties() {
+                warn!("The properties of the last will are only sent with MQTT v5");
+            }
             let last_will = LastWill::new(
                 last_will.topic(),
                 last_will.payload().clone(),
@@ -132,13 +332,51 @@ impl MqttService for MqttServiceV311 {
             options.set_last_will(last_will);
         }
 
+        if let Some(path) = self.config.outgoing_queue() {
+            if self.outgoing_queue.is_none() {
+                debug!("Opening outgoing queue {}", path.display());
+                let outgoing_queue = OutgoingQueue::open(path)
+                    .await
+                    .map_err(|e| MqttServiceError::OutgoingQueue(e, path.clone()))?;
+                self.outgoing_queue = Some(Arc::new(outgoing_queue));
+            }
+        }
+
         let (client, event_loop) = AsyncClient::new(options, 10);
 
-        let task_handle: JoinHandle<()> =
-            Self::start_connection_task(event_loop, client.clone(), channel, receiver_exit).await;
+        let task_handle: JoinHandle<()> = Self::start_connection_task(
+            event_loop,
+            client.clone(),
+            channel,
+            self.channels.clone(),
+            self.pending_publishes.clone(),
+            self.outgoing_queue.clone(),
+            self.granted_qos.clone(),
+            self.chaos.clone(),
+            self.status.clone(),
+            Reconnect::new(&self.config, self.connection_failed.clone()),
+            receiver_exit,
+        )
+        .await;
 
         self.client = Option::from(client);
 
+        if let Some(outgoing_queue) = &self.outgoing_queue {
+            let stored = outgoing_queue.stored().await.map_err(|e| {
+                MqttServiceError::OutgoingQueue(e, self.config.outgoing_queue().clone().unwrap())
+            })?;
+            if !stored.is_empty() {
+                info!(
+                    "Publishing {} messages left in the outgoing queue",
+                    stored.len()
+                );
+            }
+            let sender = self.publish_sender();
+            for (id, payload) in stored {
+                sender.send(Some(id), payload).await;
+            }
+        }
+
         Ok(task_handle)
     }
 
@@ -151,31 +389,180 @@ impl MqttService for MqttServiceV311 {
     }
 
     async fn publish(&self, payload: MessagePublishData) {
-        if let Some(client) = self.client.as_ref() {
-            if let Err(e) = client
-                .publish(
-                    &payload.topic,
-                    payload.qos.into(),
-                    payload.retain,
-                    payload.payload,
-                )
-                .await
-            {
-                error!("Error during publish: {}", e);
-            } else {
-                info!("Message published on topic {}", payload.topic);
+        if self.client.is_none() {
+            return;
+        }
+
+        if self
+            .chaos
+            .as_ref()
+            .is_some_and(|chaos| chaos.drop_publish())
+        {
+            warn!("Chaos: dropping publish on topic {}", payload.topic);
+            return;
+        }
+
+        let duplicate = self
+            .chaos
+            .as_ref()
+            .is_some_and(|chaos| chaos.duplicate(payload.qos))
+            .then(|| payload.clone());
+
+        let id = match &self.outgoing_queue {
+            Some(outgoing_queue) => match outgoing_queue.store(&payload).await {
+                Ok(id) => id,
+                Err(e) => {
+                    error!("Could not store message in the outgoing queue: {e:?}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let sender = self.publish_sender();
+        let send = async move {
+            sender.send(id, payload).await;
+
+            if let Some(duplicate) = duplicate {
+                debug!("Chaos: publishing on topic {} again", duplicate.topic);
+                sender.send(None, duplicate).await;
+            }
+        };
+
+        match self.chaos.as_ref().and_then(|chaos| chaos.publish_delay()) {
+            Some(delay) => {
+                debug!("Chaos: delaying a publish by {} ms", delay.as_millis());
+                // the caller holds the lock of the service while publishing
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    send.await;
+                });
             }
+            None => send.await,
         }
     }
 
     async fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), MqttServiceError> {
         if let Some(client) = &self.client {
+            self.granted_qos.track(topic.clone(), qos);
             return client
                 .subscribe(topic.clone(), qos.into())
                 .await
+                .inspect_err(|_| self.granted_qos.untrack())
                 .map_err(MqttServiceError::from);
         }
 
         Err(MqttServiceError::NotConnected)
     }
+
+    async fn unsubscribe(&mut self, topic: String) -> Result<(), MqttServiceError> {
+        if let Some(client) = &self.client {
+            return client
+                .unsubscribe(topic)
+                .await
+                .map_err(MqttServiceError::from);
+        }
+
+        Err(MqttServiceError::NotConnected)
+    }
+
+    fn pending_publishes(&self) -> usize {
+        self.pending_publishes.load(Ordering::Relaxed)
+    }
+
+    fn published(&self) -> MessageStatistics {
+        self.published.lock().unwrap().clone()
+    }
+
+    fn connection_failed(&self) -> bool {
+        self.connection_failed.load(Ordering::Relaxed)
+    }
+
+    fn granted_qos_failed(&self) -> bool {
+        self.granted_qos.failed()
+    }
+}
+
+/// Publishes the connection event on the status topic without waiting, as
+/// the request queue is only emptied by the connection task. The message is
+/// tracked by the outgoing queue without being stored, so the packet ids of
+/// the stored messages stay in order.
+fn publish_status(
+    client: &AsyncClient,
+    status: &Option<Arc<ConnectionStatus>>,
+    outgoing_queue: &Option<Arc<OutgoingQueue>>,
+    event: StatusEvent,
+) {
+    let Some(status) = status else {
+        return;
+    };
+
+    let message = status.message(event);
+    if let Some(outgoing_queue) = outgoing_queue {
+        outgoing_queue.track(None);
+    }
+    if let Err(e) = client.try_publish(
+        message.topic,
+        message.qos.into(),
+        message.retain,
+        message.payload,
+    ) {
+        if let Some(outgoing_queue) = outgoing_queue {
+            outgoing_queue.untrack();
+        }
+        error!("Could not publish connection status: {e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::mqtli_config::StatusConfigBuilder;
+
+    fn outgoing_publish(pkid: u16) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Outgoing(rumqttc::Outgoing::Publish(pkid)))
+    }
+
+    fn puback(pkid: u16) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::PubAck(
+            rumqttc::PubAck::new(pkid),
+        )))
+    }
+
+    #[tokio::test]
+    async fn status_between_queued_publishes() {
+        let path = std::env::temp_dir().join(format!("mqtli-queue-{}.db", uuid::Uuid::new_v4()));
+        let outgoing_queue = Some(Arc::new(OutgoingQueue::open(&path).await.unwrap()));
+        let queue = outgoing_queue.as_ref().unwrap();
+        let status = ConnectionStatus::new(&MqttBrokerConnect {
+            status: Some(
+                StatusConfigBuilder::default()
+                    .topic("mqtli/status".to_string())
+                    .qos(QoS::AtLeastOnce)
+                    .retain(false)
+                    .build()
+                    .unwrap(),
+            ),
+            ..MqttBrokerConnect::default()
+        })
+        .map(Arc::new);
+        let (client, _event_loop) =
+            AsyncClient::new(MqttOptions::new("test", "localhost", 1883), 10);
+
+        let message = MessagePublishData::new("a".to_string(), QoS::AtLeastOnce, false, vec![1]);
+        let id = queue.store(&message).await.unwrap();
+
+        publish_status(&client, &status, &outgoing_queue, StatusEvent::Connected);
+        queue.track(id);
+
+        queue.handle_event(&outgoing_publish(1)).await;
+        queue.handle_event(&outgoing_publish(2)).await;
+        queue.handle_event(&puback(1)).await;
+        assert_eq!(1, queue.stored().await.unwrap().len());
+
+        queue.handle_event(&puback(2)).await;
+        assert!(queue.stored().await.unwrap().is_empty());
+
+        let _ = std::fs::remove_file(path);
+    }
 }
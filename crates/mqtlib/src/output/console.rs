@@ -1,36 +1,468 @@
-use crate::mqtt::QoS;
+use crate::config::subscription::{ConsoleEscape, OutputTargetConsole};
+use crate::mqtt::{MessageProperties, PayloadFormatIndicator, QoS};
+use crate::output::diff::JsonChange;
+use crate::output::theme::{Role, Themed};
 use crate::output::OutputError;
 use crate::payload::PayloadFormat;
-use colored::Colorize;
+use crate::template;
+use chrono::Local;
+use colored::ColoredString;
+use serde_json::Value as JsonValue;
+use serde_yaml::Value as YamlValue;
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::io;
+use std::io::Write;
+
+const INDENT: &str = "  ";
 
 pub struct ConsoleOutput {}
 
 impl ConsoleOutput {
+    #[allow(clippy::too_many_arguments)]
     pub fn output_topic(
         topic: &str,
         content: String,
         format: PayloadFormat,
         qos: QoS,
         retain: bool,
+        payload_format_indicator: Option<PayloadFormatIndicator>,
+        properties: Option<&MessageProperties>,
+        options: &OutputTargetConsole,
     ) -> Result<(), OutputError> {
-        let retained = if retain { " retained" } else { "" };
-        let bytes = if content.len() == 1 { "byte" } else { "bytes" };
-
-        println!(
-            "{} [{} | {} {} | {}] {}",
-            topic.bold().green(),
-            format.to_string().blue(),
-            content.len().to_string().blue(),
-            bytes.blue(),
-            qos.to_string().blue(),
-            retained.purple()
-        );
-        println!("{}", content.yellow());
+        Self::output_header(
+            topic,
+            &format,
+            content.len(),
+            qos,
+            retain,
+            payload_format_indicator,
+            options,
+        );
+        Self::output_properties(properties, options);
+
+        let pretty = match (&format, options.pretty) {
+            (PayloadFormat::Json(value) | PayloadFormat::SparkplugJson(value), true) => {
+                Some(highlight_json(value.content()))
+            }
+            (PayloadFormat::Yaml(_), true) => Some(highlight_yaml(content.as_str())),
+            _ => None,
+        };
+
+        match pretty {
+            None => print_line(
+                options,
+                escape_control_characters(&content, options.escape).themed(Role::Payload),
+            ),
+            Some(pretty) => print_line(options, pretty),
+        }
+
         Ok(())
     }
 
+    /// Prints only the fields which changed compared to the previous
+    /// payload, colored differently for added, removed and changed fields.
+    #[allow(clippy::too_many_arguments)]
+    pub fn output_changes(
+        topic: &str,
+        changes: &[JsonChange],
+        format: PayloadFormat,
+        size: usize,
+        qos: QoS,
+        retain: bool,
+        payload_format_indicator: Option<PayloadFormatIndicator>,
+        properties: Option<&MessageProperties>,
+        options: &OutputTargetConsole,
+    ) -> Result<(), OutputError> {
+        Self::output_header(
+            topic,
+            &format,
+            size,
+            qos,
+            retain,
+            payload_format_indicator,
+            options,
+        );
+        Self::output_properties(properties, options);
+
+        if changes.is_empty() {
+            print_line(options, "(no changes)".themed(Role::Hint));
+        }
+
+        for change in changes {
+            print_line(options, format_change(change));
+        }
+
+        Ok(())
+    }
+
+    /// Writes the bytes unmodified to stdout (or stderr), followed by a NUL
+    /// byte, so that binary payloads can be piped to tools like `xargs -0`.
+    pub fn output_null_delimited(
+        content: Vec<u8>,
+        options: &OutputTargetConsole,
+    ) -> Result<(), OutputError> {
+        let mut writer: Box<dyn Write> = if options.stderr {
+            Box::new(io::stderr().lock())
+        } else {
+            Box::new(io::stdout().lock())
+        };
+
+        writer
+            .write_all(content.as_slice())
+            .and_then(|_| writer.write_all(&[0]))
+            .and_then(|_| writer.flush())
+            .map_err(OutputError::ErrorWhileWritingToConsole)
+    }
+
     pub fn output_string(content: String) -> Result<(), OutputError> {
         println!("{}", content);
         Ok(())
     }
+
+    fn output_header(
+        topic: &str,
+        format: &PayloadFormat,
+        size: usize,
+        qos: QoS,
+        retain: bool,
+        payload_format_indicator: Option<PayloadFormatIndicator>,
+        options: &OutputTargetConsole,
+    ) {
+        match (options.show_header, &options.header) {
+            (false, _) => {}
+            (true, None) => {
+                let retained = if retain { " retained" } else { "" };
+                let bytes = if size == 1 { "byte" } else { "bytes" };
+                let indicator = payload_format_indicator
+                    .map(|indicator| format!(" | {indicator}"))
+                    .unwrap_or_default();
+
+                print_line(
+                    options,
+                    format!(
+                        "{} [{}{} | {} {} | {}] {}",
+                        topic.themed(Role::Topic),
+                        format.to_string().themed(Role::Info),
+                        indicator.themed(Role::Info),
+                        size.to_string().themed(Role::Info),
+                        bytes.themed(Role::Info),
+                        qos.to_string().themed(Role::Info),
+                        retained.themed(Role::Retained)
+                    ),
+                );
+            }
+            (true, Some(template)) => {
+                print_line(
+                    options,
+                    render_header(
+                        template,
+                        topic,
+                        format,
+                        size,
+                        qos,
+                        retain,
+                        payload_format_indicator,
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Prints the MQTT v5 properties of the message indented below the
+    /// header, if enabled.
+    fn output_properties(properties: Option<&MessageProperties>, options: &OutputTargetConsole) {
+        if !options.show_properties {
+            return;
+        }
+
+        for line in properties
+            .map(ToString::to_string)
+            .unwrap_or_default()
+            .lines()
+        {
+            print_line(options, format!("{INDENT}{}", line.themed(Role::Info)));
+        }
+    }
+}
+
+/// Prints the line to stdout or, if configured, to stderr.
+fn print_line(options: &OutputTargetConsole, line: impl Display) {
+    if options.stderr {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Escapes control characters except line feeds and tabs, so that payloads
+/// can't garble the terminal.
+fn escape_control_characters(content: &str, escape: ConsoleEscape) -> Cow<'_, str> {
+    let is_escaped = |c: char| c.is_control() && c != '\n' && c != '\t';
+    if escape == ConsoleEscape::None || !content.contains(is_escaped) {
+        return Cow::Borrowed(content);
+    }
+
+    let mut result = String::with_capacity(content.len());
+    for c in content.chars() {
+        match (c, escape) {
+            (c, _) if !is_escaped(c) => result.push(c),
+            (_, ConsoleEscape::Replace) => result.push(char::REPLACEMENT_CHARACTER),
+            ('\0', _) => result.push_str("\\0"),
+            ('\r', _) => result.push_str("\\r"),
+            (c, _) => result.push_str(&format!("\\x{:02x}", u32::from(c))),
+        }
+    }
+
+    Cow::Owned(result)
+}
+
+fn format_change(change: &JsonChange) -> String {
+    match change {
+        JsonChange::Added { path, value } => {
+            format!("+ {path}: {value}").themed(Role::Added).to_string()
+        }
+        JsonChange::Removed { path, value } => format!("- {path}: {value}")
+            .themed(Role::Removed)
+            .to_string(),
+        JsonChange::Changed {
+            path,
+            previous,
+            current,
+        } => format!("~ {path}: {previous} -> {current}")
+            .themed(Role::Changed)
+            .to_string(),
+    }
+}
+
+/// Expands the placeholders of a header template, besides the ones of
+/// [`crate::template`]. Unknown placeholders are kept as they are.
+fn render_header(
+    template: &str,
+    topic: &str,
+    format: &PayloadFormat,
+    size: usize,
+    qos: QoS,
+    retain: bool,
+    payload_format_indicator: Option<PayloadFormatIndicator>,
+) -> String {
+    template::render(template, |name| match name {
+        "time" => Some(Local::now().format("%H:%M:%S%.3f").to_string()),
+        "topic" => Some(topic.to_string()),
+        "qos" => Some((qos as i32).to_string()),
+        "retain" => Some(retain.to_string()),
+        "size" => Some(size.to_string()),
+        "format" => Some(format.to_string()),
+        "payload_format" => Some(
+            payload_format_indicator
+                .map(|indicator| indicator.to_string())
+                .unwrap_or_default(),
+        ),
+        name => template::common(name),
+    })
+    .into_owned()
+}
+
+/// Renders the JSON value indented with keys, strings, numbers and literals
+/// in different colors.
+fn highlight_json(value: &JsonValue) -> String {
+    let mut result = String::new();
+    write_json(value, 0, &mut result);
+    result
+}
+
+fn write_json(value: &JsonValue, depth: usize, result: &mut String) {
+    let indent = INDENT.repeat(depth + 1);
+
+    match value {
+        JsonValue::Object(object) if !object.is_empty() => {
+            result.push_str("{\n");
+            for (i, (key, value)) in object.iter().enumerate() {
+                result.push_str(&indent);
+                result.push_str(&json_string(key).themed(Role::Key).to_string());
+                result.push_str(": ");
+                write_json(value, depth + 1, result);
+                result.push_str(if i + 1 < object.len() { ",\n" } else { "\n" });
+            }
+            result.push_str(&INDENT.repeat(depth));
+            result.push('}');
+        }
+        JsonValue::Array(array) if !array.is_empty() => {
+            result.push_str("[\n");
+            for (i, value) in array.iter().enumerate() {
+                result.push_str(&indent);
+                write_json(value, depth + 1, result);
+                result.push_str(if i + 1 < array.len() { ",\n" } else { "\n" });
+            }
+            result.push_str(&INDENT.repeat(depth));
+            result.push(']');
+        }
+        JsonValue::Object(_) => result.push_str("{}"),
+        JsonValue::Array(_) => result.push_str("[]"),
+        JsonValue::String(value) => {
+            result.push_str(&json_string(value).themed(Role::String).to_string())
+        }
+        JsonValue::Number(value) => {
+            result.push_str(&value.to_string().themed(Role::Number).to_string())
+        }
+        JsonValue::Bool(_) | JsonValue::Null => {
+            result.push_str(&value.to_string().themed(Role::Literal).to_string())
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    JsonValue::from(value).to_string()
+}
+
+/// Highlights the keys and scalar values of a YAML document line by line.
+/// Lines of block scalars (`|` and `>`) are highlighted as strings.
+fn highlight_yaml(content: &str) -> String {
+    let mut result: Vec<String> = vec![];
+    // indentation of the line which started the current block scalar
+    let mut block_scalar: Option<usize> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        let indentation = line.len() - trimmed.len();
+
+        if let Some(block_indentation) = block_scalar {
+            if trimmed.is_empty() || indentation > block_indentation {
+                result.push(line.themed(Role::String).to_string());
+                continue;
+            }
+            block_scalar = None;
+        }
+
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            result.push(line.to_string());
+            continue;
+        }
+
+        let mut highlighted = line[..indentation].to_string();
+        let mut rest = trimmed;
+
+        while let Some(item) = rest.strip_prefix("- ") {
+            highlighted.push_str("- ");
+            rest = item;
+        }
+
+        let value = match yaml_key(rest) {
+            Some((key, value)) => {
+                highlighted.push_str(&key.themed(Role::Key).to_string());
+                highlighted.push(':');
+                value
+            }
+            None => rest,
+        };
+
+        if value.trim_start().starts_with(['|', '>']) {
+            block_scalar = Some(indentation);
+            highlighted.push_str(value);
+        } else if value.trim().is_empty() || value.trim_start().starts_with(['{', '[']) {
+            highlighted.push_str(value);
+        } else {
+            let leading = value.len() - value.trim_start().len();
+            highlighted.push_str(&value[..leading]);
+            highlighted.push_str(&yaml_scalar(value.trim_start()).to_string());
+        }
+
+        result.push(highlighted);
+    }
+
+    result.join("\n")
+}
+
+/// Splits a line into its key and the rest after the colon.
+fn yaml_key(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with(['"', '\'', '{', '[']) {
+        return None;
+    }
+
+    if let Some(key) = line.strip_suffix(':') {
+        return Some((key, ""));
+    }
+
+    line.split_once(": ")
+        .map(|(key, _)| (key, &line[key.len() + 1..]))
+}
+
+fn yaml_scalar(value: &str) -> ColoredString {
+    match serde_yaml::from_str::<YamlValue>(value) {
+        Ok(YamlValue::Number(_)) => value.themed(Role::Number),
+        Ok(YamlValue::Bool(_)) | Ok(YamlValue::Null) => value.themed(Role::Literal),
+        _ => value.themed(Role::String),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn highlight_json_indented() {
+        colored::control::set_override(false);
+
+        assert_eq!(
+            "{\n  \"a\": [\n    1,\n    \"b\"\n  ],\n  \"c\": {},\n  \"d\": null\n}",
+            highlight_json(&json!({"a": [1, "b"], "c": {}, "d": null}))
+        );
+    }
+
+    #[test]
+    fn escape_control() {
+        let content = "a\r\x1b[2J\0\tb\n\u{85}";
+
+        assert_eq!(
+            "a\\r\\x1b[2J\\0\tb\n\\x85",
+            escape_control_characters(content, ConsoleEscape::C)
+        );
+        assert_eq!(
+            "a\u{FFFD}\u{FFFD}[2J\u{FFFD}\tb\n\u{FFFD}",
+            escape_control_characters(content, ConsoleEscape::Replace)
+        );
+        assert_eq!(
+            content,
+            escape_control_characters(content, ConsoleEscape::None)
+        );
+    }
+
+    #[test]
+    fn header_template() {
+        let format = PayloadFormat::Json(Default::default());
+
+        assert_eq!(
+            "a/b qos=1 retain=true 12B Json utf-8 {{unknown}}",
+            render_header(
+                "{{topic}} qos={{qos}} retain={{retain}} {{size}}B {{format}} {{payload_format}} {{unknown}}",
+                "a/b",
+                &format,
+                12,
+                QoS::AtLeastOnce,
+                true,
+                Some(PayloadFormatIndicator::Utf8)
+            )
+        );
+        assert_eq!(
+            "a/b ",
+            render_header(
+                "{{topic}} {{payload_format}}",
+                "a/b",
+                &format,
+                12,
+                QoS::AtLeastOnce,
+                true,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn yaml_keys() {
+        assert_eq!(Some(("a", " 1")), yaml_key("a: 1"));
+        assert_eq!(Some(("a", "")), yaml_key("a:"));
+        assert_eq!(None, yaml_key("http://example.com"));
+        assert_eq!(None, yaml_key("'a: b'"));
+    }
 }
@@ -0,0 +1,149 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Change of a single field between two JSON documents. The path of the
+/// field is given as JSONPath, e.g. `$.sensors[0].value`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonChange {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        previous: Value,
+        current: Value,
+    },
+}
+
+/// Remembers the last payload of each topic and output to compare it with
+/// the next payload.
+#[derive(Debug, Default)]
+pub struct PayloadHistory {
+    previous: HashMap<(usize, String), Value>,
+}
+
+impl PayloadHistory {
+    /// Stores the payload as the latest of the topic and returns the changes
+    /// compared to the previous payload, or `None` for the first payload.
+    pub fn update(&mut self, output: usize, topic: &str, value: Value) -> Option<Vec<JsonChange>> {
+        let previous = self
+            .previous
+            .insert((output, topic.to_string()), value.clone())?;
+
+        Some(diff_json(&previous, &value))
+    }
+}
+
+/// Compares two JSON documents field by field. Objects are compared by key
+/// and arrays by index; all other values are compared as a whole.
+pub fn diff_json(previous: &Value, current: &Value) -> Vec<JsonChange> {
+    let mut changes = vec![];
+    diff_value("$".to_string(), previous, current, &mut changes);
+    changes
+}
+
+fn diff_value(path: String, previous: &Value, current: &Value, changes: &mut Vec<JsonChange>) {
+    match (previous, current) {
+        (Value::Object(previous), Value::Object(current)) => {
+            for (key, previous_value) in previous {
+                let path = format!("{path}.{key}");
+
+                match current.get(key) {
+                    None => changes.push(JsonChange::Removed {
+                        path,
+                        value: previous_value.clone(),
+                    }),
+                    Some(current_value) => diff_value(path, previous_value, current_value, changes),
+                }
+            }
+
+            for (key, current_value) in current {
+                if !previous.contains_key(key) {
+                    changes.push(JsonChange::Added {
+                        path: format!("{path}.{key}"),
+                        value: current_value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(previous), Value::Array(current)) => {
+            for (i, previous_value) in previous.iter().enumerate() {
+                let path = format!("{path}[{i}]");
+
+                match current.get(i) {
+                    None => changes.push(JsonChange::Removed {
+                        path,
+                        value: previous_value.clone(),
+                    }),
+                    Some(current_value) => diff_value(path, previous_value, current_value, changes),
+                }
+            }
+
+            for (i, current_value) in current.iter().enumerate().skip(previous.len()) {
+                changes.push(JsonChange::Added {
+                    path: format!("{path}[{i}]"),
+                    value: current_value.clone(),
+                });
+            }
+        }
+        (previous, current) => {
+            if previous != current {
+                changes.push(JsonChange::Changed {
+                    path,
+                    previous: previous.clone(),
+                    current: current.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff() {
+        let previous = json!({"a": 1, "b": {"c": true, "d": "x"}, "e": [1, 2]});
+        let current = json!({"a": 2, "b": {"c": true}, "e": [1, 2, 3], "f": null});
+
+        assert_eq!(
+            vec![
+                JsonChange::Changed {
+                    path: "$.a".to_string(),
+                    previous: json!(1),
+                    current: json!(2)
+                },
+                JsonChange::Removed {
+                    path: "$.b.d".to_string(),
+                    value: json!("x")
+                },
+                JsonChange::Added {
+                    path: "$.e[2]".to_string(),
+                    value: json!(3)
+                },
+                JsonChange::Added {
+                    path: "$.f".to_string(),
+                    value: json!(null)
+                },
+            ],
+            diff_json(&previous, &current)
+        );
+    }
+
+    #[test]
+    fn history() {
+        let mut history = PayloadHistory::default();
+
+        assert_eq!(None, history.update(0, "a", json!({"a": 1})));
+        assert_eq!(Some(vec![]), history.update(0, "a", json!({"a": 1})));
+        assert_eq!(None, history.update(1, "a", json!({"a": 2})));
+        assert_eq!(1, history.update(0, "a", json!({"a": 2})).unwrap().len());
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::Local;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::debug;
+
+use crate::config::subscription::{OutputTargetEmail, SmtpServer, SmtpTls};
+use crate::mqtt::QoS;
+use crate::output::OutputError;
+use crate::template;
+
+pub struct EmailOutput {}
+
+impl EmailOutput {
+    /// Sends the content as email to the recipients of the target. The
+    /// placeholders of the subject and body templates are replaced with the
+    /// properties of the message.
+    pub async fn output(
+        topic: &str,
+        content: String,
+        qos: QoS,
+        retain: bool,
+        target: &OutputTargetEmail,
+    ) -> Result<(), OutputError> {
+        let mut builder = Message::builder()
+            .from(target.from.clone())
+            .subject(render_template(
+                &target.subject,
+                topic,
+                &content,
+                qos,
+                retain,
+            ));
+        for to in &target.to {
+            builder = builder.to(to.clone());
+        }
+
+        let message = builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(render_template(&target.body, topic, &content, qos, retain))
+            .map_err(OutputError::CouldNotCreateEmail)?;
+
+        debug!(
+            "Sending email for message on topic {topic} via {}",
+            target.smtp.host
+        );
+
+        transport(&target.smtp)?
+            .send(message)
+            .await
+            .map_err(|e| OutputError::CouldNotSendEmail(e, target.smtp.host.clone()))?;
+
+        Ok(())
+    }
+}
+
+fn transport(smtp: &SmtpServer) -> Result<AsyncSmtpTransport<Tokio1Executor>, OutputError> {
+    let mut builder = match smtp.tls {
+        SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host),
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host),
+        SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            &smtp.host,
+        )),
+    }
+    .map_err(|e| OutputError::CouldNotSendEmail(e, smtp.host.clone()))?;
+
+    if let Some(port) = smtp.port {
+        builder = builder.port(port);
+    }
+    if let Some(username) = &smtp.username {
+        builder = builder.credentials(Credentials::new(
+            username.clone(),
+            smtp.password.clone().unwrap_or_default(),
+        ));
+    }
+
+    Ok(builder.build())
+}
+
+/// Expands the placeholders of a subject or body template, besides the ones
+/// of [`crate::template`]. Unknown placeholders are kept as they are.
+fn render_template(template: &str, topic: &str, payload: &str, qos: QoS, retain: bool) -> String {
+    template::render(template, |name| match name {
+        "time" => Some(Local::now().to_rfc3339()),
+        "topic" => Some(topic.to_string()),
+        "qos" => Some((qos as i32).to_string()),
+        "retain" => Some(retain.to_string()),
+        "payload" => Some(payload.to_string()),
+        name => template::common(name),
+    })
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        assert_eq!(
+            "Alert on sensors/1 (qos 1, retain false): {\"temperature\":90} {{unknown}}",
+            render_template(
+                "Alert on {{topic}} (qos {{qos}}, retain {{retain}}): {{payload}} {{unknown}}",
+                "sensors/1",
+                "{\"temperature\":90}",
+                QoS::AtLeastOnce,
+                false
+            )
+        );
+    }
+}
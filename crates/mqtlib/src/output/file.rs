@@ -1,56 +1,382 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::error;
 
 use crate::config::subscription::OutputTargetFile;
+use crate::mqtt::{MessageReceivedData, QoS};
 use crate::output::OutputError;
+use crate::payload::PayloadFormat;
+use crate::template;
+
+struct BufferedFile {
+    writer: BufWriter<File>,
+    flush_interval: Duration,
+    flushed_at: Instant,
+}
 
-pub struct FileOutput {}
+impl BufferedFile {
+    fn flush(&mut self, path: &PathBuf) {
+        if let Err(e) = self.writer.flush() {
+            error!("Error while writing to file \"{}\": {e:?}", path.display());
+        }
+        self.flushed_at = Instant::now();
+    }
+}
+
+/// Metadata of a message written to a file, which can be inserted with
+/// placeholders in prepend and append.
+#[derive(Clone, Copy, Debug)]
+pub struct FileMessage<'a> {
+    pub topic: &'a str,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+impl FileMessage<'_> {
+    /// Expands these placeholders of the template besides the ones of
+    /// [`crate::template`], unknown placeholders are kept as they are:
+    ///
+    /// - `{{topic}}`: topic of the message
+    /// - `{{qos}}`: QoS of the message
+    /// - `{{retain}}`: retain flag of the message
+    /// - `{{size}}`: size of the written content in bytes
+    fn render<'t>(&self, template: &'t str, size: usize) -> Cow<'t, str> {
+        template::render(template, |name| match name {
+            "topic" => Some(self.topic.to_string()),
+            "qos" => Some((self.qos as i32).to_string()),
+            "retain" => Some(self.retain.to_string()),
+            "size" => Some(size.to_string()),
+            name => template::common(name),
+        })
+    }
+}
+
+impl<'a> From<&'a MessageReceivedData> for FileMessage<'a> {
+    fn from(value: &'a MessageReceivedData) -> Self {
+        Self {
+            topic: &value.topic,
+            qos: value.qos,
+            retain: value.retain,
+        }
+    }
+}
+
+/// File outputs of a run, see [`crate::output::Outputs`].
+#[derive(Default)]
+pub struct FileOutput {
+    /// Files which are appended to, kept open between messages
+    open_files: Mutex<HashMap<PathBuf, BufferedFile>>,
+    /// Number of the last file written per path template
+    counters: Mutex<HashMap<PathBuf, u64>>,
+}
 
 impl FileOutput {
-    pub fn output(content: Vec<u8>, target_file: &OutputTargetFile) -> Result<(), OutputError> {
-        match File::options()
+    /// Writes the content to the file, surrounded by prepend and append with
+    /// their placeholders expanded for the message, see
+    /// [`FileMessage::render`]. Files which are appended to are kept open and
+    /// written through a buffer, see [`FileOutput::flush_due`].
+    pub fn output(
+        &self,
+        message: &FileMessage,
+        content: Vec<u8>,
+        target_file: &OutputTargetFile,
+    ) -> Result<(), OutputError> {
+        if *target_file.overwrite() {
+            let mut file = Self::open(target_file)?;
+            return Self::write(&mut file, message, &content, target_file);
+        }
+
+        let mut files = self.open_files.lock().unwrap();
+
+        if !files.contains_key(target_file.path()) {
+            let file = Self::open(target_file)?;
+            files.insert(
+                target_file.path().clone(),
+                BufferedFile {
+                    writer: BufWriter::with_capacity(*target_file.buffer_size(), file),
+                    flush_interval: *target_file.flush_interval(),
+                    flushed_at: Instant::now(),
+                },
+            );
+        }
+
+        let file = files.get_mut(target_file.path()).unwrap();
+        Self::write(&mut file.writer, message, &content, target_file)?;
+
+        // without a buffer the content was already written to the file
+        if *target_file.buffer_size() == 0 {
+            file.flush(target_file.path());
+        }
+
+        Ok(())
+    }
+
+    /// Writes the JSON value as a single line. Prepend and append are not
+    /// written, so the file stays valid NDJSON.
+    pub fn output_json_line(
+        &self,
+        message: &FileMessage,
+        value: &serde_json::Value,
+        target_file: &OutputTargetFile,
+    ) -> Result<(), OutputError> {
+        let target_file = OutputTargetFile {
+            prepend: None,
+            append: Some("\n".to_string()),
+            ..target_file.clone()
+        };
+
+        self.output(message, value.to_string().into_bytes(), &target_file)
+    }
+
+    /// Writes the content to a new file whose path is rendered from the path
+    /// of the target, replacing an existing file. Parent directories are
+    /// created. Prepend and append are not written, so the file contains
+    /// the payload as it is. The path may contain these placeholders:
+    ///
+    /// - `{{topic}}`: topic of the message, characters not allowed in file
+    ///   names, including `/`, are replaced by `_`
+    /// - `{{timestamp}}`: current time in milliseconds since the epoch
+    /// - `{{counter}}`: number of the file, starting at 1
+    /// - `{{extension}}`: file extension of the payload format, see
+    ///   [`file_extension`]
+    pub fn output_per_message(
+        &self,
+        topic: &str,
+        content: Vec<u8>,
+        extension: &str,
+        target_file: &OutputTargetFile,
+    ) -> Result<(), OutputError> {
+        let path = self.render_path(topic, extension, target_file);
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| OutputError::CouldNotOpenTargetFile(e, path.clone()))?;
+        }
+
+        let mut file = File::create(&path)
+            .map_err(|e| OutputError::CouldNotOpenTargetFile(e, path.clone()))?;
+        file.write_all(&content)
+            .map_err(|e| OutputError::ErrorWhileWritingToFile(e, path))
+    }
+
+    fn render_path(&self, topic: &str, extension: &str, target_file: &OutputTargetFile) -> PathBuf {
+        let path = target_file.path().to_string_lossy();
+        let mut counter: Option<u64> = None;
+
+        let path = template::render(&path, |name| match name {
+            "topic" => Some(sanitize_file_name(topic)),
+            "extension" => Some(extension.to_string()),
+            "counter" => {
+                let value = *counter.get_or_insert_with(|| {
+                    let mut counters = self.counters.lock().unwrap();
+                    let counter = counters.entry(target_file.path().clone()).or_default();
+                    *counter += 1;
+                    *counter
+                });
+                Some(value.to_string())
+            }
+            name => template::common(name),
+        });
+
+        PathBuf::from(path.into_owned())
+    }
+
+    /// Writes the buffered content of the files whose flush interval elapsed.
+    pub fn flush_due(&self) {
+        for (path, file) in self.open_files.lock().unwrap().iter_mut() {
+            if file.flushed_at.elapsed() >= file.flush_interval {
+                file.flush(path);
+            }
+        }
+    }
+
+    /// Writes the buffered content of all files, e.g. on exit.
+    pub fn flush_all(&self) {
+        for (path, file) in self.open_files.lock().unwrap().iter_mut() {
+            file.flush(path);
+        }
+    }
+
+    fn open(target_file: &OutputTargetFile) -> Result<File, OutputError> {
+        File::options()
             .append(!*target_file.overwrite())
             .truncate(*target_file.overwrite())
             .write(true)
             .create(true)
             .open(target_file.path())
-        {
-            Ok(mut file) => {
-                if target_file.prepend().is_some() {
-                    if let Err(e) =
-                        file.write_all(target_file.prepend().clone().unwrap().as_bytes())
-                    {
-                        return Err(OutputError::ErrorWhileWritingToFile(
-                            e,
-                            PathBuf::from(target_file.path()),
-                        ));
-                    }
-                }
-
-                if let Err(e) = file.write_all(content.as_slice()) {
-                    return Err(OutputError::ErrorWhileWritingToFile(
-                        e,
-                        PathBuf::from(target_file.path()),
-                    ));
-                }
-
-                if target_file.append().is_some() {
-                    if let Err(e) = file.write_all(target_file.append().clone().unwrap().as_bytes())
-                    {
-                        return Err(OutputError::ErrorWhileWritingToFile(
-                            e,
-                            PathBuf::from(target_file.path()),
-                        ));
-                    }
-                }
-
-                Ok(())
+            .map_err(|e| OutputError::CouldNotOpenTargetFile(e, PathBuf::from(target_file.path())))
+    }
+
+    fn write(
+        file: &mut impl Write,
+        message: &FileMessage,
+        content: &[u8],
+        target_file: &OutputTargetFile,
+    ) -> Result<(), OutputError> {
+        let prepend = target_file
+            .prepend()
+            .as_deref()
+            .map(|prepend| message.render(prepend, content.len()));
+        let append = target_file
+            .append()
+            .as_deref()
+            .map(|append| message.render(append, content.len()));
+
+        let parts = [
+            prepend.as_deref().map(str::as_bytes),
+            Some(content),
+            append.as_deref().map(str::as_bytes),
+        ];
+
+        for part in parts.into_iter().flatten() {
+            if let Err(e) = file.write_all(part) {
+                return Err(OutputError::ErrorWhileWritingToFile(
+                    e,
+                    PathBuf::from(target_file.path()),
+                ));
             }
-            Err(e) => Err(OutputError::CouldNotOpenTargetFile(
-                e,
-                PathBuf::from(target_file.path()),
-            )),
         }
+
+        Ok(())
+    }
+}
+
+/// File extension of the payload format, matching the extensions which
+/// are recognized when files are published.
+pub fn file_extension(payload: &PayloadFormat) -> &'static str {
+    match payload {
+        PayloadFormat::Text(_) | PayloadFormat::Hexdump(_) => "txt",
+        PayloadFormat::Json(_) | PayloadFormat::SparkplugJson(_) => "json",
+        PayloadFormat::Yaml(_) => "yaml",
+        PayloadFormat::Hex(_) => "hex",
+        PayloadFormat::Base64(_) => "base64",
+        PayloadFormat::Raw(_) | PayloadFormat::Protobuf(_) | PayloadFormat::Sparkplug(_) => "bin",
+    }
+}
+
+fn sanitize_file_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: FileMessage = FileMessage {
+        topic: "sensors/1",
+        qos: QoS::AtLeastOnce,
+        retain: false,
+    };
+
+    #[test]
+    fn render_placeholders() {
+        assert_eq!(
+            "sensors/1 1 false 7 {{unknown}}",
+            MESSAGE.render("{{topic}} {{qos}} {{retain}} {{size}} {{unknown}}", 7)
+        );
+        assert!(matches!(MESSAGE.render("\n", 7), Cow::Borrowed("\n")));
+
+        let timestamp = MESSAGE.render("{{timestamp_iso}}", 7);
+        assert!(chrono::DateTime::parse_from_rfc3339(&timestamp).is_ok());
+    }
+
+    #[test]
+    fn buffer_until_flushed() {
+        let path = std::env::temp_dir().join("mqtli_buffer_until_flushed.txt");
+        let _ = std::fs::remove_file(&path);
+
+        let target = OutputTargetFile {
+            path: path.clone(),
+            flush_interval: Duration::from_secs(3600),
+            ..OutputTargetFile::default()
+        };
+
+        let output = FileOutput::default();
+        output.output(&MESSAGE, b"a".to_vec(), &target).unwrap();
+        output.output(&MESSAGE, b"b".to_vec(), &target).unwrap();
+        output.flush_due();
+        assert_eq!("", std::fs::read_to_string(&path).unwrap());
+
+        output.flush_all();
+        assert_eq!("a\nb\n", std::fs::read_to_string(&path).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn json_lines() {
+        let path = std::env::temp_dir().join("mqtli_json_lines.ndjson");
+        let _ = std::fs::remove_file(&path);
+
+        let target = OutputTargetFile {
+            path: path.clone(),
+            prepend: Some("[".to_string()),
+            append: None,
+            ndjson: true,
+            ..OutputTargetFile::default()
+        };
+
+        let output = FileOutput::default();
+        output
+            .output_json_line(&MESSAGE, &serde_json::json!({"topic": "a"}), &target)
+            .unwrap();
+        output
+            .output_json_line(&MESSAGE, &serde_json::json!({"topic": "b"}), &target)
+            .unwrap();
+        output.flush_all();
+
+        assert_eq!(
+            "{\"topic\":\"a\"}\n{\"topic\":\"b\"}\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_per_message() {
+        let directory = std::env::temp_dir().join("mqtli_file_per_message");
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let target = OutputTargetFile {
+            path: directory.join("{{topic}}-{{counter}}.{{extension}}"),
+            per_message: true,
+            ..OutputTargetFile::default()
+        };
+
+        let output = FileOutput::default();
+        output
+            .output_per_message("camera/1", vec![0, 1], "bin", &target)
+            .unwrap();
+        output
+            .output_per_message("camera/1", vec![2], "bin", &target)
+            .unwrap();
+
+        assert_eq!(
+            vec![0, 1],
+            std::fs::read(directory.join("camera_1-1.bin")).unwrap()
+        );
+        assert_eq!(
+            vec![2],
+            std::fs::read(directory.join("camera_1-2.bin")).unwrap()
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
     }
 }
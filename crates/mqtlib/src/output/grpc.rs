@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{Buf, BufMut};
+use chrono::Utc;
+use jsonpath_rust::JsonPath;
+use protobuf::reflect::{RuntimeFieldType, RuntimeType};
+use protobuf_json_mapping::parse_dyn_from_str;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic::{Request, Status};
+use tracing::{debug, error, info, warn};
+
+use crate::config::subscription::{GrpcFieldSource, OutputTargetGrpc};
+use crate::mqtt::QoS;
+use crate::output::OutputError;
+use crate::payload::json::PayloadFormatJson;
+use crate::payload::protobuf::PayloadFormatProtobuf;
+use crate::payload::{PayloadFormat, PayloadFormatError};
+
+struct GrpcStream {
+    sender: mpsc::Sender<Vec<u8>>,
+    handle: JoinHandle<()>,
+}
+
+/// gRPC outputs of a run, see [`crate::output::Outputs`].
+#[derive(Default)]
+pub struct GrpcOutput {
+    /// Open streams by endpoint and method, shared by all outputs with the
+    /// same endpoint and method
+    streams: Mutex<HashMap<(String, String), GrpcStream>>,
+}
+
+impl GrpcOutput {
+    /// Builds the request from the message and queues it for the stream of
+    /// the target. The stream is opened with the first message and runs in
+    /// the background.
+    pub fn output(
+        &self,
+        topic: &str,
+        payload: PayloadFormat,
+        qos: QoS,
+        retain: bool,
+        target: &OutputTargetGrpc,
+    ) -> Result<(), OutputError> {
+        let request = encode_request(topic, payload, qos, retain, target)?;
+        let key = (target.endpoint.clone(), target.method.clone());
+
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams
+            .entry(key.clone())
+            .or_insert_with(|| Self::spawn(target.clone()));
+
+        match stream.sender.try_send(request) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(OutputError::GrpcQueueFull(target.endpoint.clone())),
+            Err(TrySendError::Closed(request)) => {
+                // the stream task ended unexpectedly, start it again
+                let stream = Self::spawn(target.clone());
+                let _ = stream.sender.try_send(request);
+                streams.insert(key, stream);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends the queued requests and closes all streams, e.g. on exit.
+    /// Requests which could not be sent within the timeout are dropped.
+    pub async fn close_all(&self, timeout: Duration) {
+        let streams: Vec<((String, String), GrpcStream)> =
+            self.streams.lock().unwrap().drain().collect();
+
+        for ((endpoint, method), stream) in streams {
+            let GrpcStream { sender, handle } = stream;
+            drop(sender);
+
+            if tokio::time::timeout(timeout, handle).await.is_err() {
+                warn!("Timeout elapsed while closing gRPC stream {endpoint}{method}");
+            }
+        }
+    }
+
+    fn spawn(target: OutputTargetGrpc) -> GrpcStream {
+        let (sender, receiver) = mpsc::channel(target.queue_size.max(1));
+        let handle = tokio::spawn(run(target, receiver));
+
+        GrpcStream { sender, handle }
+    }
+}
+
+/// Streams the queued requests until the queue is closed. The stream is
+/// opened again after it failed or the service closed it.
+async fn run(target: OutputTargetGrpc, receiver: mpsc::Receiver<Vec<u8>>) {
+    let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+    let closed = Arc::new(AtomicBool::new(false));
+
+    loop {
+        match stream(&target, receiver.clone(), closed.clone()).await {
+            Ok(()) if closed.load(Ordering::Relaxed) => return,
+            Ok(()) => warn!(
+                "gRPC stream {}{} closed by the service",
+                target.endpoint, target.method
+            ),
+            Err(e) => error!(
+                "gRPC stream {}{} failed: {e}",
+                target.endpoint, target.method
+            ),
+        }
+
+        if closed.load(Ordering::Relaxed) {
+            return;
+        }
+        tokio::time::sleep(target.reconnect_interval).await;
+    }
+}
+
+async fn stream(
+    target: &OutputTargetGrpc,
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<Vec<u8>>>>,
+    closed: Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut endpoint = Endpoint::from_shared(target.endpoint.clone())?;
+    if target.endpoint.starts_with("https://") {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+    }
+    let path = PathAndQuery::from_str(&target.method)?;
+
+    let mut client = tonic::client::Grpc::new(endpoint.connect().await?);
+    client.ready().await?;
+    info!("Opened gRPC stream {}{}", target.endpoint, target.method);
+
+    let requests = futures::stream::unfold(receiver, move |receiver| {
+        let closed = closed.clone();
+        async move {
+            let request = receiver.lock().await.recv().await;
+            if request.is_none() {
+                closed.store(true, Ordering::Relaxed);
+            }
+            request.map(|request| (request, receiver))
+        }
+    });
+
+    let response = client
+        .client_streaming(Request::new(requests), path, BytesCodec)
+        .await?;
+    debug!(
+        "gRPC stream {}{} finished with {} bytes of response",
+        target.endpoint,
+        target.method,
+        response.get_ref().len()
+    );
+
+    Ok(())
+}
+
+/// Builds the request message from the fields of the target and encodes it.
+/// Fields selected by a JSONPath which doesn't match are left unset.
+fn encode_request(
+    topic: &str,
+    payload: PayloadFormat,
+    qos: QoS,
+    retain: bool,
+    target: &OutputTargetGrpc,
+) -> Result<Vec<u8>, OutputError> {
+    let descriptor =
+        PayloadFormatProtobuf::get_message_descriptor(&target.definition, &target.message)?;
+
+    let mut request = Map::new();
+    for (name, source) in &target.fields {
+        let field = descriptor
+            .field_by_name(name)
+            .ok_or_else(|| OutputError::GrpcFieldNotFound(name.clone(), target.message.clone()))?;
+        let field_type = match field.runtime_field_type() {
+            RuntimeFieldType::Singular(field_type) | RuntimeFieldType::Repeated(field_type) => {
+                Some(field_type)
+            }
+            RuntimeFieldType::Map(_, _) => None,
+        };
+
+        let value = match source {
+            GrpcFieldSource::Topic => Value::from(topic),
+            GrpcFieldSource::Qos => Value::from(qos as u8),
+            GrpcFieldSource::Retain => Value::from(retain),
+            GrpcFieldSource::Time => match field_type {
+                Some(RuntimeType::I64 | RuntimeType::U64) => {
+                    Value::from(Utc::now().timestamp_millis())
+                }
+                _ => Value::from(Utc::now().to_rfc3339()),
+            },
+            GrpcFieldSource::Payload => match field_type {
+                Some(RuntimeType::VecU8) => {
+                    Value::from(STANDARD.encode(Vec::<u8>::try_from(payload.clone())?))
+                }
+                Some(RuntimeType::String) => {
+                    let content: String = payload.clone().try_into()?;
+                    Value::from(content)
+                }
+                _ => PayloadFormatJson::try_from(payload.clone())?
+                    .content()
+                    .clone(),
+            },
+            GrpcFieldSource::JsonPath(path) => {
+                let json = PayloadFormatJson::try_from(payload.clone())?;
+                let value = json
+                    .content()
+                    .query(path.as_str())
+                    .ok()
+                    .and_then(|values| values.first().map(|value| (*value).clone()));
+                match value {
+                    Some(value) => value,
+                    None => continue,
+                }
+            }
+        };
+
+        request.insert(name.clone(), value);
+    }
+
+    let message = parse_dyn_from_str(&descriptor, &Value::Object(request).to_string())
+        .map_err(PayloadFormatError::from)?;
+
+    Ok(message
+        .write_to_bytes_dyn()
+        .map_err(PayloadFormatError::from)?)
+}
+
+/// Passes the encoded messages through as they are, the requests are
+/// encoded with the dynamic message of the definition.
+#[derive(Clone, Copy, Debug, Default)]
+struct BytesCodec;
+
+impl Codec for BytesCodec {
+    type Encode = Vec<u8>;
+    type Decode = Vec<u8>;
+    type Encoder = BytesCodec;
+    type Decoder = BytesCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        BytesCodec
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        BytesCodec
+    }
+}
+
+impl Encoder for BytesCodec {
+    type Item = Vec<u8>;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        dst.put_slice(&item);
+        Ok(())
+    }
+}
+
+impl Decoder for BytesCodec {
+    type Item = Vec<u8>;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(Some(src.copy_to_bytes(src.remaining()).to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::text::PayloadFormatText;
+    use protobuf::reflect::ReflectValueBox;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn encode() {
+        let target = OutputTargetGrpc {
+            endpoint: "http://localhost:50051".to_string(),
+            method: "/ingest.Ingest/Publish".to_string(),
+            definition: PathBuf::from("test/data/ingest.proto"),
+            message: "IngestRequest".to_string(),
+            fields: BTreeMap::from([
+                ("topic".to_string(), GrpcFieldSource::Topic),
+                ("payload".to_string(), GrpcFieldSource::Payload),
+                ("qos".to_string(), GrpcFieldSource::Qos),
+                ("retain".to_string(), GrpcFieldSource::Retain),
+                ("received_at".to_string(), GrpcFieldSource::Time),
+                (
+                    "temperature".to_string(),
+                    GrpcFieldSource::JsonPath("$.temperature".to_string()),
+                ),
+            ]),
+            reconnect_interval: Duration::from_secs(5),
+            queue_size: 10,
+        };
+        let payload = PayloadFormat::Text(PayloadFormatText::from("{\"temperature\":21.5}"));
+
+        let encoded =
+            encode_request("sensors/1", payload, QoS::AtLeastOnce, true, &target).unwrap();
+
+        let descriptor =
+            PayloadFormatProtobuf::get_message_descriptor(&target.definition, &target.message)
+                .unwrap();
+        let message = descriptor.parse_from_bytes(&encoded).unwrap();
+        let field = |name: &str| {
+            descriptor
+                .field_by_name(name)
+                .unwrap()
+                .get_singular_field_or_default(&*message)
+                .to_box()
+        };
+
+        assert_eq!(ReflectValueBox::String("sensors/1".into()), field("topic"));
+        assert_eq!(
+            ReflectValueBox::Bytes(b"{\"temperature\":21.5}".to_vec()),
+            field("payload")
+        );
+        assert_eq!(ReflectValueBox::U32(1), field("qos"));
+        assert_eq!(ReflectValueBox::Bool(true), field("retain"));
+        assert_eq!(ReflectValueBox::F64(21.5), field("temperature"));
+        assert!(matches!(field("received_at"), ReflectValueBox::I64(millis) if millis > 0));
+    }
+
+    #[test]
+    fn unknown_field() {
+        let target = OutputTargetGrpc {
+            endpoint: "http://localhost:50051".to_string(),
+            method: "/ingest.Ingest/Publish".to_string(),
+            definition: PathBuf::from("test/data/ingest.proto"),
+            message: "IngestRequest".to_string(),
+            fields: BTreeMap::from([("unknown".to_string(), GrpcFieldSource::Topic)]),
+            reconnect_interval: Duration::from_secs(5),
+            queue_size: 10,
+        };
+
+        assert!(matches!(
+            encode_request(
+                "sensors/1",
+                PayloadFormat::Text(PayloadFormatText::from("")),
+                QoS::AtMostOnce,
+                false,
+                &target
+            ),
+            Err(OutputError::GrpcFieldNotFound(_, _))
+        ));
+    }
+}
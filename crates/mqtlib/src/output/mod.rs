@@ -1,14 +1,66 @@
 use std::io;
 use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::mqtt::MessageEvent;
+use crate::output::file::FileOutput;
+#[cfg(feature = "grpc")]
+use crate::output::grpc::GrpcOutput;
+use crate::output::pipe::PipeOutput;
+#[cfg(feature = "websocket")]
+use crate::output::websocket::WebSocketOutput;
 use crate::payload::PayloadFormatError;
 use crate::storage::SqlStorageError;
 use thiserror::Error;
 use tokio::sync::broadcast::error::SendError;
 
 pub mod console;
+pub mod diff;
+#[cfg(feature = "email")]
+pub mod email;
 pub mod file;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod pipe;
+pub mod sample;
+pub mod statistics;
+pub mod theme;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+/// Outputs which keep files, commands or connections open between messages.
+/// Each run has its own context, so runs in the same process don't share or
+/// close each other's resources.
+#[derive(Default)]
+pub struct OutputContext {
+    pub file: FileOutput,
+    pub pipe: PipeOutput,
+    #[cfg(feature = "websocket")]
+    pub websocket: WebSocketOutput,
+    #[cfg(feature = "grpc")]
+    pub grpc: GrpcOutput,
+}
+
+impl OutputContext {
+    /// Flushes the files and closes the pipe commands, WebSocket connections
+    /// and gRPC streams. Queued messages are sent until the deadline.
+    #[cfg_attr(
+        not(any(feature = "websocket", feature = "grpc")),
+        allow(unused_variables)
+    )]
+    pub async fn close(&self, deadline: Instant) {
+        self.file.flush_all();
+        self.pipe.close_all();
+        #[cfg(feature = "websocket")]
+        self.websocket
+            .close_all(deadline.saturating_duration_since(Instant::now()))
+            .await;
+        #[cfg(feature = "grpc")]
+        self.grpc
+            .close_all(deadline.saturating_duration_since(Instant::now()))
+            .await;
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum OutputError {
@@ -16,6 +68,12 @@ pub enum OutputError {
     CouldNotOpenTargetFile(#[source] io::Error, PathBuf),
     #[error("Error while writing to file \"{1}\"")]
     ErrorWhileWritingToFile(#[source] io::Error, PathBuf),
+    #[error("Could not start pipe command \"{1}\"")]
+    CouldNotStartPipeCommand(#[source] io::Error, String),
+    #[error("Error while writing to pipe command \"{1}\"")]
+    ErrorWhileWritingToPipe(#[source] io::Error, String),
+    #[error("Error while writing to the console")]
+    ErrorWhileWritingToConsole(#[source] io::Error),
     #[error("Error while formatting payload: {0}")]
     ErrorPayloadFormat(#[source] PayloadFormatError),
     #[error("Error while sending payload to topic: {0}")]
@@ -24,6 +82,26 @@ pub enum OutputError {
     SqlDatabaseNotInitialized,
     #[error("SQL Storage Error")]
     SqlStorageError(#[from] SqlStorageError),
+    #[cfg(feature = "email")]
+    #[error("Could not create email")]
+    CouldNotCreateEmail(#[source] lettre::error::Error),
+    #[cfg(feature = "email")]
+    #[error("Could not send email via \"{1}\"")]
+    CouldNotSendEmail(#[source] lettre::transport::smtp::Error, String),
+    #[cfg(feature = "websocket")]
+    #[error("Payload of text frame is not valid UTF-8")]
+    WebSocketFrameNotUtf8(#[source] std::string::FromUtf8Error),
+    #[cfg(feature = "websocket")]
+    #[error("Queue of WebSocket output \"{0}\" is full, message dropped")]
+    WebSocketQueueFull(String),
+    #[cfg(feature = "grpc")]
+    #[error("Field \"{0}\" not found in message \"{1}\"")]
+    GrpcFieldNotFound(String, String),
+    #[cfg(feature = "grpc")]
+    #[error("Queue of gRPC output \"{0}\" is full, message dropped")]
+    GrpcQueueFull(String),
+    #[error("Output topic \"{0}\" is empty or contains wildcards")]
+    InvalidOutputTopic(String),
 }
 
 impl From<PayloadFormatError> for OutputError {
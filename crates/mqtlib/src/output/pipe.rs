@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+
+use tracing::{debug, error};
+
+use crate::config::subscription::OutputTargetPipe;
+use crate::output::OutputError;
+
+struct PipeCommand {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+/// Pipe outputs of a run, see [`crate::output::Outputs`].
+#[derive(Default)]
+pub struct PipeOutput {
+    /// Running pipe commands by their command line, shared by all outputs
+    /// with the same command
+    pipes: Mutex<HashMap<String, PipeCommand>>,
+}
+
+impl PipeOutput {
+    /// Writes the content followed by the delimiter to the stdin of the
+    /// command. The command is started with the first message and started
+    /// again if it exited in the meantime.
+    pub fn output(&self, content: Vec<u8>, target: &OutputTargetPipe) -> Result<(), OutputError> {
+        let mut pipes = self.pipes.lock().unwrap();
+
+        if let Some(pipe) = pipes.get_mut(target.command()) {
+            if let Ok(Some(status)) = pipe.child.try_wait() {
+                error!(
+                    "Pipe command \"{}\" exited with {status}, starting it again",
+                    target.command()
+                );
+                pipes.remove(target.command());
+            }
+        }
+
+        if !pipes.contains_key(target.command()) {
+            pipes.insert(target.command().clone(), Self::spawn(target.command())?);
+        }
+
+        let pipe = pipes.get_mut(target.command()).unwrap();
+        let result = pipe
+            .stdin
+            .write_all(&content)
+            .and_then(|_| pipe.stdin.write_all(target.delimiter().as_bytes()))
+            .and_then(|_| pipe.stdin.flush());
+
+        if let Err(e) = result {
+            // the command is started again with the next message
+            pipes.remove(target.command());
+            return Err(OutputError::ErrorWhileWritingToPipe(
+                e,
+                target.command().clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Closes the stdin of all commands and waits for them to exit, e.g. on
+    /// exit, so that they can process the remaining messages.
+    pub fn close_all(&self) {
+        for (command, pipe) in self.pipes.lock().unwrap().drain() {
+            let PipeCommand { mut child, stdin } = pipe;
+            drop(stdin);
+
+            match child.wait() {
+                Ok(status) => debug!("Pipe command \"{command}\" exited with {status}"),
+                Err(e) => error!("Error while waiting for pipe command \"{command}\": {e:?}"),
+            }
+        }
+    }
+
+    fn spawn(command: &str) -> Result<PipeCommand, OutputError> {
+        debug!("Starting pipe command \"{command}\"");
+
+        let mut child = shell(command)
+            .spawn()
+            .map_err(|e| OutputError::CouldNotStartPipeCommand(e, command.to_string()))?;
+        let stdin = child.stdin.take().unwrap();
+
+        Ok(PipeCommand { child, stdin })
+    }
+}
+
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::subscription::PipeDelimiter;
+
+    #[cfg(not(windows))]
+    #[test]
+    fn stream_to_one_command() {
+        let path = std::env::temp_dir().join("mqtli_stream_to_one_command.txt");
+        let _ = std::fs::remove_file(&path);
+
+        let target = OutputTargetPipe {
+            command: format!("echo started >> {0}; cat >> {0}", path.display()),
+            delimiter: PipeDelimiter::Nul,
+        };
+
+        let output = PipeOutput::default();
+        output.output(b"a".to_vec(), &target).unwrap();
+        output.output(b"b".to_vec(), &target).unwrap();
+        output.close_all();
+
+        assert_eq!("started\na\0b\0", std::fs::read_to_string(&path).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+use crate::config::subscription::Sample;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Decides which messages are forwarded to outputs with a sample rate.
+/// Messages are counted per output and topic, so every nth message of each
+/// topic is forwarded.
+#[derive(Debug, Default)]
+pub struct OutputSampler {
+    counts: HashMap<(usize, String), u64>,
+}
+
+impl OutputSampler {
+    /// Returns true if the message is forwarded to the output. The first
+    /// message of each topic is always forwarded when sampling every nth
+    /// message.
+    pub fn accept(&mut self, output: usize, topic: &str, sample: &Sample) -> bool {
+        match sample {
+            Sample::Every(n) => {
+                let count = self.counts.entry((output, topic.to_string())).or_default();
+                let accepted = *count % n.max(&1) == 0;
+                *count += 1;
+                accepted
+            }
+            Sample::Probability(probability) => {
+                rand::thread_rng().gen_bool(probability.clamp(0.0, 1.0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_nth_message() {
+        let mut sampler = OutputSampler::default();
+        let sample = Sample::Every(3);
+
+        let accepted: Vec<bool> = (0..7).map(|_| sampler.accept(0, "a", &sample)).collect();
+
+        assert_eq!(vec![true, false, false, true, false, false, true], accepted);
+        assert!(sampler.accept(0, "b", &sample));
+        assert!(sampler.accept(1, "a", &sample));
+    }
+
+    #[test]
+    fn probability() {
+        let mut sampler = OutputSampler::default();
+
+        assert!(sampler.accept(0, "a", &Sample::Probability(1.0)));
+        assert!(!sampler.accept(0, "a", &Sample::Probability(0.0)));
+    }
+}
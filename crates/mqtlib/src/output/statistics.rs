@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Messages and bytes received on a topic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TopicStatistics {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// Messages and bytes received per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TopicRate {
+    pub messages: f64,
+    pub bytes: f64,
+}
+
+/// Counts the messages received on each topic since the start.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageStatistics {
+    pub started_at: DateTime<Utc>,
+    pub topics: BTreeMap<String, TopicStatistics>,
+}
+
+impl Default for MessageStatistics {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            topics: BTreeMap::new(),
+        }
+    }
+}
+
+impl MessageStatistics {
+    pub fn count_message(&mut self, topic: &str, size: usize) {
+        let statistics = self.topics.entry(topic.to_string()).or_default();
+        statistics.messages += 1;
+        statistics.bytes += size as u64;
+    }
+
+    /// Adds the statistics of the topics of other, e.g. of another
+    /// connection.
+    pub fn merge(&mut self, other: MessageStatistics) {
+        for (topic, statistics) in other.topics {
+            let total = self.topics.entry(topic).or_default();
+            total.messages += statistics.messages;
+            total.bytes += statistics.bytes;
+        }
+    }
+
+    /// Returns the sum of the statistics of all topics.
+    pub fn total(&self) -> TopicStatistics {
+        self.topics
+            .values()
+            .fold(TopicStatistics::default(), |total, statistics| {
+                TopicStatistics {
+                    messages: total.messages + statistics.messages,
+                    bytes: total.bytes + statistics.bytes,
+                }
+            })
+    }
+}
+
+impl TopicStatistics {
+    /// Returns the messages and bytes per second received since the previous
+    /// statistics, which were taken the given number of seconds before.
+    pub fn rate(&self, previous: Option<&TopicStatistics>, elapsed: f64) -> TopicRate {
+        if elapsed <= 0.0 {
+            return TopicRate::default();
+        }
+
+        let previous = previous.copied().unwrap_or_default();
+
+        TopicRate {
+            messages: self.messages.saturating_sub(previous.messages) as f64 / elapsed,
+            bytes: self.bytes.saturating_sub(previous.bytes) as f64 / elapsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_messages() {
+        let mut statistics = MessageStatistics::default();
+        statistics.count_message("a", 10);
+        statistics.count_message("b", 5);
+        statistics.count_message("a", 20);
+
+        assert_eq!(
+            Some(&TopicStatistics {
+                messages: 2,
+                bytes: 30
+            }),
+            statistics.topics.get("a")
+        );
+        assert_eq!(
+            TopicStatistics {
+                messages: 3,
+                bytes: 35
+            },
+            statistics.total()
+        );
+    }
+
+    #[test]
+    fn rate() {
+        let current = TopicStatistics {
+            messages: 30,
+            bytes: 3000,
+        };
+        let previous = TopicStatistics {
+            messages: 10,
+            bytes: 1000,
+        };
+
+        assert_eq!(
+            TopicRate {
+                messages: 2.0,
+                bytes: 200.0
+            },
+            current.rate(Some(&previous), 10.0)
+        );
+        assert_eq!(
+            TopicRate {
+                messages: 3.0,
+                bytes: 300.0
+            },
+            current.rate(None, 10.0)
+        );
+        assert_eq!(TopicRate::default(), current.rate(None, 0.0));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::mqtli_config::Theme;
+use colored::{ColoredString, Colorize};
+use std::sync::RwLock;
+
+static THEME: RwLock<Theme> = RwLock::new(Theme::Dark);
+
+/// Sets the theme used for all console output.
+pub fn set_theme(theme: Theme) {
+    *THEME.write().unwrap() = theme;
+}
+
+pub fn theme() -> Theme {
+    *THEME.read().unwrap()
+}
+
+/// Meaning of a part of the console output, which is colored according to
+/// the theme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// Topic in the header of a message
+    Topic,
+    /// Format, size and QoS in the header and properties of a message
+    Info,
+    /// Marker of retained messages
+    Retained,
+    /// Payload which is not highlighted
+    Payload,
+    /// Hint that there is nothing to print
+    Hint,
+    /// Keys of JSON and YAML payloads
+    Key,
+    /// Strings of JSON and YAML payloads
+    String,
+    /// Numbers of JSON and YAML payloads
+    Number,
+    /// Booleans and null of JSON and YAML payloads
+    Literal,
+    /// Added fields and successful results
+    Added,
+    /// Removed fields, failures and warnings
+    Removed,
+    /// Changed fields
+    Changed,
+    /// Regular text
+    Text,
+    /// Names of groups, metrics and counters
+    Name,
+    /// Group ids and references standing out of the text
+    Highlight,
+    /// Edge node ids and message types of Sparkplug messages
+    Accent,
+    /// Headline of a summary
+    Title,
+    /// Alert which must not be missed
+    Alert,
+    /// Headline of Sparkplug data and device messages
+    Banner,
+    /// Headline of Sparkplug birth and death messages of edge nodes
+    BannerNode,
+}
+
+pub trait Themed {
+    /// Colors the text for its role according to the current theme.
+    fn themed(self, role: Role) -> ColoredString;
+}
+
+impl<T: Colorize> Themed for T {
+    fn themed(self, role: Role) -> ColoredString {
+        style(theme(), role, self)
+    }
+}
+
+fn style<T: Colorize>(theme: Theme, role: Role, text: T) -> ColoredString {
+    match (theme, role) {
+        (Theme::None, _) => text.clear(),
+        (_, Role::Topic) => text.bold().green(),
+        (_, Role::Info) => text.blue(),
+        (_, Role::Retained) => text.purple(),
+        (Theme::Dark, Role::Payload) => text.yellow(),
+        (Theme::Light, Role::Payload) => text.normal(),
+        (_, Role::Hint) => text.dimmed(),
+        (Theme::Dark, Role::Key) => text.cyan(),
+        (Theme::Light, Role::Key) => text.blue(),
+        (_, Role::String | Role::Added | Role::Name) => text.green(),
+        (Theme::Dark, Role::Number) => text.yellow(),
+        (Theme::Light, Role::Number) => text.magenta(),
+        (Theme::Dark, Role::Literal) => text.magenta(),
+        (Theme::Light, Role::Literal) => text.red(),
+        (_, Role::Removed) => text.red(),
+        (Theme::Dark, Role::Changed | Role::Highlight) => text.yellow(),
+        (Theme::Light, Role::Changed | Role::Highlight) => text.blue(),
+        (Theme::Dark, Role::Text) => text.white(),
+        (Theme::Light, Role::Text) => text.black(),
+        (_, Role::Accent) => text.magenta(),
+        (_, Role::Title) => text.on_blue(),
+        (_, Role::Alert) => text.on_red(),
+        (_, Role::Banner) => text.black().on_cyan(),
+        (_, Role::BannerNode) => text.black().on_magenta(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colored::Color;
+
+    #[test]
+    fn style_of_theme() {
+        assert_eq!(
+            Some(Color::Yellow),
+            style(Theme::Dark, Role::Number, "1").fgcolor
+        );
+        assert_eq!(
+            Some(Color::Magenta),
+            style(Theme::Light, Role::Number, "1").fgcolor
+        );
+        assert_eq!(
+            Some(Color::Black),
+            style(Theme::Light, Role::Text, "text").fgcolor
+        );
+        assert_eq!(None, style(Theme::Light, Role::Payload, "payload").fgcolor);
+
+        let none = style(Theme::None, Role::Banner, "banner");
+        assert_eq!((None, None), (none.fgcolor, none.bgcolor));
+    }
+}
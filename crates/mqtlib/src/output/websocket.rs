@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, error, info, warn};
+
+use crate::config::subscription::{OutputTargetWebSocket, WebSocketFrame};
+use crate::output::OutputError;
+
+struct WebSocketConnection {
+    sender: mpsc::Sender<Message>,
+    handle: JoinHandle<()>,
+}
+
+type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// WebSocket outputs of a run, see [`crate::output::Outputs`].
+#[derive(Default)]
+pub struct WebSocketOutput {
+    /// Open connections by their URL, shared by all outputs with the same
+    /// URL
+    connections: Mutex<HashMap<String, WebSocketConnection>>,
+}
+
+impl WebSocketOutput {
+    /// Queues the content as frame for the endpoint of the target. The
+    /// connection is opened with the first message and runs in the
+    /// background, so a slow or unreachable endpoint doesn't block the
+    /// other outputs.
+    pub fn output(
+        &self,
+        content: Vec<u8>,
+        target: &OutputTargetWebSocket,
+    ) -> Result<(), OutputError> {
+        let message = match target.frame {
+            WebSocketFrame::Text => Message::Text(
+                String::from_utf8(content)
+                    .map_err(OutputError::WebSocketFrameNotUtf8)?
+                    .into(),
+            ),
+            WebSocketFrame::Binary => Message::Binary(content.into()),
+        };
+
+        let mut connections = self.connections.lock().unwrap();
+        let connection = connections
+            .entry(target.url.clone())
+            .or_insert_with(|| Self::spawn(target.clone()));
+
+        match connection.sender.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(OutputError::WebSocketQueueFull(target.url.clone())),
+            Err(TrySendError::Closed(message)) => {
+                // the connection task ended unexpectedly, start it again
+                let connection = Self::spawn(target.clone());
+                let _ = connection.sender.try_send(message);
+                connections.insert(target.url.clone(), connection);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends the queued messages and closes all connections, e.g. on exit.
+    /// Messages which could not be sent within the timeout are dropped.
+    pub async fn close_all(&self, timeout: Duration) {
+        let connections: Vec<(String, WebSocketConnection)> =
+            self.connections.lock().unwrap().drain().collect();
+
+        for (url, connection) in connections {
+            let WebSocketConnection { sender, handle } = connection;
+            drop(sender);
+
+            if tokio::time::timeout(timeout, handle).await.is_err() {
+                warn!("Timeout elapsed while closing WebSocket connection to {url}");
+            }
+        }
+    }
+
+    fn spawn(target: OutputTargetWebSocket) -> WebSocketConnection {
+        let (sender, receiver) = mpsc::channel(target.queue_size.max(1));
+        let handle = tokio::spawn(run(target, receiver));
+
+        WebSocketConnection { sender, handle }
+    }
+}
+
+/// Connects to the endpoint and sends the queued messages until the queue
+/// is closed. The connection is opened again after it was lost; the message
+/// which failed to be sent is sent first on the new connection.
+async fn run(target: OutputTargetWebSocket, mut receiver: mpsc::Receiver<Message>) {
+    let mut pending: Option<Message> = None;
+
+    loop {
+        match connect(&target).await {
+            Ok(mut stream) => {
+                info!("Connected to WebSocket {}", target.url);
+
+                if send(&mut stream, &mut receiver, &mut pending, &target.url).await {
+                    if let Err(e) = stream.close(None).await {
+                        debug!("Error while closing WebSocket {}: {e:?}", target.url);
+                    }
+                    return;
+                }
+            }
+            Err(e) => error!("Could not connect to WebSocket {}: {e}", target.url),
+        }
+
+        select! {
+            _ = tokio::time::sleep(target.reconnect_interval) => {},
+            // don't wait for an unreachable endpoint on exit
+            _ = receiver_closed(&receiver, &pending) => return,
+        }
+    }
+}
+
+/// Sends messages until the connection is lost or the queue is closed.
+/// Returns true if the queue was closed.
+async fn send(
+    stream: &mut Stream,
+    receiver: &mut mpsc::Receiver<Message>,
+    pending: &mut Option<Message>,
+    url: &str,
+) -> bool {
+    loop {
+        let message = match pending.take() {
+            Some(message) => message,
+            None => select! {
+                message = receiver.recv() => match message {
+                    Some(message) => message,
+                    None => return true,
+                },
+                // read incoming frames to answer pings and notice a close
+                incoming = stream.next() => match incoming {
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        error!("WebSocket connection to {url} lost: {e}");
+                        return false;
+                    }
+                    None => {
+                        error!("WebSocket connection to {url} closed by the endpoint");
+                        return false;
+                    }
+                },
+            },
+        };
+
+        if let Err(e) = stream.send(message.clone()).await {
+            error!("Could not send message to WebSocket {url}: {e}");
+            *pending = Some(message);
+            return false;
+        }
+    }
+}
+
+/// Resolves once the queue is closed and no message is left to send.
+async fn receiver_closed(receiver: &mpsc::Receiver<Message>, pending: &Option<Message>) {
+    if pending.is_none() && receiver.is_closed() && receiver.is_empty() {
+        return;
+    }
+
+    std::future::pending::<()>().await
+}
+
+async fn connect(target: &OutputTargetWebSocket) -> Result<Stream, Error> {
+    let mut request = target.url.as_str().into_client_request()?;
+    for (name, value) in &target.headers {
+        let name =
+            HeaderName::from_bytes(name.as_bytes()).map_err(|e| Error::HttpFormat(e.into()))?;
+        let value = HeaderValue::from_str(value).map_err(|e| Error::HttpFormat(e.into()))?;
+        request.headers_mut().insert(name, value);
+    }
+
+    let (stream, _) = tokio_tungstenite::connect_async(request).await?;
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn send_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+            let mut received = vec![];
+            while let Some(Ok(message)) = stream.next().await {
+                match message {
+                    Message::Text(_) | Message::Binary(_) => received.push(message),
+                    _ => break,
+                }
+            }
+            received
+        });
+
+        let mut target = OutputTargetWebSocket {
+            url: url.clone(),
+            frame: WebSocketFrame::Text,
+            headers: BTreeMap::new(),
+            reconnect_interval: Duration::from_millis(100),
+            queue_size: 10,
+        };
+        let output = WebSocketOutput::default();
+        output.output(b"first".to_vec(), &target).unwrap();
+        target.frame = WebSocketFrame::Binary;
+        output.output(vec![0, 1, 2], &target).unwrap();
+        output.close_all(Duration::from_secs(5)).await;
+
+        let received = server.await.unwrap();
+        assert_eq!(2, received.len());
+        assert_eq!(Message::Text("first".into()), received[0]);
+        assert_eq!(Message::Binary(vec![0, 1, 2].into()), received[1]);
+    }
+}
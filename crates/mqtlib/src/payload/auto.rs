@@ -0,0 +1,249 @@
+use crate::payload::hex::PayloadFormatHex;
+use crate::payload::json::PayloadFormatJson;
+use crate::payload::text::PayloadFormatText;
+use crate::payload::yaml::PayloadFormatYaml;
+use crate::payload::{PayloadFormat, PayloadFormatError};
+use serde_json::{Map, Value};
+use tracing::debug;
+
+/// Maximum nesting of protobuf messages decoded without a definition.
+const MAX_PROTOBUF_DEPTH: usize = 16;
+
+/// Sniffs the content of a payload and picks a format for it: JSON objects
+/// and arrays, printable text, protobuf messages decoded without a
+/// definition, and hex for everything else.
+pub fn detect(content: Vec<u8>) -> Result<PayloadFormat, PayloadFormatError> {
+    if let Ok(value) = serde_json::from_slice::<Value>(&content) {
+        if value.is_object() || value.is_array() {
+            return Ok(PayloadFormat::Json(PayloadFormatJson::from(value)));
+        }
+    }
+
+    if content.is_empty() || is_printable(&content) {
+        return Ok(PayloadFormat::Text(PayloadFormatText::from(content)));
+    }
+
+    if let Some(value) = decode_protobuf(&content, 0) {
+        return Ok(PayloadFormat::Json(PayloadFormatJson::from(value)));
+    }
+
+    Ok(PayloadFormat::Hex(PayloadFormatHex::try_from(
+        PayloadFormatHex::encode_to_hex(&content),
+    )?))
+}
+
+/// Picks the format by the MIME content type of the message, e.g. given in
+/// the content type property of MQTT v5. Payloads without content type, with
+/// an unknown one or which can't be read as the given type are detected by
+/// their content.
+pub fn detect_with_content_type(
+    content_type: Option<&str>,
+    content: Vec<u8>,
+) -> Result<PayloadFormat, PayloadFormatError> {
+    let Some(content_type) = content_type else {
+        return detect(content);
+    };
+
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let result = match mime.as_str() {
+        "application/json" => PayloadFormatJson::try_from(content.clone()).map(PayloadFormat::Json),
+        mime if mime.ends_with("+json") => {
+            PayloadFormatJson::try_from(content.clone()).map(PayloadFormat::Json)
+        }
+        "application/yaml" | "application/x-yaml" | "text/yaml" => {
+            PayloadFormatYaml::try_from(content.clone()).map(PayloadFormat::Yaml)
+        }
+        "application/protobuf" | "application/x-protobuf" | "application/vnd.google.protobuf" => {
+            match decode_protobuf(&content, 0) {
+                Some(value) => Ok(PayloadFormat::Json(PayloadFormatJson::from(value))),
+                None => Err(PayloadFormatError::InvalidProtobuf),
+            }
+        }
+        "application/octet-stream" => {
+            PayloadFormatHex::try_from(PayloadFormatHex::encode_to_hex(&content))
+                .map(PayloadFormat::Hex)
+        }
+        mime if mime.starts_with("text/") => Ok(PayloadFormat::Text(PayloadFormatText::from(
+            content.clone(),
+        ))),
+        _ => return detect(content),
+    };
+
+    result.or_else(|e| {
+        debug!("Payload doesn't match its content type {content_type}, detecting the format: {e}");
+        detect(content)
+    })
+}
+
+/// Returns true if the content is UTF-8 without control characters other
+/// than line breaks and tabs.
+fn is_printable(content: &[u8]) -> bool {
+    std::str::from_utf8(content).is_ok_and(|text| {
+        text.chars()
+            .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+    })
+}
+
+/// Decodes the protobuf wire format without a definition into a JSON object
+/// keyed by field number. Returns None if the content isn't a complete
+/// message. Fields occurring more than once are collected in an array.
+fn decode_protobuf(content: &[u8], depth: usize) -> Option<Value> {
+    if content.is_empty() || depth > MAX_PROTOBUF_DEPTH {
+        return None;
+    }
+
+    let mut fields = Map::new();
+    let mut position = 0;
+
+    while position < content.len() {
+        let key = read_varint(content, &mut position)?;
+        let number = key >> 3;
+        if number == 0 || number > 536_870_911 {
+            return None;
+        }
+
+        let value = match key & 0x7 {
+            0 => Value::from(read_varint(content, &mut position)?),
+            1 => Value::from(u64::from_le_bytes(
+                read_bytes(content, &mut position, 8)?.try_into().ok()?,
+            )),
+            2 => {
+                let length = usize::try_from(read_varint(content, &mut position)?).ok()?;
+                let bytes = read_bytes(content, &mut position, length)?;
+
+                if is_printable(bytes) {
+                    Value::from(String::from_utf8_lossy(bytes).to_string())
+                } else if let Some(message) = decode_protobuf(bytes, depth + 1) {
+                    message
+                } else {
+                    Value::from(hex::encode(bytes))
+                }
+            }
+            5 => Value::from(u32::from_le_bytes(
+                read_bytes(content, &mut position, 4)?.try_into().ok()?,
+            )),
+            // groups are deprecated and other wire types are invalid
+            _ => return None,
+        };
+
+        match fields.get_mut(&number.to_string()) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                fields.insert(number.to_string(), value);
+            }
+        }
+    }
+
+    Some(Value::Object(fields))
+}
+
+fn read_varint(content: &[u8], position: &mut usize) -> Option<u64> {
+    let mut result = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *content.get(*position)?;
+        *position += 1;
+        result |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Some(result);
+        }
+    }
+
+    None
+}
+
+fn read_bytes<'a>(content: &'a [u8], position: &mut usize, length: usize) -> Option<&'a [u8]> {
+    let end = position.checked_add(length)?;
+    let bytes = content.get(*position..end)?;
+    *position = end;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn detected(content: &[u8]) -> PayloadFormat {
+        detect(content.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn json_and_text() {
+        let PayloadFormat::Json(json) = detected(b"{\"temperature\": 21.5}") else {
+            panic!("expected json");
+        };
+        assert_eq!(&json!({"temperature": 21.5}), json.content());
+
+        assert!(matches!(detected(b"21.5"), PayloadFormat::Text(_)));
+        assert!(matches!(detected(b"ONLINE\n"), PayloadFormat::Text(_)));
+        assert!(matches!(detected(b""), PayloadFormat::Text(_)));
+    }
+
+    #[test]
+    fn protobuf() {
+        // field 1: varint 150, field 2: "abc", field 3: nested message with
+        // field 1: varint 1, field 4 twice: varint 7 and 8
+        let content = [
+            0x08, 0x96, 0x01, 0x12, 0x03, b'a', b'b', b'c', 0x1a, 0x02, 0x08, 0x01, 0x20, 0x07,
+            0x20, 0x08,
+        ];
+
+        let PayloadFormat::Json(json) = detected(&content) else {
+            panic!("expected decoded protobuf");
+        };
+        assert_eq!(
+            &json!({"1": 150, "2": "abc", "3": {"1": 1}, "4": [7, 8]}),
+            json.content()
+        );
+    }
+
+    #[test]
+    fn content_type() {
+        let with_type = |content_type: &str, content: &[u8]| {
+            detect_with_content_type(Some(content_type), content.to_vec()).unwrap()
+        };
+
+        assert!(matches!(
+            with_type("application/json; charset=utf-8", b"42"),
+            PayloadFormat::Json(_)
+        ));
+        assert!(matches!(
+            with_type("application/yaml", b"a: 1"),
+            PayloadFormat::Yaml(_)
+        ));
+        assert!(matches!(
+            with_type("application/octet-stream", b"abc"),
+            PayloadFormat::Hex(_)
+        ));
+        assert!(matches!(
+            with_type("application/x-protobuf", &[0x08, 0x01]),
+            PayloadFormat::Json(_)
+        ));
+        // invalid content and unknown types are detected by their content
+        assert!(matches!(
+            with_type("application/json", b"not json"),
+            PayloadFormat::Text(_)
+        ));
+        assert!(matches!(
+            with_type("image/png", b"{\"a\": 1}"),
+            PayloadFormat::Json(_)
+        ));
+    }
+
+    #[test]
+    fn binary_falls_back_to_hex() {
+        let PayloadFormat::Hex(hex) = detected(&[0xff, 0x00, 0x07]) else {
+            panic!("expected hex");
+        };
+        assert_eq!("ff0007", hex.to_string());
+    }
+}
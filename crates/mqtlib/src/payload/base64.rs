@@ -1,10 +1,24 @@
 use std::fmt::{Display, Formatter};
 
-use base64::engine::general_purpose;
-use base64::Engine;
+use base64::engine::general_purpose::GeneralPurpose;
+use base64::engine::{general_purpose, DecodePaddingMode, GeneralPurposeConfig};
+use base64::{alphabet, DecodeError, Engine};
 
+use crate::config::{Base64Variant, PayloadBase64};
 use crate::payload::{PayloadFormat, PayloadFormatError};
 
+/// Decodes the standard alphabet, with or without padding
+const STANDARD_DECODER: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Decodes the URL safe alphabet, with or without padding
+const URL_SAFE_DECODER: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
 #[derive(Clone, Debug)]
 pub struct PayloadFormatBase64 {
     content: String,
@@ -12,15 +26,55 @@ pub struct PayloadFormatBase64 {
 
 impl PayloadFormatBase64 {
     pub fn decode_from_base64(self) -> Result<Vec<u8>, PayloadFormatError> {
-        Ok(general_purpose::STANDARD.decode(self.content)?)
+        Ok(Self::decode(&self.content)?)
     }
 
-    fn encode_to_base64(value: &Vec<u8>) -> String {
-        general_purpose::STANDARD.encode(value)
+    fn encode_to_base64(value: &[u8], variant: Base64Variant) -> String {
+        match variant {
+            Base64Variant::Standard => general_purpose::STANDARD.encode(value),
+            Base64Variant::StandardNoPad => general_purpose::STANDARD_NO_PAD.encode(value),
+            Base64Variant::UrlSafe => general_purpose::URL_SAFE.encode(value),
+            Base64Variant::UrlSafeNoPad => general_purpose::URL_SAFE_NO_PAD.encode(value),
+        }
+    }
+
+    /// Detects the variant: the alphabets only differ in the characters
+    /// for 62 and 63, which are `-` and `_` in the URL safe alphabet.
+    /// Padding is optional for both.
+    fn decode(value: &str) -> Result<Vec<u8>, DecodeError> {
+        if value.contains(['-', '_']) {
+            URL_SAFE_DECODER.decode(value)
+        } else {
+            STANDARD_DECODER.decode(value)
+        }
     }
 
-    fn is_valid_base64(value: &String) -> bool {
-        general_purpose::STANDARD.decode(value).is_ok()
+    fn is_valid_base64(value: &str) -> bool {
+        Self::decode(value).is_ok()
+    }
+
+    /// Encodes the payload using the given variant. Base64 payloads are
+    /// decoded first, so that they are converted to the variant.
+    fn encode_payload(
+        value: PayloadFormat,
+        variant: Base64Variant,
+    ) -> Result<Self, PayloadFormatError> {
+        let decoded = match value {
+            PayloadFormat::Text(value) => Vec::<u8>::from(value),
+            PayloadFormat::Raw(value) => Vec::<u8>::from(value),
+            PayloadFormat::Protobuf(value) => Vec::<u8>::try_from(value)?,
+            PayloadFormat::Hexdump(value) => value.decode(),
+            PayloadFormat::Base64(value) => value.decode_from_base64()?,
+            PayloadFormat::Hex(value) => value.decode_from_hex()?,
+            PayloadFormat::Json(value) => Vec::<u8>::from(value),
+            PayloadFormat::Yaml(value) => Vec::<u8>::try_from(value)?,
+            PayloadFormat::Sparkplug(value) => Vec::<u8>::try_from(value)?,
+            PayloadFormat::SparkplugJson(value) => Vec::<u8>::from(value),
+        };
+
+        Ok(Self {
+            content: Self::encode_to_base64(&decoded, variant),
+        })
     }
 }
 
@@ -78,40 +132,28 @@ impl From<PayloadFormatBase64> for String {
     }
 }
 
+/// Encodes the payload with the standard alphabet and padding. Base64
+/// payloads are kept as they are.
 impl TryFrom<PayloadFormat> for PayloadFormatBase64 {
     type Error = PayloadFormatError;
 
     fn try_from(value: PayloadFormat) -> Result<Self, Self::Error> {
         match value {
-            PayloadFormat::Text(value) => Self::try_from(PayloadFormatBase64::encode_to_base64(
-                &Vec::<u8>::from(value),
-            )),
-            PayloadFormat::Raw(value) => Self::try_from(PayloadFormatBase64::encode_to_base64(
-                &Vec::<u8>::from(value),
-            )),
-            PayloadFormat::Protobuf(value) => Self::try_from(
-                PayloadFormatBase64::encode_to_base64(&Vec::<u8>::try_from(value)?),
-            ),
             PayloadFormat::Base64(value) => Ok(value),
-            PayloadFormat::Hex(value) => Self::try_from(PayloadFormatBase64::encode_to_base64(
-                &value.decode_from_hex()?,
-            )),
-            PayloadFormat::Json(value) => Self::try_from(PayloadFormatBase64::encode_to_base64(
-                &Vec::<u8>::from(value),
-            )),
-            PayloadFormat::Yaml(value) => Self::try_from(PayloadFormatBase64::encode_to_base64(
-                &Vec::<u8>::try_from(value)?,
-            )),
-            PayloadFormat::Sparkplug(value) => Self::try_from(
-                PayloadFormatBase64::encode_to_base64(&Vec::<u8>::try_from(value)?),
-            ),
-            PayloadFormat::SparkplugJson(value) => Self::try_from(
-                PayloadFormatBase64::encode_to_base64(&Vec::<u8>::from(value)),
-            ),
+            value => Self::encode_payload(value, Base64Variant::default()),
         }
     }
 }
 
+/// Encodes the payload with the variant configured for the payload type.
+impl TryFrom<(PayloadFormat, &PayloadBase64)> for PayloadFormatBase64 {
+    type Error = PayloadFormatError;
+
+    fn try_from((value, options): (PayloadFormat, &PayloadBase64)) -> Result<Self, Self::Error> {
+        Self::encode_payload(value, *options.variant())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::payload::hex::PayloadFormatHex;
@@ -162,6 +204,39 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn from_url_safe_without_padding() {
+        let result = PayloadFormatBase64::try_from("-_8".to_string()).unwrap();
+
+        assert_eq!(vec![0xfb, 0xff], result.decode_from_base64().unwrap());
+    }
+
+    #[test]
+    fn from_standard_without_padding() {
+        let result = PayloadFormatBase64::try_from("+/8".to_string()).unwrap();
+
+        assert_eq!(vec![0xfb, 0xff], result.decode_from_base64().unwrap());
+    }
+
+    #[test]
+    fn to_variant() {
+        let input = PayloadFormatBase64::try_from("+/8=".to_string()).unwrap();
+
+        let results = [
+            (Base64Variant::Standard, "+/8="),
+            (Base64Variant::StandardNoPad, "+/8"),
+            (Base64Variant::UrlSafe, "-_8="),
+            (Base64Variant::UrlSafeNoPad, "-_8"),
+        ];
+        for (variant, expected) in results {
+            let result =
+                PayloadFormatBase64::encode_payload(PayloadFormat::Base64(input.clone()), variant)
+                    .unwrap();
+
+            assert_eq!(expected, result.content);
+        }
+    }
+
     #[test]
     fn to_vec_u8_into() {
         let input = PayloadFormatBase64::try_from(get_input_base64_encoded_as_string()).unwrap();
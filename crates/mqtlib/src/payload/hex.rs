@@ -93,6 +93,9 @@ impl TryFrom<PayloadFormat> for PayloadFormatHex {
                 &Vec::<u8>::try_from(value)?,
             )),
             PayloadFormat::Hex(value) => Ok(value),
+            PayloadFormat::Hexdump(value) => {
+                Self::try_from(PayloadFormatHex::encode_to_hex(&value.decode()))
+            }
             PayloadFormat::Base64(value) => Self::try_from(PayloadFormatHex::encode_to_hex(
                 &value.decode_from_base64()?,
             )),
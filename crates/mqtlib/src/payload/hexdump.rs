@@ -0,0 +1,151 @@
+use std::fmt::{Display, Formatter};
+
+use crate::payload::{PayloadFormat, PayloadFormatError};
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Renders the bytes of the content as classic hexdump with the offset, the
+/// hex encoded bytes and their printable ASCII characters in each line:
+///
+/// ```text
+/// 00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a        |Hello, world!.|
+/// 0000000e
+/// ```
+///
+/// The hexdump is meant for output only. Converting it to other formats
+/// yields the original bytes.
+#[derive(Clone, Debug)]
+pub struct PayloadFormatHexdump {
+    content: Vec<u8>,
+}
+
+impl PayloadFormatHexdump {
+    /// Returns the original bytes of the content.
+    pub fn decode(self) -> Vec<u8> {
+        self.content
+    }
+
+    fn encode_to_hexdump(value: &[u8]) -> String {
+        let mut result = String::new();
+
+        for (line, chunk) in value.chunks(BYTES_PER_LINE).enumerate() {
+            let mut hex = String::with_capacity(3 * BYTES_PER_LINE + 1);
+            for (i, byte) in chunk.iter().enumerate() {
+                if i == BYTES_PER_LINE / 2 {
+                    hex.push(' ');
+                }
+                hex.push_str(format!("{byte:02x} ").as_str());
+            }
+
+            let ascii: String = chunk
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            result.push_str(
+                format!(
+                    "{:08x}  {hex:<width$} |{ascii}|\n",
+                    line * BYTES_PER_LINE,
+                    width = 3 * BYTES_PER_LINE + 1
+                )
+                .as_str(),
+            );
+        }
+
+        result.push_str(format!("{:08x}", value.len()).as_str());
+        result
+    }
+}
+
+/// Displays the hexdump of the content.
+impl Display for PayloadFormatHexdump {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::encode_to_hexdump(&self.content))
+    }
+}
+
+/// Creates a hexdump of the given bytes.
+impl From<Vec<u8>> for PayloadFormatHexdump {
+    fn from(value: Vec<u8>) -> Self {
+        Self { content: value }
+    }
+}
+
+/// Returns the rendered hexdump as bytes.
+impl From<PayloadFormatHexdump> for Vec<u8> {
+    fn from(val: PayloadFormatHexdump) -> Self {
+        val.to_string().into_bytes()
+    }
+}
+
+/// Returns the rendered hexdump.
+impl From<PayloadFormatHexdump> for String {
+    fn from(val: PayloadFormatHexdump) -> Self {
+        val.to_string()
+    }
+}
+
+impl TryFrom<PayloadFormat> for PayloadFormatHexdump {
+    type Error = PayloadFormatError;
+
+    fn try_from(value: PayloadFormat) -> Result<Self, Self::Error> {
+        Ok(match value {
+            PayloadFormat::Text(value) => Self::from(Vec::<u8>::from(value)),
+            PayloadFormat::Raw(value) => Self::from(Vec::<u8>::from(value)),
+            PayloadFormat::Protobuf(value) => Self::from(Vec::<u8>::try_from(value)?),
+            PayloadFormat::Hex(value) => Self::from(value.decode_from_hex()?),
+            PayloadFormat::Hexdump(value) => value,
+            PayloadFormat::Base64(value) => Self::from(value.decode_from_base64()?),
+            PayloadFormat::Json(value) => Self::from(Vec::<u8>::from(value)),
+            PayloadFormat::Yaml(value) => Self::from(Vec::<u8>::try_from(value)?),
+            PayloadFormat::Sparkplug(value) => Self::from(Vec::<u8>::try_from(value)?),
+            PayloadFormat::SparkplugJson(value) => Self::from(Vec::<u8>::from(value)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::hex::PayloadFormatHex;
+    use crate::payload::raw::PayloadFormatRaw;
+
+    #[test]
+    fn to_string() {
+        let input = PayloadFormatHexdump::from(b"Hello, world!\nThis is MQTli".to_vec());
+
+        assert_eq!(
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 54 68  |Hello, world!.Th|\n\
+             00000010  69 73 20 69 73 20 4d 51  54 6c 69                 |is is MQTli|\n\
+             0000001b",
+            input.to_string()
+        );
+    }
+
+    #[test]
+    fn to_string_empty() {
+        assert_eq!("00000000", PayloadFormatHexdump::from(vec![]).to_string());
+    }
+
+    #[test]
+    fn from_hex() {
+        let input = PayloadFormatHex::try_from(String::from("00ff")).unwrap();
+        let result = PayloadFormatHexdump::try_from(PayloadFormat::Hex(input)).unwrap();
+
+        assert_eq!(vec![0x00, 0xff], result.decode());
+    }
+
+    #[test]
+    fn to_raw() {
+        let input = PayloadFormatHexdump::from(vec![0x01, 0x02]);
+        let result = PayloadFormatRaw::try_from(PayloadFormat::Hexdump(input)).unwrap();
+
+        assert_eq!(vec![0x01, 0x02], Vec::<u8>::from(result));
+    }
+}
@@ -1,5 +1,4 @@
 use std::fmt::{Display, Formatter};
-use std::ops::Deref;
 
 use crate::payload::{PayloadFormat, PayloadFormatError};
 use derive_getters::Getters;
@@ -11,16 +10,43 @@ use serde_json::{from_slice, Value};
 #[derive(Clone, Debug, Default, Getters)]
 pub struct PayloadFormatJson {
     content: Value,
+    /// Indent the JSON when it is converted to a string
+    pretty: bool,
 }
 
 impl PayloadFormatJson {
+    /// Sets whether the JSON is indented or written on a single line.
+    pub fn with_pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
     fn decode_from_json_payload(&self) -> String {
-        self.content.to_string()
+        if self.pretty {
+            format!("{:#}", self.content)
+        } else {
+            self.content.to_string()
+        }
     }
 
     fn encode_to_json(value: Vec<u8>) -> serde_json::Result<Value> {
         from_slice(value.as_slice())
     }
+
+    /// Parses hand-written JSON, which may also be JSON5 or JSONC with
+    /// comments, trailing commas, unquoted keys and single quoted strings.
+    /// If it can't be parsed either way, the error of the strict JSON
+    /// parser is returned.
+    pub fn from_relaxed(value: Vec<u8>) -> Result<Self, PayloadFormatError> {
+        match from_slice::<Value>(value.as_slice()) {
+            Ok(content) => Ok(Self::from(content)),
+            Err(e) => std::str::from_utf8(value.as_slice())
+                .ok()
+                .and_then(|value| json5::from_str::<Value>(value).ok())
+                .map(Self::from)
+                .ok_or_else(|| e.into()),
+        }
+    }
 }
 
 /// Displays the json encoded content.
@@ -30,6 +56,7 @@ impl Display for PayloadFormatJson {
             Value::String(content) => {
                 write!(f, "{}", content)
             }
+            content if self.pretty => write!(f, "{:#}", content),
             content => write!(f, "{}", content),
         }
     }
@@ -53,9 +80,7 @@ impl TryFrom<Vec<u8>> for PayloadFormatJson {
     type Error = PayloadFormatError;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        Ok(Self {
-            content: Self::encode_to_json(value)?,
-        })
+        Ok(Self::from(Self::encode_to_json(value)?))
     }
 }
 
@@ -95,7 +120,10 @@ impl TryFrom<String> for PayloadFormatJson {
 /// ```
 impl From<Value> for PayloadFormatJson {
     fn from(val: Value) -> Self {
-        Self { content: val }
+        Self {
+            content: val,
+            pretty: false,
+        }
     }
 }
 
@@ -153,10 +181,9 @@ impl TryFrom<PayloadFormat> for PayloadFormatJson {
         match value {
             PayloadFormat::Text(value) => Self::try_from(String::from(value)),
             PayloadFormat::Raw(value) => Self::try_from(Vec::<u8>::from(value)),
-            PayloadFormat::Protobuf(value) => {
-                Self::try_from(print_protobuf_to_json_string(value.content().deref())?)
-            }
+            PayloadFormat::Protobuf(value) => Ok(Self::from(value.to_json_value()?)),
             PayloadFormat::Hex(value) => Self::try_from(value.decode_from_hex()?),
+            PayloadFormat::Hexdump(value) => Self::try_from(value.decode()),
             PayloadFormat::Base64(value) => Self::try_from(value.decode_from_base64()?),
             PayloadFormat::Json(value) => Ok(value),
             PayloadFormat::Yaml(value) => Ok(Self::from(serde_yaml::from_value::<Value>(
@@ -209,6 +236,20 @@ mod tests {
         from_str(get_input_json_string(value).as_str()).unwrap()
     }
 
+    #[test]
+    fn from_relaxed() {
+        let input = "// sensor reading\n{content: 'INPUT', /* unit */ values: [1, 2,],}";
+
+        let result = PayloadFormatJson::from_relaxed(input.into()).unwrap();
+
+        assert_eq!(
+            serde_json::json!({"content": INPUT_STRING, "values": [1, 2]}),
+            result.content
+        );
+        assert!(PayloadFormatJson::from_relaxed("{content: }".into()).is_err());
+        assert!(PayloadFormatJson::try_from(Vec::<u8>::from(input)).is_err());
+    }
+
     #[test]
     fn from_vec_u8() {
         let result = PayloadFormatJson::try_from(get_input_json_vec()).unwrap();
@@ -248,6 +289,17 @@ mod tests {
         assert_eq!(get_input_json_string(INPUT_STRING), result.as_str());
     }
 
+    #[test]
+    fn to_string_pretty() {
+        let input = PayloadFormatJson::try_from("{\"a\":[1,2]}".to_string()).unwrap();
+
+        assert_eq!("{\"a\":[1,2]}", String::from(input.clone()));
+        assert_eq!(
+            "{\n  \"a\": [\n    1,\n    2\n  ]\n}",
+            String::from(input.with_pretty(true))
+        );
+    }
+
     #[test]
     fn from_text() {
         let input = PayloadFormatText::from(get_input_json_string(INPUT_STRING));
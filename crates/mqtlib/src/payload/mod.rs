@@ -1,21 +1,30 @@
+use std::env::VarError;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
 use std::string::FromUtf8Error;
+use std::time::Duration;
 
 use ::base64::DecodeError;
 use ::hex::FromHexError;
 use protobuf_json_mapping::PrintError;
+use serde::Serialize;
 use strum_macros::IntoStaticStr;
 use thiserror::Error;
 use tracing::error;
 
 use crate::config::filter::FilterError;
-use crate::config::{PayloadType, PublishInputType, PublishInputTypeContentPath};
+use crate::config::{
+    PayloadType, PublishInputType, PublishInputTypeContentPath, PublishInputTypeCsv,
+    PublishInputTypeDirectory, PublishInputTypeEnv, PublishInputTypeNdjson,
+};
+use crate::mqtt::QoS;
 use crate::payload::base64::PayloadFormatBase64;
 use crate::payload::hex::PayloadFormatHex;
+use crate::payload::hexdump::PayloadFormatHexdump;
 use crate::payload::json::PayloadFormatJson;
 use crate::payload::protobuf::PayloadFormatProtobuf;
 use crate::payload::raw::PayloadFormatRaw;
@@ -23,8 +32,10 @@ use crate::payload::sparkplug::PayloadFormatSparkplug;
 use crate::payload::text::PayloadFormatText;
 use crate::payload::yaml::PayloadFormatYaml;
 
+pub mod auto;
 pub mod base64;
 pub mod hex;
+pub mod hexdump;
 pub mod json;
 pub mod protobuf;
 pub mod raw;
@@ -42,18 +53,40 @@ pub enum PayloadFormatError {
     DisplayNotPossible(String),
     #[error("Cannot read content from path {1}")]
     CannotReadInputFromPath(#[source] io::Error, PathBuf),
+    #[error("Cannot read directory {1}")]
+    CannotReadDirectory(#[source] io::Error, PathBuf),
+    #[error("Directory {0} does not contain any files")]
+    DirectoryIsEmpty(PathBuf),
+    #[error("Cannot read CSV file {1}")]
+    CannotReadCsv(#[source] csv::Error, PathBuf),
+    #[error("CSV file {0} does not contain any rows")]
+    CsvIsEmpty(PathBuf),
+    #[error("CSV delimiter {0} must be a single byte character")]
+    InvalidCsvDelimiter(char),
+    #[error("Line {2} of NDJSON file {1} is not valid JSON")]
+    InvalidNdjsonLine(#[source] serde_json::Error, PathBuf, usize),
+    #[error("QoS {0} in line {2} of NDJSON file {1} must be 0, 1 or 2")]
+    InvalidNdjsonQos(serde_json::Value, PathBuf, usize),
+    #[error("NDJSON file {0} does not contain any lines")]
+    NdjsonIsEmpty(PathBuf),
     #[error("Either content or path to content must be given")]
     EitherContentOrPathMustBeGiven,
     #[error("Could not open definition file {0}")]
     CouldNotOpenDefinitionFile(String),
     #[error("Could not open protobuf definition file")]
     CouldNotOpenProtobufDefinitionFile,
+    #[error("Invalid protobuf definition file {0}: {1}")]
+    InvalidProtobufDefinition(PathBuf, String),
     #[error("Message {0} not found in proto file, cannot decode payload")]
     MessageNotFoundInProtoFile(String),
     #[error("Invalid protobuf")]
     InvalidProtobuf,
     #[error("Protobuf message {0} not found")]
     ProtobufMessageNotFound(String),
+    #[error("Payload has fields unknown to protobuf message {0} or misses required fields")]
+    ProtobufMessageNotClean(String),
+    #[error("Payload can't be parsed as any of the protobuf messages {0}")]
+    ProtobufNoCandidateMatches(String),
     #[error("Field with number {0} not found in proto file")]
     FieldNumberNotFoundInProtoFile(u64),
     #[error("Could not convert payload to yaml")]
@@ -64,6 +97,8 @@ pub enum PayloadFormatError {
     CouldNotConvertToJson(#[source] serde_json::Error),
     #[error("Could not convert payload from json")]
     CouldNotConvertFromJson(String),
+    #[error("Could not serialize the value")]
+    CouldNotSerialize(#[source] serde_json::Error),
     #[error("Could not convert payload from protobuf to format {0}")]
     CouldNotConvertFromProtobuf(&'static str),
     #[error("Could not convert payload to hex")]
@@ -72,6 +107,8 @@ pub enum PayloadFormatError {
     CouldNotConvertToBase64(#[source] DecodeError),
     #[error("Could not convert payload from sparkplug json")]
     CouldNotConvertFromSparkplugJson,
+    #[error("Invalid sparkplug input: {0}")]
+    InvalidSparkplugInput(String),
     #[error("The value is not valid hex formatted: {0}")]
     ValueIsNotValidHex(String),
     #[error("The value is not valid base64 formatted: {0}")]
@@ -84,6 +121,22 @@ pub enum PayloadFormatError {
     ProtobufJsonMappingError(#[from] protobuf_json_mapping::ParseError),
     #[error("Error while applying filters")]
     FilterError(#[from] FilterError),
+    #[error("Could not fetch payload from {1}")]
+    CouldNotFetchHttpInput(#[source] reqwest::Error, String),
+    #[error("Fetching payload from {0} failed with status {1}")]
+    HttpInputRequestFailed(String, reqwest::StatusCode),
+    #[error("Could not run input command \"{1}\"")]
+    CommandInputNotExecutable(#[source] io::Error, String),
+    #[error("Input command \"{0}\" failed with {1}")]
+    CommandInputFailed(String, ExitStatus),
+    #[error("Input command \"{0}\" did not finish within {1:?}")]
+    CommandInputTimedOut(String, Duration),
+    #[error("Could not read environment variable {1}")]
+    CouldNotReadEnvironmentVariable(#[source] VarError, String),
+    #[error("HTTP and command inputs can only be published by periodic and on_message triggers")]
+    InputMustBeFetched,
+    #[error("Messages input contains no messages")]
+    NoMessages,
 }
 
 impl From<FromUtf8Error> for PayloadFormatError {
@@ -122,6 +175,7 @@ pub enum PayloadFormat {
     Raw(PayloadFormatRaw),
     Protobuf(PayloadFormatProtobuf),
     Hex(PayloadFormatHex),
+    Hexdump(PayloadFormatHexdump),
     Base64(PayloadFormatBase64),
     Json(PayloadFormatJson),
     Yaml(PayloadFormatYaml),
@@ -132,7 +186,10 @@ pub enum PayloadFormat {
 impl Display for PayloadFormat {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name: &'static str = self.into();
-        write!(f, "{}", name)
+        match self {
+            PayloadFormat::Protobuf(value) => write!(f, "{} ({})", name, value.message_name()),
+            _ => write!(f, "{}", name),
+        }
     }
 }
 impl TryFrom<PayloadFormat> for Vec<u8> {
@@ -144,6 +201,7 @@ impl TryFrom<PayloadFormat> for Vec<u8> {
             PayloadFormat::Raw(value) => Ok(value.into()),
             PayloadFormat::Protobuf(value) => Ok(value.try_into()?),
             PayloadFormat::Hex(value) => Ok(value.into()),
+            PayloadFormat::Hexdump(value) => Ok(value.into()),
             PayloadFormat::Base64(value) => Ok(value.into()),
             PayloadFormat::Json(value) => Ok(value.into()),
             PayloadFormat::Yaml(value) => value.try_into(),
@@ -162,8 +220,9 @@ impl TryInto<String> for PayloadFormat {
             PayloadFormat::Raw(value) => {
                 Ok(String::from_utf8_lossy(Vec::<u8>::from(value).as_slice()).to_string())
             }
-            PayloadFormat::Protobuf(value) => Ok(value.to_string()),
+            PayloadFormat::Protobuf(value) => value.render_to_string(),
             PayloadFormat::Hex(value) => Ok(value.into()),
+            PayloadFormat::Hexdump(value) => Ok(value.into()),
             PayloadFormat::Base64(value) => Ok(value.into()),
             PayloadFormat::Json(value) => Ok(value.into()),
             PayloadFormat::Yaml(value) => value.try_into(),
@@ -178,11 +237,16 @@ impl TryFrom<(PayloadFormat, &PayloadType)> for PayloadFormat {
 
     fn try_from((value, payload_type): (PayloadFormat, &PayloadType)) -> Result<Self, Self::Error> {
         Ok(match payload_type {
-            PayloadType::Text => PayloadFormat::Text(PayloadFormatText::try_from(value)?),
+            PayloadType::Text(options) => {
+                PayloadFormat::Text(PayloadFormatText::try_from((value, options))?)
+            }
             PayloadType::Json => PayloadFormat::Json(PayloadFormatJson::try_from(value)?),
             PayloadType::Yaml => PayloadFormat::Yaml(PayloadFormatYaml::try_from(value)?),
             PayloadType::Hex => PayloadFormat::Hex(PayloadFormatHex::try_from(value)?),
-            PayloadType::Base64 => PayloadFormat::Base64(PayloadFormatBase64::try_from(value)?),
+            PayloadType::Hexdump => PayloadFormat::Hexdump(PayloadFormatHexdump::try_from(value)?),
+            PayloadType::Base64(options) => {
+                PayloadFormat::Base64(PayloadFormatBase64::try_from((value, options))?)
+            }
             PayloadType::Raw => PayloadFormat::Raw(PayloadFormatRaw::try_from(value)?),
             PayloadType::Protobuf(options) => {
                 PayloadFormat::Protobuf(PayloadFormatProtobuf::try_from((value, options))?)
@@ -193,6 +257,10 @@ impl TryFrom<(PayloadFormat, &PayloadType)> for PayloadFormat {
             PayloadType::SparkplugJson => {
                 PayloadFormat::SparkplugJson(PayloadFormatJson::try_from(value)?)
             }
+            PayloadType::Auto => match value {
+                PayloadFormat::Raw(value) => auto::detect(value.into())?,
+                value => value,
+            },
         })
     }
 }
@@ -205,16 +273,19 @@ impl TryFrom<(PayloadType, Vec<u8>)> for PayloadFormat {
 
     fn try_from((payload_type, content): (PayloadType, Vec<u8>)) -> Result<Self, Self::Error> {
         Ok(match payload_type {
-            PayloadType::Text => PayloadFormat::Text(PayloadFormatText::from(content)),
-            PayloadType::Protobuf(options) => PayloadFormat::Protobuf(PayloadFormatProtobuf::new(
-                content,
-                options.definition(),
-                options.message().clone(),
-            )?),
+            PayloadType::Text(options) => PayloadFormat::Text(
+                PayloadFormatText::from(content).with_encoding(*options.encoding()),
+            ),
+            PayloadType::Protobuf(options) => PayloadFormat::Protobuf(
+                PayloadFormatProtobuf::new_from_candidates(content, &options)?,
+            ),
             PayloadType::Json => PayloadFormat::Json(PayloadFormatJson::try_from(content)?),
             PayloadType::Yaml => PayloadFormat::Yaml(PayloadFormatYaml::try_from(content)?),
             PayloadType::Hex => PayloadFormat::Hex(PayloadFormatHex::try_from(content)?),
-            PayloadType::Base64 => PayloadFormat::Base64(PayloadFormatBase64::try_from(content)?),
+            PayloadType::Hexdump => PayloadFormat::Hexdump(PayloadFormatHexdump::from(content)),
+            PayloadType::Base64(_) => {
+                PayloadFormat::Base64(PayloadFormatBase64::try_from(content)?)
+            }
             PayloadType::Raw => PayloadFormat::Raw(PayloadFormatRaw::from(content)),
             PayloadType::Sparkplug => {
                 PayloadFormat::Sparkplug(PayloadFormatSparkplug::try_from(content)?)
@@ -222,6 +293,7 @@ impl TryFrom<(PayloadType, Vec<u8>)> for PayloadFormat {
             PayloadType::SparkplugJson => {
                 PayloadFormat::SparkplugJson(PayloadFormatJson::try_from(content)?)
             }
+            PayloadType::Auto => auto::detect(content)?,
         })
     }
 }
@@ -235,6 +307,79 @@ impl PayloadFormat {
 
         Self::try_from((content, output_type))
     }
+
+    /// Serializes the value with serde and converts it to the payload type,
+    /// in the same way as JSON content given on the command line.
+    pub fn from_serialize<T: Serialize + ?Sized>(
+        value: &T,
+        payload_type: &PayloadType,
+    ) -> Result<PayloadFormat, PayloadFormatError> {
+        let content = serde_json::to_value(value).map_err(PayloadFormatError::CouldNotSerialize)?;
+
+        Self::try_from((
+            PayloadFormat::Json(PayloadFormatJson::from(content)),
+            payload_type,
+        ))
+    }
+
+    /// Interprets the body according to its MIME content type: JSON, YAML,
+    /// text for all other text types, and raw bytes otherwise.
+    pub fn from_content_type(
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> Result<PayloadFormat, PayloadFormatError> {
+        let mime = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        Ok(match mime.as_str() {
+            "application/json" => PayloadFormat::Json(PayloadFormatJson::try_from(body)?),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => {
+                PayloadFormat::Yaml(PayloadFormatYaml::try_from(body)?)
+            }
+            mime if mime.starts_with("text/") => PayloadFormat::Text(PayloadFormatText::from(body)),
+            _ => PayloadFormat::Raw(PayloadFormatRaw::from(body)),
+        })
+    }
+
+    /// Reads the value of the environment variable of the input.
+    pub fn from_env(input: &PublishInputTypeEnv) -> Result<PayloadFormat, PayloadFormatError> {
+        let value = std::env::var(input.variable()).map_err(|e| {
+            PayloadFormatError::CouldNotReadEnvironmentVariable(e, input.variable().clone())
+        })?;
+
+        let format = input
+            .format()
+            .clone()
+            .unwrap_or(PayloadType::Text(Default::default()));
+
+        PayloadFormat::try_from((format, value.into_bytes()))
+    }
+
+    /// Reads all payloads of the given input. Returns one payload for every
+    /// input type except directories, which yield one payload per file, CSV
+    /// and NDJSON files, which yield one payload per row or line, and
+    /// messages, which yield the payloads of all their inputs.
+    pub fn from_input(input_type: &PublishInputType) -> Result<Vec<Self>, PayloadFormatError> {
+        match input_type {
+            PublishInputType::Directory(input) => read_directory(input),
+            PublishInputType::Csv(input) => read_csv(input),
+            PublishInputType::Ndjson(input) => Ok(read_ndjson(input)?
+                .into_iter()
+                .map(|line| line.payload)
+                .collect()),
+            PublishInputType::Messages(input) => Ok(input
+                .messages()
+                .iter()
+                .map(PayloadFormat::from_input)
+                .collect::<Result<Vec<Vec<PayloadFormat>>, PayloadFormatError>>()?
+                .into_iter()
+                .flatten()
+                .collect()),
+            _ => Ok(vec![PayloadFormat::try_from(input_type)?]),
+        }
+    }
 }
 
 impl TryFrom<(PayloadFormat, PayloadType)> for PayloadFormat {
@@ -264,7 +409,7 @@ impl TryFrom<&PublishInputType> for PayloadFormat {
             }
             PublishInputType::Json(input) => {
                 let c = read_input_type_content_path(input)?;
-                PayloadFormat::Json(PayloadFormatJson::try_from(c)?)
+                PayloadFormat::Json(PayloadFormatJson::from_relaxed(c)?)
             }
             PublishInputType::Yaml(input) => {
                 let c = read_input_type_content_path(input)?;
@@ -274,6 +419,17 @@ impl TryFrom<&PublishInputType> for PayloadFormat {
                 let c = read_input_type_content_path(input)?;
                 PayloadFormat::Base64(PayloadFormatBase64::try_from(String::from_utf8(c)?)?)
             }
+            PublishInputType::Directory(input) => read_directory(input)?.remove(0),
+            PublishInputType::Csv(input) => read_csv(input)?.remove(0),
+            PublishInputType::Ndjson(input) => read_ndjson(input)?.remove(0).payload,
+            PublishInputType::Env(input) => PayloadFormat::from_env(input)?,
+            PublishInputType::Messages(input) => PayloadFormat::from_input(value)?
+                .into_iter()
+                .next()
+                .ok_or(PayloadFormatError::NoMessages)?,
+            PublishInputType::Http(_) | PublishInputType::Command(_) => {
+                return Err(PayloadFormatError::InputMustBeFetched)
+            }
             PublishInputType::Null => {
                 PayloadFormat::Text(PayloadFormatText::from(Vec::<u8>::new()))
             }
@@ -281,6 +437,188 @@ impl TryFrom<&PublishInputType> for PayloadFormat {
     }
 }
 
+fn read_directory(
+    input: &PublishInputTypeDirectory,
+) -> Result<Vec<PayloadFormat>, PayloadFormatError> {
+    let entries = std::fs::read_dir(input.path())
+        .map_err(|e| PayloadFormatError::CannotReadDirectory(e, input.path().clone()))?;
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(PayloadFormatError::DirectoryIsEmpty(input.path().clone()));
+    }
+
+    paths.iter().map(|path| read_file(path)).collect()
+}
+
+fn read_csv(input: &PublishInputTypeCsv) -> Result<Vec<PayloadFormat>, PayloadFormatError> {
+    let file = File::open(input.path())
+        .map_err(|e| PayloadFormatError::CannotReadInputFromPath(e, input.path().clone()))?;
+
+    let rows = parse_csv(file, input)?;
+    if rows.is_empty() {
+        return Err(PayloadFormatError::CsvIsEmpty(input.path().clone()));
+    }
+
+    Ok(rows)
+}
+
+/// Converts each row to a JSON object with the names of the header row as
+/// keys. Missing trailing columns are left out.
+fn parse_csv(
+    reader: impl Read,
+    input: &PublishInputTypeCsv,
+) -> Result<Vec<PayloadFormat>, PayloadFormatError> {
+    let delimiter = u8::try_from(*input.delimiter())
+        .map_err(|_| PayloadFormatError::InvalidCsvDelimiter(*input.delimiter()))?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| PayloadFormatError::CannotReadCsv(e, input.path().clone()))?
+        .clone();
+
+    let infer_types = *input.infer_types();
+
+    reader
+        .records()
+        .map(|record| {
+            let record =
+                record.map_err(|e| PayloadFormatError::CannotReadCsv(e, input.path().clone()))?;
+
+            let row = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(column, value)| (column.to_string(), csv_value(value, infer_types)))
+                .collect::<serde_json::Map<String, serde_json::Value>>();
+
+            Ok(PayloadFormat::Json(PayloadFormatJson::from(
+                serde_json::Value::Object(row),
+            )))
+        })
+        .collect()
+}
+
+fn csv_value(value: &str, infer_types: bool) -> serde_json::Value {
+    if infer_types {
+        if let Ok(value) = value.parse::<i64>() {
+            return value.into();
+        }
+        if let Ok(value) = value.parse::<f64>() {
+            if value.is_finite() {
+                return value.into();
+            }
+        }
+        if let Ok(value) = value.parse::<bool>() {
+            return value.into();
+        }
+    }
+
+    value.into()
+}
+
+/// Line of an NDJSON input with the topic and QoS read from its fields.
+#[derive(Debug)]
+pub struct NdjsonLine {
+    pub payload: PayloadFormat,
+    pub topic: Option<String>,
+    pub qos: Option<QoS>,
+}
+
+pub fn read_ndjson(input: &PublishInputTypeNdjson) -> Result<Vec<NdjsonLine>, PayloadFormatError> {
+    let file = File::open(input.path())
+        .map_err(|e| PayloadFormatError::CannotReadInputFromPath(e, input.path().clone()))?;
+
+    let lines = parse_ndjson(BufReader::new(file), input)?;
+    if lines.is_empty() {
+        return Err(PayloadFormatError::NdjsonIsEmpty(input.path().clone()));
+    }
+
+    Ok(lines)
+}
+
+/// Parses each non-empty line as JSON. The payload field is published as
+/// text if it holds a string and as JSON otherwise.
+fn parse_ndjson(
+    reader: impl BufRead,
+    input: &PublishInputTypeNdjson,
+) -> Result<Vec<NdjsonLine>, PayloadFormatError> {
+    let mut result = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let number = index + 1;
+        let line =
+            line.map_err(|e| PayloadFormatError::CannotReadInputFromPath(e, input.path().clone()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut value = serde_json::from_str::<serde_json::Value>(&line)
+            .map_err(|e| PayloadFormatError::InvalidNdjsonLine(e, input.path().clone(), number))?;
+
+        let topic = input
+            .topic_field()
+            .as_ref()
+            .and_then(|field| value.get(field))
+            .and_then(|topic| topic.as_str())
+            .map(String::from);
+
+        let qos = match input
+            .qos_field()
+            .as_ref()
+            .and_then(|field| value.get(field))
+        {
+            None => None,
+            Some(qos) => Some(match qos.as_u64() {
+                Some(0) => QoS::AtMostOnce,
+                Some(1) => QoS::AtLeastOnce,
+                Some(2) => QoS::ExactlyOnce,
+                _ => {
+                    return Err(PayloadFormatError::InvalidNdjsonQos(
+                        qos.clone(),
+                        input.path().clone(),
+                        number,
+                    ))
+                }
+            }),
+        };
+
+        if let Some(field) = input.payload_field() {
+            value = value
+                .get_mut(field)
+                .map(serde_json::Value::take)
+                .unwrap_or_default();
+        }
+
+        let payload = match value {
+            serde_json::Value::String(text) => PayloadFormat::Text(PayloadFormatText::from(text)),
+            value => PayloadFormat::Json(PayloadFormatJson::from(value)),
+        };
+
+        result.push(NdjsonLine {
+            payload,
+            topic,
+            qos,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Reads a single file, deriving its format from the file extension.
+fn read_file(path: &Path) -> Result<PayloadFormat, PayloadFormatError> {
+    PayloadFormat::try_from(&PublishInputType::from_file(path))
+}
+
 fn read_input_type_content_path(
     input: &PublishInputTypeContentPath,
 ) -> Result<Vec<u8>, PayloadFormatError> {
@@ -315,3 +653,134 @@ fn read_from_path(path: &PathBuf) -> Result<Vec<u8>, PayloadFormatError> {
     };
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct Inner {
+        kind: String,
+    }
+
+    #[derive(Serialize)]
+    struct Response {
+        distance: i32,
+        inside: Inner,
+    }
+
+    #[test]
+    fn from_serialize() {
+        let value = Response {
+            distance: 32,
+            inside: Inner {
+                kind: "kindof".to_string(),
+            },
+        };
+
+        let yaml = PayloadFormat::from_serialize(&value, &PayloadType::Yaml).unwrap();
+        assert_eq!(
+            "distance: 32\ninside:\n  kind: kindof\n",
+            String::try_from(yaml).unwrap()
+        );
+
+        let protobuf = PayloadType::Protobuf(
+            serde_yaml::from_str("definition: test/data/message.proto\nmessage: Response").unwrap(),
+        );
+        let payload = PayloadFormat::from_serialize(&value, &protobuf).unwrap();
+        assert_eq!(
+            hex::decode("082012080a066b696e646f66").unwrap(),
+            Vec::<u8>::try_from(payload).unwrap()
+        );
+    }
+
+    fn csv_input(yaml: &str) -> PublishInputTypeCsv {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn rows(payloads: Vec<PayloadFormat>) -> Vec<serde_json::Value> {
+        payloads
+            .into_iter()
+            .map(|payload| match payload {
+                PayloadFormat::Json(json) => json.content().clone(),
+                _ => panic!("CSV rows must be JSON"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn csv_rows_as_json() {
+        let input = csv_input("path: data.csv");
+        let csv = "device,temperature,online\nsensor-1,21.5,true\nsensor-2,19,false\nsensor-3\n";
+
+        assert_eq!(
+            vec![
+                json!({"device": "sensor-1", "temperature": 21.5, "online": true}),
+                json!({"device": "sensor-2", "temperature": 19, "online": false}),
+                json!({"device": "sensor-3"}),
+            ],
+            rows(parse_csv(csv.as_bytes(), &input).unwrap())
+        );
+    }
+
+    #[test]
+    fn ndjson_lines() {
+        let input: PublishInputTypeNdjson = serde_yaml::from_str(
+            "path: data.ndjson\ntopic_field: topic\nqos_field: qos\npayload_field: payload",
+        )
+        .unwrap();
+        let ndjson = concat!(
+            r#"{"topic": "a", "qos": 1, "payload": {"value": 1}}"#,
+            "\n\n",
+            r#"{"payload": "text"}"#,
+            "\n",
+        );
+
+        let lines = parse_ndjson(ndjson.as_bytes(), &input).unwrap();
+        assert_eq!(2, lines.len());
+        assert_eq!(Some("a".to_string()), lines[0].topic);
+        assert_eq!(Some(QoS::AtLeastOnce), lines[0].qos);
+        assert_eq!(None, lines[1].topic);
+        assert_eq!(None, lines[1].qos);
+        assert!(matches!(lines[1].payload, PayloadFormat::Text(_)));
+
+        let payloads = lines
+            .into_iter()
+            .map(|line| Vec::<u8>::try_from(line.payload).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![b"{\"value\":1}".to_vec(), b"text".to_vec()], payloads);
+
+        let invalid = parse_ndjson(r#"{"qos": 3}"#.as_bytes(), &input);
+        assert!(matches!(
+            invalid,
+            Err(PayloadFormatError::InvalidNdjsonQos(_, _, 1))
+        ));
+    }
+
+    #[test]
+    fn messages_in_order() {
+        let input: PublishInputType = serde_yaml::from_str(
+            "type: messages\nmessages:\n  - type: text\n    content: first\n  - type: json\n    content: '{\"value\": 2}'",
+        )
+        .unwrap();
+
+        let payloads = PayloadFormat::from_input(&input)
+            .unwrap()
+            .into_iter()
+            .map(|payload| Vec::<u8>::try_from(payload).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![b"first".to_vec(), b"{\"value\":2}".to_vec()], payloads);
+    }
+
+    #[test]
+    fn csv_without_type_inference() {
+        let input = csv_input("path: data.csv\ndelimiter: ;\ninfer_types: false");
+        let csv = "device;temperature\nsensor-1;21.5\n";
+
+        assert_eq!(
+            vec![json!({"device": "sensor-1", "temperature": "21.5"})],
+            rows(parse_csv(csv.as_bytes(), &input).unwrap())
+        );
+    }
+}
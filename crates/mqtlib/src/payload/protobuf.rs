@@ -1,18 +1,60 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
-use crate::config::PayloadProtobuf;
+use crate::config::{PayloadProtobuf, ProtobufRender};
 use crate::payload::json::PayloadFormatJson;
 use crate::payload::{PayloadFormat, PayloadFormatError};
 use derive_getters::Getters;
-use protobuf::reflect::{FileDescriptor, MessageDescriptor};
+use lazy_static::lazy_static;
+use protobuf::reflect::{FileDescriptor, MessageDescriptor, ReflectFieldRef, ReflectValueRef};
 use protobuf::text_format::print_to_string_pretty;
-use protobuf::MessageDyn;
-use protobuf_json_mapping::parse_dyn_from_str;
+use protobuf::{MessageDyn, UnknownValueRef};
+use protobuf_json_mapping::{parse_dyn_from_str, print_to_string as print_to_json_string};
+
+lazy_static! {
+    /// Parsed definition files by path, with the modification time of the
+    /// file when it was parsed. A file is parsed again once it was modified.
+    static ref FILE_DESCRIPTORS: Mutex<HashMap<PathBuf, (Option<SystemTime>, FileDescriptor)>> =
+        Mutex::new(HashMap::new());
+}
 
 #[derive(Clone, Debug, Getters)]
 pub struct PayloadFormatProtobuf {
     content: Box<dyn MessageDyn>,
+    /// Rendering used when the message is converted to text
+    render: ProtobufRender,
+    /// Shows the unknown fields when converted to text or JSON
+    show_unknown: bool,
+}
+
+/// Field of a received message which is not part of its definition.
+#[derive(Clone, Debug, PartialEq)]
+struct UnknownField {
+    /// JSON path of the message containing the field, empty for the
+    /// top-level message
+    path: String,
+    tag: u32,
+    wire_type: &'static str,
+    /// Raw value in the wire format
+    value: Vec<u8>,
+}
+
+impl Display for UnknownField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}.", self.path)?;
+        }
+        write!(
+            f,
+            "{} ({}): {}",
+            self.tag,
+            self.wire_type,
+            hex::encode(&self.value)
+        )
+    }
 }
 
 impl PayloadFormatProtobuf {
@@ -23,7 +65,56 @@ impl PayloadFormatProtobuf {
     ) -> Result<Self, PayloadFormatError> {
         let result = Self::convert_from_vec(content, definition_file, message_name.as_str())?;
 
-        Ok(Self { content: result })
+        Ok(Self {
+            content: result,
+            render: ProtobufRender::default(),
+            show_unknown: false,
+        })
+    }
+
+    /// Parses the content as the first of the candidate messages of the
+    /// options which parses cleanly.
+    pub fn new_from_candidates(
+        content: Vec<u8>,
+        options: &PayloadProtobuf,
+    ) -> Result<Self, PayloadFormatError> {
+        let candidates: Vec<&String> = options.candidates().collect();
+        let mut error = None;
+
+        for message_name in &candidates {
+            match Self::convert_from_vec(content.clone(), options.definition(), message_name) {
+                Ok(message) if !*options.strict() || is_clean(&*message) => {
+                    return Ok(Self {
+                        content: message,
+                        render: *options.render(),
+                        show_unknown: *options.show_unknown(),
+                    });
+                }
+                Ok(_) => {
+                    error = Some(PayloadFormatError::ProtobufMessageNotClean(
+                        message_name.to_string(),
+                    ))
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+
+        if candidates.len() > 1 {
+            error = Some(PayloadFormatError::ProtobufNoCandidateMatches(
+                candidates
+                    .iter()
+                    .map(|message_name| message_name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ));
+        }
+
+        Err(error.unwrap_or(PayloadFormatError::ProtobufMessageNotFound(String::new())))
+    }
+
+    /// Fully qualified name of the message type of the content.
+    pub fn message_name(&self) -> String {
+        self.content.descriptor_dyn().full_name().to_string()
     }
 
     pub fn convert_from(
@@ -45,6 +136,9 @@ impl PayloadFormatProtobuf {
             PayloadFormat::Hex(value) => {
                 Self::convert_from_vec(value.decode_from_hex()?, definition_file, message_name)?
             }
+            PayloadFormat::Hexdump(value) => {
+                Self::convert_from_vec(value.decode(), definition_file, message_name)?
+            }
             PayloadFormat::Base64(value) => {
                 Self::convert_from_vec(value.decode_from_base64()?, definition_file, message_name)?
             }
@@ -63,7 +157,11 @@ impl PayloadFormatProtobuf {
             }
         };
 
-        Ok(Self { content })
+        Ok(Self {
+            content,
+            render: ProtobufRender::default(),
+            show_unknown: false,
+        })
     }
 
     fn convert_from_vec(
@@ -88,29 +186,195 @@ impl PayloadFormatProtobuf {
         Ok(payload)
     }
 
-    fn get_message_descriptor(
+    pub(crate) fn get_message_descriptor(
         proto_message_path: &PathBuf,
         message_name: &str,
     ) -> Result<MessageDescriptor, PayloadFormatError> {
+        Self::get_file_descriptor(proto_message_path)?
+            .message_by_package_relative_name(message_name)
+            .ok_or(PayloadFormatError::ProtobufMessageNotFound(
+                message_name.to_string(),
+            ))
+    }
+
+    /// Returns the parsed definition file from the cache, or parses it if it
+    /// is not cached yet or was modified since it was parsed.
+    pub(crate) fn get_file_descriptor(
+        proto_message_path: &PathBuf,
+    ) -> Result<FileDescriptor, PayloadFormatError> {
+        let modified = std::fs::metadata(proto_message_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        if let Some((cached_modified, descriptor)) =
+            FILE_DESCRIPTORS.lock().unwrap().get(proto_message_path)
+        {
+            if modified.is_some() && *cached_modified == modified {
+                return Ok(descriptor.clone());
+            }
+        }
+
+        let descriptor = Self::parse_file_descriptor(proto_message_path)?;
+
+        FILE_DESCRIPTORS
+            .lock()
+            .unwrap()
+            .insert(proto_message_path.clone(), (modified, descriptor.clone()));
+
+        Ok(descriptor)
+    }
+
+    fn parse_file_descriptor(
+        proto_message_path: &PathBuf,
+    ) -> Result<FileDescriptor, PayloadFormatError> {
         let include_path = proto_message_path
             .parent()
             .ok_or(PayloadFormatError::CouldNotOpenProtobufDefinitionFile)?;
-        let proto_file = protobuf_parse::Parser::new()
+        let proto_files = protobuf_parse::Parser::new()
             .pure()
             .include(include_path)
             .input(proto_message_path)
             .parse_and_typecheck()
-            .unwrap()
-            .file_descriptors
-            .pop()
-            .unwrap();
+            .map_err(|e| {
+                PayloadFormatError::InvalidProtobufDefinition(
+                    proto_message_path.clone(),
+                    format!("{e:#}"),
+                )
+            })?
+            .file_descriptors;
+
+        // the imported files are parsed as well and come before the file
+        let name = proto_files
+            .last()
+            .ok_or(PayloadFormatError::CouldNotOpenProtobufDefinitionFile)?
+            .name()
+            .to_string();
+
+        FileDescriptor::new_dynamic_fds(proto_files, &[])?
+            .into_iter()
+            .find(|descriptor| descriptor.name() == name)
+            .ok_or(PayloadFormatError::CouldNotOpenProtobufDefinitionFile)
+    }
+}
 
-        let dynamic_file_descriptor = FileDescriptor::new_dynamic(proto_file.clone(), &[])?;
-        dynamic_file_descriptor
-            .message_by_package_relative_name(message_name)
-            .ok_or(PayloadFormatError::ProtobufMessageNotFound(
-                message_name.to_string(),
-            ))
+impl PayloadFormatProtobuf {
+    /// Sets how the message is rendered when it is converted to text.
+    pub fn with_render(mut self, render: ProtobufRender) -> Self {
+        self.render = render;
+        self
+    }
+
+    /// Sets whether the unknown fields are shown when the message is
+    /// converted to text or JSON.
+    pub fn with_show_unknown(mut self, show_unknown: bool) -> Self {
+        self.show_unknown = show_unknown;
+        self
+    }
+
+    /// Returns the message as text in the configured rendering.
+    pub fn render_to_string(&self) -> Result<String, PayloadFormatError> {
+        Ok(match self.render {
+            ProtobufRender::Text => {
+                let mut text = print_to_string_pretty(&*self.content);
+                for field in self.unknown_fields() {
+                    text.push_str(&format!("# unknown field {field}\n"));
+                }
+                text
+            }
+            ProtobufRender::Json => self.to_json_value()?.to_string(),
+            ProtobufRender::JsonPretty => serde_json::to_string_pretty(&self.to_json_value()?)?,
+        })
+    }
+
+    /// Returns the message as JSON. If unknown fields are shown, they are
+    /// added as list with the key `_unknown`.
+    pub fn to_json_value(&self) -> Result<serde_json::Value, PayloadFormatError> {
+        let mut value =
+            serde_json::from_str::<serde_json::Value>(&print_to_json_string(&*self.content)?)?;
+
+        let unknown_fields = self.unknown_fields();
+        if let (false, Some(object)) = (unknown_fields.is_empty(), value.as_object_mut()) {
+            let fields = unknown_fields
+                .into_iter()
+                .map(|field| {
+                    let mut entry = serde_json::json!({
+                        "tag": field.tag,
+                        "wire_type": field.wire_type,
+                        "value": hex::encode(&field.value),
+                    });
+                    if !field.path.is_empty() {
+                        entry["path"] = serde_json::Value::String(field.path);
+                    }
+                    entry
+                })
+                .collect();
+            object.insert("_unknown".to_string(), serde_json::Value::Array(fields));
+        }
+
+        Ok(value)
+    }
+
+    /// Unknown fields of the message and its nested messages, or none if
+    /// they are not shown.
+    fn unknown_fields(&self) -> Vec<UnknownField> {
+        let mut fields = vec![];
+        if self.show_unknown {
+            collect_unknown_fields(&*self.content, "", &mut fields);
+        }
+        fields
+    }
+}
+
+fn collect_unknown_fields(message: &dyn MessageDyn, path: &str, fields: &mut Vec<UnknownField>) {
+    for (tag, value) in message.special_fields_dyn().unknown_fields().iter() {
+        let (wire_type, value) = match value {
+            UnknownValueRef::Varint(value) => ("varint", encode_varint(value)),
+            UnknownValueRef::Fixed64(value) => ("fixed64", value.to_le_bytes().to_vec()),
+            UnknownValueRef::LengthDelimited(value) => ("length-delimited", value.to_vec()),
+            UnknownValueRef::Fixed32(value) => ("fixed32", value.to_le_bytes().to_vec()),
+        };
+        fields.push(UnknownField {
+            path: path.to_string(),
+            tag,
+            wire_type,
+            value,
+        });
+    }
+
+    for field in message.descriptor_dyn().fields() {
+        let field_path = match path {
+            "" => field.json_name().to_string(),
+            path => format!("{path}.{}", field.json_name()),
+        };
+
+        match field.get_reflect(message) {
+            ReflectFieldRef::Optional(value) => {
+                if let Some(nested) = value.value().and_then(|value| value.to_message()) {
+                    collect_unknown_fields(&*nested, &field_path, fields);
+                }
+            }
+            ReflectFieldRef::Repeated(values) => {
+                for index in 0..values.len() {
+                    if let Some(nested) = values.get(index).to_message() {
+                        collect_unknown_fields(&*nested, &format!("{field_path}[{index}]"), fields);
+                    }
+                }
+            }
+            ReflectFieldRef::Map(_) => {}
+        }
+    }
+}
+
+fn encode_varint(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
     }
 }
 
@@ -133,10 +397,39 @@ impl TryFrom<(PayloadFormat, &PayloadProtobuf)> for PayloadFormatProtobuf {
     type Error = PayloadFormatError;
 
     fn try_from((value, options): (PayloadFormat, &PayloadProtobuf)) -> Result<Self, Self::Error> {
-        Self::convert_from(value, options.definition(), options.message())
+        Ok(
+            Self::convert_from(value, options.definition(), options.primary_message())?
+                .with_render(*options.render())
+                .with_show_unknown(*options.show_unknown()),
+        )
     }
 }
 
+/// Checks that the message and all messages nested in singular and repeated
+/// fields have no unknown fields and all required fields set.
+fn is_clean(message: &dyn MessageDyn) -> bool {
+    let is_clean_value =
+        |value: ReflectValueRef| value.to_message().map_or(true, |m| is_clean(&*m));
+
+    message.is_initialized_dyn()
+        && message
+            .special_fields_dyn()
+            .unknown_fields()
+            .iter()
+            .next()
+            .is_none()
+        && message
+            .descriptor_dyn()
+            .fields()
+            .all(|field| match field.get_reflect(message) {
+                ReflectFieldRef::Optional(value) => value.value().map_or(true, is_clean_value),
+                ReflectFieldRef::Repeated(values) => {
+                    (0..values.len()).all(|index| is_clean_value(values.get(index)))
+                }
+                ReflectFieldRef::Map(_) => true,
+            })
+}
+
 impl From<PayloadFormatProtobuf> for Box<dyn MessageDyn> {
     fn from(value: PayloadFormatProtobuf) -> Self {
         value.content
@@ -216,6 +509,86 @@ mod tests {
         assert_eq!(get_input_as_bytes(), result);
     }
 
+    #[test]
+    fn render() {
+        let input = PayloadFormatProtobuf::new(
+            get_input_as_bytes(),
+            &INPUT_PATH_MESSAGE,
+            MESSAGE_NAME.to_string(),
+        )
+        .unwrap();
+
+        let text = input.clone().render_to_string().unwrap();
+        assert!(text.contains("distance: 32\n"));
+
+        let json = input
+            .clone()
+            .with_render(ProtobufRender::Json)
+            .render_to_string()
+            .unwrap();
+        assert!(json.starts_with('{'));
+        assert!(!json.contains('\n'));
+
+        let json_pretty = input
+            .with_render(ProtobufRender::JsonPretty)
+            .render_to_string()
+            .unwrap();
+        assert!(json_pretty.contains("\n  \"distance\": 32"));
+    }
+
+    #[test]
+    fn candidates() {
+        let options = |strict: bool| -> PayloadProtobuf {
+            serde_yaml::from_str(&format!(
+                "definition: test/data/message.proto\nmessages: [Status, Response]\nstrict: {strict}"
+            ))
+            .unwrap()
+        };
+
+        let result =
+            PayloadFormatProtobuf::new_from_candidates(get_input_as_bytes(), &options(true))
+                .unwrap();
+        assert_eq!("Proto.Response", result.message_name());
+        assert_eq!(32, extract_distance(&result));
+        assert_eq!(
+            "Protobuf (Proto.Response)",
+            PayloadFormat::Protobuf(result).to_string()
+        );
+
+        // without strict, the fields unknown to the first candidate are kept
+        let result =
+            PayloadFormatProtobuf::new_from_candidates(get_input_as_bytes(), &options(false))
+                .unwrap();
+        assert_eq!("Proto.Status", result.message_name());
+
+        let result = PayloadFormatProtobuf::new_from_candidates(vec![0xff], &options(true));
+        assert!(matches!(
+            result,
+            Err(PayloadFormatError::ProtobufNoCandidateMatches(_))
+        ));
+    }
+
+    #[test]
+    fn show_unknown() {
+        // field 5 is unknown to the message Response
+        let mut input = get_input_as_bytes();
+        input.extend_from_slice(&[0x28, 0x96, 0x01]);
+
+        let result =
+            PayloadFormatProtobuf::new(input, &INPUT_PATH_MESSAGE, MESSAGE_NAME.into()).unwrap();
+        assert!(result.to_json_value().unwrap().get("_unknown").is_none());
+
+        let result = result.with_show_unknown(true);
+        assert_eq!(
+            serde_json::json!([{"tag": 5, "wire_type": "varint", "value": "9601"}]),
+            result.to_json_value().unwrap()["_unknown"]
+        );
+        assert!(result
+            .render_to_string()
+            .unwrap()
+            .ends_with("# unknown field 5 (varint): 9601\n"));
+    }
+
     #[test]
     fn from_text() {
         let input = PayloadFormatText::from("not possible");
@@ -295,6 +668,35 @@ mod tests {
         assert_eq!("kindof".to_string(), extract_kind(&result));
     }
 
+    #[test]
+    fn cache_file_descriptor() {
+        let path = std::env::temp_dir().join("mqtli_cache_file_descriptor.proto");
+        std::fs::copy(INPUT_PATH_MESSAGE.as_path(), &path).unwrap();
+
+        let descriptor = PayloadFormatProtobuf::get_file_descriptor(&path).unwrap();
+        assert!(descriptor
+            .message_by_package_relative_name("Response")
+            .is_some());
+
+        let definition = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, definition.replace("Response", "Request")).unwrap();
+        let modified = SystemTime::now() + std::time::Duration::from_secs(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let result = PayloadFormatProtobuf::get_file_descriptor(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.message_by_package_relative_name("Request").is_some());
+        assert!(result
+            .message_by_package_relative_name("Response")
+            .is_none());
+    }
+
     fn extract_kind(result: &PayloadFormatProtobuf) -> String {
         let descriptor = result.content.descriptor_dyn();
 
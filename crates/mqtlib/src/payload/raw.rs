@@ -36,6 +36,7 @@ impl TryFrom<PayloadFormat> for PayloadFormatRaw {
             PayloadFormat::Raw(value) => Ok(value),
             PayloadFormat::Protobuf(value) => Ok(Self::from(Vec::<u8>::try_from(value)?)),
             PayloadFormat::Hex(value) => Ok(Self::from(value.decode_from_hex()?)),
+            PayloadFormat::Hexdump(value) => Ok(Self::from(value.decode())),
             PayloadFormat::Base64(value) => Ok(Self::from(value.decode_from_base64()?)),
             PayloadFormat::Json(value) => Ok(Self::from(Vec::<u8>::from(value))),
             PayloadFormat::Yaml(value) => Ok(Self::from(Vec::<u8>::try_from(value)?)),
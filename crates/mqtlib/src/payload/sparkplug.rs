@@ -6,10 +6,13 @@ use protobuf::Message;
 use protobuf_json_mapping::parse_from_str;
 use std::fmt::{Display, Formatter};
 
+mod input;
+
 pub mod protos {
     include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
 }
 
+use crate::payload::sparkplug::input::{is_readable_input, payload_from_readable_input};
 use crate::payload::sparkplug::protos::sparkplug_b::Payload as SparkplugPayload;
 
 #[derive(Clone, Debug, Getters)]
@@ -56,6 +59,23 @@ impl TryFrom<Vec<u8>> for PayloadFormatSparkplug {
     }
 }
 
+/// Creates the payload from JSON, either given in the readable representation
+/// with typed metric values or following the protobuf JSON mapping.
+impl TryFrom<PayloadFormatJson> for PayloadFormatSparkplug {
+    type Error = PayloadFormatError;
+
+    fn try_from(value: PayloadFormatJson) -> Result<Self, Self::Error> {
+        if is_readable_input(value.content()) {
+            return Ok(Self::from(payload_from_readable_input(
+                value.content().clone(),
+            )?));
+        }
+
+        let payload: SparkplugPayload = parse_from_str(value.to_string().as_str())?;
+        Ok(Self::from(payload))
+    }
+}
+
 impl TryFrom<PayloadFormat> for PayloadFormatSparkplug {
     type Error = PayloadFormatError;
 
@@ -65,16 +85,11 @@ impl TryFrom<PayloadFormat> for PayloadFormatSparkplug {
             PayloadFormat::Raw(value) => Ok(Self::try_from(Vec::<u8>::from(value))?),
             PayloadFormat::Protobuf(value) => Ok(Self::try_from(Vec::<u8>::try_from(value)?)?),
             PayloadFormat::Hex(value) => Ok(Self::try_from(value.decode_from_hex()?)?),
+            PayloadFormat::Hexdump(value) => Ok(Self::try_from(value.decode())?),
             PayloadFormat::Base64(value) => Ok(Self::try_from(value.decode_from_base64()?)?),
-            PayloadFormat::Json(value) => {
-                let payload: SparkplugPayload = parse_from_str(value.to_string().as_str())?;
-
-                Ok(Self::from(payload))
-            }
+            PayloadFormat::Json(value) => Self::try_from(value),
             PayloadFormat::Yaml(value) => {
-                let json = PayloadFormatJson::try_from(PayloadFormat::Yaml(value))?;
-                let payload: SparkplugPayload = parse_from_str(json.to_string().as_str())?;
-                Ok(Self::from(payload))
+                Self::try_from(PayloadFormatJson::try_from(PayloadFormat::Yaml(value))?)
             }
             PayloadFormat::Sparkplug(value) => Ok(value),
             PayloadFormat::SparkplugJson(_) => {
@@ -186,6 +201,18 @@ mod tests {
         assert_eq!("humidity", result.content.metrics[0].clone().name.unwrap());
     }
 
+    #[test]
+    fn from_readable_json() {
+        let input = PayloadFormatJson::try_from(Vec::<u8>::from(
+            "{\"metrics\":[{\"name\":\"humidity\",\"type\":\"float\",\"value\":71.9}]}",
+        ))
+        .unwrap();
+        let result = PayloadFormatSparkplug::try_from(PayloadFormat::Json(input)).unwrap();
+
+        assert_eq!("humidity", result.content.metrics[0].clone().name.unwrap());
+        assert_eq!(Some(9), result.content.metrics[0].datatype);
+    }
+
     #[test]
     fn from_yaml() {
         let input = PayloadFormatYaml::try_from(Vec::<u8>::from(INPUT_STRING_YAML)).unwrap();
@@ -0,0 +1,255 @@
+use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value as MetricValue;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::Metric;
+use crate::payload::sparkplug::protos::sparkplug_b::{DataType, Payload};
+use crate::payload::PayloadFormatError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Readable representation of a Sparkplug payload used to publish Sparkplug
+/// messages from JSON or YAML input:
+///
+/// ```json
+/// {
+///   "timestamp": 1700000000000,
+///   "seq": 1,
+///   "metrics": [
+///     { "name": "Temperature", "alias": 1, "type": "double", "value": 21.5 },
+///     { "name": "Running", "value": true, "timestamp": 1700000000000 }
+///   ]
+/// }
+/// ```
+///
+/// The timestamp of the payload defaults to the current time and is used for
+/// all metrics without a timestamp. If the type of a metric is omitted, it is
+/// derived from the value (boolean, int64, double or string). A metric whose
+/// value is null or missing is sent with is_null set; its type must be given.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SparkplugInput {
+    timestamp: Option<u64>,
+    seq: Option<u64>,
+    uuid: Option<String>,
+    #[serde(default)]
+    metrics: Vec<SparkplugMetricInput>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SparkplugMetricInput {
+    name: Option<String>,
+    alias: Option<u64>,
+    timestamp: Option<u64>,
+    #[serde(rename = "type")]
+    datatype: Option<MetricInputType>,
+    #[serde(default)]
+    value: Value,
+    is_historical: Option<bool>,
+    is_transient: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MetricInputType {
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float,
+    Double,
+    Boolean,
+    String,
+    DateTime,
+    Text,
+    Uuid,
+    Bytes,
+}
+
+/// Returns true if the JSON value is given in the readable representation,
+/// i.e. at least one metric has a `value` or `type` attribute. Otherwise the
+/// value is expected to follow the protobuf JSON mapping of the payload.
+pub(crate) fn is_readable_input(value: &Value) -> bool {
+    value
+        .get("metrics")
+        .and_then(Value::as_array)
+        .is_some_and(|metrics| {
+            metrics
+                .iter()
+                .any(|metric| metric.get("value").is_some() || metric.get("type").is_some())
+        })
+}
+
+/// Creates a Sparkplug payload from its readable representation.
+pub(crate) fn payload_from_readable_input(value: Value) -> Result<Payload, PayloadFormatError> {
+    let input: SparkplugInput = serde_json::from_value(value)
+        .map_err(|e| PayloadFormatError::InvalidSparkplugInput(e.to_string()))?;
+
+    let timestamp = input
+        .timestamp
+        .unwrap_or_else(|| Utc::now().timestamp_millis() as u64);
+
+    let mut payload = Payload::new();
+    payload.timestamp = Some(timestamp);
+    payload.seq = input.seq;
+    payload.uuid = input.uuid;
+
+    for metric in input.metrics {
+        payload.metrics.push(metric.into_metric(timestamp)?);
+    }
+
+    Ok(payload)
+}
+
+impl SparkplugMetricInput {
+    fn into_metric(self, timestamp: u64) -> Result<Metric, PayloadFormatError> {
+        let mut metric = Metric::new();
+        metric.timestamp = Some(self.timestamp.unwrap_or(timestamp));
+        metric.is_historical = self.is_historical;
+        metric.is_transient = self.is_transient;
+
+        let name = self
+            .name
+            .clone()
+            .or_else(|| self.alias.map(|alias| format!("alias {alias}")))
+            .unwrap_or_default();
+
+        let datatype = match self.datatype {
+            Some(datatype) => datatype,
+            None => MetricInputType::from_value(&self.value).ok_or_else(|| {
+                PayloadFormatError::InvalidSparkplugInput(format!(
+                    "Type of metric \"{name}\" must be given if its value is null or not a number, boolean or string"
+                ))
+            })?,
+        };
+
+        metric.name = self.name;
+        metric.alias = self.alias;
+        metric.datatype = Some(datatype.data_type() as u32);
+
+        if self.value.is_null() {
+            metric.is_null = Some(true);
+        } else {
+            metric.value = Some(datatype.to_metric_value(&self.value).ok_or_else(|| {
+                PayloadFormatError::InvalidSparkplugInput(format!(
+                    "Value {} of metric \"{name}\" is not of type {datatype:?}",
+                    self.value
+                ))
+            })?);
+        }
+
+        Ok(metric)
+    }
+}
+
+impl MetricInputType {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(_) => Some(Self::Boolean),
+            Value::Number(number) if number.is_f64() => Some(Self::Double),
+            Value::Number(_) => Some(Self::Int64),
+            Value::String(_) => Some(Self::String),
+            _ => None,
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            Self::Int8 => DataType::Int8,
+            Self::Int16 => DataType::Int16,
+            Self::Int32 => DataType::Int32,
+            Self::Int64 => DataType::Int64,
+            Self::UInt8 => DataType::UInt8,
+            Self::UInt16 => DataType::UInt16,
+            Self::UInt32 => DataType::UInt32,
+            Self::UInt64 => DataType::UInt64,
+            Self::Float => DataType::Float,
+            Self::Double => DataType::Double,
+            Self::Boolean => DataType::Boolean,
+            Self::String => DataType::String,
+            Self::DateTime => DataType::DateTime,
+            Self::Text => DataType::Text,
+            Self::Uuid => DataType::UUID,
+            Self::Bytes => DataType::Bytes,
+        }
+    }
+
+    /// Converts the value to the field of the metric value used by the
+    /// Sparkplug specification for this type. Signed integers are stored in
+    /// their two's complement representation.
+    fn to_metric_value(self, value: &Value) -> Option<MetricValue> {
+        Some(match self {
+            Self::Int8 => MetricValue::IntValue(i8::try_from(value.as_i64()?).ok()? as u32),
+            Self::Int16 => MetricValue::IntValue(i16::try_from(value.as_i64()?).ok()? as u32),
+            Self::Int32 => MetricValue::IntValue(i32::try_from(value.as_i64()?).ok()? as u32),
+            Self::UInt8 => MetricValue::IntValue(u8::try_from(value.as_u64()?).ok()? as u32),
+            Self::UInt16 => MetricValue::IntValue(u16::try_from(value.as_u64()?).ok()? as u32),
+            Self::UInt32 => MetricValue::IntValue(u32::try_from(value.as_u64()?).ok()?),
+            Self::Int64 => MetricValue::LongValue(value.as_i64()? as u64),
+            Self::UInt64 | Self::DateTime => MetricValue::LongValue(value.as_u64()?),
+            Self::Float => MetricValue::FloatValue(value.as_f64()? as f32),
+            Self::Double => MetricValue::DoubleValue(value.as_f64()?),
+            Self::Boolean => MetricValue::BooleanValue(value.as_bool()?),
+            Self::String | Self::Text | Self::Uuid => {
+                MetricValue::StringValue(value.as_str()?.to_string())
+            }
+            Self::Bytes => MetricValue::BytesValue(STANDARD.decode(value.as_str()?).ok()?.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn readable_input() {
+        let input = json!({
+            "timestamp": 1700000000000u64,
+            "seq": 3,
+            "metrics": [
+                { "name": "Temperature", "alias": 1, "type": "float", "value": 21.5 },
+                { "name": "Offset", "type": "int16", "value": -2 },
+                { "name": "Running", "value": true, "timestamp": 1700000000001u64 },
+                { "alias": 4, "type": "string", "value": null }
+            ]
+        });
+        assert!(is_readable_input(&input));
+
+        let payload = payload_from_readable_input(input).unwrap();
+        assert_eq!(Some(1700000000000), payload.timestamp);
+        assert_eq!(Some(3), payload.seq);
+
+        let metrics = &payload.metrics;
+        assert_eq!(Some(DataType::Float as u32), metrics[0].datatype);
+        assert_eq!(Some(MetricValue::FloatValue(21.5)), metrics[0].value);
+        assert_eq!(Some(1700000000000), metrics[0].timestamp);
+        assert_eq!(Some(MetricValue::IntValue(-2i16 as u32)), metrics[1].value);
+        assert_eq!(Some(DataType::Boolean as u32), metrics[2].datatype);
+        assert_eq!(Some(1700000000001), metrics[2].timestamp);
+        assert_eq!(Some(4), metrics[3].alias);
+        assert!(metrics[3].is_null());
+    }
+
+    #[test]
+    fn invalid_readable_input() {
+        assert!(payload_from_readable_input(
+            json!({ "metrics": [{ "name": "a", "type": "uint8", "value": 300 }] })
+        )
+        .is_err());
+        assert!(payload_from_readable_input(json!({ "metrics": [{ "name": "a" }] })).is_err());
+        assert!(payload_from_readable_input(
+            json!({ "metrics": [{ "name": "a", "value": 1, "unknown": 1 }] })
+        )
+        .is_err());
+        assert!(!is_readable_input(
+            &json!({ "metrics": [{ "name": "a", "datatype": 9, "floatValue": 1.5 }] })
+        ));
+    }
+}
@@ -1,37 +1,77 @@
+use crate::config::{PayloadText, TextEncoding};
 use crate::payload::{PayloadFormat, PayloadFormatError};
 use derive_getters::Getters;
 use std::fmt::{Display, Formatter};
 
-/// Represents a lossy UTF-8 encoded String.
+/// Represents a lossy encoded String, UTF-8 by default.
 /// Any vector of u8 can be used to construct this String.
-/// Non-UTF-8 characters will be ignored when rendering the
-/// underlying vector as UTF-8.
+/// Characters which are invalid in the encoding will be replaced
+/// when rendering the underlying vector as String.
 #[derive(Clone, Debug, Getters)]
 pub struct PayloadFormatText {
     pub content: Vec<u8>,
+    /// Character encoding of the content
+    encoding: TextEncoding,
 }
 
 impl PayloadFormatText {
-    fn decode_from_utf8(value: String) -> Vec<u8> {
-        value.into_bytes()
+    /// Sets the encoding of the content. The content is not modified.
+    pub fn with_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    fn decode_text(value: &[u8], encoding: TextEncoding) -> String {
+        match encoding {
+            TextEncoding::Utf8 => String::from_utf8_lossy(value).to_string(),
+            TextEncoding::Latin1 => value.iter().map(|byte| char::from(*byte)).collect(),
+            TextEncoding::Utf16Le => Self::decode_utf16(value, u16::from_le_bytes),
+            TextEncoding::Utf16Be => Self::decode_utf16(value, u16::from_be_bytes),
+        }
     }
 
-    fn encode_to_utf8(value: Vec<u8>) -> String {
-        String::from_utf8_lossy(value.as_slice()).to_string()
+    fn decode_utf16(value: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+        let chunks = value.chunks_exact(2);
+        let incomplete = !chunks.remainder().is_empty();
+        let units: Vec<u16> = chunks
+            .map(|chunk| from_bytes([chunk[0], chunk[1]]))
+            .collect();
+
+        let mut text = String::from_utf16_lossy(&units);
+        if incomplete {
+            text.push(char::REPLACEMENT_CHARACTER);
+        }
+        text
+    }
+
+    /// Characters which can't be represented in Latin-1 are replaced by `?`.
+    fn encode_text(value: &str, encoding: TextEncoding) -> Vec<u8> {
+        match encoding {
+            TextEncoding::Utf8 => value.as_bytes().to_vec(),
+            TextEncoding::Latin1 => value
+                .chars()
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect(),
+            TextEncoding::Utf16Le => value.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            TextEncoding::Utf16Be => value.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
     }
 }
 
-/// Displays the UTF-8 encoded content.
+/// Displays the content decoded with its encoding.
 impl Display for PayloadFormatText {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", Self::encode_to_utf8(self.content.clone()))
+        write!(f, "{}", Self::decode_text(&self.content, self.encoding))
     }
 }
 
 /// Encodes the given bytes as UTF-8 string.
 impl From<Vec<u8>> for PayloadFormatText {
     fn from(value: Vec<u8>) -> Self {
-        Self { content: value }
+        Self {
+            content: value,
+            encoding: TextEncoding::default(),
+        }
     }
 }
 
@@ -39,9 +79,7 @@ impl From<Vec<u8>> for PayloadFormatText {
 /// The value is not modified, only moved to the new instance.
 impl From<String> for PayloadFormatText {
     fn from(val: String) -> Self {
-        Self {
-            content: Self::decode_from_utf8(val),
-        }
+        Self::from(val.into_bytes())
     }
 }
 
@@ -53,7 +91,7 @@ impl From<&str> for PayloadFormatText {
     }
 }
 
-/// Converts the utf-8 encoded content to its bytes.
+/// Converts the encoded content to its bytes.
 ///
 /// # Examples
 /// ```
@@ -71,7 +109,7 @@ impl From<PayloadFormatText> for Vec<u8> {
 
 impl From<PayloadFormatText> for String {
     fn from(val: PayloadFormatText) -> Self {
-        PayloadFormatText::encode_to_utf8(val.content)
+        PayloadFormatText::decode_text(&val.content, val.encoding)
     }
 }
 
@@ -81,28 +119,38 @@ impl TryFrom<PayloadFormat> for PayloadFormatText {
     fn try_from(value: PayloadFormat) -> Result<Self, Self::Error> {
         match value {
             PayloadFormat::Text(value) => Ok(value),
-            PayloadFormat::Raw(value) => Ok(Self {
-                content: value.into(),
-            }),
-            PayloadFormat::Protobuf(value) => Ok(Self {
-                content: value.to_string().into_bytes(),
-            }),
-            PayloadFormat::Hex(value) => Ok(Self {
-                content: value.decode_from_hex()?,
-            }),
-            PayloadFormat::Base64(value) => Ok(Self {
-                content: value.decode_from_base64()?,
-            }),
+            PayloadFormat::Raw(value) => Ok(Self::from(Vec::<u8>::from(value))),
+            PayloadFormat::Protobuf(value) => Ok(Self::from(value.render_to_string()?)),
+            PayloadFormat::Hex(value) => Ok(Self::from(value.decode_from_hex()?)),
+            PayloadFormat::Hexdump(value) => Ok(Self::from(value.decode())),
+            PayloadFormat::Base64(value) => Ok(Self::from(value.decode_from_base64()?)),
             PayloadFormat::Json(value) => Ok(Self::from(value.to_string())),
             PayloadFormat::Yaml(value) => Ok(Self::from(value.to_string())),
-            PayloadFormat::Sparkplug(value) => Ok(Self {
-                content: value.to_string().into_bytes(),
-            }),
+            PayloadFormat::Sparkplug(value) => Ok(Self::from(value.to_string())),
             PayloadFormat::SparkplugJson(value) => Ok(Self::from(value.to_string())),
         }
     }
 }
 
+/// Converts the payload to text in the encoding configured for the payload
+/// type.
+impl TryFrom<(PayloadFormat, &PayloadText)> for PayloadFormatText {
+    type Error = PayloadFormatError;
+
+    fn try_from((value, options): (PayloadFormat, &PayloadText)) -> Result<Self, Self::Error> {
+        let text = Self::try_from(value)?;
+        if text.encoding == *options.encoding() {
+            return Ok(text);
+        }
+
+        let encoding = *options.encoding();
+        Ok(Self {
+            content: Self::encode_text(&String::from(text), encoding),
+            encoding,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,6 +207,61 @@ mod tests {
         assert_eq!(get_input(), result.content);
     }
 
+    #[test]
+    fn to_string_with_encoding() {
+        let results = [
+            (TextEncoding::Utf8, vec![0x47, 0x72, 0xc3, 0xbc, 0xc3, 0x9f]),
+            (TextEncoding::Latin1, vec![0x47, 0x72, 0xfc, 0xdf]),
+            (
+                TextEncoding::Utf16Le,
+                vec![0x47, 0x00, 0x72, 0x00, 0xfc, 0x00, 0xdf, 0x00],
+            ),
+            (
+                TextEncoding::Utf16Be,
+                vec![0x00, 0x47, 0x00, 0x72, 0x00, 0xfc, 0x00, 0xdf],
+            ),
+        ];
+
+        for (encoding, content) in results {
+            let input = PayloadFormatText::from(content).with_encoding(encoding);
+
+            assert_eq!("Grüß", String::from(input));
+        }
+    }
+
+    #[test]
+    fn to_string_with_incomplete_utf16() {
+        let input =
+            PayloadFormatText::from(vec![0x47, 0x00, 0x72]).with_encoding(TextEncoding::Utf16Le);
+
+        assert_eq!("G\u{FFFD}", String::from(input));
+    }
+
+    #[test]
+    fn to_encoding() {
+        let input = PayloadFormatText::from(vec![0x47, 0x72, 0xfc, 0xdf])
+            .with_encoding(TextEncoding::Latin1);
+        let options: PayloadText = serde_yaml::from_str("encoding: utf-16be").unwrap();
+
+        let result = PayloadFormatText::try_from((PayloadFormat::Text(input), &options)).unwrap();
+
+        assert_eq!(
+            vec![0x00, 0x47, 0x00, 0x72, 0x00, 0xfc, 0x00, 0xdf],
+            result.content
+        );
+        assert_eq!("Grüß", result.to_string());
+    }
+
+    #[test]
+    fn to_latin1_with_unsupported_characters() {
+        let input = PayloadFormatText::from("a€");
+        let options: PayloadText = serde_yaml::from_str("encoding: latin1").unwrap();
+
+        let result = PayloadFormatText::try_from((PayloadFormat::Text(input), &options)).unwrap();
+
+        assert_eq!(b"a?".to_vec(), result.content);
+    }
+
     #[test]
     fn to_vec_u8_into() {
         let input = PayloadFormatText::try_from(get_input()).unwrap();
@@ -1,24 +1,46 @@
 use std::fmt::{Display, Formatter};
 
 use derive_getters::Getters;
-use serde_yaml::{from_slice, Value};
+use serde::Deserialize;
+use serde_yaml::{Deserializer, Value};
 use tracing::error;
 
 use crate::payload::json::PayloadFormatJson;
 use crate::payload::{PayloadFormat, PayloadFormatError};
 
+/// YAML payload. Payloads with multiple documents are represented as a
+/// sequence of the documents, so that they convert to a JSON array.
 #[derive(Clone, Debug, Getters)]
 pub struct PayloadFormatYaml {
     content: Value,
+    /// The content is a sequence of multiple documents
+    multi_document: bool,
 }
 
 impl PayloadFormatYaml {
     fn decode_from_yaml_payload(&self) -> serde_yaml::Result<String> {
-        serde_yaml::to_string(&self.content)
+        match (&self.content, self.multi_document) {
+            (Value::Sequence(documents), true) => documents
+                .iter()
+                .map(serde_yaml::to_string)
+                .collect::<serde_yaml::Result<Vec<String>>>()
+                .map(|documents| documents.join("---\n")),
+            (content, _) => serde_yaml::to_string(content),
+        }
+    }
+
+    fn encode_to_yaml(value: Vec<u8>) -> serde_yaml::Result<Vec<Value>> {
+        Deserializer::from_slice(value.as_slice())
+            .map(Value::deserialize)
+            .collect()
     }
 
-    fn encode_to_yaml(value: Vec<u8>) -> serde_yaml::Result<Value> {
-        from_slice(value.as_slice())
+    /// Splits the payload into one payload per document.
+    pub fn split_documents(self) -> Vec<Self> {
+        match (self.content, self.multi_document) {
+            (Value::Sequence(documents), true) => documents.into_iter().map(Self::from).collect(),
+            (content, _) => vec![Self::from(content)],
+        }
     }
 }
 
@@ -38,12 +60,20 @@ impl Display for PayloadFormatYaml {
     }
 }
 
+/// Parses the YAML, which may contain multiple documents separated by `---`.
 impl TryFrom<Vec<u8>> for PayloadFormatYaml {
     type Error = PayloadFormatError;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        Ok(Self {
-            content: Self::encode_to_yaml(value)?,
+        let mut documents = Self::encode_to_yaml(value)?;
+
+        Ok(match documents.len() {
+            0 => Self::from(Value::Null),
+            1 => Self::from(documents.remove(0)),
+            _ => Self {
+                content: Value::Sequence(documents),
+                multi_document: true,
+            },
         })
     }
 }
@@ -58,7 +88,10 @@ impl TryFrom<String> for PayloadFormatYaml {
 
 impl From<Value> for PayloadFormatYaml {
     fn from(val: Value) -> Self {
-        Self { content: val }
+        Self {
+            content: val,
+            multi_document: false,
+        }
     }
 }
 
@@ -90,6 +123,7 @@ impl TryFrom<PayloadFormat> for PayloadFormatYaml {
                 Self::try_from(PayloadFormat::Json(json))
             }
             PayloadFormat::Hex(value) => Self::try_from(value.decode_from_hex()?),
+            PayloadFormat::Hexdump(value) => Self::try_from(value.decode()),
             PayloadFormat::Base64(value) => Self::try_from(value.decode_from_base64()?),
             PayloadFormat::Yaml(value) => Ok(value),
             PayloadFormat::Json(value) => Ok(Self::from(serde_json::from_value::<Value>(
@@ -151,6 +185,42 @@ mod tests {
         assert_eq!(get_input_yaml_value(), result.content);
     }
 
+    #[test]
+    fn from_multiple_documents() {
+        let input = PayloadFormatYaml::try_from("a: 1\n---\nb: 2\n".to_string()).unwrap();
+
+        assert!(input.multi_document);
+        assert_eq!(
+            from_str::<Value>("[{a: 1}, {b: 2}]").unwrap(),
+            input.content
+        );
+        assert_eq!("a: 1\n---\nb: 2\n", input.to_string());
+
+        let json = PayloadFormatJson::try_from(PayloadFormat::Yaml(input)).unwrap();
+        assert_eq!("[{\"a\":1},{\"b\":2}]", json.to_string());
+    }
+
+    #[test]
+    fn split_documents() {
+        let input = PayloadFormatYaml::try_from("a: 1\n---\nb: 2\n".to_string()).unwrap();
+
+        let result = input.split_documents();
+
+        assert_eq!(2, result.len());
+        assert_eq!("a: 1\n", result[0].to_string());
+        assert_eq!("b: 2\n", result[1].to_string());
+    }
+
+    #[test]
+    fn split_single_sequence() {
+        let input = PayloadFormatYaml::try_from("- a\n- b\n".to_string()).unwrap();
+
+        let result = input.split_documents();
+
+        assert_eq!(1, result.len());
+        assert_eq!("- a\n- b\n", result[0].to_string());
+    }
+
     #[test]
     fn to_vec_u8_into() {
         let input = PayloadFormatYaml::try_from(get_input_yaml_vec()).unwrap();
@@ -0,0 +1,202 @@
+use crate::config::ping::Ping;
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService};
+use crate::MqtlibError;
+use colored::Colorize;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{debug, warn};
+
+/// Latencies measured by [`run_ping`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PingResult {
+    /// Time from connecting until the broker acknowledged the connection
+    pub connect: Duration,
+    /// Round trip times of the messages which came back
+    pub round_trips: Vec<Duration>,
+    /// Number of messages which didn't come back in time
+    pub lost: usize,
+}
+
+impl PingResult {
+    pub fn min(&self) -> Option<Duration> {
+        self.round_trips.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.round_trips.iter().max().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        let count = u32::try_from(self.round_trips.len())
+            .ok()
+            .filter(|c| *c > 0)?;
+        Some(self.round_trips.iter().sum::<Duration>() / count)
+    }
+}
+
+impl Display for PingResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", " Ping ".on_blue())?;
+        writeln!(f, "connect: {}", format_duration(self.connect))?;
+        writeln!(
+            f,
+            "messages: {} sent, {} received, {} lost",
+            self.round_trips.len() + self.lost,
+            self.round_trips.len(),
+            self.lost
+        )?;
+
+        match (self.min(), self.avg(), self.max()) {
+            (Some(min), Some(avg), Some(max)) => write!(
+                f,
+                "round trip min/avg/max: {}/{}/{}",
+                format_duration(min),
+                format_duration(avg),
+                format_duration(max)
+            ),
+            _ => write!(f, "round trip min/avg/max: -"),
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Connects to the broker, subscribes to the ping topic and publishes the
+/// configured number of messages on it, measuring the time until each
+/// message is received again.
+pub async fn run_ping(
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    ping: &Ping,
+    capacity: usize,
+) -> Result<PingResult, MqtlibError> {
+    let (sender_receive, mut receiver) = broadcast::channel::<MqttReceiveEvent>(capacity);
+    let (sender_disconnect, _) = broadcast::channel::<()>(1);
+    let mut result = PingResult::default();
+
+    let started_at = Instant::now();
+    let mqtt_loop_handle = mqtt_service
+        .lock()
+        .await
+        .connect(sender_receive.clone(), sender_disconnect.subscribe())
+        .await?;
+
+    wait_for(&mut receiver, *ping.timeout(), |event| event.is_connect())
+        .await
+        .ok_or(MqtlibError::Disconnected)?;
+    result.connect = started_at.elapsed();
+
+    mqtt_service
+        .lock()
+        .await
+        .subscribe(ping.topic().clone(), *ping.qos())
+        .await?;
+    wait_for(&mut receiver, *ping.timeout(), |event| {
+        event.is_subscribe_ack()
+    })
+    .await
+    .ok_or(MqtlibError::Disconnected)?;
+
+    for index in 0..*ping.count() {
+        if index > 0 {
+            tokio::time::sleep(*ping.interval()).await;
+        }
+
+        let payload = format!("mqtli ping {index}").into_bytes();
+        let sent_at = Instant::now();
+        mqtt_service
+            .lock()
+            .await
+            .publish(MessagePublishData::new(
+                ping.topic().clone(),
+                *ping.qos(),
+                false,
+                payload.clone(),
+            ))
+            .await;
+
+        let received = wait_for(&mut receiver, *ping.timeout(), |event| {
+            event
+                .incoming_publish()
+                .is_some_and(|(topic, _, _, received)| {
+                    topic == *ping.topic() && received == payload
+                })
+        })
+        .await;
+
+        match received {
+            Some(()) => {
+                let round_trip = sent_at.elapsed();
+                debug!("Message {index} came back after {round_trip:?}");
+                result.round_trips.push(round_trip);
+            }
+            None => {
+                warn!(
+                    "Message {index} did not come back within {:?}",
+                    ping.timeout()
+                );
+                result.lost += 1;
+            }
+        }
+    }
+
+    let _ = mqtt_service
+        .lock()
+        .await
+        .unsubscribe(ping.topic().clone())
+        .await;
+    let _ = sender_disconnect.send(());
+    mqtt_loop_handle
+        .await
+        .expect("Error while waiting for the connection to close");
+
+    Ok(result)
+}
+
+/// Waits until an event matches or the timeout elapsed. Returns None on
+/// timeout or if the connection was closed.
+pub(crate) async fn wait_for(
+    receiver: &mut Receiver<MqttReceiveEvent>,
+    timeout: Duration,
+    mut matches: impl FnMut(&MqttReceiveEvent) -> bool,
+) -> Option<()> {
+    tokio::time::timeout(timeout, async {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if matches(&event) => return Some(()),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics() {
+        let result = PingResult {
+            connect: Duration::from_millis(12),
+            round_trips: vec![
+                Duration::from_millis(2),
+                Duration::from_millis(6),
+                Duration::from_millis(4),
+            ],
+            lost: 1,
+        };
+
+        assert_eq!(Some(Duration::from_millis(2)), result.min());
+        assert_eq!(Some(Duration::from_millis(4)), result.avg());
+        assert_eq!(Some(Duration::from_millis(6)), result.max());
+        assert_eq!(None, PingResult::default().avg());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::config::mqtli_config::MqtliConfig;
+use crate::config::proto_check::ProtoCheck;
+use crate::config::{PayloadProtobuf, PayloadType};
+use crate::output::theme::{Role, Themed};
+use crate::payload::protobuf::PayloadFormatProtobuf;
+use crate::MqtlibError;
+use colored::Colorize;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref IMPORT: Regex =
+        Regex::new(r#"^\s*import\s+(?:public\s+|weak\s+)?"([^"]+)"\s*;"#).unwrap();
+}
+
+/// Imports of well-known types, which are bundled with the parser.
+const BUNDLED_IMPORT_PREFIX: &str = "google/protobuf/";
+
+/// Place in the configuration where a protobuf payload type is used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProtoReference {
+    /// Where the payload type is configured, e.g. topic sensors/# (payload)
+    pub location: String,
+    pub options: PayloadProtobuf,
+}
+
+/// Findings of [`run_proto_check`] for one reference.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProtoReferenceResult {
+    pub location: String,
+    pub definition: PathBuf,
+    /// Messages found in the definition and the message the sample was
+    /// decoded as
+    pub passed: Vec<String>,
+    pub problems: Vec<String>,
+}
+
+/// Results of all references of the configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProtoCheckResult {
+    pub references: Vec<ProtoReferenceResult>,
+}
+
+impl ProtoCheckResult {
+    pub fn is_success(&self) -> bool {
+        self.references
+            .iter()
+            .all(|reference| reference.problems.is_empty())
+    }
+}
+
+impl Display for ProtoCheckResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", " Protobuf check ".themed(Role::Title))?;
+
+        for reference in &self.references {
+            writeln!(
+                f,
+                "{} ({})",
+                reference.location.as_str().themed(Role::Name),
+                reference.definition.display()
+            )?;
+            for passed in &reference.passed {
+                writeln!(f, "  {} {passed}", "ok".themed(Role::Added))?;
+            }
+            for problem in &reference.problems {
+                writeln!(f, "  {} {problem}", "failed".themed(Role::Removed))?;
+            }
+        }
+
+        match (self.references.is_empty(), self.is_success()) {
+            (true, _) => write!(
+                f,
+                "{}",
+                "No protobuf definitions referenced".themed(Role::Hint)
+            ),
+            (false, true) => write!(f, "{}", "Protobuf check passed".themed(Role::Added).bold()),
+            (false, false) => write!(
+                f,
+                "{}",
+                "Protobuf check failed".themed(Role::Removed).bold()
+            ),
+        }
+    }
+}
+
+/// Loads every protobuf definition referenced from the configuration,
+/// verifies the configured messages exist and decodes the sample payload, if
+/// given, with the messages of each reference.
+pub fn run_proto_check(
+    config: &MqtliConfig,
+    check: &ProtoCheck,
+) -> Result<ProtoCheckResult, MqtlibError> {
+    let sample = match check.sample() {
+        Some(path) => Some(
+            std::fs::read(path)
+                .map_err(|e| MqtlibError::CouldNotReadProtoSample(e, path.clone()))?,
+        ),
+        None => None,
+    };
+
+    let references = collect_references(config, check.topic().as_deref());
+    let mut imports: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+
+    Ok(ProtoCheckResult {
+        references: references
+            .into_iter()
+            .map(|reference| {
+                let unresolved = imports
+                    .entry(reference.options.definition().clone())
+                    .or_insert_with_key(|definition| unresolved_imports(definition));
+                check_reference(reference, unresolved, sample.as_deref())
+            })
+            .collect(),
+    })
+}
+
+/// Returns the protobuf payload types of the topics, their payload
+/// overrides and outputs and of the bridge rules, optionally only those of
+/// the given topic.
+pub fn collect_references(config: &MqtliConfig, topic: Option<&str>) -> Vec<ProtoReference> {
+    let mut types: Vec<(String, &PayloadType)> = vec![];
+
+    for entry in &config.topic_storage.topics {
+        if topic.is_some_and(|topic| topic != entry.topic) {
+            continue;
+        }
+
+        types.push((
+            format!("topic {} (payload)", entry.topic),
+            &entry.payload_type,
+        ));
+        for payload_override in &entry.payload_overrides {
+            types.push((
+                format!(
+                    "topic {} (payload override {})",
+                    entry.topic, payload_override.topic
+                ),
+                &payload_override.payload_type,
+            ));
+        }
+        if let Some(subscription) = &entry.subscription {
+            for (index, output) in subscription.outputs.iter().enumerate() {
+                types.push((
+                    format!("topic {} (output {})", entry.topic, index + 1),
+                    &output.format,
+                ));
+            }
+        }
+    }
+
+    if topic.is_none() {
+        for (index, rule) in config.bridge.rules.iter().enumerate() {
+            types.push((
+                format!("bridge rule {} (payload)", index + 1),
+                &rule.payload_type,
+            ));
+            if let Some(format) = &rule.format {
+                types.push((format!("bridge rule {} (format)", index + 1), format));
+            }
+        }
+    }
+
+    types
+        .into_iter()
+        .filter_map(|(location, payload_type)| match payload_type {
+            PayloadType::Protobuf(options) => Some(ProtoReference {
+                location,
+                options: options.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn check_reference(
+    reference: ProtoReference,
+    unresolved_imports: &[String],
+    sample: Option<&[u8]>,
+) -> ProtoReferenceResult {
+    let definition = reference.options.definition();
+    let mut result = ProtoReferenceResult {
+        location: reference.location.clone(),
+        definition: definition.clone(),
+        ..Default::default()
+    };
+
+    if !definition.is_file() {
+        result
+            .problems
+            .push("Definition file not found".to_string());
+        return result;
+    }
+
+    if !unresolved_imports.is_empty() {
+        result.problems.extend(
+            unresolved_imports
+                .iter()
+                .map(|import| format!("Unresolved import \"{import}\"")),
+        );
+        return result;
+    }
+
+    let file_descriptor = match PayloadFormatProtobuf::get_file_descriptor(definition) {
+        Ok(file_descriptor) => file_descriptor,
+        Err(e) => {
+            result.problems.push(e.to_string());
+            return result;
+        }
+    };
+
+    let candidates: Vec<&String> = reference.options.candidates().collect();
+    if candidates.is_empty() {
+        result.problems.push("No message configured".to_string());
+        return result;
+    }
+
+    for message in candidates {
+        match file_descriptor.message_by_package_relative_name(message) {
+            Some(_) => result.passed.push(format!("Message {message} found")),
+            None => result.problems.push(format!(
+                "Message {message} not found; defined messages: {}",
+                file_descriptor
+                    .messages()
+                    .map(|message| message.name().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    if let (Some(sample), true) = (sample, result.problems.is_empty()) {
+        match PayloadFormatProtobuf::new_from_candidates(sample.to_vec(), &reference.options) {
+            Ok(payload) => result
+                .passed
+                .push(format!("Sample decoded as {}", payload.message_name())),
+            Err(e) => result
+                .problems
+                .push(format!("Could not decode the sample: {e}")),
+        }
+    }
+
+    result
+}
+
+/// Returns the imports of the definition which can't be found relative to
+/// its directory, which is the include path when parsing it. Imports of
+/// imported files are checked as well.
+fn unresolved_imports(definition: &Path) -> Vec<String> {
+    let Some(include_path) = definition.parent() else {
+        return vec![];
+    };
+
+    let mut unresolved = vec![];
+    let mut visited = vec![];
+    let mut pending = vec![definition.to_path_buf()];
+
+    while let Some(path) = pending.pop() {
+        if visited.contains(&path) {
+            continue;
+        }
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+
+        for import in imports(&content) {
+            if import.starts_with(BUNDLED_IMPORT_PREFIX) {
+                continue;
+            }
+            let import_path = include_path.join(&import);
+            if import_path.is_file() {
+                pending.push(import_path);
+            } else if !unresolved.contains(&import) {
+                unresolved.push(import);
+            }
+        }
+        visited.push(path);
+    }
+
+    unresolved
+}
+
+fn imports(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| IMPORT.captures(line))
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(message: &str) -> ProtoReference {
+        let options: PayloadProtobuf = serde_yaml::from_str(
+            format!("definition: test/data/message.proto\nmessage: {message}").as_str(),
+        )
+        .unwrap();
+
+        ProtoReference {
+            location: "topic test (payload)".to_string(),
+            options,
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let result = check_reference(reference("Response"), &[], None);
+        assert_eq!(vec!["Message Response found".to_string()], result.passed);
+        assert!(result.problems.is_empty());
+
+        let result = check_reference(reference("Missing"), &[], None);
+        assert_eq!(1, result.problems.len());
+        assert!(result.problems[0].starts_with("Message Missing not found"));
+    }
+
+    #[test]
+    fn sample() {
+        let sample = hex::decode("082012080a066b696e646f66").unwrap();
+
+        let result = check_reference(reference("Response"), &[], Some(&sample));
+        assert_eq!(
+            Some(&"Sample decoded as Proto.Response".to_string()),
+            result.passed.last()
+        );
+    }
+
+    #[test]
+    fn import_statements() {
+        let content = "syntax = \"proto3\";\nimport \"common.proto\";\n  import public \"types/base.proto\" ;\n// import \"commented.proto\";\n";
+
+        assert_eq!(
+            vec!["common.proto".to_string(), "types/base.proto".to_string()],
+            imports(content)
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::process::Stdio;
+
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::config::{PayloadType, PublishInputTypeCommand};
+use crate::payload::{PayloadFormat, PayloadFormatError};
+
+/// Runs the command of a command input and reads its output.
+#[derive(Clone, Debug)]
+pub struct CommandInput {
+    input: PublishInputTypeCommand,
+}
+
+impl CommandInput {
+    pub fn new(input: PublishInputTypeCommand) -> Self {
+        Self { input }
+    }
+
+    /// Runs the command in the shell and converts its output to the
+    /// configured format. The trailing line break is removed unless the
+    /// format is raw. Stderr is passed through.
+    pub async fn fetch(&self) -> Result<PayloadFormat, PayloadFormatError> {
+        let command = self.input.command();
+        debug!("Running input command \"{command}\"");
+
+        let output = tokio::time::timeout(*self.input.timeout(), shell(command).output())
+            .await
+            .map_err(|_| {
+                PayloadFormatError::CommandInputTimedOut(command.clone(), *self.input.timeout())
+            })?
+            .map_err(|e| PayloadFormatError::CommandInputNotExecutable(e, command.clone()))?;
+
+        if !output.status.success() {
+            return Err(PayloadFormatError::CommandInputFailed(
+                command.clone(),
+                output.status,
+            ));
+        }
+
+        let format = self
+            .input
+            .format()
+            .clone()
+            .unwrap_or(PayloadType::Text(Default::default()));
+
+        let mut content = output.stdout;
+        if format != PayloadType::Raw {
+            trim_line_break(&mut content);
+        }
+
+        PayloadFormat::try_from((format, content))
+    }
+}
+
+pub(crate) fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    shell
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true);
+
+    shell
+}
+
+fn trim_line_break(content: &mut Vec<u8>) {
+    if content.ends_with(b"\n") {
+        content.pop();
+        if content.ends_with(b"\r") {
+            content.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PublishInputTypeContentPath;
+
+    fn command_input(command: &str) -> CommandInput {
+        CommandInput::new(PublishInputTypeCommand::from(PublishInputTypeContentPath {
+            content: Some(Vec::from(command)),
+            path: None,
+        }))
+    }
+
+    #[cfg(not(windows))]
+    #[tokio::test]
+    async fn fetch_output() {
+        let input = command_input("echo hello");
+
+        let PayloadFormat::Text(result) = input.fetch().await.unwrap() else {
+            panic!()
+        };
+        assert_eq!("hello", result.to_string());
+    }
+
+    #[cfg(not(windows))]
+    #[tokio::test]
+    async fn fetch_failed() {
+        let input = command_input("exit 3");
+
+        assert!(matches!(
+            input.fetch().await,
+            Err(PayloadFormatError::CommandInputFailed(_, _))
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::PublishInputType;
+use crate::payload::PayloadFormatError;
+use crate::template;
+use std::path::{Path, PathBuf};
+
+/// File of a directory tree together with the topic it is published on.
+#[derive(Clone, Debug)]
+pub struct DirectoryTreeFile {
+    pub topic: String,
+    pub input: PublishInputType,
+}
+
+/// Walks the directory recursively and derives the topic of every file from
+/// the template, sorted by path. The template may contain placeholders which
+/// are expanded from the path relative to the directory:
+///
+/// - `{{path}}`: relative path without the extension, e.g. `app/db`
+/// - `{{name}}`: file name without the extension, e.g. `db`
+/// - `{{extension}}`: extension of the file, e.g. `json`
+///
+/// Other placeholders are kept, so they are expanded when publishing. The
+/// format of each file is derived from its extension.
+pub fn read_directory_tree(
+    root: &Path,
+    topic_template: &str,
+) -> Result<Vec<DirectoryTreeFile>, PayloadFormatError> {
+    let mut paths = Vec::new();
+    collect_files(root, &mut paths)?;
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(PayloadFormatError::DirectoryIsEmpty(root.to_path_buf()));
+    }
+
+    Ok(paths
+        .into_iter()
+        .map(|path| DirectoryTreeFile {
+            topic: render_topic(topic_template, path.strip_prefix(root).unwrap_or(&path)),
+            input: PublishInputType::from_file(&path),
+        })
+        .collect())
+}
+
+fn collect_files(directory: &Path, paths: &mut Vec<PathBuf>) -> Result<(), PayloadFormatError> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|e| PayloadFormatError::CannotReadDirectory(e, directory.to_path_buf()))?;
+
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else if path.is_file() {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn render_topic(template: &str, relative: &Path) -> String {
+    let name = relative
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = relative
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path = relative
+        .with_extension("")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/");
+
+    // the other placeholders are expanded by the topic template per message
+    template::render(template, |placeholder| match placeholder {
+        "path" => Some(path.clone()),
+        "name" => Some(name.clone()),
+        "extension" => Some(extension.clone()),
+        _ => None,
+    })
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        assert_eq!(
+            "data/app/db/db.json",
+            render_topic(
+                "data/{{path}}/{{name}}.{{extension}}",
+                Path::new("app/db.json")
+            )
+        );
+        assert_eq!(
+            "data/readme/{{counter}}",
+            render_topic("data/{{path}}/{{counter}}", Path::new("readme"))
+        );
+    }
+
+    #[test]
+    fn directory_tree() {
+        let root = std::env::temp_dir().join(format!("mqtli-tree-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        std::fs::write(root.join("app/db.json"), "{}").unwrap();
+        std::fs::write(root.join("version.txt"), "1").unwrap();
+
+        let files = read_directory_tree(&root, "config/{{path}}").unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(2, files.len());
+        assert_eq!("config/app/db", files[0].topic);
+        assert!(matches!(files[0].input, PublishInputType::Json(_)));
+        assert_eq!("config/version", files[1].topic);
+        assert!(matches!(files[1].input, PublishInputType::Text(_)));
+    }
+}
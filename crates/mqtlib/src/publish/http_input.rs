@@ -0,0 +1,71 @@
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use tracing::debug;
+
+use crate::config::PublishInputTypeHttp;
+use crate::payload::{PayloadFormat, PayloadFormatError};
+
+/// Fetches the payload of an HTTP input. The client is reused for all
+/// requests, so that connections are kept alive between triggers.
+#[derive(Clone, Debug)]
+pub struct HttpInput {
+    client: Client,
+    input: PublishInputTypeHttp,
+}
+
+impl HttpInput {
+    pub fn new(input: PublishInputTypeHttp) -> Result<Self, PayloadFormatError> {
+        let client = Client::builder()
+            .timeout(*input.timeout())
+            .build()
+            .map_err(|e| PayloadFormatError::CouldNotFetchHttpInput(e, input.url().clone()))?;
+
+        Ok(Self { client, input })
+    }
+
+    /// Requests the URL and converts the response body to the configured
+    /// format. Responses with a status other than success are an error.
+    pub async fn fetch(&self) -> Result<PayloadFormat, PayloadFormatError> {
+        let url = self.input.url();
+        debug!("Fetching payload from {url}");
+
+        let mut request = self.client.get(url);
+        for (name, value) in self.input.headers() {
+            request = request.header(name, value);
+        }
+        if let Some(username) = self.input.username() {
+            request = request.basic_auth(username, self.input.password().as_ref());
+        }
+        if let Some(token) = self.input.bearer_token() {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PayloadFormatError::CouldNotFetchHttpInput(e, url.clone()))?;
+
+        if !response.status().is_success() {
+            return Err(PayloadFormatError::HttpInputRequestFailed(
+                url.clone(),
+                response.status(),
+            ));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| PayloadFormatError::CouldNotFetchHttpInput(e, url.clone()))?
+            .to_vec();
+
+        match self.input.format() {
+            Some(format) => PayloadFormat::try_from((format.clone(), body)),
+            None => PayloadFormat::from_content_type(content_type.as_deref(), body),
+        }
+    }
+}
@@ -1,8 +1,17 @@
+use std::io;
+use std::net::SocketAddr;
 use thiserror::Error;
 use tokio_cron_scheduler::JobSchedulerError;
 
 use crate::payload::PayloadFormatError;
 
+pub mod command_input;
+pub mod directory_tree;
+pub mod http_input;
+pub mod payload_sequence;
+pub mod topic_template;
+pub mod trigger_http;
+pub mod trigger_on_message;
 pub mod trigger_periodic;
 
 #[derive(Error, Debug)]
@@ -11,6 +20,10 @@ pub enum TriggerError {
     CouldNotConvertPayload(#[source] PayloadFormatError),
     #[error("Job scheduling error")]
     JobSchedulerError(#[from] JobSchedulerError),
+    #[error("Could not bind HTTP API to address {1}")]
+    CouldNotBindHttpApi(#[source] io::Error, SocketAddr),
+    #[error("Periodic trigger was already started")]
+    AlreadyStarted,
 }
 
 impl From<PayloadFormatError> for TriggerError {
@@ -0,0 +1,184 @@
+use crate::mqtt::QoS;
+use crate::publish::topic_template::TopicTemplate;
+use futures::future::BoxFuture;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Result of asking a sequence for the payload of a firing trigger.
+#[derive(Debug, PartialEq)]
+pub enum NextPayload {
+    Payload(SequencePayload),
+    /// No payload for this firing, e.g. because fetching it failed
+    Skip,
+    /// The end of the sequence was reached
+    End,
+}
+
+/// Payload of a sequence. The topic and QoS are set if they were read from
+/// the input along with the payload and take precedence over the ones of
+/// the topic and the publish.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SequencePayload {
+    pub payload: Vec<u8>,
+    pub topic: Option<String>,
+    pub qos: Option<QoS>,
+}
+
+impl SequencePayload {
+    /// Topic to publish the payload to.
+    pub fn topic(&self, template: &TopicTemplate) -> String {
+        match &self.topic {
+            Some(topic) => topic.clone(),
+            None => template.render(&self.payload),
+        }
+    }
+
+    /// QoS to publish the payload with.
+    pub fn qos(&self, qos: QoS) -> QoS {
+        self.qos.unwrap_or(qos)
+    }
+}
+
+impl From<Vec<u8>> for SequencePayload {
+    fn from(payload: Vec<u8>) -> Self {
+        Self {
+            payload,
+            ..Self::default()
+        }
+    }
+}
+
+/// Function fetching the payload each time the trigger fires.
+pub type FetchPayload = Arc<dyn Fn() -> BoxFuture<'static, NextPayload> + Send + Sync>;
+
+#[derive(Clone)]
+enum PayloadSource {
+    Payloads(Arc<Vec<SequencePayload>>),
+    Fetch(FetchPayload),
+}
+
+impl Debug for PayloadSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadSource::Payloads(payloads) => f.debug_tuple("Payloads").field(payloads).finish(),
+            PayloadSource::Fetch(_) => f.write_str("Fetch"),
+        }
+    }
+}
+
+/// Payloads published by a trigger, one per firing of the trigger. Clones
+/// share the same position, so a sequence can be handed to several jobs.
+#[derive(Clone, Debug)]
+pub struct PayloadSequence {
+    source: PayloadSource,
+    position: Arc<AtomicUsize>,
+    repeat: bool,
+}
+
+impl PayloadSequence {
+    pub fn new(payloads: Vec<Vec<u8>>, repeat: bool) -> Self {
+        Self::with_payloads(
+            payloads.into_iter().map(SequencePayload::from).collect(),
+            repeat,
+        )
+    }
+
+    /// Creates a sequence of payloads which may have their own topic and
+    /// QoS.
+    pub fn with_payloads(payloads: Vec<SequencePayload>, repeat: bool) -> Self {
+        Self {
+            source: PayloadSource::Payloads(Arc::new(payloads)),
+            position: Arc::new(AtomicUsize::new(0)),
+            repeat,
+        }
+    }
+
+    /// Creates a sequence which always yields the same payload.
+    pub fn single(payload: Vec<u8>) -> Self {
+        Self::new(vec![payload], true)
+    }
+
+    /// Creates a sequence which calls the function for every payload.
+    pub fn fetched(fetch: FetchPayload) -> Self {
+        Self {
+            source: PayloadSource::Fetch(fetch),
+            position: Arc::new(AtomicUsize::new(0)),
+            repeat: true,
+        }
+    }
+
+    /// Returns the next payload, or the end if the end of the sequence was
+    /// reached and the sequence does not repeat.
+    pub async fn next_payload(&self) -> NextPayload {
+        let payloads = match &self.source {
+            PayloadSource::Payloads(payloads) => payloads,
+            PayloadSource::Fetch(fetch) => return fetch().await,
+        };
+
+        if payloads.is_empty() {
+            return NextPayload::End;
+        }
+
+        let position = self.position.fetch_add(1, Ordering::Relaxed);
+
+        let payload = if self.repeat {
+            payloads.get(position % payloads.len())
+        } else {
+            payloads.get(position)
+        };
+
+        match payload {
+            Some(payload) => NextPayload::Payload(payload.clone()),
+            None => NextPayload::End,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn payload(value: &str) -> NextPayload {
+        NextPayload::Payload(Vec::from(value).into())
+    }
+
+    #[tokio::test]
+    async fn single_repeats() {
+        let sequence = PayloadSequence::single(Vec::from("a"));
+
+        assert_eq!(payload("a"), sequence.next_payload().await);
+        assert_eq!(payload("a"), sequence.next_payload().await);
+    }
+
+    #[tokio::test]
+    async fn loop_and_stop() {
+        let payloads = vec![Vec::from("a"), Vec::from("b")];
+
+        let sequence = PayloadSequence::new(payloads.clone(), true);
+        let shared = sequence.clone();
+        assert_eq!(payload("a"), sequence.next_payload().await);
+        assert_eq!(payload("b"), shared.next_payload().await);
+        assert_eq!(payload("a"), sequence.next_payload().await);
+
+        let sequence = PayloadSequence::new(payloads, false);
+        assert_eq!(payload("a"), sequence.next_payload().await);
+        assert_eq!(payload("b"), sequence.next_payload().await);
+        assert_eq!(NextPayload::End, sequence.next_payload().await);
+    }
+
+    #[tokio::test]
+    async fn fetched() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let fetch_counter = counter.clone();
+        let sequence = PayloadSequence::fetched(Arc::new(move || {
+            let value = fetch_counter.fetch_add(1, Ordering::Relaxed);
+            async move { NextPayload::Payload(value.to_string().into_bytes().into()) }.boxed()
+        }));
+
+        assert_eq!(payload("0"), sequence.next_payload().await);
+        assert_eq!(payload("1"), sequence.clone().next_payload().await);
+        assert_eq!(2, counter.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use jsonpath_rust::JsonPath;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::config::PayloadType;
+use crate::payload::PayloadFormat;
+use crate::template;
+
+/// Publish topic which may contain placeholders, expanded for every message,
+/// besides the ones of [`crate::template`]:
+///
+/// - `{{counter}}`: number of the message, starting at 1
+/// - `{{$.path}}`: value selected by the JSONPath from the payload
+///
+/// Clones share the same counter.
+#[derive(Clone, Debug)]
+pub struct TopicTemplate {
+    template: String,
+    payload_type: PayloadType,
+    counter: Arc<AtomicU64>,
+}
+
+impl TopicTemplate {
+    pub fn new(template: &str, payload_type: &PayloadType) -> Self {
+        Self {
+            template: template.to_owned(),
+            payload_type: payload_type.clone(),
+            counter: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn payload_type(&self) -> &PayloadType {
+        &self.payload_type
+    }
+
+    /// Expands all placeholders of the template. Unknown placeholders and
+    /// JSONPath expressions not matching the payload expand to an empty
+    /// string.
+    pub fn render(&self, payload: &[u8]) -> String {
+        let mut counter: Option<u64> = None;
+        let mut json: Option<Option<Value>> = None;
+
+        template::render(&self.template, |name| match name {
+            "counter" => {
+                let value =
+                    *counter.get_or_insert_with(|| self.counter.fetch_add(1, Ordering::Relaxed));
+                Some(value.to_string())
+            }
+            path if path.starts_with('$') => {
+                let json = json.get_or_insert_with(|| self.payload_as_json(payload));
+                Some(Self::query(json.as_ref(), path))
+            }
+            placeholder => Some(template::common(placeholder).unwrap_or_else(|| {
+                debug!(
+                    "Unknown placeholder {placeholder} in topic {}",
+                    self.template
+                );
+                String::new()
+            })),
+        })
+        .into_owned()
+    }
+
+    fn payload_as_json(&self, payload: &[u8]) -> Option<Value> {
+        let format = PayloadFormat::try_from((self.payload_type.clone(), payload.to_vec()))
+            .and_then(|format| PayloadFormat::try_from((format, &PayloadType::Json)));
+
+        match format {
+            Ok(PayloadFormat::Json(json)) => Some(json.content().clone()),
+            _ => {
+                debug!(
+                    "Payload for topic {} cannot be converted to JSON",
+                    self.template
+                );
+                None
+            }
+        }
+    }
+
+    fn query(json: Option<&Value>, path: &str) -> String {
+        let Some(value) = json.and_then(|json| json.query(path).ok()?.first().cloned()) else {
+            return String::new();
+        };
+
+        match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counter() {
+        let template = TopicTemplate::new(
+            "devices/{{counter}}/data",
+            &PayloadType::Text(Default::default()),
+        );
+        let shared = template.clone();
+
+        assert_eq!("devices/1/data", template.render(&[]));
+        assert_eq!("devices/2/data", shared.render(&[]));
+    }
+
+    #[test]
+    fn render_json_path() {
+        let template = TopicTemplate::new(
+            "devices/{{ $.id }}/{{$.kind}}/{{$.missing}}",
+            &PayloadType::Json,
+        );
+
+        assert_eq!(
+            "devices/42/sensor/",
+            template.render(b"{\"id\": 42, \"kind\": \"sensor\"}")
+        );
+        assert_eq!("devices///", template.render(b"no json"));
+    }
+
+    #[test]
+    fn render_without_placeholders() {
+        let template =
+            TopicTemplate::new("devices/{{unclosed", &PayloadType::Text(Default::default()));
+
+        assert_eq!("devices/{{unclosed", template.render(&[]));
+        assert_eq!(
+            36,
+            TopicTemplate::new("{{uuid}}", &PayloadType::Text(Default::default()))
+                .render(&[])
+                .len()
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::task;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::config::http_api::HttpApi;
+use crate::config::topic::TopicStorage;
+use crate::config::PayloadType;
+use crate::mqtt::{MessageEvent, MessagePublishData, QoS};
+use crate::payload::{PayloadFormat, PayloadFormatError};
+use crate::publish::TriggerError;
+
+const PUBLISH_PATH_PREFIX: &str = "/publish/";
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+/// Time a client has to send the complete request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct HttpResponse {
+    status: u16,
+    reason: &'static str,
+    body: String,
+}
+
+impl HttpResponse {
+    fn new(status: u16, reason: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            reason,
+            body: body.into(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.reason,
+            self.body.len(),
+            self.body
+        )
+        .into_bytes()
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Accepts HTTP requests of the form `POST /publish/<topic>` and publishes
+/// the request body on the given topic.
+pub struct TriggerHttp {
+    config: HttpApi,
+    topic_storage: Arc<TopicStorage>,
+    sender_message: Sender<MessageEvent>,
+}
+
+impl TriggerHttp {
+    pub fn new(
+        config: HttpApi,
+        topic_storage: Arc<TopicStorage>,
+        sender_message: Sender<MessageEvent>,
+    ) -> Self {
+        Self {
+            config,
+            topic_storage,
+            sender_message,
+        }
+    }
+
+    pub async fn start(self, receiver_exit: Receiver<()>) -> Result<JoinHandle<()>, TriggerError> {
+        let listener = TcpListener::bind(self.config.listen())
+            .await
+            .map_err(|e| TriggerError::CouldNotBindHttpApi(e, *self.config.listen()))?;
+
+        info!("HTTP API listening on {}", self.config.listen());
+
+        let mut receiver_exit = receiver_exit;
+
+        Ok(task::spawn(async move {
+            loop {
+                select! {
+                    connection = listener.accept() => {
+                        match connection {
+                            Ok((stream, address)) => {
+                                debug!("Accepted HTTP connection from {address}");
+
+                                let topic_storage = self.topic_storage.clone();
+                                let sender_message = self.sender_message.clone();
+
+                                task::spawn(async move {
+                                    if let Err(e) = Self::handle_connection(
+                                        stream,
+                                        topic_storage,
+                                        sender_message,
+                                    )
+                                    .await
+                                    {
+                                        debug!("Error while handling HTTP connection: {e:?}");
+                                    }
+                                });
+                            }
+                            Err(e) => {
+                                error!("Error while accepting HTTP connection: {e:?}");
+                            }
+                        }
+                    },
+                    _ = receiver_exit.recv() => {
+                        break;
+                    }
+                }
+            }
+
+            debug!("HTTP API terminated")
+        }))
+    }
+
+    async fn handle_connection(
+        mut stream: TcpStream,
+        topic_storage: Arc<TopicStorage>,
+        sender_message: Sender<MessageEvent>,
+    ) -> io::Result<()> {
+        let request =
+            match tokio::time::timeout(REQUEST_TIMEOUT, Self::read_request(&mut stream)).await {
+                Ok(Ok(request)) => request,
+                Ok(Err(response)) => return stream.write_all(&response.to_bytes()).await,
+                Err(_) => {
+                    let response =
+                        HttpResponse::new(408, "Request Timeout", "Request not received in time");
+                    return stream.write_all(&response.to_bytes()).await;
+                }
+            };
+
+        let response = match Self::handle_request(
+            &request.method,
+            &request.path,
+            request.content_type.as_deref(),
+            request.body,
+            &topic_storage,
+        ) {
+            Ok(message) => {
+                info!(
+                    "Publishing message received via HTTP on topic {}",
+                    message.topic
+                );
+
+                match sender_message.send(MessageEvent::Publish(message)) {
+                    Ok(_) => HttpResponse::new(202, "Accepted", ""),
+                    Err(_) => HttpResponse::new(
+                        503,
+                        "Service Unavailable",
+                        "Message could not be passed on for publishing",
+                    ),
+                }
+            }
+            Err(response) => response,
+        };
+
+        stream.write_all(&response.to_bytes()).await
+    }
+
+    /// Reads the header and the body of the request. Only bodies with a
+    /// Content-Length are supported.
+    async fn read_request<R: AsyncRead + Unpin>(
+        stream: &mut R,
+    ) -> Result<HttpRequest, HttpResponse> {
+        let incomplete = || HttpResponse::new(400, "Bad Request", "Incomplete request");
+
+        let mut buf: Vec<u8> = Vec::with_capacity(4096);
+        let mut chunk = [0u8; 4096];
+
+        let (mut request, content_length, header_length) = loop {
+            let read = stream.read(&mut chunk).await.map_err(|_| incomplete())?;
+            if read == 0 {
+                return Err(incomplete());
+            }
+            buf.extend_from_slice(&chunk[..read]);
+
+            let mut headers = [httparse::EMPTY_HEADER; 32];
+            let mut request = httparse::Request::new(&mut headers);
+
+            match request.parse(&buf) {
+                Ok(httparse::Status::Complete(header_length)) => {
+                    let header = |name: &str| {
+                        request
+                            .headers
+                            .iter()
+                            .find(|h| h.name.eq_ignore_ascii_case(name))
+                            .map(|h| String::from_utf8_lossy(h.value).to_string())
+                    };
+
+                    if header("Transfer-Encoding")
+                        .is_some_and(|value| !value.trim().eq_ignore_ascii_case("identity"))
+                    {
+                        return Err(HttpResponse::new(
+                            411,
+                            "Length Required",
+                            "Transfer encodings are not supported, send the body with a Content-Length",
+                        ));
+                    }
+
+                    let content_length = match header("Content-Length") {
+                        Some(value) => value.trim().parse::<usize>().map_err(|_| {
+                            HttpResponse::new(400, "Bad Request", "Invalid Content-Length")
+                        })?,
+                        None => 0,
+                    };
+
+                    break (
+                        HttpRequest {
+                            method: request.method.unwrap_or_default().to_string(),
+                            path: request.path.unwrap_or_default().to_string(),
+                            content_type: header("Content-Type"),
+                            body: vec![],
+                        },
+                        content_length,
+                        header_length,
+                    );
+                }
+                Ok(httparse::Status::Partial) if buf.len() <= MAX_HEADER_SIZE => {}
+                _ => {
+                    return Err(HttpResponse::new(400, "Bad Request", "Malformed request"));
+                }
+            }
+        };
+
+        if content_length > MAX_BODY_SIZE {
+            return Err(HttpResponse::new(
+                413,
+                "Payload Too Large",
+                "Request body too large",
+            ));
+        }
+
+        let mut body = buf.split_off(header_length);
+        while body.len() < content_length {
+            let read = stream.read(&mut chunk).await.map_err(|_| incomplete())?;
+            if read == 0 {
+                return Err(HttpResponse::new(
+                    400,
+                    "Bad Request",
+                    "Request body is shorter than its Content-Length",
+                ));
+            }
+            body.extend_from_slice(&chunk[..read]);
+        }
+        body.truncate(content_length);
+
+        request.body = body;
+        Ok(request)
+    }
+
+    fn handle_request(
+        method: &str,
+        path: &str,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+        topic_storage: &TopicStorage,
+    ) -> Result<MessagePublishData, HttpResponse> {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+
+        let Some(topic) = path
+            .strip_prefix(PUBLISH_PATH_PREFIX)
+            .filter(|topic| !topic.is_empty())
+        else {
+            return Err(HttpResponse::new(404, "Not Found", "Unknown path"));
+        };
+        let topic = percent_decode_str(topic)
+            .decode_utf8()
+            .map_err(|_| HttpResponse::new(400, "Bad Request", "Topic must be valid UTF-8"))?;
+        let topic = &*topic;
+
+        if method != "POST" {
+            return Err(HttpResponse::new(
+                405,
+                "Method Not Allowed",
+                "Only POST is allowed",
+            ));
+        }
+
+        if topic.contains(['+', '#']) {
+            return Err(HttpResponse::new(
+                400,
+                "Bad Request",
+                "Topic must not contain wildcards",
+            ));
+        }
+
+        let mut qos = QoS::AtMostOnce;
+        let mut retain = false;
+
+        for (key, value) in query.split('&').filter_map(|part| part.split_once('=')) {
+            match key {
+                "qos" => {
+                    qos = match value {
+                        "0" => QoS::AtMostOnce,
+                        "1" => QoS::AtLeastOnce,
+                        "2" => QoS::ExactlyOnce,
+                        _ => {
+                            return Err(HttpResponse::new(
+                                400,
+                                "Bad Request",
+                                "QoS value must be 0, 1 or 2",
+                            ))
+                        }
+                    }
+                }
+                "retain" => retain = value == "true" || value == "1",
+                _ => {}
+            }
+        }
+
+        let payload_type = topic_storage
+            .topics
+            .iter()
+            .find(|t| t.contains(topic))
+            .map(|t| t.payload_type_of(topic).clone())
+            .unwrap_or_default();
+
+        let payload = Self::convert_payload(content_type, body, &payload_type)
+            .map_err(|e| HttpResponse::new(400, "Bad Request", e.to_string()))?;
+
+        Ok(
+            MessagePublishData::new(topic.to_string(), qos, retain, payload)
+                .with_payload_type(&payload_type),
+        )
+    }
+
+    /// Interprets the body according to the content type of the request and
+    /// converts it to the payload type configured for the topic.
+    fn convert_payload(
+        content_type: Option<&str>,
+        body: Vec<u8>,
+        payload_type: &PayloadType,
+    ) -> Result<Vec<u8>, PayloadFormatError> {
+        let input = PayloadFormat::from_content_type(content_type, body)?;
+
+        PayloadFormat::try_from((input, payload_type))?.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::topic::Topic;
+
+    fn get_topic_storage() -> TopicStorage {
+        TopicStorage {
+            topics: vec![Topic {
+                topic: "devices/+/yaml".to_string(),
+                payload_type: PayloadType::Yaml,
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn publish_text() {
+        let result = TriggerHttp::handle_request(
+            "POST",
+            "/publish/devices/1/data",
+            Some("text/plain"),
+            Vec::from("hello"),
+            &get_topic_storage(),
+        );
+
+        let Ok(message) = result else { panic!() };
+        assert_eq!("devices/1/data", message.topic);
+        assert_eq!(QoS::AtMostOnce, message.qos);
+        assert!(!message.retain);
+        assert_eq!(Vec::from("hello"), message.payload);
+    }
+
+    #[test]
+    fn publish_query_parameters() {
+        let result = TriggerHttp::handle_request(
+            "POST",
+            "/publish/devices/1/data?qos=2&retain=true",
+            None,
+            vec![],
+            &get_topic_storage(),
+        );
+
+        let Ok(message) = result else { panic!() };
+        assert_eq!("devices/1/data", message.topic);
+        assert_eq!(QoS::ExactlyOnce, message.qos);
+        assert!(message.retain);
+    }
+
+    #[test]
+    fn publish_converts_to_topic_payload_type() {
+        let result = TriggerHttp::handle_request(
+            "POST",
+            "/publish/devices/1/yaml",
+            Some("application/json; charset=utf-8"),
+            Vec::from("{\"name\":\"MQTli\"}"),
+            &get_topic_storage(),
+        );
+
+        let Ok(message) = result else { panic!() };
+        assert_eq!(Vec::from("name: MQTli\n"), message.payload);
+    }
+
+    #[test]
+    fn publish_percent_encoded_topic() {
+        let result = TriggerHttp::handle_request(
+            "POST",
+            "/publish/devices/living%20room/a%2Fb",
+            None,
+            vec![],
+            &get_topic_storage(),
+        );
+
+        let Ok(message) = result else { panic!() };
+        assert_eq!("devices/living room/a/b", message.topic);
+
+        let result = TriggerHttp::handle_request(
+            "POST",
+            "/publish/devices/%23",
+            None,
+            vec![],
+            &get_topic_storage(),
+        );
+        assert_eq!(400, result.err().unwrap().status);
+    }
+
+    #[tokio::test]
+    async fn read_request() {
+        let mut input: &[u8] =
+            b"POST /publish/test HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello";
+        let Ok(request) = TriggerHttp::read_request(&mut input).await else {
+            panic!()
+        };
+        assert_eq!(
+            ("POST", "/publish/test"),
+            (request.method.as_str(), request.path.as_str())
+        );
+        assert_eq!(Some("text/plain".to_string()), request.content_type);
+        assert_eq!(Vec::from("hello"), request.body);
+
+        let mut input: &[u8] = b"POST /publish/test HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello";
+        assert_eq!(
+            400,
+            TriggerHttp::read_request(&mut input)
+                .await
+                .err()
+                .unwrap()
+                .status
+        );
+
+        let mut input: &[u8] =
+            b"POST /publish/test HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        assert_eq!(
+            411,
+            TriggerHttp::read_request(&mut input)
+                .await
+                .err()
+                .unwrap()
+                .status
+        );
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let topic_storage = get_topic_storage();
+        let request =
+            |method, path| TriggerHttp::handle_request(method, path, None, vec![], &topic_storage);
+
+        assert_eq!(404, request("POST", "/other").err().unwrap().status);
+        assert_eq!(404, request("POST", "/publish/").err().unwrap().status);
+        assert_eq!(405, request("GET", "/publish/topic").err().unwrap().status);
+        assert_eq!(
+            400,
+            request("POST", "/publish/topic/+").err().unwrap().status
+        );
+        assert_eq!(
+            400,
+            request("POST", "/publish/topic?qos=3")
+                .err()
+                .unwrap()
+                .status
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use tokio::select;
+use tokio::sync::broadcast::Receiver as BroadcastReceiver;
+use tokio::sync::Mutex;
+use tokio::task;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::channel;
+use crate::config::filter::FilterTypes;
+use crate::config::publish::PublishTriggerTypeOnMessage;
+use crate::config::topic::Topic;
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService, QoS};
+use crate::payload::text::PayloadFormatText;
+use crate::payload::PayloadFormat;
+use crate::publish::payload_sequence::{NextPayload, PayloadSequence};
+use crate::publish::topic_template::TopicTemplate;
+
+struct OnMessageWatch {
+    topic: Topic,
+    qos: QoS,
+    filters: FilterTypes,
+    publish_topic: TopicTemplate,
+    publish_qos: QoS,
+    publish_retain: bool,
+    payloads: PayloadSequence,
+}
+
+impl OnMessageWatch {
+    /// Returns true if the incoming message on the given topic passes the
+    /// filter conditions of this watch. Filters yielding no payload at all
+    /// are treated as a non-match.
+    fn matches(&self, incoming_topic: &str, payload: Vec<u8>) -> bool {
+        if !self.topic.contains(incoming_topic) {
+            return false;
+        }
+
+        match self
+            .filters
+            .apply(PayloadFormat::Text(PayloadFormatText::from(payload)))
+        {
+            Ok(result) => !result.is_empty(),
+            Err(e) => {
+                debug!(
+                    "Filter of on_message trigger for topic {} did not match: {e:?}",
+                    self.topic.topic()
+                );
+                false
+            }
+        }
+    }
+}
+
+pub struct TriggerOnMessage {
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    watches: Vec<OnMessageWatch>,
+}
+
+impl TriggerOnMessage {
+    pub fn new(mqtt_service: Arc<Mutex<dyn MqttService>>) -> Self {
+        Self {
+            mqtt_service,
+            watches: vec![],
+        }
+    }
+
+    pub fn add_watch(
+        &mut self,
+        trigger: &PublishTriggerTypeOnMessage,
+        topic: &TopicTemplate,
+        qos: &QoS,
+        retain: bool,
+        payloads: PayloadSequence,
+    ) {
+        self.watches.push(OnMessageWatch {
+            topic: Topic {
+                topic: trigger.topic().clone(),
+                ..Default::default()
+            },
+            qos: *trigger.qos(),
+            filters: trigger.filters().clone(),
+            publish_topic: topic.clone(),
+            publish_qos: *qos,
+            publish_retain: retain,
+            payloads,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    pub async fn start(
+        self,
+        receiver: BroadcastReceiver<MqttReceiveEvent>,
+        receiver_exit: BroadcastReceiver<()>,
+    ) -> JoinHandle<()> {
+        let mut receiver = receiver;
+        let mut receiver_exit = receiver_exit;
+
+        // the trigger is started once the first connection is acknowledged
+        self.subscribe().await;
+
+        task::spawn(async move {
+            debug!("Starting on_message trigger");
+
+            loop {
+                select! {
+                    event = channel::recv(&mut receiver, "On message trigger") => {
+                        let Some(event) = event else {
+                            break;
+                        };
+
+                        // the broker may have started a new session without
+                        // the subscriptions
+                        if event.is_connect() {
+                            self.subscribe().await;
+                            continue;
+                        }
+
+                        let Some((incoming_topic, payload)) = Self::get_incoming_publish(event) else {
+                            continue;
+                        };
+
+                        for watch in self.watches.iter() {
+                            if !watch.matches(&incoming_topic, payload.clone()) {
+                                continue;
+                            }
+
+                            let NextPayload::Payload(message_payload) = watch.payloads.next_payload().await else {
+                                continue;
+                            };
+
+                            let publish_topic = message_payload.topic(&watch.publish_topic);
+
+                            // watches triggering each other would publish endlessly as well
+                            if let Some(triggered) = self.watches.iter().find(|other| {
+                                other.matches(&publish_topic, message_payload.payload.clone())
+                            }) {
+                                error!(
+                                    "Not publishing to topic {publish_topic}, the message would trigger the on_message trigger for topic {} again",
+                                    triggered.topic.topic()
+                                );
+                                continue;
+                            }
+
+                            debug!(
+                                "Message on topic {} triggered publish to topic {}",
+                                incoming_topic, publish_topic
+                            );
+
+                            self.mqtt_service
+                                .lock()
+                                .await
+                                .publish(MessagePublishData::new(
+                                    publish_topic,
+                                    message_payload.qos(watch.publish_qos),
+                                    watch.publish_retain,
+                                    message_payload.payload,
+                                ).with_payload_type(watch.publish_topic.payload_type()))
+                                .await;
+                        }
+                    },
+                    _ = receiver_exit.recv() => {
+                        break;
+                    }
+                }
+            }
+
+            debug!("On_message trigger terminated")
+        })
+    }
+
+    /// Subscribes to the topics of all watches, each topic only once.
+    async fn subscribe(&self) {
+        let mut subscribed: Vec<&str> = vec![];
+        for watch in self.watches.iter() {
+            let topic = watch.topic.topic().as_str();
+            if subscribed.contains(&topic) {
+                continue;
+            }
+
+            info!(
+                "Subscribing to topic {} with QoS {:?} for on_message trigger",
+                topic, watch.qos
+            );
+            if let Err(e) = self
+                .mqtt_service
+                .lock()
+                .await
+                .subscribe(topic.to_owned(), watch.qos)
+                .await
+            {
+                error!("Could not subscribe to topic {}: {}", topic, e);
+            }
+
+            subscribed.push(topic);
+        }
+    }
+
+    fn get_incoming_publish(event: MqttReceiveEvent) -> Option<(String, Vec<u8>)> {
+        match event {
+            MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::Publish(
+                value,
+            ))) => Some((
+                from_utf8(value.topic.as_ref()).ok()?.to_string(),
+                value.payload.to_vec(),
+            )),
+            MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(value))) => {
+                Some((value.topic.clone(), value.payload.to_vec()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::filter::FilterType;
+    use crate::config::PayloadType;
+    use crate::testing::MockMqttService;
+    use serde_yaml::from_str;
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    fn get_watch(pattern: &str, filters: FilterTypes) -> OnMessageWatch {
+        OnMessageWatch {
+            topic: Topic {
+                topic: pattern.to_string(),
+                ..Default::default()
+            },
+            qos: QoS::AtMostOnce,
+            filters,
+            publish_topic: TopicTemplate::new("reply", &PayloadType::Text(Default::default())),
+            publish_qos: QoS::AtMostOnce,
+            publish_retain: false,
+            payloads: PayloadSequence::single(vec![]),
+        }
+    }
+
+    fn publishing_to(mut watch: OnMessageWatch, topic: &str, payload: &str) -> OnMessageWatch {
+        watch.publish_topic = TopicTemplate::new(topic, &PayloadType::Text(Default::default()));
+        watch.payloads = PayloadSequence::single(Vec::from(payload));
+        watch
+    }
+
+    fn incoming_publish(topic: &str, payload: &str) -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::Publish(
+            rumqttc::Publish::new(topic, rumqttc::QoS::AtMostOnce, payload),
+        )))
+    }
+
+    fn connack() -> MqttReceiveEvent {
+        MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::ConnAck(
+            rumqttc::ConnAck::new(rumqttc::ConnectReturnCode::Success, false),
+        )))
+    }
+
+    #[test]
+    fn matches_topic_without_filters() {
+        let watch = get_watch("devices/+/ping", FilterTypes::default());
+
+        assert!(watch.matches("devices/1/ping", Vec::from("anything")));
+        assert!(!watch.matches("devices/1/pong", Vec::from("anything")));
+    }
+
+    #[test]
+    fn matches_filter_condition() {
+        let filters: Vec<FilterType> =
+            from_str("- type: extract_json\n  jsonpath: $.ping").unwrap();
+        let watch = get_watch("devices/+/ping", FilterTypes::from(filters));
+
+        assert!(watch.matches("devices/1/ping", Vec::from("{\"ping\": true}")));
+        assert!(!watch.matches("devices/1/ping", Vec::from("{\"pong\": true}")));
+        assert!(!watch.matches("devices/1/ping", Vec::from("no json")));
+    }
+
+    #[tokio::test]
+    async fn resubscribe_on_connect() {
+        let (service, broker) = MockMqttService::new();
+        let (sender, _) = broadcast::channel(10);
+        let (_sender_exit, receiver_exit) = broadcast::channel(1);
+
+        TriggerOnMessage {
+            mqtt_service: service.into_shared(),
+            watches: vec![get_watch("devices/+/ping", FilterTypes::default())],
+        }
+        .start(sender.subscribe(), receiver_exit)
+        .await;
+        assert_eq!(1, broker.subscriptions().len());
+
+        sender.send(connack()).unwrap();
+        sender
+            .send(incoming_publish("devices/1/ping", "ping"))
+            .unwrap();
+        broker.wait_for_published(1, Duration::from_secs(1)).await;
+
+        assert_eq!(
+            vec![
+                ("devices/+/ping".to_string(), QoS::AtMostOnce),
+                ("devices/+/ping".to_string(), QoS::AtMostOnce)
+            ],
+            broker.subscriptions()
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_own_messages() {
+        let (service, broker) = MockMqttService::new();
+        let (sender, _) = broadcast::channel(10);
+        let (_sender_exit, receiver_exit) = broadcast::channel(1);
+
+        let filters: Vec<FilterType> =
+            from_str("- type: extract_json\n  jsonpath: $.ping").unwrap();
+        let filters = FilterTypes::from(filters);
+        TriggerOnMessage {
+            mqtt_service: service.into_shared(),
+            watches: vec![
+                publishing_to(
+                    get_watch("devices/+/ping", filters.clone()),
+                    "devices/1/ping",
+                    "{\"ping\": true}",
+                ),
+                publishing_to(
+                    get_watch("devices/+/ping", filters.clone()),
+                    "devices/1/ping",
+                    "{\"pong\": true}",
+                ),
+                get_watch("devices/+/ping", filters),
+            ],
+        }
+        .start(sender.subscribe(), receiver_exit)
+        .await;
+
+        sender
+            .send(incoming_publish("devices/2/ping", "{\"ping\": true}"))
+            .unwrap();
+        let published = broker.wait_for_published(2, Duration::from_secs(1)).await;
+
+        assert_eq!(
+            vec!["devices/1/ping", "reply"],
+            published
+                .iter()
+                .map(|message| message.topic.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_messages_triggering_other_watches() {
+        let (service, broker) = MockMqttService::new();
+        let (sender, _) = broadcast::channel(10);
+        let (_sender_exit, receiver_exit) = broadcast::channel(1);
+
+        TriggerOnMessage {
+            mqtt_service: service.into_shared(),
+            watches: vec![
+                publishing_to(get_watch("a/+", FilterTypes::default()), "b/1", "a"),
+                publishing_to(get_watch("b/+", FilterTypes::default()), "a/1", "b"),
+                get_watch("a/+", FilterTypes::default()),
+            ],
+        }
+        .start(sender.subscribe(), receiver_exit)
+        .await;
+
+        sender.send(incoming_publish("a/2", "a")).unwrap();
+        let published = broker.wait_for_published(1, Duration::from_secs(1)).await;
+
+        assert_eq!(
+            vec!["reply"],
+            published
+                .iter()
+                .map(|message| message.topic.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn continue_after_lagging() {
+        let (service, broker) = MockMqttService::new();
+        let (sender, _) = broadcast::channel(1);
+        let (_sender_exit, receiver_exit) = broadcast::channel(1);
+
+        let receiver = sender.subscribe();
+        sender
+            .send(incoming_publish("devices/1/ping", "ping"))
+            .unwrap();
+        sender
+            .send(incoming_publish("devices/2/ping", "ping"))
+            .unwrap();
+
+        TriggerOnMessage {
+            mqtt_service: service.into_shared(),
+            watches: vec![get_watch("devices/+/ping", FilterTypes::default())],
+        }
+        .start(receiver, receiver_exit)
+        .await;
+
+        broker.wait_for_published(1, Duration::from_secs(1)).await;
+        sender
+            .send(incoming_publish("devices/3/ping", "ping"))
+            .unwrap();
+        let published = broker.wait_for_published(2, Duration::from_secs(1)).await;
+
+        assert_eq!(2, published.len());
+    }
+}
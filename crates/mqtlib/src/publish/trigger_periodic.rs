@@ -1,18 +1,38 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use chrono::Local;
+use rand::Rng;
 use tokio::sync::broadcast::Receiver as BroadcastReceiver;
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 use tokio::{select, task};
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
 use tracing::{debug, error};
 use uuid::Uuid;
 
+use crate::config::publish::TriggerWindow;
 use crate::mqtt::{MessagePublishData, MqttService, QoS};
+use crate::publish::payload_sequence::{NextPayload, PayloadSequence};
+use crate::publish::topic_template::TopicTemplate;
 use crate::publish::TriggerError;
 
+/// Returns the time between two messages published at the given rate of
+/// messages per second, or none if the rate is too low or too high to space
+/// the messages by at least a nanosecond.
+pub fn rate_interval(rate: f64) -> Option<Duration> {
+    if rate.is_nan() || rate <= 0.0 {
+        return None;
+    }
+
+    Duration::try_from_secs_f64(1.0 / rate)
+        .ok()
+        .filter(|interval| !interval.is_zero())
+}
+
 #[derive(Clone, Debug)]
 pub enum Command {
     NoMoreTasksPending,
@@ -57,17 +77,54 @@ impl JobContextStorage {
     }
 }
 
+/// State of the schedules of a [`TriggerPeriodic`].
+#[derive(Clone)]
+pub struct SchedulerStatus {
+    scheduler: Arc<Mutex<JobScheduler>>,
+    sender_data: mpsc::Sender<MessagePublishData>,
+    paused: Arc<AtomicBool>,
+}
+
+impl SchedulerStatus {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages which are due but not yet published.
+    pub fn queued_publishes(&self) -> usize {
+        self.sender_data.max_capacity() - self.sender_data.capacity()
+    }
+
+    /// Time until the next schedule fires, none if no schedule is pending.
+    pub async fn time_till_next_publish(&self) -> Option<Duration> {
+        self.scheduler
+            .lock()
+            .await
+            .time_till_next_job()
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
 pub struct TriggerPeriodic {
     scheduler: Arc<Mutex<JobScheduler>>,
     mqtt_service: Arc<Mutex<dyn MqttService>>,
-    sender_data: broadcast::Sender<(String, QoS, bool, Vec<u8>)>,
+    sender_data: mpsc::Sender<MessagePublishData>,
+    /// Taken by [`TriggerPeriodic::start`]
+    receiver_data: Option<mpsc::Receiver<MessagePublishData>>,
     job_contexts: Arc<Mutex<JobContextStorage>>,
     sender_command: broadcast::Sender<Command>,
+    paused: Arc<AtomicBool>,
+    rate: Option<f64>,
 }
 
 impl TriggerPeriodic {
-    pub async fn new(mqtt_service: Arc<Mutex<dyn MqttService>>) -> Self {
-        let (sender_data, _) = broadcast::channel::<(String, QoS, bool, Vec<u8>)>(32);
+    /// Due messages are queued until they are published, up to the given
+    /// capacity; once the queue is full, the schedules wait for room, so
+    /// that no message is dropped.
+    pub async fn new(mqtt_service: Arc<Mutex<dyn MqttService>>, capacity: usize) -> Self {
+        let (sender_data, receiver_data) = mpsc::channel::<MessagePublishData>(capacity);
         let (sender_command, _) = broadcast::channel::<Command>(4);
 
         Self {
@@ -78,21 +135,39 @@ impl TriggerPeriodic {
             )),
             mqtt_service,
             sender_data,
+            receiver_data: Some(receiver_data),
             job_contexts: Arc::new(Mutex::new(JobContextStorage::new())),
             sender_command,
+            paused: Arc::new(AtomicBool::new(false)),
+            rate: None,
         }
     }
 
+    /// Limits the number of published messages per second. Messages are
+    /// spaced evenly; messages which are due earlier wait for their slot.
+    pub fn with_rate(mut self, rate: Option<f64>) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Flag to pause the schedules; while it is set, due messages are
+    /// dropped instead of being published.
+    pub fn paused(&self) -> Arc<AtomicBool> {
+        self.paused.clone()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn add_schedule(
         &mut self,
         interval: &Duration,
         count: &Option<u32>,
         initial_delay: &Duration,
-        topic: &str,
+        jitter: &Duration,
+        window: &TriggerWindow,
+        topic: &TopicTemplate,
         qos: &QoS,
         retain: bool,
-        payload: Vec<u8>,
+        payloads: PayloadSequence,
     ) -> Result<(), TriggerError> {
         let qos = *qos;
 
@@ -101,17 +176,21 @@ impl TriggerPeriodic {
         let contexts = self.job_contexts.clone();
         let count = *count;
         let interval = *interval;
-        let topic = topic.to_owned();
+        let jitter = *jitter;
+        let window = window.clone();
+        let topic = topic.clone();
 
         match count {
             Some(count) => {
                 if count > 0 {
                     let job_initial = Self::create_job_one_shot(
                         &initial_delay,
+                        &jitter,
+                        &window,
                         retain,
                         qos,
-                        &payload,
-                        topic.as_ref(),
+                        &payloads,
+                        &topic,
                         self.sender_data.clone(),
                     )?;
 
@@ -126,10 +205,12 @@ impl TriggerPeriodic {
                             let Ok(job_repeated) = Self::create_job_repeated_count(
                                 contexts,
                                 &interval,
+                                &jitter,
+                                &window,
                                 retain,
                                 qos,
-                                &payload,
-                                topic.as_ref(),
+                                &payloads,
+                                &topic,
                                 sender_data,
                                 count - 1,
                             ) else {
@@ -143,16 +224,21 @@ impl TriggerPeriodic {
                         });
                     }
                 } else {
-                    debug!("Not adding task to publish to topic {topic}, count is zero");
+                    debug!(
+                        "Not adding task to publish to topic {}, count is zero",
+                        topic.template()
+                    );
                 }
             }
             None => {
                 let job_initial = Self::create_job_one_shot(
                     &initial_delay,
+                    &jitter,
+                    &window,
                     retain,
                     qos,
-                    &payload,
-                    topic.as_ref(),
+                    &payloads,
+                    &topic,
                     self.sender_data.clone(),
                 )?;
 
@@ -165,10 +251,12 @@ impl TriggerPeriodic {
 
                     let Ok(job_repeated) = Self::create_job_repeated_forever(
                         &interval,
+                        &jitter,
+                        &window,
                         retain,
                         qos,
-                        payload,
-                        topic.as_ref(),
+                        payloads,
+                        &topic,
                         sender_data,
                     ) else {
                         error!("Error while scheduling repeated job");
@@ -185,19 +273,37 @@ impl TriggerPeriodic {
         Ok(())
     }
 
+    /// Handle to query the state of the schedules while they are running.
+    pub fn status(&self) -> SchedulerStatus {
+        SchedulerStatus {
+            scheduler: self.scheduler.clone(),
+            sender_data: self.sender_data.clone(),
+            paused: self.paused.clone(),
+        }
+    }
+
     pub fn get_receiver_command(&self) -> broadcast::Receiver<Command> {
         self.sender_command.subscribe()
     }
 
     pub async fn start(
-        &self,
+        &mut self,
         receiver_exit: BroadcastReceiver<()>,
     ) -> Result<JoinHandle<()>, TriggerError> {
-        let mut receiver = self.sender_data.subscribe();
+        let mut receiver = self
+            .receiver_data
+            .take()
+            .ok_or(TriggerError::AlreadyStarted)?;
         let mut receiver_exit = receiver_exit;
         let mqtt_service = self.mqtt_service.clone();
         let scheduler = self.scheduler.clone();
         let sender_command = self.sender_command.clone();
+        let paused = self.paused.clone();
+        let mut pacer = self.rate.and_then(rate_interval).map(|interval| {
+            let mut pacer = tokio::time::interval(interval);
+            pacer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            pacer
+        });
 
         async fn is_task_pending(
             scheduler: &Arc<Mutex<JobScheduler>>,
@@ -221,18 +327,28 @@ impl TriggerPeriodic {
 
             tokio::time::sleep(Duration::from_millis(100)).await;
 
-            if is_task_pending(&scheduler, &sender_command).await {
+            if !receiver.is_empty() || is_task_pending(&scheduler, &sender_command).await {
                 loop {
                     select! {
                         data = receiver.recv() => {
-                            if let Ok((topic, qos, retain, payload)) = data {
-                                mqtt_service
-                                    .lock()
-                                    .await
-                                    .publish(MessagePublishData::new(topic, qos, retain, payload))
-                                    .await;
-
-                                if !is_task_pending(&scheduler, &sender_command).await {
+                            if let Some(data) = data {
+                                if paused.load(Ordering::Relaxed) {
+                                    debug!("Schedules are paused, not publishing on topic {}", data.topic);
+                                } else {
+                                    if let Some(pacer) = pacer.as_mut() {
+                                        pacer.tick().await;
+                                    }
+
+                                    mqtt_service
+                                        .lock()
+                                        .await
+                                        .publish(data)
+                                        .await;
+                                }
+
+                                if receiver.is_empty()
+                                    && !is_task_pending(&scheduler, &sender_command).await
+                                {
                                     break
                                 };
                             } else {
@@ -260,25 +376,50 @@ impl TriggerPeriodic {
 
     fn create_job_one_shot(
         initial_delay: &Duration,
+        jitter: &Duration,
+        window: &TriggerWindow,
         retain: bool,
         qos: QoS,
-        payload: &[u8],
-        topic: &str,
-        sender_data: broadcast::Sender<(String, QoS, bool, Vec<u8>)>,
+        payloads: &PayloadSequence,
+        topic: &TopicTemplate,
+        sender_data: mpsc::Sender<MessagePublishData>,
     ) -> Result<Job, JobSchedulerError> {
-        let payload = payload.to_owned();
-        let topic = topic.to_owned();
+        let payloads = payloads.clone();
+        let topic = topic.clone();
+        let jitter = *jitter;
+        let window = window.clone();
 
         Job::new_one_shot_async(
             *initial_delay,
             move |_uuid: Uuid, _scheduler: JobScheduler| {
-                let payload = payload.clone();
+                let payloads = payloads.clone();
                 let pc = sender_data.clone();
                 let topic = topic.clone();
+                let window = window.clone();
 
                 Box::pin(async move {
-                    let tx = (topic, qos, retain, payload.clone());
-                    let _ = pc.clone().send(tx);
+                    if !window.is_active(&Local::now()) {
+                        debug!(
+                            "Not publishing to topic {}, trigger is outside its window",
+                            topic.template()
+                        );
+                        return;
+                    }
+
+                    let NextPayload::Payload(payload) = payloads.next_payload().await else {
+                        return;
+                    };
+
+                    Self::sleep_jitter(&jitter).await;
+
+                    let tx = MessagePublishData::new(
+                        payload.topic(&topic),
+                        payload.qos(qos),
+                        retain,
+                        payload.payload,
+                    )
+                    .with_payload_type(topic.payload_type());
+                    let _ = pc.send(tx).await;
                 })
             },
         )
@@ -288,23 +429,39 @@ impl TriggerPeriodic {
     fn create_job_repeated_count(
         contexts: Arc<Mutex<JobContextStorage>>,
         interval: &Duration,
+        jitter: &Duration,
+        window: &TriggerWindow,
         retain: bool,
         qos: QoS,
-        payload: &[u8],
-        topic: &str,
-        sender_data: broadcast::Sender<(String, QoS, bool, Vec<u8>)>,
+        payloads: &PayloadSequence,
+        topic: &TopicTemplate,
+        sender_data: mpsc::Sender<MessagePublishData>,
         count: u32,
     ) -> Result<Job, JobSchedulerError> {
-        let payload = payload.to_owned();
-        let topic = topic.to_owned();
+        let payloads = payloads.clone();
+        let topic = topic.clone();
+        let jitter = *jitter;
+        let window = window.clone();
 
         Job::new_repeated_async(*interval, move |uuid: Uuid, scheduler: JobScheduler| {
-            let payload = payload.clone();
+            let payloads = payloads.clone();
             let pc = sender_data.clone();
             let topic = topic.clone();
             let contexts = contexts.clone();
+            let window = window.clone();
 
             Box::pin(async move {
+                if window.is_expired(&Local::now()) {
+                    debug!("Removing periodic trigger {}, its window has ended", uuid);
+                    contexts.lock().await.remove(&uuid);
+                    let _ = scheduler.remove(&uuid).await;
+                    return;
+                }
+
+                if !window.is_active(&Local::now()) {
+                    return;
+                }
+
                 if !contexts.lock().await.exists(&uuid) {
                     contexts.lock().await.get_or_create_context(&uuid).count = Some(count);
                 }
@@ -315,8 +472,27 @@ impl TriggerPeriodic {
                     .count
                     .unwrap();
 
-                let tx = (topic, qos, retain, payload.clone());
-                let _ = pc.clone().send(tx);
+                let payload = match payloads.next_payload().await {
+                    NextPayload::Payload(payload) => payload,
+                    NextPayload::Skip => return,
+                    NextPayload::End => {
+                        debug!("Removing periodic trigger {}, no more payloads left", uuid);
+                        contexts.lock().await.remove(&uuid);
+                        let _ = scheduler.remove(&uuid).await;
+                        return;
+                    }
+                };
+
+                Self::sleep_jitter(&jitter).await;
+
+                let tx = MessagePublishData::new(
+                    payload.topic(&topic),
+                    payload.qos(qos),
+                    retain,
+                    payload.payload,
+                )
+                .with_payload_type(topic.payload_type());
+                let _ = pc.send(tx).await;
 
                 counter -= 1;
                 contexts.lock().await.get_or_create_context(&uuid).count = Some(counter);
@@ -332,24 +508,127 @@ impl TriggerPeriodic {
 
     fn create_job_repeated_forever(
         interval: &Duration,
+        jitter: &Duration,
+        window: &TriggerWindow,
         retain: bool,
         qos: QoS,
-        payload: Vec<u8>,
-        topic: &str,
-        sender_data: broadcast::Sender<(String, QoS, bool, Vec<u8>)>,
+        payloads: PayloadSequence,
+        topic: &TopicTemplate,
+        sender_data: mpsc::Sender<MessagePublishData>,
     ) -> Result<Job, JobSchedulerError> {
-        let payload = payload.clone();
-        let topic = topic.to_owned();
+        let topic = topic.clone();
+        let jitter = *jitter;
+        let window = window.clone();
 
-        Job::new_repeated_async(*interval, move |_uuid: Uuid, _scheduler: JobScheduler| {
-            let payload = payload.clone();
+        Job::new_repeated_async(*interval, move |uuid: Uuid, scheduler: JobScheduler| {
+            let payloads = payloads.clone();
             let pc = sender_data.clone();
             let topic = topic.clone();
+            let window = window.clone();
 
             Box::pin(async move {
-                let tx = (topic, qos, retain, payload.clone());
-                let _ = pc.clone().send(tx);
+                if window.is_expired(&Local::now()) {
+                    debug!("Removing periodic trigger {}, its window has ended", uuid);
+                    let _ = scheduler.remove(&uuid).await;
+                    return;
+                }
+
+                if !window.is_active(&Local::now()) {
+                    return;
+                }
+
+                let payload = match payloads.next_payload().await {
+                    NextPayload::Payload(payload) => payload,
+                    NextPayload::Skip => return,
+                    NextPayload::End => {
+                        debug!("Removing periodic trigger {}, no more payloads left", uuid);
+                        let _ = scheduler.remove(&uuid).await;
+                        return;
+                    }
+                };
+
+                Self::sleep_jitter(&jitter).await;
+
+                let tx = MessagePublishData::new(
+                    payload.topic(&topic),
+                    payload.qos(qos),
+                    retain,
+                    payload.payload,
+                )
+                .with_payload_type(topic.payload_type());
+                let _ = pc.send(tx).await;
             })
         })
     }
+
+    /// Delays the job by a random duration between zero and twice the
+    /// jitter. As the first job is delayed the same way, each message is
+    /// sent within ±jitter around its nominal point in time.
+    async fn sleep_jitter(jitter: &Duration) {
+        if jitter.is_zero() {
+            return;
+        }
+
+        let max = jitter.as_millis() as u64 * 2;
+        let delay = Duration::from_millis(rand::thread_rng().gen_range(0..=max));
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PayloadType;
+    use crate::testing::MockMqttService;
+
+    #[test]
+    fn interval_of_rate() {
+        assert_eq!(Some(Duration::from_millis(4)), rate_interval(250.0));
+        assert_eq!(Some(Duration::from_secs(2)), rate_interval(0.5));
+        assert_eq!(Some(Duration::from_nanos(1)), rate_interval(1e9));
+        assert_eq!(None, rate_interval(1e12));
+        assert_eq!(None, rate_interval(f64::INFINITY));
+        assert_eq!(None, rate_interval(1e-300));
+        assert_eq!(None, rate_interval(0.0));
+        assert_eq!(None, rate_interval(f64::NAN));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_keeps_all_messages() {
+        let (service, broker) = MockMqttService::new();
+        let mut trigger = TriggerPeriodic::new(service.into_shared(), 32)
+            .await
+            .with_rate(Some(100.0));
+        let topic = TopicTemplate::new("test", &PayloadType::default());
+
+        // all messages are due at once, more than fit into the queue
+        for index in 0..40 {
+            trigger
+                .add_schedule(
+                    &Duration::from_secs(1),
+                    &Some(1),
+                    &Duration::ZERO,
+                    &Duration::ZERO,
+                    &TriggerWindow::default(),
+                    &topic,
+                    &QoS::AtMostOnce,
+                    false,
+                    PayloadSequence::single(format!("{index}").into_bytes()),
+                )
+                .await
+                .unwrap();
+        }
+
+        let (_sender_exit, receiver_exit) = broadcast::channel::<()>(1);
+        let task = trigger.start(receiver_exit).await.unwrap();
+
+        let published = broker.wait_for_published(40, Duration::from_secs(10)).await;
+        assert_eq!(40, published.len());
+
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .unwrap()
+            .unwrap();
+    }
 }
@@ -0,0 +1,263 @@
+use crate::config::self_test::SelfTest;
+use crate::config::PayloadType;
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService};
+use crate::output::theme::{Role, Themed};
+use crate::payload::json::PayloadFormatJson;
+use crate::payload::{PayloadFormat, PayloadFormatError};
+use crate::ping::wait_for;
+use crate::MqtlibError;
+use colored::Colorize;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex};
+use tracing::debug;
+
+/// Steps of [`run_self_test`], in the order they are run.
+#[derive(Clone, Copy, Debug, PartialEq, strum_macros::Display)]
+pub enum SelfTestStep {
+    #[strum(serialize = "connect")]
+    Connect,
+    #[strum(serialize = "subscribe")]
+    Subscribe,
+    #[strum(serialize = "convert")]
+    Convert,
+    #[strum(serialize = "publish and receive")]
+    Receive,
+    #[strum(serialize = "verify payload")]
+    Verify,
+}
+
+/// Steps which passed with their duration, and the step which failed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SelfTestResult {
+    pub passed: Vec<(SelfTestStep, Duration)>,
+    pub failed: Option<(SelfTestStep, String)>,
+}
+
+impl SelfTestResult {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_none()
+    }
+
+    fn pass(&mut self, step: SelfTestStep, started_at: Instant) {
+        debug!("Self test step {step} passed");
+        self.passed.push((step, started_at.elapsed()));
+    }
+
+    fn fail(mut self, step: SelfTestStep, reason: String) -> Self {
+        self.failed = Some((step, reason));
+        self
+    }
+}
+
+impl Display for SelfTestResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", " Self test ".themed(Role::Title))?;
+
+        for (step, duration) in &self.passed {
+            writeln!(
+                f,
+                "{} {step} ({:.3} ms)",
+                "ok".themed(Role::Added),
+                duration.as_secs_f64() * 1000.0
+            )?;
+        }
+
+        match &self.failed {
+            Some((step, reason)) => {
+                writeln!(f, "{} {step}: {reason}", "failed".themed(Role::Removed))?;
+                write!(f, "{}", "Self test failed".themed(Role::Removed).bold())
+            }
+            None => write!(f, "{}", "Self test passed".themed(Role::Added).bold()),
+        }
+    }
+}
+
+/// Connects to the broker, subscribes to the topic of the self test and
+/// publishes the content converted to the payload type on it. The test
+/// passes if the message is received and converted back to JSON equals the
+/// content. Stops at the first failing step.
+pub async fn run_self_test(
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    self_test: &SelfTest,
+    capacity: usize,
+) -> Result<SelfTestResult, MqtlibError> {
+    let (sender_receive, mut receiver) = broadcast::channel::<MqttReceiveEvent>(capacity);
+    let (sender_disconnect, _) = broadcast::channel::<()>(1);
+    let timeout = *self_test.timeout();
+
+    let started_at = Instant::now();
+    let mqtt_loop_handle = mqtt_service
+        .lock()
+        .await
+        .connect(sender_receive, sender_disconnect.subscribe())
+        .await?;
+
+    let result = steps(mqtt_service.clone(), self_test, &mut receiver, started_at).await;
+
+    if result.passed.len() > 1 {
+        let _ = mqtt_service
+            .lock()
+            .await
+            .unsubscribe(self_test.topic().clone())
+            .await;
+    }
+    let _ = sender_disconnect.send(());
+    let _ = tokio::time::timeout(timeout, mqtt_loop_handle).await;
+
+    Ok(result)
+}
+
+async fn steps(
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    self_test: &SelfTest,
+    receiver: &mut broadcast::Receiver<MqttReceiveEvent>,
+    started_at: Instant,
+) -> SelfTestResult {
+    let mut result = SelfTestResult::default();
+    let timeout = *self_test.timeout();
+
+    if wait_for(receiver, timeout, |event| event.is_connect())
+        .await
+        .is_none()
+    {
+        return result.fail(
+            SelfTestStep::Connect,
+            format!("broker did not accept the connection within {timeout:?}, check host, port, TLS and credentials"),
+        );
+    }
+    result.pass(SelfTestStep::Connect, started_at);
+
+    let started_at = Instant::now();
+    if let Err(e) = mqtt_service
+        .lock()
+        .await
+        .subscribe(self_test.topic().clone(), *self_test.qos())
+        .await
+    {
+        return result.fail(SelfTestStep::Subscribe, e.to_string());
+    }
+    let mut rejected = false;
+    if wait_for(receiver, timeout, |event| {
+        rejected = event.is_subscribe_rejected();
+        event.is_subscribe_ack()
+    })
+    .await
+    .is_none()
+    {
+        return result.fail(
+            SelfTestStep::Subscribe,
+            format!("subscription was not acknowledged within {timeout:?}"),
+        );
+    }
+    if rejected {
+        return result.fail(
+            SelfTestStep::Subscribe,
+            format!(
+                "broker rejected the subscription to {}, check the permissions",
+                self_test.topic()
+            ),
+        );
+    }
+    result.pass(SelfTestStep::Subscribe, started_at);
+
+    let started_at = Instant::now();
+    let payload = match encode(self_test.content(), self_test.payload_type()) {
+        Ok(payload) => payload,
+        Err(e) => return result.fail(SelfTestStep::Convert, e.to_string()),
+    };
+    result.pass(SelfTestStep::Convert, started_at);
+
+    let started_at = Instant::now();
+    mqtt_service
+        .lock()
+        .await
+        .publish(MessagePublishData::new(
+            self_test.topic().clone(),
+            *self_test.qos(),
+            false,
+            payload.clone(),
+        ))
+        .await;
+
+    let mut received = Vec::new();
+    if wait_for(receiver, timeout, |event| match event.incoming_publish() {
+        Some((topic, _, _, payload)) if topic == *self_test.topic() => {
+            received = payload;
+            true
+        }
+        _ => false,
+    })
+    .await
+    .is_none()
+    {
+        return result.fail(
+            SelfTestStep::Receive,
+            format!("message did not come back within {timeout:?}, check the publish permissions"),
+        );
+    }
+    result.pass(SelfTestStep::Receive, started_at);
+
+    let started_at = Instant::now();
+    match decode(received, self_test.payload_type()) {
+        Ok(content) if content == *self_test.content() => {
+            result.pass(SelfTestStep::Verify, started_at);
+            result
+        }
+        Ok(content) => result.fail(
+            SelfTestStep::Verify,
+            format!("received {content} instead of {}", self_test.content()),
+        ),
+        Err(e) => result.fail(SelfTestStep::Verify, e.to_string()),
+    }
+}
+
+/// Converts the JSON content to the payload published on the topic.
+fn encode(content: &Value, payload_type: &PayloadType) -> Result<Vec<u8>, PayloadFormatError> {
+    let json = PayloadFormat::Json(PayloadFormatJson::from(content.clone()));
+    Vec::<u8>::try_from(PayloadFormat::try_from((json, payload_type))?)
+}
+
+/// Converts a received payload of the payload type back to JSON.
+fn decode(payload: Vec<u8>, payload_type: &PayloadType) -> Result<Value, PayloadFormatError> {
+    let format = PayloadFormat::try_from((payload_type.clone(), payload))?;
+    match PayloadFormat::try_from((format, &PayloadType::Json))? {
+        PayloadFormat::Json(json) => Ok(json.content().clone()),
+        _ => unreachable!("conversion to JSON yields JSON"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let content = json!({"mqtli": "selftest", "values": [1, 2.5, true]});
+
+        for payload_type in [
+            PayloadType::Json,
+            PayloadType::Yaml,
+            PayloadType::Text(Default::default()),
+        ] {
+            let payload = encode(&content, &payload_type).unwrap();
+            assert_eq!(content, decode(payload, &payload_type).unwrap());
+        }
+    }
+
+    #[test]
+    fn display() {
+        let result = SelfTestResult {
+            passed: vec![(SelfTestStep::Connect, Duration::from_millis(5))],
+            failed: Some((SelfTestStep::Subscribe, "rejected".to_string())),
+        };
+
+        assert!(!result.is_success());
+        let output = result.to_string();
+        assert!(output.contains("connect (5.000 ms)"));
+        assert!(output.contains("subscribe: rejected"));
+    }
+}
@@ -0,0 +1,513 @@
+use crate::config::sparkplug::SparkplugCheck;
+use crate::mqtt::{MqttReceiveEvent, MqttService};
+use crate::output::theme::{Role, Themed};
+use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
+use crate::payload::sparkplug::PayloadFormatSparkplug;
+use crate::sparkplug::topic::{SparkplugTopic, SparkplugTopicEdgeNode};
+use crate::sparkplug::{
+    DeviceId, EdgeNodeId, GroupId, SparkplugMessageType, BD_SEQ, SPARKPLUG_TOPIC_VERSION,
+};
+use crate::MqtlibError;
+use colored::Colorize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Requirement of the Sparkplug B specification which is checked.
+#[derive(Clone, Copy, Debug, PartialEq, strum_macros::Display)]
+pub enum ConformanceRule {
+    /// NDATA, DBIRTH and DDATA require the birth of their edge node or device
+    #[strum(serialize = "birth before data")]
+    BirthBeforeData,
+    /// NBIRTH and NDEATH contain the same bdSeq metric
+    #[strum(serialize = "bdSeq")]
+    BdSeq,
+    /// Each message of an edge node increments the sequence number by one
+    #[strum(serialize = "sequence number")]
+    SequenceNumber,
+    /// Every metric of a birth message declares its datatype
+    #[strum(serialize = "metric datatype")]
+    MetricDatatype,
+    /// STATE messages of host applications are published retained
+    #[strum(serialize = "STATE retained")]
+    StateRetained,
+}
+
+/// Edge node or host application which published a message.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ConformanceSubject {
+    EdgeNode(GroupId, EdgeNodeId),
+    HostApplication(String),
+}
+
+impl Display for ConformanceSubject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConformanceSubject::EdgeNode(group_id, edge_node_id) => {
+                write!(f, "Edge node {group_id}/{edge_node_id}")
+            }
+            ConformanceSubject::HostApplication(host_id) => {
+                write!(f, "Host application {host_id}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub rule: ConformanceRule,
+    pub topic: String,
+    pub description: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.rule, self.topic, self.description)
+    }
+}
+
+/// Lifecycle of an edge node as seen in its messages.
+#[derive(Debug, Default)]
+struct EdgeNodeState {
+    born: bool,
+    /// A missing birth is reported once until the next NBIRTH
+    missing_birth_reported: bool,
+    bd_seq: Option<u64>,
+    seq: Option<u64>,
+    devices: HashSet<DeviceId>,
+}
+
+/// Validates the messages of a Sparkplug network and collects the
+/// violations per edge node and host application.
+#[derive(Debug, Default)]
+pub struct ConformanceChecker {
+    edge_nodes: BTreeMap<(GroupId, EdgeNodeId), EdgeNodeState>,
+    violations: BTreeMap<ConformanceSubject, Vec<Violation>>,
+    messages: usize,
+}
+
+impl ConformanceChecker {
+    /// Checks a received message and returns the violations it caused. The
+    /// payload is only needed for messages of edge nodes.
+    pub fn check(
+        &mut self,
+        topic: &SparkplugTopic,
+        payload: Option<&PayloadFormatSparkplug>,
+        retain: bool,
+    ) -> Vec<Violation> {
+        self.messages += 1;
+
+        let (subject, violations) = match topic {
+            SparkplugTopic::HostApplication(host) => {
+                let violations = if retain {
+                    vec![]
+                } else {
+                    vec![Violation {
+                        rule: ConformanceRule::StateRetained,
+                        topic: topic.to_string(),
+                        description: "STATE message was not retained".to_string(),
+                    }]
+                };
+                (
+                    ConformanceSubject::HostApplication(host.host_id.clone()),
+                    violations,
+                )
+            }
+            SparkplugTopic::EdgeNode(edge_node) => {
+                let Some(payload) = payload else {
+                    return vec![];
+                };
+                (
+                    ConformanceSubject::EdgeNode(
+                        edge_node.group_id.clone(),
+                        edge_node.edge_node_id.clone(),
+                    ),
+                    self.check_edge_node(topic.to_string(), edge_node, payload),
+                )
+            }
+        };
+
+        if !violations.is_empty() {
+            self.violations
+                .entry(subject)
+                .or_default()
+                .extend(violations.clone());
+        }
+
+        violations
+    }
+
+    pub fn violation_count(&self) -> usize {
+        self.violations.values().map(Vec::len).sum()
+    }
+
+    fn check_edge_node(
+        &mut self,
+        topic: String,
+        edge_node: &SparkplugTopicEdgeNode,
+        payload: &PayloadFormatSparkplug,
+    ) -> Vec<Violation> {
+        let state = self
+            .edge_nodes
+            .entry((edge_node.group_id.clone(), edge_node.edge_node_id.clone()))
+            .or_default();
+        let mut violations = vec![];
+        let mut violation = |rule: ConformanceRule, description: String| {
+            violations.push(Violation {
+                rule,
+                topic: topic.clone(),
+                description,
+            })
+        };
+        let message_type = &edge_node.message_type;
+
+        match message_type {
+            SparkplugMessageType::NBIRTH => {
+                state.born = true;
+                state.missing_birth_reported = false;
+                state.devices.clear();
+                state.seq = payload.content.seq;
+                state.bd_seq = bd_seq(payload);
+
+                if state.bd_seq.is_none() {
+                    violation(
+                        ConformanceRule::BdSeq,
+                        "NBIRTH contains no bdSeq metric".to_string(),
+                    );
+                }
+                if payload.content.seq.is_none() {
+                    violation(
+                        ConformanceRule::SequenceNumber,
+                        "NBIRTH contains no sequence number".to_string(),
+                    );
+                }
+            }
+            SparkplugMessageType::NDEATH => {
+                match (bd_seq(payload), state.bd_seq) {
+                    (None, _) => violation(
+                        ConformanceRule::BdSeq,
+                        "NDEATH contains no bdSeq metric".to_string(),
+                    ),
+                    (Some(death), Some(birth)) if state.born && death != birth => violation(
+                        ConformanceRule::BdSeq,
+                        format!("bdSeq {death} of NDEATH doesn't match bdSeq {birth} of NBIRTH"),
+                    ),
+                    _ => {}
+                }
+
+                state.born = false;
+                state.seq = None;
+                state.devices.clear();
+            }
+            SparkplugMessageType::NDATA
+            | SparkplugMessageType::DBIRTH
+            | SparkplugMessageType::DDATA
+            | SparkplugMessageType::DDEATH => {
+                if !state.born {
+                    if !state.missing_birth_reported {
+                        state.missing_birth_reported = true;
+                        violation(
+                            ConformanceRule::BirthBeforeData,
+                            format!("{message_type} received before NBIRTH of the edge node"),
+                        );
+                    }
+                } else {
+                    match (payload.content.seq, state.seq) {
+                        (None, _) => violation(
+                            ConformanceRule::SequenceNumber,
+                            format!("{message_type} contains no sequence number"),
+                        ),
+                        (Some(seq), Some(previous)) if seq != (previous + 1) % 256 => violation(
+                            ConformanceRule::SequenceNumber,
+                            format!(
+                                "Sequence number {seq} doesn't follow {previous}, expected {}",
+                                (previous + 1) % 256
+                            ),
+                        ),
+                        _ => {}
+                    }
+                    state.seq = payload.content.seq.or(state.seq);
+
+                    let device_id = edge_node.device_id.clone().unwrap_or_default();
+                    match message_type {
+                        SparkplugMessageType::DBIRTH => {
+                            state.devices.insert(device_id);
+                        }
+                        SparkplugMessageType::DDEATH => {
+                            state.devices.remove(&device_id);
+                        }
+                        SparkplugMessageType::DDATA if !state.devices.contains(&device_id) => {
+                            violation(
+                                ConformanceRule::BirthBeforeData,
+                                format!("DDATA received before DBIRTH of device {device_id}"),
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if matches!(
+            message_type,
+            SparkplugMessageType::NBIRTH | SparkplugMessageType::DBIRTH
+        ) {
+            for metric in &payload.content.metrics {
+                if metric.datatype.unwrap_or_default() == 0 {
+                    violation(
+                        ConformanceRule::MetricDatatype,
+                        format!(
+                            "Metric {} has no datatype",
+                            metric.name.as_deref().unwrap_or("without name")
+                        ),
+                    );
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+/// Value of the bdSeq metric, if the payload contains it.
+fn bd_seq(payload: &PayloadFormatSparkplug) -> Option<u64> {
+    payload
+        .content
+        .metrics
+        .iter()
+        .find(|metric| metric.name.as_deref() == Some(BD_SEQ))
+        .and_then(|metric| match metric.value {
+            Some(Value::LongValue(value)) => Some(value),
+            Some(Value::IntValue(value)) => Some(value as u64),
+            _ => None,
+        })
+}
+
+/// Prints the number of checked messages and the violations grouped by
+/// edge node and host application.
+impl Display for ConformanceChecker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} messages of {} edge nodes checked, {} violations",
+            self.messages,
+            self.edge_nodes.len(),
+            self.violation_count()
+        )?;
+
+        for (subject, violations) in &self.violations {
+            writeln!(f, "{}", subject.to_string().bold())?;
+            for violation in violations {
+                writeln!(f, "  {}", violation.to_string().themed(Role::Removed))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Subscribes to the messages of the groups and the STATE messages of all
+/// host applications and checks them until the duration elapsed or the
+/// shutdown future completes.
+pub async fn run_conformance_check<F>(
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    check: &SparkplugCheck,
+    capacity: usize,
+    shutdown: F,
+) -> Result<ConformanceChecker, MqtlibError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (sender_receive, mut receiver) = broadcast::channel::<MqttReceiveEvent>(capacity);
+    let (sender_disconnect, _) = broadcast::channel::<()>(1);
+    let mut checker = ConformanceChecker::default();
+
+    let mut topics: Vec<String> = if check.groups().is_empty() {
+        vec![format!("{SPARKPLUG_TOPIC_VERSION}/+/#")]
+    } else {
+        check
+            .groups()
+            .iter()
+            .map(|group_id| format!("{SPARKPLUG_TOPIC_VERSION}/{group_id}/#"))
+            .collect()
+    };
+    topics.push(format!("{SPARKPLUG_TOPIC_VERSION}/STATE/+"));
+
+    let mqtt_loop_handle = mqtt_service
+        .lock()
+        .await
+        .connect(sender_receive, sender_disconnect.subscribe())
+        .await?;
+
+    tokio::pin!(shutdown);
+
+    let connected = loop {
+        select! {
+            _ = &mut shutdown => break false,
+            event = receiver.recv() => match event {
+                Ok(event) if event.is_connect() => break true,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(MqtlibError::Disconnected),
+            }
+        }
+    };
+
+    if connected {
+        for topic in &topics {
+            mqtt_service
+                .lock()
+                .await
+                .subscribe(topic.clone(), *check.qos())
+                .await?;
+        }
+        info!("Checking Sparkplug messages on {}", topics.join(", "));
+
+        let deadline = async {
+            match check.duration() {
+                Some(duration) => tokio::time::sleep(*duration).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(deadline);
+
+        loop {
+            select! {
+                _ = &mut deadline => break,
+                _ = &mut shutdown => break,
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        let Some((topic, _, retain, payload)) = event.incoming_publish() else {
+                            continue;
+                        };
+                        let Ok(topic) = SparkplugTopic::try_from(topic.as_str()) else {
+                            continue;
+                        };
+                        let payload = match topic {
+                            SparkplugTopic::EdgeNode(_) => {
+                                match PayloadFormatSparkplug::try_from(payload) {
+                                    Ok(payload) => Some(payload),
+                                    Err(e) => {
+                                        warn!("Could not decode Sparkplug message on topic {topic}: {e}");
+                                        continue;
+                                    }
+                                }
+                            }
+                            SparkplugTopic::HostApplication(_) => None,
+                        };
+
+                        for violation in checker.check(&topic, payload.as_ref(), retain) {
+                            warn!("{violation}");
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Conformance check lagged behind, {count} messages were not checked");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+
+        for topic in topics {
+            let _ = mqtt_service.lock().await.unsubscribe(topic).await;
+        }
+    }
+
+    let _ = sender_disconnect.send(());
+    let _ = mqtt_loop_handle.await;
+
+    Ok(checker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::sparkplug::{EmulatedMetric, SparkplugEmulator};
+    use crate::sparkplug::emulator::EdgeNodeEmulator;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn get_emulator() -> EdgeNodeEmulator {
+        EdgeNodeEmulator::new(SparkplugEmulator {
+            group_id: "group".to_string(),
+            edge_node_id: "edge".to_string(),
+            interval: Duration::from_secs(1),
+            metrics: vec![EmulatedMetric::from_str("counter:int={{counter}}").unwrap()],
+        })
+    }
+
+    fn rules(violations: Vec<Violation>) -> Vec<ConformanceRule> {
+        violations.iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn conforming_edge_node() {
+        let mut emulator = get_emulator();
+        let mut checker = ConformanceChecker::default();
+
+        for (topic, payload) in [
+            emulator.birth(),
+            emulator.data(),
+            emulator.data(),
+            emulator.death_certificate(),
+        ] {
+            assert!(checker.check(&topic, Some(&payload), false).is_empty());
+        }
+        assert_eq!(0, checker.violation_count());
+    }
+
+    #[test]
+    fn violations() {
+        let mut emulator = get_emulator();
+        let mut checker = ConformanceChecker::default();
+
+        // data before birth is reported once
+        let (topic, data) = emulator.data();
+        assert_eq!(
+            vec![ConformanceRule::BirthBeforeData],
+            rules(checker.check(&topic, Some(&data), false))
+        );
+        let (topic, data) = emulator.data();
+        assert!(checker.check(&topic, Some(&data), false).is_empty());
+
+        let (topic, mut birth) = emulator.birth();
+        birth.content.metrics[2].datatype = None;
+        assert_eq!(
+            vec![ConformanceRule::MetricDatatype],
+            rules(checker.check(&topic, Some(&birth), false))
+        );
+
+        // a lost message breaks the sequence
+        emulator.data();
+        let (topic, data) = emulator.data();
+        assert_eq!(
+            vec![ConformanceRule::SequenceNumber],
+            rules(checker.check(&topic, Some(&data), false))
+        );
+
+        let (topic, mut death) = emulator.death_certificate();
+        death.content.metrics.clear();
+        assert_eq!(
+            vec![ConformanceRule::BdSeq],
+            rules(checker.check(&topic, Some(&death), false))
+        );
+
+        let state = SparkplugTopic::try_from("spBv1.0/STATE/scada").unwrap();
+        assert!(checker.check(&state, None, true).is_empty());
+        assert_eq!(
+            vec![ConformanceRule::StateRetained],
+            rules(checker.check(&state, None, false))
+        );
+
+        assert_eq!(5, checker.violation_count());
+        let report = checker.to_string();
+        assert!(report.contains("Edge node group/edge"));
+        assert!(report.contains("Host application scada"));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::config::mqtli_config::LastWillConfig;
+use crate::config::sparkplug::{EmulatedDataType, EmulatedMetric, SparkplugEmulator};
+use crate::mqtt::QoS;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::Metric;
+use crate::payload::sparkplug::protos::sparkplug_b::{DataType, Payload};
+use crate::payload::sparkplug::PayloadFormatSparkplug;
+use crate::payload::PayloadFormatError;
+use crate::sparkplug::topic::{SparkplugTopic, SparkplugTopicEdgeNode};
+use crate::sparkplug::{
+    SparkplugMessageType, BD_SEQ, NODE_CONTROL_REBIRTH, SPARKPLUG_TOPIC_VERSION,
+};
+use crate::template;
+use chrono::Utc;
+use rand::Rng;
+use tracing::warn;
+
+/// Emulated edge node which creates the messages of its lifecycle: NBIRTH
+/// with all metrics, NDATA with freshly rendered values and the NDEATH used
+/// as last will.
+///
+/// The bdSeq number stays the same for the whole session because the last
+/// will can't be changed after connecting.
+#[derive(Clone, Debug)]
+pub struct EdgeNodeEmulator {
+    config: SparkplugEmulator,
+    bd_seq: u64,
+    seq: u64,
+    counter: u64,
+}
+
+impl EdgeNodeEmulator {
+    pub fn new(config: SparkplugEmulator) -> Self {
+        Self {
+            config,
+            bd_seq: 0,
+            seq: 0,
+            counter: 0,
+        }
+    }
+
+    pub fn config(&self) -> &SparkplugEmulator {
+        &self.config
+    }
+
+    pub fn topic(&self, message_type: SparkplugMessageType) -> SparkplugTopic {
+        SparkplugTopic::EdgeNode(SparkplugTopicEdgeNode {
+            version: SPARKPLUG_TOPIC_VERSION.to_string(),
+            group_id: self.config.group_id.clone(),
+            edge_node_id: self.config.edge_node_id.clone(),
+            message_type,
+            device_id: None,
+            metric_levels: vec![],
+        })
+    }
+
+    /// Creates the NDEATH message which the broker publishes when the
+    /// connection of the edge node is lost.
+    pub fn death_certificate(&self) -> (SparkplugTopic, PayloadFormatSparkplug) {
+        let mut payload = Payload::new();
+        payload.timestamp = Some(Self::now());
+        payload.metrics.push(self.bd_seq_metric());
+
+        (
+            self.topic(SparkplugMessageType::NDEATH),
+            PayloadFormatSparkplug::from(payload),
+        )
+    }
+
+    /// Last will publishing the death certificate with QoS 1 as required by
+    /// the Sparkplug specification.
+    pub fn last_will(&self) -> Result<LastWillConfig, PayloadFormatError> {
+        let (topic, payload) = self.death_certificate();
+
+        Ok(LastWillConfig {
+            topic: topic.to_string(),
+            payload: Vec::<u8>::try_from(payload)?,
+            qos: QoS::AtLeastOnce,
+            ..Default::default()
+        })
+    }
+
+    /// Creates the NBIRTH message announcing all metrics with their aliases.
+    /// The sequence number starts again at 0.
+    pub fn birth(&mut self) -> (SparkplugTopic, PayloadFormatSparkplug) {
+        self.seq = 0;
+
+        let mut rebirth = Metric::new();
+        rebirth.name = Some(NODE_CONTROL_REBIRTH.to_string());
+        rebirth.datatype = Some(DataType::Boolean as u32);
+        rebirth.value = Some(Value::BooleanValue(false));
+
+        let mut payload = self.payload();
+        payload.metrics.push(self.bd_seq_metric());
+        payload.metrics.push(rebirth);
+
+        for (alias, metric) in self.config.metrics.iter().enumerate() {
+            let mut birth_metric = self.render(metric, alias as u64 + 1);
+            birth_metric.name = Some(metric.name.clone());
+            birth_metric.datatype = Some(Self::datatype(&metric.datatype) as u32);
+            payload.metrics.push(birth_metric);
+        }
+
+        (
+            self.topic(SparkplugMessageType::NBIRTH),
+            PayloadFormatSparkplug::from(payload),
+        )
+    }
+
+    /// Creates a NDATA message with the current values of all metrics,
+    /// referenced by their aliases.
+    pub fn data(&mut self) -> (SparkplugTopic, PayloadFormatSparkplug) {
+        self.counter += 1;
+
+        let mut payload = self.payload();
+
+        for (alias, metric) in self.config.metrics.iter().enumerate() {
+            payload.metrics.push(self.render(metric, alias as u64 + 1));
+        }
+
+        (
+            self.topic(SparkplugMessageType::NDATA),
+            PayloadFormatSparkplug::from(payload),
+        )
+    }
+
+    /// Checks if the message is a Node Control/Rebirth command for this edge
+    /// node.
+    pub fn is_rebirth_request(
+        &self,
+        topic: &SparkplugTopic,
+        payload: &PayloadFormatSparkplug,
+    ) -> bool {
+        let SparkplugTopic::EdgeNode(topic) = topic else {
+            return false;
+        };
+
+        topic.message_type == SparkplugMessageType::NCMD
+            && topic.group_id == self.config.group_id
+            && topic.edge_node_id == self.config.edge_node_id
+            && topic.device_id.is_none()
+            && payload.content.metrics.iter().any(|metric| {
+                metric.name.as_deref() == Some(NODE_CONTROL_REBIRTH)
+                    && matches!(metric.value, Some(Value::BooleanValue(true)))
+            })
+    }
+
+    fn payload(&mut self) -> Payload {
+        let mut payload = Payload::new();
+        payload.timestamp = Some(Self::now());
+        payload.seq = Some(self.seq);
+
+        self.seq = (self.seq + 1) % 256;
+
+        payload
+    }
+
+    fn bd_seq_metric(&self) -> Metric {
+        let mut metric = Metric::new();
+        metric.name = Some(BD_SEQ.to_string());
+        metric.datatype = Some(DataType::Int64 as u32);
+        metric.value = Some(Value::LongValue(self.bd_seq));
+        metric
+    }
+
+    fn render(&self, metric: &EmulatedMetric, alias: u64) -> Metric {
+        let value = render_template(&metric.value, &metric.datatype, self.counter);
+
+        let mut result = Metric::new();
+        result.alias = Some(alias);
+        result.timestamp = Some(Self::now());
+        result.value = match metric.datatype {
+            EmulatedDataType::Int => value
+                .parse::<i64>()
+                .ok()
+                .map(|v| Value::LongValue(v as u64)),
+            EmulatedDataType::Double => value.parse::<f64>().ok().map(Value::DoubleValue),
+            EmulatedDataType::Boolean => value.parse::<bool>().ok().map(Value::BooleanValue),
+            EmulatedDataType::String => Some(Value::StringValue(value.clone())),
+        };
+
+        if result.value.is_none() {
+            warn!(
+                "Value \"{value}\" of metric {} is not of type {:?}, sending null",
+                metric.name, metric.datatype
+            );
+            result.is_null = Some(true);
+        }
+
+        result
+    }
+
+    fn datatype(datatype: &EmulatedDataType) -> DataType {
+        match datatype {
+            EmulatedDataType::Int => DataType::Int64,
+            EmulatedDataType::Double => DataType::Double,
+            EmulatedDataType::Boolean => DataType::Boolean,
+            EmulatedDataType::String => DataType::String,
+        }
+    }
+
+    fn now() -> u64 {
+        Utc::now().timestamp_millis() as u64
+    }
+}
+
+/// Expands the placeholders of a metric value, besides the ones of
+/// [`crate::template`]. Unknown placeholders expand to an empty string.
+fn render_template(template: &str, datatype: &EmulatedDataType, counter: u64) -> String {
+    template::render(template, |name| match name {
+        "counter" => Some(counter.to_string()),
+        "toggle" => Some((counter % 2 == 1).to_string()),
+        name => Some(
+            name.strip_prefix("random:")
+                .and_then(|range| random(range, datatype))
+                .or_else(|| template::common(name))
+                .unwrap_or_default(),
+        ),
+    })
+    .into_owned()
+}
+
+fn random(range: &str, datatype: &EmulatedDataType) -> Option<String> {
+    let (min, max) = range.split_once(':')?;
+
+    match datatype {
+        EmulatedDataType::Int => {
+            let (min, max) = (
+                min.trim().parse::<i64>().ok()?,
+                max.trim().parse::<i64>().ok()?,
+            );
+            (min <= max).then(|| rand::thread_rng().gen_range(min..=max).to_string())
+        }
+        _ => {
+            let (min, max) = (
+                min.trim().parse::<f64>().ok()?,
+                max.trim().parse::<f64>().ok()?,
+            );
+            (min <= max).then(|| rand::thread_rng().gen_range(min..=max).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn get_emulator() -> EdgeNodeEmulator {
+        EdgeNodeEmulator::new(SparkplugEmulator {
+            group_id: "group".to_string(),
+            edge_node_id: "edge".to_string(),
+            interval: Duration::from_secs(1),
+            metrics: vec![
+                EmulatedMetric::from_str("counter:int={{counter}}").unwrap(),
+                EmulatedMetric::from_str("temperature:double={{random:15:25}}").unwrap(),
+                EmulatedMetric::from_str("running:bool={{toggle}}").unwrap(),
+            ],
+        })
+    }
+
+    #[test]
+    fn birth_and_data() {
+        let mut emulator = get_emulator();
+
+        let (topic, birth) = emulator.birth();
+        assert_eq!("spBv1.0/group/NBIRTH/edge", topic.to_string());
+        assert_eq!(Some(0), birth.content.seq);
+        assert_eq!(5, birth.content.metrics.len());
+        assert_eq!(Some("counter"), birth.content.metrics[2].name.as_deref());
+        assert_eq!(Some(1), birth.content.metrics[2].alias);
+
+        let (topic, data) = emulator.data();
+        assert_eq!("spBv1.0/group/NDATA/edge", topic.to_string());
+        assert_eq!(Some(1), data.content.seq);
+        assert_eq!(3, data.content.metrics.len());
+        assert_eq!(None, data.content.metrics[0].name);
+        assert_eq!(Some(Value::LongValue(1)), data.content.metrics[0].value);
+        assert_eq!(
+            Some(Value::BooleanValue(true)),
+            data.content.metrics[2].value
+        );
+
+        let Some(Value::DoubleValue(temperature)) = data.content.metrics[1].value else {
+            panic!()
+        };
+        assert!((15.0..=25.0).contains(&temperature));
+
+        let (topic, death) = emulator.death_certificate();
+        assert_eq!("spBv1.0/group/NDEATH/edge", topic.to_string());
+        assert_eq!(Some(BD_SEQ), death.content.metrics[0].name.as_deref());
+    }
+
+    #[test]
+    fn rebirth_request() {
+        let emulator = get_emulator();
+
+        let SparkplugTopic::EdgeNode(topic) = emulator.topic(SparkplugMessageType::NDATA) else {
+            panic!()
+        };
+        let (command_topic, command) = crate::sparkplug::network::create_rebirth_request(&topic);
+
+        assert!(emulator.is_rebirth_request(&command_topic, &command));
+        assert!(
+            !emulator.is_rebirth_request(&emulator.topic(SparkplugMessageType::NDATA), &command)
+        );
+    }
+
+    #[test]
+    fn render_templates() {
+        assert_eq!(
+            "value 3",
+            render_template("value {{counter}}", &EmulatedDataType::String, 3)
+        );
+        assert_eq!(
+            "",
+            render_template("{{random:5:1}}", &EmulatedDataType::Int, 0)
+        );
+        assert_eq!(
+            "2",
+            render_template("{{random:2:2}}", &EmulatedDataType::Int, 0)
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::sparkplug::MessageStorage;
+use serde::Serialize;
 use std::collections::HashMap;
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
@@ -16,3 +17,49 @@ impl SparkplugHostApplicationStorage {
         self.0.values().map(|e| e.messages.len()).sum()
     }
 }
+
+/// State of the primary host application announced on its STATE topic.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PrimaryHostState {
+    pub host_id: String,
+    pub online: bool,
+    /// Milliseconds since the epoch given by the host application
+    pub timestamp: Option<u64>,
+}
+
+/// Parses the payload of a STATE message, either the JSON object of
+/// Sparkplug 3.0 (`{"online": true, "timestamp": 1700000000000}`) or the
+/// text `ONLINE` / `OFFLINE` of earlier versions.
+pub fn parse_state_payload(payload: &[u8]) -> Option<(bool, Option<u64>)> {
+    if let Ok(value) = serde_json::from_slice::<serde_json::Value>(payload) {
+        if let Some(online) = value.get("online").and_then(serde_json::Value::as_bool) {
+            let timestamp = value.get("timestamp").and_then(serde_json::Value::as_u64);
+            return Some((online, timestamp));
+        }
+    }
+
+    match std::str::from_utf8(payload).ok()?.trim() {
+        "ONLINE" => Some((true, None)),
+        "OFFLINE" => Some((false, None)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_payload() {
+        assert_eq!(
+            Some((true, Some(1700000000000))),
+            parse_state_payload(br#"{"online": true, "timestamp": 1700000000000}"#)
+        );
+        assert_eq!(
+            Some((false, None)),
+            parse_state_payload(br#"{"online": false}"#)
+        );
+        assert_eq!(Some((false, None)), parse_state_payload(b"OFFLINE"));
+        assert_eq!(None, parse_state_payload(b"{}"));
+    }
+}
@@ -1,8 +1,13 @@
+pub mod conformance;
 pub mod device;
 pub mod edge_node;
+pub mod emulator;
 pub mod host_application;
 pub mod network;
+pub mod snapshot;
+pub mod summary;
 pub mod topic;
+pub mod value;
 
 use crate::payload::sparkplug::PayloadFormatSparkplug;
 use strum_macros::{Display, EnumString};
@@ -13,6 +18,8 @@ pub type EdgeNodeId = String;
 pub type DeviceId = String;
 
 pub const SPARKPLUG_TOPIC_VERSION: &str = "spBv1.0";
+pub const NODE_CONTROL_REBIRTH: &str = "Node Control/Rebirth";
+pub const BD_SEQ: &str = "bdSeq";
 
 #[derive(Debug, Error)]
 pub enum SparkplugError {
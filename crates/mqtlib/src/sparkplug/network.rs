@@ -1,21 +1,93 @@
+use crate::config::sparkplug::SparkplugMetricFilter;
 use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
-use crate::payload::sparkplug::protos::sparkplug_b::payload::Template;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::{Metric, Template};
+use crate::payload::sparkplug::protos::sparkplug_b::{DataType, Payload};
 use crate::payload::sparkplug::PayloadFormatSparkplug;
 use crate::sparkplug::edge_node::SparkplugEdgeNodeStorage;
 use crate::sparkplug::host_application::{
-    SparkplugHostApplication, SparkplugHostApplicationStorage,
+    parse_state_payload, PrimaryHostState, SparkplugHostApplication,
+    SparkplugHostApplicationStorage,
 };
-use crate::sparkplug::topic::SparkplugTopic;
-use std::collections::HashMap;
+use crate::sparkplug::snapshot::{
+    replace_metrics, update_metrics, SparkplugGroupSnapshot, SparkplugSnapshot,
+};
+use crate::sparkplug::summary::{
+    SparkplugGroupStatistics, SparkplugGroupSummary, SparkplugSummary,
+};
+use crate::sparkplug::topic::{SparkplugTopic, SparkplugTopicEdgeNode};
+use crate::sparkplug::{EdgeNodeId, GroupId, SparkplugMessageType, Status, NODE_CONTROL_REBIRTH};
+use chrono::Utc;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use tracing::{debug, trace, warn};
 
 #[derive(Clone, Debug, Default)]
 pub struct SparkplugNetwork {
     pub host_applications: SparkplugHostApplicationStorage,
     pub edge_nodes: SparkplugEdgeNodeStorage,
+
+    // metric names by alias as defined by the birth messages of each edge node
+    aliases: HashMap<(GroupId, EdgeNodeId), HashMap<u64, String>>,
+    rebirth_requested: HashSet<(GroupId, EdgeNodeId)>,
+    groups: BTreeMap<GroupId, SparkplugGroupSnapshot>,
+    statistics: BTreeMap<GroupId, SparkplugGroupStatistics>,
+    metric_filter: SparkplugMetricFilter,
+    primary_host_id: Option<String>,
+    primary_host: Option<PrimaryHostState>,
 }
 
 impl SparkplugNetwork {
+    pub fn new(metric_filter: SparkplugMetricFilter) -> Self {
+        Self {
+            metric_filter,
+            ..Default::default()
+        }
+    }
+
+    /// Tracks the STATE of the given primary host application.
+    pub fn with_primary_host(mut self, host_id: Option<String>) -> Self {
+        self.primary_host_id = host_id;
+        self
+    }
+
+    /// Last known state of the primary host application, None if no STATE
+    /// message of it was received yet.
+    pub fn primary_host(&self) -> Option<&PrimaryHostState> {
+        self.primary_host.as_ref()
+    }
+
+    /// Updates the state of the primary host application from the payload
+    /// of a STATE message and returns the new state if it changed. STATE
+    /// messages of other host applications and messages older than the
+    /// current state are ignored.
+    pub fn update_host_state(&mut self, host_id: &str, payload: &[u8]) -> Option<PrimaryHostState> {
+        if self.primary_host_id.as_deref() != Some(host_id) {
+            return None;
+        }
+
+        let Some((online, timestamp)) = parse_state_payload(payload) else {
+            warn!("Could not parse STATE message of primary host {host_id}");
+            return None;
+        };
+
+        if let Some(current) = &self.primary_host {
+            let is_older = matches!(
+                (current.timestamp, timestamp),
+                (Some(current), Some(timestamp)) if timestamp < current
+            );
+            if is_older || current.online == online {
+                return None;
+            }
+        }
+
+        let state = PrimaryHostState {
+            host_id: host_id.to_string(),
+            online,
+            timestamp,
+        };
+        self.primary_host = Some(state.clone());
+        Some(state)
+    }
+
     pub fn count_received_messages(&self) -> usize {
         self.edge_nodes.count_received_messages() + self.host_applications.count_received_messages()
     }
@@ -23,6 +95,16 @@ impl SparkplugNetwork {
     pub fn parse_message(&mut self, topic: SparkplugTopic, message: PayloadFormatSparkplug) {
         match topic {
             SparkplugTopic::EdgeNode(data) => {
+                self.statistics
+                    .entry(data.group_id.clone())
+                    .or_default()
+                    .count_message(data.message_type.to_string(), &data.edge_node_id);
+
+                // aliases of excluded metrics are still needed to detect unknown aliases
+                self.update_aliases(&data, &message);
+                let message = self.filter_metrics(&data, &message);
+                self.update_state(&data, &message);
+
                 let storage = self
                     .edge_nodes
                     .get_message_storage(data.group_id, data.edge_node_id);
@@ -39,6 +121,167 @@ impl SparkplugNetwork {
         }
     }
 
+    /// Removes the metrics excluded by the metric filter from the message.
+    /// Metrics only carrying an alias are resolved by the birth messages seen
+    /// so far; metrics whose name is unknown are kept.
+    pub fn filter_metrics(
+        &self,
+        topic: &SparkplugTopicEdgeNode,
+        message: &PayloadFormatSparkplug,
+    ) -> PayloadFormatSparkplug {
+        let mut message = message.clone();
+
+        if self.metric_filter.is_empty() {
+            return message;
+        }
+
+        let aliases = self
+            .aliases
+            .get(&(topic.group_id.clone(), topic.edge_node_id.clone()));
+
+        message.content.metrics.retain(|metric| {
+            let name = metric.name.as_ref().or_else(|| {
+                metric
+                    .alias
+                    .and_then(|alias| aliases.and_then(|aliases| aliases.get(&alias)))
+            });
+
+            name.map_or(true, |name| self.metric_filter.is_included(name))
+        });
+
+        message
+    }
+
+    /// Returns true if a data message of an edge node can't be resolved
+    /// because no NBIRTH was seen for it or it references metric aliases not
+    /// defined by a birth message. Returns true only once per edge node until
+    /// its next NBIRTH arrives, so that a rebirth is requested only once.
+    pub fn needs_rebirth(
+        &mut self,
+        topic: &SparkplugTopicEdgeNode,
+        message: &PayloadFormatSparkplug,
+    ) -> bool {
+        if !matches!(
+            topic.message_type,
+            SparkplugMessageType::NDATA | SparkplugMessageType::DDATA
+        ) {
+            return false;
+        }
+
+        let key = (topic.group_id.clone(), topic.edge_node_id.clone());
+
+        let is_unknown = match self.aliases.get(&key) {
+            None => true,
+            Some(aliases) => message
+                .content
+                .metrics
+                .iter()
+                .filter(|metric| metric.name.is_none())
+                .filter_map(|metric| metric.alias)
+                .any(|alias| !aliases.contains_key(&alias)),
+        };
+
+        is_unknown && self.rebirth_requested.insert(key)
+    }
+
+    fn update_aliases(&mut self, topic: &SparkplugTopicEdgeNode, message: &PayloadFormatSparkplug) {
+        let key = (topic.group_id.clone(), topic.edge_node_id.clone());
+        let aliases = message
+            .content
+            .metrics
+            .iter()
+            .filter_map(|metric| Some((metric.alias?, metric.name.clone()?)));
+
+        match topic.message_type {
+            SparkplugMessageType::NBIRTH => {
+                self.rebirth_requested.remove(&key);
+                self.aliases.insert(key, aliases.collect());
+            }
+            SparkplugMessageType::DBIRTH => {
+                self.aliases.entry(key).or_default().extend(aliases);
+            }
+            SparkplugMessageType::NDEATH => {
+                self.aliases.remove(&key);
+            }
+            _ => {}
+        }
+    }
+
+    fn update_state(&mut self, topic: &SparkplugTopicEdgeNode, message: &PayloadFormatSparkplug) {
+        let aliases = self
+            .aliases
+            .get(&(topic.group_id.clone(), topic.edge_node_id.clone()));
+
+        let edge_node = self
+            .groups
+            .entry(topic.group_id.clone())
+            .or_default()
+            .edge_nodes
+            .entry(topic.edge_node_id.clone())
+            .or_default();
+
+        match (&topic.message_type, &topic.device_id) {
+            (SparkplugMessageType::NBIRTH, _) => {
+                edge_node.set_status(Status::ONLINE);
+                replace_metrics(&mut edge_node.metrics, &message.content.metrics, aliases);
+            }
+            (SparkplugMessageType::NDATA, _) => {
+                update_metrics(&mut edge_node.metrics, &message.content.metrics, aliases);
+            }
+            (SparkplugMessageType::NDEATH, _) => {
+                edge_node.set_status(Status::OFFLINE);
+            }
+            (SparkplugMessageType::DBIRTH, Some(device_id)) => {
+                let device = edge_node.devices.entry(device_id.clone()).or_default();
+                device.set_status(Status::ONLINE);
+                replace_metrics(&mut device.metrics, &message.content.metrics, aliases);
+            }
+            (SparkplugMessageType::DDATA, Some(device_id)) => {
+                let device = edge_node.devices.entry(device_id.clone()).or_default();
+                update_metrics(&mut device.metrics, &message.content.metrics, aliases);
+            }
+            (SparkplugMessageType::DDEATH, Some(device_id)) => {
+                edge_node
+                    .devices
+                    .entry(device_id.clone())
+                    .or_default()
+                    .set_status(Status::OFFLINE);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the current state of all groups, edge nodes and devices
+    /// including the latest value of each metric.
+    pub fn snapshot(&self) -> SparkplugSnapshot {
+        SparkplugSnapshot {
+            created_at: Utc::now().to_rfc3339(),
+            groups: self.groups.clone(),
+        }
+    }
+
+    /// Returns the number of edge nodes and devices as well as the received
+    /// messages of each group.
+    pub fn summary(&self) -> SparkplugSummary {
+        SparkplugSummary {
+            created_at: Utc::now(),
+            groups: self
+                .groups
+                .keys()
+                .chain(self.statistics.keys())
+                .map(|group_id| {
+                    (
+                        group_id.clone(),
+                        SparkplugGroupSummary::new(
+                            self.groups.get(group_id),
+                            self.statistics.get(group_id),
+                        ),
+                    )
+                })
+                .collect(),
+        }
+    }
+
     fn _extract_templates(&self, message: &PayloadFormatSparkplug) -> HashMap<String, Template> {
         let mut result = HashMap::new();
 
@@ -62,3 +305,241 @@ impl SparkplugNetwork {
         result
     }
 }
+
+/// Creates a Node Control/Rebirth command (NCMD) for the edge node of the
+/// given topic.
+pub fn create_rebirth_request(
+    topic: &SparkplugTopicEdgeNode,
+) -> (SparkplugTopic, PayloadFormatSparkplug) {
+    let timestamp = Utc::now().timestamp_millis() as u64;
+
+    let mut metric = Metric::new();
+    metric.name = Some(NODE_CONTROL_REBIRTH.to_string());
+    metric.timestamp = Some(timestamp);
+    metric.datatype = Some(DataType::Boolean as u32);
+    metric.value = Some(Value::BooleanValue(true));
+
+    let mut payload = Payload::new();
+    payload.timestamp = Some(timestamp);
+    payload.metrics.push(metric);
+
+    let topic = SparkplugTopic::EdgeNode(SparkplugTopicEdgeNode {
+        version: topic.version.clone(),
+        group_id: topic.group_id.clone(),
+        edge_node_id: topic.edge_node_id.clone(),
+        message_type: SparkplugMessageType::NCMD,
+        device_id: None,
+        metric_levels: vec![],
+    });
+
+    (topic, PayloadFormatSparkplug::from(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_message(
+        message_type: SparkplugMessageType,
+        metrics: Vec<(Option<&str>, u64)>,
+    ) -> (SparkplugTopicEdgeNode, PayloadFormatSparkplug) {
+        let topic = SparkplugTopicEdgeNode {
+            version: "spBv1.0".to_string(),
+            group_id: "group".to_string(),
+            edge_node_id: "edge".to_string(),
+            message_type,
+            device_id: None,
+            metric_levels: vec![],
+        };
+
+        let mut payload = Payload::new();
+        for (name, alias) in metrics {
+            let mut metric = Metric::new();
+            metric.name = name.map(str::to_string);
+            metric.alias = Some(alias);
+            payload.metrics.push(metric);
+        }
+
+        (topic, PayloadFormatSparkplug::from(payload))
+    }
+
+    #[test]
+    fn needs_rebirth() {
+        let mut network = SparkplugNetwork::default();
+
+        let (topic, data) = get_message(SparkplugMessageType::NDATA, vec![(None, 1)]);
+        assert!(network.needs_rebirth(&topic, &data));
+        assert!(!network.needs_rebirth(&topic, &data));
+
+        let (topic_birth, birth) =
+            get_message(SparkplugMessageType::NBIRTH, vec![(Some("temperature"), 1)]);
+        network.parse_message(SparkplugTopic::EdgeNode(topic_birth), birth);
+        assert!(!network.needs_rebirth(&topic, &data));
+
+        let (topic, data) = get_message(SparkplugMessageType::NDATA, vec![(None, 2)]);
+        assert!(network.needs_rebirth(&topic, &data));
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut network = SparkplugNetwork::default();
+
+        let (topic, mut birth) =
+            get_message(SparkplugMessageType::NBIRTH, vec![(Some("temperature"), 1)]);
+        birth.content.metrics[0].value = Some(Value::DoubleValue(20.5));
+        network.parse_message(SparkplugTopic::EdgeNode(topic), birth);
+
+        let (topic, mut data) = get_message(SparkplugMessageType::NDATA, vec![(None, 1)]);
+        data.content.metrics[0].value = Some(Value::DoubleValue(21.0));
+        network.parse_message(SparkplugTopic::EdgeNode(topic), data);
+
+        let snapshot = network.snapshot();
+        let edge_node = &snapshot.groups["group"].edge_nodes["edge"];
+        assert_eq!("online", edge_node.status);
+        assert_eq!(
+            serde_json::Value::from(21.0),
+            edge_node.metrics["temperature"].value
+        );
+        assert_eq!(
+            serde_json::Value::from(20.5),
+            edge_node.metrics["temperature"].history[0].1
+        );
+
+        let (topic, death) = get_message(SparkplugMessageType::NDEATH, vec![]);
+        network.parse_message(SparkplugTopic::EdgeNode(topic), death);
+        assert_eq!(
+            "offline",
+            network.snapshot().groups["group"].edge_nodes["edge"].status
+        );
+    }
+
+    #[test]
+    fn rebirth_replaces_metrics() {
+        let mut network = SparkplugNetwork::default();
+
+        let (topic, birth) = get_message(
+            SparkplugMessageType::NBIRTH,
+            vec![(Some("temperature"), 1), (Some("pressure"), 2)],
+        );
+        network.parse_message(SparkplugTopic::EdgeNode(topic), birth);
+        let (topic, birth) =
+            get_message(SparkplugMessageType::NBIRTH, vec![(Some("temperature"), 1)]);
+        network.parse_message(SparkplugTopic::EdgeNode(topic), birth);
+
+        let snapshot = network.snapshot();
+        let metrics = &snapshot.groups["group"].edge_nodes["edge"].metrics;
+        assert_eq!(vec!["temperature"], metrics.keys().collect::<Vec<_>>());
+        assert_eq!(1, metrics["temperature"].history.len());
+    }
+
+    #[test]
+    fn summary() {
+        let mut network = SparkplugNetwork::default();
+
+        let (topic, birth) = get_message(SparkplugMessageType::NBIRTH, vec![]);
+        network.parse_message(SparkplugTopic::EdgeNode(topic), birth);
+
+        let (mut topic, birth) = get_message(SparkplugMessageType::DBIRTH, vec![]);
+        topic.device_id = Some("device".to_string());
+        network.parse_message(SparkplugTopic::EdgeNode(topic), birth);
+
+        for _ in 0..3 {
+            let (topic, data) = get_message(SparkplugMessageType::NDATA, vec![]);
+            network.parse_message(SparkplugTopic::EdgeNode(topic), data);
+        }
+
+        let summary = network.summary();
+        let group = &summary.groups["group"];
+        assert_eq!(1, group.online_edge_nodes);
+        assert_eq!(1, group.online_devices);
+        assert_eq!(Some(&3), group.messages.get("NDATA"));
+        assert_eq!(Some(&1), group.messages.get("DBIRTH"));
+        assert!(group.last_seen.is_some());
+
+        let (topic, death) = get_message(SparkplugMessageType::NDEATH, vec![]);
+        network.parse_message(SparkplugTopic::EdgeNode(topic), death);
+
+        let summary = network.summary();
+        let group = &summary.groups["group"];
+        assert_eq!((1, 0), (group.edge_nodes, group.online_edge_nodes));
+        assert_eq!((1, 0), (group.devices, group.online_devices));
+    }
+
+    #[test]
+    fn primary_host_state() {
+        let mut network = SparkplugNetwork::default().with_primary_host(Some("scada".to_string()));
+
+        assert_eq!(None, network.update_host_state("other", b"ONLINE"));
+
+        let online = network.update_host_state("scada", br#"{"online": true, "timestamp": 2}"#);
+        assert_eq!(Some(true), online.map(|state| state.online));
+        assert_eq!(
+            None,
+            network.update_host_state("scada", br#"{"online": true, "timestamp": 3}"#)
+        );
+        assert_eq!(
+            None,
+            network.update_host_state("scada", br#"{"online": false, "timestamp": 1}"#)
+        );
+
+        let offline = network.update_host_state("scada", br#"{"online": false, "timestamp": 4}"#);
+        assert_eq!(Some(false), offline.map(|state| state.online));
+        assert_eq!(Some(4), network.primary_host().unwrap().timestamp);
+    }
+
+    #[test]
+    fn rebirth_request() {
+        let (topic, _) = get_message(SparkplugMessageType::NDATA, vec![]);
+        let (topic, payload) = create_rebirth_request(&topic);
+
+        assert_eq!("spBv1.0/group/NCMD/edge", topic.to_string());
+        assert_eq!(
+            Some(NODE_CONTROL_REBIRTH.to_string()),
+            payload.content.metrics[0].name
+        );
+        assert_eq!(
+            Some(Value::BooleanValue(true)),
+            payload.content.metrics[0].value
+        );
+    }
+
+    #[test]
+    fn filter_metrics() {
+        let mut network = SparkplugNetwork::new(SparkplugMetricFilter {
+            include: vec![],
+            exclude: vec!["Diagnostics/*".to_string()],
+        });
+
+        let (topic, birth) = get_message(
+            SparkplugMessageType::NBIRTH,
+            vec![(Some("temperature"), 1), (Some("Diagnostics/Cpu"), 2)],
+        );
+        assert_eq!(
+            1,
+            network.filter_metrics(&topic, &birth).content.metrics.len()
+        );
+        network.parse_message(SparkplugTopic::EdgeNode(topic), birth);
+
+        let (topic, data) = get_message(
+            SparkplugMessageType::NDATA,
+            vec![(None, 1), (None, 2), (None, 3)],
+        );
+        let filtered = network.filter_metrics(&topic, &data);
+        assert_eq!(
+            vec![Some(1), Some(3)],
+            filtered
+                .content
+                .metrics
+                .iter()
+                .map(|metric| metric.alias)
+                .collect::<Vec<_>>()
+        );
+
+        let (topic, data) = get_message(SparkplugMessageType::NDATA, vec![(None, 2)]);
+        assert!(!network.needs_rebirth(&topic, &data));
+
+        let snapshot = network.snapshot();
+        let edge_node = &snapshot.groups["group"].edge_nodes["edge"];
+        assert!(!edge_node.metrics.contains_key("Diagnostics/Cpu"));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::config::sparkplug::SnapshotFormat;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::Metric;
+use crate::payload::PayloadFormatError;
+use crate::sparkplug::value::metric_value_to_json;
+use crate::sparkplug::{DeviceId, EdgeNodeId, GroupId, Status};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// State of the Sparkplug network at a point in time, suitable to be
+/// exported as JSON or YAML document.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SparkplugSnapshot {
+    pub created_at: String,
+    pub groups: BTreeMap<GroupId, SparkplugGroupSnapshot>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SparkplugGroupSnapshot {
+    pub edge_nodes: BTreeMap<EdgeNodeId, SparkplugEdgeNodeSnapshot>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SparkplugEdgeNodeSnapshot {
+    pub status: String,
+    pub last_status_update: Option<String>,
+    pub metrics: BTreeMap<String, SparkplugMetricSnapshot>,
+    pub devices: BTreeMap<DeviceId, SparkplugDeviceSnapshot>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SparkplugDeviceSnapshot {
+    pub status: String,
+    pub last_status_update: Option<String>,
+    pub metrics: BTreeMap<String, SparkplugMetricSnapshot>,
+}
+
+const METRIC_HISTORY_SIZE: usize = 100;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SparkplugMetricSnapshot {
+    pub value: serde_json::Value,
+    pub timestamp: Option<String>,
+    /// Previous values with their timestamps, latest first.
+    #[serde(skip)]
+    pub history: VecDeque<(Option<String>, serde_json::Value)>,
+}
+
+impl SparkplugSnapshot {
+    pub fn to_bytes(&self, format: &SnapshotFormat) -> Result<Vec<u8>, PayloadFormatError> {
+        Ok(match format {
+            SnapshotFormat::Json => serde_json::to_vec_pretty(self)?,
+            SnapshotFormat::Yaml => serde_yaml::to_string(self)?.into_bytes(),
+        })
+    }
+}
+
+impl SparkplugEdgeNodeSnapshot {
+    pub(crate) fn set_status(&mut self, status: Status) {
+        self.status = status_to_string(&status);
+        self.last_status_update = Some(Utc::now().to_rfc3339());
+
+        if status == Status::OFFLINE {
+            self.devices
+                .values_mut()
+                .for_each(|device| device.set_status(Status::OFFLINE));
+        }
+    }
+}
+
+impl SparkplugDeviceSnapshot {
+    pub(crate) fn set_status(&mut self, status: Status) {
+        self.status = status_to_string(&status);
+        self.last_status_update = Some(Utc::now().to_rfc3339());
+    }
+}
+
+fn status_to_string(status: &Status) -> String {
+    match status {
+        Status::ONLINE => "online".to_string(),
+        Status::OFFLINE => "offline".to_string(),
+    }
+}
+
+/// Stores the latest value of each metric, resolving metric names by their
+/// alias if the metric doesn't carry a name.
+pub(crate) fn update_metrics(
+    target: &mut BTreeMap<String, SparkplugMetricSnapshot>,
+    metrics: &[Metric],
+    aliases: Option<&HashMap<u64, String>>,
+) {
+    for metric in metrics {
+        let Some(name) = metric_name(metric, aliases) else {
+            continue;
+        };
+
+        let update = SparkplugMetricSnapshot::from(metric);
+
+        match target.get_mut(&name) {
+            None => {
+                target.insert(name, update);
+            }
+            Some(current) => {
+                let previous_value = std::mem::replace(&mut current.value, update.value);
+                let previous_timestamp =
+                    std::mem::replace(&mut current.timestamp, update.timestamp);
+
+                current
+                    .history
+                    .push_front((previous_timestamp, previous_value));
+                current.history.truncate(METRIC_HISTORY_SIZE);
+            }
+        }
+    }
+}
+
+/// Replaces the metrics with those of a birth certificate, which contains the
+/// full metric set. Metrics missing from the birth are removed, the history
+/// of the remaining metrics is kept.
+pub(crate) fn replace_metrics(
+    target: &mut BTreeMap<String, SparkplugMetricSnapshot>,
+    metrics: &[Metric],
+    aliases: Option<&HashMap<u64, String>>,
+) {
+    let mut previous = std::mem::take(target);
+    *target = metrics
+        .iter()
+        .filter_map(|metric| metric_name(metric, aliases))
+        .filter_map(|name| previous.remove(&name).map(|metric| (name, metric)))
+        .collect();
+
+    update_metrics(target, metrics, aliases);
+}
+
+fn metric_name(metric: &Metric, aliases: Option<&HashMap<u64, String>>) -> Option<String> {
+    match (&metric.name, metric.alias) {
+        (Some(name), _) => Some(name.clone()),
+        (None, Some(alias)) => Some(
+            aliases
+                .and_then(|aliases| aliases.get(&alias))
+                .cloned()
+                .unwrap_or_else(|| format!("alias {alias}")),
+        ),
+        (None, None) => None,
+    }
+}
+
+impl From<&Metric> for SparkplugMetricSnapshot {
+    fn from(metric: &Metric) -> Self {
+        Self {
+            value: metric_value_to_json(metric),
+            timestamp: metric
+                .timestamp
+                .and_then(|timestamp| DateTime::from_timestamp_millis(timestamp as i64))
+                .map(|timestamp| timestamp.to_rfc3339()),
+            history: VecDeque::new(),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::sparkplug::snapshot::SparkplugGroupSnapshot;
+use crate::sparkplug::{EdgeNodeId, GroupId};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+const ONLINE: &str = "online";
+
+/// Messages received from the edge nodes of a group since the start.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SparkplugGroupStatistics {
+    /// Number of messages per message type
+    pub messages: BTreeMap<String, u64>,
+    /// Time of the last message of each edge node
+    pub last_seen: BTreeMap<EdgeNodeId, DateTime<Utc>>,
+}
+
+/// Overview of the Sparkplug network at a point in time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SparkplugSummary {
+    pub created_at: DateTime<Utc>,
+    pub groups: BTreeMap<GroupId, SparkplugGroupSummary>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SparkplugGroupSummary {
+    pub edge_nodes: usize,
+    pub online_edge_nodes: usize,
+    pub devices: usize,
+    pub online_devices: usize,
+    pub messages: BTreeMap<String, u64>,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+impl SparkplugGroupStatistics {
+    pub(crate) fn count_message(&mut self, message_type: String, edge_node_id: &EdgeNodeId) {
+        *self.messages.entry(message_type).or_default() += 1;
+        self.last_seen.insert(edge_node_id.clone(), Utc::now());
+    }
+}
+
+impl SparkplugGroupSummary {
+    pub(crate) fn new(
+        group: Option<&SparkplugGroupSnapshot>,
+        statistics: Option<&SparkplugGroupStatistics>,
+    ) -> Self {
+        let mut summary = Self::default();
+
+        for edge_node in group.iter().flat_map(|group| group.edge_nodes.values()) {
+            summary.edge_nodes += 1;
+            summary.online_edge_nodes += usize::from(edge_node.status == ONLINE);
+            summary.devices += edge_node.devices.len();
+            summary.online_devices += edge_node
+                .devices
+                .values()
+                .filter(|device| device.status == ONLINE)
+                .count();
+        }
+
+        if let Some(statistics) = statistics {
+            summary.messages = statistics.messages.clone();
+            summary.last_seen = statistics.last_seen.values().max().copied();
+        }
+
+        summary
+    }
+}
+
+impl SparkplugSummary {
+    /// Returns the messages per second of each message type of a group
+    /// received since the previous summary.
+    pub fn message_rates(
+        &self,
+        group_id: &GroupId,
+        previous: &SparkplugSummary,
+    ) -> BTreeMap<String, f64> {
+        let Some(group) = self.groups.get(group_id) else {
+            return BTreeMap::new();
+        };
+
+        let elapsed = (self.created_at - previous.created_at).num_milliseconds() as f64 / 1000.0;
+        let previous = previous.groups.get(group_id);
+
+        group
+            .messages
+            .iter()
+            .map(|(message_type, count)| {
+                let previous_count = previous
+                    .and_then(|previous| previous.messages.get(message_type))
+                    .copied()
+                    .unwrap_or_default();
+                let received = count.saturating_sub(previous_count) as f64;
+
+                (
+                    message_type.clone(),
+                    if elapsed > 0.0 {
+                        received / elapsed
+                    } else {
+                        0.0
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn message_rates() {
+        let created_at = Utc::now();
+
+        let mut previous = SparkplugSummary {
+            created_at,
+            ..Default::default()
+        };
+        previous.groups.insert(
+            "group".to_string(),
+            SparkplugGroupSummary {
+                messages: BTreeMap::from([("NDATA".to_string(), 10)]),
+                ..Default::default()
+            },
+        );
+
+        let mut current = SparkplugSummary {
+            created_at: created_at + Duration::seconds(10),
+            ..Default::default()
+        };
+        current.groups.insert(
+            "group".to_string(),
+            SparkplugGroupSummary {
+                messages: BTreeMap::from([("NBIRTH".to_string(), 5), ("NDATA".to_string(), 30)]),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            BTreeMap::from([("NBIRTH".to_string(), 0.5), ("NDATA".to_string(), 2.0)]),
+            current.message_rates(&"group".to_string(), &previous)
+        );
+        assert!(current
+            .message_rates(&"unknown".to_string(), &previous)
+            .is_empty());
+    }
+}
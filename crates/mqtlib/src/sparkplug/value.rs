@@ -0,0 +1,164 @@
+use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::template::parameter::Value as ParameterValue;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::template::Parameter;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::{Metric, Template};
+use serde_json::{Map, Value as JsonValue};
+
+/// Converts the value of a metric to JSON. Templates are converted to an
+/// object as described by [template_to_json].
+pub fn metric_value_to_json(metric: &Metric) -> JsonValue {
+    if metric.is_null() {
+        return JsonValue::Null;
+    }
+
+    match &metric.value {
+        None => JsonValue::Null,
+        Some(Value::IntValue(value)) => JsonValue::from(*value),
+        Some(Value::LongValue(value)) => JsonValue::from(*value),
+        Some(Value::FloatValue(value)) => JsonValue::from(*value),
+        Some(Value::DoubleValue(value)) => JsonValue::from(*value),
+        Some(Value::BooleanValue(value)) => JsonValue::from(*value),
+        Some(Value::StringValue(value)) => JsonValue::from(value.clone()),
+        Some(Value::BytesValue(value)) => {
+            JsonValue::from(String::from_utf8_lossy(value.as_ref()).to_string())
+        }
+        Some(Value::DatasetValue(value)) => JsonValue::from(value.to_string()),
+        Some(Value::TemplateValue(value)) => template_to_json(value),
+        Some(Value::ExtensionValue(value)) => JsonValue::from(value.to_string()),
+    }
+}
+
+/// Converts a template definition or instance to a JSON object:
+///
+/// ```json
+/// {
+///   "template_ref": "Motor",
+///   "is_definition": false,
+///   "version": "1.0",
+///   "parameters": { "max_rpm": 3000 },
+///   "metrics": { "Speed": 1200 }
+/// }
+/// ```
+///
+/// `template_ref` and `version` are omitted if not set. Metrics without a
+/// name are keyed by their alias.
+pub fn template_to_json(template: &Template) -> JsonValue {
+    let mut result = Map::new();
+
+    if let Some(template_ref) = &template.template_ref {
+        result.insert(
+            "template_ref".to_string(),
+            JsonValue::from(template_ref.clone()),
+        );
+    }
+
+    result.insert(
+        "is_definition".to_string(),
+        JsonValue::from(template.is_definition()),
+    );
+
+    if let Some(version) = &template.version {
+        result.insert("version".to_string(), JsonValue::from(version.clone()));
+    }
+
+    result.insert(
+        "parameters".to_string(),
+        JsonValue::Object(
+            template
+                .parameters
+                .iter()
+                .map(|parameter| {
+                    (
+                        parameter.name.clone().unwrap_or_default(),
+                        parameter_value_to_json(parameter),
+                    )
+                })
+                .collect(),
+        ),
+    );
+
+    result.insert(
+        "metrics".to_string(),
+        JsonValue::Object(
+            template
+                .metrics
+                .iter()
+                .map(|metric| (metric_key(metric), metric_value_to_json(metric)))
+                .collect(),
+        ),
+    );
+
+    JsonValue::Object(result)
+}
+
+pub fn parameter_value_to_json(parameter: &Parameter) -> JsonValue {
+    match &parameter.value {
+        None => JsonValue::Null,
+        Some(ParameterValue::IntValue(value)) => JsonValue::from(*value),
+        Some(ParameterValue::LongValue(value)) => JsonValue::from(*value),
+        Some(ParameterValue::FloatValue(value)) => JsonValue::from(*value),
+        Some(ParameterValue::DoubleValue(value)) => JsonValue::from(*value),
+        Some(ParameterValue::BooleanValue(value)) => JsonValue::from(*value),
+        Some(ParameterValue::StringValue(value)) => JsonValue::from(value.clone()),
+        Some(ParameterValue::ExtensionValue(value)) => JsonValue::from(value.to_string()),
+    }
+}
+
+fn metric_key(metric: &Metric) -> String {
+    match (&metric.name, metric.alias) {
+        (Some(name), _) => name.clone(),
+        (None, Some(alias)) => format!("alias {alias}"),
+        (None, None) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn template_instance_to_json() {
+        let mut speed = Metric::new();
+        speed.name = Some("Speed".to_string());
+        speed.value = Some(Value::IntValue(1200));
+
+        let mut bearing = Template::new();
+        bearing.template_ref = Some("Bearing".to_string());
+        bearing.is_definition = Some(false);
+
+        let mut nested = Metric::new();
+        nested.alias = Some(7);
+        nested.value = Some(Value::TemplateValue(bearing));
+
+        let mut max_rpm = Parameter::new();
+        max_rpm.name = Some("max_rpm".to_string());
+        max_rpm.value = Some(ParameterValue::IntValue(3000));
+
+        let mut motor = Template::new();
+        motor.template_ref = Some("Motor".to_string());
+        motor.version = Some("1.0".to_string());
+        motor.parameters.push(max_rpm);
+        motor.metrics.push(speed);
+        motor.metrics.push(nested);
+
+        assert_eq!(
+            json!({
+                "template_ref": "Motor",
+                "is_definition": false,
+                "version": "1.0",
+                "parameters": { "max_rpm": 3000 },
+                "metrics": {
+                    "Speed": 1200,
+                    "alias 7": {
+                        "template_ref": "Bearing",
+                        "is_definition": false,
+                        "parameters": {},
+                        "metrics": {}
+                    }
+                }
+            }),
+            template_to_json(&motor)
+        );
+    }
+}
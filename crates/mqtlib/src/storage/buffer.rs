@@ -0,0 +1,141 @@
+use crate::storage::{SqlStorageError, SqlValue};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{Row, SqlitePool};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Prepared statements of one write together with their values.
+pub type SqlQueries = Vec<(String, Vec<SqlValue>)>;
+
+/// Keeps writes in a SQLite database while the SQL storage is unavailable,
+/// so that they survive an outage and a restart of the process and can be
+/// replayed in their original order once the database is reachable again.
+#[derive(Clone, Debug)]
+pub struct SqlBuffer {
+    pool: SqlitePool,
+    max_size: u64,
+    len: Arc<AtomicU64>,
+}
+
+impl SqlBuffer {
+    pub async fn open(path: &Path, max_size: usize) -> Result<Self, SqlStorageError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS buffered_writes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                queries TEXT NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        let len: i64 = sqlx::query("SELECT COUNT(*) AS count FROM buffered_writes")
+            .fetch_one(&pool)
+            .await?
+            .get("count");
+
+        Ok(Self {
+            pool,
+            max_size: max_size as u64,
+            len: Arc::new(AtomicU64::new(len as u64)),
+        })
+    }
+
+    /// Number of buffered writes.
+    pub fn len(&self) -> u64 {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the write to the buffer. Returns false if the buffer is full
+    /// and the write was not stored.
+    pub async fn push(&self, queries: &SqlQueries) -> Result<bool, SqlStorageError> {
+        if self.len() >= self.max_size {
+            return Ok(false);
+        }
+
+        sqlx::query("INSERT INTO buffered_writes (queries) VALUES ($1)")
+            .bind(serde_json::to_string(queries)?)
+            .execute(&self.pool)
+            .await?;
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        Ok(true)
+    }
+
+    /// Returns the id and the queries of the oldest buffered write.
+    pub async fn oldest(&self) -> Result<Option<(i64, SqlQueries)>, SqlStorageError> {
+        let row = sqlx::query("SELECT id, queries FROM buffered_writes ORDER BY id LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => Ok(Some((
+                row.get("id"),
+                serde_json::from_str(row.get::<&str, _>("queries"))?,
+            ))),
+        }
+    }
+
+    /// Removes a buffered write after it was executed.
+    pub async fn remove(&self, id: i64) -> Result<(), SqlStorageError> {
+        let removed = sqlx::query("DELETE FROM buffered_writes WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        self.len.fetch_sub(removed, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queries(topic: &str) -> SqlQueries {
+        vec![(
+            "INSERT INTO messages (topic, payload) VALUES ($1, $2)".to_string(),
+            vec![SqlValue::from(topic), SqlValue::Blob(Some(vec![0, 1, 2]))],
+        )]
+    }
+
+    #[tokio::test]
+    async fn push_and_replay() {
+        let path = std::env::temp_dir().join(format!("mqtli-buffer-{}.db", uuid::Uuid::new_v4()));
+        let buffer = SqlBuffer::open(&path, 2).await.unwrap();
+
+        assert!(buffer.push(&queries("a")).await.unwrap());
+        assert!(buffer.push(&queries("b")).await.unwrap());
+        assert!(!buffer.push(&queries("c")).await.unwrap());
+        assert_eq!(2, buffer.len());
+        drop(buffer);
+
+        let buffer = SqlBuffer::open(&path, 2).await.unwrap();
+        assert_eq!(2, buffer.len());
+
+        let (id, oldest) = buffer.oldest().await.unwrap().unwrap();
+        assert_eq!(queries("a"), oldest);
+        buffer.remove(id).await.unwrap();
+
+        let (id, oldest) = buffer.oldest().await.unwrap().unwrap();
+        assert_eq!(queries("b"), oldest);
+        buffer.remove(id).await.unwrap();
+
+        assert!(buffer.is_empty());
+        assert!(buffer.oldest().await.unwrap().is_none());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
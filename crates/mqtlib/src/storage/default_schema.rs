@@ -0,0 +1,62 @@
+use crate::config::PayloadType;
+use crate::mqtt::QoS;
+use crate::payload::PayloadFormat;
+use crate::storage::{SqlStorageError, SqlStorageImpl, SqlValue};
+use chrono::{DateTime, Utc};
+
+/// Table of the messages stored by SQL outputs without an insert statement.
+pub const DEFAULT_TABLE: &str = "mqtli_messages";
+
+/// Statement creating the default table if it doesn't exist yet.
+pub(crate) fn create_schema_statement<S: SqlStorageImpl + ?Sized>(db: &S) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {DEFAULT_TABLE} (
+    {},
+    topic VARCHAR(1024) NOT NULL,
+    received_at BIGINT NOT NULL,
+    qos INTEGER NOT NULL,
+    retain INTEGER NOT NULL,
+    payload {} NULL,
+    payload_json TEXT NULL
+)",
+        db.get_auto_increment_key("id"),
+        db.get_blob_type()
+    )
+}
+
+/// Creates the statement storing a message in the default table. The payload
+/// is stored as it was received; payload_json is only set if the payload can
+/// be converted to JSON.
+pub(crate) fn create_query<S: SqlStorageImpl + ?Sized>(
+    db: &S,
+    topic: &str,
+    qos: QoS,
+    retain: bool,
+    payload: &PayloadFormat,
+    received_at: DateTime<Utc>,
+) -> Result<(String, Vec<SqlValue>), SqlStorageError> {
+    let payload_json: Option<String> =
+        PayloadFormat::try_from((payload.clone(), PayloadType::Json))
+            .ok()
+            .and_then(|json| json.try_into().ok());
+
+    Ok((
+        format!(
+            "INSERT INTO {DEFAULT_TABLE} (topic, received_at, qos, retain, payload, payload_json) VALUES ({}, {}, {}, {}, {}, {})",
+            db.get_placeholder(1),
+            db.get_placeholder(2),
+            db.get_placeholder(3),
+            db.get_placeholder(4),
+            db.get_placeholder(5),
+            db.get_placeholder(6),
+        ),
+        vec![
+            SqlValue::from(topic),
+            SqlValue::from(received_at.timestamp_millis()),
+            SqlValue::from(qos as i64),
+            SqlValue::from(i64::from(retain)),
+            SqlValue::Blob(Some(Vec::<u8>::try_from(payload.clone())?)),
+            SqlValue::Text(payload_json),
+        ],
+    ))
+}
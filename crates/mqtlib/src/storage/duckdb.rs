@@ -0,0 +1,222 @@
+use crate::mqtt::QoS;
+use crate::payload::PayloadFormat;
+use crate::storage::{SqlStorageError, SqlStorageImpl, SqlValue};
+use async_trait::async_trait;
+use duckdb::types::Value;
+use duckdb::{params_from_iter, Connection};
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+/// Sequence providing the values of auto incremented keys, DuckDB has no
+/// auto increment column type.
+const ID_SEQUENCE: &str = "mqtli_id_seq";
+
+/// Embedded DuckDB database. DuckDB is accessed synchronously, so all
+/// statements are executed on the blocking thread pool.
+pub struct SqlStorageDuckDb {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Debug for SqlStorageDuckDb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlStorageDuckDb").finish_non_exhaustive()
+    }
+}
+
+impl SqlStorageDuckDb {
+    /// Opens the database of the connection string, which is kept in memory
+    /// if no path is given.
+    pub fn open(connection_string: &str) -> Result<Self, SqlStorageError> {
+        let connection = match database_path(connection_string) {
+            Some(path) => Connection::open(path)?,
+            None => Connection::open_in_memory()?,
+        };
+
+        connection
+            .execute_batch(format!("CREATE SEQUENCE IF NOT EXISTS {ID_SEQUENCE}").as_str())?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn execute_values(
+        &self,
+        queries: Vec<(String, Vec<Value>)>,
+    ) -> Result<u64, SqlStorageError> {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().unwrap();
+
+            let mut affected_rows = 0;
+            for (query, values) in queries {
+                affected_rows += connection.execute(query.as_str(), params_from_iter(values))?;
+            }
+            Ok::<u64, SqlStorageError>(affected_rows as u64)
+        })
+        .await?
+    }
+}
+
+/// Extracts the path of the database file from the connection string, e.g.
+/// duckdb:data.db or duckdb://data.db. duckdb::memory: and duckdb:// open an
+/// in-memory database.
+fn database_path(connection_string: &str) -> Option<&str> {
+    let path = connection_string
+        .strip_prefix("duckdb:")
+        .unwrap_or(connection_string);
+    let path = path.strip_prefix("//").unwrap_or(path);
+
+    match path {
+        "" | ":memory:" => None,
+        path => Some(path),
+    }
+}
+
+#[async_trait]
+impl SqlStorageImpl for SqlStorageDuckDb {
+    async fn insert(
+        &self,
+        statement: &str,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: &PayloadFormat,
+    ) -> Result<u64, SqlStorageError> {
+        let mut queries: Vec<(String, Vec<Vec<u8>>)> = vec![];
+
+        self.create_queries(statement, topic, qos, retain, payload, &mut queries)?;
+
+        self.execute_values(
+            queries
+                .into_iter()
+                .map(|(query, binds)| (query, binds.into_iter().map(Value::Blob).collect()))
+                .collect(),
+        )
+        .await
+    }
+
+    async fn execute(&self, statement: &str) -> Result<u64, SqlStorageError> {
+        self.execute_values(vec![(statement.to_string(), vec![])])
+            .await
+    }
+
+    async fn execute_with_values(
+        &self,
+        queries: Vec<(String, Vec<SqlValue>)>,
+    ) -> Result<u64, SqlStorageError> {
+        self.execute_values(
+            queries
+                .into_iter()
+                .map(|(query, values)| (query, values.into_iter().map(Value::from).collect()))
+                .collect(),
+        )
+        .await
+    }
+
+    fn get_placeholder(&self, counter: usize) -> String {
+        format!("${}", counter)
+    }
+
+    fn get_auto_increment_key(&self, column: &str) -> String {
+        format!("{column} BIGINT PRIMARY KEY DEFAULT nextval('{ID_SEQUENCE}')")
+    }
+}
+
+impl From<SqlValue> for Value {
+    fn from(value: SqlValue) -> Self {
+        match value {
+            SqlValue::Text(value) => value.map_or(Value::Null, Value::Text),
+            SqlValue::Integer(value) => value.map_or(Value::Null, Value::BigInt),
+            SqlValue::Blob(value) => value.map_or(Value::Null, Value::Blob),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::text::PayloadFormatText;
+
+    #[test]
+    fn path_of_connection_string() {
+        assert_eq!(None, database_path("duckdb::memory:"));
+        assert_eq!(None, database_path("duckdb://"));
+        assert_eq!(Some("data.db"), database_path("duckdb:data.db"));
+        assert_eq!(Some("data.db"), database_path("duckdb://data.db"));
+    }
+
+    #[tokio::test]
+    async fn insert_default() {
+        let db = SqlStorageDuckDb::open("duckdb::memory:").unwrap();
+        assert!(db.create_default_schema().await.is_ok());
+        assert!(db.create_default_schema().await.is_ok());
+
+        for payload in ["{\"temperature\":20.5}", "PAYLOAD"] {
+            let result = db
+                .insert_default(
+                    "topic",
+                    QoS::AtLeastOnce,
+                    true,
+                    &PayloadFormat::Text(PayloadFormatText::from(payload)),
+                )
+                .await;
+            assert_eq!(1, result.unwrap());
+        }
+
+        let connection = db.connection.lock().unwrap();
+        let rows: Vec<(i64, String, Option<String>)> = connection
+            .prepare("SELECT id, topic, payload_json FROM mqtli_messages ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(
+            vec![
+                (
+                    1,
+                    "topic".to_string(),
+                    Some("{\"temperature\":20.5}".to_string())
+                ),
+                (2, "topic".to_string(), None),
+            ],
+            rows
+        );
+    }
+
+    #[tokio::test]
+    async fn insert() {
+        let db = SqlStorageDuckDb::open("duckdb::memory:").unwrap();
+        assert!(db
+            .execute(
+                "CREATE TABLE test (topic VARCHAR NOT NULL, qos INTEGER NOT NULL, payload BLOB)"
+            )
+            .await
+            .is_ok());
+
+        let result = db
+            .insert(
+                "INSERT INTO test (topic, qos, payload) VALUES ('{{topic}}', {{qos}}, {{payload}})",
+                "topic",
+                QoS::ExactlyOnce,
+                false,
+                &PayloadFormat::Text(PayloadFormatText::from("PAYLOAD")),
+            )
+            .await;
+        assert_eq!(1, result.unwrap());
+
+        let connection = db.connection.lock().unwrap();
+        let (topic, qos, payload): (String, i64, Vec<u8>) = connection
+            .query_row("SELECT topic, qos, payload FROM test", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(
+            ("topic".to_string(), 2, b"PAYLOAD".to_vec()),
+            (topic, qos, payload)
+        );
+    }
+}
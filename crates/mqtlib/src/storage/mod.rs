@@ -2,13 +2,18 @@ use crate::mqtt::QoS;
 use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
 use crate::payload::{PayloadFormat, PayloadFormatError};
 use crate::sparkplug::topic::SparkplugTopic;
+use crate::sparkplug::value::template_to_json;
 use crate::sparkplug::SparkplugError;
+use crate::storage::buffer::SqlQueries;
+#[cfg(feature = "duckdb")]
+use crate::storage::duckdb::SqlStorageDuckDb;
 use crate::storage::mysql::SqlStorageMySql;
 use crate::storage::postgres::SqlStoragePostgres;
 use crate::storage::sqlite::SqlStorageSqlite;
 use async_trait::async_trait;
 use chrono::Utc;
 use protobuf::Message;
+use serde::{Deserialize, Serialize};
 use sqlx::mysql::MySqlConnectOptions;
 use sqlx::postgres::PgConnectOptions;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
@@ -19,8 +24,14 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tracing::warn;
 
+pub mod buffer;
+pub mod default_schema;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
 pub mod mysql;
 mod postgres;
+pub mod queue;
+mod sparkplug;
 pub mod sqlite;
 
 #[derive(Debug, Error)]
@@ -33,6 +44,49 @@ pub enum SqlStorageError {
     PayloadFormatError(#[from] PayloadFormatError),
     #[error("Error in Sparkplug format")]
     SparkplugError(#[from] SparkplugError),
+    #[error("Could not serialize the buffered write")]
+    BufferSerializationError(#[from] serde_json::Error),
+    #[cfg(feature = "duckdb")]
+    #[error("Error in DuckDB database")]
+    DuckDbError(#[from] ::duckdb::Error),
+    #[error("Database task failed")]
+    TaskError(#[from] tokio::task::JoinError),
+}
+
+impl SqlStorageError {
+    /// Checks if the database could not be reached, in contrast to errors
+    /// of the statement itself.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            SqlStorageError::SqlConnectionError(
+                sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+            )
+        )
+    }
+}
+
+/// Typed value bound to a prepared statement.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum SqlValue {
+    Text(Option<String>),
+    Integer(Option<i64>),
+    Blob(Option<Vec<u8>>),
+}
+
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        SqlValue::Text(Some(value.to_string()))
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        SqlValue::Integer(Some(value))
+    }
 }
 
 #[async_trait]
@@ -46,9 +100,100 @@ pub trait SqlStorageImpl: Debug + Send + Sync {
         payload: &PayloadFormat,
     ) -> Result<u64, SqlStorageError>;
     async fn execute(&self, statement: &str) -> Result<u64, SqlStorageError>;
+    async fn execute_with_values(
+        &self,
+        queries: Vec<(String, Vec<SqlValue>)>,
+    ) -> Result<u64, SqlStorageError>;
 
     fn get_placeholder(&self, usize: usize) -> String;
 
+    /// Column definition of an auto incremented primary key.
+    fn get_auto_increment_key(&self, column: &str) -> String;
+
+    /// Column type of binary data.
+    fn get_blob_type(&self) -> &'static str {
+        "BLOB"
+    }
+
+    /// Clause appended to an insert statement to update the given columns
+    /// if a row with the same keys exists already.
+    fn get_upsert_clause(&self, keys: &[&str], columns: &[&str]) -> String {
+        format!(
+            "ON CONFLICT ({}) DO UPDATE SET {}",
+            keys.join(", "),
+            columns
+                .iter()
+                .map(|column| format!("{column} = excluded.{column}"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+
+    /// Creates the tables of the normalized Sparkplug schema if they don't
+    /// exist yet.
+    async fn create_sparkplug_schema(&self) -> Result<(), SqlStorageError> {
+        for statement in sparkplug::create_schema_statements(self) {
+            self.execute(statement.as_str()).await?;
+        }
+        Ok(())
+    }
+
+    /// Stores a Sparkplug message in the normalized Sparkplug schema.
+    async fn insert_sparkplug(
+        &self,
+        topic: &str,
+        payload: &PayloadFormat,
+    ) -> Result<u64, SqlStorageError> {
+        let queries = sparkplug::create_queries(self, topic, payload)?;
+        self.execute_with_values(queries).await
+    }
+
+    /// Prepares the statements of an insert statement with placeholders, so
+    /// that they can be executed with execute_with_values.
+    fn prepare_insert(
+        &self,
+        statement: &str,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: &PayloadFormat,
+    ) -> Result<SqlQueries, SqlStorageError> {
+        let mut queries: Vec<(String, Vec<Vec<u8>>)> = vec![];
+        self.create_queries(statement, topic, qos, retain, payload, &mut queries)?;
+
+        Ok(queries
+            .into_iter()
+            .map(|(query, binds)| {
+                (
+                    query,
+                    binds
+                        .into_iter()
+                        .map(|bind| SqlValue::Blob(Some(bind)))
+                        .collect(),
+                )
+            })
+            .collect())
+    }
+
+    /// Creates the table of the default schema if it doesn't exist yet.
+    async fn create_default_schema(&self) -> Result<(), SqlStorageError> {
+        self.execute(default_schema::create_schema_statement(self).as_str())
+            .await?;
+        Ok(())
+    }
+
+    /// Stores a message in the table of the default schema.
+    async fn insert_default(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: &PayloadFormat,
+    ) -> Result<u64, SqlStorageError> {
+        let query = default_schema::create_query(self, topic, qos, retain, payload, Utc::now())?;
+        self.execute_with_values(vec![query]).await
+    }
+
     fn replace_basic_properties(
         &self,
         statement: &str,
@@ -163,7 +308,7 @@ pub trait SqlStorageImpl: Debug + Send + Sync {
                                     value.write_to_bytes().unwrap_or(vec![])
                                 }
                                 Value::TemplateValue(value) => {
-                                    value.write_to_bytes().unwrap_or(vec![])
+                                    template_to_json(value).to_string().into_bytes()
                                 }
                                 Value::ExtensionValue(value) => {
                                     value.write_to_bytes().unwrap_or(vec![])
@@ -285,6 +430,12 @@ pub async fn get_sql_storage(
 
             Ok(Box::new(db))
         }
+        #[cfg(feature = "duckdb")]
+        "duckdb" => {
+            let db = SqlStorageDuckDb::open(sql.connection_string.as_str())?;
+
+            Ok(Box::new(db))
+        }
         scheme => Err(SqlStorageError::UnsupportedSqlDatabase(scheme.to_string())),
     }
 }
@@ -1,6 +1,6 @@
 use crate::mqtt::QoS;
 use crate::payload::PayloadFormat;
-use crate::storage::{SqlStorageError, SqlStorageImpl};
+use crate::storage::{SqlStorageError, SqlStorageImpl, SqlValue};
 use async_trait::async_trait;
 use sqlx::MySqlPool;
 use std::fmt::Debug;
@@ -47,7 +47,46 @@ impl SqlStorageImpl for SqlStorageMySql {
         Ok(result?.rows_affected())
     }
 
+    async fn execute_with_values(
+        &self,
+        queries: Vec<(String, Vec<SqlValue>)>,
+    ) -> Result<u64, SqlStorageError> {
+        let mut affected_rows = 0;
+        for (query, values) in queries {
+            let mut result = sqlx::query(query.as_ref());
+            for value in values {
+                result = match value {
+                    SqlValue::Text(value) => result.bind(value),
+                    SqlValue::Integer(value) => result.bind(value),
+                    SqlValue::Blob(value) => result.bind(value),
+                };
+            }
+            let result = result.execute(&self.pool).await;
+            affected_rows += result?.rows_affected();
+        }
+        Ok(affected_rows)
+    }
+
     fn get_placeholder(&self, _counter: usize) -> String {
         "?".to_string()
     }
+
+    fn get_auto_increment_key(&self, column: &str) -> String {
+        format!("{column} BIGINT AUTO_INCREMENT PRIMARY KEY")
+    }
+
+    fn get_blob_type(&self) -> &'static str {
+        "LONGBLOB"
+    }
+
+    fn get_upsert_clause(&self, _keys: &[&str], columns: &[&str]) -> String {
+        format!(
+            "ON DUPLICATE KEY UPDATE {}",
+            columns
+                .iter()
+                .map(|column| format!("{column} = VALUES({column})"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
 }
@@ -0,0 +1,421 @@
+use crate::config::sql_storage::{SqlQueueOverflow, SqlStatements, SqlStorage};
+use crate::mqtt::QoS;
+use crate::payload::PayloadFormat;
+use crate::storage::buffer::{SqlBuffer, SqlQueries};
+use crate::storage::{default_schema, sparkplug, SqlStorageError, SqlStorageImpl};
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, error, info, warn};
+
+/// Write to the database waiting in the queue.
+#[derive(Debug)]
+pub enum SqlWrite {
+    /// Executes the insert statement of a SQL output, or stores the message
+    /// in the default schema if there is none
+    Insert {
+        statement: Option<String>,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: PayloadFormat,
+        /// Moment the message was received from the broker
+        received_at: DateTime<Utc>,
+        /// Time spent converting and filtering the message
+        processing_time: Duration,
+    },
+    /// Stores a message in the normalized Sparkplug schema
+    Sparkplug {
+        topic: String,
+        payload: PayloadFormat,
+    },
+}
+
+/// Queue of the writes to the database, which are executed one after another
+/// by a dedicated task, so that a slow database doesn't delay other outputs.
+#[derive(Clone, Debug)]
+pub struct SqlWriteQueue {
+    sender: mpsc::Sender<SqlWrite>,
+    overflow: SqlQueueOverflow,
+    dropped: Arc<AtomicU64>,
+    buffer: Option<SqlBuffer>,
+}
+
+impl SqlWriteQueue {
+    /// Starts the task executing the writes. On exit, the writes still in
+    /// the queue are executed before the returned handle completes.
+    ///
+    /// If a buffer is given, writes which fail because the database is
+    /// unavailable are stored in the buffer and replayed in the retry
+    /// interval. Until the buffer is empty, new writes are appended to it
+    /// as well, so that the order of the writes is kept.
+    pub fn start(
+        db: Box<dyn SqlStorageImpl>,
+        config: &SqlStorage,
+        buffer: Option<SqlBuffer>,
+        mut receiver_exit: broadcast::Receiver<()>,
+    ) -> (Self, JoinHandle<()>) {
+        let (sender, mut receiver) = mpsc::channel::<SqlWrite>(config.queue_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let writer = SqlWriter {
+            db,
+            statements: config.statements.clone(),
+            buffer: buffer.clone(),
+            dropped: dropped.clone(),
+        };
+        let mut retry = tokio::time::interval(config.retry_interval);
+        retry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let handle = tokio::spawn(async move {
+            debug!("Starting SQL write queue");
+
+            loop {
+                select! {
+                    write = receiver.recv() => match write {
+                        Some(write) => writer.write(write).await,
+                        None => break,
+                    },
+                    _ = retry.tick(), if writer.has_buffered() => writer.replay().await,
+                    _ = receiver_exit.recv() => {
+                        receiver.close();
+                        while let Some(write) = receiver.recv().await {
+                            writer.write(write).await;
+                        }
+                        break;
+                    }
+                }
+            }
+
+            debug!("SQL write queue exited");
+        });
+
+        (
+            Self {
+                sender,
+                overflow: config.queue_overflow,
+                dropped,
+                buffer,
+            },
+            handle,
+        )
+    }
+
+    /// Adds the write to the queue. If the queue is full, waits until there
+    /// is room or drops the write, depending on the overflow policy.
+    pub async fn push(&self, write: SqlWrite) {
+        let result = match self.overflow {
+            SqlQueueOverflow::Block => self.sender.send(write).await.is_ok(),
+            SqlQueueOverflow::Drop => match self.sender.try_send(write) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    warn!("SQL write queue is full, dropping write");
+                    return;
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        };
+
+        if !result {
+            error!("SQL write queue is closed, dropping write");
+        }
+    }
+
+    /// Number of writes waiting for the database.
+    pub fn depth(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+
+    /// Number of writes dropped because the queue or the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of writes buffered while the database is unavailable, None if
+    /// no buffer is configured.
+    pub fn buffered(&self) -> Option<u64> {
+        self.buffer.as_ref().map(SqlBuffer::len)
+    }
+}
+
+/// Executes the writes of the queue, buffering them if configured.
+struct SqlWriter {
+    db: Box<dyn SqlStorageImpl>,
+    statements: SqlStatements,
+    buffer: Option<SqlBuffer>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl SqlWriter {
+    fn has_buffered(&self) -> bool {
+        self.buffer
+            .as_ref()
+            .is_some_and(|buffer| !buffer.is_empty())
+    }
+
+    async fn write(&self, write: SqlWrite) {
+        let queries = match prepare(self.db.as_ref(), &self.statements, &write) {
+            Ok(queries) => queries,
+            Err(e) => {
+                error!("Error while writing to SQL storage: {e:?}");
+                return;
+            }
+        };
+
+        let Some(buffer) = &self.buffer else {
+            if let Err(e) = self.db.execute_with_values(queries).await {
+                error!("Error while writing to SQL storage: {e:?}");
+            }
+            return;
+        };
+
+        if buffer.is_empty() {
+            match self.db.execute_with_values(queries.clone()).await {
+                Ok(_) => return,
+                Err(e) if e.is_unavailable() => {
+                    warn!("SQL storage is unavailable, buffering writes until it is reachable again: {e}");
+                }
+                Err(e) => {
+                    error!("Error while writing to SQL storage: {e:?}");
+                    return;
+                }
+            }
+        }
+
+        match buffer.push(&queries).await {
+            Ok(true) => {}
+            Ok(false) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("SQL buffer is full, dropping write");
+            }
+            Err(e) => error!("Could not buffer SQL write: {e:?}"),
+        }
+    }
+
+    /// Executes the buffered writes oldest first until the buffer is empty
+    /// or the database is unavailable again.
+    async fn replay(&self) {
+        let Some(buffer) = &self.buffer else {
+            return;
+        };
+
+        let mut replayed = 0;
+        loop {
+            let (id, queries) = match buffer.oldest().await {
+                Ok(Some(oldest)) => oldest,
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Could not read SQL buffer: {e:?}");
+                    return;
+                }
+            };
+
+            match self.db.execute_with_values(queries).await {
+                Ok(_) => replayed += 1,
+                Err(e) if e.is_unavailable() => {
+                    debug!("SQL storage is still unavailable: {e}");
+                    return;
+                }
+                Err(e) => error!("Dropping buffered SQL write which can't be executed: {e:?}"),
+            }
+
+            if let Err(e) = buffer.remove(id).await {
+                error!("Could not remove write {id} from the SQL buffer: {e:?}");
+                return;
+            }
+        }
+
+        if replayed > 0 {
+            info!("Replayed {replayed} buffered SQL writes");
+        }
+    }
+}
+
+/// Creates the statements of the write. Inserts without a statement use
+/// the statement for their payload type, or the default schema if there is
+/// none.
+fn prepare(
+    db: &dyn SqlStorageImpl,
+    statements: &SqlStatements,
+    write: &SqlWrite,
+) -> Result<SqlQueries, SqlStorageError> {
+    match write {
+        SqlWrite::Insert {
+            statement,
+            topic,
+            qos,
+            retain,
+            payload,
+            received_at,
+            processing_time,
+        } => match statement
+            .as_ref()
+            .or_else(|| statements.for_payload(payload))
+        {
+            Some(statement) => {
+                debug!("Writing to SQL storage");
+                let statement = replace_message_times(statement, *received_at, *processing_time);
+                db.prepare_insert(&statement, topic, *qos, *retain, payload)
+            }
+            None => {
+                debug!("Writing to default SQL schema");
+                Ok(vec![default_schema::create_query(
+                    db,
+                    topic,
+                    *qos,
+                    *retain,
+                    payload,
+                    *received_at,
+                )?])
+            }
+        },
+        SqlWrite::Sparkplug { topic, payload } => {
+            debug!("Writing to Sparkplug SQL schema");
+            sparkplug::create_queries(db, topic, payload)
+        }
+    }
+}
+
+/// Expands the placeholders of the moment the message was received and the
+/// time spent processing it, so that the latency of the pipeline can be
+/// analyzed from the stored data.
+fn replace_message_times(
+    statement: &str,
+    received_at: DateTime<Utc>,
+    processing_time: Duration,
+) -> String {
+    statement
+        .replace(
+            "{{received_at}}",
+            received_at.timestamp().to_string().as_str(),
+        )
+        .replace(
+            "{{received_at_millis}}",
+            received_at.timestamp_millis().to_string().as_str(),
+        )
+        .replace(
+            "{{received_at_iso}}",
+            received_at
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string()
+                .as_str(),
+        )
+        .replace(
+            "{{processing_ms}}",
+            format!("{:.3}", processing_time.as_micros() as f64 / 1000.0).as_str(),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::text::PayloadFormatText;
+    use crate::storage::sqlite::SqlStorageSqlite;
+    use sqlx::{Row, SqlitePool};
+
+    fn sparkplug_write() -> SqlWrite {
+        SqlWrite::Sparkplug {
+            topic: "spBv1.0/group/NDATA/edge".to_string(),
+            payload: PayloadFormat::Text(PayloadFormatText::from(vec![])),
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_when_full() {
+        let (sender, _receiver) = mpsc::channel(1);
+        let queue = SqlWriteQueue {
+            sender,
+            overflow: SqlQueueOverflow::Drop,
+            dropped: Arc::new(AtomicU64::new(0)),
+            buffer: None,
+        };
+
+        queue.push(sparkplug_write()).await;
+        queue.push(sparkplug_write()).await;
+
+        assert_eq!(1, queue.depth());
+        assert_eq!(1, queue.dropped());
+    }
+
+    fn default_write(payload: &str) -> SqlWrite {
+        SqlWrite::Insert {
+            statement: None,
+            topic: "topic".to_string(),
+            qos: QoS::AtMostOnce,
+            retain: false,
+            payload: PayloadFormat::Text(PayloadFormatText::from(payload)),
+            received_at: Utc::now(),
+            processing_time: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn message_times() {
+        let received_at = DateTime::from_timestamp_millis(1_700_000_000_123).unwrap();
+
+        assert_eq!(
+            "VALUES (1700000000, 1700000000123, '2023-11-14 22:13:20.123', 2.500)",
+            replace_message_times(
+                "VALUES ({{received_at}}, {{received_at_millis}}, '{{received_at_iso}}', {{processing_ms}})",
+                received_at,
+                Duration::from_micros(2500),
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn buffer_while_unavailable() {
+        let path = std::env::temp_dir().join(format!("mqtli-buffer-{}.db", uuid::Uuid::new_v4()));
+        let buffer = SqlBuffer::open(&path, 2).await.unwrap();
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let unavailable = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        unavailable.close().await;
+        let writer = SqlWriter {
+            db: Box::new(SqlStorageSqlite::new(unavailable)),
+            statements: SqlStatements::default(),
+            buffer: Some(buffer.clone()),
+            dropped: dropped.clone(),
+        };
+
+        writer.write(default_write("a")).await;
+        writer.write(default_write("b")).await;
+        writer.write(default_write("c")).await;
+        assert_eq!(2, buffer.len());
+        assert_eq!(1, dropped.load(Ordering::Relaxed));
+
+        writer.replay().await;
+        assert_eq!(2, buffer.len());
+
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let writer = SqlWriter {
+            db: Box::new(SqlStorageSqlite::new(pool.clone())),
+            statements: SqlStatements::default(),
+            buffer: Some(buffer.clone()),
+            dropped,
+        };
+        writer.db.create_default_schema().await.unwrap();
+
+        writer.replay().await;
+        assert!(buffer.is_empty());
+
+        let payloads: Vec<Vec<u8>> = sqlx::query("SELECT payload FROM mqtli_messages ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("payload"))
+            .collect();
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], payloads);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
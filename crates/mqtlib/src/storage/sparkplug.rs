@@ -0,0 +1,260 @@
+use crate::payload::sparkplug::protos::sparkplug_b::payload::Metric;
+use crate::payload::PayloadFormat;
+use crate::sparkplug::topic::{SparkplugTopic, SparkplugTopicEdgeNode};
+use crate::sparkplug::value::metric_value_to_json;
+use crate::sparkplug::SparkplugMessageType;
+use crate::storage::{SqlStorageError, SqlStorageImpl, SqlValue};
+use chrono::Utc;
+use tracing::warn;
+
+const STATUS_ONLINE: &str = "online";
+const STATUS_OFFLINE: &str = "offline";
+
+/// Statements creating the tables of the normalized Sparkplug schema if they
+/// don't exist yet. Node metrics are stored with an empty device id.
+pub(crate) fn create_schema_statements<S: SqlStorageImpl + ?Sized>(db: &S) -> Vec<String> {
+    vec![
+        "CREATE TABLE IF NOT EXISTS sparkplug_nodes (
+    group_id VARCHAR(255) NOT NULL,
+    edge_node_id VARCHAR(255) NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    last_status_update BIGINT NOT NULL,
+    PRIMARY KEY (group_id, edge_node_id)
+)"
+        .to_string(),
+        "CREATE TABLE IF NOT EXISTS sparkplug_devices (
+    group_id VARCHAR(255) NOT NULL,
+    edge_node_id VARCHAR(255) NOT NULL,
+    device_id VARCHAR(255) NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    last_status_update BIGINT NOT NULL,
+    PRIMARY KEY (group_id, edge_node_id, device_id)
+)"
+        .to_string(),
+        "CREATE TABLE IF NOT EXISTS sparkplug_metrics (
+    group_id VARCHAR(255) NOT NULL,
+    edge_node_id VARCHAR(255) NOT NULL,
+    device_id VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    alias BIGINT NULL,
+    datatype INTEGER NULL,
+    PRIMARY KEY (group_id, edge_node_id, device_id, name)
+)"
+        .to_string(),
+        format!(
+            "CREATE TABLE IF NOT EXISTS sparkplug_metric_values (
+    {},
+    group_id VARCHAR(255) NOT NULL,
+    edge_node_id VARCHAR(255) NOT NULL,
+    device_id VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NULL,
+    alias BIGINT NULL,
+    metric_timestamp BIGINT NULL,
+    value TEXT NULL,
+    received_at BIGINT NOT NULL
+)",
+            db.get_auto_increment_key("id")
+        ),
+    ]
+}
+
+/// Creates the statements storing a Sparkplug message in the normalized
+/// schema: the status of nodes and devices, the metric definitions of birth
+/// messages and one row per metric value.
+pub(crate) fn create_queries<S: SqlStorageImpl + ?Sized>(
+    db: &S,
+    topic: &str,
+    payload: &PayloadFormat,
+) -> Result<Vec<(String, Vec<SqlValue>)>, SqlStorageError> {
+    let PayloadFormat::Sparkplug(payload) = payload else {
+        warn!("Received non Sparkplug payload on topic {topic} which can't be stored in the Sparkplug schema");
+        return Ok(vec![]);
+    };
+
+    let SparkplugTopic::EdgeNode(topic) = SparkplugTopic::try_from(topic)? else {
+        warn!("Messages of host applications ({topic}) are not stored in the Sparkplug schema");
+        return Ok(vec![]);
+    };
+
+    let now = Utc::now().timestamp_millis();
+    let metrics = &payload.content.metrics;
+    let mut queries = vec![];
+
+    match topic.message_type {
+        SparkplugMessageType::NBIRTH => {
+            queries.push(upsert_node(db, &topic, STATUS_ONLINE, now));
+            queries.extend(upsert_metrics(db, &topic, metrics));
+            queries.extend(insert_values(db, &topic, metrics, now));
+        }
+        SparkplugMessageType::NDEATH => {
+            queries.push(upsert_node(db, &topic, STATUS_OFFLINE, now));
+            queries.push((
+                format!(
+                    "UPDATE sparkplug_devices SET status = {}, last_status_update = {} WHERE group_id = {} AND edge_node_id = {}",
+                    db.get_placeholder(1),
+                    db.get_placeholder(2),
+                    db.get_placeholder(3),
+                    db.get_placeholder(4),
+                ),
+                vec![
+                    SqlValue::from(STATUS_OFFLINE),
+                    SqlValue::from(now),
+                    SqlValue::from(topic.group_id.as_str()),
+                    SqlValue::from(topic.edge_node_id.as_str()),
+                ],
+            ));
+        }
+        SparkplugMessageType::DBIRTH => {
+            queries.push(upsert_device(db, &topic, STATUS_ONLINE, now));
+            queries.extend(upsert_metrics(db, &topic, metrics));
+            queries.extend(insert_values(db, &topic, metrics, now));
+        }
+        SparkplugMessageType::DDEATH => {
+            queries.push(upsert_device(db, &topic, STATUS_OFFLINE, now));
+        }
+        SparkplugMessageType::NDATA | SparkplugMessageType::DDATA => {
+            queries.extend(insert_values(db, &topic, metrics, now));
+        }
+        _ => {}
+    }
+
+    Ok(queries)
+}
+
+fn device_id(topic: &SparkplugTopicEdgeNode) -> &str {
+    topic.device_id.as_deref().unwrap_or("")
+}
+
+fn placeholders<S: SqlStorageImpl + ?Sized>(db: &S, count: usize) -> String {
+    (1..=count)
+        .map(|i| db.get_placeholder(i))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn upsert_node<S: SqlStorageImpl + ?Sized>(
+    db: &S,
+    topic: &SparkplugTopicEdgeNode,
+    status: &str,
+    now: i64,
+) -> (String, Vec<SqlValue>) {
+    (
+        format!(
+            "INSERT INTO sparkplug_nodes (group_id, edge_node_id, status, last_status_update) VALUES ({}) {}",
+            placeholders(db, 4),
+            db.get_upsert_clause(
+                &["group_id", "edge_node_id"],
+                &["status", "last_status_update"]
+            )
+        ),
+        vec![
+            SqlValue::from(topic.group_id.as_str()),
+            SqlValue::from(topic.edge_node_id.as_str()),
+            SqlValue::from(status),
+            SqlValue::from(now),
+        ],
+    )
+}
+
+fn upsert_device<S: SqlStorageImpl + ?Sized>(
+    db: &S,
+    topic: &SparkplugTopicEdgeNode,
+    status: &str,
+    now: i64,
+) -> (String, Vec<SqlValue>) {
+    (
+        format!(
+            "INSERT INTO sparkplug_devices (group_id, edge_node_id, device_id, status, last_status_update) VALUES ({}) {}",
+            placeholders(db, 5),
+            db.get_upsert_clause(
+                &["group_id", "edge_node_id", "device_id"],
+                &["status", "last_status_update"]
+            )
+        ),
+        vec![
+            SqlValue::from(topic.group_id.as_str()),
+            SqlValue::from(topic.edge_node_id.as_str()),
+            SqlValue::from(device_id(topic)),
+            SqlValue::from(status),
+            SqlValue::from(now),
+        ],
+    )
+}
+
+fn upsert_metrics<S: SqlStorageImpl + ?Sized>(
+    db: &S,
+    topic: &SparkplugTopicEdgeNode,
+    metrics: &[Metric],
+) -> Vec<(String, Vec<SqlValue>)> {
+    metrics
+        .iter()
+        .filter_map(|metric| {
+            let name = metric.name.as_ref()?;
+
+            Some((
+                format!(
+                    "INSERT INTO sparkplug_metrics (group_id, edge_node_id, device_id, name, alias, datatype) VALUES ({}) {}",
+                    placeholders(db, 6),
+                    db.get_upsert_clause(
+                        &["group_id", "edge_node_id", "device_id", "name"],
+                        &["alias", "datatype"]
+                    )
+                ),
+                vec![
+                    SqlValue::from(topic.group_id.as_str()),
+                    SqlValue::from(topic.edge_node_id.as_str()),
+                    SqlValue::from(device_id(topic)),
+                    SqlValue::from(name.as_str()),
+                    SqlValue::Integer(metric.alias.map(|alias| alias as i64)),
+                    SqlValue::Integer(metric.datatype.map(i64::from)),
+                ],
+            ))
+        })
+        .collect()
+}
+
+/// Inserts one row per metric. Metrics only carrying an alias get their name
+/// from the metric definitions stored by the last birth message.
+fn insert_values<S: SqlStorageImpl + ?Sized>(
+    db: &S,
+    topic: &SparkplugTopicEdgeNode,
+    metrics: &[Metric],
+    now: i64,
+) -> Vec<(String, Vec<SqlValue>)> {
+    let p = |i: usize| db.get_placeholder(i);
+
+    metrics
+        .iter()
+        .filter(|metric| metric.name.is_some() || metric.alias.is_some())
+        .map(|metric| {
+            let value = match metric_value_to_json(metric) {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(value) => Some(value),
+                value => Some(value.to_string()),
+            };
+            let alias = metric.alias.map(|alias| alias as i64);
+
+            (
+                format!(
+                    "INSERT INTO sparkplug_metric_values (group_id, edge_node_id, device_id, name, alias, metric_timestamp, value, received_at) \
+                    VALUES ({}, {}, {}, COALESCE({}, (SELECT name FROM sparkplug_metrics WHERE group_id = {} AND edge_node_id = {} AND device_id = {} AND alias = {})), {}, {}, {}, {})",
+                    p(1), p(2), p(3), p(4), p(5), p(6), p(7), p(8), p(9), p(10), p(11), p(12)
+                ),
+                vec![
+                    SqlValue::from(topic.group_id.as_str()),
+                    SqlValue::from(topic.edge_node_id.as_str()),
+                    SqlValue::from(device_id(topic)),
+                    SqlValue::Text(metric.name.clone()),
+                    SqlValue::from(topic.group_id.as_str()),
+                    SqlValue::from(topic.edge_node_id.as_str()),
+                    SqlValue::from(device_id(topic)),
+                    SqlValue::Integer(alias),
+                    SqlValue::Integer(alias),
+                    SqlValue::Integer(metric.timestamp.map(|timestamp| timestamp as i64)),
+                    SqlValue::Text(value),
+                    SqlValue::from(now),
+                ],
+            )
+        })
+        .collect()
+}
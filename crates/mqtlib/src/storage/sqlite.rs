@@ -1,6 +1,6 @@
 use crate::mqtt::QoS;
 use crate::payload::PayloadFormat;
-use crate::storage::{SqlStorageError, SqlStorageImpl};
+use crate::storage::{SqlStorageError, SqlStorageImpl, SqlValue};
 use async_trait::async_trait;
 use sqlx::SqlitePool;
 use std::fmt::Debug;
@@ -47,14 +47,42 @@ impl SqlStorageImpl for SqlStorageSqlite {
         Ok(result?.rows_affected())
     }
 
+    async fn execute_with_values(
+        &self,
+        queries: Vec<(String, Vec<SqlValue>)>,
+    ) -> Result<u64, SqlStorageError> {
+        let mut affected_rows = 0;
+        for (query, values) in queries {
+            let mut result = sqlx::query(query.as_ref());
+            for value in values {
+                result = match value {
+                    SqlValue::Text(value) => result.bind(value),
+                    SqlValue::Integer(value) => result.bind(value),
+                    SqlValue::Blob(value) => result.bind(value),
+                };
+            }
+            let result = result.execute(&self.pool).await;
+            affected_rows += result?.rows_affected();
+        }
+        Ok(affected_rows)
+    }
+
     fn get_placeholder(&self, counter: usize) -> String {
         format!("${}", counter)
     }
+
+    fn get_auto_increment_key(&self, column: &str) -> String {
+        format!("{column} INTEGER PRIMARY KEY AUTOINCREMENT")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
+    use crate::payload::sparkplug::protos::sparkplug_b::payload::Metric;
+    use crate::payload::sparkplug::protos::sparkplug_b::Payload;
+    use crate::payload::sparkplug::PayloadFormatSparkplug;
     use crate::payload::text::PayloadFormatText;
     use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
     use sqlx::Row;
@@ -86,9 +114,7 @@ VALUES
                 "topic",
                 QoS::AtLeastOnce,
                 false,
-                &PayloadFormat::Text(PayloadFormatText {
-                    content: "PAYLOAD".as_bytes().to_vec(),
-                }),
+                &PayloadFormat::Text(PayloadFormatText::from("PAYLOAD")),
             )
             .await;
         assert!(result.is_ok());
@@ -96,6 +122,105 @@ VALUES
         print_table_content(&db).await;
     }
 
+    #[tokio::test]
+    async fn insert_default() {
+        let db = get_db().await;
+        assert!(db.create_default_schema().await.is_ok());
+        assert!(db.create_default_schema().await.is_ok());
+
+        for payload in ["{\"temperature\":20.5}", "PAYLOAD"] {
+            let result = db
+                .insert_default(
+                    "topic",
+                    QoS::AtLeastOnce,
+                    true,
+                    &PayloadFormat::Text(PayloadFormatText::from(payload)),
+                )
+                .await;
+            assert_eq!(1, result.unwrap());
+        }
+
+        let rows = sqlx::query("SELECT * FROM mqtli_messages ORDER BY id")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(2, rows.len());
+        assert_eq!("topic", rows[0].get::<String, &str>("topic"));
+        assert_eq!(1, rows[0].get::<i64, &str>("qos"));
+        assert_eq!(1, rows[0].get::<i64, &str>("retain"));
+        assert_eq!(b"PAYLOAD".to_vec(), rows[1].get::<Vec<u8>, &str>("payload"));
+        assert_eq!(
+            Some("{\"temperature\":20.5}".to_string()),
+            rows[0].get::<Option<String>, &str>("payload_json")
+        );
+        assert_eq!(None, rows[1].get::<Option<String>, &str>("payload_json"));
+    }
+
+    fn get_sparkplug_payload(metric: (Option<&str>, u64, f64)) -> PayloadFormat {
+        let (name, alias, value) = metric;
+
+        let mut metric = Metric::new();
+        metric.name = name.map(str::to_string);
+        metric.alias = Some(alias);
+        metric.value = Some(Value::DoubleValue(value));
+
+        let mut payload = Payload::new();
+        payload.metrics.push(metric);
+
+        PayloadFormat::Sparkplug(PayloadFormatSparkplug::from(payload))
+    }
+
+    #[tokio::test]
+    async fn insert_sparkplug() {
+        let db = get_db().await;
+        assert!(db.create_sparkplug_schema().await.is_ok());
+        assert!(db.create_sparkplug_schema().await.is_ok());
+
+        let birth = get_sparkplug_payload((Some("temperature"), 1, 20.5));
+        let data = get_sparkplug_payload((None, 1, 21.0));
+        let death = PayloadFormat::Sparkplug(PayloadFormatSparkplug::from(Payload::new()));
+
+        for (topic, payload) in [
+            ("spBv1.0/group/NBIRTH/edge", &birth),
+            ("spBv1.0/group/NDATA/edge", &data),
+            ("spBv1.0/group/NBIRTH/edge", &birth),
+            ("spBv1.0/group/NDEATH/edge", &death),
+        ] {
+            assert!(db.insert_sparkplug(topic, payload).await.is_ok());
+        }
+
+        let status: String = sqlx::query("SELECT status FROM sparkplug_nodes")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+            .get("status");
+        assert_eq!("offline", status);
+
+        let metrics = sqlx::query("SELECT name, alias FROM sparkplug_metrics")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(1, metrics.len());
+        assert_eq!(1, metrics[0].get::<i64, &str>("alias"));
+
+        let values: Vec<(String, String)> =
+            sqlx::query("SELECT name, value FROM sparkplug_metric_values ORDER BY id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap()
+                .iter()
+                .map(|r| (r.get("name"), r.get("value")))
+                .collect();
+        assert_eq!(
+            vec![
+                ("temperature".to_string(), "20.5".to_string()),
+                ("temperature".to_string(), "21.0".to_string()),
+                ("temperature".to_string(), "20.5".to_string()),
+            ],
+            values
+        );
+    }
+
     async fn get_db() -> SqlStorageSqlite {
         let opts = SqliteConnectOptions::from_str("sqlite::memory:")
             .unwrap()
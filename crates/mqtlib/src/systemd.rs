@@ -0,0 +1,130 @@
+use std::io;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Tells systemd that the service finished starting up.
+pub fn ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={status}"));
+}
+
+/// Updates the status shown by `systemctl status`.
+pub fn status(status: &str) {
+    notify(&format!("STATUS={status}"));
+}
+
+/// Tells systemd that the service is shutting down.
+pub fn stopping() {
+    notify("STOPPING=1\nSTATUS=Shutting down");
+}
+
+/// Tells systemd that the service is still alive.
+pub fn watchdog() {
+    notify("WATCHDOG=1");
+}
+
+/// Returns the interval in which systemd expects watchdog keepalives, if
+/// the watchdog is enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog_interval(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog_interval(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    // the watchdog may be meant for another process, e.g. a parent shell
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    usec?
+        .parse::<u64>()
+        .ok()
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// Sends the state to the service manager, see `sd_notify(3)`. Without the
+/// environment variable `NOTIFY_SOCKET`, e.g. if not started as a service of
+/// type notify, nothing is sent.
+fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    debug!("Notifying systemd: {}", state.replace('\n', ", "));
+
+    if let Err(e) = send(&path, state) {
+        warn!("Could not notify systemd: {e:?}");
+    }
+}
+
+#[cfg(unix)]
+fn send(path: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+
+    // sockets in the abstract namespace start with @
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let address = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are only supported on Linux",
+            ))
+        }
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_path: &std::ffi::OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "systemd is only supported on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_interval_for_own_process() {
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_watchdog_interval(Some("30000000"), None, 10)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_watchdog_interval(Some("30000000"), Some("10"), 10)
+        );
+        assert_eq!(
+            None,
+            parse_watchdog_interval(Some("30000000"), Some("11"), 10)
+        );
+        assert_eq!(None, parse_watchdog_interval(Some("0"), None, 10));
+        assert_eq!(None, parse_watchdog_interval(None, None, 10));
+    }
+}
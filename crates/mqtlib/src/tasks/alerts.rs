@@ -0,0 +1,379 @@
+use crate::channel;
+use crate::config::alert::{AlertAction, AlertCondition, AlertRule, AlertWebhook};
+use crate::config::connection_hooks::{ConnectionHookCommand, ConnectionHookPublish};
+use crate::config::topic::topic_matches;
+use crate::mqtt::{MessageEvent, MessagePublishData, MqttReceiveEvent, MqttService};
+use crate::publish::command_input::shell;
+use crate::template;
+use chrono::Utc;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+/// Interval in which the absence conditions are checked
+const ABSENCE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Alert raised by a rule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    /// Topic of the message which raised the alert, or the topic of the rule
+    /// if no message arrived in time
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct RuleState {
+    rule: AlertRule,
+    /// Topics whose last message met the JSON condition
+    active: HashSet<String>,
+    last_message: Instant,
+    absence_raised: bool,
+}
+
+/// Evaluates the conditions of the rules. An alert is raised once when its
+/// condition becomes true and raised again only after the condition was
+/// false in between, so that a device staying in an alarming state doesn't
+/// flood the actions.
+#[derive(Debug)]
+pub struct AlertEngine {
+    states: Vec<RuleState>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>, now: Instant) -> Self {
+        Self {
+            states: rules
+                .into_iter()
+                .map(|rule| RuleState {
+                    rule,
+                    active: HashSet::new(),
+                    last_message: now,
+                    absence_raised: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Evaluates the rules matching the topic of the message.
+    pub fn on_message(&mut self, topic: &str, payload: &[u8], now: Instant) -> Vec<Alert> {
+        let mut alerts = vec![];
+
+        for state in self
+            .states
+            .iter_mut()
+            .filter(|state| topic_matches(&state.rule.topic, topic))
+        {
+            match &state.rule.condition {
+                AlertCondition::Json(condition) => {
+                    if !condition.matches(payload) {
+                        state.active.remove(topic);
+                    } else if state.active.insert(topic.to_string()) {
+                        alerts.push(Alert {
+                            rule: state.rule.name.clone(),
+                            topic: topic.to_string(),
+                            payload: payload.to_vec(),
+                        });
+                    }
+                }
+                AlertCondition::Absence(_) => {
+                    if state.absence_raised {
+                        info!(
+                            "Alert \"{}\" cleared by message on topic {topic}",
+                            state.rule.name
+                        );
+                    }
+                    state.last_message = now;
+                    state.absence_raised = false;
+                }
+            }
+        }
+
+        alerts
+    }
+
+    /// Raises the alerts of the rules which didn't receive a message within
+    /// their timeout.
+    pub fn on_tick(&mut self, now: Instant) -> Vec<Alert> {
+        let mut alerts = vec![];
+
+        for state in self.states.iter_mut() {
+            if let AlertCondition::Absence(condition) = &state.rule.condition {
+                if !state.absence_raised
+                    && now.duration_since(state.last_message) >= condition.timeout
+                {
+                    state.absence_raised = true;
+                    alerts.push(Alert {
+                        rule: state.rule.name.clone(),
+                        topic: state.rule.topic.clone(),
+                        payload: vec![],
+                    });
+                }
+            }
+        }
+
+        alerts
+    }
+
+    fn rule(&self, name: &str) -> Option<&AlertRule> {
+        self.states
+            .iter()
+            .map(|state| &state.rule)
+            .find(|rule| rule.name == name)
+    }
+}
+
+/// Subscribes to the topics of the rules on each connect and runs the
+/// actions of the raised alerts.
+pub fn start_alerts_task(
+    rules: Vec<AlertRule>,
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    sender_receive: &Sender<MqttReceiveEvent>,
+    sender_message: Sender<MessageEvent>,
+    mut receiver_exit: Receiver<()>,
+) {
+    let mut receiver = sender_receive.subscribe();
+
+    tokio::spawn(async move {
+        debug!("Starting alerts");
+
+        let mut engine = AlertEngine::new(rules, Instant::now());
+        let client = reqwest::Client::new();
+        let mut interval = tokio::time::interval(ABSENCE_CHECK_INTERVAL);
+
+        loop {
+            let alerts = select! {
+                event = channel::recv(&mut receiver, "Alerts") => {
+                    let Some(event) = event else {
+                        break;
+                    };
+
+                    if event.is_connect() {
+                        subscribe(&engine, &mqtt_service).await;
+                        vec![]
+                    } else if let Some((topic, _, _, payload)) = event.incoming_publish() {
+                        engine.on_message(&topic, &payload, Instant::now())
+                    } else {
+                        vec![]
+                    }
+                },
+                _ = interval.tick() => engine.on_tick(Instant::now()),
+                _ = receiver_exit.recv() => break,
+            };
+
+            for alert in alerts {
+                let Some(rule) = engine.rule(&alert.rule) else {
+                    continue;
+                };
+                warn!("Alert \"{}\" raised on topic {}", alert.rule, alert.topic);
+
+                // actions may take a while, e.g. slow webhooks
+                let actions = rule.actions.clone();
+                let sender_message = sender_message.clone();
+                let client = client.clone();
+                tokio::spawn(async move {
+                    for action in actions {
+                        match action {
+                            AlertAction::Publish(publish) => {
+                                publish_alert(&alert, &publish, &sender_message)
+                            }
+                            AlertAction::Command(command) => run_command(&alert, &command).await,
+                            AlertAction::Webhook(webhook) => {
+                                call_webhook(&alert, &webhook, &client).await
+                            }
+                        }
+                    }
+                });
+            }
+        }
+
+        debug!("Alerts exited");
+    });
+}
+
+async fn subscribe(engine: &AlertEngine, mqtt_service: &Arc<Mutex<dyn MqttService>>) {
+    let mut subscribed = HashSet::new();
+
+    for state in &engine.states {
+        if !subscribed.insert(&state.rule.topic) {
+            continue;
+        }
+
+        info!(
+            "Subscribing to topic {} with QoS {:?} for alert \"{}\"",
+            state.rule.topic, state.rule.qos, state.rule.name
+        );
+        if let Err(e) = mqtt_service
+            .lock()
+            .await
+            .subscribe(state.rule.topic.clone(), state.rule.qos)
+            .await
+        {
+            error!("Could not subscribe to topic {}: {}", state.rule.topic, e);
+        }
+    }
+}
+
+/// Expands the placeholders of a payload template, besides the ones of
+/// [`crate::template`]. Unknown placeholders are kept as they are.
+fn render_template(template: &str, alert: &Alert) -> String {
+    template::render(template, |name| match name {
+        "rule" => Some(alert.rule.clone()),
+        "time" => Some(Utc::now().to_rfc3339()),
+        "topic" => Some(alert.topic.clone()),
+        "payload" => Some(String::from_utf8_lossy(&alert.payload).into_owned()),
+        name => template::common(name),
+    })
+    .into_owned()
+}
+
+fn publish_alert(alert: &Alert, publish: &ConnectionHookPublish, sender: &Sender<MessageEvent>) {
+    if let Err(e) = sender.send(MessageEvent::Publish(MessagePublishData::new(
+        publish.topic.clone(),
+        publish.qos,
+        publish.retain,
+        render_template(&publish.payload, alert).into_bytes(),
+    ))) {
+        error!("Could not publish alert \"{}\": {e:?}", alert.rule);
+    }
+}
+
+/// Runs the command with the alert in the environment variables
+/// MQTLI_ALERT_RULE, MQTLI_ALERT_TOPIC and MQTLI_ALERT_PAYLOAD.
+async fn run_command(alert: &Alert, command: &ConnectionHookCommand) {
+    let status = shell(&command.command)
+        .env("MQTLI_ALERT_RULE", &alert.rule)
+        .env("MQTLI_ALERT_TOPIC", &alert.topic)
+        .env(
+            "MQTLI_ALERT_PAYLOAD",
+            String::from_utf8_lossy(&alert.payload).as_ref(),
+        )
+        .status();
+
+    match tokio::time::timeout(command.timeout, status).await {
+        Ok(Ok(status)) if status.success() => {}
+        Ok(Ok(status)) => error!("Alert command \"{}\" failed: {status}", command.command),
+        Ok(Err(e)) => error!("Could not run alert command \"{}\": {e:?}", command.command),
+        Err(_) => error!(
+            "Alert command \"{}\" timed out after {:?}",
+            command.command, command.timeout
+        ),
+    }
+}
+
+async fn call_webhook(alert: &Alert, webhook: &AlertWebhook, client: &reqwest::Client) {
+    let mut request = client
+        .post(&webhook.url)
+        .timeout(webhook.timeout)
+        .json(&json!({
+            "rule": alert.rule,
+            "topic": alert.topic,
+            "payload": String::from_utf8_lossy(&alert.payload),
+            "time": Utc::now().to_rfc3339(),
+        }));
+    for (name, value) in &webhook.headers {
+        request = request.header(name, value);
+    }
+
+    match request.send().await.and_then(|r| r.error_for_status()) {
+        Ok(_) => debug!("Alert \"{}\" sent to {}", alert.rule, webhook.url),
+        Err(e) => error!(
+            "Could not send alert \"{}\" to {}: {e:?}",
+            alert.rule, webhook.url
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Vec<AlertRule> {
+        serde_yaml::from_str(
+            "- name: overheating
+  topic: sensors/+/temperature
+  condition: { type: json, jsonpath: $.value, operator: gt, value: 80 }
+  actions: [{ type: publish, topic: alerts }]
+- name: silent
+  topic: heartbeat
+  condition: { type: absence, timeout: 10000 }
+  actions: [{ type: publish, topic: alerts }]",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn json_alert_raised_once() {
+        let now = Instant::now();
+        let mut engine = AlertEngine::new(rules(), now);
+
+        let hot = b"{\"value\": 90}";
+        let cold = b"{\"value\": 20}";
+
+        assert!(engine
+            .on_message("sensors/1/temperature", cold, now)
+            .is_empty());
+
+        let alerts = engine.on_message("sensors/1/temperature", hot, now);
+        assert_eq!(1, alerts.len());
+        assert_eq!("overheating", alerts[0].rule);
+        assert_eq!("sensors/1/temperature", alerts[0].topic);
+        assert_eq!(hot.to_vec(), alerts[0].payload);
+
+        assert!(engine
+            .on_message("sensors/1/temperature", hot, now)
+            .is_empty());
+        assert_eq!(
+            1,
+            engine.on_message("sensors/2/temperature", hot, now).len()
+        );
+
+        assert!(engine
+            .on_message("sensors/1/temperature", cold, now)
+            .is_empty());
+        assert_eq!(
+            1,
+            engine.on_message("sensors/1/temperature", hot, now).len()
+        );
+
+        assert!(engine.on_message("other/temperature", hot, now).is_empty());
+    }
+
+    #[test]
+    fn absence_alert() {
+        let start = Instant::now();
+        let mut engine = AlertEngine::new(rules(), start);
+
+        assert!(engine.on_tick(start + Duration::from_secs(9)).is_empty());
+
+        let alerts = engine.on_tick(start + Duration::from_secs(10));
+        assert_eq!(1, alerts.len());
+        assert_eq!("silent", alerts[0].rule);
+        assert_eq!("heartbeat", alerts[0].topic);
+        assert!(engine.on_tick(start + Duration::from_secs(11)).is_empty());
+
+        engine.on_message("heartbeat", b"", start + Duration::from_secs(12));
+        assert!(engine.on_tick(start + Duration::from_secs(21)).is_empty());
+        assert_eq!(1, engine.on_tick(start + Duration::from_secs(22)).len());
+    }
+
+    #[test]
+    fn render() {
+        let alert = Alert {
+            rule: "overheating".to_string(),
+            topic: "sensors/1/temperature".to_string(),
+            payload: b"{\"value\": 90}".to_vec(),
+        };
+
+        assert_eq!(
+            "overheating on sensors/1/temperature: {\"value\": 90} {{unknown}}",
+            render_template("{{rule}} on {{topic}}: {{payload}} {{unknown}}", &alert)
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use crate::channel;
+use crate::config::mqtli_config::BirthConfig;
+use crate::mqtt::{MessageEvent, MessagePublishData, MqttReceiveEvent};
+use rumqttc::v5::Incoming;
+use rumqttc::Incoming as IncomingV311;
+use tokio::sync::broadcast::Sender;
+use tracing::{error, info};
+
+/// Publishes the birth message after each successful connect.
+pub fn start_birth_task(
+    birth: BirthConfig,
+    sender_receive: Sender<MqttReceiveEvent>,
+    sender_message: Sender<MessageEvent>,
+) {
+    let mut receiver_connect = sender_receive.subscribe();
+
+    tokio::spawn(async move {
+        while let Some(event) = channel::recv(&mut receiver_connect, "Birth").await {
+            match event {
+                MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(Incoming::ConnAck(_)))
+                | MqttReceiveEvent::V311(rumqttc::Event::Incoming(IncomingV311::ConnAck(_))) => {
+                    info!(
+                        "Publishing birth message to topic {} [Payload length: {}, QoS {:?}; retain: {}]",
+                        birth.topic(),
+                        birth.payload().len(),
+                        birth.qos(),
+                        birth.retain(),
+                    );
+
+                    if let Err(e) =
+                        sender_message.send(MessageEvent::Publish(MessagePublishData::new(
+                            birth.topic().clone(),
+                            *birth.qos(),
+                            *birth.retain(),
+                            birth.payload().clone(),
+                        )))
+                    {
+                        error!("Could not publish birth message: {e:?}");
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,36 @@
+use crate::channel;
+use crate::mqtt::broker_info::BrokerInfo;
+use crate::mqtt::MqttReceiveEvent;
+use crate::output::console::ConsoleOutput;
+use rumqttc::v5::Incoming;
+use tokio::sync::broadcast::Sender;
+use tracing::{error, info, warn};
+
+/// Logs the capabilities the broker announced in the CONNACK of each
+/// connect and prints them if requested.
+pub fn start_broker_info_task(sender: Sender<MqttReceiveEvent>, print: bool) {
+    let mut receiver_connect = sender.subscribe();
+
+    tokio::spawn(async move {
+        while let Some(event) = channel::recv(&mut receiver_connect, "Broker info").await {
+            match event {
+                MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(Incoming::ConnAck(connack))) => {
+                    let info = BrokerInfo::from(connack.properties.as_ref());
+                    info!("Broker announced {info:?}");
+
+                    if print {
+                        if let Err(e) = ConsoleOutput::output_string(info.to_string()) {
+                            error!("Error while printing the broker info: {e:?}");
+                        }
+                    }
+                }
+                MqttReceiveEvent::V311(rumqttc::Event::Incoming(rumqttc::Incoming::ConnAck(_)))
+                    if print =>
+                {
+                    warn!("The broker info is only available with MQTT v5");
+                }
+                _ => {}
+            }
+        }
+    });
+}
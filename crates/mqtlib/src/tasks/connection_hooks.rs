@@ -0,0 +1,78 @@
+use crate::channel;
+use crate::config::connection_hooks::{
+    ConnectionHookAction, ConnectionHookCommand, ConnectionHookPublish, ConnectionHooks,
+};
+use crate::mqtt::{MessageEvent, MessagePublishData, MqttReceiveEvent};
+use crate::publish::command_input::shell;
+use tokio::sync::broadcast::Sender;
+use tracing::{debug, error, info};
+
+/// Runs the connect hooks after each successful connect.
+pub fn start_connection_hooks_task(
+    hooks: ConnectionHooks,
+    sender_receive: Sender<MqttReceiveEvent>,
+    sender_message: Sender<MessageEvent>,
+) {
+    let mut receiver_connect = sender_receive.subscribe();
+
+    tokio::spawn(async move {
+        while let Some(event) = channel::recv(&mut receiver_connect, "Connection hooks").await {
+            if event.is_connect() {
+                debug!("Running connect hooks");
+                for action in hooks.on_connect() {
+                    match action {
+                        ConnectionHookAction::Command(command) => run_command(command).await,
+                        ConnectionHookAction::Publish(message) => publish(message, &sender_message),
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Publishes the messages of the disconnect hooks, which must happen while
+/// the client is still connected.
+pub fn publish_disconnect_messages(hooks: &ConnectionHooks, sender_message: &Sender<MessageEvent>) {
+    for action in hooks.on_disconnect() {
+        if let ConnectionHookAction::Publish(message) = action {
+            publish(message, sender_message);
+        }
+    }
+}
+
+/// Runs the commands of the disconnect hooks and waits until they exited.
+pub async fn run_disconnect_commands(hooks: &ConnectionHooks) {
+    for action in hooks.on_disconnect() {
+        if let ConnectionHookAction::Command(command) = action {
+            run_command(command).await;
+        }
+    }
+}
+
+async fn run_command(hook: &ConnectionHookCommand) {
+    info!("Running hook command \"{}\"", hook.command());
+
+    match tokio::time::timeout(*hook.timeout(), shell(hook.command()).status()).await {
+        Ok(Ok(status)) if status.success() => {}
+        Ok(Ok(status)) => error!("Hook command \"{}\" failed: {status}", hook.command()),
+        Ok(Err(e)) => error!("Could not run hook command \"{}\": {e:?}", hook.command()),
+        Err(_) => error!(
+            "Hook command \"{}\" timed out after {:?}",
+            hook.command(),
+            hook.timeout()
+        ),
+    }
+}
+
+fn publish(hook: &ConnectionHookPublish, sender_message: &Sender<MessageEvent>) {
+    info!("Publishing hook message to topic {}", hook.topic());
+
+    if let Err(e) = sender_message.send(MessageEvent::Publish(MessagePublishData::new(
+        hook.topic().clone(),
+        *hook.qos(),
+        *hook.retain(),
+        hook.payload().clone().into_bytes(),
+    ))) {
+        error!("Could not publish hook message: {e:?}");
+    }
+}
@@ -0,0 +1,133 @@
+use crate::channel;
+use crate::config::subscription::OutputTargetConsole;
+use crate::config::PayloadType;
+use crate::control::Controller;
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, QoS};
+use crate::output::console::ConsoleOutput;
+use crate::output::OutputError;
+use crate::payload::PayloadFormat;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tracing::{debug, error, info, warn};
+
+/// Executes the commands received on the control topic and publishes their
+/// responses. Messages of subscriptions added by commands are printed to the
+/// console; all received messages are counted for the statistics command.
+pub fn start_control_task(
+    mut controller: Controller,
+    mut receiver: Receiver<MqttReceiveEvent>,
+    mut receiver_exit: Receiver<()>,
+) {
+    debug!("Starting control on topic {}", controller.topic());
+
+    tokio::spawn(async move {
+        loop {
+            select! {
+                event = receiver.recv() => match event {
+                    Ok(event) if event.is_connect() => subscribe(&controller).await,
+                    Ok(event) => {
+                        if let Some((topic, qos, retain, payload)) = event.incoming_publish() {
+                            handle_message(&mut controller, topic, qos, retain, payload).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped_events)) => {
+                        channel::count_dropped(skipped_events);
+                        warn!("Control skipped {skipped_events} events");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = receiver_exit.recv() => break,
+            }
+        }
+
+        debug!("Control exited");
+    });
+}
+
+/// Subscribes to the control topic and, after a reconnect, again to the
+/// subscriptions added by commands.
+async fn subscribe(controller: &Controller) {
+    let topics = std::iter::once((controller.topic().to_string(), QoS::ExactlyOnce)).chain(
+        controller
+            .subscriptions()
+            .iter()
+            .map(|subscription| (subscription.topic.clone(), subscription.qos)),
+    );
+
+    for (topic, qos) in topics {
+        info!("Subscribing to topic {} with QoS {:?}", topic, qos);
+        if let Err(e) = controller
+            .mqtt_service()
+            .lock()
+            .await
+            .subscribe(topic.clone(), qos)
+            .await
+        {
+            error!("Could not subscribe to topic {}: {}", topic, e);
+        }
+    }
+}
+
+async fn handle_message(
+    controller: &mut Controller,
+    topic: String,
+    qos: QoS,
+    retain: bool,
+    payload: Vec<u8>,
+) {
+    if topic == controller.topic() {
+        let response = controller.handle(&payload).await;
+
+        match serde_json::to_vec(&response) {
+            Ok(response) => {
+                controller
+                    .mqtt_service()
+                    .lock()
+                    .await
+                    .publish(MessagePublishData::new(
+                        controller.response_topic(),
+                        QoS::AtLeastOnce,
+                        false,
+                        response,
+                    ))
+                    .await
+            }
+            Err(e) => error!("Could not serialize control response: {e:?}"),
+        }
+
+        return;
+    }
+
+    controller.count_message(&topic, payload.len());
+
+    let Some(subscription) = controller.find_subscription(&topic) else {
+        return;
+    };
+
+    let format = subscription.format.clone();
+    if let Err(e) = output_message(&topic, qos, retain, format, payload) {
+        error!("Could not output message of topic {topic}: {e:?}");
+    }
+}
+
+fn output_message(
+    topic: &str,
+    qos: QoS,
+    retain: bool,
+    payload_type: PayloadType,
+    payload: Vec<u8>,
+) -> Result<(), OutputError> {
+    let format = PayloadFormat::try_from((payload_type, payload))?;
+
+    ConsoleOutput::output_topic(
+        topic,
+        format.clone().try_into()?,
+        format,
+        qos,
+        retain,
+        None,
+        None,
+        &OutputTargetConsole::default(),
+    )
+}
@@ -0,0 +1,87 @@
+use crate::channel;
+use crate::config::dead_letter::DeadLetter;
+use crate::mqtt::{ConversionErrorData, MessageEvent, MessagePublishData};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{Receiver, Sender};
+use tracing::{debug, error};
+
+/// Republishes the messages which failed conversion to the dead letter topic,
+/// wrapped in a JSON envelope describing the error.
+pub fn start_dead_letter_task(
+    dead_letter: DeadLetter,
+    mut receiver: Receiver<MessageEvent>,
+    sender_message: Sender<MessageEvent>,
+) {
+    tokio::spawn(async move {
+        while let Some(event) = channel::recv(&mut receiver, "Dead letter").await {
+            let MessageEvent::ConversionError(error) = event else {
+                continue;
+            };
+
+            // a failing dead letter must not be republished again and again
+            if error.topic == *dead_letter.topic() {
+                continue;
+            }
+
+            debug!(
+                "Republishing message on topic {} to dead letter topic {}",
+                error.topic,
+                dead_letter.topic()
+            );
+
+            if let Err(e) = sender_message.send(MessageEvent::Publish(MessagePublishData::new(
+                dead_letter.topic().clone(),
+                *dead_letter.qos(),
+                *dead_letter.retain(),
+                envelope(&error).to_string().into_bytes(),
+            ))) {
+                error!("Could not publish dead letter: {e:?}");
+            }
+        }
+    });
+}
+
+/// Contains the original topic, the error and the payload, as text if it is
+/// valid UTF-8 and otherwise base64 encoded.
+fn envelope(error: &ConversionErrorData) -> Value {
+    let mut envelope = json!({
+        "topic": error.topic,
+        "error": error.error,
+    });
+
+    match String::from_utf8(error.payload.clone()) {
+        Ok(payload) => envelope["payload"] = Value::String(payload),
+        Err(_) => envelope["payload_base64"] = Value::String(STANDARD.encode(&error.payload)),
+    }
+
+    envelope
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_payload() {
+        let error = ConversionErrorData {
+            topic: "sensors/1".to_string(),
+            payload: b"{invalid".to_vec(),
+            error: "Could not convert".to_string(),
+        };
+        assert_eq!(
+            json!({"topic": "sensors/1", "error": "Could not convert", "payload": "{invalid"}),
+            envelope(&error)
+        );
+
+        let error = ConversionErrorData {
+            payload: vec![0xff, 0x00],
+            ..error
+        };
+        assert_eq!(
+            json!({"topic": "sensors/1", "error": "Could not convert", "payload_base64": "/wA="}),
+            envelope(&error)
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use crate::channel;
+use crate::mqtt::{MessageEvent, MqttReceiveEvent, MqttService};
+use crate::output::statistics::MessageStatistics;
+use crate::publish::trigger_periodic::SchedulerStatus;
+use crate::storage::queue::SqlWriteQueue;
+use crate::tasks::worker_pool;
+use chrono::{DateTime, Local};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+/// Sources of the runtime statistics.
+pub struct StatisticsDump {
+    pub mqtt_service: Arc<Mutex<dyn MqttService>>,
+    pub sender_receive: Sender<MqttReceiveEvent>,
+    pub sender_message: Sender<MessageEvent>,
+    pub scheduler: SchedulerStatus,
+    pub sql_queue: Option<SqlWriteQueue>,
+}
+
+/// Logs the current runtime statistics each time SIGUSR1 (ctrl + break on
+/// Windows) is received, so a running instance can be inspected without
+/// restarting it.
+pub fn start_statistics_dump_task(dump: StatisticsDump, mut receiver_exit: Receiver<()>) {
+    let mut receiver_events = dump.sender_receive.subscribe();
+    let mut receiver_messages = dump.sender_message.subscribe();
+
+    tokio::spawn(async move {
+        let mut signal = DumpSignal::new();
+        let mut connection = ConnectionState::default();
+        let mut received = MessageStatistics::default();
+
+        loop {
+            select! {
+                _ = signal.recv() => {
+                    let status = dump.status(&connection, &received).await;
+                    info!("Runtime statistics\n{status}");
+                }
+                event = channel::recv(&mut receiver_events, "Statistics dump") => {
+                    match event {
+                        Some(event) if event.is_connect() => {
+                            connection.connected_since = Some(Local::now());
+                            connection.connects += 1;
+                        }
+                        Some(event) if event.is_disconnect() => connection.connected_since = None,
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                message = channel::recv(&mut receiver_messages, "Statistics dump") => {
+                    match message {
+                        Some(MessageEvent::ReceivedUnfiltered(message)) => {
+                            let size = Vec::<u8>::try_from(message.payload)
+                                .map(|payload| payload.len())
+                                .unwrap_or_default();
+
+                            received.count_message(&message.topic, size);
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                _ = receiver_exit.recv() => break,
+            }
+        }
+
+        debug!("Statistics dump exited");
+    });
+}
+
+impl StatisticsDump {
+    async fn status(
+        &self,
+        connection: &ConnectionState,
+        received: &MessageStatistics,
+    ) -> RuntimeStatus {
+        let (published, pending_publishes) = {
+            let mqtt_service = self.mqtt_service.lock().await;
+            (mqtt_service.published(), mqtt_service.pending_publishes())
+        };
+
+        RuntimeStatus {
+            connection: connection.clone(),
+            received: received.clone(),
+            published,
+            queues: QueueDepths {
+                events: self.sender_receive.len(),
+                messages: self.sender_message.len(),
+                workers: worker_pool::pending_items(),
+                publishes: pending_publishes,
+                sql_writes: self.sql_queue.as_ref().map(SqlWriteQueue::depth),
+                dropped: channel::dropped_messages(),
+            },
+            scheduler: ScheduleState {
+                paused: self.scheduler.is_paused(),
+                queued: self.scheduler.queued_publishes(),
+                next_publish: self.scheduler.time_till_next_publish().await,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct ConnectionState {
+    connected_since: Option<DateTime<Local>>,
+    /// Number of acknowledged connects, including reconnects
+    connects: u32,
+}
+
+#[derive(Debug)]
+struct QueueDepths {
+    events: usize,
+    messages: usize,
+    workers: usize,
+    publishes: usize,
+    sql_writes: Option<usize>,
+    dropped: u64,
+}
+
+#[derive(Debug)]
+struct ScheduleState {
+    paused: bool,
+    queued: usize,
+    next_publish: Option<Duration>,
+}
+
+/// Snapshot of the state of a running instance.
+#[derive(Debug)]
+struct RuntimeStatus {
+    connection: ConnectionState,
+    received: MessageStatistics,
+    published: MessageStatistics,
+    queues: QueueDepths,
+    scheduler: ScheduleState,
+}
+
+impl Display for RuntimeStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.connection.connected_since {
+            Some(since) => writeln!(
+                f,
+                "Connection: connected since {} ({} connects)",
+                since.format("%Y-%m-%d %H:%M:%S"),
+                self.connection.connects
+            )?,
+            None => writeln!(
+                f,
+                "Connection: disconnected ({} connects)",
+                self.connection.connects
+            )?,
+        }
+
+        write_topics(f, "Received", &self.received)?;
+        write_topics(f, "Published", &self.published)?;
+
+        let queues = &self.queues;
+        write!(
+            f,
+            "Queues: {} events, {} messages, {} worker items, {} unacknowledged publishes",
+            queues.events, queues.messages, queues.workers, queues.publishes
+        )?;
+        if let Some(sql_writes) = queues.sql_writes {
+            write!(f, ", {sql_writes} SQL writes")?;
+        }
+        writeln!(f, ", {} dropped messages", queues.dropped)?;
+
+        let scheduler = &self.scheduler;
+        write!(
+            f,
+            "Scheduler: {}, {} queued publishes, ",
+            if scheduler.paused {
+                "paused"
+            } else {
+                "running"
+            },
+            scheduler.queued
+        )?;
+        match scheduler.next_publish {
+            Some(next) => write!(f, "next publish in {:.1} s", next.as_secs_f64()),
+            None => write!(f, "no pending schedules"),
+        }
+    }
+}
+
+fn write_topics(
+    f: &mut Formatter<'_>,
+    title: &str,
+    statistics: &MessageStatistics,
+) -> std::fmt::Result {
+    let total = statistics.total();
+    writeln!(
+        f,
+        "{title}: {} messages, {} bytes",
+        total.messages, total.bytes
+    )?;
+
+    for (topic, topic_statistics) in &statistics.topics {
+        writeln!(
+            f,
+            "  {topic}: {} messages, {} bytes",
+            topic_statistics.messages, topic_statistics.bytes
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Signal requesting a dump of the statistics.
+struct DumpSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+    #[cfg(windows)]
+    signal: Option<tokio::signal::windows::CtrlBreak>,
+}
+
+impl DumpSignal {
+    #[cfg(unix)]
+    fn new() -> Self {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        Self {
+            signal: signal(SignalKind::user_defined1())
+                .inspect_err(|e| error!("Could not add SIGUSR1 handler: {e:?}"))
+                .ok(),
+        }
+    }
+
+    #[cfg(windows)]
+    fn new() -> Self {
+        Self {
+            signal: tokio::signal::windows::ctrl_break()
+                .inspect_err(|e| error!("Could not add ctrl + break handler: {e:?}"))
+                .ok(),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn new() -> Self {
+        Self {}
+    }
+
+    /// Completes when the signal is received, never if it couldn't be
+    /// registered.
+    async fn recv(&mut self) {
+        #[cfg(any(unix, windows))]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_runtime_status() {
+        let mut received = MessageStatistics::default();
+        received.count_message("sensors/1", 10);
+        received.count_message("sensors/2", 5);
+
+        let status = RuntimeStatus {
+            connection: ConnectionState {
+                connected_since: None,
+                connects: 2,
+            },
+            received,
+            published: MessageStatistics::default(),
+            queues: QueueDepths {
+                events: 1,
+                messages: 2,
+                workers: 3,
+                publishes: 4,
+                sql_writes: Some(5),
+                dropped: 6,
+            },
+            scheduler: ScheduleState {
+                paused: true,
+                queued: 7,
+                next_publish: Some(Duration::from_millis(1500)),
+            },
+        };
+
+        assert_eq!(
+            "Connection: disconnected (2 connects)\n\
+             Received: 2 messages, 15 bytes\n\
+             \x20 sensors/1: 1 messages, 10 bytes\n\
+             \x20 sensors/2: 1 messages, 5 bytes\n\
+             Published: 0 messages, 0 bytes\n\
+             Queues: 1 events, 2 messages, 3 worker items, 4 unacknowledged publishes, \
+             5 SQL writes, 6 dropped messages\n\
+             Scheduler: paused, 7 queued publishes, next publish in 1.5 s",
+            status.to_string()
+        );
+    }
+}
@@ -0,0 +1,16 @@
+pub mod alerts;
+pub mod birth;
+pub mod broker_info;
+pub mod connection_hooks;
+pub mod control;
+pub mod dead_letter;
+pub mod dump;
+pub mod output;
+pub mod publish;
+pub mod scheduler;
+pub mod shutdown;
+pub mod sparkplug;
+pub mod statistics;
+pub mod subscription;
+pub mod systemd;
+pub mod worker_pool;
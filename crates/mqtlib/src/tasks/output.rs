@@ -0,0 +1,350 @@
+use crate::channel;
+use crate::config::subscription::{Output, OutputTarget};
+use crate::config::topic::TopicStorage;
+use crate::config::PayloadType;
+use crate::mqtt::{ConversionErrorData, MessageEvent, MessagePublishData, MessageReceivedData};
+use crate::output::console::ConsoleOutput;
+use crate::output::diff::PayloadHistory;
+#[cfg(feature = "email")]
+use crate::output::email::EmailOutput;
+use crate::output::file::{file_extension, FileMessage};
+use crate::output::sample::OutputSampler;
+use crate::output::{OutputContext, OutputError};
+use crate::payload::json::PayloadFormatJson;
+use crate::payload::PayloadFormat;
+use crate::publish::topic_template::TopicTemplate;
+use crate::storage::queue::{SqlWrite, SqlWriteQueue};
+use crate::tasks::worker_pool::{WorkerPool, WorkerReceiver};
+use chrono::{Local, SecondsFormat};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tracing::error;
+
+/// Interval in which the file outputs are checked for content to flush.
+const FILE_FLUSH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Writes the filtered messages to the outputs of their topics. Messages are
+/// processed concurrently by the given number of workers, messages of the
+/// same topic, or of the same ordered subscription, are always written in
+/// the order they were received.
+pub fn start_output_task(
+    mut receiver: Receiver<MessageEvent>,
+    topic_storage: Arc<TopicStorage>,
+    sender_message: Sender<MessageEvent>,
+    exclude_types: Vec<PayloadType>,
+    db: Option<SqlWriteQueue>,
+    print_to_console: bool,
+    workers: usize,
+    output_context: Arc<OutputContext>,
+) {
+    let ordering = topic_storage.clone();
+    let pool = WorkerPool::start(
+        workers,
+        move |mut messages: WorkerReceiver<MessageReceivedData>| {
+            let topic_storage = topic_storage.clone();
+            let sender_message = sender_message.clone();
+            let db = db.clone();
+            let output_context = output_context.clone();
+
+            async move {
+                let mut history = PayloadHistory::default();
+                let mut sampler = OutputSampler::default();
+
+                while let Some(message) = messages.recv().await {
+                    let outputs = topic_storage.get_outputs_for_topic(&message.topic);
+                    for (index, output) in outputs.iter().enumerate() {
+                        if let Some(when) = output.when() {
+                            if !when.matches(&message.topic, &message.payload) {
+                                continue;
+                            }
+                        }
+
+                        if let Some(sample) = output.sample() {
+                            if !sampler.accept(index, &message.topic, sample) {
+                                continue;
+                            }
+                        }
+
+                        if let Err(e) = write_to_output(
+                            sender_message.clone(),
+                            &message,
+                            (index, output),
+                            db.as_ref(),
+                            &mut history,
+                            print_to_console,
+                            &output_context,
+                        )
+                        .await
+                        {
+                            error!("Error while writing to output {}: {e:?}", output.target);
+
+                            if let OutputError::ErrorPayloadFormat(_) = e {
+                                let _ = sender_message.send(MessageEvent::ConversionError(
+                                    ConversionErrorData {
+                                        topic: message.topic.clone(),
+                                        payload: Vec::try_from(message.payload.clone())
+                                            .unwrap_or_default(),
+                                        error: e.to_string(),
+                                    },
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    );
+
+    tokio::spawn(async move {
+        while let Some(event) = channel::recv(&mut receiver, "Output").await {
+            if let MessageEvent::ReceivedFiltered(message) = event {
+                if !exclude_types.contains(&message.payload.clone().to_owned().into()) {
+                    let topic = message.topic.clone();
+                    pool.dispatch(ordering.ordering_key(&topic), message).await;
+                }
+            }
+        }
+    });
+}
+
+/// Writes the buffered content of the file outputs in their flush interval
+/// until the exit signal is received.
+pub fn start_file_flush_task(output_context: Arc<OutputContext>, mut receiver_exit: Receiver<()>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FILE_FLUSH_CHECK_INTERVAL);
+
+        loop {
+            select! {
+                _ = interval.tick() => output_context.file.flush_due(),
+                _ = receiver_exit.recv() => break,
+            }
+        }
+    });
+}
+
+async fn write_to_output(
+    sender_message: Sender<MessageEvent>,
+    message: &MessageReceivedData,
+    (index, output): (usize, &Output),
+    db: Option<&SqlWriteQueue>,
+    history: &mut PayloadHistory,
+    print_to_console: bool,
+    output_context: &OutputContext,
+) -> Result<(), OutputError> {
+    if matches!(output.target(), OutputTarget::Console(_)) && !print_to_console {
+        return Ok(());
+    }
+
+    let conv = with_json_pretty(
+        PayloadFormat::try_from((message.payload.clone(), output.format()))?,
+        output.json_pretty,
+    );
+    match output.target() {
+        OutputTarget::Console(options) if options.null_delimited => {
+            ConsoleOutput::output_null_delimited(conv.try_into()?, options)
+        }
+        OutputTarget::Console(options) if options.diff && is_structured(&conv) => {
+            let content: String = conv.clone().try_into()?;
+            let value = PayloadFormatJson::try_from(conv.clone())?.content().clone();
+
+            match history.update(index, &message.topic, value) {
+                None => ConsoleOutput::output_topic(
+                    &message.topic,
+                    content,
+                    conv,
+                    message.qos,
+                    message.retain,
+                    message.payload_format_indicator,
+                    message.properties.as_ref(),
+                    options,
+                ),
+                Some(changes) => ConsoleOutput::output_changes(
+                    &message.topic,
+                    &changes,
+                    conv,
+                    content.len(),
+                    message.qos,
+                    message.retain,
+                    message.payload_format_indicator,
+                    message.properties.as_ref(),
+                    options,
+                ),
+            }
+        }
+        OutputTarget::Console(options) => ConsoleOutput::output_topic(
+            &message.topic,
+            conv.clone().try_into()?,
+            conv,
+            message.qos,
+            message.retain,
+            message.payload_format_indicator,
+            message.properties.as_ref(),
+            options,
+        ),
+        OutputTarget::File(file) if file.per_message => {
+            let extension = file_extension(&conv);
+            output_context.file.output_per_message(
+                &message.topic,
+                conv.try_into()?,
+                extension,
+                file,
+            )
+        }
+        OutputTarget::File(file) if file.ndjson => output_context.file.output_json_line(
+            &FileMessage::from(message),
+            &json_line(message, conv)?,
+            file,
+        ),
+        OutputTarget::File(file) => {
+            output_context
+                .file
+                .output(&FileMessage::from(message), conv.try_into()?, file)
+        }
+        OutputTarget::Pipe(pipe) => output_context.pipe.output(conv.try_into()?, pipe),
+        #[cfg(feature = "websocket")]
+        OutputTarget::WebSocket(websocket) => {
+            output_context.websocket.output(conv.try_into()?, websocket)
+        }
+        #[cfg(feature = "grpc")]
+        OutputTarget::Grpc(grpc) => {
+            output_context
+                .grpc
+                .output(&message.topic, conv, message.qos, message.retain, grpc)
+        }
+        #[cfg(feature = "email")]
+        OutputTarget::Email(email) => {
+            EmailOutput::output(
+                &message.topic,
+                conv.try_into()?,
+                message.qos,
+                message.retain,
+                email,
+            )
+            .await
+        }
+        OutputTarget::Topic(options) => {
+            let payload: Vec<u8> = conv.try_into()?;
+            let topic = output_topic(options.topic(), output.format(), &payload)?;
+
+            sender_message
+                .send(MessageEvent::Publish(
+                    MessagePublishData::new(topic, *options.qos(), *options.retain(), payload)
+                        .with_payload_type(output.format()),
+                ))
+                .map_err(OutputError::SendError)?;
+            Ok(())
+        }
+        OutputTarget::Sql(sql) => {
+            let db = db.ok_or(OutputError::SqlDatabaseNotInitialized)?;
+            db.push(SqlWrite::Insert {
+                statement: sql.insert_statement.clone(),
+                topic: message.topic.clone(),
+                qos: message.qos,
+                retain: message.retain,
+                payload: message.payload.clone(),
+                received_at: message.received_at,
+                processing_time: message.processing_time,
+            })
+            .await;
+            Ok(())
+        }
+        OutputTarget::SparkplugSql(_) => {
+            let db = db.ok_or(OutputError::SqlDatabaseNotInitialized)?;
+            db.push(SqlWrite::Sparkplug {
+                topic: message.topic.clone(),
+                payload: message.payload.clone(),
+            })
+            .await;
+            Ok(())
+        }
+    }
+}
+
+/// Expands the placeholders of the topic of a topic output, e.g.
+/// `alerts/{{$.device_id}}`, with the payload written to it.
+fn output_topic(
+    template: &str,
+    payload_type: &PayloadType,
+    payload: &[u8],
+) -> Result<String, OutputError> {
+    let topic = TopicTemplate::new(template, payload_type).render(payload);
+
+    if topic.is_empty() || topic.contains(['+', '#']) {
+        return Err(OutputError::InvalidOutputTopic(topic));
+    }
+
+    Ok(topic)
+}
+
+/// Returns the message as a line of an NDJSON file. Structured payloads are
+/// embedded as JSON, all others as string.
+fn json_line(message: &MessageReceivedData, payload: PayloadFormat) -> Result<Value, OutputError> {
+    let payload = if is_structured(&payload) {
+        PayloadFormatJson::try_from(payload)?.content().clone()
+    } else {
+        Value::String(payload.try_into()?)
+    };
+
+    let mut line = json!({
+        "time": Local::now().to_rfc3339(),
+        "topic": message.topic,
+        "qos": message.qos as u8,
+        "retain": message.retain,
+        "received_at": message.received_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "processing_ms": message.processing_ms(),
+        "payload": payload,
+    });
+    if let Some(indicator) = message.payload_format_indicator {
+        line["payload_format_indicator"] = indicator.to_string().into();
+    }
+    if let Some(properties) = &message.properties {
+        line["properties"] = properties.to_json();
+    }
+
+    Ok(line)
+}
+
+/// Returns true if the payload can be compared field by field.
+fn is_structured(payload: &PayloadFormat) -> bool {
+    matches!(
+        payload,
+        PayloadFormat::Json(_) | PayloadFormat::Yaml(_) | PayloadFormat::SparkplugJson(_)
+    )
+}
+
+/// Sets whether JSON payloads are indented when they are written.
+fn with_json_pretty(payload: PayloadFormat, pretty: bool) -> PayloadFormat {
+    match payload {
+        PayloadFormat::Json(value) => PayloadFormat::Json(value.with_pretty(pretty)),
+        PayloadFormat::SparkplugJson(value) => {
+            PayloadFormat::SparkplugJson(value.with_pretty(pretty))
+        }
+        payload => payload,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_topic_from_payload() {
+        let payload = b"{\"device_id\": \"pump-1\", \"level\": \"+\"}";
+
+        assert_eq!(
+            "alerts/pump-1",
+            output_topic("alerts/{{$.device_id}}", &PayloadType::Json, payload).unwrap()
+        );
+        assert!(matches!(
+            output_topic("alerts/{{$.level}}", &PayloadType::Json, payload),
+            Err(OutputError::InvalidOutputTopic(_))
+        ));
+        assert!(matches!(
+            output_topic("{{$.missing}}", &PayloadType::Json, payload),
+            Err(OutputError::InvalidOutputTopic(_))
+        ));
+    }
+}
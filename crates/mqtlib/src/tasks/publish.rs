@@ -0,0 +1,18 @@
+use crate::channel;
+use crate::mqtt::{MessageEvent, MqttService};
+use std::sync::Arc;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex;
+
+pub fn start_publish_task(
+    mut receiver_publish: Receiver<MessageEvent>,
+    mqtt_service_publish: Arc<Mutex<dyn MqttService>>,
+) {
+    tokio::spawn(async move {
+        while let Some(event) = channel::recv(&mut receiver_publish, "Publisher").await {
+            if let MessageEvent::Publish(event) = event {
+                mqtt_service_publish.lock().await.publish(event).await;
+            }
+        }
+    });
+}
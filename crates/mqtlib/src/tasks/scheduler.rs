@@ -0,0 +1,313 @@
+use crate::config::mqtli_config::OnPublishComplete;
+use crate::config::publish::Publish;
+use crate::config::publish::PublishTriggerType::{OnMessage, Periodic};
+use crate::config::topic::{Topic, TopicStorage};
+use crate::config::{
+    DirectoryEnd, PayloadType, PublishInputType, PublishInputTypeEnv, PublishInputTypeNdjson,
+};
+use crate::mqtt::MqttReceiveEvent;
+use crate::payload::{read_ndjson, PayloadFormat, PayloadFormatError};
+use crate::publish::command_input::CommandInput;
+use crate::publish::http_input::HttpInput;
+use crate::publish::payload_sequence::{NextPayload, PayloadSequence, SequencePayload};
+use crate::publish::topic_template::TopicTemplate;
+use crate::publish::trigger_on_message::TriggerOnMessage;
+use crate::publish::trigger_periodic::{Command, TriggerPeriodic};
+use crate::publish::TriggerError;
+use chrono::Local;
+use futures::FutureExt;
+use rumqttc::v5::Incoming;
+use rumqttc::Incoming as IncomingV311;
+use std::sync::Arc;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+/// Waits until all periodic publishes were sent and signals that mqtli is
+/// done, depending on the configured behavior. Without periodic publishes,
+/// the exit and summary behaviors only apply if nothing else keeps mqtli
+/// connected, like with auto.
+pub fn start_scheduler_monitor_task(
+    mut receiver_command: Receiver<Command>,
+    on_publish_complete: OnPublishComplete,
+    has_trigger_periodic: bool,
+    keep_connected: bool,
+    sender_complete: oneshot::Sender<()>,
+) {
+    tokio::spawn(async move {
+        let complete = match receiver_command.recv().await {
+            Ok(Command::NoMoreTasksPending) => match on_publish_complete {
+                OnPublishComplete::Auto => !keep_connected,
+                OnPublishComplete::Exit | OnPublishComplete::Summary => {
+                    has_trigger_periodic || !keep_connected
+                }
+                OnPublishComplete::Stay => false,
+            },
+            Err(e) => {
+                debug!("Received error from scheduler: {e:?}");
+                true
+            }
+        };
+
+        if complete {
+            debug!("No more pending tasks, shutting down");
+            let _ = sender_complete.send(());
+        }
+    });
+}
+
+pub fn start_scheduler_task(
+    scheduler: TriggerPeriodic,
+    trigger_on_message: TriggerOnMessage,
+    sender: Sender<MqttReceiveEvent>,
+    topics: Arc<TopicStorage>,
+    receiver_exit: Receiver<()>,
+) {
+    let mut receiver_connect = sender.subscribe();
+
+    tokio::spawn(async move {
+        while let Ok(event) = receiver_connect.recv().await {
+            match event {
+                MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(Incoming::ConnAck(_)))
+                | MqttReceiveEvent::V311(rumqttc::Event::Incoming(IncomingV311::ConnAck(_))) => {
+                    info!("Connected to broker");
+
+                    let _ = start_scheduler(
+                        topics.clone(),
+                        scheduler,
+                        trigger_on_message,
+                        sender.subscribe(),
+                        receiver_exit,
+                    )
+                    .await;
+
+                    return;
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+async fn start_scheduler(
+    topic_storage: Arc<TopicStorage>,
+    mut scheduler: TriggerPeriodic,
+    mut trigger_on_message: TriggerOnMessage,
+    receiver_messages: Receiver<MqttReceiveEvent>,
+    receiver_exit: Receiver<()>,
+) -> Result<JoinHandle<()>, TriggerError> {
+    for topic in topic_storage.topics.iter() {
+        if let Some(publish) = topic
+            .publish()
+            .as_ref()
+            .filter(|publish| *publish.enabled())
+        {
+            let topic_template = TopicTemplate::new(topic.topic(), topic.payload_type());
+
+            let sequences = match get_payload_sequences(publish, topic) {
+                Ok(val) => val,
+                Err(e) => {
+                    error!("Error while converting payload: {e}");
+                    continue;
+                }
+            };
+
+            for trigger in publish.trigger() {
+                match trigger {
+                    Periodic(value) => {
+                        for sequence in sequences.iter() {
+                            if let Err(e) = scheduler
+                                .add_schedule(
+                                    value.interval(),
+                                    value.count(),
+                                    &value.start_delay(&Local::now()),
+                                    value.jitter(),
+                                    &value.window(),
+                                    &topic_template,
+                                    publish.qos(),
+                                    *publish.retain(),
+                                    sequence.clone(),
+                                )
+                                .await
+                            {
+                                error!("Error while adding schedule: {}", e);
+                            };
+                        }
+                    }
+                    OnMessage(value) => {
+                        for sequence in sequences.iter() {
+                            trigger_on_message.add_watch(
+                                value,
+                                &topic_template,
+                                publish.qos(),
+                                *publish.retain(),
+                                sequence.clone(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if !trigger_on_message.is_empty() {
+        trigger_on_message
+            .start(receiver_messages, receiver_exit.resubscribe())
+            .await;
+    }
+
+    scheduler.start(receiver_exit).await
+}
+
+/// Converts the publish input to the payloads to publish. Each payload gets
+/// its own sequence which always yields it, except for directory, CSV,
+/// NDJSON and messages inputs whose payloads are published one after
+/// another from a single sequence.
+/// HTTP and command inputs, and environment variables if configured, are
+/// read each time the trigger fires.
+fn get_payload_sequences(
+    publish: &Publish,
+    topic: &Topic,
+) -> Result<Vec<PayloadSequence>, PayloadFormatError> {
+    let fetched_input = match publish.input() {
+        PublishInputType::Http(input) => Some(FetchedInput::Http(HttpInput::new(input.clone())?)),
+        PublishInputType::Command(input) => {
+            Some(FetchedInput::Command(CommandInput::new(input.clone())))
+        }
+        PublishInputType::Env(input) if *input.per_trigger() => {
+            Some(FetchedInput::Env(input.clone()))
+        }
+        _ => None,
+    };
+
+    if let Some(input) = fetched_input {
+        return Ok(vec![fetch_payload_sequence(
+            input,
+            publish.clone(),
+            topic.payload_type().clone(),
+        )]);
+    }
+
+    if let PublishInputType::Ndjson(input) = publish.input() {
+        return Ok(vec![ndjson_payload_sequence(publish, topic, input)?]);
+    }
+
+    let payloads = PayloadFormat::from_input(publish.input())?
+        .into_iter()
+        .map(|data| convert_payload(publish, topic.payload_type(), data))
+        .collect::<Result<Vec<Vec<Vec<u8>>>, PayloadFormatError>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<Vec<u8>>>();
+
+    Ok(match publish.input() {
+        PublishInputType::Directory(input) => vec![PayloadSequence::new(
+            payloads,
+            *input.on_end() == DirectoryEnd::Loop,
+        )],
+        PublishInputType::Csv(input) => vec![PayloadSequence::new(
+            payloads,
+            *input.on_end() == DirectoryEnd::Loop,
+        )],
+        PublishInputType::Messages(input) => vec![PayloadSequence::new(
+            payloads,
+            *input.on_end() == DirectoryEnd::Loop,
+        )],
+        _ => payloads.into_iter().map(PayloadSequence::single).collect(),
+    })
+}
+
+/// Creates a single sequence of the lines of an NDJSON input, each
+/// published with the topic and QoS read from it.
+fn ndjson_payload_sequence(
+    publish: &Publish,
+    topic: &Topic,
+    input: &PublishInputTypeNdjson,
+) -> Result<PayloadSequence, PayloadFormatError> {
+    let mut payloads = Vec::new();
+
+    for line in read_ndjson(input)? {
+        for payload in convert_payload(publish, topic.payload_type(), line.payload)? {
+            payloads.push(SequencePayload {
+                payload,
+                topic: line.topic.clone(),
+                qos: line.qos,
+            });
+        }
+    }
+
+    Ok(PayloadSequence::with_payloads(
+        payloads,
+        *input.on_end() == DirectoryEnd::Loop,
+    ))
+}
+
+/// Applies the filters of the publish and converts the resulting payloads
+/// to the payload type of the topic.
+fn convert_payload(
+    publish: &Publish,
+    payload_type: &PayloadType,
+    data: PayloadFormat,
+) -> Result<Vec<Vec<u8>>, PayloadFormatError> {
+    publish
+        .apply_filters(data)
+        .map_err(PayloadFormatError::from)?
+        .into_iter()
+        .map(|data| PayloadFormat::try_from((data, payload_type)))
+        .map(|data| data.and_then(|payload| payload.try_into()))
+        .collect()
+}
+
+/// Input which is read each time the trigger fires.
+#[derive(Clone, Debug)]
+enum FetchedInput {
+    Http(HttpInput),
+    Command(CommandInput),
+    Env(PublishInputTypeEnv),
+}
+
+impl FetchedInput {
+    async fn fetch(&self) -> Result<PayloadFormat, PayloadFormatError> {
+        match self {
+            FetchedInput::Http(input) => input.fetch().await,
+            FetchedInput::Command(input) => input.fetch().await,
+            FetchedInput::Env(input) => PayloadFormat::from_env(input),
+        }
+    }
+}
+
+/// Creates a sequence fetching the payload from the input. If the
+/// filters yield several payloads, only the first one is published; the
+/// firing is skipped if fetching or converting fails.
+fn fetch_payload_sequence(
+    input: FetchedInput,
+    publish: Publish,
+    payload_type: PayloadType,
+) -> PayloadSequence {
+    PayloadSequence::fetched(Arc::new(move || {
+        let input = input.clone();
+        let publish = publish.clone();
+        let payload_type = payload_type.clone();
+
+        async move {
+            let payload = input
+                .fetch()
+                .await
+                .and_then(|data| convert_payload(&publish, &payload_type, data));
+
+            match payload.map(|payloads| payloads.into_iter().next()) {
+                Ok(Some(payload)) => NextPayload::Payload(payload.into()),
+                Ok(None) => {
+                    debug!("Filters yielded no payload for fetched input, skipping publish");
+                    NextPayload::Skip
+                }
+                Err(e) => {
+                    error!("Error while fetching payload: {e}");
+                    NextPayload::Skip
+                }
+            }
+        }
+        .boxed()
+    }))
+}
@@ -0,0 +1,110 @@
+use crate::mqtt::{MessageEvent, MqttReceiveEvent, MqttService};
+use crate::storage::queue::SqlWriteQueue;
+use crate::tasks::worker_pool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::Sender;
+use tokio::sync::Mutex;
+use tokio::{select, signal};
+use tracing::{debug, error, warn};
+
+/// Interval in which the queues are checked while draining.
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Exit code if the shutdown was aborted, like a process terminated by
+/// SIGINT.
+pub const FORCE_QUIT_EXIT_CODE: i32 = 130;
+
+/// Exits the process immediately if ctrl + c is pressed again during the
+/// shutdown or the shutdown takes longer than the force quit timeout, e.g.
+/// because the broker doesn't respond or a task hangs.
+pub fn start_force_quit_task(timeout: Option<Duration>) {
+    tokio::spawn(async move {
+        select! {
+            result = signal::ctrl_c() => {
+                if let Err(e) = result {
+                    error!("Could not add ctrl + c handler: {e:?}");
+                    std::future::pending::<()>().await;
+                }
+                warn!("Exit signal received again, aborting shutdown");
+            }
+            _ = sleep_optional(timeout) => {
+                warn!("Force quit timeout elapsed, aborting shutdown");
+            }
+        }
+
+        std::process::exit(FORCE_QUIT_EXIT_CODE);
+    });
+}
+
+async fn sleep_optional(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => tokio::time::sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Queues which are waited for on shutdown.
+pub struct DrainQueues<'a> {
+    pub mqtt_service: &'a Arc<Mutex<dyn MqttService>>,
+    pub sender_receive: &'a Sender<MqttReceiveEvent>,
+    pub sender_message: &'a Sender<MessageEvent>,
+    pub sql_queue: Option<&'a SqlWriteQueue>,
+    /// Publishes can only be acknowledged while connected
+    pub connected: bool,
+}
+
+impl DrainQueues<'_> {
+    /// Waits until all received messages are written to the outputs, the
+    /// SQL write queue is empty and all QoS 1 and 2 publishes are
+    /// acknowledged by the broker. Gives up after the timeout, logging the
+    /// work which is left.
+    pub async fn drain(&self, timeout: Duration) {
+        debug!("Draining queues");
+
+        let result = tokio::time::timeout(timeout, async {
+            while !self.pending().await.is_empty() {
+                tokio::time::sleep(DRAIN_CHECK_INTERVAL).await;
+            }
+        })
+        .await;
+
+        if result.is_err() {
+            let pending = self.pending().await;
+            warn!(
+                "Shutdown timeout of {} s elapsed, {} messages, {} SQL writes and {} publishes are left",
+                timeout.as_secs_f64(),
+                pending.messages,
+                pending.sql_writes,
+                pending.publishes,
+            );
+        }
+    }
+
+    async fn pending(&self) -> PendingWork {
+        PendingWork {
+            messages: self.sender_receive.len()
+                + self.sender_message.len()
+                + worker_pool::pending_items(),
+            sql_writes: self.sql_queue.map(SqlWriteQueue::depth).unwrap_or_default(),
+            publishes: if self.connected {
+                self.mqtt_service.lock().await.pending_publishes()
+            } else {
+                0
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct PendingWork {
+    messages: usize,
+    sql_writes: usize,
+    publishes: usize,
+}
+
+impl PendingWork {
+    fn is_empty(&self) -> bool {
+        *self == PendingWork::default()
+    }
+}
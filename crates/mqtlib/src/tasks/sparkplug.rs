@@ -0,0 +1,771 @@
+use crate::channel;
+use crate::config::sparkplug::{
+    Sparkplug, SparkplugEmulator, SparkplugPrimaryHost, SparkplugSnapshotExport,
+};
+use crate::config::subscription::OutputTarget;
+use crate::config::topic::TopicStorage;
+use crate::mqtt::{MessageEvent, MessagePublishData, QoS};
+use crate::output::console::ConsoleOutput;
+use crate::output::file::FileMessage;
+use crate::output::theme::{Role, Themed};
+use crate::output::OutputContext;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::metric::Value;
+use crate::payload::sparkplug::protos::sparkplug_b::payload::{Metric, Template};
+use crate::payload::sparkplug::PayloadFormatSparkplug;
+use crate::payload::PayloadFormat;
+use crate::sparkplug::emulator::EdgeNodeEmulator;
+use crate::sparkplug::host_application::PrimaryHostState;
+use crate::sparkplug::network::{create_rebirth_request, SparkplugNetwork};
+use crate::sparkplug::summary::SparkplugSummary;
+use crate::sparkplug::topic::{SparkplugTopic, SparkplugTopicEdgeNode};
+use crate::sparkplug::value::parameter_value_to_json;
+use crate::sparkplug::SparkplugMessageType;
+use crate::storage::queue::{SqlWrite, SqlWriteQueue};
+use chrono::DateTime;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, trace, warn};
+
+pub fn start_sparkplug_monitor(
+    sparkplug_network: Arc<Mutex<SparkplugNetwork>>,
+    config: Sparkplug,
+    topic_storage: Arc<TopicStorage>,
+    sender_message: Sender<MessageEvent>,
+    mut receiver: Receiver<MessageEvent>,
+    db: Option<SqlWriteQueue>,
+    output_context: Arc<OutputContext>,
+) {
+    debug!("Starting sparkplug network monitor");
+
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(MessageEvent::ReceivedUnfiltered(message)) => {
+                    if let PayloadFormat::Sparkplug(payload) = message.payload {
+                        debug!("Received sparkplug message on topic {}", message.topic);
+                        trace!("{}", payload);
+
+                        match SparkplugTopic::try_from(message.topic.as_str()) {
+                            Ok(topic) => {
+                                let payload = {
+                                    let mut network = sparkplug_network.lock().await;
+
+                                    let filtered = match &topic {
+                                        SparkplugTopic::EdgeNode(edge_node) => {
+                                            if *config.request_rebirth()
+                                                && network.needs_rebirth(edge_node, &payload)
+                                            {
+                                                request_rebirth(edge_node, &sender_message);
+                                            }
+
+                                            network.filter_metrics(edge_node, &payload)
+                                        }
+                                        SparkplugTopic::HostApplication(_) => payload.clone(),
+                                    };
+
+                                    network.parse_message(topic.clone(), payload);
+                                    filtered
+                                };
+
+                                output_sparkplug_message(
+                                    &payload,
+                                    &topic,
+                                    (message.qos, message.retain),
+                                    topic_storage.clone(),
+                                    &output_context,
+                                );
+
+                                if *config.store_sql() {
+                                    if let Some(db) = &db {
+                                        db.push(SqlWrite::Sparkplug {
+                                            topic: message.topic.clone(),
+                                            payload: PayloadFormat::Sparkplug(payload),
+                                        })
+                                        .await;
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                error!("Error while parsing sparkplug topic: {e:?}");
+                            }
+                        };
+                    } else if let Ok(SparkplugTopic::HostApplication(host)) =
+                        SparkplugTopic::try_from(message.topic.as_str())
+                    {
+                        let Ok(payload) = Vec::<u8>::try_from(message.payload) else {
+                            continue;
+                        };
+                        let state = sparkplug_network
+                            .lock()
+                            .await
+                            .update_host_state(&host.host_id, &payload);
+
+                        if let Some(state) = state {
+                            if state.online {
+                                info!("Primary host {} is online", state.host_id);
+                            } else {
+                                warn!("Primary host {} is offline", state.host_id);
+                            }
+                            let _ = sender_message.send(MessageEvent::PrimaryHostState(state));
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped_messages)) => {
+                    channel::count_dropped(skipped_messages);
+                    warn!("Receiver skipped {skipped_messages} messages");
+                }
+                Err(RecvError::Closed) => break,
+                _ => {}
+            }
+        }
+
+        debug!("Sparkplug network monitor exited");
+    });
+}
+
+/// Alerts on the console and/or by posting to a webhook when the primary
+/// host application goes offline.
+pub fn start_primary_host_alert_task(
+    primary_host: SparkplugPrimaryHost,
+    mut receiver: Receiver<MessageEvent>,
+) {
+    debug!("Starting primary host alerts");
+
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+
+        loop {
+            match receiver.recv().await {
+                Ok(MessageEvent::PrimaryHostState(state)) if !state.online => {
+                    if *primary_host.alert_console() {
+                        let alert = format!(" Primary host {} is OFFLINE ", state.host_id);
+                        if let Err(e) =
+                            ConsoleOutput::output_string(alert.themed(Role::Alert).to_string())
+                        {
+                            error!("Error while printing the primary host alert: {e:?}");
+                        }
+                    }
+
+                    if let Some(webhook) = primary_host.webhook() {
+                        post_webhook(&client, webhook, &state).await;
+                    }
+                }
+                Err(RecvError::Lagged(skipped_messages)) => {
+                    channel::count_dropped(skipped_messages);
+                    warn!("Primary host alerts skipped {skipped_messages} messages");
+                }
+                Err(RecvError::Closed) => break,
+                _ => {}
+            }
+        }
+
+        debug!("Primary host alerts exited");
+    });
+}
+
+async fn post_webhook(client: &reqwest::Client, webhook: &str, state: &PrimaryHostState) {
+    let body = match serde_json::to_string(state) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Could not serialize the primary host state: {e:?}");
+            return;
+        }
+    };
+
+    let result = client
+        .post(webhook)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    if let Err(e) = result {
+        error!("Could not post the primary host alert to {webhook}: {e:?}");
+    }
+}
+
+pub fn start_snapshot_export_task(
+    sparkplug_network: Arc<Mutex<SparkplugNetwork>>,
+    export: SparkplugSnapshotExport,
+    sender_message: Sender<MessageEvent>,
+    mut receiver_exit: Receiver<()>,
+) {
+    debug!("Starting sparkplug snapshot export");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(*export.interval());
+
+        loop {
+            select! {
+                _ = interval.tick() => {
+                    export_snapshot(&sparkplug_network, &export, &sender_message).await;
+                },
+                _ = receiver_exit.recv() => {
+                    export_snapshot(&sparkplug_network, &export, &sender_message).await;
+                    break;
+                }
+            }
+        }
+
+        debug!("Sparkplug snapshot export exited");
+    });
+}
+
+async fn export_snapshot(
+    sparkplug_network: &Arc<Mutex<SparkplugNetwork>>,
+    export: &SparkplugSnapshotExport,
+    sender_message: &Sender<MessageEvent>,
+) {
+    let snapshot = sparkplug_network.lock().await.snapshot();
+
+    let content = match snapshot.to_bytes(export.format()) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Could not serialize sparkplug snapshot: {e:?}");
+            return;
+        }
+    };
+
+    if let Some(path) = export.path() {
+        if let Err(e) = std::fs::write(path, &content) {
+            error!("Could not write sparkplug snapshot to {path:?}: {e:?}");
+        }
+    }
+
+    if let Some(topic) = export.topic() {
+        let message = MessagePublishData::new(topic.clone(), QoS::AtMostOnce, true, content);
+
+        if let Err(e) = sender_message.send(MessageEvent::Publish(message)) {
+            error!("Could not send sparkplug snapshot: {e:?}");
+        }
+    }
+}
+
+pub fn start_summary_task(
+    sparkplug_network: Arc<Mutex<SparkplugNetwork>>,
+    interval: Duration,
+    mut receiver_exit: Receiver<()>,
+) {
+    debug!("Starting sparkplug summary");
+
+    tokio::spawn(async move {
+        let mut previous = sparkplug_network.lock().await.summary();
+        let mut interval = tokio::time::interval(interval);
+
+        // the first tick completes immediately, but no rates can be computed yet
+        interval.tick().await;
+
+        loop {
+            select! {
+                _ = interval.tick() => {
+                    let summary = sparkplug_network.lock().await.summary();
+
+                    if let Err(e) = ConsoleOutput::output_string(format_summary(&summary, &previous)) {
+                        error!("Error while printing sparkplug summary: {e:?}");
+                    }
+
+                    previous = summary;
+                },
+                _ = receiver_exit.recv() => break,
+            }
+        }
+
+        debug!("Sparkplug summary exited");
+    });
+}
+
+fn format_summary(summary: &SparkplugSummary, previous: &SparkplugSummary) -> String {
+    let mut result: Vec<String> = vec![];
+
+    result.push(
+        format!(
+            " Sparkplug summary ({}) ",
+            summary.created_at.format("%H:%M:%S")
+        )
+        .themed(Role::Title)
+        .to_string(),
+    );
+
+    if summary.groups.is_empty() {
+        result.push("No groups seen yet".themed(Role::Text).to_string());
+    }
+
+    for (group_id, group) in &summary.groups {
+        result.push(format!(
+            "{} {}/{} edge nodes online, {}/{} devices online, last seen {}",
+            format!("Group \"{}\":", group_id).themed(Role::Name),
+            group.online_edge_nodes,
+            group.edge_nodes,
+            group.online_devices,
+            group.devices,
+            group.last_seen.map_or("never".to_string(), |last_seen| {
+                last_seen.format("%H:%M:%S").to_string()
+            })
+        ));
+
+        let rates = summary.message_rates(group_id, previous);
+
+        for (message_type, count) in &group.messages {
+            result.push(
+                format!(
+                    "    - {message_type}: {:.2} msg/s ({count} total)",
+                    rates.get(message_type).copied().unwrap_or_default()
+                )
+                .themed(Role::Text)
+                .to_string(),
+            );
+        }
+    }
+
+    result.join("\n")
+}
+
+pub fn start_sparkplug_emulator(
+    emulator: SparkplugEmulator,
+    sender_message: Sender<MessageEvent>,
+    mut receiver: Receiver<MessageEvent>,
+    mut receiver_exit: Receiver<()>,
+) {
+    debug!("Starting sparkplug edge node emulator");
+
+    tokio::spawn(async move {
+        let mut emulator = EdgeNodeEmulator::new(emulator);
+        let mut interval = tokio::time::interval(*emulator.config().interval());
+
+        // the first tick completes immediately, the birth message is sent instead
+        interval.tick().await;
+        publish_sparkplug_message(emulator.birth(), &sender_message);
+
+        loop {
+            select! {
+                _ = interval.tick() => {
+                    publish_sparkplug_message(emulator.data(), &sender_message);
+                },
+                message = receiver.recv() => {
+                    match message {
+                        Ok(MessageEvent::ReceivedUnfiltered(message)) => {
+                            if let PayloadFormat::Sparkplug(payload) = message.payload {
+                                let is_rebirth_request = SparkplugTopic::try_from(message.topic)
+                                    .is_ok_and(|topic| emulator.is_rebirth_request(&topic, &payload));
+
+                                if is_rebirth_request {
+                                    info!("Rebirth requested, publishing birth message");
+                                    publish_sparkplug_message(emulator.birth(), &sender_message);
+                                    interval.reset();
+                                }
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped_messages)) => {
+                            channel::count_dropped(skipped_messages);
+                            warn!("Receiver skipped {skipped_messages} messages");
+                        }
+                        Err(RecvError::Closed) => break,
+                        _ => {}
+                    }
+                },
+                _ = receiver_exit.recv() => {
+                    // the broker only publishes the last will if the connection is lost
+                    publish_sparkplug_message(emulator.death_certificate(), &sender_message);
+                    break;
+                }
+            }
+        }
+
+        debug!("Sparkplug edge node emulator exited");
+    });
+}
+
+fn request_rebirth(topic: &SparkplugTopicEdgeNode, sender_message: &Sender<MessageEvent>) {
+    let message = create_rebirth_request(topic);
+
+    info!("Requesting rebirth of edge node via {}", message.0);
+
+    publish_sparkplug_message(message, sender_message);
+}
+
+fn publish_sparkplug_message(
+    (topic, payload): (SparkplugTopic, PayloadFormatSparkplug),
+    sender_message: &Sender<MessageEvent>,
+) {
+    match Vec::<u8>::try_from(payload) {
+        Ok(payload) => {
+            let message =
+                MessagePublishData::new(topic.to_string(), QoS::AtMostOnce, false, payload);
+
+            if let Err(e) = sender_message.send(MessageEvent::Publish(message)) {
+                error!("Could not send sparkplug message: {e:?}");
+            }
+        }
+        Err(e) => {
+            error!("Could not encode sparkplug message for topic {topic}: {e:?}");
+        }
+    }
+}
+
+fn output_sparkplug_message(
+    message: &PayloadFormatSparkplug,
+    topic: &SparkplugTopic,
+    (qos, retain): (QoS, bool),
+    topic_storage: Arc<TopicStorage>,
+    output_context: &OutputContext,
+) {
+    let topic_name = topic.to_string();
+    let outputs = topic_storage.get_outputs_for_topic(topic_name.as_str());
+
+    let content: String = match topic {
+        SparkplugTopic::EdgeNode(topic) => match topic.message_type {
+            SparkplugMessageType::NBIRTH => format_nbirth(message, topic),
+            SparkplugMessageType::NDATA => format_ndata(message, topic),
+            SparkplugMessageType::NDEATH => format_ndeath(message, topic),
+            SparkplugMessageType::DBIRTH => format_dbirth(message, topic),
+            SparkplugMessageType::DDATA => format_ddata(message, topic),
+            SparkplugMessageType::DDEATH => format_ddeath(message, topic),
+            SparkplugMessageType::NCMD => {
+                let mut result: Vec<String> = vec![];
+                result.push(format!(
+                    "Command arrived from edge node \"{}\"",
+                    topic.edge_node_id
+                ));
+
+                result
+            }
+            SparkplugMessageType::DCMD => {
+                let mut result: Vec<String> = vec![];
+                result.push(format!(
+                    "Command arrived from device \"{}\"",
+                    topic.device_id.as_ref().unwrap_or(&"unknown".to_string())
+                ));
+
+                result
+            }
+            _ => vec![],
+        },
+        SparkplugTopic::HostApplication(topic) => match topic.message_type {
+            SparkplugMessageType::STATE => {
+                let mut result: Vec<String> = vec![];
+                result.push(format!(
+                    "State arrived from host application \"{}\"",
+                    topic.host_id
+                ));
+
+                result
+            }
+            _ => vec![],
+        },
+    }
+    .join("\n");
+
+    for output in outputs {
+        if let Err(e) =
+            match output.target() {
+                OutputTarget::Console(_options) => ConsoleOutput::output_string(content.clone()),
+                OutputTarget::File(file) if file.per_message => output_context
+                    .file
+                    .output_per_message(&topic_name, content.clone().into_bytes(), "txt", file),
+                OutputTarget::File(file) => output_context.file.output(
+                    &FileMessage {
+                        topic: &topic_name,
+                        qos,
+                        retain,
+                    },
+                    content.clone().into_bytes(),
+                    file,
+                ),
+                _ => Ok(()),
+            }
+        {
+            error!("Error while printing sparkplug message: {e:?}");
+        }
+    }
+}
+
+fn format_ddata(message: &PayloadFormatSparkplug, topic: &SparkplugTopicEdgeNode) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    let content = format!(
+        "[{}] {}/{}/{} (seq {})",
+        topic.message_type.to_string().themed(Role::Info),
+        topic.group_id.themed(Role::Highlight),
+        topic.edge_node_id.themed(Role::Accent),
+        topic
+            .device_id
+            .as_ref()
+            .unwrap_or(&"unknown".to_string())
+            .themed(Role::Info),
+        message
+            .content
+            .seq
+            .unwrap_or(999)
+            .to_string()
+            .themed(Role::Text)
+    )
+    .themed(Role::Banner);
+
+    result.push(content.to_string());
+    result.extend(add_metrics(&message.content.metrics, 0));
+
+    result
+}
+
+fn format_nbirth(message: &PayloadFormatSparkplug, topic: &SparkplugTopicEdgeNode) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    let content = format!(
+        "[{}] Edge node {}/{} joined the network (seq {})",
+        topic.message_type.to_string().themed(Role::Accent),
+        topic.group_id.themed(Role::Highlight),
+        topic.edge_node_id.themed(Role::Accent),
+        message
+            .content
+            .seq
+            .unwrap_or(999)
+            .to_string()
+            .themed(Role::Text)
+    )
+    .themed(Role::BannerNode);
+
+    result.push(content.to_string());
+    result.extend(add_metrics(&message.content.metrics, 0));
+
+    result
+}
+
+fn format_ndata(message: &PayloadFormatSparkplug, topic: &SparkplugTopicEdgeNode) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    let content = format!(
+        "[{}] {}/{} (seq {})",
+        topic.message_type.to_string().themed(Role::Accent),
+        topic.group_id.themed(Role::Highlight),
+        topic.edge_node_id.themed(Role::Accent),
+        message
+            .content
+            .seq
+            .unwrap_or(999)
+            .to_string()
+            .themed(Role::Text)
+    )
+    .themed(Role::BannerNode);
+
+    result.push(content.to_string());
+    result.extend(add_metrics(&message.content.metrics, 0));
+
+    result
+}
+
+fn format_ndeath(message: &PayloadFormatSparkplug, topic: &SparkplugTopicEdgeNode) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    let bd_seq = message
+        .content
+        .metrics
+        .iter()
+        .filter(|m| m.name.is_some())
+        .find(|m| m.name.as_ref().unwrap() == "bdSeq");
+
+    let content = format!(
+        "[{}] Edge node {}/{} left the network (seq {})",
+        topic.message_type.to_string().themed(Role::Accent),
+        topic.group_id.themed(Role::Highlight),
+        topic.edge_node_id.themed(Role::Accent),
+        message
+            .content
+            .seq
+            .unwrap_or(999)
+            .to_string()
+            .themed(Role::Text)
+    )
+    .themed(Role::BannerNode);
+
+    result.push(content.to_string());
+
+    match bd_seq {
+        None => {
+            warn!("NDEATH message did not have a bdSeq metric");
+        }
+        Some(bd_seq) if !bd_seq.has_long_value() => {
+            warn!("bdSeq metric is not a long integer value");
+        }
+        Some(bd_seq) => {
+            result.push(
+                format!("bdSeq number: {}", bd_seq.int_value())
+                    .themed(Role::Added)
+                    .to_string(),
+            );
+        }
+    }
+
+    result
+}
+
+fn format_dbirth(message: &PayloadFormatSparkplug, topic: &SparkplugTopicEdgeNode) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    let content = format!(
+        "[{}] Device node {}/{}/{} joined the network (seq {})",
+        topic.message_type.to_string().themed(Role::Info),
+        topic.group_id.themed(Role::Highlight),
+        topic.edge_node_id.themed(Role::Accent),
+        topic
+            .device_id
+            .as_ref()
+            .unwrap_or(&"unknown".to_string())
+            .themed(Role::Info),
+        message
+            .content
+            .seq
+            .unwrap_or(999)
+            .to_string()
+            .themed(Role::Text)
+    )
+    .themed(Role::Banner);
+
+    result.push(content.to_string());
+    result.extend(add_metrics(&message.content.metrics, 0));
+
+    result
+}
+
+fn format_ddeath(message: &PayloadFormatSparkplug, topic: &SparkplugTopicEdgeNode) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    let content = format!(
+        "[{}] Device {}/{}/{} died (seq {})",
+        topic.message_type.to_string().themed(Role::Info),
+        topic.group_id.themed(Role::Highlight),
+        topic.edge_node_id.themed(Role::Accent),
+        topic
+            .device_id
+            .as_ref()
+            .unwrap_or(&"unknown".to_string())
+            .themed(Role::Info),
+        message
+            .content
+            .seq
+            .unwrap_or(999)
+            .to_string()
+            .themed(Role::Text)
+    )
+    .themed(Role::Banner);
+
+    result.push(content.to_string());
+
+    result
+}
+
+fn add_metrics(metrics: &Vec<Metric>, depth: usize) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    let indent = "    ".repeat(depth);
+
+    for metric in metrics {
+        let mut nested: Vec<String> = vec![];
+
+        let value = if metric.is_null() {
+            "null".to_string()
+        } else {
+            match &metric.value {
+                None => "unknown".to_string(),
+                Some(value) => match value {
+                    Value::IntValue(value) => format!("{}", value),
+                    Value::LongValue(value) => format!("{}", value),
+                    Value::FloatValue(value) => format!("{}", value),
+                    Value::DoubleValue(value) => format!("{}", value),
+                    Value::BooleanValue(value) => format!("{}", value),
+                    Value::StringValue(value) => value.clone(),
+                    Value::BytesValue(value) => {
+                        format!("{}", String::from_utf8_lossy(value.as_ref()))
+                    }
+                    Value::DatasetValue(value) => format!("{}", value),
+                    Value::TemplateValue(value) => {
+                        nested = add_template(value, depth + 1);
+                        describe_template(value)
+                    }
+                    Value::ExtensionValue(value) => format!("{}", value),
+                    &_ => "".to_string(),
+                },
+            }
+        };
+
+        let data = format!(
+            "{indent}- [{}{}{}] {} = {}",
+            metric.timestamp.map_or("unknown".to_string(), |t| {
+                if let Some(utc) = DateTime::from_timestamp_millis(t as i64) {
+                    return utc.format("%H:%M:%S%.3f").to_string();
+                }
+                "unknown".to_string()
+            }),
+            if metric.is_historical() {
+                ", historical".themed(Role::Removed).to_string()
+            } else {
+                "".to_string()
+            },
+            if metric.is_transient() {
+                ", transient".themed(Role::Removed).to_string()
+            } else {
+                "".to_string()
+            },
+            metric
+                .name
+                .clone()
+                .unwrap_or("unknown".to_string())
+                .themed(Role::Name),
+            value,
+        );
+        result.push(data.themed(Role::Text).to_string());
+        result.extend(nested);
+    }
+
+    result
+}
+
+fn describe_template(template: &Template) -> String {
+    let kind = if template.is_definition() {
+        "Template definition".to_string()
+    } else {
+        format!(
+            "Template instance of {}",
+            template
+                .template_ref
+                .as_deref()
+                .unwrap_or("unknown")
+                .themed(Role::Highlight)
+        )
+    };
+
+    match &template.version {
+        None => kind,
+        Some(version) => format!("{kind} (version {version})"),
+    }
+}
+
+/// Lists the parameters of a template followed by its metrics.
+fn add_template(template: &Template, depth: usize) -> Vec<String> {
+    let indent = "    ".repeat(depth);
+
+    let mut result: Vec<String> = template
+        .parameters
+        .iter()
+        .map(|parameter| {
+            format!(
+                "{indent}* parameter {} = {}",
+                parameter
+                    .name
+                    .as_deref()
+                    .unwrap_or("unknown")
+                    .themed(Role::Key),
+                parameter_value_to_json(parameter)
+            )
+            .themed(Role::Text)
+            .to_string()
+        })
+        .collect();
+
+    result.extend(add_metrics(&template.metrics, depth));
+
+    result
+}
@@ -0,0 +1,197 @@
+use crate::channel;
+use crate::mqtt::MessageEvent;
+use crate::output::console::ConsoleOutput;
+use crate::output::statistics::{MessageStatistics, TopicRate, TopicStatistics};
+use crate::output::theme::{Role, Themed};
+use crate::storage::queue::SqlWriteQueue;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+/// Counts the received messages and prints the statistics of each topic in
+/// the given interval. A final summary is printed on exit; await the returned
+/// handle to make sure it is printed before the application ends.
+pub fn start_statistics_task(
+    mut receiver: Receiver<MessageEvent>,
+    interval: Duration,
+    sql_queue: Option<SqlWriteQueue>,
+    mut receiver_exit: Receiver<()>,
+) -> JoinHandle<()> {
+    debug!("Starting message statistics");
+
+    tokio::spawn(async move {
+        let mut statistics = MessageStatistics::default();
+        let mut previous = (statistics.started_at, statistics.topics.clone());
+        let mut interval = tokio::time::interval(interval);
+
+        // the first tick completes immediately, but no rates can be computed yet
+        interval.tick().await;
+
+        loop {
+            select! {
+                _ = interval.tick() => {
+                    let now = Utc::now();
+                    let elapsed = (now - previous.0).num_milliseconds() as f64 / 1000.0;
+
+                    let mut content = format_statistics(
+                        &statistics,
+                        &previous.1,
+                        elapsed,
+                        channel::dropped_messages(),
+                    );
+                    if let Some(sql_queue) = &sql_queue {
+                        content.push('\n');
+                        content.push_str(&format_sql_queue(sql_queue));
+                    }
+
+                    print_statistics(content);
+
+                    previous = (now, statistics.topics.clone());
+                },
+                message = receiver.recv() => {
+                    match message {
+                        Ok(MessageEvent::ReceivedUnfiltered(message)) => {
+                            let size = Vec::<u8>::try_from(message.payload)
+                                .map(|payload| payload.len())
+                                .unwrap_or_default();
+
+                            statistics.count_message(&message.topic, size);
+                        }
+                        Err(RecvError::Lagged(skipped_messages)) => {
+                            channel::count_dropped(skipped_messages);
+                            warn!("Statistics skipped {skipped_messages} messages");
+                        }
+                        Err(RecvError::Closed) => break,
+                        _ => {}
+                    }
+                },
+                _ = receiver_exit.recv() => break,
+            }
+        }
+
+        let mut content = format_final_summary(&statistics, channel::dropped_messages());
+        if let Some(sql_queue) = &sql_queue {
+            content.push('\n');
+            content.push_str(&format_sql_queue(sql_queue));
+        }
+
+        print_statistics(content);
+
+        debug!("Message statistics exited");
+    })
+}
+
+/// Prints the number of messages and bytes published on each topic.
+pub fn print_publish_summary(published: &MessageStatistics) {
+    print_statistics(format_summary("Publish summary", published));
+}
+
+fn print_statistics(content: String) {
+    if let Err(e) = ConsoleOutput::output_string(content) {
+        error!("Error while printing message statistics: {e:?}");
+    }
+}
+
+fn format_statistics(
+    statistics: &MessageStatistics,
+    previous: &BTreeMap<String, TopicStatistics>,
+    elapsed: f64,
+    dropped: u64,
+) -> String {
+    let mut result: Vec<String> = vec![];
+
+    result.push(
+        format!(" Message statistics ({}) ", Utc::now().format("%H:%M:%S"))
+            .themed(Role::Title)
+            .to_string(),
+    );
+
+    if statistics.topics.is_empty() {
+        result.push("No messages received yet".themed(Role::Text).to_string());
+    }
+
+    for (topic, topic_statistics) in &statistics.topics {
+        result.push(format_topic(
+            topic,
+            topic_statistics,
+            &topic_statistics.rate(previous.get(topic), elapsed),
+        ));
+    }
+
+    result.extend(format_dropped(dropped));
+
+    result.join("\n")
+}
+
+fn format_final_summary(statistics: &MessageStatistics, dropped: u64) -> String {
+    let mut result = format_summary("Message summary", statistics);
+
+    if let Some(dropped) = format_dropped(dropped) {
+        result.push('\n');
+        result.push_str(&dropped);
+    }
+
+    result
+}
+
+/// Returns the totals and average rates of each topic since the start.
+fn format_summary(title: &str, statistics: &MessageStatistics) -> String {
+    let mut result: Vec<String> = vec![];
+    let elapsed = (Utc::now() - statistics.started_at).num_milliseconds() as f64 / 1000.0;
+
+    result.push(
+        format!(" {title} ({elapsed:.1} s) ")
+            .themed(Role::Title)
+            .to_string(),
+    );
+
+    for (topic, topic_statistics) in &statistics.topics {
+        result.push(format_topic(
+            topic,
+            topic_statistics,
+            &topic_statistics.rate(None, elapsed),
+        ));
+    }
+
+    let total = statistics.total();
+    result.push(format_topic("Total", &total, &total.rate(None, elapsed)));
+
+    result.join("\n")
+}
+
+/// Returns the number of messages dropped by slow receivers, if any.
+fn format_dropped(dropped: u64) -> Option<String> {
+    (dropped > 0).then(|| format!("{} {dropped} messages", "Dropped:".themed(Role::Removed)))
+}
+
+/// Returns the writes waiting in, buffered by and dropped from the SQL
+/// write queue.
+fn format_sql_queue(sql_queue: &SqlWriteQueue) -> String {
+    let buffered = sql_queue
+        .buffered()
+        .map(|buffered| format!(", {buffered} buffered writes"))
+        .unwrap_or_default();
+
+    format!(
+        "{} {} pending writes{buffered}, {} dropped writes",
+        "SQL queue:".themed(Role::Name),
+        sql_queue.depth(),
+        sql_queue.dropped()
+    )
+}
+
+fn format_topic(topic: &str, statistics: &TopicStatistics, rate: &TopicRate) -> String {
+    format!(
+        "{} {} messages ({:.2} msg/s), {} bytes ({:.1} B/s)",
+        format!("{topic}:").themed(Role::Name),
+        statistics.messages,
+        rate.messages,
+        statistics.bytes,
+        rate.bytes,
+    )
+}
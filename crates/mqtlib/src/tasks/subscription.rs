@@ -1,5 +1,6 @@
-use mqtlib::config::subscription::Subscription;
-use mqtlib::mqtt::{MqttReceiveEvent, MqttService};
+use crate::channel;
+use crate::config::subscription::Subscription;
+use crate::mqtt::{MqttReceiveEvent, MqttService};
 use rumqttc::v5::Incoming;
 use rumqttc::Incoming as IncomingV311;
 use std::sync::Arc;
@@ -15,7 +16,7 @@ pub fn start_subscription_task(
     let mut receiver_connect = sender.subscribe();
 
     tokio::spawn(async move {
-        while let Ok(event) = receiver_connect.recv().await {
+        while let Some(event) = channel::recv(&mut receiver_connect, "Subscriber").await {
             match event {
                 MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(Incoming::ConnAck(_)))
                 | MqttReceiveEvent::V311(rumqttc::Event::Incoming(IncomingV311::ConnAck(_))) => {
@@ -0,0 +1,86 @@
+use crate::channel;
+use crate::mqtt::MqttReceiveEvent;
+use crate::systemd;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tracing::{debug, warn};
+
+/// Notifies systemd once the client is connected and the broker acknowledged
+/// the given number of subscriptions, and when shutting down. Sends watchdog
+/// keepalives if the watchdog is enabled for the service.
+pub fn start_systemd_task(
+    subscriptions: usize,
+    mut receiver: Receiver<MqttReceiveEvent>,
+    mut receiver_exit: Receiver<()>,
+) {
+    debug!("Starting systemd notifications");
+
+    if let Some(interval) = systemd::watchdog_interval() {
+        start_watchdog_task(interval, receiver_exit.resubscribe());
+    }
+
+    tokio::spawn(async move {
+        // subscriptions acknowledged since the last connect
+        let mut acknowledged: Option<usize> = None;
+
+        loop {
+            select! {
+                event = receiver.recv() => match event {
+                    Ok(event) if event.is_connect() => {
+                        acknowledged = Some(0);
+                        if subscriptions == 0 {
+                            systemd::ready("Connected to broker");
+                        } else {
+                            systemd::status("Connected to broker, subscribing");
+                        }
+                    }
+                    Ok(event) if event.is_subscribe_ack() => {
+                        if let Some(count) = acknowledged.as_mut() {
+                            *count += 1;
+                            if *count == subscriptions {
+                                systemd::ready(&format!(
+                                    "Connected to broker, subscribed to {subscriptions} topics"
+                                ));
+                            }
+                        }
+                    }
+                    Ok(event) if event.is_disconnect() => {
+                        acknowledged = None;
+                        systemd::status("Disconnected from broker");
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped_events)) => {
+                        channel::count_dropped(skipped_events);
+                        warn!("Systemd notifications skipped {skipped_events} events");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = receiver_exit.recv() => {
+                    systemd::stopping();
+                    break;
+                }
+            }
+        }
+
+        debug!("Systemd notifications exited");
+    });
+}
+
+/// Sends keepalives twice per watchdog interval, as recommended by
+/// `sd_watchdog_enabled(3)`, until the exit signal is received.
+fn start_watchdog_task(interval: Duration, mut receiver_exit: Receiver<()>) {
+    debug!("Sending systemd watchdog keepalives every {interval:?}");
+
+    tokio::spawn(async move {
+        let mut keepalive = tokio::time::interval(interval / 2);
+
+        loop {
+            select! {
+                _ = keepalive.tick() => systemd::watchdog(),
+                _ = receiver_exit.recv() => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,156 @@
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Number of items queued per worker before dispatching waits.
+const WORKER_QUEUE_SIZE: usize = 64;
+
+/// Items dispatched to any worker pool and not yet processed.
+static PENDING_ITEMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of items queued or being processed by the workers of
+/// all pools, e.g. to wait until they are processed on shutdown.
+pub fn pending_items() -> usize {
+    PENDING_ITEMS.load(Ordering::Relaxed)
+}
+
+/// Receiver of the items of a worker. An item counts as processed once the
+/// worker asks for the next one or drops the receiver.
+pub struct WorkerReceiver<T> {
+    receiver: mpsc::Receiver<T>,
+    processing: bool,
+}
+
+impl<T> WorkerReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        self.finish_item();
+
+        let item = self.receiver.recv().await;
+        self.processing = item.is_some();
+        item
+    }
+
+    fn finish_item(&mut self) {
+        if std::mem::take(&mut self.processing) {
+            PENDING_ITEMS.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Drop for WorkerReceiver<T> {
+    fn drop(&mut self) {
+        self.finish_item();
+
+        // items left in the queue are never processed
+        self.receiver.close();
+        while self.receiver.try_recv().is_ok() {
+            PENDING_ITEMS.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Processes items concurrently in a fixed number of tasks. Items with the
+/// same key, e.g. the topic of a message, are always processed by the same
+/// worker, so their order is preserved.
+pub struct WorkerPool<T> {
+    senders: Vec<mpsc::Sender<T>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Spawns the workers, each one running the future returned by the given
+    /// function with the receiver of its items. The receivers are closed when
+    /// the pool is dropped.
+    pub fn start<F, Fut>(size: usize, worker: F) -> Self
+    where
+        F: Fn(WorkerReceiver<T>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let senders = (0..size.max(1))
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel(WORKER_QUEUE_SIZE);
+                tokio::spawn(worker(WorkerReceiver {
+                    receiver,
+                    processing: false,
+                }));
+                sender
+            })
+            .collect();
+
+        Self { senders }
+    }
+
+    /// Queues the item for the worker responsible for the key.
+    pub async fn dispatch(&self, key: &str, item: T) {
+        PENDING_ITEMS.fetch_add(1, Ordering::Relaxed);
+
+        if self.senders[self.worker_index(key)]
+            .send(item)
+            .await
+            .is_err()
+        {
+            PENDING_ITEMS.fetch_sub(1, Ordering::Relaxed);
+            warn!("Worker for {key} exited, dropping item");
+        }
+    }
+
+    fn worker_index(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        (hasher.finish() % self.senders.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn preserve_order_per_key() {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<(String, usize)>();
+
+        let pool = WorkerPool::start(4, move |mut items: WorkerReceiver<(String, usize)>| {
+            let sender = sender.clone();
+            async move {
+                while let Some(item) = items.recv().await {
+                    sender.send(item).unwrap();
+                }
+            }
+        });
+
+        for index in 0..100 {
+            let key = format!("topic/{}", index % 5);
+            pool.dispatch(&key, (key.clone(), index)).await;
+        }
+        drop(pool);
+
+        let mut received: Vec<(String, usize)> = vec![];
+        while let Some(item) = receiver.recv().await {
+            received.push(item);
+        }
+
+        assert_eq!(100, received.len());
+        for key in 0..5 {
+            let indices: Vec<usize> = received
+                .iter()
+                .filter(|(topic, _)| *topic == format!("topic/{key}"))
+                .map(|(_, index)| *index)
+                .collect();
+
+            assert_eq!((key..100).step_by(5).collect::<Vec<usize>>(), indices);
+        }
+    }
+
+    #[test]
+    fn same_worker_for_key() {
+        let pool = WorkerPool::<()> {
+            senders: (0..3).map(|_| mpsc::channel(1).0).collect(),
+        };
+
+        assert_eq!(pool.worker_index("a/b"), pool.worker_index("a/b"));
+        assert!(pool.worker_index("c/d") < 3);
+    }
+}
@@ -0,0 +1,88 @@
+//! Templates of topics, file names, headers, emails, alerts and metric values
+//! contain placeholders of the form `{{name}}`, which are expanded for every
+//! message. Whitespace around the name is ignored, e.g. `{{ $.id }}`. Each
+//! template has placeholders of its own; these are supported by all of them:
+//!
+//! - `{{timestamp}}`: current time in milliseconds since the epoch
+//! - `{{timestamp_iso}}`: current time in UTC as RFC 3339 with milliseconds
+//! - `{{uuid}}`: random UUID
+
+use std::borrow::Cow;
+
+use chrono::{SecondsFormat, Utc};
+use uuid::Uuid;
+
+/// Expands the placeholders of the template with the given function, which
+/// returns none for placeholders it doesn't know; these are kept as they
+/// are. A `{{` without closing `}}` is kept as well.
+pub fn render<'t>(
+    template: &'t str,
+    mut expand: impl FnMut(&str) -> Option<String>,
+) -> Cow<'t, str> {
+    if !template.contains("{{") {
+        return Cow::Borrowed(template);
+    }
+
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+
+        result.push_str(&rest[..start]);
+        match expand(rest[start + 2..end].trim()) {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&rest[start..end + 2]),
+        }
+
+        rest = &rest[end + 2..];
+    }
+
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
+/// Expands the placeholders supported by all templates, see the module
+/// documentation.
+pub fn common(name: &str) -> Option<String> {
+    match name {
+        "timestamp" => Some(Utc::now().timestamp_millis().to_string()),
+        "timestamp_iso" => Some(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        "uuid" => Some(Uuid::new_v4().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_placeholders() {
+        let rendered = render("a/{{ name }}/{{unknown}}/{{timestamp}}", |name| {
+            (name == "name")
+                .then(|| "b".to_string())
+                .or_else(|| common(name))
+        });
+
+        let (rendered, timestamp) = rendered.rsplit_once('/').unwrap();
+        assert_eq!("a/b/{{unknown}}", rendered);
+        assert!(timestamp.parse::<i64>().is_ok());
+    }
+
+    #[test]
+    fn render_without_placeholders() {
+        assert!(matches!(render("a/b", common), Cow::Borrowed("a/b")));
+        assert_eq!("a/{{b", render("a/{{b", common));
+    }
+
+    #[test]
+    fn values_are_not_expanded() {
+        assert_eq!(
+            "{{uuid}}",
+            render("{{payload}}", |_| Some("{{uuid}}".to_string()))
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! In-process MQTT broker for end-to-end tests of subscribe, publish,
+//! trigger and output flows without an external broker. Requires the
+//! `embedded-broker` feature.
+
+use crate::config::mqtli_config::{MqttBrokerConnect, MqttVersion};
+use rumqttd::{Broker, Config};
+use serde_json::json;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::error;
+
+/// Maximum time to wait until the broker accepts connections.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum EmbeddedBrokerError {
+    #[error("Could not find a free port for the embedded broker")]
+    NoFreePort(#[source] io::Error),
+    #[error("Invalid configuration of the embedded broker")]
+    InvalidConfig(#[source] serde_json::Error),
+    #[error("Embedded broker did not accept connections within {0:?}")]
+    NotStarted(Duration),
+}
+
+/// MQTT broker running in a background thread, listening on free local
+/// ports for MQTT 3.1.1 and 5. Messages are routed between both versions.
+/// The broker can't be stopped, it runs until the process exits.
+#[derive(Clone, Debug)]
+pub struct EmbeddedBroker {
+    port_v311: u16,
+    port_v5: u16,
+}
+
+impl EmbeddedBroker {
+    /// Starts the broker and waits until it accepts connections.
+    pub fn start() -> Result<Self, EmbeddedBrokerError> {
+        let port_v311 = free_port()?;
+        let port_v5 = free_port()?;
+
+        let server = |name: &str, port: u16| {
+            json!({
+                "name": name,
+                "listen": SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+                "next_connection_delay_ms": 1,
+                "connections": {
+                    "connection_timeout_ms": 60000,
+                    "max_payload_size": 1048576,
+                    "max_inflight_count": 100,
+                    "dynamic_filters": true
+                }
+            })
+        };
+
+        let config: Config = serde_json::from_value(json!({
+            "id": 0,
+            "router": {
+                "max_connections": 1000,
+                "max_outgoing_packet_count": 200,
+                "max_segment_size": 104857600,
+                "max_segment_count": 10
+            },
+            "v4": { "v311": server("v311", port_v311) },
+            "v5": { "v5": server("v5", port_v5) }
+        }))
+        .map_err(EmbeddedBrokerError::InvalidConfig)?;
+
+        thread::spawn(move || {
+            if let Err(e) = Broker::new(config).start() {
+                error!("Embedded broker stopped: {e:?}");
+            }
+        });
+
+        let broker = Self { port_v311, port_v5 };
+        broker.wait_until_started()?;
+
+        Ok(broker)
+    }
+
+    pub fn host(&self) -> String {
+        Ipv4Addr::LOCALHOST.to_string()
+    }
+
+    /// Port of the listener for the given MQTT version.
+    pub fn port(&self, mqtt_version: &MqttVersion) -> u16 {
+        match mqtt_version {
+            MqttVersion::V311 => self.port_v311,
+            MqttVersion::V5 => self.port_v5,
+        }
+    }
+
+    /// Connection settings for this broker using the given MQTT version.
+    pub fn broker_config(&self, mqtt_version: MqttVersion) -> MqttBrokerConnect {
+        MqttBrokerConnect {
+            host: self.host(),
+            port: self.port(&mqtt_version),
+            mqtt_version,
+            ..MqttBrokerConnect::default()
+        }
+    }
+
+    fn wait_until_started(&self) -> Result<(), EmbeddedBrokerError> {
+        let started_at = Instant::now();
+
+        for port in [self.port_v311, self.port_v5] {
+            while TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_err() {
+                if started_at.elapsed() >= START_TIMEOUT {
+                    return Err(EmbeddedBrokerError::NotStarted(START_TIMEOUT));
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns a port which is free right now by letting the operating system
+/// pick one.
+fn free_port() -> Result<u16, EmbeddedBrokerError> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .map_err(EmbeddedBrokerError::NoFreePort)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::mqtli_config::MqtliConfig;
+    use crate::config::topic::{Topic, TopicStorage};
+    use crate::Mqtlib;
+    use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS};
+    use tokio::select;
+    use tokio::sync::oneshot;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn republishes_received_messages() {
+        let broker = EmbeddedBroker::start().unwrap();
+
+        let topic: Topic = serde_yaml::from_str(
+            r#"
+topic: sensors/#
+subscription:
+  enabled: true
+  outputs:
+    - format:
+        type: json
+      target:
+        type: topic
+        topic: copy
+"#,
+        )
+        .unwrap();
+        let config = MqtliConfig {
+            broker: broker.broker_config(MqttVersion::V5),
+            topic_storage: TopicStorage {
+                topics: vec![topic],
+            },
+            ..MqtliConfig::default()
+        };
+
+        let (sender_shutdown, receiver_shutdown) = oneshot::channel::<()>();
+        let handle = tokio::spawn(Mqtlib::new(config).run_until(async {
+            let _ = receiver_shutdown.await;
+        }));
+
+        let options =
+            MqttOptions::new("mqtli-test", broker.host(), broker.port(&MqttVersion::V311));
+        let (client, mut event_loop) = AsyncClient::new(options, 10);
+        client.subscribe("copy", QoS::AtMostOnce).await.unwrap();
+
+        // publish until mqtli subscribed and republished the message
+        let received = tokio::time::timeout(Duration::from_secs(10), async {
+            let mut interval = tokio::time::interval(Duration::from_millis(200));
+            loop {
+                select! {
+                    _ = interval.tick() => {
+                        let _ = client.try_publish(
+                            "sensors/1",
+                            QoS::AtMostOnce,
+                            false,
+                            "{\"value\": 21.5}",
+                        );
+                    }
+                    event = event_loop.poll() => {
+                        if let Ok(Event::Incoming(Incoming::Publish(publish))) = event {
+                            if publish.topic == "copy" {
+                                return publish.payload.to_vec();
+                            }
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(b"{\"value\":21.5}".to_vec(), received);
+
+        let _ = sender_shutdown.send(());
+        handle.await.unwrap().unwrap();
+    }
+}
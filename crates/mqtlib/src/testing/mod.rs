@@ -0,0 +1,321 @@
+//! Helpers to test configurations of topics, filters and outputs without a
+//! broker. [`MockMqttService`] takes the place of the MQTT client: messages
+//! are injected with the [`MockBroker`] and publishes are captured by it.
+//!
+//! ```no_run
+//! # async fn example(config: mqtlib::config::mqtli_config::MqtliConfig) {
+//! use mqtlib::mqtt::QoS;
+//! use mqtlib::testing::MockMqttService;
+//! use mqtlib::Mqtlib;
+//! use std::time::Duration;
+//!
+//! let (service, broker) = MockMqttService::new();
+//! let mqtlib = Mqtlib::new(config).with_mqtt_service(service.into_shared());
+//! tokio::spawn(mqtlib.run_until(std::future::pending()));
+//!
+//! broker.wait_for_subscription("sensors/#", Duration::from_secs(1)).await;
+//! broker.inject_publish("sensors/1", QoS::AtMostOnce, false, "21.5");
+//! let published = broker.wait_for_published(1, Duration::from_secs(1)).await;
+//! # }
+//! ```
+
+#[cfg(feature = "embedded-broker")]
+pub mod broker;
+
+use crate::mqtt::{MessagePublishData, MqttReceiveEvent, MqttService, MqttServiceError, QoS};
+use crate::output::statistics::MessageStatistics;
+use async_trait::async_trait;
+use rumqttc::v5::mqttbytes::v5::{ConnAck, ConnectReturnCode, Publish};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// Time between two checks while waiting for subscriptions or publishes.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Default)]
+struct MockState {
+    published: Mutex<Vec<MessagePublishData>>,
+    subscriptions: Mutex<Vec<(String, QoS)>>,
+    statistics: Mutex<MessageStatistics>,
+}
+
+/// MQTT service which doesn't connect to a broker. It acknowledges the
+/// connection with MQTT v5 after the connect delay, forwards the events
+/// injected by its [`MockBroker`] and records subscriptions and publishes.
+pub struct MockMqttService {
+    state: Arc<MockState>,
+    /// Injected events, None closes the connection
+    receiver: Option<mpsc::UnboundedReceiver<Option<MqttReceiveEvent>>>,
+    connect_delay: Duration,
+}
+
+impl MockMqttService {
+    /// Creates the service and the broker handle to inject events and
+    /// inspect what was published.
+    pub fn new() -> (Self, MockBroker) {
+        let state = Arc::new(MockState::default());
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        (
+            Self {
+                state: state.clone(),
+                receiver: Some(receiver),
+                connect_delay: Duration::from_millis(50),
+            },
+            MockBroker { state, sender },
+        )
+    }
+
+    /// Sets the time until the connection is acknowledged, which gives the
+    /// tasks waiting for the acknowledgement time to start (default: 50 ms).
+    pub fn with_connect_delay(mut self, connect_delay: Duration) -> Self {
+        self.connect_delay = connect_delay;
+        self
+    }
+
+    /// Wraps the service to pass it to [`crate::Mqtlib::with_mqtt_service`].
+    pub fn into_shared(self) -> Arc<tokio::sync::Mutex<dyn MqttService>> {
+        Arc::new(tokio::sync::Mutex::new(self))
+    }
+}
+
+#[async_trait]
+impl MqttService for MockMqttService {
+    async fn connect(
+        &mut self,
+        channel: broadcast::Sender<MqttReceiveEvent>,
+        mut receiver_exit: Receiver<()>,
+    ) -> Result<JoinHandle<()>, MqttServiceError> {
+        let receiver = self.receiver.take();
+        let connect_delay = self.connect_delay;
+
+        Ok(tokio::spawn(async move {
+            select! {
+                _ = tokio::time::sleep(connect_delay) => {
+                    let _ = channel.send(connack());
+                }
+                _ = receiver_exit.recv() => return,
+            }
+
+            // the injected events can only be received by the first
+            // connection
+            let Some(mut receiver) = receiver else {
+                let _ = receiver_exit.recv().await;
+                return;
+            };
+
+            loop {
+                select! {
+                    event = receiver.recv() => match event {
+                        Some(Some(event)) => {
+                            let _ = channel.send(event);
+                        }
+                        Some(None) | None => break,
+                    },
+                    _ = receiver_exit.recv() => break,
+                }
+            }
+        }))
+    }
+
+    async fn disconnect(&self) -> Result<(), MqttServiceError> {
+        Ok(())
+    }
+
+    async fn publish(&self, payload: MessagePublishData) {
+        self.state
+            .statistics
+            .lock()
+            .unwrap()
+            .count_message(&payload.topic, payload.payload.len());
+        self.state.published.lock().unwrap().push(payload);
+    }
+
+    async fn subscribe(&mut self, topic: String, qos: QoS) -> Result<(), MqttServiceError> {
+        self.state.subscriptions.lock().unwrap().push((topic, qos));
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, topic: String) -> Result<(), MqttServiceError> {
+        self.state
+            .subscriptions
+            .lock()
+            .unwrap()
+            .retain(|(subscribed, _)| *subscribed != topic);
+        Ok(())
+    }
+
+    fn pending_publishes(&self) -> usize {
+        0
+    }
+
+    fn granted_qos_failed(&self) -> bool {
+        false
+    }
+
+    fn connection_failed(&self) -> bool {
+        false
+    }
+
+    fn published(&self) -> MessageStatistics {
+        self.state.statistics.lock().unwrap().clone()
+    }
+}
+
+/// Handle of a [`MockMqttService`] playing the part of the broker.
+#[derive(Clone, Debug)]
+pub struct MockBroker {
+    state: Arc<MockState>,
+    sender: mpsc::UnboundedSender<Option<MqttReceiveEvent>>,
+}
+
+impl MockBroker {
+    /// Passes the event to the service as if it was received from the
+    /// broker. Events injected before connecting are queued.
+    pub fn inject(&self, event: MqttReceiveEvent) {
+        let _ = self.sender.send(Some(event));
+    }
+
+    /// Injects a message received on the topic.
+    pub fn inject_publish(&self, topic: &str, qos: QoS, retain: bool, payload: impl Into<Vec<u8>>) {
+        self.inject(MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(
+            rumqttc::v5::Incoming::Publish(Publish {
+                dup: false,
+                qos: qos.into(),
+                retain,
+                topic: topic.as_bytes().to_vec().into(),
+                pkid: 0,
+                payload: payload.into().into(),
+                properties: None,
+            }),
+        )));
+    }
+
+    /// Closes the connection as if the broker went away.
+    pub fn close(&self) {
+        let _ = self.sender.send(None);
+    }
+
+    /// All messages published by the service so far.
+    pub fn published(&self) -> Vec<MessagePublishData> {
+        self.state.published.lock().unwrap().clone()
+    }
+
+    /// Topics the service is currently subscribed to.
+    pub fn subscriptions(&self) -> Vec<(String, QoS)> {
+        self.state.subscriptions.lock().unwrap().clone()
+    }
+
+    /// Waits until the service subscribed to the topic. Returns false if
+    /// the timeout elapsed before.
+    pub async fn wait_for_subscription(&self, topic: &str, timeout: Duration) -> bool {
+        self.wait_until(timeout, || {
+            self.subscriptions()
+                .iter()
+                .any(|(subscribed, _)| subscribed == topic)
+        })
+        .await
+    }
+
+    /// Waits until at least the given number of messages was published and
+    /// returns all published messages, which may be fewer if the timeout
+    /// elapsed.
+    pub async fn wait_for_published(
+        &self,
+        count: usize,
+        timeout: Duration,
+    ) -> Vec<MessagePublishData> {
+        self.wait_until(timeout, || {
+            self.state.published.lock().unwrap().len() >= count
+        })
+        .await;
+        self.published()
+    }
+
+    async fn wait_until(&self, timeout: Duration, condition: impl Fn() -> bool) -> bool {
+        let started_at = Instant::now();
+
+        loop {
+            if condition() {
+                return true;
+            }
+            if started_at.elapsed() >= timeout {
+                return false;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+fn connack() -> MqttReceiveEvent {
+    MqttReceiveEvent::V5(rumqttc::v5::Event::Incoming(
+        rumqttc::v5::Incoming::ConnAck(ConnAck {
+            session_present: false,
+            code: ConnectReturnCode::Success,
+            properties: None,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::mqtli_config::MqtliConfig;
+    use crate::config::topic::{Topic, TopicStorage};
+    use crate::Mqtlib;
+    use tokio::sync::oneshot;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn republishes_received_messages() {
+        let topic: Topic = serde_yaml::from_str(
+            r#"
+topic: sensors/#
+subscription:
+  enabled: true
+  outputs:
+    - format:
+        type: json
+      target:
+        type: topic
+        topic: copy
+"#,
+        )
+        .unwrap();
+        let config = MqtliConfig {
+            topic_storage: TopicStorage {
+                topics: vec![topic],
+            },
+            ..MqtliConfig::default()
+        };
+
+        let (service, broker) = MockMqttService::new();
+        let (sender_shutdown, receiver_shutdown) = oneshot::channel::<()>();
+        let handle = tokio::spawn(
+            Mqtlib::new(config)
+                .with_mqtt_service(service.into_shared())
+                .run_until(async {
+                    let _ = receiver_shutdown.await;
+                }),
+        );
+
+        assert!(
+            broker
+                .wait_for_subscription("sensors/#", Duration::from_secs(5))
+                .await
+        );
+        broker.inject_publish("sensors/1", QoS::AtMostOnce, false, "{\"value\": 21.5}");
+        broker.inject_publish("other", QoS::AtMostOnce, false, "ignored");
+
+        let published = broker.wait_for_published(1, Duration::from_secs(5)).await;
+        assert_eq!(1, published.len());
+        assert_eq!("copy", published[0].topic);
+        assert_eq!(b"{\"value\":21.5}".to_vec(), published[0].payload);
+
+        let _ = sender_shutdown.send(());
+        handle.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+use crate::config::topic_stats::TopicStats;
+use crate::mqtt::{MqttReceiveEvent, MqttService};
+use crate::MqtlibError;
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{info, warn};
+
+/// Level of the topic tree holding the number of messages and bytes received
+/// on its topic and all topics below.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TopicNode {
+    pub messages: u64,
+    pub bytes: u64,
+    pub children: BTreeMap<String, TopicNode>,
+}
+
+impl TopicNode {
+    /// Counts a message on the topic in this node and all levels of the
+    /// topic below it.
+    pub fn add(&mut self, topic: &str, bytes: usize) {
+        let mut node = self;
+        node.count(bytes);
+
+        for level in topic.split('/') {
+            node = node.children.entry(level.to_string()).or_default();
+            node.count(bytes);
+        }
+    }
+
+    fn count(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+
+    /// Children ordered by message count, the noisiest first.
+    fn sorted_children(&self) -> Vec<(&String, &TopicNode)> {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by(|(_, a), (_, b)| b.messages.cmp(&a.messages));
+        children
+    }
+}
+
+/// Topic tree collected by [`run_topic_stats`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TopicStatsResult {
+    pub root: TopicNode,
+    /// Time messages were collected, used to calculate the rates
+    pub elapsed: Duration,
+    /// Number of levels printed
+    pub depth: Option<usize>,
+}
+
+impl TopicStatsResult {
+    fn write_node(
+        &self,
+        f: &mut Formatter<'_>,
+        name: &str,
+        node: &TopicNode,
+        level: usize,
+    ) -> std::fmt::Result {
+        let seconds = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        let name = if name.is_empty() { "<empty>" } else { name };
+
+        write!(
+            f,
+            "\n{:indent$}{} {} messages ({:.1}/s), {} ({}/s)",
+            "",
+            name.bold(),
+            node.messages,
+            node.messages as f64 / seconds,
+            format_bytes(node.bytes as f64),
+            format_bytes(node.bytes as f64 / seconds),
+            indent = level * 2
+        )?;
+
+        if self.depth.is_some_and(|depth| level + 1 >= depth) {
+            return Ok(());
+        }
+
+        for (name, child) in node.sorted_children() {
+            self.write_node(f, name, child, level + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TopicStatsResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", " Topic statistics ".on_blue())?;
+        write!(
+            f,
+            "{} messages, {} in {:.1} s",
+            self.root.messages,
+            format_bytes(self.root.bytes as f64),
+            self.elapsed.as_secs_f64()
+        )?;
+
+        for (name, child) in self.root.sorted_children() {
+            self.write_node(f, name, child, 0)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Subscribes to the topic pattern and counts the received messages per
+/// topic until the configured duration elapsed or the shutdown future
+/// completes.
+pub async fn run_topic_stats<F>(
+    mqtt_service: Arc<Mutex<dyn MqttService>>,
+    stats: &TopicStats,
+    capacity: usize,
+    shutdown: F,
+) -> Result<TopicStatsResult, MqtlibError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (sender_receive, mut receiver) = broadcast::channel::<MqttReceiveEvent>(capacity);
+    let (sender_disconnect, _) = broadcast::channel::<()>(1);
+    let mut result = TopicStatsResult {
+        depth: *stats.depth(),
+        ..Default::default()
+    };
+
+    let mqtt_loop_handle = mqtt_service
+        .lock()
+        .await
+        .connect(sender_receive, sender_disconnect.subscribe())
+        .await?;
+
+    tokio::pin!(shutdown);
+
+    let connected = loop {
+        select! {
+            _ = &mut shutdown => break false,
+            event = receiver.recv() => match event {
+                Ok(event) if event.is_connect() => break true,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(MqtlibError::Disconnected),
+            }
+        }
+    };
+
+    if connected {
+        mqtt_service
+            .lock()
+            .await
+            .subscribe(stats.pattern().clone(), *stats.qos())
+            .await?;
+        info!(
+            "Collecting messages on {} for {} seconds",
+            stats.pattern(),
+            stats.duration().as_secs()
+        );
+
+        let started_at = Instant::now();
+        let deadline = tokio::time::sleep(*stats.duration());
+        tokio::pin!(deadline);
+
+        loop {
+            select! {
+                _ = &mut deadline => break,
+                _ = &mut shutdown => break,
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        if let Some((topic, _, _, payload)) = event.incoming_publish() {
+                            result.root.add(&topic, payload.len());
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Statistics lagged behind, {count} events were not counted");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+        result.elapsed = started_at.elapsed();
+
+        let _ = mqtt_service
+            .lock()
+            .await
+            .unsubscribe(stats.pattern().clone())
+            .await;
+    }
+
+    let _ = sender_disconnect.send(());
+    let _ = mqtt_loop_handle.await;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_tree() {
+        let mut root = TopicNode::default();
+        root.add("sensors/1/temperature", 4);
+        root.add("sensors/1/humidity", 2);
+        root.add("sensors/2/temperature", 4);
+        root.add("status", 10);
+
+        assert_eq!(4, root.messages);
+        assert_eq!(20, root.bytes);
+
+        let sensors = &root.children["sensors"];
+        assert_eq!(3, sensors.messages);
+        assert_eq!(10, sensors.bytes);
+        assert_eq!(2, sensors.children["1"].messages);
+        assert_eq!(6, sensors.children["1"].bytes);
+        assert_eq!(
+            vec!["sensors", "status"],
+            root.sorted_children()
+                .into_iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!("512 B", format_bytes(512.0));
+        assert_eq!("1.5 KiB", format_bytes(1536.0));
+        assert_eq!("2.0 MiB", format_bytes(2.0 * 1024.0 * 1024.0));
+    }
+}
@@ -1,13 +1,21 @@
+use crate::args::parsers::deserialize_duration_milliseconds;
 use crate::args::parsers::deserialize_duration_seconds;
+use crate::args::parsers::deserialize_key_value_map;
 use crate::args::parsers::deserialize_qos_option;
+use crate::args::parsers::parse_duration_milliseconds;
 use crate::args::parsers::parse_duration_seconds;
+use crate::args::parsers::parse_key_value;
 use crate::args::parsers::parse_qos;
 use crate::args::ArgsError;
 use clap::{Args, ValueEnum};
 use derive_getters::Getters;
 use mqtlib::config::mqtli_config::{
-    LastWillConfig, LastWillConfigBuilder, MqttBrokerConnect, MqttBrokerConnectBuilder,
+    BirthConfig, BirthConfigBuilder, ChaosConfig, ChaosConfigBuilder, LastWillConfig,
+    LastWillConfigBuilder, MqttBrokerConnect, MqttBrokerConnectBuilder, StatusConfig,
+    StatusConfigBuilder,
 };
+use mqtlib::config::secret::{resolve_secret, KeyringEntry};
+use mqtlib::config::PayloadType;
 use mqtlib::mqtt::QoS;
 use serde::Deserialize;
 use std::path::PathBuf;
@@ -64,6 +72,53 @@ pub struct MqttBrokerConnectArgs {
     )]
     pub mqtt_version: Option<MqttVersion>,
 
+    #[arg(
+        long = "outgoing-queue",
+        env = "BROKER_OUTGOING_QUEUE",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Path to a SQLite database keeping QoS 1 and 2 publishes until the broker acknowledged them; messages left from a previous run are published after connecting (default: empty)"
+    )]
+    pub outgoing_queue: Option<PathBuf>,
+
+    #[arg(
+        long = "receive-maximum",
+        env = "BROKER_RECEIVE_MAXIMUM",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Maximum number of unacknowledged QoS 1 and 2 messages the broker may send to mqtli at once; MQTT v5 only (default: 65535)"
+    )]
+    pub receive_maximum: Option<u16>,
+
+    #[arg(
+        long = "max-packet-size",
+        env = "BROKER_MAXIMUM_PACKET_SIZE",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Maximum size in bytes of packets sent to or received from the broker (default: 10240 bytes with MQTT v3.1.1, unlimited with MQTT v5)"
+    )]
+    pub maximum_packet_size: Option<u32>,
+
+    #[arg(
+        long = "inflight",
+        env = "BROKER_INFLIGHT",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Maximum number of QoS 1 and 2 messages published by mqtli which may be unacknowledged at once (default: 100)"
+    )]
+    pub inflight: Option<u16>,
+
+    #[arg(
+        long = "strict-qos",
+        env = "BROKER_STRICT_QOS",
+        global = true,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help_heading = "Broker",
+        help = "Exit with an error if the broker grants a lower QoS than requested for a subscription or rejects it, instead of only warning (default: false)"
+    )]
+    pub strict_qos: Option<bool>,
+
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_duration_seconds")]
     #[arg(
@@ -77,6 +132,27 @@ pub struct MqttBrokerConnectArgs {
     )]
     pub keep_alive: Option<Duration>,
 
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_seconds")]
+    #[arg(
+        long = "connect-timeout",
+        env = "BROKER_CONNECT_TIMEOUT",
+        value_parser = parse_duration_seconds,
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Exit with an error if the broker didn't acknowledge the first connect within this time in seconds (default: wait indefinitely)"
+    )]
+    pub connect_timeout: Option<Duration>,
+
+    #[arg(
+        long = "max-reconnect-attempts",
+        env = "BROKER_MAX_RECONNECT_ATTEMPTS",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Reconnect at most this often after the connection failed or was lost, then exit with an error (default: exit when the connection is lost)"
+    )]
+    pub max_reconnect_attempts: Option<u32>,
+
     #[arg(
         short = 'u',
         long = "username",
@@ -97,6 +173,24 @@ pub struct MqttBrokerConnectArgs {
     )]
     pub password: Option<String>,
 
+    #[arg(
+        long = "password-cmd",
+        env = "BROKER_PASSWORD_CMD",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Command whose output is used as password, e.g. \"pass show mqtt/broker\" (default: empty)"
+    )]
+    pub password_cmd: Option<String>,
+
+    #[arg(
+        long = "password-keyring",
+        env = "BROKER_PASSWORD_KEYRING",
+        global = true,
+        help_heading = "Broker",
+        help = "(optional) Entry in the keyring of the operating system holding the password, given as service:user (default: empty)"
+    )]
+    pub password_keyring: Option<KeyringEntry>,
+
     #[arg(
         long = "use-tls",
         env = "BROKER_USE_TLS",
@@ -129,10 +223,37 @@ pub struct MqttBrokerConnectArgs {
         env = "BROKER_TLS_CLIENT_KEY_FILE",
         global = true,
         help_heading = "TLS",
-        help = "(optional) Path to a PKCS#8 encoded, unencrypted client private key for authenticating against the broker; must be specified with client-cert (default: empty)"
+        help = "(optional) Path to a PKCS#8 encoded client private key for authenticating against the broker, or a pkcs11: URI of a key on a token; must be specified with client-cert (default: empty)"
     )]
     pub tls_client_key: Option<PathBuf>,
 
+    #[arg(
+        long = "client-key-passphrase",
+        env = "BROKER_TLS_CLIENT_KEY_PASSPHRASE",
+        global = true,
+        help_heading = "TLS",
+        help = "(optional) Passphrase to decrypt an encrypted PKCS#8 client key (default: empty)"
+    )]
+    pub tls_client_key_passphrase: Option<String>,
+
+    #[arg(
+        long = "client-key-passphrase-cmd",
+        env = "BROKER_TLS_CLIENT_KEY_PASSPHRASE_CMD",
+        global = true,
+        help_heading = "TLS",
+        help = "(optional) Command whose output is used as passphrase of the client key (default: empty)"
+    )]
+    pub tls_client_key_passphrase_cmd: Option<String>,
+
+    #[arg(
+        long = "client-key-passphrase-keyring",
+        env = "BROKER_TLS_CLIENT_KEY_PASSPHRASE_KEYRING",
+        global = true,
+        help_heading = "TLS",
+        help = "(optional) Entry in the keyring of the operating system holding the passphrase of the client key, given as service:user (default: empty)"
+    )]
+    pub tls_client_key_passphrase_keyring: Option<KeyringEntry>,
+
     #[arg(
         long = "tls-version",
         env = "BROKER_TLS_VERSION",
@@ -144,6 +265,15 @@ pub struct MqttBrokerConnectArgs {
 
     #[command(flatten)]
     pub last_will: Option<LastWillConfigArgs>,
+
+    #[command(flatten)]
+    pub birth: Option<BirthConfigArgs>,
+
+    #[command(flatten)]
+    pub status: Option<StatusConfigArgs>,
+
+    #[command(flatten)]
+    pub chaos: Option<ChaosConfigArgs>,
 }
 
 impl MqttBrokerConnectArgs {
@@ -180,15 +310,25 @@ impl MqttBrokerConnectArgs {
             None => other.keep_alive,
         });
 
+        builder.connect_timeout(self.connect_timeout.or(other.connect_timeout));
+        builder
+            .max_reconnect_attempts(self.max_reconnect_attempts.or(other.max_reconnect_attempts));
+
         builder.username(match &self.username {
             Some(username) => Some(username.to_string()),
             None => other.username,
         });
 
-        builder.password(match &self.password {
-            Some(password) => Some(password.to_string()),
-            None => other.password,
-        });
+        builder.password(
+            match resolve_secret(
+                self.password,
+                self.password_cmd.as_deref(),
+                self.password_keyring.as_ref(),
+            )? {
+                Some(password) => Some(password),
+                None => other.password,
+            },
+        );
 
         builder.use_tls(match self.use_tls {
             Some(use_tls) => use_tls,
@@ -210,6 +350,17 @@ impl MqttBrokerConnectArgs {
             None => other.tls_client_key,
         });
 
+        builder.tls_client_key_passphrase(
+            match resolve_secret(
+                self.tls_client_key_passphrase,
+                self.tls_client_key_passphrase_cmd.as_deref(),
+                self.tls_client_key_passphrase_keyring.as_ref(),
+            )? {
+                Some(passphrase) => Some(passphrase),
+                None => other.tls_client_key_passphrase,
+            },
+        );
+
         builder.tls_version(match &self.tls_version {
             Some(tls_version) => tls_version.into(),
             None => other.tls_version,
@@ -226,6 +377,34 @@ impl MqttBrokerConnectArgs {
             None => other.last_will,
         });
 
+        builder.outgoing_queue(match self.outgoing_queue {
+            Some(outgoing_queue) => Some(outgoing_queue),
+            None => other.outgoing_queue,
+        });
+
+        builder.receive_maximum(self.receive_maximum.or(other.receive_maximum));
+        builder.maximum_packet_size(self.maximum_packet_size.or(other.maximum_packet_size));
+        builder.inflight(self.inflight.or(other.inflight));
+        builder.strict_qos(self.strict_qos.unwrap_or(other.strict_qos));
+
+        builder.birth(match self.birth {
+            Some(birth_args) => Some(birth_args.merge(other.birth.unwrap_or_default())?),
+            None => other.birth,
+        });
+
+        builder.status(match self.status {
+            Some(status_args) => Some(status_args.merge(other.status.unwrap_or_default())?),
+            None => other.status,
+        });
+
+        builder.chaos(match self.chaos {
+            Some(chaos_args) if chaos_args.enabled != Some(false) => {
+                Some(chaos_args.merge(other.chaos.unwrap_or_default())?)
+            }
+            Some(_) => None,
+            None => other.chaos,
+        });
+
         builder.build().map_err(ArgsError::from)
     }
 }
@@ -242,6 +421,26 @@ pub struct LastWillConfigArgs {
     )]
     pub payload: Option<String>,
 
+    #[arg(
+        id = "payload_file_lw",
+        long = "will-payload-file",
+        env = "BROKER_WILL_PAYLOAD_FILE",
+        global = true,
+        help_heading = "Last will",
+        help = "Read the payload of the will message from this file; ignored if a payload is given"
+    )]
+    pub payload_file: Option<PathBuf>,
+
+    #[arg(
+        id = "payload_format_lw",
+        long = "will-payload-format",
+        env = "BROKER_WILL_PAYLOAD_FORMAT",
+        global = true,
+        help_heading = "Last will",
+        help = "Format of the will payload, which is converted to the payload type of the will topic if it is configured (default: sent as it is)"
+    )]
+    pub payload_format: Option<PayloadType>,
+
     #[arg(
         id = "topic_lw",
         long = "will-topic",
@@ -274,6 +473,43 @@ pub struct LastWillConfigArgs {
         help = "If true, last will message will be retained, else not (default: false)"
     )]
     pub retain: Option<bool>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_seconds")]
+    #[arg(
+        id = "delay_interval_lw",
+        long = "will-delay-interval",
+        env = "BROKER_WILL_DELAY_INTERVAL",
+        value_parser = parse_duration_seconds,
+        global = true,
+        help_heading = "Last will",
+        help = "Seconds the broker waits after the connection is lost before publishing the will message; MQTT v5 only (default: 0)"
+    )]
+    pub delay_interval: Option<Duration>,
+
+    #[arg(
+        id = "content_type_lw",
+        long = "will-content-type",
+        env = "BROKER_WILL_CONTENT_TYPE",
+        global = true,
+        help_heading = "Last will",
+        help = "Content type of the will message; MQTT v5 only (default: none)"
+    )]
+    pub content_type: Option<String>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_key_value_map")]
+    #[arg(
+        id = "user_property_lw",
+        long = "will-user-property",
+        env = "BROKER_WILL_USER_PROPERTIES",
+        value_parser = parse_key_value,
+        value_delimiter = ',',
+        global = true,
+        help_heading = "Last will",
+        help = "User property of the will message as key=value; may be given multiple times; MQTT v5 only"
+    )]
+    pub user_properties: Vec<(String, String)>,
 }
 
 impl LastWillConfigArgs {
@@ -288,19 +524,217 @@ impl LastWillConfigArgs {
             Some(qos) => qos,
             None => other.qos,
         });
-        lw.payload(match self.payload {
-            Some(payload) => payload.into_bytes(),
-            None => other.payload,
+        lw.payload(match (self.payload, self.payload_file) {
+            (Some(payload), _) => payload.into_bytes(),
+            (None, Some(path)) => std::fs::read(&path)
+                .map_err(|e| ArgsError::CouldNotReadLastWillPayloadFile(e, path))?,
+            (None, None) => other.payload,
         });
+        lw.payload_format(self.payload_format.or(other.payload_format));
         lw.retain(match self.retain {
             Some(retain) => retain,
             None => other.retain,
         });
+        lw.delay_interval(self.delay_interval.or(other.delay_interval));
+        lw.content_type(self.content_type.or(other.content_type));
+        lw.user_properties(if self.user_properties.is_empty() {
+            other.user_properties
+        } else {
+            self.user_properties
+        });
 
         lw.build().map_err(ArgsError::from)
     }
 }
 
+#[derive(Args, Debug, Default, Deserialize, Getters)]
+pub struct BirthConfigArgs {
+    #[arg(
+        id = "payload_birth",
+        long = "birth-payload",
+        env = "BROKER_BIRTH_PAYLOAD",
+        global = true,
+        help_heading = "Birth",
+        help = "The UTF-8 encoded payload of the birth message (default: empty)"
+    )]
+    pub payload: Option<String>,
+
+    #[arg(
+        id = "topic_birth",
+        long = "birth-topic",
+        env = "BROKER_BIRTH_TOPIC",
+        global = true,
+        help_heading = "Birth",
+        help = "The topic where the birth message is published after each connect (default: empty)"
+    )]
+    pub topic: Option<String>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos_option")]
+    #[arg(
+        id = "qos_birth",
+        long = "birth-qos",
+        env = "BROKER_BIRTH_QOS",
+        global = true,
+        value_parser = parse_qos,
+        help_heading = "Birth",
+        help = "Quality of Service (default: 0) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+
+    #[arg(
+        id = "retain_birth",
+        long = "birth-retain",
+        env = "BROKER_BIRTH_RETAIN",
+        global = true,
+        help_heading = "Birth",
+        help = "If true, the birth message will be retained, else not (default: false)"
+    )]
+    pub retain: Option<bool>,
+}
+
+impl BirthConfigArgs {
+    fn merge(self, other: BirthConfig) -> Result<BirthConfig, ArgsError> {
+        let mut birth = BirthConfigBuilder::default();
+
+        birth.topic(self.topic.unwrap_or(other.topic));
+        birth.qos(self.qos.unwrap_or(other.qos));
+        birth.payload(match self.payload {
+            Some(payload) => payload.into_bytes(),
+            None => other.payload,
+        });
+        birth.retain(self.retain.unwrap_or(other.retain));
+
+        birth.build().map_err(ArgsError::from)
+    }
+}
+
+#[derive(Args, Debug, Default, Deserialize, Getters)]
+pub struct StatusConfigArgs {
+    #[arg(
+        id = "topic_status",
+        long = "status-topic",
+        env = "BROKER_STATUS_TOPIC",
+        global = true,
+        help_heading = "Status",
+        help = "The topic where connection events (connected, disconnected, reconnecting, subscribed) are published as JSON (default: empty)"
+    )]
+    pub topic: Option<String>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_qos_option")]
+    #[arg(
+        id = "qos_status",
+        long = "status-qos",
+        env = "BROKER_STATUS_QOS",
+        global = true,
+        value_parser = parse_qos,
+        help_heading = "Status",
+        help = "Quality of Service (default: 0) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+
+    #[arg(
+        id = "retain_status",
+        long = "status-retain",
+        env = "BROKER_STATUS_RETAIN",
+        global = true,
+        help_heading = "Status",
+        help = "If true, the status events will be retained, else not (default: false)"
+    )]
+    pub retain: Option<bool>,
+}
+
+impl StatusConfigArgs {
+    fn merge(self, other: StatusConfig) -> Result<StatusConfig, ArgsError> {
+        let mut status = StatusConfigBuilder::default();
+
+        status.topic(self.topic.unwrap_or(other.topic));
+        status.qos(self.qos.unwrap_or(other.qos));
+        status.retain(self.retain.unwrap_or(other.retain));
+
+        status.build().map_err(ArgsError::from)
+    }
+}
+
+#[derive(Args, Debug, Default, Deserialize, Getters)]
+pub struct ChaosConfigArgs {
+    #[serde(default)]
+    #[arg(
+        id = "enabled_chaos",
+        long = "chaos",
+        env = "BROKER_CHAOS",
+        global = true,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help_heading = "Chaos",
+        help = "Randomly drop the connection, delay publishes and duplicate QoS 1 publishes to test consuming applications (default: false)"
+    )]
+    pub enabled: Option<bool>,
+
+    #[arg(
+        long = "chaos-drop-connection",
+        env = "BROKER_CHAOS_DROP_CONNECTION",
+        global = true,
+        help_heading = "Chaos",
+        help = "Probability per second that the connection is dropped (default: 0.01)"
+    )]
+    pub drop_connection: Option<f64>,
+
+    #[arg(
+        long = "chaos-drop-publish",
+        env = "BROKER_CHAOS_DROP_PUBLISH",
+        global = true,
+        help_heading = "Chaos",
+        help = "Probability that a publish is dropped (default: 0)"
+    )]
+    pub drop_publish: Option<f64>,
+
+    #[arg(
+        long = "chaos-delay-publish",
+        env = "BROKER_CHAOS_DELAY_PUBLISH",
+        global = true,
+        help_heading = "Chaos",
+        help = "Probability that a publish is delayed (default: 0.1)"
+    )]
+    pub delay_publish: Option<f64>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_milliseconds")]
+    #[arg(
+        long = "chaos-max-delay",
+        env = "BROKER_CHAOS_MAX_DELAY",
+        value_parser = parse_duration_milliseconds,
+        global = true,
+        help_heading = "Chaos",
+        help = "Maximum delay of a delayed publish in milliseconds (default: 2000)"
+    )]
+    pub max_delay: Option<Duration>,
+
+    #[arg(
+        long = "chaos-duplicate",
+        env = "BROKER_CHAOS_DUPLICATE",
+        global = true,
+        help_heading = "Chaos",
+        help = "Probability that a QoS 1 publish is sent twice (default: 0.05)"
+    )]
+    pub duplicate: Option<f64>,
+}
+
+impl ChaosConfigArgs {
+    fn merge(self, other: ChaosConfig) -> Result<ChaosConfig, ArgsError> {
+        let mut chaos = ChaosConfigBuilder::default();
+
+        chaos.drop_connection(self.drop_connection.unwrap_or(other.drop_connection));
+        chaos.drop_publish(self.drop_publish.unwrap_or(other.drop_publish));
+        chaos.delay_publish(self.delay_publish.unwrap_or(other.delay_publish));
+        chaos.max_delay(self.max_delay.unwrap_or(other.max_delay));
+        chaos.duplicate(self.duplicate.unwrap_or(other.duplicate));
+
+        chaos.build().map_err(ArgsError::from)
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, ValueEnum)]
 pub enum TlsVersion {
     #[default]
@@ -0,0 +1,186 @@
+use crate::args::broker::{MqttBrokerConnectArgs, MqttVersion};
+use crate::args::parsers::parse_qos;
+use crate::args::ArgsError;
+use clap::Args;
+use mqtlib::config::bridge::{Bridge, BridgeRule};
+use mqtlib::config::PayloadType;
+use mqtlib::mqtt::QoS;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Bridge section of the config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct BridgeArgs {
+    #[serde(default)]
+    pub target: Option<MqttBrokerConnectArgs>,
+    #[serde(default)]
+    pub rules: Vec<BridgeRule>,
+}
+
+impl BridgeArgs {
+    pub fn merge(self, other: Bridge) -> Result<Bridge, ArgsError> {
+        Ok(Bridge {
+            target: match self.target {
+                Some(target) => target.merge(other.target)?,
+                None => other.target,
+            },
+            rules: other.rules.into_iter().chain(self.rules).collect(),
+        })
+    }
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct CommandBridge {
+    #[arg(
+        short = 't',
+        long = "topic",
+        env = "BRIDGE_TOPIC",
+        value_delimiter = ',',
+        help_heading = "Bridge",
+        help = "Topic pattern subscribed on the source broker whose messages are republished on the target broker; can be given multiple times"
+    )]
+    pub topics: Vec<String>,
+
+    #[arg(
+        short = 'q',
+        long = "qos",
+        env = "BRIDGE_QOS",
+        value_parser = parse_qos,
+        help_heading = "Bridge",
+        help = "Quality of Service of the subscriptions (default: 0) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+
+    #[arg(
+        long = "strip-prefix",
+        env = "BRIDGE_STRIP_PREFIX",
+        help_heading = "Bridge",
+        help = "Remove this prefix from the topic before republishing"
+    )]
+    pub strip_prefix: Option<String>,
+
+    #[arg(
+        long = "prefix",
+        env = "BRIDGE_PREFIX",
+        help_heading = "Bridge",
+        help = "Add this prefix to the topic before republishing"
+    )]
+    pub prefix: Option<String>,
+
+    #[arg(
+        short = 'y',
+        long = "topic-type",
+        env = "BRIDGE_TOPIC_TYPE",
+        help_heading = "Bridge",
+        help = "Payload type of the received messages (default: text)"
+    )]
+    pub topic_type: Option<PayloadType>,
+
+    #[arg(
+        long = "output-type",
+        env = "BRIDGE_OUTPUT_TYPE",
+        help_heading = "Bridge",
+        help = "Convert the payload to this type before republishing (default: unchanged)"
+    )]
+    pub output_type: Option<PayloadType>,
+
+    #[arg(
+        long = "target-host",
+        env = "BRIDGE_TARGET_HOST",
+        help_heading = "Bridge target",
+        help = "The ip address or hostname of the target broker (default: localhost)"
+    )]
+    pub target_host: Option<String>,
+
+    #[arg(
+        long = "target-port",
+        env = "BRIDGE_TARGET_PORT",
+        help_heading = "Bridge target",
+        help = "The port the target broker is listening on (default: 1883)"
+    )]
+    pub target_port: Option<u16>,
+
+    #[arg(
+        long = "target-id",
+        env = "BRIDGE_TARGET_CLIENT_ID",
+        help_heading = "Bridge target",
+        help = "The client id used on the target broker (default: mqtli-bridge)"
+    )]
+    pub target_client_id: Option<String>,
+
+    #[arg(
+        long = "target-mqtt-version",
+        env = "BRIDGE_TARGET_MQTT_VERSION",
+        help_heading = "Bridge target",
+        help = "The MQTT version to use on the target broker (v5 or v311, default: v5)"
+    )]
+    pub target_mqtt_version: Option<MqttVersion>,
+
+    #[arg(
+        long = "target-username",
+        env = "BRIDGE_TARGET_USERNAME",
+        help_heading = "Bridge target",
+        help = "(optional) Username used to authenticate against the target broker (default: empty)"
+    )]
+    pub target_username: Option<String>,
+
+    #[arg(
+        long = "target-password",
+        env = "BRIDGE_TARGET_PASSWORD",
+        help_heading = "Bridge target",
+        help = "(optional) Password used to authenticate against the target broker (default: empty)"
+    )]
+    pub target_password: Option<String>,
+
+    #[arg(
+        long = "target-use-tls",
+        env = "BRIDGE_TARGET_USE_TLS",
+        help_heading = "Bridge target",
+        help = "If specified, TLS is used to communicate with the target broker (default: false)"
+    )]
+    pub target_use_tls: Option<bool>,
+
+    #[arg(
+        long = "target-ca-file",
+        env = "BRIDGE_TARGET_TLS_CA_FILE",
+        help_heading = "Bridge target",
+        help = "Path to a PEM encoded ca certificate to verify the target broker's certificate (default: empty)"
+    )]
+    pub target_tls_ca_file: Option<PathBuf>,
+}
+
+impl CommandBridge {
+    /// Adds a rule for each topic and overrides the connection settings of
+    /// the target broker given on the command line.
+    pub fn merge(self, other: Bridge) -> Result<Bridge, ArgsError> {
+        let target = MqttBrokerConnectArgs {
+            host: self.target_host,
+            port: self.target_port,
+            client_id: self.target_client_id,
+            mqtt_version: self.target_mqtt_version,
+            username: self.target_username,
+            password: self.target_password,
+            use_tls: self.target_use_tls,
+            tls_ca_file: self.target_tls_ca_file,
+            ..MqttBrokerConnectArgs::default()
+        }
+        .merge(other.target)?;
+
+        let rules = self.topics.into_iter().map(|topic| BridgeRule {
+            topic,
+            qos: self.qos.unwrap_or_default(),
+            strip_prefix: self.strip_prefix.clone(),
+            prefix: self.prefix.clone(),
+            payload_type: self.topic_type.clone().unwrap_or_default(),
+            format: self.output_type.clone(),
+            ..BridgeRule::default()
+        });
+
+        let rules: Vec<BridgeRule> = other.rules.into_iter().chain(rules).collect();
+        if rules.is_empty() {
+            return Err(ArgsError::BridgeRulesMissing);
+        }
+
+        Ok(Bridge { target, rules })
+    }
+}
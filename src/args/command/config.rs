@@ -0,0 +1,16 @@
+use clap::{Args, Subcommand};
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandConfig {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ConfigCommand {
+    #[command(
+        name = "show",
+        about = "Print the effective configuration merged from command line, environment and config files as YAML, with secrets redacted"
+    )]
+    Show,
+}
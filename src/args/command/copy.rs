@@ -0,0 +1,119 @@
+use crate::args::parsers::parse_qos;
+use clap::Args;
+use mqtlib::config::PayloadType;
+use mqtlib::mqtt::QoS;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct CommandCopy {
+    #[arg(
+        long = "from",
+        env = "COPY_FROM",
+        help_heading = "Copy",
+        help = "Topic to subscribe, may contain wildcards"
+    )]
+    pub from: String,
+
+    #[arg(
+        long = "to",
+        env = "COPY_TO",
+        help_heading = "Copy",
+        help = "Topic the messages are republished on"
+    )]
+    pub to: String,
+
+    #[arg(
+        short = 'q',
+        long = "qos",
+        env = "COPY_QOS",
+        value_parser = parse_qos,
+        help_heading = "Copy",
+        help = "Quality of Service of the subscription and the republished messages (default: 1) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+
+    #[arg(
+        long = "retain",
+        env = "COPY_RETAIN",
+        help_heading = "Copy",
+        help = "If specified, the messages are republished with the retain flag"
+    )]
+    pub retain: bool,
+
+    #[arg(
+        long = "input-type",
+        env = "COPY_INPUT_TYPE",
+        help_heading = "Copy",
+        help = "Payload type of the messages on the source topic (default: raw)"
+    )]
+    pub input_type: Option<PayloadType>,
+
+    #[arg(
+        long = "output-type",
+        env = "COPY_OUTPUT_TYPE",
+        help_heading = "Copy",
+        help = "Payload type the messages are converted to before they are republished (default: input type)"
+    )]
+    pub output_type: Option<PayloadType>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::command::Command;
+    use crate::args::content::MqtliArgs;
+    use clap::Parser;
+    use mqtlib::config::subscription::OutputTarget;
+    use mqtlib::config::PayloadType;
+    use mqtlib::mqtt::QoS;
+
+    #[test]
+    fn copy() {
+        let args = [
+            "mqtli",
+            "copy",
+            "--from",
+            "sensors/+/json",
+            "--to",
+            "archive/yaml",
+            "--input-type",
+            "json",
+            "--output-type",
+            "yaml",
+        ];
+        let result = MqtliArgs::try_parse_from(args).unwrap();
+        let command = result.command.unwrap();
+
+        let topics = command.get_topics(None).unwrap();
+        assert_eq!(topics.len(), 1);
+        assert_eq!(topics[0].topic, "sensors/+/json");
+        assert_eq!(topics[0].payload_type, PayloadType::Json);
+
+        let subscription = topics[0].subscription.as_ref().unwrap();
+        assert_eq!(subscription.outputs.len(), 1);
+        assert_eq!(subscription.outputs[0].format, PayloadType::Yaml);
+        match &subscription.outputs[0].target {
+            OutputTarget::Topic(target) => {
+                assert_eq!(target.topic, "archive/yaml");
+                assert_eq!(target.qos, QoS::AtLeastOnce);
+                assert!(!target.retain);
+            }
+            target => panic!("unexpected output target {target:?}"),
+        }
+
+        assert!(matches!(command, Command::Copy(_)));
+    }
+
+    #[test]
+    fn loop_is_rejected() {
+        let args = [
+            "mqtli",
+            "copy",
+            "--from",
+            "sensors/#",
+            "--to",
+            "sensors/copy",
+        ];
+        let result = MqtliArgs::try_parse_from(args).unwrap();
+
+        assert!(result.command.unwrap().get_topics(None).is_err());
+    }
+}
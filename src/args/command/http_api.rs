@@ -0,0 +1,17 @@
+use clap::Args;
+use derive_getters::Getters;
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+#[derive(Args, Debug, Deserialize, Getters)]
+pub struct HttpApi {
+    #[arg(
+        long = "http-api-listen",
+        env = "HTTP_API_LISTEN",
+        global = true,
+        help_heading = "HTTP API",
+        help = "Address the HTTP API listens on for publish requests, e.g. 127.0.0.1:8080 (default: disabled)"
+    )]
+    #[serde(rename = "listen")]
+    pub listen: SocketAddr,
+}
@@ -0,0 +1,51 @@
+use clap::{Args, CommandFactory};
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandMan {
+    #[arg(
+        short = 'o',
+        long = "output-dir",
+        env = "MAN_OUTPUT_DIR",
+        default_value = ".",
+        help_heading = "Man",
+        help = "Directory the man pages are written to, one file per command (e.g. mqtli.1, mqtli-sub.1)"
+    )]
+    pub output_dir: PathBuf,
+}
+
+impl CommandMan {
+    /// Writes the man pages of the command line definition of C and all its
+    /// subcommands to the output directory.
+    pub fn generate<C: CommandFactory>(&self) -> Result<(), io::Error> {
+        std::fs::create_dir_all(&self.output_dir)?;
+
+        clap_mangen::generate_to(C::command(), &self.output_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::command::Command;
+    use crate::args::content::MqtliArgs;
+    use clap::Parser;
+
+    #[test]
+    fn man() {
+        let output_dir = std::env::temp_dir().join(format!("mqtli-man-{}", std::process::id()));
+        let args = ["mqtli", "man", "-o", output_dir.to_str().unwrap()];
+        let result = MqtliArgs::try_parse_from(args).unwrap();
+
+        let Some(Command::Man(command)) = result.command else {
+            panic!("man command expected");
+        };
+        command.generate::<MqtliArgs>().unwrap();
+
+        for page in ["mqtli.1", "mqtli-sub.1", "mqtli-config-show.1"] {
+            assert!(output_dir.join(page).is_file(), "{page} missing");
+        }
+
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+}
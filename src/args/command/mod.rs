@@ -1,6 +1,14 @@
+use crate::args::command::bridge::CommandBridge;
+use crate::args::command::config::CommandConfig;
+use crate::args::command::copy::CommandCopy;
+use crate::args::command::man::CommandMan;
+use crate::args::command::ping::CommandPing;
+use crate::args::command::proto::CommandProto;
 use crate::args::command::publish::CommandPublish;
+use crate::args::command::self_test::CommandSelfTest;
 use crate::args::command::sparkplug::CommandSparkplug;
 use crate::args::command::subscribe::{CommandSubscribe, OutputTarget as OutputTargetArgs};
+use crate::args::command::topics::CommandTopics;
 use crate::args::ArgsError;
 use clap::Subcommand;
 use mqtlib::config::filter::FilterTypes;
@@ -9,17 +17,32 @@ use mqtlib::config::subscription::{
     Output, OutputTarget, OutputTargetConsole, OutputTargetFile, OutputTargetTopic, Subscription,
     SubscriptionBuilder,
 };
-use mqtlib::config::topic::{Topic, TopicBuilder};
-use mqtlib::config::{PayloadType, PublishInputType, PublishInputTypeContentPath};
+use mqtlib::config::topic::{topic_matches, DecodeErrorPolicy, Topic, TopicBuilder};
+use mqtlib::config::{
+    DirectoryEnd, PayloadType, PublishInputType, PublishInputTypeContentPath,
+    PublishInputTypeMessages,
+};
 use mqtlib::mqtt::QoS;
+use mqtlib::publish::directory_tree::read_directory_tree;
+use mqtlib::publish::trigger_periodic::rate_interval;
 use mqtlib::sparkplug::{GroupId, SPARKPLUG_TOPIC_VERSION};
 use std::fmt::Display;
+use std::path::PathBuf;
 use std::time::Duration;
 
+pub mod bridge;
+pub mod config;
+pub mod copy;
+pub mod http_api;
+pub mod man;
+pub mod ping;
+pub mod proto;
 pub mod publish;
+pub mod self_test;
 pub mod sparkplug;
 pub mod sql_storage;
 pub mod subscribe;
+pub mod topics;
 
 #[derive(Clone, Debug, Subcommand)]
 pub enum Command {
@@ -29,96 +52,245 @@ pub enum Command {
     Subscribe(CommandSubscribe),
     #[command(name = "sparkplug", alias = "sp")]
     Sparkplug(CommandSparkplug),
+    /// Republish the messages of a topic on another topic, optionally converted to another payload type
+    #[command(name = "copy")]
+    Copy(CommandCopy),
+    /// Measure the connect time and the round trip time of messages
+    #[command(name = "ping")]
+    Ping(CommandPing),
+    /// Republish messages from the broker on a target broker
+    #[command(name = "bridge")]
+    Bridge(CommandBridge),
+    /// Inspect the topics of the broker
+    #[command(name = "topics")]
+    Topics(CommandTopics),
+    /// Check connection and credentials by publishing a message and receiving it again
+    #[command(name = "selftest")]
+    SelfTest(CommandSelfTest),
+    /// Inspect the configuration
+    #[command(name = "config")]
+    Config(CommandConfig),
+    /// Generate man pages for mqtli and all its subcommands
+    #[command(name = "man")]
+    Man(CommandMan),
+    /// Check the protobuf definitions referenced from the configuration
+    #[command(name = "proto")]
+    Proto(CommandProto),
 }
 
 impl Command {
-    pub(crate) fn get_topics(&self) -> Result<Vec<Topic>, crate::args::ArgsError> {
+    /// Topics of the command. The publish rate is used as interval of the
+    /// messages published by pub if no interval is given.
+    pub(crate) fn get_topics(
+        &self,
+        publish_rate: Option<f64>,
+    ) -> Result<Vec<Topic>, crate::args::ArgsError> {
         match self {
-            Command::Publish(config) => Command::get_topics_for_publish(config),
+            Command::Publish(config) => Command::get_topics_for_publish(config, publish_rate),
             Command::Subscribe(config) => Command::get_topics_for_subscribe(config),
             Command::Sparkplug(config) => Command::get_topics_for_sparkplug(config),
+            Command::Copy(config) => Command::get_topics_for_copy(config),
+            Command::Ping(_)
+            | Command::Bridge(_)
+            | Command::Topics(_)
+            | Command::SelfTest(_)
+            | Command::Config(_)
+            | Command::Man(_)
+            | Command::Proto(_) => Ok(vec![]),
         }
     }
 
     fn get_topics_for_publish(
         config: &CommandPublish,
+        publish_rate: Option<f64>,
     ) -> Result<Vec<Topic>, crate::args::ArgsError> {
         let mut result = Vec::new();
 
+        let interval = match (config.interval, publish_rate) {
+            (Some(interval), _) => interval,
+            (None, Some(rate)) => rate_interval(rate).ok_or(ArgsError::InvalidPublishRate(rate))?,
+            (None, None) => Duration::from_secs(1),
+        };
+
+        let contents: Vec<PublishInputTypeContentPath> = if config.message.null_message {
+            vec![PublishInputTypeContentPath::default()]
+        } else if !config.message.message.is_empty() {
+            config
+                .message
+                .message
+                .iter()
+                .map(|message| PublishInputTypeContentPath {
+                    content: Some(message.clone()),
+                    path: None,
+                })
+                .collect()
+        } else if !config.message.file.is_empty() {
+            config
+                .message
+                .file
+                .iter()
+                .map(|file| PublishInputTypeContentPath {
+                    content: None,
+                    path: Some(file.clone()),
+                })
+                .collect()
+        } else {
+            vec![PublishInputTypeContentPath::default()]
+        };
+
+        let mut inputs: Vec<PublishInputType> = contents
+            .into_iter()
+            .map(|content| Command::get_publish_input(config.message_type.as_ref(), content))
+            .collect::<Result<_, _>>()?;
+
+        // CSV and NDJSON files are published row by row until their end,
+        // other messages are published one after another
+        let streamed = matches!(
+            config.message_type,
+            Some(PublishInputType::Csv(_) | PublishInputType::Ndjson(_))
+        );
+        let count = if streamed {
+            config.count
+        } else {
+            Some(config.count.unwrap_or(1) * inputs.len() as u32)
+        };
+
+        let message_input_type = if inputs.len() == 1 {
+            inputs.remove(0)
+        } else {
+            PublishInputType::Messages(PublishInputTypeMessages::new(
+                inputs,
+                if streamed {
+                    DirectoryEnd::Stop
+                } else {
+                    DirectoryEnd::Loop
+                },
+            ))
+        };
+
         let trigger = PublishTriggerType::Periodic(PublishTriggerTypePeriodic::new(
-            config.interval.unwrap_or(Duration::from_secs(1)),
-            config.count.or(Some(1)),
+            interval,
+            count,
             Duration::from_millis(1000),
+            Duration::ZERO,
+            None,
+            None,
+            config.align_to,
         ));
 
-        let message_type = PublishInputTypeContentPath {
-            content: if config.message.null_message {
-                None
-            } else if config.message.message.is_some() {
-                Some(config.message.clone().message.unwrap().to_vec().clone())
-            } else {
-                None
-            },
-            path: if config.message.file.is_some() {
-                config.message.file.clone()
-            } else {
-                None
-            },
+        let topic_type = config
+            .topic_type
+            .clone()
+            .unwrap_or(PayloadType::Text(Default::default()));
+
+        let inputs = match &config.message.directory {
+            Some(directory) => read_directory_tree(directory, &config.topic)
+                .map_err(ArgsError::CouldNotReadPublishDirectory)?
+                .into_iter()
+                .map(|file| (file.topic, file.input))
+                .collect(),
+            None => vec![(config.topic.clone(), message_input_type)],
         };
 
-        let message_input_type = match &config.message_type {
-            None => PublishInputType::Text(message_type),
+        for (topic, input) in inputs {
+            let publish = PublishBuilder::default()
+                .qos(config.qos.unwrap_or(QoS::AtLeastOnce))
+                .retain(config.retain)
+                .enabled(true)
+                .trigger(vec![trigger.clone()])
+                .input(input)
+                .filters(FilterTypes::default())
+                .build()?;
+            let topic = TopicBuilder::default()
+                .topic(topic)
+                .publish(Some(publish))
+                .subscription(None)
+                .payload_type(topic_type.clone())
+                .on_decode_error(DecodeErrorPolicy::default())
+                .payload_overrides(vec![])
+                .connection(None)
+                .build()?;
+
+            result.push(topic);
+        }
+
+        Ok(result)
+    }
+
+    /// Input of a message or file given on the command line, read as the
+    /// given message type. Message types reading a file fail for messages.
+    fn get_publish_input(
+        message_type: Option<&PublishInputType>,
+        content: PublishInputTypeContentPath,
+    ) -> Result<PublishInputType, ArgsError> {
+        let input = match message_type {
+            None => PublishInputType::Text(content),
             Some(payload_type) => match payload_type {
-                PublishInputType::Text(_) => PublishInputType::Text(message_type),
-                PublishInputType::Raw(_) => PublishInputType::Raw(message_type.into()),
-                PublishInputType::Hex(_) => PublishInputType::Hex(message_type),
-                PublishInputType::Json(_) => PublishInputType::Json(message_type),
-                PublishInputType::Yaml(_) => PublishInputType::Yaml(message_type),
-                PublishInputType::Base64(_) => PublishInputType::Base64(message_type),
-                PublishInputType::Null => {
+                PublishInputType::Text(_) => PublishInputType::Text(content),
+                PublishInputType::Raw(_) => PublishInputType::Raw(content.into()),
+                PublishInputType::Hex(_) => PublishInputType::Hex(content),
+                PublishInputType::Json(_) => PublishInputType::Json(content),
+                PublishInputType::Yaml(_) => PublishInputType::Yaml(content),
+                PublishInputType::Base64(_) => PublishInputType::Base64(content),
+                PublishInputType::Directory(_) => {
+                    PublishInputType::Directory(Self::required_path(content, "directory")?.into())
+                }
+                PublishInputType::Csv(_) => {
+                    PublishInputType::Csv(Self::required_path(content, "csv")?.into())
+                }
+                PublishInputType::Ndjson(_) => {
+                    PublishInputType::Ndjson(Self::required_path(content, "ndjson")?.into())
+                }
+                PublishInputType::Http(_) => PublishInputType::Http(content.into()),
+                PublishInputType::Command(_) => PublishInputType::Command(content.into()),
+                PublishInputType::Env(_) => PublishInputType::Env(content.into()),
+                PublishInputType::Messages(_) | PublishInputType::Null => {
                     PublishInputType::Text(PublishInputTypeContentPath::default())
                 }
             },
         };
 
-        let topic_type = config.topic_type.clone().unwrap_or(PayloadType::Text);
-
-        let publish = PublishBuilder::default()
-            .qos(config.qos.unwrap_or(QoS::AtLeastOnce))
-            .retain(config.retain)
-            .enabled(true)
-            .trigger(vec![trigger])
-            .input(message_input_type)
-            .filters(FilterTypes::default())
-            .build()?;
-        let topic = TopicBuilder::default()
-            .topic(config.topic.clone())
-            .publish(Some(publish))
-            .subscription(None)
-            .payload_type(topic_type)
-            .build()?;
-
-        result.push(topic);
+        Ok(input)
+    }
 
-        Ok(result)
+    fn required_path(
+        content: PublishInputTypeContentPath,
+        message_type: &str,
+    ) -> Result<PathBuf, ArgsError> {
+        content
+            .path
+            .ok_or_else(|| ArgsError::MessageTypeRequiresFile(message_type.to_string()))
     }
 
     fn get_topics_for_subscribe(config: &CommandSubscribe) -> Result<Vec<Topic>, ArgsError> {
         let mut result = Vec::new();
 
-        let topic_type = config.topic_type.clone().unwrap_or(PayloadType::Text);
+        let topic_type = config
+            .topic_type
+            .clone()
+            .unwrap_or(PayloadType::Text(Default::default()));
 
         let output_target: OutputTarget = match &config.output_target {
             None => OutputTarget::Console(OutputTargetConsole::default()),
             Some(target) => match target {
-                OutputTargetArgs::Console(_) => {
-                    OutputTarget::Console(OutputTargetConsole::default())
-                }
+                OutputTargetArgs::Console(config) => OutputTarget::Console(OutputTargetConsole {
+                    pretty: config.pretty,
+                    diff: config.diff,
+                    null_delimited: config.null_delimited,
+                    stderr: config.stderr,
+                    show_header: !config.no_header,
+                    header: config.header.clone(),
+                    escape: config.escape.unwrap_or_default(),
+                    show_properties: config.show_properties,
+                }),
                 OutputTargetArgs::File(config) => OutputTarget::File(OutputTargetFile {
                     path: config.path.clone(),
                     overwrite: config.overwrite,
                     prepend: config.prepend.clone(),
                     append: config.append.clone(),
+                    per_message: config.per_message,
+                    ndjson: config.ndjson,
+                    ..OutputTargetFile::default()
                 }),
                 OutputTargetArgs::Topic(config) => OutputTarget::Topic(OutputTargetTopic {
                     topic: config.topic.clone(),
@@ -129,26 +301,95 @@ impl Command {
         };
 
         let output = Output {
-            format: config.output_type.clone().unwrap_or(PayloadType::Text),
+            format: config
+                .output_type
+                .clone()
+                .unwrap_or(PayloadType::Text(Default::default())),
             target: output_target,
+            json_pretty: false,
+            sample: config.sample,
+            when: None,
+        };
+
+        for (topic, qos) in config.get_topics()? {
+            let subscription = SubscriptionBuilder::default()
+                .qos(qos.or(config.qos).unwrap_or(QoS::AtLeastOnce))
+                .enabled(true)
+                .filters(FilterTypes::default())
+                .topic_filter_regex(config.topic_filter_regex.clone())
+                .ignore_retained(config.no_retained)
+                .only_retained(config.only_retained)
+                .only_live(false)
+                .min_qos(config.min_qos)
+                .max_qos(config.max_qos)
+                .ordered(config.ordered)
+                .payload_limit(None)
+                .outputs(vec![output.clone()])
+                .build()?;
+            let topic = TopicBuilder::default()
+                .topic(topic)
+                .subscription(Some(subscription))
+                .publish(None)
+                .payload_type(topic_type)
+                .on_decode_error(config.on_decode_error.unwrap_or_default())
+                .payload_overrides(vec![])
+                .connection(None)
+                .build()?;
+
+            result.push(topic);
+        }
+
+        Ok(result)
+    }
+
+    fn get_topics_for_copy(config: &CommandCopy) -> Result<Vec<Topic>, ArgsError> {
+        // the republished messages would be received again
+        if topic_matches(&config.from, &config.to) {
+            return Err(ArgsError::CopyLoop(config.from.clone(), config.to.clone()));
+        }
+
+        let qos = config.qos.unwrap_or(QoS::AtLeastOnce);
+        let input_type = config.input_type.clone().unwrap_or(PayloadType::Raw);
+
+        let output = Output {
+            format: config
+                .output_type
+                .clone()
+                .unwrap_or_else(|| input_type.clone()),
+            target: OutputTarget::Topic(OutputTargetTopic {
+                topic: config.to.clone(),
+                qos,
+                retain: config.retain,
+            }),
+            json_pretty: false,
+            sample: None,
+            when: None,
         };
 
         let subscription = SubscriptionBuilder::default()
-            .qos(config.qos.unwrap_or(QoS::AtLeastOnce))
+            .qos(qos)
             .enabled(true)
             .filters(FilterTypes::default())
+            .topic_filter_regex(None)
+            .ignore_retained(false)
+            .only_retained(false)
+            .only_live(false)
+            .min_qos(None)
+            .max_qos(None)
+            .ordered(false)
+            .payload_limit(None)
             .outputs(vec![output])
             .build()?;
-        let topic = TopicBuilder::default()
-            .topic(config.topic.clone())
+
+        Ok(vec![TopicBuilder::default()
+            .topic(config.from.clone())
             .subscription(Some(subscription))
             .publish(None)
-            .payload_type(topic_type)
-            .build()?;
-
-        result.push(topic);
-
-        Ok(result)
+            .payload_type(input_type)
+            .on_decode_error(DecodeErrorPolicy::default())
+            .payload_overrides(vec![])
+            .connection(None)
+            .build()?])
     }
 
     fn get_topics_for_sparkplug(
@@ -156,40 +397,159 @@ impl Command {
     ) -> Result<Vec<Topic>, crate::args::ArgsError> {
         let mut result = Vec::new();
 
+        // the conformance check subscribes on its own
+        if config.get_check().is_some() {
+            return Ok(result);
+        }
+
+        if let Some(emulator) = config.get_emulator() {
+            let subscription = SubscriptionBuilder::default()
+                .qos(QoS::AtLeastOnce)
+                .enabled(true)
+                .filters(FilterTypes::default())
+                .topic_filter_regex(None)
+                .ignore_retained(false)
+                .only_retained(false)
+                .only_live(false)
+                .min_qos(None)
+                .max_qos(None)
+                .ordered(false)
+                .payload_limit(None)
+                .outputs(vec![Output {
+                    format: PayloadType::Sparkplug,
+                    target: OutputTarget::Console(OutputTargetConsole::default()),
+                    json_pretty: false,
+                    sample: None,
+                    when: None,
+                }])
+                .build()?;
+
+            result.push(
+                TopicBuilder::default()
+                    .topic(format!(
+                        "{}/{}/NCMD/{}",
+                        SPARKPLUG_TOPIC_VERSION,
+                        emulator.group_id(),
+                        emulator.edge_node_id()
+                    ))
+                    .subscription(Some(subscription))
+                    .publish(None)
+                    .payload_type(PayloadType::Sparkplug)
+                    .on_decode_error(DecodeErrorPolicy::default())
+                    .payload_overrides(vec![])
+                    .connection(None)
+                    .build()?,
+            );
+
+            return Ok(result);
+        }
+
         if config.include_groups.is_empty() {
             result.append(&mut Self::add_sparkplug_topics_for_group_id(
                 "+",
                 config.qos.unwrap_or(QoS::AtLeastOnce),
+                !config.tui,
             )?);
         } else {
             for group_id in &config.include_groups {
                 result.append(&mut Self::add_sparkplug_topics_for_group_id(
                     group_id,
                     config.qos.unwrap_or(QoS::AtLeastOnce),
+                    !config.tui,
                 )?);
             }
         }
 
+        if let Some(primary_host) = config.get_primary_host() {
+            result.push(Self::get_sparkplug_state_topic(
+                primary_host.host_id(),
+                config.qos.unwrap_or(QoS::AtLeastOnce),
+                !config.tui,
+            )?);
+        }
+
         Ok(result)
     }
 
+    /// Topic of the STATE messages of a host application as defined by
+    /// Sparkplug 3.0. The payload is read as text, so that both the JSON
+    /// payload and the ONLINE/OFFLINE text of earlier versions are accepted.
+    fn get_sparkplug_state_topic(
+        host_id: &str,
+        qos: QoS,
+        console_output: bool,
+    ) -> Result<Topic, ArgsError> {
+        let outputs = if console_output {
+            vec![Output {
+                format: PayloadType::Text(Default::default()),
+                target: OutputTarget::Console(OutputTargetConsole::default()),
+                json_pretty: false,
+                sample: None,
+                when: None,
+            }]
+        } else {
+            vec![]
+        };
+
+        let subscription = SubscriptionBuilder::default()
+            .qos(qos)
+            .enabled(true)
+            .filters(FilterTypes::default())
+            .topic_filter_regex(None)
+            .ignore_retained(false)
+            .only_retained(false)
+            .only_live(false)
+            .min_qos(None)
+            .max_qos(None)
+            .ordered(false)
+            .payload_limit(None)
+            .outputs(outputs)
+            .build()?;
+
+        Ok(TopicBuilder::default()
+            .topic(format!("{}/STATE/{}", SPARKPLUG_TOPIC_VERSION, host_id))
+            .subscription(Some(subscription))
+            .publish(None)
+            .payload_type(PayloadType::Text(Default::default()))
+            .on_decode_error(DecodeErrorPolicy::default())
+            .payload_overrides(vec![])
+            .connection(None)
+            .build()?)
+    }
+
     fn add_sparkplug_topics_for_group_id<T: Into<GroupId> + Display>(
         group_id: T,
         qos: QoS,
+        console_output: bool,
     ) -> Result<Vec<Topic>, ArgsError> {
-        fn get_subscription(qos: QoS, format: PayloadType) -> Result<Subscription, ArgsError> {
-            let output = Output {
-                format,
-                target: OutputTarget::Console(OutputTargetConsole::default()),
+        let get_subscription = |qos: QoS, format: PayloadType| -> Result<Subscription, ArgsError> {
+            let outputs = if console_output {
+                vec![Output {
+                    format,
+                    target: OutputTarget::Console(OutputTargetConsole::default()),
+                    json_pretty: false,
+                    sample: None,
+                    when: None,
+                }]
+            } else {
+                vec![]
             };
 
             Ok(SubscriptionBuilder::default()
                 .qos(qos)
                 .enabled(true)
                 .filters(FilterTypes::default())
-                .outputs(vec![output])
+                .topic_filter_regex(None)
+                .ignore_retained(false)
+                .only_retained(false)
+                .only_live(false)
+                .min_qos(None)
+                .max_qos(None)
+                .ordered(false)
+                .payload_limit(None)
+                .outputs(outputs)
                 .build()?)
-        }
+        };
         let mut result: Vec<Topic> = vec![];
 
         let topic_nbirth = TopicBuilder::default()
@@ -197,6 +557,9 @@ impl Command {
             .subscription(Some(get_subscription(qos, PayloadType::Sparkplug)?))
             .publish(None)
             .payload_type(PayloadType::Sparkplug)
+            .on_decode_error(DecodeErrorPolicy::default())
+            .payload_overrides(vec![])
+            .connection(None)
             .build()?;
 
         let mut topic_ndeath = topic_nbirth.clone();
@@ -225,6 +588,9 @@ impl Command {
             .subscription(Some(get_subscription(qos, PayloadType::Json)?))
             .publish(None)
             .payload_type(PayloadType::Json)
+            .on_decode_error(DecodeErrorPolicy::default())
+            .payload_overrides(vec![])
+            .connection(None)
             .build()?;
 
         result.push(topic_nbirth);
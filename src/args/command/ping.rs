@@ -0,0 +1,68 @@
+use crate::args::parsers::{parse_duration_milliseconds, parse_qos};
+use clap::Args;
+use mqtlib::config::ping::Ping;
+use mqtlib::mqtt::QoS;
+use std::time::Duration;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct CommandPing {
+    #[arg(
+        short = 'n',
+        long = "count",
+        env = "PING_COUNT",
+        help_heading = "Ping",
+        help = "Number of messages to publish (default: 5)"
+    )]
+    pub count: Option<usize>,
+
+    #[arg(
+        long = "interval",
+        env = "PING_INTERVAL",
+        value_parser = parse_duration_milliseconds,
+        help_heading = "Ping",
+        help = "Interval between the messages in milliseconds (default: 1000)"
+    )]
+    pub interval: Option<Duration>,
+
+    #[arg(
+        long = "timeout",
+        env = "PING_TIMEOUT",
+        value_parser = parse_duration_milliseconds,
+        help_heading = "Ping",
+        help = "Time in milliseconds to wait for the connection and each message before it counts as lost (default: 5000)"
+    )]
+    pub timeout: Option<Duration>,
+
+    #[arg(
+        short = 't',
+        long = "topic",
+        env = "PING_TOPIC",
+        help_heading = "Ping",
+        help = "Topic the messages are published on and received from (default: mqtli/ping/<random id>)"
+    )]
+    pub topic: Option<String>,
+
+    #[arg(
+        short = 'q',
+        long = "qos",
+        env = "PING_QOS",
+        value_parser = parse_qos,
+        help_heading = "Ping",
+        help = "Quality of Service (default: 0) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+}
+
+impl CommandPing {
+    pub fn get_ping(&self) -> Ping {
+        let default = Ping::default();
+
+        Ping {
+            count: self.count.unwrap_or(default.count),
+            interval: self.interval.unwrap_or(default.interval),
+            timeout: self.timeout.unwrap_or(default.timeout),
+            topic: self.topic.clone().unwrap_or(default.topic),
+            qos: self.qos.unwrap_or(default.qos),
+        }
+    }
+}
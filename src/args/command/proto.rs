@@ -0,0 +1,78 @@
+use clap::{Args, Subcommand};
+use mqtlib::config::proto_check::ProtoCheck;
+use std::path::PathBuf;
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandProto {
+    #[command(subcommand)]
+    pub command: ProtoCommand,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ProtoCommand {
+    #[command(
+        name = "check",
+        about = "Load every protobuf definition referenced from the configuration, verify the configured messages exist and report unresolved imports"
+    )]
+    Check(CommandProtoCheck),
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct CommandProtoCheck {
+    #[arg(
+        long = "sample",
+        env = "PROTO_CHECK_SAMPLE",
+        help_heading = "Protobuf check",
+        help = "(optional) File with a binary payload which is decoded with the messages of each reference"
+    )]
+    pub sample: Option<PathBuf>,
+
+    #[arg(
+        short = 't',
+        long = "topic",
+        env = "PROTO_CHECK_TOPIC",
+        help_heading = "Protobuf check",
+        help = "(optional) Only check the definitions referenced by this topic of the configuration (default: all topics and bridge rules)"
+    )]
+    pub topic: Option<String>,
+}
+
+impl CommandProto {
+    pub fn get_proto_check(&self) -> ProtoCheck {
+        match &self.command {
+            ProtoCommand::Check(check) => ProtoCheck {
+                sample: check.sample.clone(),
+                topic: check.topic.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::command::Command;
+    use crate::args::content::MqtliArgs;
+    use clap::Parser;
+    use std::path::PathBuf;
+
+    #[test]
+    fn check() {
+        let args = MqtliArgs::try_parse_from([
+            "mqtli",
+            "proto",
+            "check",
+            "--sample",
+            "sample.bin",
+            "-t",
+            "sensors/#",
+        ])
+        .unwrap();
+
+        let Some(Command::Proto(command)) = args.command else {
+            panic!("proto command expected");
+        };
+        let check = command.get_proto_check();
+        assert_eq!(Some(PathBuf::from("sample.bin")), check.sample);
+        assert_eq!(Some("sensors/#".to_string()), check.topic);
+    }
+}
@@ -64,6 +64,15 @@ pub struct CommandPublish {
     )]
     pub interval: Option<Duration>,
 
+    #[arg(
+        long = "align-to",
+        env = "PUBLISH_ALIGN_TO",
+        value_parser = parse_duration_milliseconds,
+        help_heading = "Publish",
+        help = "Align the messages to wall-clock boundaries of this duration in milliseconds, e.g. 60000 for the top of each minute"
+    )]
+    pub align_to: Option<Duration>,
+
     #[arg(
         long = "repeat",
         env = "PUBLISH_REPEAT",
@@ -82,11 +91,11 @@ pub struct CommandPublishMessage {
         env = "PUBLISH_MESSAGE",
         value_parser = parse_string_as_vec,
         help_heading = "Publish",
-        help = "Message to publish",
+        help = "Message to publish; may be given several times to publish the messages one after another in the interval",
         group = "publish_message"
     )]
-    #[allow(clippy::box_collection)]
-    pub message: Option<Box<Vec<u8>>>,
+    // the inner vector is written out so clap parses each message as one value
+    pub message: Vec<::std::vec::Vec<u8>>,
 
     #[arg(
         short = 'n',
@@ -103,10 +112,10 @@ pub struct CommandPublishMessage {
         long = "file",
         env = "PUBLISH_FILE",
         help_heading = "Publish",
-        help = "Loads a message from a file",
+        help = "Loads a message from a file; may be given several times to publish the files one after another in the interval",
         group = "publish_message"
     )]
-    pub file: Option<PathBuf>,
+    pub file: Vec<PathBuf>,
 
     #[arg(
         short = 's',
@@ -117,6 +126,15 @@ pub struct CommandPublishMessage {
         group = "publish_message"
     )]
     pub from_stdin: bool,
+
+    #[arg(
+        long = "directory",
+        env = "PUBLISH_DIRECTORY",
+        help_heading = "Publish",
+        help = "Publishes every file of the directory and its subdirectories once; the topic may contain the placeholders {{path}}, {{name}} and {{extension}} of the relative file path, the format is derived from the file extension",
+        group = "publish_message"
+    )]
+    pub directory: Option<PathBuf>,
 }
 
 #[cfg(test)]
@@ -124,6 +142,9 @@ mod tests {
     use crate::args::command::Command;
     use crate::args::content::MqtliArgs;
     use clap::Parser;
+    use mqtlib::config::publish::PublishTriggerType;
+    use mqtlib::config::PublishInputType;
+    use std::time::Duration;
 
     #[test]
     fn null() {
@@ -138,8 +159,8 @@ mod tests {
             assert_eq!(value.topic, "TOPIC");
             assert!(value.message.null_message);
             assert!(!value.message.from_stdin);
-            assert!(value.message.message.is_none());
-            assert!(value.message.file.is_none());
+            assert!(value.message.message.is_empty());
+            assert!(value.message.file.is_empty());
         }
     }
 
@@ -156,11 +177,141 @@ mod tests {
             assert_eq!(value.topic, "TOPIC");
             assert!(!value.message.null_message);
             assert!(!value.message.from_stdin);
-            assert!(value.message.message.is_none());
-            assert!(value.message.file.is_some());
+            assert!(value.message.message.is_empty());
+            assert!(!value.message.file.is_empty());
         }
     }
 
+    #[test]
+    fn directory() {
+        let args = [
+            "mqtli",
+            "pub",
+            "--topic",
+            "config/{{path}}",
+            "--directory",
+            "config",
+            "-r",
+        ];
+        let result = MqtliArgs::try_parse_from(args);
+
+        assert!(result.is_ok());
+        let result = result.unwrap();
+
+        if let Command::Publish(value) = result.command.unwrap() {
+            assert_eq!(value.topic, "config/{{path}}");
+            assert!(value.retain);
+            assert!(value.message.directory.is_some());
+            assert!(value.message.file.is_empty());
+        }
+    }
+
+    #[test]
+    fn rate() {
+        let args = ["mqtli", "pub", "--topic", "TOPIC", "-n", "--rate", "250"];
+        let result = MqtliArgs::try_parse_from(args).unwrap();
+        assert_eq!(Some(250.0), result.publish_rate);
+
+        let topics = result
+            .command
+            .unwrap()
+            .get_topics(result.publish_rate)
+            .unwrap();
+        let publish = topics[0].publish().clone().unwrap();
+        let PublishTriggerType::Periodic(trigger) = &publish.trigger()[0] else {
+            panic!("Expected periodic trigger");
+        };
+        assert_eq!(&Duration::from_millis(4), trigger.interval());
+    }
+
+    #[test]
+    fn rate_out_of_range() {
+        for rate in ["inf", "1e12", "1e-300", "0"] {
+            let args = ["mqtli", "pub", "--topic", "TOPIC", "-n", "--rate", rate];
+            let result = MqtliArgs::try_parse_from(args).unwrap();
+
+            assert!(result
+                .command
+                .unwrap()
+                .get_topics(result.publish_rate)
+                .is_err());
+        }
+    }
+
+    fn message_type_without_file(args: &[&str]) -> bool {
+        let result = MqtliArgs::try_parse_from(args).unwrap();
+        result.command.unwrap().get_topics(None).is_err()
+    }
+
+    #[test]
+    fn csv_requires_file() {
+        assert!(message_type_without_file(&[
+            "mqtli",
+            "pub",
+            "-t",
+            "a",
+            "-m",
+            "foo",
+            "--message-type",
+            "csv"
+        ]));
+        assert!(message_type_without_file(&[
+            "mqtli",
+            "pub",
+            "-t",
+            "a",
+            "-n",
+            "--message-type",
+            "csv"
+        ]));
+    }
+
+    #[test]
+    fn ndjson_requires_file() {
+        assert!(message_type_without_file(&[
+            "mqtli",
+            "pub",
+            "-t",
+            "a",
+            "-m",
+            "foo",
+            "--message-type",
+            "ndjson"
+        ]));
+        assert!(message_type_without_file(&[
+            "mqtli",
+            "pub",
+            "-t",
+            "a",
+            "-n",
+            "--message-type",
+            "ndjson"
+        ]));
+    }
+
+    #[test]
+    fn directory_requires_file() {
+        assert!(message_type_without_file(&[
+            "mqtli",
+            "pub",
+            "-t",
+            "a",
+            "-m",
+            "foo",
+            "--message-type",
+            "directory"
+        ]));
+        assert!(message_type_without_file(&[
+            "mqtli",
+            "pub",
+            "-t",
+            "a",
+            "-n",
+            "--message-type",
+            "directory"
+        ]));
+    }
+
     #[test]
     fn stdin() {
         let args = ["mqtli", "pub", "--topic", "TOPIC", "-s"];
@@ -174,8 +325,8 @@ mod tests {
             assert_eq!(value.topic, "TOPIC");
             assert!(!value.message.null_message);
             assert!(value.message.from_stdin);
-            assert!(value.message.message.is_none());
-            assert!(value.message.file.is_none());
+            assert!(value.message.message.is_empty());
+            assert!(value.message.file.is_empty());
         }
     }
 
@@ -199,6 +350,7 @@ mod tests {
             ("-n", "-f"),
             ("-n", "-l"),
             ("-f", "-l"),
+            ("-n", "--directory=config"),
         ]
         .iter()
         .try_for_each(|(a, b)| illegal_combination(a, b))?;
@@ -224,10 +376,7 @@ mod tests {
 
         if let Command::Publish(value) = result.command.unwrap() {
             assert_eq!(value.topic, "TOPIC");
-            assert_eq!(
-                value.message.message.unwrap().to_vec(),
-                "MESSAGE to send".as_bytes()
-            );
+            assert_eq!(value.message.message[0], "MESSAGE to send".as_bytes());
         }
     }
 
@@ -251,13 +400,40 @@ mod tests {
 
         if let Command::Publish(value) = result.command.unwrap() {
             assert_eq!(value.topic, "TOPIC");
-            assert_eq!(
-                value.message.message.unwrap().to_vec(),
-                "MESSAGE to send".as_bytes()
-            );
+            assert_eq!(value.message.message[0], "MESSAGE to send".as_bytes());
         }
     }
 
+    #[test]
+    fn multiple_messages() {
+        let args = [
+            "mqtli", "pub", "--topic", "TOPIC", "-m", "first", "-m", "second", "--repeat", "2",
+        ];
+        let result = MqtliArgs::try_parse_from(args).unwrap();
+
+        let topics = result.command.unwrap().get_topics(None).unwrap();
+        assert_eq!(1, topics.len());
+
+        let publish = topics[0].publish().clone().unwrap();
+        let PublishInputType::Messages(input) = publish.input() else {
+            panic!("Expected messages input");
+        };
+        let messages = input
+            .messages()
+            .iter()
+            .map(|message| match message {
+                PublishInputType::Text(message) => message.content().clone().unwrap(),
+                message => panic!("Expected text input, got {message}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![b"first".to_vec(), b"second".to_vec()], messages);
+
+        let PublishTriggerType::Periodic(trigger) = &publish.trigger()[0] else {
+            panic!("Expected periodic trigger");
+        };
+        assert_eq!(&Some(4), trigger.count());
+    }
+
     #[test]
     fn invalid_qos() {
         let args = [
@@ -0,0 +1,70 @@
+use crate::args::parsers::{parse_duration_milliseconds, parse_json, parse_qos};
+use clap::Args;
+use mqtlib::config::self_test::SelfTest;
+use mqtlib::config::PayloadType;
+use mqtlib::mqtt::QoS;
+use std::time::Duration;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct CommandSelfTest {
+    #[arg(
+        short = 't',
+        long = "topic",
+        env = "SELFTEST_TOPIC",
+        help_heading = "Self test",
+        help = "Topic the message is published on and received from (default: mqtli/selftest/<random id>)"
+    )]
+    pub topic: Option<String>,
+
+    #[arg(
+        short = 'q',
+        long = "qos",
+        env = "SELFTEST_QOS",
+        value_parser = parse_qos,
+        help_heading = "Self test",
+        help = "Quality of Service (default: 1) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+
+    #[arg(
+        long = "timeout",
+        env = "SELFTEST_TIMEOUT",
+        value_parser = parse_duration_milliseconds,
+        help_heading = "Self test",
+        help = "Time in milliseconds to wait for each step before the test fails (default: 5000)"
+    )]
+    pub timeout: Option<Duration>,
+
+    #[arg(
+        short = 'y',
+        long = "topic-type",
+        env = "SELFTEST_TOPIC_TYPE",
+        help_heading = "Self test",
+        help = "Payload type the message is published as (default: json)"
+    )]
+    pub topic_type: Option<PayloadType>,
+
+    #[arg(
+        short = 'm',
+        long = "message",
+        env = "SELFTEST_MESSAGE",
+        value_parser = parse_json,
+        help_heading = "Self test",
+        help = "Content of the message as JSON, converted to the topic type (default: an object with a random id)"
+    )]
+    pub message: Option<serde_json::Value>,
+}
+
+impl CommandSelfTest {
+    pub fn get_self_test(&self) -> SelfTest {
+        let default = SelfTest::default();
+
+        SelfTest {
+            topic: self.topic.clone().unwrap_or(default.topic),
+            qos: self.qos.unwrap_or(default.qos),
+            timeout: self.timeout.unwrap_or(default.timeout),
+            payload_type: self.topic_type.clone().unwrap_or(default.payload_type),
+            content: self.message.clone().unwrap_or(default.content),
+        }
+    }
+}
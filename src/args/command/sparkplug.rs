@@ -1,7 +1,13 @@
-use crate::args::parsers::parse_qos;
-use clap::Args;
+use crate::args::parsers::{parse_duration_milliseconds, parse_duration_seconds, parse_qos};
+use clap::{Args, Subcommand};
+use mqtlib::config::sparkplug::{
+    EmulatedMetric, SnapshotFormat, SparkplugCheck, SparkplugEmulator, SparkplugPrimaryHost,
+    SparkplugSnapshotExport,
+};
 use mqtlib::mqtt::QoS;
-use mqtlib::sparkplug::GroupId;
+use mqtlib::sparkplug::{EdgeNodeId, GroupId};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Args, Clone, Debug, Default)]
 pub struct CommandSparkplug {
@@ -42,4 +48,227 @@ pub struct CommandSparkplug {
         help = "Include only the given topics; if not specified, all groups are subscribed to"
     )]
     pub include_groups: Vec<GroupId>,
+
+    #[arg(
+        long = "include-metric",
+        env = "SPARKPLUG_INCLUDE_METRICS",
+        value_delimiter = ',',
+        help_heading = "Sparkplug",
+        help = "Include only metrics whose names match one of the given glob patterns (* and ?); if not specified, all metrics are included"
+    )]
+    pub include_metrics: Vec<String>,
+
+    #[arg(
+        long = "exclude-metric",
+        env = "SPARKPLUG_EXCLUDE_METRICS",
+        value_delimiter = ',',
+        help_heading = "Sparkplug",
+        help = "Exclude metrics whose names match one of the given glob patterns (* and ?)"
+    )]
+    pub exclude_metrics: Vec<String>,
+
+    #[arg(
+        long = "request-rebirth",
+        env = "SPARKPLUG_REQUEST_REBIRTH",
+        help_heading = "Sparkplug",
+        help = "Request a rebirth from edge nodes whose data messages reference unknown metric aliases"
+    )]
+    pub request_rebirth: bool,
+
+    #[arg(
+        long = "tui",
+        env = "SPARKPLUG_TUI",
+        help_heading = "Sparkplug",
+        help = "Browse the network in an interactive terminal UI instead of printing messages"
+    )]
+    pub tui: bool,
+
+    #[arg(
+        long = "sql",
+        env = "SPARKPLUG_SQL",
+        help_heading = "Sparkplug",
+        help = "Store the network in a normalized schema (nodes, devices, metrics, values) of the SQL storage"
+    )]
+    pub sql: bool,
+
+    #[arg(
+        long = "summary-interval",
+        env = "SPARKPLUG_SUMMARY_INTERVAL",
+        value_parser = parse_duration_seconds,
+        help_heading = "Sparkplug",
+        help = "Print a summary of each group (online edge nodes and devices, message rates, last seen) in this interval in seconds"
+    )]
+    pub summary_interval: Option<Duration>,
+
+    #[arg(
+        long = "snapshot-file",
+        env = "SPARKPLUG_SNAPSHOT_FILE",
+        help_heading = "Sparkplug",
+        help = "Periodically export the state of the network to this file"
+    )]
+    pub snapshot_file: Option<PathBuf>,
+
+    #[arg(
+        long = "snapshot-topic",
+        env = "SPARKPLUG_SNAPSHOT_TOPIC",
+        help_heading = "Sparkplug",
+        help = "Periodically publish the state of the network on this topic (retained)"
+    )]
+    pub snapshot_topic: Option<String>,
+
+    #[arg(
+        long = "snapshot-format",
+        env = "SPARKPLUG_SNAPSHOT_FORMAT",
+        help_heading = "Sparkplug",
+        help = "Format of the exported state (default: json) (possible values: json, yaml)"
+    )]
+    pub snapshot_format: Option<SnapshotFormat>,
+
+    #[arg(
+        long = "snapshot-interval",
+        env = "SPARKPLUG_SNAPSHOT_INTERVAL",
+        value_parser = parse_duration_seconds,
+        help_heading = "Sparkplug",
+        help = "Interval in seconds between two exports of the state (default: 10)"
+    )]
+    pub snapshot_interval: Option<Duration>,
+
+    #[arg(
+        long = "primary-host",
+        env = "SPARKPLUG_PRIMARY_HOST",
+        help_heading = "Sparkplug",
+        help = "Track the STATE messages of the primary host application with this id and log when it goes online or offline"
+    )]
+    pub primary_host: Option<String>,
+
+    #[arg(
+        long = "primary-host-alert",
+        env = "SPARKPLUG_PRIMARY_HOST_ALERT",
+        requires = "primary_host",
+        help_heading = "Sparkplug",
+        help = "Highlight on the console when the primary host goes offline"
+    )]
+    pub primary_host_alert: bool,
+
+    #[arg(
+        long = "primary-host-webhook",
+        env = "SPARKPLUG_PRIMARY_HOST_WEBHOOK",
+        requires = "primary_host",
+        help_heading = "Sparkplug",
+        help = "Post a JSON notification to this URL when the primary host goes offline"
+    )]
+    pub primary_host_webhook: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<SparkplugCommand>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum SparkplugCommand {
+    #[command(
+        name = "emulate",
+        about = "Act as a Sparkplug edge node publishing the given metrics"
+    )]
+    Emulate(CommandSparkplugEmulate),
+    #[command(
+        name = "check",
+        about = "Observe the traffic and report violations of the Sparkplug B specification per edge node"
+    )]
+    Check(CommandSparkplugCheck),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandSparkplugEmulate {
+    #[arg(
+        long = "group-id",
+        env = "SPARKPLUG_EMULATE_GROUP_ID",
+        help_heading = "Sparkplug emulator",
+        help = "Group id of the emulated edge node"
+    )]
+    pub group_id: GroupId,
+
+    #[arg(
+        long = "edge-node-id",
+        env = "SPARKPLUG_EMULATE_EDGE_NODE_ID",
+        help_heading = "Sparkplug emulator",
+        help = "Id of the emulated edge node"
+    )]
+    pub edge_node_id: EdgeNodeId,
+
+    #[arg(
+        short = 'm',
+        long = "metric",
+        help_heading = "Sparkplug emulator",
+        help = "Metric given as name:type=value; may be given multiple times (types: int, double, bool, string; placeholders: {{counter}}, {{toggle}}, {{timestamp}}, {{random:min:max}})"
+    )]
+    pub metrics: Vec<EmulatedMetric>,
+
+    #[arg(
+        long = "interval",
+        env = "SPARKPLUG_EMULATE_INTERVAL",
+        value_parser = parse_duration_milliseconds,
+        help_heading = "Sparkplug emulator",
+        help = "Interval in milliseconds between two data messages (default: 5000)"
+    )]
+    pub interval: Option<Duration>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandSparkplugCheck {
+    #[arg(
+        long = "duration",
+        env = "SPARKPLUG_CHECK_DURATION",
+        value_parser = parse_duration_seconds,
+        help_heading = "Sparkplug conformance check",
+        help = "Seconds to observe the traffic before the report is printed; if not specified, the report is printed on exit"
+    )]
+    pub duration: Option<Duration>,
+}
+
+impl CommandSparkplug {
+    pub fn get_emulator(&self) -> Option<SparkplugEmulator> {
+        let Some(SparkplugCommand::Emulate(emulate)) = &self.command else {
+            return None;
+        };
+
+        Some(SparkplugEmulator {
+            group_id: emulate.group_id.clone(),
+            edge_node_id: emulate.edge_node_id.clone(),
+            interval: emulate.interval.unwrap_or(Duration::from_secs(5)),
+            metrics: emulate.metrics.clone(),
+        })
+    }
+
+    pub fn get_check(&self) -> Option<SparkplugCheck> {
+        let Some(SparkplugCommand::Check(check)) = &self.command else {
+            return None;
+        };
+
+        Some(SparkplugCheck {
+            groups: self.include_groups.clone(),
+            duration: check.duration,
+            qos: self.qos.unwrap_or(QoS::AtLeastOnce),
+        })
+    }
+
+    pub fn get_primary_host(&self) -> Option<SparkplugPrimaryHost> {
+        Some(SparkplugPrimaryHost {
+            host_id: self.primary_host.clone()?,
+            alert_console: self.primary_host_alert,
+            webhook: self.primary_host_webhook.clone(),
+        })
+    }
+
+    pub fn get_snapshot_export(&self) -> Option<SparkplugSnapshotExport> {
+        if self.snapshot_file.is_none() && self.snapshot_topic.is_none() {
+            return None;
+        }
+
+        Some(SparkplugSnapshotExport {
+            interval: self.snapshot_interval.unwrap_or(Duration::from_secs(10)),
+            format: self.snapshot_format.unwrap_or_default(),
+            path: self.snapshot_file.clone(),
+            topic: self.snapshot_topic.clone(),
+        })
+    }
 }
@@ -1,6 +1,11 @@
+use crate::args::parsers::{deserialize_duration_seconds, parse_duration_seconds};
 use clap::Args;
 use derive_getters::Getters;
+use mqtlib::config::secret::KeyringEntry;
+use mqtlib::config::sql_storage::{SqlQueueOverflow, SqlStatements};
 use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Args, Debug, Default, Deserialize, Getters)]
 pub struct SqlStorage {
@@ -12,5 +17,82 @@ pub struct SqlStorage {
         help = "The connection string to the SQL storage (currently only sqlite is supported)"
     )]
     #[serde(rename = "connection_string")]
-    pub connection_string: String,
+    #[serde(default)]
+    pub connection_string: Option<String>,
+
+    #[arg(
+        long = "connection-string-cmd",
+        env = "SQL_CONNECTION_STRING_CMD",
+        global = true,
+        help_heading = "SQL storage",
+        help = "Command whose output is used as connection string"
+    )]
+    #[serde(default)]
+    pub connection_string_cmd: Option<String>,
+
+    #[arg(
+        long = "connection-string-keyring",
+        env = "SQL_CONNECTION_STRING_KEYRING",
+        global = true,
+        help_heading = "SQL storage",
+        help = "Entry in the keyring of the operating system holding the connection string, given as service:user"
+    )]
+    #[serde(default)]
+    pub connection_string_keyring: Option<KeyringEntry>,
+
+    #[arg(
+        long = "sql-queue-size",
+        env = "SQL_QUEUE_SIZE",
+        global = true,
+        help_heading = "SQL storage",
+        help = "Number of writes waiting for the database before --sql-queue-overflow applies (default: 1000)"
+    )]
+    #[serde(default)]
+    pub queue_size: Option<usize>,
+
+    #[arg(
+        long = "sql-queue-overflow",
+        env = "SQL_QUEUE_OVERFLOW",
+        global = true,
+        help_heading = "SQL storage",
+        help = "Handling of writes if the queue is full (default: block) (possible values: block, drop)"
+    )]
+    #[serde(default)]
+    pub queue_overflow: Option<SqlQueueOverflow>,
+
+    #[arg(
+        long = "sql-buffer-file",
+        env = "SQL_BUFFER_FILE",
+        global = true,
+        help_heading = "SQL storage",
+        help = "File in which writes are buffered while the database is unavailable, they are replayed once it is reachable again"
+    )]
+    #[serde(default)]
+    pub buffer_path: Option<PathBuf>,
+
+    #[arg(
+        long = "sql-buffer-size",
+        env = "SQL_BUFFER_SIZE",
+        global = true,
+        help_heading = "SQL storage",
+        help = "Maximum number of buffered writes, further writes are dropped (default: 100000)"
+    )]
+    #[serde(default)]
+    pub buffer_size: Option<usize>,
+
+    #[arg(
+        long = "sql-retry-interval",
+        env = "SQL_RETRY_INTERVAL",
+        global = true,
+        value_parser = parse_duration_seconds,
+        help_heading = "SQL storage",
+        help = "Interval in seconds in which the buffered writes are replayed (default: 5)"
+    )]
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_seconds")]
+    pub retry_interval: Option<Duration>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub statements: Option<SqlStatements>,
 }
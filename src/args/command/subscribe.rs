@@ -1,5 +1,8 @@
 use crate::args::parsers::parse_qos;
+use crate::args::ArgsError;
 use clap::{Args, Subcommand};
+use mqtlib::config::subscription::{ConsoleEscape, Sample, TopicRegex};
+use mqtlib::config::topic::DecodeErrorPolicy;
 use mqtlib::config::PayloadType;
 use mqtlib::mqtt::QoS;
 use std::path::PathBuf;
@@ -11,10 +14,19 @@ pub struct CommandSubscribe {
         short = 't',
         long = "topic",
         env = "SUBSCRIBE_TOPIC",
+        required_unless_present = "topics_file",
         help_heading = "Subscribe",
         help = "Topic to subscribe"
     )]
-    pub topic: String,
+    pub topic: Option<String>,
+
+    #[arg(
+        long = "topics-file",
+        env = "SUBSCRIBE_TOPICS_FILE",
+        help_heading = "Subscribe",
+        help = "File with one topic to subscribe per line, optionally followed by its QoS, e.g. \"sensors/# 1\"; empty lines are ignored"
+    )]
+    pub topics_file: Option<PathBuf>,
 
     #[arg(short = 'q', long = "qos", env = "SUBSCRIBE_QOS",
     value_parser = parse_qos,
@@ -32,6 +44,65 @@ pub struct CommandSubscribe {
     )]
     pub topic_type: Option<PayloadType>,
 
+    #[arg(
+        long = "on-decode-error",
+        env = "SUBSCRIBE_ON_DECODE_ERROR",
+        help_heading = "Subscribe",
+        help = "What to do with messages which can't be decoded with the topic type (default: error) (possible values: skip, raw, hex, error)"
+    )]
+    pub on_decode_error: Option<DecodeErrorPolicy>,
+
+    #[arg(
+        long = "topic-filter-regex",
+        env = "SUBSCRIBE_TOPIC_FILTER_REGEX",
+        help_heading = "Subscribe",
+        help = "Only process messages whose topic matches this regular expression"
+    )]
+    pub topic_filter_regex: Option<TopicRegex>,
+
+    #[arg(
+        long = "no-retained",
+        env = "SUBSCRIBE_NO_RETAINED",
+        help_heading = "Subscribe",
+        help = "Drop messages delivered with the retain flag set, so only live messages are shown"
+    )]
+    pub no_retained: bool,
+
+    #[arg(
+        long = "only-retained",
+        env = "SUBSCRIBE_ONLY_RETAINED",
+        conflicts_with = "no_retained",
+        help_heading = "Subscribe",
+        help = "Drop messages delivered without the retain flag, so only retained state is shown"
+    )]
+    pub only_retained: bool,
+
+    #[arg(
+        long = "min-qos",
+        env = "SUBSCRIBE_MIN_QOS",
+        value_parser = parse_qos,
+        help_heading = "Subscribe",
+        help = "Drop messages delivered with a lower QoS"
+    )]
+    pub min_qos: Option<QoS>,
+
+    #[arg(
+        long = "max-qos",
+        env = "SUBSCRIBE_MAX_QOS",
+        value_parser = parse_qos,
+        help_heading = "Subscribe",
+        help = "Drop messages delivered with a higher QoS"
+    )]
+    pub max_qos: Option<QoS>,
+
+    #[arg(
+        long = "ordered",
+        env = "SUBSCRIBE_ORDERED",
+        help_heading = "Subscribe",
+        help = "Process all messages matching the topic in the order they were received, also across the topics matching a wildcard"
+    )]
+    pub ordered: bool,
+
     #[arg(
         long = "output-type",
         env = "SUBSCRIBE_OUTPUT_TYPE",
@@ -40,10 +111,67 @@ pub struct CommandSubscribe {
     )]
     pub output_type: Option<PayloadType>,
 
+    #[arg(
+        long = "sample",
+        env = "SUBSCRIBE_SAMPLE",
+        help_heading = "Subscribe",
+        help = "Only output every nth message per topic if given a whole number n, or each message with this probability if given a number between 0 and 1"
+    )]
+    pub sample: Option<Sample>,
+
     #[command(subcommand)]
     pub output_target: Option<OutputTarget>,
 }
 
+impl CommandSubscribe {
+    /// Topics to subscribe together with their QoS, if given: the topic of
+    /// the command line followed by the topics of the topics file.
+    pub fn get_topics(&self) -> Result<Vec<(String, Option<QoS>)>, ArgsError> {
+        let mut result: Vec<(String, Option<QoS>)> = vec![];
+
+        if let Some(topic) = &self.topic {
+            result.push((topic.clone(), None));
+        }
+
+        if let Some(path) = &self.topics_file {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| ArgsError::CouldNotReadTopicsFile(e, path.clone()))?;
+            result.extend(parse_topics_file(&content).map_err(|(line, reason)| {
+                ArgsError::InvalidTopicsFileLine(path.clone(), line, reason)
+            })?);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Parses the lines of a topics file, each a topic optionally followed by
+/// its QoS. Returns the number of the invalid line and the reason on error.
+fn parse_topics_file(content: &str) -> Result<Vec<(String, Option<QoS>)>, (usize, String)> {
+    let mut result = vec![];
+
+    for (index, line) in content.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => {}
+            (Some(topic), None, _) => result.push((topic.to_string(), None)),
+            (Some(topic), Some(qos), None) => {
+                let qos = parse_qos(qos).map_err(|e| (index + 1, e))?;
+                result.push((topic.to_string(), Some(qos)));
+            }
+            (Some(_), Some(_), Some(_)) => {
+                return Err((
+                    index + 1,
+                    "Expected a topic optionally followed by its QoS".to_string(),
+                ))
+            }
+        }
+    }
+
+    Ok(result)
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum OutputTarget {
     #[command(name = "output-console")]
@@ -63,7 +191,79 @@ impl Default for OutputTarget {
 }
 
 #[derive(Args, Clone, Debug, Default, PartialEq, Validate)]
-pub struct OutputTargetConsole {}
+pub struct OutputTargetConsole {
+    #[arg(
+        id = "output-pretty",
+        long = "output-pretty",
+        env = "SUBSCRIBE_OUTPUT_PRETTY",
+        help_heading = "Subscribe target console",
+        help = "Indent and highlight JSON and YAML payloads"
+    )]
+    pub pretty: bool,
+
+    #[arg(
+        id = "output-diff",
+        long = "output-diff",
+        env = "SUBSCRIBE_OUTPUT_DIFF",
+        help_heading = "Subscribe target console",
+        help = "Print only the fields of JSON and YAML payloads which changed since the previous message on the same topic"
+    )]
+    pub diff: bool,
+
+    #[arg(
+        id = "output-null-delimited",
+        long = "output-null-delimited",
+        env = "SUBSCRIBE_OUTPUT_NULL_DELIMITED",
+        help_heading = "Subscribe target console",
+        help = "Write the payloads unmodified without header, each terminated by a NUL byte instead of a newline"
+    )]
+    pub null_delimited: bool,
+
+    #[arg(
+        id = "output-stderr",
+        long = "output-stderr",
+        env = "SUBSCRIBE_OUTPUT_STDERR",
+        help_heading = "Subscribe target console",
+        help = "Write the messages to stderr instead of stdout"
+    )]
+    pub stderr: bool,
+
+    #[arg(
+        id = "output-header",
+        long = "output-header",
+        env = "SUBSCRIBE_OUTPUT_HEADER",
+        help_heading = "Subscribe target console",
+        help = "Template of the header printed before each message (placeholders: {{time}}, {{timestamp}}, {{topic}}, {{qos}}, {{retain}}, {{size}}, {{format}})"
+    )]
+    pub header: Option<String>,
+
+    #[arg(
+        id = "output-no-header",
+        long = "output-no-header",
+        env = "SUBSCRIBE_OUTPUT_NO_HEADER",
+        help_heading = "Subscribe target console",
+        help = "Don't print a header before each message"
+    )]
+    pub no_header: bool,
+
+    #[arg(
+        id = "output-escape",
+        long = "output-escape",
+        env = "SUBSCRIBE_OUTPUT_ESCAPE",
+        help_heading = "Subscribe target console",
+        help = "How control characters in payloads are printed (default: c) (possible values: c, replace, none)"
+    )]
+    pub escape: Option<ConsoleEscape>,
+
+    #[arg(
+        id = "output-show-properties",
+        long = "output-show-properties",
+        env = "SUBSCRIBE_OUTPUT_SHOW_PROPERTIES",
+        help_heading = "Subscribe target console",
+        help = "Print the MQTT v5 properties of each message below its header"
+    )]
+    pub show_properties: bool,
+}
 
 #[derive(Args, Clone, Debug, Default, PartialEq, Validate)]
 pub struct OutputTargetTopic {
@@ -72,7 +272,7 @@ pub struct OutputTargetTopic {
         long = "output-topic",
         env = "SUBSCRIBE_OUTPUT_TOPIC",
         help_heading = "Subscribe target topic",
-        help = "Topic of the output"
+        help = "Topic of the output, may contain {{$.path}} to insert a value of the JSON payload"
     )]
     pub topic: String,
 
@@ -121,7 +321,7 @@ pub struct OutputTargetFile {
         long = "output-prepend",
         env = "SUBSCRIBE_OUTPUT_PREPEND",
         help_heading = "Subscribe target file",
-        help = "Prepend the output with this"
+        help = "Prepend the output with this, may contain {{topic}}, {{qos}}, {{retain}}, {{size}}, {{timestamp}} and {{timestamp_iso}}"
     )]
     pub prepend: Option<String>,
 
@@ -130,7 +330,46 @@ pub struct OutputTargetFile {
         long = "output-append",
         env = "SUBSCRIBE_OUTPUT_APPEND",
         help_heading = "Subscribe target file",
-        help = "Append the output with this"
+        help = "Append the output with this, may contain the same placeholders as prepend"
     )]
     pub append: Option<String>,
+
+    #[arg(
+        id = "output-per-message",
+        long = "output-per-message",
+        env = "SUBSCRIBE_OUTPUT_PER_MESSAGE",
+        help_heading = "Subscribe target file",
+        help = "Write each message to a new file, the path may contain {{topic}}, {{timestamp}}, {{counter}} and {{extension}}"
+    )]
+    pub per_message: bool,
+
+    #[arg(
+        id = "output-ndjson",
+        long = "output-ndjson",
+        env = "SUBSCRIBE_OUTPUT_NDJSON",
+        help_heading = "Subscribe target file",
+        help = "Write each message as a JSON line with its topic, QoS, retain flag, payload and MQTT v5 properties"
+    )]
+    pub ndjson: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topics_file() {
+        let content = "sensors/#\n\n  plant/+/state 2\n#\n";
+
+        assert_eq!(
+            vec![
+                ("sensors/#".to_string(), None),
+                ("plant/+/state".to_string(), Some(QoS::ExactlyOnce)),
+                ("#".to_string(), None),
+            ],
+            parse_topics_file(content).unwrap()
+        );
+        assert_eq!(3, parse_topics_file("a\nb 1\nc 3").unwrap_err().0);
+        assert_eq!(1, parse_topics_file("a 1 b").unwrap_err().0);
+    }
 }
@@ -0,0 +1,71 @@
+use crate::args::parsers::{parse_duration_seconds, parse_qos};
+use clap::{Args, Subcommand};
+use mqtlib::config::topic_stats::TopicStats;
+use mqtlib::mqtt::QoS;
+use std::time::Duration;
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandTopics {
+    #[command(subcommand)]
+    pub command: TopicsCommand,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum TopicsCommand {
+    #[command(
+        name = "stats",
+        about = "Print a tree of topics with message counts, byte volumes and rates per subtree"
+    )]
+    Stats(CommandTopicsStats),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CommandTopicsStats {
+    #[arg(
+        value_name = "PATTERN",
+        help = "Topic pattern to subscribe to, may contain wildcards (default: #)"
+    )]
+    pub pattern: Option<String>,
+
+    #[arg(
+        short = 'd',
+        long = "duration",
+        env = "TOPICS_STATS_DURATION",
+        value_parser = parse_duration_seconds,
+        help_heading = "Topic statistics",
+        help = "Time in seconds to collect messages before the tree is printed (default: 10)"
+    )]
+    pub duration: Option<Duration>,
+
+    #[arg(
+        long = "depth",
+        env = "TOPICS_STATS_DEPTH",
+        help_heading = "Topic statistics",
+        help = "Number of topic levels printed; deeper levels are summed up in their parent (default: all levels)"
+    )]
+    pub depth: Option<usize>,
+
+    #[arg(
+        short = 'q',
+        long = "qos",
+        env = "TOPICS_STATS_QOS",
+        value_parser = parse_qos,
+        help_heading = "Topic statistics",
+        help = "Quality of Service (default: 0) (possible values: 0 = at most once; 1 = at least once; 2 = exactly once)"
+    )]
+    pub qos: Option<QoS>,
+}
+
+impl CommandTopics {
+    pub fn get_topic_stats(&self) -> TopicStats {
+        let TopicsCommand::Stats(stats) = &self.command;
+        let default = TopicStats::default();
+
+        TopicStats {
+            pattern: stats.pattern.clone().unwrap_or(default.pattern),
+            duration: stats.duration.unwrap_or(default.duration),
+            qos: stats.qos.unwrap_or(default.qos),
+            depth: stats.depth,
+        }
+    }
+}
@@ -1,15 +1,32 @@
 use crate::args::broker::MqttBrokerConnectArgs;
-use crate::args::parsers::deserialize_level_filter;
+use crate::args::parsers::{
+    deserialize_duration_seconds, deserialize_level_filter, parse_duration_seconds,
+};
 use crate::args::ArgsError;
 
+use crate::args::command::bridge::BridgeArgs;
+use crate::args::command::http_api::HttpApi;
 use crate::args::command::sql_storage::SqlStorage;
 use crate::args::command::Command;
 use clap::Parser;
-use mqtlib::config::mqtli_config::{Mode, MqtliConfig, MqtliConfigBuilder};
+use mqtlib::config::alert::AlertRule;
+use mqtlib::config::channels::{Channels, OverflowPolicy};
+use mqtlib::config::connection_hooks::ConnectionHooks;
+use mqtlib::config::dead_letter::DeadLetter;
+use mqtlib::config::http_api::HttpApi as HttpApiConfig;
+use mqtlib::config::logging::{LogFile, LogFormat, LogRotation};
+use mqtlib::config::mqtli_config::{
+    Mode, MqtliConfig, MqtliConfigBuilder, OnPublishComplete, Theme,
+};
+use mqtlib::config::secret::resolve_secret;
+use mqtlib::config::sparkplug::{Sparkplug as SparkplugConfig, SparkplugMetricFilter};
 use mqtlib::config::sql_storage::SqlStorage as SqlStorageConfig;
+use mqtlib::config::subscription::{PayloadLimit, PayloadLimitAction};
 use mqtlib::config::topic::{Topic, TopicStorage};
+use mqtlib::sparkplug::emulator::EdgeNodeEmulator;
 use serde::Deserialize;
 use std::path::PathBuf;
+use std::time::Duration;
 use tracing::Level;
 
 #[derive(Debug, Deserialize, Parser)]
@@ -39,15 +56,278 @@ pub struct MqtliArgs {
     )]
     pub log_level: Option<Level>,
 
+    #[serde(default)]
+    #[arg(
+        long = "log-filter",
+        global = true,
+        env = "RUST_LOG",
+        help_heading = "Logging",
+        help = "Log level per module, overriding the log level for matching modules (e.g. rumqttc=warn,mqtlib::storage=debug)"
+    )]
+    pub log_filter: Option<String>,
+
+    #[serde(default)]
+    #[arg(
+        long = "log-format",
+        global = true,
+        env = "LOG_FORMAT",
+        help_heading = "Logging",
+        help = "Format of the log messages (default: text) (possible values: text, json)"
+    )]
+    pub log_format: Option<LogFormat>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "log-file",
+        global = true,
+        env = "LOG_FILE",
+        help_heading = "Logging",
+        help = "Also write the log messages to this file"
+    )]
+    pub log_file_path: Option<PathBuf>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "log-rotation",
+        global = true,
+        env = "LOG_ROTATION",
+        help_heading = "Logging",
+        help = "Start a new log file in this interval (default: never) (possible values: never, minutely, hourly, daily)"
+    )]
+    pub log_rotation: Option<LogRotation>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub log_file: Option<LogFile>,
+
+    #[serde(default)]
+    #[arg(
+        long = "no-color",
+        global = true,
+        env = "NO_COLOR",
+        help_heading = "Output",
+        help = "Disable colored output"
+    )]
+    pub no_color: bool,
+
+    #[serde(default)]
+    #[arg(
+        long = "theme",
+        global = true,
+        env = "THEME",
+        help_heading = "Output",
+        help = "Colors of the console output, readable on dark or light terminals (default: dark) (possible values: dark, light, none)"
+    )]
+    pub theme: Option<Theme>,
+
+    #[serde(default)]
+    #[serde(rename = "stats_interval")]
+    #[serde(deserialize_with = "deserialize_duration_seconds")]
+    #[arg(
+        long = "stats",
+        global = true,
+        env = "STATS_INTERVAL",
+        value_parser = parse_duration_seconds,
+        help_heading = "Output",
+        help = "Print the number of messages and bytes and the rates per topic in this interval in seconds instead of the messages, and a summary on exit"
+    )]
+    pub stats: Option<Duration>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "max-payload-size",
+        global = true,
+        env = "MAX_PAYLOAD_SIZE",
+        help_heading = "Output",
+        help = "Maximum size of received payloads in bytes; larger payloads are handled according to --payload-limit-action"
+    )]
+    pub max_payload_size: Option<usize>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "payload-limit-action",
+        global = true,
+        env = "PAYLOAD_LIMIT_ACTION",
+        help_heading = "Output",
+        help = "Handling of payloads exceeding the maximum size (default: truncate) (possible values: truncate, skip, error)"
+    )]
+    pub payload_limit_action: Option<PayloadLimitAction>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub payload_limit: Option<PayloadLimit>,
+
+    #[serde(default)]
+    #[arg(
+        long = "workers",
+        global = true,
+        env = "WORKERS",
+        help = "Number of tasks converting, filtering and writing received messages concurrently; messages of the same topic stay in order (default: 1)"
+    )]
+    pub workers: Option<usize>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "channel-capacity",
+        global = true,
+        env = "CHANNEL_CAPACITY",
+        help = "Number of messages buffered between receiving, converting and writing messages (default: 1024)"
+    )]
+    pub channel_capacity: Option<usize>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "channel-overflow",
+        global = true,
+        env = "CHANNEL_OVERFLOW",
+        help = "Handling of messages if a buffer is full (default: drop_oldest) (possible values: drop_oldest, block_then_drop)"
+    )]
+    pub channel_overflow: Option<OverflowPolicy>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub channels: Option<Channels>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub connection_hooks: Option<ConnectionHooks>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub alerts: Option<Vec<AlertRule>>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub connection_client_id: Option<String>,
+
+    #[serde(default)]
+    #[arg(
+        long = "clients",
+        global = true,
+        env = "CLIENTS",
+        help = "Number of parallel clients, each subscribing and publishing all topics, e.g. to load test the broker (default: 1)"
+    )]
+    pub clients: Option<usize>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub clients_client_id: Option<String>,
+
+    #[serde(default)]
+    #[arg(
+        long = "control-topic",
+        global = true,
+        env = "CONTROL_TOPIC",
+        help = "Accept commands on this topic to change subscriptions, pause or resume publishing and report statistics while running"
+    )]
+    pub control_topic: Option<String>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "dead-letter-topic",
+        global = true,
+        env = "DEAD_LETTER_TOPIC",
+        help = "Republish messages which can't be decoded, filtered or converted to the format of an output to this topic, wrapped in a JSON object with the error"
+    )]
+    pub dead_letter_topic: Option<String>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub dead_letter: Option<DeadLetter>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_seconds")]
+    #[arg(
+        long = "shutdown-timeout",
+        global = true,
+        env = "SHUTDOWN_TIMEOUT",
+        value_parser = parse_duration_seconds,
+        help = "Maximum time in seconds to wait on exit for received messages to be written and publishes to be acknowledged (default: 5)"
+    )]
+    pub shutdown_timeout: Option<Duration>,
+
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration_seconds")]
+    #[arg(
+        long = "force-quit-timeout",
+        global = true,
+        env = "FORCE_QUIT_TIMEOUT",
+        value_parser = parse_duration_seconds,
+        help = "Maximum time in seconds the whole shutdown may take before mqtli exits immediately (default: unlimited)"
+    )]
+    pub force_quit_timeout: Option<Duration>,
+
+    #[serde(default)]
+    #[arg(
+        long = "on-publish-complete",
+        global = true,
+        env = "ON_PUBLISH_COMPLETE",
+        help = "What to do once all messages of periodic triggers with a count were published (default: auto) (possible values: auto, exit, stay, summary)"
+    )]
+    pub on_publish_complete: Option<OnPublishComplete>,
+
+    #[serde(default)]
+    #[arg(
+        long = "rate",
+        global = true,
+        env = "PUBLISH_RATE",
+        help = "Maximum number of messages per second published by periodic triggers, spaced evenly; pub sends its messages at this rate unless --interval is given"
+    )]
+    pub publish_rate: Option<f64>,
+
+    #[serde(default)]
+    #[arg(
+        long = "systemd",
+        global = true,
+        env = "SYSTEMD",
+        help = "Notify systemd when connected and subscribed and when stopping, and send watchdog keepalives; requires a service of type notify"
+    )]
+    pub systemd: bool,
+
+    #[serde(default)]
+    #[arg(
+        long = "show-broker-info",
+        global = true,
+        env = "SHOW_BROKER_INFO",
+        help = "Print the capabilities the broker announced when connecting (maximum QoS, retain available, topic alias maximum, ...); MQTT v5 only"
+    )]
+    pub show_broker_info: bool,
+
+    #[serde(skip)]
+    #[arg(
+        long = "only-topic",
+        global = true,
+        env = "ONLY_TOPICS",
+        value_delimiter = ',',
+        help = "Disable all configured topics except the ones matching this pattern; may be given multiple times, patterns may contain the wildcards + and #"
+    )]
+    pub only_topic: Vec<String>,
+
+    #[serde(skip)]
+    #[arg(
+        long = "skip-topic",
+        global = true,
+        env = "SKIP_TOPICS",
+        value_delimiter = ',',
+        help = "Disable the configured topics matching this pattern; may be given multiple times, patterns may contain the wildcards + and #"
+    )]
+    pub skip_topic: Vec<String>,
+
     #[arg(
         short = 'c',
         long = "config-file",
         global = true,
         env = "CONFIG_FILE_PATH",
-        help = "Path to the config file (default: config.yaml)"
+        value_delimiter = ',',
+        help = "Paths to the config files, later files override earlier ones (default: config.yaml)"
     )]
     #[serde(skip_serializing)]
-    pub config_file: Option<PathBuf>,
+    #[serde(default)]
+    pub config_file: Vec<PathBuf>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
 
     #[clap(skip)]
     #[serde(default)]
@@ -61,6 +341,14 @@ pub struct MqtliArgs {
     #[serde(default)]
     #[serde(rename = "database")]
     pub sql_storage: Option<SqlStorage>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub http_api: Option<HttpApi>,
+
+    #[clap(skip)]
+    #[serde(default)]
+    pub bridge: Option<BridgeArgs>,
 }
 
 impl MqtliArgs {
@@ -69,13 +357,105 @@ impl MqtliArgs {
 
         let topics = self.assemble_topics(self.topics.clone())?;
 
-        builder.broker(self.broker.merge(other.broker)?);
+        let mut broker = self.broker.merge(other.broker)?;
 
         builder.log_level(match self.log_level {
             None => other.log_level,
             Some(log_level) => log_level,
         });
 
+        builder.log_filter(self.log_filter.or(other.log_filter));
+
+        builder.log_format(self.log_format.unwrap_or(other.log_format));
+
+        builder.log_file(match self.log_file_path {
+            Some(path) => Some(LogFile {
+                path,
+                rotation: self.log_rotation.unwrap_or_default(),
+            }),
+            None => self.log_file.or(other.log_file).map(|mut log_file| {
+                if let Some(rotation) = self.log_rotation {
+                    log_file.rotation = rotation;
+                }
+                log_file
+            }),
+        });
+
+        builder.no_color(self.no_color || other.no_color);
+        builder.theme(self.theme.unwrap_or(other.theme));
+
+        builder.stats_interval(self.stats.or(other.stats_interval));
+
+        builder.payload_limit(match self.max_payload_size {
+            Some(max_size) => Some(PayloadLimit {
+                max_size,
+                action: self.payload_limit_action.unwrap_or_default(),
+            }),
+            None => self.payload_limit.or(other.payload_limit).map(|mut limit| {
+                if let Some(action) = self.payload_limit_action {
+                    limit.action = action;
+                }
+                limit
+            }),
+        });
+
+        builder.workers(self.workers.unwrap_or(other.workers));
+
+        let mut channels = self.channels.unwrap_or(other.channels);
+        if let Some(capacity) = self.channel_capacity {
+            channels.capacity = capacity;
+        }
+        if let Some(overflow) = self.channel_overflow {
+            channels.overflow = overflow;
+        }
+        builder.channels(channels);
+
+        builder.control_topic(self.control_topic.or(other.control_topic));
+
+        builder.dead_letter(match self.dead_letter_topic {
+            Some(topic) => Some(match self.dead_letter.or(other.dead_letter) {
+                Some(dead_letter) => DeadLetter {
+                    topic,
+                    ..dead_letter
+                },
+                None => DeadLetter::new(topic),
+            }),
+            None => self.dead_letter.or(other.dead_letter),
+        });
+
+        builder.shutdown_timeout(self.shutdown_timeout.unwrap_or(other.shutdown_timeout));
+        builder.force_quit_timeout(self.force_quit_timeout.or(other.force_quit_timeout));
+
+        builder.systemd(self.systemd || other.systemd);
+        builder.show_broker_info(self.show_broker_info || other.show_broker_info);
+
+        builder.connection_hooks(self.connection_hooks.unwrap_or(other.connection_hooks));
+        builder.alerts(self.alerts.unwrap_or(other.alerts));
+        builder.connection_client_id(
+            self.connection_client_id
+                .unwrap_or(other.connection_client_id),
+        );
+        builder.clients(self.clients.unwrap_or(other.clients));
+        builder.clients_client_id(self.clients_client_id.unwrap_or(other.clients_client_id));
+
+        builder.on_publish_complete(
+            self.on_publish_complete
+                .unwrap_or(other.on_publish_complete),
+        );
+
+        builder.publish_rate(self.publish_rate.or(other.publish_rate));
+
+        builder.sparkplug(other.sparkplug);
+        builder.ping(other.ping);
+        builder.topic_stats(other.topic_stats);
+        builder.self_test(other.self_test);
+        builder.proto_check(other.proto_check);
+
+        let mut bridge = match self.bridge {
+            Some(bridge_args) => bridge_args.merge(other.bridge)?,
+            None => other.bridge,
+        };
+
         match self.command {
             None => {
                 builder.mode(Mode::MultiTopic);
@@ -84,24 +464,102 @@ impl MqtliArgs {
                 match command {
                     Command::Publish(_) => builder.mode(Mode::Publish),
                     Command::Subscribe(_) => builder.mode(Mode::Subscribe),
-                    Command::Sparkplug(_) => builder.mode(Mode::Sparkplug),
+                    Command::Copy(_) => builder.mode(Mode::Copy),
+                    Command::Ping(config) => builder.mode(Mode::Ping).ping(config.get_ping()),
+                    Command::Topics(config) => builder
+                        .mode(Mode::TopicStats)
+                        .topic_stats(config.get_topic_stats()),
+                    // man pages are generated before the configuration is loaded
+                    Command::Config(_) | Command::Man(_) => builder.mode(Mode::ConfigShow),
+                    Command::Proto(config) => builder
+                        .mode(Mode::ProtoCheck)
+                        .proto_check(config.get_proto_check()),
+                    Command::SelfTest(config) => builder
+                        .mode(Mode::SelfTest)
+                        .self_test(config.get_self_test()),
+                    Command::Bridge(config) => {
+                        bridge = config.merge(bridge)?;
+                        builder.mode(Mode::Bridge)
+                    }
+                    Command::Sparkplug(config) => {
+                        let emulator = config.get_emulator();
+                        let check = config.get_check();
+
+                        if let Some(emulator) = &emulator {
+                            broker.last_will =
+                                Some(EdgeNodeEmulator::new(emulator.clone()).last_will()?);
+                        }
+
+                        builder
+                            .mode(if emulator.is_some() {
+                                Mode::SparkplugEmulator
+                            } else if check.is_some() {
+                                Mode::SparkplugCheck
+                            } else {
+                                Mode::Sparkplug
+                            })
+                            .sparkplug(SparkplugConfig {
+                                request_rebirth: config.request_rebirth,
+                                tui: config.tui,
+                                store_sql: config.sql,
+                                summary_interval: config.summary_interval,
+                                snapshot: config.get_snapshot_export(),
+                                primary_host: config.get_primary_host(),
+                                emulator,
+                                check: check.unwrap_or_default(),
+                                metric_filter: SparkplugMetricFilter {
+                                    include: config.include_metrics,
+                                    exclude: config.exclude_metrics,
+                                },
+                            })
+                    }
                 };
             }
         };
 
-        builder.topic_storage(TopicStorage {
+        builder.broker(broker);
+        builder.bridge(bridge);
+
+        let mut topic_storage = TopicStorage {
             topics: other
                 .topic_storage
                 .topics
                 .into_iter()
                 .chain(topics)
                 .collect(),
-        });
+        };
+        topic_storage.disable_topics(&self.only_topic, &self.skip_topic);
+
+        builder.topic_storage(topic_storage);
 
         builder.sql_storage(match self.sql_storage {
             None => other.sql_storage,
             Some(sql) => Some(SqlStorageConfig {
-                connection_string: sql.connection_string,
+                connection_string: resolve_secret(
+                    sql.connection_string,
+                    sql.connection_string_cmd.as_deref(),
+                    sql.connection_string_keyring.as_ref(),
+                )?
+                .ok_or(ArgsError::SqlConnectionStringMissing)?,
+                queue_size: sql
+                    .queue_size
+                    .unwrap_or(SqlStorageConfig::default().queue_size),
+                queue_overflow: sql.queue_overflow.unwrap_or_default(),
+                buffer_path: sql.buffer_path,
+                buffer_size: sql
+                    .buffer_size
+                    .unwrap_or(SqlStorageConfig::default().buffer_size),
+                retry_interval: sql
+                    .retry_interval
+                    .unwrap_or(SqlStorageConfig::default().retry_interval),
+                statements: sql.statements.unwrap_or_default(),
+            }),
+        });
+
+        builder.http_api(match self.http_api {
+            None => other.http_api,
+            Some(http_api) => Some(HttpApiConfig {
+                listen: http_api.listen,
             }),
         });
 
@@ -115,12 +573,14 @@ impl MqtliArgs {
         let mut result = Vec::new();
 
         if let Some(command) = self.command.as_ref() {
-            result.extend(command.get_topics()?);
+            result.extend(command.get_topics(self.publish_rate)?);
 
-            if let Command::Sparkplug(config) = command {
-                if config.include_topics_from_file {
+            match command {
+                Command::Sparkplug(config) if config.include_topics_from_file => {
                     result.extend(topics_from_config_file);
                 }
+                Command::Config(_) => result.extend(topics_from_config_file),
+                _ => {}
             }
         } else {
             result.extend(topics_from_config_file);
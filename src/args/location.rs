@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+/// Fields of the config whose key in the config file differs from their name.
+const RENAMED_FIELDS: [(&str, &str); 1] = [("sql_storage", "database")];
+
+const TOPICS_PATH: &str = "topic_storage.topics";
+
+/// Position of a value in a config file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    /// Content of the value, if it is a scalar
+    pub value: Option<String>,
+}
+
+/// Positions of all values of a YAML document by their path, e.g.
+/// `topics[2].subscription.qos`.
+#[derive(Debug, Default)]
+pub struct YamlLocations {
+    values: HashMap<String, Location>,
+    /// Index in the document of each topic, if topics were added or removed
+    /// after parsing
+    topic_origins: Option<Vec<usize>>,
+}
+
+impl YamlLocations {
+    /// Collects the positions of the values. Syntax errors are ignored, they
+    /// are reported when deserializing the config.
+    pub fn parse(content: &str) -> Self {
+        let mut collector = LocationCollector::default();
+        let _ = Parser::new_from_str(content).load(&mut collector, false);
+
+        Self {
+            values: collector.locations,
+            topic_origins: None,
+        }
+    }
+
+    /// Collects the positions of the values of a document which only
+    /// contains the list of topics, as if it were the topics of a config.
+    pub fn parse_topics(content: &str) -> Self {
+        let mut locations = Self::parse(content);
+        locations.values = locations
+            .values
+            .into_iter()
+            .map(|(path, location)| (format!("topics{path}"), location))
+            .collect();
+
+        locations
+    }
+
+    pub fn with_topic_origins(mut self, topic_origins: Option<Vec<usize>>) -> Self {
+        self.topic_origins = topic_origins;
+        self
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Location> {
+        self.values.get(path)
+    }
+
+    /// Returns the index in the document of the topic at the given index.
+    fn topic_origin(&self, index: usize) -> Option<usize> {
+        match &self.topic_origins {
+            None => Some(index),
+            Some(topic_origins) => topic_origins.get(index).copied(),
+        }
+    }
+}
+
+enum Frame {
+    Mapping { path: String, key: Option<String> },
+    Sequence { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct LocationCollector {
+    frames: Vec<Frame>,
+    locations: HashMap<String, Location>,
+}
+
+impl LocationCollector {
+    /// Returns the path of the next value, or none if the next scalar is the
+    /// key of a mapping.
+    fn next_path(&mut self) -> Option<String> {
+        match self.frames.last_mut() {
+            None => Some(String::new()),
+            Some(Frame::Mapping { path, key }) => key.take().map(|key| {
+                if path.is_empty() {
+                    key
+                } else {
+                    format!("{path}.{key}")
+                }
+            }),
+            Some(Frame::Sequence { path, index }) => {
+                *index += 1;
+                Some(format!("{path}[{}]", *index - 1))
+            }
+        }
+    }
+
+    fn add(&mut self, path: &str, mark: Marker, value: Option<String>) {
+        self.locations.insert(
+            path.to_string(),
+            Location {
+                line: mark.line(),
+                column: mark.col() + 1,
+                value,
+            },
+        );
+    }
+}
+
+impl MarkedEventReceiver for LocationCollector {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => match self.next_path() {
+                Some(path) => self.add(&path, mark, Some(value)),
+                None => {
+                    if let Some(Frame::Mapping { key, .. }) = self.frames.last_mut() {
+                        *key = Some(value);
+                    }
+                }
+            },
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                // complex mapping keys are not supported in the config
+                let path = self.next_path().unwrap_or_default();
+                self.add(&path, mark, None);
+
+                self.frames.push(match event {
+                    Event::MappingStart(..) => Frame::Mapping { path, key: None },
+                    _ => Frame::Sequence { path, index: 0 },
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.frames.pop();
+            }
+            Event::Alias(..) => {
+                self.next_path();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Config files in the order they were merged, to find the location of
+/// invalid values in the files.
+#[derive(Debug, Default)]
+pub struct ConfigSources {
+    files: Vec<(PathBuf, YamlLocations)>,
+    /// File and index in the file of each topic of the merged config
+    topics: Vec<(usize, usize)>,
+}
+
+impl ConfigSources {
+    pub fn add_file(&mut self, path: PathBuf, locations: YamlLocations, topic_count: usize) {
+        let file = self.files.len();
+        self.topics
+            .extend((0..topic_count).map(|index| (file, index)));
+        self.files.push((path, locations));
+    }
+
+    /// Returns the file and location of the value of the merged config at the
+    /// given path. Values which are not in any file, e.g. missing values, are
+    /// located at their closest parent.
+    pub fn find(&self, path: &str) -> Option<(&Path, &Location)> {
+        if let Some(rest) = path.strip_prefix(TOPICS_PATH) {
+            let (index, rest) = rest.strip_prefix('[')?.split_once(']')?;
+            let (file, index) = *self.topics.get(index.parse::<usize>().ok()?)?;
+            let (file_path, locations) = &self.files[file];
+            let index = locations.topic_origin(index)?;
+
+            return find_closest(locations, &format!("topics[{index}]{rest}"))
+                .map(|location| (file_path.as_path(), location));
+        }
+
+        let path = RENAMED_FIELDS
+            .iter()
+            .find_map(|(field, key)| {
+                path.strip_prefix(field)
+                    .filter(|rest| rest.is_empty() || rest.starts_with('.'))
+                    .map(|rest| format!("{key}{rest}"))
+            })
+            .unwrap_or_else(|| path.to_string());
+
+        // later files override the values of earlier files
+        self.files.iter().rev().find_map(|(file_path, locations)| {
+            locations
+                .get(&path)
+                .map(|location| (file_path.as_path(), location))
+        })
+    }
+
+    /// Lists each validation error with the file, line and value it refers to.
+    pub fn report(&self, errors: &ValidationErrors) -> String {
+        let mut report = String::new();
+
+        for (path, error) in flatten_errors(String::new(), errors) {
+            let location = self.find(&path);
+
+            if let Some((file, location)) = location {
+                let _ = write!(
+                    report,
+                    "\n  {}:{}:{}: ",
+                    file.display(),
+                    location.line,
+                    location.column
+                );
+            } else {
+                report.push_str("\n  ");
+            }
+
+            let message = error.message.clone().unwrap_or_else(|| error.code.clone());
+            let path = path.replace(TOPICS_PATH, "topics");
+            let _ = write!(report, "{path}: {message}");
+
+            let value = location
+                .and_then(|(_, location)| location.value.clone())
+                .or_else(|| error.params.get("value").map(|value| value.to_string()));
+            if let Some(value) = value {
+                let _ = write!(report, " (value: {value})");
+            }
+        }
+
+        report
+    }
+}
+
+fn find_closest<'a>(locations: &'a YamlLocations, path: &str) -> Option<&'a Location> {
+    let mut path = path;
+
+    loop {
+        if let Some(location) = locations.get(path) {
+            return Some(location);
+        }
+
+        path = &path[..path.rfind(['.', '['])?];
+    }
+}
+
+fn flatten_errors(path: String, errors: &ValidationErrors) -> Vec<(String, ValidationError)> {
+    let mut result = Vec::new();
+
+    let mut fields: Vec<_> = errors.errors().iter().collect();
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (field, kind) in fields {
+        let field_path = match (field.as_ref(), path.is_empty()) {
+            ("__all__", _) => path.clone(),
+            (field, true) => field.to_string(),
+            (field, false) => format!("{path}.{field}"),
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                result.extend(
+                    errors
+                        .iter()
+                        .map(|error| (field_path.clone(), error.clone())),
+                );
+            }
+            ValidationErrorsKind::Struct(errors) => {
+                result.extend(flatten_errors(field_path, errors));
+            }
+            ValidationErrorsKind::List(errors) => {
+                for (index, errors) in errors {
+                    result.extend(flatten_errors(format!("{field_path}[{index}]"), errors));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+broker:
+  host: localhost
+  keep_alive: 2
+topics:
+  - topic: a/b
+  - topic: c/d
+    subscription:
+      outputs:
+        - format:
+            type: json
+"#;
+
+    #[test]
+    fn parse_topic_list_locations() {
+        let locations = YamlLocations::parse_topics("- topic: a/b\n- topic: c/d\n");
+
+        assert_eq!(
+            Some(&Location {
+                line: 2,
+                column: 10,
+                value: Some("c/d".to_string())
+            }),
+            locations.get("topics[1].topic")
+        );
+        assert!(locations.get("[1].topic").is_none());
+    }
+
+    #[test]
+    fn parse_locations() {
+        let locations = YamlLocations::parse(CONFIG);
+
+        assert_eq!(
+            Some(&Location {
+                line: 4,
+                column: 15,
+                value: Some("2".to_string())
+            }),
+            locations.get("broker.keep_alive")
+        );
+        assert_eq!(
+            Some(7),
+            locations
+                .get("topics[1].topic")
+                .map(|location| location.line)
+        );
+        assert_eq!(
+            Some(11),
+            locations
+                .get("topics[1].subscription.outputs[0].format.type")
+                .map(|location| location.line)
+        );
+    }
+
+    #[test]
+    fn find_in_merged_config() {
+        let mut sources = ConfigSources::default();
+        sources.add_file(
+            PathBuf::from("shared.yaml"),
+            YamlLocations::parse(CONFIG),
+            2,
+        );
+        sources.add_file(
+            PathBuf::from("project.yaml"),
+            YamlLocations::parse("topics:\n  - topic: e/f\n"),
+            1,
+        );
+
+        let (file, location) = sources.find("topic_storage.topics[2].topic").unwrap();
+        assert_eq!(Path::new("project.yaml"), file);
+        assert_eq!(2, location.line);
+
+        let (file, location) = sources
+            .find("topic_storage.topics[1].subscription.outputs[0].target")
+            .unwrap();
+        assert_eq!(Path::new("shared.yaml"), file);
+        assert_eq!(10, location.line);
+
+        let (file, location) = sources.find("broker.keep_alive").unwrap();
+        assert_eq!(Path::new("shared.yaml"), file);
+        assert_eq!(4, location.line);
+
+        assert!(sources.find("topic_storage.topics[3].topic").is_none());
+    }
+}
@@ -1,23 +1,31 @@
 pub mod broker;
 mod command;
 pub mod content;
+mod location;
 mod parsers;
+mod topic_defaults;
+mod topic_templates;
 
 use crate::args::command::Command;
 use crate::args::content::MqtliArgs;
-use clap::Parser;
+use crate::args::location::{ConfigSources, YamlLocations};
+use crate::args::topic_defaults::apply_topic_defaults;
+use crate::args::topic_templates::apply_topic_templates;
 use mqtlib::config::mqtli_config::MqtliConfigBuilderError;
 use mqtlib::config::mqtli_config::{
-    LastWillConfigBuilderError, MqtliConfig, MqttBrokerConnectBuilderError,
+    BirthConfigBuilderError, ChaosConfigBuilderError, LastWillConfigBuilderError, MqtliConfig,
+    MqttBrokerConnectBuilderError, StatusConfigBuilderError,
 };
 use mqtlib::config::publish::PublishBuilderError;
+use mqtlib::config::secret::SecretError;
 use mqtlib::config::subscription::SubscriptionBuilderError;
-use mqtlib::config::topic::TopicBuilderError;
+use mqtlib::config::topic::{Topic, TopicBuilderError};
+use mqtlib::payload::PayloadFormatError;
 use std::fmt::Debug;
 use std::fs::read_to_string;
 use std::io;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 use validator::{Validate, ValidationErrors};
 
@@ -27,6 +35,12 @@ pub enum ArgsError {
     BrokerConfig(#[from] MqttBrokerConnectBuilderError),
     #[error("Error while parsing last will args")]
     LastWillConfig(#[from] LastWillConfigBuilderError),
+    #[error("Error while parsing birth args")]
+    BirthConfig(#[from] BirthConfigBuilderError),
+    #[error("Error while parsing status args")]
+    StatusConfig(#[from] StatusConfigBuilderError),
+    #[error("Error while parsing chaos args")]
+    ChaosConfig(#[from] ChaosConfigBuilderError),
     #[error("Error while parsing config args")]
     MqtliConfig(#[from] MqtliConfigBuilderError),
     #[error("Error while parsing topic args")]
@@ -39,54 +53,137 @@ pub enum ArgsError {
     CouldNotReadConfigFile(#[source] io::Error, PathBuf),
     #[error("Could not parse config file \"{1}\"")]
     CouldNotParseConfigFile(#[source] serde_yaml::Error, PathBuf),
-    #[error("Invalid configuration")]
-    InvalidConfiguration(#[source] ValidationErrors),
+    #[error("Could not parse the topics of the environment variable MQTLI_TOPICS")]
+    CouldNotParseTopicsFromEnv(#[source] serde_yaml::Error),
+    #[error("Config file \"{0}\" includes itself")]
+    CircularInclude(PathBuf),
+    #[error("Could not read secret")]
+    Secret(#[from] SecretError),
+    #[error("SQL connection string must be given")]
+    SqlConnectionStringMissing,
+    #[error("Invalid configuration:{1}")]
+    InvalidConfiguration(#[source] ValidationErrors, String),
     #[error("Error while reading data from stdin")]
     StdInError(#[from] io::Error),
+    #[error("Could not read the last will payload file \"{1}\"")]
+    CouldNotReadLastWillPayloadFile(#[source] io::Error, PathBuf),
+    #[error("Could not convert the last will payload")]
+    CouldNotConvertLastWillPayload(#[source] PayloadFormatError),
+    #[error("Could not create the death certificate of the emulated edge node")]
+    SparkplugDeathCertificate(#[from] PayloadFormatError),
+    #[error("Could not read the directory to publish")]
+    CouldNotReadPublishDirectory(#[source] PayloadFormatError),
+    #[error("Bridge requires at least one topic or rule")]
+    BridgeRulesMissing,
+    #[error("Could not write the man pages to \"{1}\"")]
+    CouldNotWriteManPages(#[source] io::Error, PathBuf),
+    #[error("Could not read the topics file \"{1}\"")]
+    CouldNotReadTopicsFile(#[source] io::Error, PathBuf),
+    #[error("Invalid line {1} in the topics file \"{0}\": {2}")]
+    InvalidTopicsFileLine(PathBuf, usize, String),
+    #[error("Copy target \"{1}\" matches the source topic \"{0}\"")]
+    CopyLoop(String, String),
+    #[error("--message-type {0} requires --file")]
+    MessageTypeRequiresFile(String),
+    #[error("Publish rate {0} must be greater than 0 and at most 1000000000 messages per second")]
+    InvalidPublishRate(f64),
 }
 
-pub fn load_config() -> Result<MqtliConfig, ArgsError> {
-    let mut args = MqtliArgs::parse();
+/// Environment variable containing the topics as YAML or JSON list, e.g. in
+/// containers without a config file.
+const TOPICS_ENV: &str = "MQTLI_TOPICS";
+
+/// Generates the man pages if the man command is given, returns true if they
+/// were generated. The configuration is not loaded then.
+pub fn generate_man_pages(args: &MqtliArgs) -> Result<bool, ArgsError> {
+    match &args.command {
+        Some(Command::Man(command)) => command
+            .generate::<MqtliArgs>()
+            .map(|_| true)
+            .map_err(|e| ArgsError::CouldNotWriteManPages(e, command.output_dir.clone())),
+        _ => Ok(false),
+    }
+}
+
+pub fn load_config(mut args: MqtliArgs) -> Result<MqtliConfig, ArgsError> {
     let mut config = MqtliConfig::default();
 
-    let config_file_path = match &args.config_file {
-        None => PathBuf::from("config.yaml"),
-        Some(config_file) => config_file.to_path_buf(),
+    let config_file_paths = if args.config_file.is_empty() {
+        vec![PathBuf::from("config.yaml")]
+    } else {
+        args.config_file.clone()
+    };
+
+    let clear_topics = match &args.command {
+        None | Some(Command::Config(_) | Command::Man(_) | Command::Proto(_)) => false,
+        Some(
+            Command::Publish(_)
+            | Command::Subscribe(_)
+            | Command::Copy(_)
+            | Command::Ping(_)
+            | Command::Bridge(_)
+            | Command::Topics(_)
+            | Command::SelfTest(_),
+        ) => true,
+        Some(Command::Sparkplug(config)) => !config.include_topics_from_file,
     };
 
-    match read_config_from_file(&config_file_path) {
-        Ok(mut config_from_file) => {
-            if let Some(command) = &args.command {
-                match command {
-                    Command::Publish(_) | Command::Subscribe(_) => {
+    let topics_from_env = read_topics_from_env()?;
+
+    let mut loaded = vec![];
+    let mut sources = ConfigSources::default();
+    for config_file_path in config_file_paths {
+        match read_config_with_includes(&config_file_path, &mut vec![], &mut loaded) {
+            Ok(configs_from_file) => {
+                for (path, mut config_from_file, locations) in configs_from_file {
+                    if clear_topics {
                         config_from_file.topics.clear();
                     }
-                    Command::Sparkplug(config) => {
-                        if !config.include_topics_from_file {
-                            config_from_file.topics.clear();
-                        }
-                    }
+                    sources.add_file(path, locations, config_from_file.topics.len());
+                    config = config_from_file.merge(config)?;
                 }
             }
-            config = config_from_file.merge(config)?;
-        }
-        Err(e) => match e {
-            ArgsError::CouldNotReadConfigFile(_, _) => match args.command.as_ref() {
-                Some(_) => {}
+            Err(e) => match e {
+                ArgsError::CouldNotReadConfigFile(_, ref path) if *path == config_file_path => {
+                    if args.command.is_none() && topics_from_env.is_none() {
+                        return Err(e);
+                    }
+                }
                 _ => return Err(e),
             },
-            _ => return Err(e),
-        },
-    };
+        };
+    }
+
+    // topics from the environment are added after the topics of the files
+    if let Some((topics, locations)) = topics_from_env.filter(|_| !clear_topics) {
+        sources.add_file(PathBuf::from(TOPICS_ENV), locations, topics.len());
+        config.topic_storage.topics.extend(topics);
+    }
 
     move_stdin_to_message(&mut args)?;
 
     config = args.merge(config)?;
 
-    config
-        .validate()
-        .map(|_| config)
-        .map_err(ArgsError::InvalidConfiguration)
+    if let Some(last_will) = config.broker.last_will.as_mut() {
+        last_will
+            .convert_payload(&config.topic_storage)
+            .map_err(ArgsError::CouldNotConvertLastWillPayload)?;
+    }
+
+    config.validate().map(|_| config).map_err(|errors| {
+        let report = sources.report(&errors);
+        ArgsError::InvalidConfiguration(errors, report)
+    })
+}
+
+fn read_topics_from_env() -> Result<Option<(Vec<Topic>, YamlLocations)>, ArgsError> {
+    let Ok(content) = std::env::var(TOPICS_ENV) else {
+        return Ok(None);
+    };
+
+    let topics = serde_yaml::from_str(&content).map_err(ArgsError::CouldNotParseTopicsFromEnv)?;
+
+    Ok(Some((topics, YamlLocations::parse_topics(&content))))
 }
 
 fn move_stdin_to_message(args: &mut MqtliArgs) -> Result<(), io::Error> {
@@ -96,14 +193,48 @@ fn move_stdin_to_message(args: &mut MqtliArgs) -> Result<(), io::Error> {
             let mut buf_from_stdin = Vec::new();
             stdin.lock().read_to_end(&mut buf_from_stdin)?;
 
-            publish_command.message.message = Some(Box::new(buf_from_stdin));
+            publish_command.message.message = vec![Box::new(buf_from_stdin)];
         }
     }
 
     Ok(())
 }
 
-fn read_config_from_file(buf: &PathBuf) -> Result<MqtliArgs, ArgsError> {
+/// Reads the config file and all files it includes, in the order they must be
+/// merged: included files first, so that the including file overrides them.
+/// Files included more than once are only read the first time.
+fn read_config_with_includes(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    loaded: &mut Vec<PathBuf>,
+) -> Result<Vec<(PathBuf, MqtliArgs, YamlLocations)>, ArgsError> {
+    let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    if including.contains(&canonical_path) {
+        return Err(ArgsError::CircularInclude(path.to_path_buf()));
+    }
+    if loaded.contains(&canonical_path) {
+        return Ok(vec![]);
+    }
+
+    let (mut config, locations) = read_config_from_file(path)?;
+    let mut configs = Vec::new();
+
+    including.push(canonical_path.clone());
+    for include in std::mem::take(&mut config.include) {
+        // relative paths are resolved against the directory of the including file
+        let include_path = path.parent().unwrap_or(Path::new("")).join(include);
+        configs.extend(read_config_with_includes(&include_path, including, loaded)?);
+    }
+    including.pop();
+
+    loaded.push(canonical_path);
+    configs.push((path.to_path_buf(), config, locations));
+
+    Ok(configs)
+}
+
+fn read_config_from_file(buf: &Path) -> Result<(MqtliArgs, YamlLocations), ArgsError> {
     let content = match read_to_string(buf) {
         Ok(content) => content,
         Err(e) => {
@@ -111,12 +242,31 @@ fn read_config_from_file(buf: &PathBuf) -> Result<MqtliArgs, ArgsError> {
         }
     };
 
-    let config: MqtliArgs = match serde_yaml::from_str(content.as_str()) {
+    let (config, topic_origins) = match parse_config(content.as_str()) {
         Ok(config) => config,
         Err(e) => {
             return Err(ArgsError::CouldNotParseConfigFile(e, PathBuf::from(buf)));
         }
     };
 
-    Ok(config)
+    let locations = YamlLocations::parse(&content).with_topic_origins(topic_origins);
+
+    Ok((config, locations))
+}
+
+/// Parses the config and returns the index in the file of each topic, unless
+/// it is the same as in the config.
+fn parse_config(content: &str) -> Result<(MqtliArgs, Option<Vec<usize>>), serde_yaml::Error> {
+    let mut config: serde_yaml::Value = serde_yaml::from_str(content)?;
+
+    let topic_origins = apply_topic_templates(&mut config)
+        .map_err(<serde_yaml::Error as serde::de::Error>::custom)?;
+    let has_defaults = apply_topic_defaults(&mut config);
+
+    if topic_origins.is_some() || has_defaults {
+        Ok((serde_yaml::from_value(config)?, topic_origins))
+    } else {
+        // parsing the content directly keeps the location in error messages
+        Ok((serde_yaml::from_str(content)?, None))
+    }
 }
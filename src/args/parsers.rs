@@ -2,6 +2,7 @@ use mqtlib::config::deserialize_qos;
 use mqtlib::mqtt::QoS;
 use serde::de::{Error, Unexpected};
 use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::time::Duration;
 use tracing::Level;
@@ -14,6 +15,16 @@ where
     Ok(Some(Duration::from_secs(value)))
 }
 
+pub fn deserialize_duration_milliseconds<'a, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let value: u64 = Deserialize::deserialize(deserializer)?;
+    Ok(Some(Duration::from_millis(value)))
+}
+
 pub fn deserialize_qos_option<'a, D>(deserializer: D) -> Result<Option<QoS>, D::Error>
 where
     D: Deserializer<'a>,
@@ -37,6 +48,22 @@ pub fn parse_duration_milliseconds(input: &str) -> Result<Duration, String> {
     Ok(Duration::from_millis(duration_in_milliseconds))
 }
 
+pub fn parse_key_value(input: &str) -> Result<(String, String), String> {
+    let (key, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("{input} is not a key=value pair"))?;
+
+    Ok((key.to_string(), value.to_string()))
+}
+
+pub fn deserialize_key_value_map<'a, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let value: BTreeMap<String, String> = Deserialize::deserialize(deserializer)?;
+    Ok(value.into_iter().collect())
+}
+
 pub fn parse_qos(input: &str) -> Result<QoS, String> {
     let qos: QoS = match input {
         "0" => QoS::AtMostOnce,
@@ -48,9 +75,12 @@ pub fn parse_qos(input: &str) -> Result<QoS, String> {
     Ok(qos)
 }
 
-#[allow(clippy::box_collection)]
-pub fn parse_string_as_vec(input: &str) -> Result<Box<Vec<u8>>, String> {
-    Ok(Box::new(Vec::from(input)))
+pub fn parse_json(input: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(input).map_err(|e| format!("{input} is not valid JSON: {e}"))
+}
+
+pub fn parse_string_as_vec(input: &str) -> Result<Vec<u8>, String> {
+    Ok(Vec::from(input))
 }
 
 pub fn deserialize_level_filter<'a, D>(deserializer: D) -> Result<Option<Level>, D::Error>
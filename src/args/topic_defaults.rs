@@ -0,0 +1,161 @@
+use serde_yaml::{Mapping, Value};
+
+const TOPIC_DEFAULTS_KEY: &str = "topic_defaults";
+const TOPICS_KEY: &str = "topics";
+
+/// Sections of a topic whose defaults only apply if the topic has the section,
+/// so that defaults don't turn every topic into a subscription.
+const OPTIONAL_SECTIONS: [&str; 2] = ["subscription", "publish"];
+
+/// Removes the `topic_defaults` section from the config and merges it into
+/// every topic of the config. Returns true if the config had defaults.
+pub fn apply_topic_defaults(config: &mut Value) -> bool {
+    let Some(config) = config.as_mapping_mut() else {
+        return false;
+    };
+    let Some(defaults) = config.remove(TOPIC_DEFAULTS_KEY) else {
+        return false;
+    };
+
+    if let Some(Value::Sequence(topics)) = config.get_mut(TOPICS_KEY) {
+        for topic in topics.iter_mut().filter_map(Value::as_mapping_mut) {
+            merge_topic_defaults(topic, &defaults);
+        }
+    }
+
+    true
+}
+
+fn merge_topic_defaults(topic: &mut Mapping, defaults: &Value) {
+    let Some(defaults) = defaults.as_mapping() else {
+        return;
+    };
+
+    for (key, default) in defaults {
+        match topic.get_mut(key) {
+            Some(value) => merge_defaults(value, default),
+            None if is_optional_section(key) => {}
+            None => {
+                topic.insert(key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+fn is_optional_section(key: &Value) -> bool {
+    key.as_str()
+        .is_some_and(|key| OPTIONAL_SECTIONS.contains(&key))
+}
+
+/// Adds the defaults missing in the value. Mappings are merged recursively,
+/// unless they have a different `type`, all other values replace the default.
+pub fn merge_defaults(value: &mut Value, default: &Value) {
+    let (Value::Mapping(value), Value::Mapping(default)) = (value, default) else {
+        return;
+    };
+
+    if let (Some(value_type), Some(default_type)) = (value.get("type"), default.get("type")) {
+        if value_type != default_type {
+            return;
+        }
+    }
+
+    for (key, default) in default {
+        match value.get_mut(key) {
+            Some(value) => merge_defaults(value, default),
+            None => {
+                value.insert(key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(config: &str) -> Value {
+        let mut config: Value = serde_yaml::from_str(config).unwrap();
+        assert!(apply_topic_defaults(&mut config));
+        config
+    }
+
+    #[test]
+    fn defaults_apply_to_topics() {
+        let config = apply(
+            r#"
+topic_defaults:
+  payload: { type: json }
+  subscription:
+    enabled: true
+    qos: 1
+    outputs:
+      - format: { type: json }
+topics:
+  - topic: a
+    subscription:
+      qos: 2
+  - topic: b
+    payload: { type: text }
+    subscription:
+      outputs:
+        - format: { type: text }
+  - topic: c
+    publish:
+      input: { type: text, content: "x" }
+"#,
+        );
+
+        let expected: Value = serde_yaml::from_str(
+            r#"
+topics:
+  - topic: a
+    payload: { type: json }
+    subscription:
+      enabled: true
+      qos: 2
+      outputs:
+        - format: { type: json }
+  - topic: b
+    payload: { type: text }
+    subscription:
+      enabled: true
+      qos: 1
+      outputs:
+        - format: { type: text }
+  - topic: c
+    payload: { type: json }
+    publish:
+      input: { type: text, content: "x" }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(expected, config);
+    }
+
+    #[test]
+    fn defaults_with_other_type_are_ignored() {
+        let config = apply(
+            r#"
+topic_defaults:
+  payload: { type: protobuf, definition: a.proto, message: A }
+topics:
+  - topic: a
+    payload: { type: json }
+"#,
+        );
+
+        assert_eq!(
+            Some("json"),
+            config["topics"][0]["payload"]["type"].as_str()
+        );
+        assert!(config["topics"][0]["payload"].get("definition").is_none());
+    }
+
+    #[test]
+    fn no_defaults() {
+        let mut config: Value = serde_yaml::from_str("topics: []").unwrap();
+        assert!(!apply_topic_defaults(&mut config));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::args::topic_defaults::merge_defaults;
+use serde_yaml::{Mapping, Value};
+
+const TOPIC_TEMPLATES_KEY: &str = "topic_templates";
+const TOPICS_KEY: &str = "topics";
+const TEMPLATE_KEY: &str = "template";
+const PARAMETERS_KEY: &str = "parameters";
+
+/// Removes the `topic_templates` section from the config and replaces every
+/// topic referencing a template with the template, parameterized and merged
+/// with the values of the topic. A topic with a list of parameters becomes
+/// one topic per entry. Returns the index of the original topic of each
+/// topic, or none if the config has no templates.
+pub fn apply_topic_templates(config: &mut Value) -> Result<Option<Vec<usize>>, String> {
+    let Some(config) = config.as_mapping_mut() else {
+        return Ok(None);
+    };
+    let Some(templates) = config.remove(TOPIC_TEMPLATES_KEY) else {
+        return Ok(None);
+    };
+    let templates = templates
+        .as_mapping()
+        .ok_or_else(|| format!("{TOPIC_TEMPLATES_KEY} must be a mapping of names to templates"))?;
+
+    let mut origins = Vec::new();
+
+    if let Some(Value::Sequence(topics)) = config.get_mut(TOPICS_KEY) {
+        let mut expanded = Vec::with_capacity(topics.len());
+
+        for (index, topic) in topics.drain(..).enumerate() {
+            let topics = expand_topic(topic, templates)?;
+            origins.extend(std::iter::repeat(index).take(topics.len()));
+            expanded.extend(topics);
+        }
+
+        *topics = expanded;
+    }
+
+    Ok(Some(origins))
+}
+
+fn expand_topic(topic: Value, templates: &Mapping) -> Result<Vec<Value>, String> {
+    let Value::Mapping(mut topic) = topic else {
+        return Ok(vec![topic]);
+    };
+    let Some(name) = topic.remove(TEMPLATE_KEY) else {
+        return Ok(vec![Value::Mapping(topic)]);
+    };
+
+    let name = name
+        .as_str()
+        .ok_or_else(|| format!("{TEMPLATE_KEY} must be the name of a topic template"))?;
+    let template = templates
+        .get(name)
+        .ok_or_else(|| format!("Topic template \"{name}\" is not defined"))?;
+
+    let parameters = match topic.remove(PARAMETERS_KEY) {
+        None => vec![Mapping::new()],
+        Some(Value::Mapping(parameters)) => vec![parameters],
+        Some(Value::Sequence(parameters)) => parameters
+            .into_iter()
+            .map(|parameters| match parameters {
+                Value::Mapping(parameters) => Ok(parameters),
+                _ => Err(format!(
+                    "{PARAMETERS_KEY} of template \"{name}\" must be mappings"
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => {
+            return Err(format!(
+                "{PARAMETERS_KEY} of template \"{name}\" must be a mapping or a list of mappings"
+            ))
+        }
+    };
+
+    parameters
+        .iter()
+        .map(|parameters| {
+            let mut result = Value::Mapping(topic.clone());
+            merge_defaults(&mut result, &parameterize(template.clone(), parameters)?);
+            Ok(result)
+        })
+        .collect()
+}
+
+/// Replaces the placeholders `{{name}}` of the given parameters in all strings
+/// of the template. Other placeholders, e.g. of SQL statements, are kept.
+fn parameterize(template: Value, parameters: &Mapping) -> Result<Value, String> {
+    Ok(match template {
+        Value::String(mut content) => {
+            for (name, value) in parameters {
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| "Names of template parameters must be strings".to_string())?;
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    Value::Number(value) => value.to_string(),
+                    Value::Bool(value) => value.to_string(),
+                    _ => return Err(format!("Template parameter \"{name}\" must be a scalar")),
+                };
+
+                content = content.replace(&format!("{{{{{name}}}}}"), &value);
+            }
+
+            Value::String(content)
+        }
+        Value::Sequence(values) => Value::Sequence(
+            values
+                .into_iter()
+                .map(|value| parameterize(value, parameters))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Mapping(values) => Value::Mapping(
+            values
+                .into_iter()
+                .map(|(key, value)| Ok((key, parameterize(value, parameters)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        value => value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+topic_templates:
+  device:
+    topic: devices/{{device}}/data
+    payload: { type: json }
+    subscription:
+      enabled: true
+      outputs:
+        - format: { type: json }
+          target:
+            type: file
+            path: "{{device}}.log"
+topics:
+  - template: device
+    parameters:
+      - device: 1
+      - device: pump
+  - template: device
+    parameters: { device: 2 }
+    payload: { type: text }
+  - topic: other
+"#;
+
+    #[test]
+    fn expand_templates() {
+        let mut config: Value = serde_yaml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            Some(vec![0, 0, 1, 2]),
+            apply_topic_templates(&mut config).unwrap()
+        );
+
+        let topics = config["topics"].as_sequence().unwrap();
+        assert_eq!(4, topics.len());
+        assert_eq!(Some("devices/1/data"), topics[0]["topic"].as_str());
+        assert_eq!(
+            Some("pump.log"),
+            topics[1]["subscription"]["outputs"][0]["target"]["path"].as_str()
+        );
+        assert_eq!(Some("devices/2/data"), topics[2]["topic"].as_str());
+        assert_eq!(Some("text"), topics[2]["payload"]["type"].as_str());
+        assert_eq!(Some("other"), topics[3]["topic"].as_str());
+        assert!(config.get(TOPIC_TEMPLATES_KEY).is_none());
+    }
+
+    #[test]
+    fn unknown_template() {
+        let mut config: Value =
+            serde_yaml::from_str("topic_templates: {}\ntopics:\n  - template: device").unwrap();
+
+        assert!(apply_topic_templates(&mut config).is_err());
+    }
+}
@@ -14,34 +14,49 @@ mod args;
 mod built_info;
 mod tasks;
 
-use std::sync::Arc;
-
-use crate::args::load_config;
-use anyhow::Context;
-use mqtlib::config::mqtli_config::{Mode, MqttVersion};
-use mqtlib::config::subscription::Subscription;
-use mqtlib::config::PayloadType;
-use mqtlib::mqtt::mqtt_handler::MqttHandler;
-use mqtlib::mqtt::v311::mqtt_service::MqttServiceV311;
-use mqtlib::mqtt::v5::mqtt_service::MqttServiceV5;
-use mqtlib::mqtt::{MessageEvent, MqttReceiveEvent, MqttService};
-use mqtlib::publish::trigger_periodic::TriggerPeriodic;
-use mqtlib::sparkplug::network::SparkplugNetwork;
-use mqtlib::storage::get_sql_storage;
-use tokio::sync::broadcast::Sender;
-use tokio::sync::{broadcast, Mutex};
-use tokio::{signal, task};
-use tracing::{error, info, trace, warn, Level};
-use tracing_subscriber::fmt::SubscriberBuilder;
-use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+use std::path::Path;
+
+use crate::args::content::MqtliArgs;
+use crate::args::{generate_man_pages, load_config};
+use clap::Parser;
+use mqtlib::config::effective::effective_config_yaml;
+use mqtlib::config::logging::{LogFile, LogFormat, LogRotation};
+use mqtlib::config::mqtli_config::{Mode, MqtliConfig};
+use mqtlib::Mqtlib;
+use tokio::sync::broadcast;
+use tracing::{info, trace, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
 
 type ExitCommand = ();
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = load_config()?;
+    let args = MqtliArgs::parse();
+    if generate_man_pages(&args)? {
+        return Ok(());
+    }
 
-    init_logger(config.log_level)?;
+    let config = load_config(args)?;
+
+    if config.mode == Mode::ConfigShow {
+        print!("{}", effective_config_yaml(&config)?);
+        return Ok(());
+    }
+
+    if config.no_color {
+        colored::control::set_override(false);
+    }
+    mqtlib::output::theme::set_theme(config.theme);
+
+    // logs written to the file are lost if the guard is dropped early
+    let _log_file_guard = init_logger(&config)?;
 
     info!(
         "MQTli {} version {} starting",
@@ -51,118 +66,97 @@ async fn main() -> anyhow::Result<()> {
 
     trace!("{}", config);
 
-    let (sender_exit, _) = broadcast::channel::<ExitCommand>(5);
-
-    let mqtt_service: Arc<Mutex<dyn MqttService>> = match config.broker().mqtt_version() {
-        MqttVersion::V311 => Arc::new(Mutex::new(MqttServiceV311::new(Arc::new(
-            config.broker().clone(),
-        )))),
-        MqttVersion::V5 => Arc::new(Mutex::new(MqttServiceV5::new(Arc::new(
-            config.broker().clone(),
-        )))),
-    };
-
-    let filtered_subscriptions: Vec<(Subscription, String)> = config
-        .topic_storage
-        .topics
-        .iter()
-        .filter_map(|topic| {
-            topic
-                .subscription()
-                .clone()
-                .map(|s| (s, topic.topic().clone()))
-        })
-        .filter(|(s, _)| *s.enabled())
-        .collect();
-
-    let (sender_receive, _) = broadcast::channel::<MqttReceiveEvent>(32);
-    let (sender_message, _) = broadcast::channel::<MessageEvent>(32);
-
-    let topic_storage = Arc::new(config.topic_storage);
-
-    let mqtt_loop_handle = mqtt_service
-        .lock()
-        .await
-        .connect(sender_receive.clone(), sender_exit.subscribe())
-        .await
-        .with_context(|| "Error while connecting to mqtt broker")?;
-
-    tasks::publish::start_publish_task(sender_message.subscribe(), mqtt_service.clone());
-
-    let scheduler = TriggerPeriodic::new(mqtt_service.clone()).await;
-
-    tasks::scheduler::start_scheduler_monitor_task(
-        mqtt_service.clone(),
-        scheduler.get_receiver_command(),
-        filtered_subscriptions.clone(),
-    );
-
-    tasks::scheduler::start_scheduler_task(
-        scheduler,
-        sender_receive.clone(),
-        topic_storage.clone(),
-        sender_exit.subscribe(),
-    );
-
-    let mut incoming_messages_handler = MqttHandler::new(topic_storage.clone());
-    incoming_messages_handler.start_task(sender_receive.subscribe(), sender_message.clone());
-
-    tasks::subscription::start_subscription_task(
-        mqtt_service,
-        sender_receive,
-        filtered_subscriptions,
-    );
+    let tui = config.sparkplug.tui;
+    let mqtlib = Mqtlib::new(config);
 
-    let exclude_types = match config.mode {
-        Mode::Sparkplug => vec![PayloadType::Sparkplug],
-        _ => vec![],
-    };
+    if tui {
+        // quitting the terminal UI exits mqtli
+        let (sender_exit, mut receiver_exit) = broadcast::channel::<ExitCommand>(5);
+        tasks::sparkplug_tui::start_sparkplug_tui(mqtlib.sparkplug_network(), sender_exit.clone());
 
-    let sparkplug_network = Arc::new(Mutex::new(SparkplugNetwork::default()));
-    tasks::sparkplug::start_sparkplug_monitor(
-        sparkplug_network,
-        topic_storage.clone(),
-        sender_message.subscribe(),
-    );
+        mqtlib
+            .run_until(async move {
+                let _ = receiver_exit.recv().await;
+            })
+            .await?;
 
-    let db = if let Some(sql) = &config.sql_storage {
-        Some(get_sql_storage(sql).await?)
+        let _ = sender_exit.send(());
     } else {
-        None
-    };
-
-    tasks::output::start_output_task(
-        sender_message.subscribe(),
-        topic_storage.clone(),
-        sender_message,
-        exclude_types,
-        Arc::new(db),
-    );
-
-    start_exit_task(sender_exit).await;
-
-    mqtt_loop_handle
-        .await
-        .expect("Error while waiting for tasks to shut down");
+        mqtlib.run().await?;
+    }
 
     Ok(())
 }
 
-async fn start_exit_task(sender: Sender<()>) {
-    task::spawn(async move {
-        if let Err(_e) = signal::ctrl_c().await {
-            error!("Could not add ctrl + c handler");
+fn init_logger(config: &MqtliConfig) -> anyhow::Result<Option<WorkerGuard>> {
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+
+    // log output would corrupt the terminal UI
+    if !config.sparkplug.tui {
+        layers.push(format_layer(
+            config.log_format,
+            std::io::stdout,
+            !config.no_color,
+        ));
+    }
+
+    let guard = match &config.log_file {
+        Some(log_file) => {
+            let (writer, guard) = tracing_appender::non_blocking(file_appender(log_file)?);
+            layers.push(format_layer(config.log_format, writer, false));
+            Some(guard)
         }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::from_level(config.log_level).into())
+                .parse(config.log_filter.as_deref().unwrap_or_default())?,
+        )
+        .try_init()?;
 
-        info!("Exit signal received, shutting down");
+    Ok(guard)
+}
 
-        if let Err(e) = sender.send(()) {
-            warn!("No active listeners for exit signal present: {e:?}");
-        };
-    });
+fn format_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
 }
 
-fn init_logger(level: Level) -> Result<(), TryInitError> {
-    let subscriber = SubscriberBuilder::default().with_max_level(level).finish();
-    subscriber.try_init()
+fn file_appender(log_file: &LogFile) -> Result<RollingFileAppender, InitError> {
+    let rotation = match log_file.rotation {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    let directory = log_file
+        .path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = log_file
+        .path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("mqtli.log"));
+
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name)
+        .build(directory)
 }
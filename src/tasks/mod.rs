@@ -1,5 +1 @@
-pub mod output;
-pub mod publish;
-pub mod scheduler;
-pub mod sparkplug;
-pub mod subscription;
+pub mod sparkplug_tui;
@@ -0,0 +1,207 @@
+use mqtlib::sparkplug::network::SparkplugNetwork;
+use mqtlib::sparkplug::snapshot::{SparkplugMetricSnapshot, SparkplugSnapshot};
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Mutex;
+use tokio::task;
+use tracing::{debug, error};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// One line of the network tree.
+struct Row {
+    depth: usize,
+    label: String,
+    style: Style,
+    metric: Option<(String, SparkplugMetricSnapshot)>,
+}
+
+/// Starts an interactive terminal UI showing the tree of groups, edge nodes,
+/// devices and metrics of the Sparkplug network. Quitting the UI exits mqtli.
+pub fn start_sparkplug_tui(
+    sparkplug_network: Arc<Mutex<SparkplugNetwork>>,
+    sender_exit: Sender<()>,
+) {
+    let mut receiver_exit = sender_exit.subscribe();
+
+    task::spawn_blocking(move || {
+        debug!("Starting sparkplug TUI");
+
+        let mut terminal = ratatui::init();
+        let result = run(&mut terminal, &sparkplug_network, &mut receiver_exit);
+        ratatui::restore();
+
+        if let Err(e) = result {
+            error!("Error in sparkplug TUI: {e:?}");
+        }
+
+        let _ = sender_exit.send(());
+
+        debug!("Sparkplug TUI exited");
+    });
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    sparkplug_network: &Arc<Mutex<SparkplugNetwork>>,
+    receiver_exit: &mut Receiver<()>,
+) -> io::Result<()> {
+    let mut state = ListState::default().with_selected(Some(0));
+
+    loop {
+        if !matches!(receiver_exit.try_recv(), Err(TryRecvError::Empty)) {
+            return Ok(());
+        }
+
+        let rows = build_rows(&sparkplug_network.blocking_lock().snapshot());
+
+        terminal.draw(|frame| render(frame, &rows, &mut state))?;
+
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+            KeyCode::Home => state.select_first(),
+            KeyCode::End => state.select_last(),
+            _ => {}
+        }
+    }
+}
+
+fn render(frame: &mut Frame, rows: &[Row], state: &mut ListState) {
+    let [tree_area, detail_area] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(frame.area());
+
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|row| {
+            ListItem::new(Line::from(vec![
+                Span::raw("  ".repeat(row.depth)),
+                Span::styled(row.label.clone(), row.style),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::bordered().title(" Sparkplug network (q to quit) "))
+        .highlight_style(Style::new().reversed());
+
+    frame.render_stateful_widget(list, tree_area, state);
+
+    let selected = state
+        .selected()
+        .and_then(|index| rows.get(index))
+        .and_then(|row| row.metric.as_ref());
+
+    let detail = match selected {
+        None => Paragraph::new("Select a metric to show its history"),
+        Some((name, metric)) => {
+            let mut lines = vec![
+                Line::from(name.clone().bold()),
+                Line::from(format!("Value: {}", metric.value)),
+                Line::from(format!(
+                    "Timestamp: {}",
+                    metric.timestamp.as_deref().unwrap_or("unknown")
+                )),
+                Line::from(""),
+                Line::from("History".bold()),
+            ];
+
+            lines.extend(metric.history.iter().map(|(timestamp, value)| {
+                Line::from(format!(
+                    "{}  {}",
+                    timestamp.as_deref().unwrap_or("unknown"),
+                    value
+                ))
+            }));
+
+            Paragraph::new(lines)
+        }
+    };
+
+    frame.render_widget(
+        detail.block(Block::bordered().title(" Metric ")),
+        detail_area,
+    );
+}
+
+fn build_rows(snapshot: &SparkplugSnapshot) -> Vec<Row> {
+    fn status_style(status: &str) -> Style {
+        match status {
+            "online" => Style::new().fg(Color::Green),
+            _ => Style::new().fg(Color::Red),
+        }
+    }
+
+    fn metric_row(depth: usize, name: &str, metric: &SparkplugMetricSnapshot) -> Row {
+        Row {
+            depth,
+            label: format!("{name} = {}", metric.value),
+            style: Style::new(),
+            metric: Some((name.to_string(), metric.clone())),
+        }
+    }
+
+    let mut rows = vec![];
+
+    for (group_id, group) in snapshot.groups.iter() {
+        rows.push(Row {
+            depth: 0,
+            label: group_id.clone(),
+            style: Style::new().fg(Color::Yellow).bold(),
+            metric: None,
+        });
+
+        for (edge_node_id, edge_node) in group.edge_nodes.iter() {
+            rows.push(Row {
+                depth: 1,
+                label: format!("{edge_node_id} ({})", edge_node.status),
+                style: status_style(&edge_node.status).bold(),
+                metric: None,
+            });
+
+            for (name, metric) in edge_node.metrics.iter() {
+                rows.push(metric_row(2, name, metric));
+            }
+
+            for (device_id, device) in edge_node.devices.iter() {
+                rows.push(Row {
+                    depth: 2,
+                    label: format!("{device_id} ({})", device.status),
+                    style: status_style(&device.status),
+                    metric: None,
+                });
+
+                for (name, metric) in device.metrics.iter() {
+                    rows.push(metric_row(3, name, metric));
+                }
+            }
+        }
+    }
+
+    rows
+}